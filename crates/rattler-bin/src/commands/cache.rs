@@ -0,0 +1,100 @@
+use rattler::{
+    package_cache::{CorruptPackageAction, PackageCache},
+    validation::ValidationMode,
+};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Validates the packages in the package cache
+    Verify(VerifyOpt),
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct VerifyOpt {
+    /// Only check that the files of the packages exist and have the expected size, instead of
+    /// also checking their hashes
+    #[clap(long)]
+    fast: bool,
+
+    /// Remove corrupt packages from the cache
+    #[clap(long, conflicts_with = "quarantine")]
+    repair: bool,
+
+    /// Move corrupt packages to the quarantine directory of the cache
+    #[clap(long)]
+    quarantine: bool,
+
+    /// Output the report as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn cache(opt: Opt) -> anyhow::Result<()> {
+    match opt.command {
+        Command::Verify(opt) => verify(opt).await,
+    }
+}
+
+async fn verify(opt: VerifyOpt) -> anyhow::Result<()> {
+    let mode = if opt.fast {
+        ValidationMode::Fast
+    } else {
+        ValidationMode::Full
+    };
+    let action = if opt.repair {
+        CorruptPackageAction::Remove
+    } else if opt.quarantine {
+        CorruptPackageAction::Quarantine
+    } else {
+        CorruptPackageAction::Report
+    };
+
+    let package_cache = PackageCache::new(crate::config::cache_dir()?.join("pkgs"));
+    let report = package_cache.verify(mode, action).await?;
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for package in &report.corrupt {
+            println!("* Corrupt: {}: {}", package.path.display(), package.error);
+            match (
+                &package.action,
+                &package.quarantined_to,
+                &package.repair_error,
+            ) {
+                (_, _, Some(error)) => println!("    could not repair the package: {error}"),
+                (CorruptPackageAction::Remove, _, _) => println!("    removed"),
+                (_, Some(quarantined_to), _) => {
+                    println!("    moved to {}", quarantined_to.display())
+                }
+                _ => {}
+            }
+        }
+        println!(
+            "{} Verified {} packages, {} are corrupt",
+            if report.is_valid() {
+                console::style(console::Emoji("✔", "")).green()
+            } else {
+                console::style(console::Emoji("✘", "")).red()
+            },
+            report.valid.len() + report.corrupt.len(),
+            report.corrupt.len()
+        );
+    }
+
+    if report
+        .corrupt
+        .iter()
+        .any(|package| package.repair_error.is_some())
+        || (!report.is_valid() && action == CorruptPackageAction::Report)
+    {
+        anyhow::bail!("the package cache contains corrupt packages");
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use crate::config::{ConfigKey, RattlerConfig};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Prints the value of a setting
+    Get {
+        #[clap(value_enum)]
+        key: ConfigKey,
+    },
+
+    /// Validates and stores the value of a setting. Only `channels` accepts multiple values.
+    Set {
+        #[clap(value_enum)]
+        key: ConfigKey,
+
+        #[clap(required = true)]
+        values: Vec<String>,
+    },
+
+    /// Removes the value of a setting so its default is used again
+    Unset {
+        #[clap(value_enum)]
+        key: ConfigKey,
+    },
+
+    /// Prints all settings that are configured and the path of the configuration file
+    List,
+}
+
+pub fn config(opt: Opt) -> anyhow::Result<()> {
+    let path = RattlerConfig::path()?;
+    let mut config = RattlerConfig::from_path(&path)?;
+
+    match opt.command {
+        Command::Get { key } => match config.get(key) {
+            Some(value) => println!("{value}"),
+            None => anyhow::bail!("{} is not configured", key.name()),
+        },
+        Command::Set { key, values } => {
+            config.set(key, values)?;
+            config.write_to_path(&path)?;
+        }
+        Command::Unset { key } => {
+            config.unset(key);
+            config.write_to_path(&path)?;
+        }
+        Command::List => {
+            println!("# {}", path.display());
+            for key in ConfigKey::ALL {
+                if let Some(value) = config.get(key) {
+                    println!("{}: {}", key.name(), value.replace('\n', ", "));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,557 +1,887 @@
-use crate::global_multi_progress;
+use crate::{
+    config::RattlerConfig, global_client_options, global_clock, global_download_manager,
+    global_http_cassette, global_link_runtime, global_multi_progress,
+};
 use anyhow::Context;
-use futures::{stream, stream::FuturesUnordered, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{stream::FuturesUnordered, StreamExt};
 use indicatif::{HumanBytes, ProgressBar, ProgressState, ProgressStyle};
+use itertools::Itertools;
 use rattler::{
-    default_cache_dir,
-    install::{link_package, InstallDriver, InstallOptions, Transaction, TransactionOperation},
+    advisory::OsvDirectory,
+    install::{
+        verify_installed_package, FailedOperations, InstallOptions, Installer, InstallerError,
+        InstallerReporter, PartialLinks, Transaction, TransactionOperation,
+    },
     package_cache::PackageCache,
 };
 use rattler_conda_types::{
-    Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, PackageRecord, Platform,
-    PrefixRecord, RepoDataRecord, Version,
-};
-use rattler_networking::{
-    retry_policies::default_retry_policy, AuthenticatedClient, AuthenticationStorage,
+    Channel, GenericVirtualPackage, MatchSpec, PackageName, PackageRecord, PinnedSpecs, Platform,
+    PrefixRecord, RepoDataRecord, StringMatcher, Version,
 };
+use rattler_networking::{AuthenticatedClient, AuthenticationStorage};
 use rattler_repodata_gateway::fetch::{
-    CacheResult, DownloadProgress, FetchRepoDataError, FetchRepoDataOptions,
+    CacheResult, CachedRepoData, DownloadProgress, FetchRepoDataError, FetchRepoDataOptions,
+    MultiRequestRepoDataBuilder,
 };
 use rattler_repodata_gateway::sparse::SparseRepoData;
-use rattler_solve::{libsolv_c, resolvo, SolverImpl, SolverTask};
-use reqwest::Client;
+use rattler_solve::{
+    solve_environment, ChannelPriority, DependencyMode, EnvironmentSpec, Policy, SolveOptions,
+    SolveStrategy, SolverBackend,
+};
+use serde::Serialize;
 use std::{
     borrow::Cow,
+    collections::HashMap,
     env,
     fmt::Write,
-    future::ready,
-    io::ErrorKind,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Mutex,
     time::Duration,
 };
 use tokio::task::JoinHandle;
 
 #[derive(Debug, clap::Parser)]
 pub struct Opt {
-    #[clap(short)]
-    channels: Option<Vec<String>>,
+    #[clap(flatten)]
+    solve: SolveOpt,
+
+    #[clap(long)]
+    dry_run: bool,
+
+    /// A directory with security advisories in the OSV format. Packages affected by an advisory
+    /// are not installed.
+    #[clap(long)]
+    advisories: Option<PathBuf>,
+
+    /// Install packages even if they are affected by a security advisory
+    #[clap(long, requires = "advisories")]
+    allow_vulnerable: bool,
+
+    #[clap(flatten)]
+    keep_going: KeepGoingOpt,
+}
+
+/// Options that control what happens when a package fails to install.
+#[derive(Debug, Default, clap::Parser)]
+pub struct KeepGoingOpt {
+    /// Continue installing the other packages if a package fails to install. Packages that depend
+    /// on a package that failed to install are skipped
+    #[clap(long)]
+    keep_going: bool,
 
+    /// Write a JSON report of the packages that failed to install or that were skipped to this
+    /// file
+    #[clap(long, requires = "keep_going")]
+    failure_report: Option<PathBuf>,
+}
+
+/// Options that describe the environment to solve.
+#[derive(Debug, clap::Parser)]
+pub struct SolveOpt {
     #[clap(required = true)]
     specs: Vec<String>,
 
-    #[clap(long)]
-    dry_run: bool,
+    #[clap(flatten)]
+    solver: SolverOpt,
+}
 
+/// Options that control how specs are solved, independent of the specs themselves.
+#[derive(Debug, clap::Parser)]
+pub struct SolverOpt {
+    #[clap(short)]
+    channels: Option<Vec<String>>,
+
+    /// The platform to solve for (e.g. `linux-64`), which can differ from the current platform.
+    /// Defaults to the current platform.
     #[clap(long)]
     platform: Option<String>,
 
+    /// The virtual packages to solve with, formatted as `name=version=build`. Defaults to the
+    /// virtual packages of the platform that is solved for.
     #[clap(long)]
     virtual_package: Option<Vec<String>>,
 
+    /// Constrain the packages in the solution without requesting them (e.g. `python 3.11.*`).
+    #[clap(long = "pin")]
+    pins: Vec<String>,
+
+    /// Only install the dependencies of the specs, not the packages of the specs themselves.
+    #[clap(long, conflicts_with = "no_deps")]
+    only_deps: bool,
+
+    /// Only install the packages of the specs, not their dependencies.
+    #[clap(long)]
+    no_deps: bool,
+
+    /// Ignore packages that were published after this moment, formatted as a date (`2023-06-01`)
+    /// or as an RFC 3339 timestamp (`2023-06-01T12:00:00Z`). This solves against the channels as
+    /// they existed at that moment.
+    #[clap(long, value_parser = parse_cutoff)]
+    exclude_newer: Option<DateTime<Utc>>,
+
+    /// Warn when the repodata of a channel was fetched from the server longer ago than this, e.g.
+    /// because the server could not be reached.
+    #[clap(long, default_value = "7days", value_parser = humantime::parse_duration)]
+    stale_after: Duration,
+
+    /// Refuse to solve when the repodata of a channel was fetched from the server longer ago than
+    /// this (e.g. `12h` or `3days`).
+    #[clap(long, value_parser = humantime::parse_duration)]
+    max_staleness: Option<Duration>,
+
+    /// Never install packages whose name matches this pattern (e.g. `openssl` or `*-gpl`).
+    #[clap(long = "deny-package", value_parser = StringMatcher::from_str)]
+    denied_packages: Vec<StringMatcher>,
+
+    /// Only install packages with one of these license families (e.g. `MIT` or `BSD`).
+    #[clap(long = "allow-license-family")]
+    allowed_license_families: Vec<String>,
+
+    /// Only install packages that are downloaded from one of these hosts (e.g.
+    /// `conda.anaconda.org`).
+    #[clap(long = "allow-channel-host")]
+    allowed_channel_hosts: Vec<String>,
+
+    /// How the order of the channels influences the selected packages: `strict`, `flexible` or
+    /// `disabled`. Only `disabled` is supported without `--use-experimental-libsolv-rs`.
+    #[clap(long, default_value = "disabled", value_parser = ChannelPriority::from_str)]
+    channel_priority: ChannelPriority,
+
+    /// Which candidates of a package are preferred: `highest` or `lowest-version`. Only `highest`
+    /// is supported without `--use-experimental-libsolv-rs`.
+    #[clap(long, default_value = "highest", value_parser = SolveStrategy::from_str)]
+    strategy: SolveStrategy,
+
+    /// Prefer an architecture specific build over a `noarch` build of the same version of a
+    /// package. Only supported with `--use-experimental-libsolv-rs`.
+    #[clap(long)]
+    prefer_arch_over_noarch: bool,
+
     #[clap(long)]
     use_experimental_libsolv_rs: bool,
 }
 
+impl SolveOpt {
+    /// Returns the platform to solve for. Defaults to the current platform.
+    pub fn platform(&self) -> anyhow::Result<Platform> {
+        self.solver.platform()
+    }
+}
+
+impl SolverOpt {
+    /// Returns the platform to solve for. Defaults to the current platform.
+    pub fn platform(&self) -> anyhow::Result<Platform> {
+        Ok(match &self.platform {
+            Some(platform) => Platform::from_str(platform)?,
+            None => Platform::current(),
+        })
+    }
+
+    /// Returns which packages of the solution should be installed.
+    pub fn dependency_mode(&self) -> DependencyMode {
+        if self.only_deps {
+            DependencyMode::OnlyDeps
+        } else if self.no_deps {
+            DependencyMode::NoDeps
+        } else {
+            DependencyMode::All
+        }
+    }
+
+    /// Returns the policy that restricts which packages may be installed.
+    pub fn policy(&self) -> Policy {
+        let allowed = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        Policy {
+            denied_packages: self.denied_packages.clone(),
+            allowed_license_families: allowed(&self.allowed_license_families),
+            allowed_channel_hosts: allowed(&self.allowed_channel_hosts),
+        }
+    }
+
+    /// Returns the options to solve with, see [`rattler_solve::solve_environment`].
+    pub fn solve_options(&self) -> SolveOptions {
+        SolveOptions {
+            backend: if self.use_experimental_libsolv_rs {
+                SolverBackend::Resolvo
+            } else {
+                SolverBackend::LibsolvC
+            },
+            strategy: self.strategy,
+            channel_priority: self.channel_priority,
+            prefer_arch_over_noarch: self.prefer_arch_over_noarch,
+            dependency_mode: self.dependency_mode(),
+            policy: self.policy(),
+            exclude_newer: self.exclude_newer,
+        }
+    }
+}
+
+/// Parses the value of `--exclude-newer` and `--now`. A date without a time refers to the start of
+/// that day in UTC.
+pub(crate) fn parse_cutoff(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let start_of_day = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        return Ok(DateTime::from_naive_utc_and_offset(start_of_day, Utc));
+    }
+    Ok(DateTime::parse_from_rfc3339(s)
+        .with_context(|| format!("'{s}' is neither a date nor an RFC 3339 timestamp"))?
+        .with_timezone(&Utc))
+}
+
 pub async fn create(opt: Opt) -> anyhow::Result<()> {
-    let channel_config = ChannelConfig::default();
     let target_prefix = env::current_dir()?.join(".prefix");
 
     // Determine the platform we're going to install for
-    let install_platform = if let Some(platform) = opt.platform {
-        Platform::from_str(&platform)?
-    } else {
-        Platform::current()
-    };
+    let install_platform = opt.solve.platform()?;
 
     println!("installing for platform: {:?}", install_platform);
 
-    // Parse the specs from the command line. We do this explicitly instead of allow clap to deal
-    // with this because we need to parse the `channel_config` when parsing matchspecs.
-    let specs = opt
-        .specs
-        .iter()
-        .map(|spec| MatchSpec::from_str(spec))
-        .collect::<Result<Vec<_>, _>>()?;
-
     // Find the default cache directory. Create it if it doesnt exist yet.
-    let cache_dir = default_cache_dir()?;
+    let cache_dir = crate::config::cache_dir()?;
     std::fs::create_dir_all(&cache_dir)
         .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
 
-    // Determine the channels to use from the command line or select the default. Like matchspecs
-    // this also requires the use of the `channel_config` so we have to do this manually.
-    let channels = opt
-        .channels
-        .unwrap_or_else(|| vec![String::from("conda-forge")])
-        .into_iter()
-        .map(|channel_str| Channel::from_str(channel_str, &channel_config))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    // Each channel contains multiple subdirectories. Users can specify the subdirectories they want
-    // to use when specifying their channels. If the user didn't specify the default subdirectories
-    // we use defaults based on the current platform.
-    let channel_urls = channels
-        .iter()
-        .flat_map(|channel| {
-            vec![
-                (channel.clone(), install_platform),
-                (channel.clone(), Platform::NoArch),
-            ]
-        })
-        .collect::<Vec<_>>();
-
     // Determine the packages that are currently installed in the environment.
     let installed_packages = find_installed_packages(&target_prefix, 100)
         .await
         .context("failed to determine currently installed packages")?;
 
-    // For each channel/subdirectory combination, download and cache the `repodata.json` that should
-    // be available from the corresponding Url. The code below also displays a nice CLI progress-bar
-    // to give users some more information about what is going on.
-    let download_client = Client::builder()
-        .no_gzip()
-        .build()
-        .expect("failed to create client");
-
-    let auth_dir = dirs::config_local_dir()
-        .ok_or_else(|| anyhow::anyhow!("could not determine cache directory for current platform"))?
-        .join("rattler/auth");
-
-    let authentication_storage = AuthenticationStorage::new("rattler_credentials", &auth_dir);
-
-    let download_client = AuthenticatedClient::from_client(download_client, authentication_storage);
-    let multi_progress = global_multi_progress();
+    let download_client = download_client()?;
 
-    let repodata_cache_path = cache_dir.join("repodata");
-    let channel_and_platform_len = channel_urls.len();
-    let repodata_download_client = download_client.clone();
-    let sparse_repo_datas = futures::stream::iter(channel_urls)
-        .map(move |(channel, platform)| {
-            let repodata_cache = repodata_cache_path.clone();
-            let download_client = repodata_download_client.clone();
-            let multi_progress = multi_progress.clone();
-            async move {
-                fetch_repo_data_records_with_progress(
-                    channel,
-                    platform,
-                    &repodata_cache,
-                    download_client.clone(),
-                    multi_progress,
-                )
-                .await
-            }
-        })
-        .buffer_unordered(channel_and_platform_len)
-        .filter_map(|result| async move {
-            match result {
-                Err(e) => Some(Err(e)),
-                Ok(Some(data)) => Some(Ok(data)),
-                Ok(None) => None,
-            }
-        })
-        .collect::<Vec<_>>()
-        .await
-        // Collect into another iterator where we extract the first erroneous result
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()?;
-
-    // Get the package names from the matchspecs so we can only load the package records that we need.
-    let package_names = specs.iter().filter_map(|spec| spec.name.as_ref().cloned());
-    let repodatas = wrap_in_progress("parsing repodata", move || {
-        SparseRepoData::load_records_recursive(
-            &sparse_repo_datas,
-            package_names,
-            Some(|record| {
-                if record.name.as_normalized() == "python" {
-                    record.depends.push("pip".to_string());
-                }
-            }),
-            true,
+    // Read the specs that are pinned in the environment. The solution must satisfy them.
+    let pinned_specs = PinnedSpecs::from_prefix(&target_prefix).with_context(|| {
+        format!(
+            "failed to read {}",
+            PinnedSpecs::path_in_prefix(&target_prefix).display()
         )
     })?;
 
-    // Determine virtual packages of the system. These packages define the capabilities of the
-    // system. Some packages depend on these virtual packages to indiciate compability with the
-    // hardware of the system.
-    let virtual_packages = wrap_in_progress("determining virtual packages", move || {
-        if let Some(virtual_packages) = opt.virtual_package {
-            Ok(virtual_packages
-                .iter()
-                .map(|virt_pkg| {
-                    let elems = virt_pkg.split('=').collect::<Vec<&str>>();
-                    Ok(GenericVirtualPackage {
-                        name: elems[0].try_into()?,
-                        version: elems
-                            .get(1)
-                            .map(|s| Version::from_str(s))
-                            .unwrap_or(Version::from_str("0"))
-                            .expect("Could not parse virtual package version"),
-                        build_string: elems.get(2).unwrap_or(&"").to_string(),
-                    })
-                })
-                .collect::<anyhow::Result<Vec<_>>>()?)
-        } else {
-            rattler_virtual_packages::VirtualPackage::current()
-                .map(|vpkgs| {
-                    vpkgs
-                        .iter()
-                        .map(|vpkg| GenericVirtualPackage::from(vpkg.clone()))
-                        .collect::<Vec<_>>()
-                })
-                .map_err(anyhow::Error::from)
+    // Remember the specs that the user requested so they can be recorded in the conda-meta
+    // records of the packages.
+    let mut requested_specs = HashMap::new();
+    for spec in &opt.solve.specs {
+        if let Some(name) = MatchSpec::from_str(spec)?.name {
+            requested_specs.insert(name, spec.clone());
         }
-    })?;
-
-    println!("virtual packages: {:?}", virtual_packages);
+    }
 
-    // Now that we parsed and downloaded all information, construct the packaging problem that we
-    // need to solve. We do this by constructing a `SolverProblem`. This encapsulates all the
-    // information required to be able to solve the problem.
+    // Solve the environment. The packages that are currently installed are passed as locked
+    // packages so the solver prefers to keep them.
     let locked_packages = installed_packages
         .iter()
         .map(|record| record.repodata_record.clone())
         .collect();
-
-    let solver_task = SolverTask {
-        available_packages: &repodatas,
+    let policy = opt.solve.solver.policy();
+    let required_packages = solve(
+        opt.solve,
+        install_platform,
         locked_packages,
-        virtual_packages,
-        specs,
-        pinned_packages: Vec::new(),
-    };
-
-    // Next, use a solver to solve this specific problem. This provides us with all the operations
-    // we need to apply to our environment to bring it up to date.
-    let use_libsolv_rs = opt.use_experimental_libsolv_rs;
-    let required_packages = wrap_in_progress("solving", move || {
-        if use_libsolv_rs {
-            resolvo::Solver.solve(solver_task)
-        } else {
-            libsolv_c::Solver.solve(solver_task)
-        }
-    })?;
+        pinned_specs.specs,
+        &cache_dir,
+        download_client.clone(),
+    )
+    .await?;
 
     // sort topologically
     let required_packages = PackageRecord::sort_topologically(required_packages);
 
+    // Remember the installed packages that other packages might depend on at link time.
+    let installed_records = installed_packages
+        .iter()
+        .map(|record| record.repodata_record.package_record.clone())
+        .collect::<Vec<_>>();
+
+    // Packages that were damaged, e.g. by a previous installation that failed halfway, have to be
+    // installed again.
+    let damaged_packages = find_damaged_packages(&target_prefix, &installed_packages).await?;
+
     // Construct a transaction to
-    let transaction = Transaction::from_current_and_desired(
+    let mut transaction = Transaction::from_current_and_desired_with_policy(
         installed_packages,
         required_packages,
         install_platform,
+        &policy,
     )?;
+    transaction.reinstall(damaged_packages);
+
+    if let Some(advisories) = &opt.advisories {
+        let provider = OsvDirectory::from_path(advisories)?;
+        let vulnerable = transaction.find_vulnerable_packages(&provider)?;
+        for package in &vulnerable {
+            let ids = package
+                .advisories
+                .iter()
+                .map(|advisory| &advisory.id)
+                .join(", ");
+            println!(
+                "{} {} {} is affected by {ids}",
+                console::style(console::Emoji("⚠", "")).yellow(),
+                package.record.name.as_normalized(),
+                package.record.version
+            );
+        }
+        if !vulnerable.is_empty() && !opt.allow_vulnerable && !opt.dry_run {
+            anyhow::bail!(
+                "{} packages are affected by security advisories, use --allow-vulnerable to install them anyway",
+                vulnerable.len()
+            );
+        }
+    }
 
     if opt.dry_run {
-        if transaction.operations.is_empty() {
-            println!("No operations necessary");
-        }
+        print_transaction(&transaction);
+        return Ok(());
+    }
 
-        let format_record = |r: &RepoDataRecord| {
-            format!(
-                "{} {} {}",
-                r.package_record.name.as_normalized(),
-                r.package_record.version,
-                r.package_record.build
-            )
-        };
+    apply_transaction(
+        transaction,
+        &installed_records,
+        &requested_specs,
+        target_prefix,
+        cache_dir,
+        download_client,
+        &opt.keep_going,
+    )
+    .await
+}
 
-        for operation in &transaction.operations {
-            match operation {
-                TransactionOperation::Install(r) => println!("* Install: {}", format_record(r)),
-                TransactionOperation::Change { old, new } => {
-                    println!(
-                        "* Change: {} -> {}",
-                        format_record(&old.repodata_record),
-                        format_record(new)
-                    );
-                }
-                TransactionOperation::Reinstall(r) => {
-                    println!("* Reinstall: {}", format_record(&r.repodata_record))
-                }
-                TransactionOperation::Remove(r) => {
-                    println!("* Remove: {}", format_record(&r.repodata_record))
-                }
+/// Prints the operations of a transaction without executing them.
+pub(crate) fn print_transaction(transaction: &Transaction<PrefixRecord, RepoDataRecord>) {
+    if transaction.operations.is_empty() {
+        println!("No operations necessary");
+    }
+
+    let format_record = |r: &RepoDataRecord| {
+        format!(
+            "{} {} {}",
+            r.package_record.name.as_normalized(),
+            r.package_record.version,
+            r.package_record.build
+        )
+    };
+
+    for operation in &transaction.operations {
+        match operation {
+            TransactionOperation::Install(r) => println!("* Install: {}", format_record(r)),
+            TransactionOperation::Change { old, new } => {
+                println!(
+                    "* Change: {} -> {}",
+                    format_record(&old.repodata_record),
+                    format_record(new)
+                );
+            }
+            TransactionOperation::Reinstall(r) => {
+                println!("* Reinstall: {}", format_record(&r.repodata_record))
+            }
+            TransactionOperation::Remove(r) => {
+                println!("* Remove: {}", format_record(&r.repodata_record))
             }
         }
-
-        return Ok(());
     }
+}
 
-    if !transaction.operations.is_empty() {
-        // Execute the operations that are returned by the solver.
-        execute_transaction(transaction, target_prefix, cache_dir, download_client).await?;
+/// Executes the operations of a transaction on the environment at `target_prefix`. The
+/// `installed_records` are the records that were installed before the transaction.
+///
+/// The `requested_specs` are the specs with which the user explicitly requested packages. They are
+/// recorded in the conda-meta records of the installed packages. Packages that replace an
+/// installed package keep the requested spec of the package they replace.
+pub(crate) async fn apply_transaction(
+    transaction: Transaction<PrefixRecord, RepoDataRecord>,
+    installed_records: &[PackageRecord],
+    requested_specs: &HashMap<PackageName, String>,
+    target_prefix: PathBuf,
+    cache_dir: PathBuf,
+    download_client: AuthenticatedClient,
+    keep_going: &KeepGoingOpt,
+) -> anyhow::Result<()> {
+    if transaction.operations.is_empty() {
         println!(
-            "{} Successfully updated the environment",
+            "{} Already up to date",
             console::style(console::Emoji("✔", "")).green(),
         );
-    } else {
+        return Ok(());
+    }
+
+    // Open the package cache
+    let package_cache =
+        PackageCache::new(cache_dir.join("pkgs")).with_download_manager(global_download_manager());
+
+    // Execute the operations that are returned by the solver. Packages that are still being
+    // linked when the transaction is interrupted (e.g. on Ctrl-C) are removed again by
+    // `roll_back_interrupted_links` before the process exits.
+    let total_operations = transaction.operations.len();
+    let installer = Installer::new(package_cache, download_client)
+        .with_install_options(InstallOptions {
+            runtime: global_link_runtime(),
+            ..Default::default()
+        })
+        .with_requested_specs(requested_specs.clone())
+        .with_keep_going(keep_going.keep_going)
+        .with_reporter(ProgressReporter::new(&transaction))
+        .with_interrupt_handler(|partial_links| {
+            INTERRUPTED_LINKS.lock().unwrap().push(partial_links)
+        });
+    let result = installer
+        .install(&target_prefix, transaction, installed_records)
+        .await;
+
+    // Explain how much disk space the installed files take up.
+    let report = installer.install_driver().report();
+    let statistics = report.link_statistics;
+    if statistics.total().files > 0 {
+        tracing::info!(
+            "linked {} files of {} packages: {} files ({}) hard linked, {} files ({}) soft linked, {} files ({}) copied and {} files ({}) patched",
+            statistics.total().files,
+            report.linked_packages,
+            statistics.hard_linked.files,
+            HumanBytes(statistics.hard_linked.bytes),
+            statistics.soft_linked.files,
+            HumanBytes(statistics.soft_linked.bytes),
+            statistics.copied.files,
+            HumanBytes(statistics.copied.bytes),
+            statistics.patched.files,
+            HumanBytes(statistics.patched.bytes),
+        );
         println!(
-            "{} Already up to date",
+            "{} Linked {} ({} of additional disk space used)",
             console::style(console::Emoji("✔", "")).green(),
+            HumanBytes(statistics.total().bytes),
+            HumanBytes(statistics.additional_disk_usage()),
         );
     }
 
+    let failure_report = match result {
+        Ok(_) => FailureReport::default(),
+        Err(InstallerError::OperationsFailed(failures)) => FailureReport::from(failures),
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(path) = &keep_going.failure_report {
+        std::fs::write(path, serde_json::to_string_pretty(&failure_report)?)
+            .with_context(|| format!("failed to write the failure report to {}", path.display()))?;
+    }
+    if !failure_report.is_empty() {
+        anyhow::bail!(
+            "{} of {total_operations} operations failed and {} were skipped: {}",
+            failure_report.failed.len(),
+            failure_report.skipped.len(),
+            failure_report
+                .failed
+                .iter()
+                .map(|failed| failed.package.as_str())
+                .chain(
+                    failure_report
+                        .skipped
+                        .iter()
+                        .map(|skipped| skipped.package.as_str())
+                )
+                .join(", ")
+        );
+    }
+
+    println!(
+        "{} Successfully updated the environment",
+        console::style(console::Emoji("✔", "")).green(),
+    );
     Ok(())
 }
 
-/// Executes the transaction on the given environment.
-async fn execute_transaction(
-    transaction: Transaction<PrefixRecord, RepoDataRecord>,
-    target_prefix: PathBuf,
-    cache_dir: PathBuf,
-    download_client: AuthenticatedClient,
-) -> anyhow::Result<()> {
-    // Open the package cache
-    let package_cache = PackageCache::new(cache_dir.join("pkgs"));
+/// Shows the progress of an [`Installer`] with a progress bar for the downloads and one for all
+/// operations. With `--keep-going` the packages that failed or were skipped are printed as well.
+struct ProgressReporter {
+    download_pb: Option<ProgressBar>,
+    link_pb: ProgressBar,
+}
 
-    // Create an install driver which helps limit the number of concurrent fileystem operations
-    let install_driver = InstallDriver::default();
+impl ProgressReporter {
+    /// Adds the progress bars for the operations of the transaction.
+    fn new(transaction: &Transaction<PrefixRecord, RepoDataRecord>) -> Self {
+        let multi_progress = global_multi_progress();
 
-    // Define default installation options.
-    let install_options = InstallOptions {
-        python_info: transaction.python_info.clone(),
-        platform: Some(transaction.platform),
-        ..Default::default()
-    };
+        // Create a progress bars for downloads.
+        let total_packages_to_download = transaction
+            .operations
+            .iter()
+            .filter(|op| op.record_to_install().is_some())
+            .count();
+        let download_pb = if total_packages_to_download > 0 {
+            let pb = multi_progress.add(
+                indicatif::ProgressBar::new(total_packages_to_download as u64)
+                    .with_style(default_progress_style())
+                    .with_finish(indicatif::ProgressFinish::WithMessage("Done!".into()))
+                    .with_prefix("downloading"),
+            );
+            pb.enable_steady_tick(Duration::from_millis(100));
+            Some(pb)
+        } else {
+            None
+        };
 
-    // Create a progress bars for downloads.
-    let multi_progress = global_multi_progress();
-    let total_packages_to_download = transaction
-        .operations
-        .iter()
-        .filter(|op| op.record_to_install().is_some())
-        .count();
-    let download_pb = if total_packages_to_download > 0 {
-        let pb = multi_progress.add(
-            indicatif::ProgressBar::new(total_packages_to_download as u64)
+        // Create a progress bar to track all operations.
+        let link_pb = multi_progress.add(
+            indicatif::ProgressBar::new(transaction.operations.len() as u64)
                 .with_style(default_progress_style())
                 .with_finish(indicatif::ProgressFinish::WithMessage("Done!".into()))
-                .with_prefix("downloading"),
+                .with_prefix("linking"),
         );
-        pb.enable_steady_tick(Duration::from_millis(100));
-        Some(pb)
-    } else {
-        None
-    };
+        link_pb.enable_steady_tick(Duration::from_millis(100));
 
-    // Create a progress bar to track all operations.
-    let total_operations = transaction.operations.len();
-    let link_pb = multi_progress.add(
-        indicatif::ProgressBar::new(total_operations as u64)
-            .with_style(default_progress_style())
-            .with_finish(indicatif::ProgressFinish::WithMessage("Done!".into()))
-            .with_prefix("linking"),
-    );
-    link_pb.enable_steady_tick(Duration::from_millis(100));
-
-    // Perform all transactions operations in parallel.
-    stream::iter(transaction.operations)
-        .map(Ok)
-        .try_for_each_concurrent(50, |op| {
-            let target_prefix = target_prefix.clone();
-            let download_client = download_client.clone();
-            let package_cache = &package_cache;
-            let install_driver = &install_driver;
-            let download_pb = download_pb.as_ref();
-            let link_pb = &link_pb;
-            let install_options = &install_options;
-            async move {
-                execute_operation(
-                    &target_prefix,
-                    download_client,
-                    package_cache,
-                    install_driver,
-                    download_pb,
-                    link_pb,
-                    op,
-                    install_options,
-                )
-                .await
-            }
-        })
-        .await?;
+        Self {
+            download_pb,
+            link_pb,
+        }
+    }
 
-    Ok(())
+    /// Increments the progress bar and marks it as finished once all steps are done.
+    fn inc(pb: &ProgressBar) {
+        pb.inc(1);
+        if pb.length() == Some(pb.position()) {
+            pb.set_style(finished_progress_style());
+        }
+    }
 }
 
-/// Executes a single operation of a transaction on the environment.
-/// TODO: Move this into an object or something.
-#[allow(clippy::too_many_arguments)]
-async fn execute_operation(
-    target_prefix: &Path,
+impl InstallerReporter for ProgressReporter {
+    fn on_fetch_complete(&self, _record: &RepoDataRecord) {
+        if let Some(pb) = &self.download_pb {
+            Self::inc(pb);
+        }
+    }
+
+    fn on_operation_complete(&self, _package: &PackageName) {
+        Self::inc(&self.link_pb);
+    }
+
+    fn on_operation_skipped(&self, package: &PackageName, failed_dependency: &PackageName) {
+        Self::inc(&self.link_pb);
+        self.link_pb.suspend(|| {
+            eprintln!(
+                "{} Skipped {} because {} failed to install",
+                console::style(console::Emoji("✘", "")).yellow(),
+                package.as_normalized(),
+                failed_dependency.as_normalized()
+            )
+        });
+    }
+
+    fn on_operation_failed(&self, package: &PackageName, error: &InstallerError) {
+        self.link_pb.suspend(|| {
+            eprintln!(
+                "{} Failed to install {}: {}",
+                console::style(console::Emoji("✘", "")).red(),
+                package.as_normalized(),
+                error_chain(error)
+            )
+        });
+    }
+}
+
+/// Formats an error together with its sources, like the alternate format of [`anyhow::Error`].
+fn error_chain(error: &dyn std::error::Error) -> String {
+    std::iter::successors(Some(error), |error| error.source()).join(": ")
+}
+
+/// Fetches the repodata of the channels and solves the specs in `opt` for the given platform. The
+/// solution satisfies both the `constraints` and the pins in `opt`.
+pub(crate) async fn solve(
+    opt: SolveOpt,
+    platform: Platform,
+    locked_packages: Vec<RepoDataRecord>,
+    constraints: Vec<MatchSpec>,
+    cache_dir: &Path,
     download_client: AuthenticatedClient,
-    package_cache: &PackageCache,
-    install_driver: &InstallDriver,
-    download_pb: Option<&ProgressBar>,
-    link_pb: &ProgressBar,
-    op: TransactionOperation<PrefixRecord, RepoDataRecord>,
-    install_options: &InstallOptions,
-) -> anyhow::Result<()> {
-    // Determine the package to install
-    let install_record = op.record_to_install();
-    let remove_record = op.record_to_remove();
+) -> anyhow::Result<Vec<RepoDataRecord>> {
+    // Parse the specs from the command line. We do this explicitly instead of allow clap to deal
+    // with this because we need to parse the `channel_config` when parsing matchspecs.
+    let specs = opt
+        .specs
+        .iter()
+        .map(|spec| MatchSpec::from_str(spec))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    // Create a future to remove the existing package
-    let remove_future = if let Some(remove_record) = remove_record {
-        remove_package_from_environment(target_prefix, remove_record).left_future()
-    } else {
-        ready(Ok(())).right_future()
+    solve_specs(
+        opt.solver,
+        specs,
+        platform,
+        locked_packages,
+        constraints,
+        cache_dir,
+        download_client,
+    )
+    .await
+}
+
+/// Fetches the repodata of the channels in `opt` and solves the `specs` for the given platform.
+/// The solution satisfies both the `constraints` and the pins in `opt`.
+pub(crate) async fn solve_specs(
+    opt: SolverOpt,
+    specs: Vec<MatchSpec>,
+    platform: Platform,
+    locked_packages: Vec<RepoDataRecord>,
+    mut constraints: Vec<MatchSpec>,
+    cache_dir: &Path,
+    download_client: AuthenticatedClient,
+) -> anyhow::Result<Vec<RepoDataRecord>> {
+    let config = RattlerConfig::load()?;
+    let channel_config = config.channel_config();
+    let clock = global_clock();
+
+    for pin in &opt.pins {
+        constraints.push(MatchSpec::from_str(pin)?);
+    }
+    let solve_options = opt.solve_options();
+
+    // Determine the channels to use from the command line or select the configured default. Like
+    // matchspecs this also requires the use of the `channel_config` so we have to do this
+    // manually. The `defaults` and `nodefaults` keywords are supported like in an
+    // `environment.yml` file.
+    let channels = channel_config
+        .resolve_channels(opt.channels.unwrap_or_else(|| config.default_channels()))?;
+
+    // Each channel contains multiple subdirectories. Users can specify the subdirectories they want
+    // to use when specifying their channels (e.g. `conda-forge[linux-64]`). If the user didn't
+    // specify the subdirectories we use the subdirectory of the platform and `noarch`.
+    let channel_urls = channels
+        .iter()
+        .flat_map(|channel| {
+            channel
+                .subdirs_for_platform(platform)
+                .into_iter()
+                .map(|subdir| (channel.clone(), subdir))
+        })
+        .collect::<Vec<_>>();
+
+    // For each channel/subdirectory combination, download and cache the `repodata.json` that should
+    // be available from the corresponding Url. The code below also displays a nice CLI progress-bar
+    // to give users some more information about what is going on.
+    let multi_progress = global_multi_progress();
+
+    // Requests to the same host are limited to avoid being throttled by servers like
+    // anaconda.org, requests to different hosts are sent in parallel.
+    let mut repodata_requests =
+        MultiRequestRepoDataBuilder::new(download_client.clone(), cache_dir.join("repodata"))
+            .with_options(FetchRepoDataOptions {
+                clock: clock.clone(),
+                ..FetchRepoDataOptions::default()
+            });
+    let mut progress_bars = Vec::with_capacity(channel_urls.len());
+    for (channel, platform) in channel_urls.iter() {
+        let progress_bar = repodata_progress_bar(channel, *platform, &multi_progress);
+        let download_progress_bar = progress_bar.clone();
+        repodata_requests = repodata_requests.add_subdir_with_progress(
+            channel.platform_url(*platform),
+            Some(Box::new(
+                move |DownloadProgress { total, bytes, .. }| {
+                    download_progress_bar.set_length(total.unwrap_or(bytes));
+                    download_progress_bar.set_position(bytes);
+                },
+            )),
+        );
+        progress_bars.push(progress_bar);
+    }
+    let fetch_results = repodata_requests.fetch().await;
+
+    // Load the fetched repodata. The results are in the order of the channels the user specified.
+    let sparse_repo_datas = futures::future::join_all(
+        channel_urls
+            .into_iter()
+            .zip(progress_bars)
+            .zip(fetch_results)
+            .map(|(((channel, platform), progress_bar), (_, result))| {
+                load_repo_data_records_with_progress(channel, platform, result, progress_bar)
+            }),
+    )
+    .await
+    .into_iter()
+    .filter_map(Result::transpose)
+    // Collect into another iterator where we extract the first erroneous result
+    .collect::<Result<Vec<_>, _>>()?;
+
+    // Make sure the repodata is recent enough to solve against.
+    check_staleness(
+        &sparse_repo_datas,
+        clock.now(),
+        opt.stale_after,
+        opt.max_staleness,
+    )?;
+
+    // Only consider the records that were published before the cutoff, if any.
+    let sparse_repo_datas = match opt.exclude_newer {
+        Some(cutoff) => sparse_repo_datas
+            .into_iter()
+            .map(|repo_data| repo_data.with_cutoff(cutoff))
+            .collect(),
+        None => sparse_repo_datas,
     };
 
-    // Create a future to download the package
-    let cached_package_dir_fut = if let Some(install_record) = install_record {
-        async {
-            // Make sure the package is available in the package cache.
-            let result = package_cache
-                .get_or_fetch_from_url_with_retry(
-                    &install_record.package_record,
-                    install_record.url.clone(),
-                    download_client.clone(),
-                    default_retry_policy(),
-                )
-                .map_ok(|cache_dir| Some((install_record.clone(), cache_dir)))
-                .map_err(anyhow::Error::from)
-                .await;
-
-            // Increment the download progress bar.
-            if let Some(pb) = download_pb {
-                pb.inc(1);
-                if pb.length() == Some(pb.position()) {
-                    pb.set_style(finished_progress_style());
+    // Get the package names from the matchspecs so we can only load the package records that we need.
+    let package_names = specs.iter().filter_map(|spec| spec.name.as_ref().cloned());
+    let repodatas = wrap_in_progress("parsing repodata", move || {
+        SparseRepoData::load_records_recursive(
+            &sparse_repo_datas,
+            package_names,
+            Some(|record| {
+                if record.name.as_normalized() == "python" {
+                    record.depends.push("pip".to_string());
                 }
-            }
+            }),
+            true,
+        )
+    })?;
 
-            result
-        }
-        .left_future()
-    } else {
-        ready(Ok(None)).right_future()
-    };
+    // Determine virtual packages of the system. These packages define the capabilities of the
+    // system. Some packages depend on these virtual packages to indiciate compability with the
+    // hardware of the system.
+    let virtual_packages = wrap_in_progress("determining virtual packages", move || {
+        virtual_packages(opt.virtual_package.as_deref(), platform)
+    })?;
 
-    // Await removal and downloading concurrently
-    let (_, install_package) = tokio::try_join!(remove_future, cached_package_dir_fut)?;
-
-    // If there is a package to install, do that now.
-    if let Some((record, package_dir)) = install_package {
-        install_package_to_environment(
-            target_prefix,
-            package_dir,
-            record.clone(),
-            install_driver,
-            install_options,
+    println!("virtual packages: {:?}", virtual_packages);
+
+    // Now that we parsed and downloaded all information, solve the environment with the backend
+    // and the options that were selected. This provides us with all the packages that should be
+    // part of the environment.
+    let required_packages = wrap_in_progress("solving", move || {
+        solve_environment(
+            EnvironmentSpec {
+                available_packages: &repodatas,
+                specs,
+                constraints,
+                locked_packages,
+                virtual_packages,
+            },
+            solve_options,
         )
-        .await?;
-    }
+    })?;
 
-    // Increment the link progress bar since we finished a step!
-    link_pb.inc(1);
-    if link_pb.length() == Some(link_pb.position()) {
-        link_pb.set_style(finished_progress_style());
-    }
+    Ok(required_packages)
+}
 
-    Ok(())
+/// Returns the virtual packages to solve with. These are either parsed from the given
+/// `name=version=build` strings or, if none were given, determined for the `platform` that is
+/// solved for.
+pub(crate) fn virtual_packages(
+    virtual_packages: Option<&[String]>,
+    platform: Platform,
+) -> anyhow::Result<Vec<GenericVirtualPackage>> {
+    if let Some(virtual_packages) = virtual_packages {
+        Ok(virtual_packages
+            .iter()
+            .map(|virt_pkg| {
+                let elems = virt_pkg.split('=').collect::<Vec<&str>>();
+                Ok(GenericVirtualPackage {
+                    name: elems[0].try_into()?,
+                    version: elems
+                        .get(1)
+                        .map(|s| Version::from_str(s))
+                        .unwrap_or(Version::from_str("0"))
+                        .expect("Could not parse virtual package version"),
+                    build_string: elems.get(2).unwrap_or(&"").to_string(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?)
+    } else {
+        rattler_virtual_packages::VirtualPackage::for_platform(platform)
+            .map(|vpkgs| {
+                vpkgs
+                    .into_iter()
+                    .map(GenericVirtualPackage::from)
+                    .collect::<Vec<_>>()
+            })
+            .map_err(anyhow::Error::from)
+    }
 }
 
-/// Install a package into the environment and write a `conda-meta` file that contains information
-/// about how the file was linked.
-async fn install_package_to_environment(
-    target_prefix: &Path,
-    package_dir: PathBuf,
-    repodata_record: RepoDataRecord,
-    install_driver: &InstallDriver,
-    install_options: &InstallOptions,
-) -> anyhow::Result<()> {
-    // Link the contents of the package into our environment. This returns all the paths that were
-    // linked.
-    let paths = link_package(
-        &package_dir,
-        target_prefix,
-        install_driver,
-        install_options.clone(),
-    )
-    .await?;
+/// Constructs the client that is used to download repodata and packages. Credentials are read from
+/// the rattler authentication storage. Requests are logged when the `rattler_networking::http`
+/// target is enabled (see `--trace-http`) and recorded or replayed when a cassette is used (see
+/// `--record-http` and `--replay-http`). The client does not retry requests itself, the package
+/// cache retries downloads that failed with a transient error.
+pub(crate) fn download_client() -> anyhow::Result<AuthenticatedClient> {
+    let download_client = global_client_options()
+        .build_client()
+        .expect("failed to create client");
 
-    // Construct a PrefixRecord for the package
-    let prefix_record = PrefixRecord {
-        repodata_record,
-        package_tarball_full_path: None,
-        extracted_package_dir: Some(package_dir),
-        files: paths
-            .iter()
-            .map(|entry| entry.relative_path.clone())
-            .collect(),
-        paths_data: paths.into(),
-        // TODO: Retrieve the requested spec for this package from the request
-        requested_spec: None,
-        // TODO: What to do with this?
-        link: None,
-    };
+    let auth_dir = dirs::config_local_dir()
+        .ok_or_else(|| anyhow::anyhow!("could not determine cache directory for current platform"))?
+        .join("rattler/auth");
 
-    // Create the conda-meta directory if it doesnt exist yet.
-    let target_prefix = target_prefix.to_path_buf();
-    match tokio::task::spawn_blocking(move || {
-        let conda_meta_path = target_prefix.join("conda-meta");
-        std::fs::create_dir_all(&conda_meta_path)?;
-
-        // Write the conda-meta information
-        let pkg_meta_path = conda_meta_path.join(format!(
-            "{}-{}-{}.json",
-            prefix_record
-                .repodata_record
-                .package_record
-                .name
-                .as_normalized(),
-            prefix_record.repodata_record.package_record.version,
-            prefix_record.repodata_record.package_record.build
-        ));
-        prefix_record.write_to_path(pkg_meta_path, true)
+    let authentication_storage = AuthenticationStorage::new("rattler_credentials", &auth_dir);
+
+    let client = AuthenticatedClient::from_client(download_client, authentication_storage)
+        .with_http_trace(true);
+    Ok(match global_http_cassette() {
+        Some(cassette) => client.with_cassette(cassette),
+        None => client,
     })
-    .await
-    {
-        Ok(result) => Ok(result?),
-        Err(err) => {
-            if let Ok(panic) = err.try_into_panic() {
-                std::panic::resume_unwind(panic);
-            }
-            // The operation has been cancelled, so we can also just ignore everything.
-            Ok(())
-        }
+}
+
+/// The packages that failed to install or that were skipped with `--keep-going`.
+#[derive(Debug, Default, Serialize)]
+struct FailureReport {
+    failed: Vec<FailedPackage>,
+    skipped: Vec<SkippedPackage>,
+}
+
+impl FailureReport {
+    fn is_empty(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
     }
 }
 
-/// Completely remove the specified package from the environment.
-async fn remove_package_from_environment(
-    target_prefix: &Path,
-    package: &PrefixRecord,
-) -> anyhow::Result<()> {
-    // TODO: Take into account any clobbered files, they need to be restored.
-    // TODO: Can we also delete empty directories?
-
-    // Remove all entries
-    for paths in package.paths_data.paths.iter() {
-        match tokio::fs::remove_file(target_prefix.join(&paths.relative_path)).await {
-            Ok(_) => {}
-            Err(e) if e.kind() == ErrorKind::NotFound => {
-                // Simply ignore if the file is already gone.
-            }
-            Err(e) => {
-                return Err(e)
-                    .with_context(|| format!("failed to delete {}", paths.relative_path.display()))
-            }
+impl From<FailedOperations> for FailureReport {
+    fn from(failures: FailedOperations) -> Self {
+        Self {
+            failed: failures
+                .failed
+                .into_iter()
+                .map(|(package, error)| FailedPackage {
+                    package: package.as_normalized().to_owned(),
+                    error: error_chain(&error),
+                })
+                .collect(),
+            skipped: failures
+                .skipped
+                .into_iter()
+                .map(|(package, failed_dependency)| SkippedPackage {
+                    package: package.as_normalized().to_owned(),
+                    failed_dependency: failed_dependency.as_normalized().to_owned(),
+                })
+                .collect(),
         }
     }
+}
 
-    // Remove the conda-meta file
-    let conda_meta_path = target_prefix.join("conda-meta").join(format!(
-        "{}-{}-{}.json",
-        package.repodata_record.package_record.name.as_normalized(),
-        package.repodata_record.package_record.version,
-        package.repodata_record.package_record.build
-    ));
-    tokio::fs::remove_file(conda_meta_path).await?;
+/// A package that failed to install.
+#[derive(Debug, Serialize)]
+struct FailedPackage {
+    package: String,
+    error: String,
+}
 
-    Ok(())
+/// A package that was not installed because a package it depends on failed to install.
+#[derive(Debug, Serialize)]
+struct SkippedPackage {
+    package: String,
+    failed_dependency: String,
+}
+
+/// The packages that were still being linked when a transaction was interrupted, see
+/// [`Installer::with_interrupt_handler`].
+static INTERRUPTED_LINKS: Mutex<Vec<PartialLinks>> = Mutex::new(Vec::new());
+
+/// Removes the files of the packages that were only partially linked when a transaction was
+/// interrupted or failed. This first waits for the tasks that were still linking files of those
+/// packages, so no files are written after they have been removed.
+///
+/// This must be called before the process exits.
+pub(crate) async fn roll_back_interrupted_links() {
+    let interrupted = std::mem::take(&mut *INTERRUPTED_LINKS.lock().unwrap());
+    for partial_links in interrupted {
+        partial_links.roll_back().await;
+    }
 }
 
 /// Displays a spinner with the given message while running the specified function to completion.
@@ -565,38 +895,31 @@ fn wrap_in_progress<T, F: FnOnce() -> T>(msg: impl Into<Cow<'static, str>>, func
     result
 }
 
-/// Given a channel and platform, download and cache the `repodata.json` for it. This function
-/// reports its progress via a CLI progressbar.
-async fn fetch_repo_data_records_with_progress(
-    channel: Channel,
+/// Adds a progress bar that reports the progress of fetching the repodata of the given channel
+/// and platform.
+fn repodata_progress_bar(
+    channel: &Channel,
     platform: Platform,
-    repodata_cache: &Path,
-    client: AuthenticatedClient,
-    multi_progress: indicatif::MultiProgress,
-) -> Result<Option<SparseRepoData>, anyhow::Error> {
-    // Create a progress bar
+    multi_progress: &indicatif::MultiProgress,
+) -> ProgressBar {
     let progress_bar = multi_progress.add(
         indicatif::ProgressBar::new(1)
             .with_finish(indicatif::ProgressFinish::AndLeave)
-            .with_prefix(format!("{}/{platform}", friendly_channel_name(&channel)))
+            .with_prefix(format!("{}/{platform}", friendly_channel_name(channel)))
             .with_style(default_bytes_style()),
     );
     progress_bar.enable_steady_tick(Duration::from_millis(100));
+    progress_bar
+}
 
-    // Download the repodata.json
-    let download_progress_progress_bar = progress_bar.clone();
-    let result = rattler_repodata_gateway::fetch::fetch_repo_data(
-        channel.platform_url(platform),
-        client,
-        repodata_cache.to_path_buf(),
-        FetchRepoDataOptions::default(),
-        Some(Box::new(move |DownloadProgress { total, bytes }| {
-            download_progress_progress_bar.set_length(total.unwrap_or(bytes));
-            download_progress_progress_bar.set_position(bytes);
-        })),
-    )
-    .await;
-
+/// Given the result of fetching the `repodata.json` of a channel and platform, load it as sparse
+/// repodata. This function reports its progress via a CLI progressbar.
+async fn load_repo_data_records_with_progress(
+    channel: Channel,
+    platform: Platform,
+    result: Result<CachedRepoData, FetchRepoDataError>,
+    progress_bar: ProgressBar,
+) -> Result<Option<SparseRepoData>, anyhow::Error> {
     // Error out if an error occurred, but also update the progress bar
     let result = match result {
         Err(e) => {
@@ -642,7 +965,9 @@ async fn fetch_repo_data_records_with_progress(
                 CacheResult::CacheHit | CacheResult::CacheHitAfterFetch
             );
             progress_bar.finish_with_message(if is_cache_hit { "Using cache" } else { "Done" });
-            Ok(Some(repodata))
+            Ok(Some(
+                repodata.with_freshness(result.cache_state.freshness()),
+            ))
         }
         Ok(Err(err)) => {
             progress_bar.set_style(errored_progress_style());
@@ -663,6 +988,44 @@ async fn fetch_repo_data_records_with_progress(
     }
 }
 
+/// Warns about repodata that was fetched longer than `stale_after` before `now` and returns an
+/// error if any repodata was fetched longer than `max_staleness` before `now`.
+fn check_staleness(
+    repo_datas: &[SparseRepoData],
+    now: DateTime<Utc>,
+    stale_after: Duration,
+    max_staleness: Option<Duration>,
+) -> anyhow::Result<()> {
+    for repo_data in repo_datas {
+        let Some(freshness) = repo_data.freshness() else {
+            continue;
+        };
+
+        // Sub-second precision only clutters the messages.
+        let age = Duration::from_secs(freshness.age(now).as_secs());
+        let name = format!(
+            "{}/{}",
+            friendly_channel_name(repo_data.channel()),
+            repo_data.subdir()
+        );
+        if let Some(max_staleness) = max_staleness.filter(|max_staleness| age > *max_staleness) {
+            anyhow::bail!(
+                "the repodata of {name} was fetched {} ago, which is longer ago than the maximum of {}",
+                humantime::format_duration(age),
+                humantime::format_duration(max_staleness)
+            );
+        }
+        if age > stale_after {
+            println!(
+                "{} the repodata of {name} was fetched {} ago and might be out of date",
+                console::style(console::Emoji("⚠", "")).yellow(),
+                humantime::format_duration(age)
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Returns a friendly name for the specified channel.
 fn friendly_channel_name(channel: &Channel) -> String {
     channel
@@ -730,9 +1093,42 @@ fn long_running_progress_style() -> indicatif::ProgressStyle {
     ProgressStyle::with_template("{spinner:.green} {msg}").unwrap()
 }
 
+/// Returns the installed packages of which files are missing or damaged in the prefix, e.g.
+/// because a previous installation was interrupted while it replaced them. Packages that were
+/// completely installed before a failure are left alone, so a failed installation can simply be
+/// retried. The damaged packages should be reinstalled with [`Transaction::reinstall`].
+pub(crate) async fn find_damaged_packages(
+    target_prefix: &Path,
+    installed_packages: &[PrefixRecord],
+) -> anyhow::Result<Vec<PrefixRecord>> {
+    let target_prefix = target_prefix.to_path_buf();
+    let installed_packages = installed_packages.to_vec();
+    let damaged = tokio::task::spawn_blocking(move || {
+        installed_packages
+            .into_iter()
+            .filter(
+                |record| match verify_installed_package(&target_prefix, record) {
+                    Ok(()) => false,
+                    Err(err) => {
+                        println!(
+                            "{} {} {} is damaged and will be reinstalled: {err}",
+                            console::style(console::Emoji("⚠", "")).yellow(),
+                            record.repodata_record.package_record.name.as_normalized(),
+                            record.repodata_record.package_record.version
+                        );
+                        true
+                    }
+                },
+            )
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    Ok(damaged)
+}
+
 /// Scans the conda-meta directory of an environment and returns all the [`PrefixRecord`]s found in
 /// there.
-async fn find_installed_packages(
+pub(crate) async fn find_installed_packages(
     target_prefix: &Path,
     concurrency_limit: usize,
 ) -> Result<Vec<PrefixRecord>, std::io::Error> {
@@ -745,7 +1141,7 @@ async fn find_installed_packages(
     {
         let entry = entry?;
         let path = entry.path();
-        if path.ends_with(".json") {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
             continue;
         }
 
@@ -0,0 +1,46 @@
+use rattler_conda_types::{EnvironmentDiff, PackageChangeKind, PackageRecord};
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The prefix of the old environment
+    old: PathBuf,
+
+    /// The prefix of the new environment
+    new: PathBuf,
+}
+
+pub fn diff(opt: Opt) -> anyhow::Result<()> {
+    let diff = EnvironmentDiff::from_prefixes(&opt.old, &opt.new)?;
+    if diff.is_empty() {
+        println!(
+            "{} The environments contain the same packages",
+            console::style(console::Emoji("✔", "")).green(),
+        );
+        return Ok(());
+    }
+
+    let format_record =
+        |r: &PackageRecord| format!("{} {} {}", r.name.as_normalized(), r.version, r.build);
+
+    for record in &diff.added {
+        println!("* Added: {}", format_record(record));
+    }
+    for record in &diff.removed {
+        println!("* Removed: {}", format_record(record));
+    }
+    for change in &diff.changed {
+        let kind = match change.kind() {
+            PackageChangeKind::Upgrade => "Upgraded",
+            PackageChangeKind::Downgrade => "Downgraded",
+            PackageChangeKind::Rebuild => "Rebuilt",
+        };
+        println!(
+            "* {kind}: {} -> {}",
+            format_record(&change.old),
+            format_record(&change.new)
+        );
+    }
+
+    Ok(())
+}
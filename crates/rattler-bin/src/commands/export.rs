@@ -0,0 +1,93 @@
+use itertools::Itertools;
+use rattler::install::{find_pip_distributions, PythonDistribution, PythonInfo};
+use rattler_conda_types::{
+    EnvironmentYaml, ExplicitEnvironmentSpec, MatchSpec, PackageRecord, Platform, PrefixRecord,
+};
+use std::{path::Path, path::PathBuf, str::FromStr};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The prefix of the environment to export
+    prefix: PathBuf,
+
+    /// Export the environment as an `@EXPLICIT` file that lists the urls of the packages anchored
+    /// with the hashes of their archives, similar to `conda list --explicit --sha256`.
+    #[clap(long)]
+    explicit: bool,
+}
+
+pub fn export(opt: Opt) -> anyhow::Result<()> {
+    let records = PrefixRecord::collect_from_prefix(&opt.prefix)?
+        .into_iter()
+        .sorted_by(|a, b| {
+            a.repodata_record
+                .package_record
+                .name
+                .cmp(&b.repodata_record.package_record.name)
+        })
+        .collect::<Vec<_>>();
+    if opt.explicit {
+        let records = PackageRecord::sort_topologically(records);
+        let platform = records
+            .iter()
+            .map(|r| &r.repodata_record.package_record.subdir)
+            .find(|subdir| subdir.as_str() != "noarch")
+            .and_then(|subdir| Platform::from_str(subdir).ok());
+        let environment = ExplicitEnvironmentSpec::from_records(
+            records.iter().map(|r| &r.repodata_record),
+            platform,
+        );
+        print!("{environment}");
+        return Ok(());
+    }
+
+    let pip_distributions = installed_pip_distributions(&opt.prefix, &records)?;
+
+    let environment = EnvironmentYaml {
+        name: None,
+        channels: records
+            .iter()
+            .map(|r| r.repodata_record.channel.clone())
+            .unique()
+            .collect(),
+        dependencies: records
+            .iter()
+            .map(|r| {
+                let record = &r.repodata_record.package_record;
+                MatchSpec::from_str(&format!(
+                    "{}=={}={}",
+                    record.name.as_normalized(),
+                    record.version,
+                    record.build
+                ))
+            })
+            .collect::<Result<_, _>>()?,
+        pip_dependencies: pip_distributions
+            .iter()
+            .map(PythonDistribution::requirement)
+            .collect(),
+    };
+
+    print!("{}", environment.to_yaml_string());
+
+    Ok(())
+}
+
+/// Returns the Python distributions in the prefix that were not installed by conda. Returns an
+/// empty list if Python is not installed in the prefix.
+pub fn installed_pip_distributions(
+    prefix: &Path,
+    records: &[PrefixRecord],
+) -> anyhow::Result<Vec<PythonDistribution>> {
+    let Some(python) = records
+        .iter()
+        .map(|r| &r.repodata_record.package_record)
+        .find(|r| r.name.as_normalized() == "python")
+    else {
+        return Ok(Vec::new());
+    };
+
+    let platform = Platform::from_str(&python.subdir).unwrap_or_else(|_| Platform::current());
+    let python_info = PythonInfo::from_version(python.version.version(), platform)?;
+    Ok(find_pip_distributions(prefix, &python_info, records)?)
+}
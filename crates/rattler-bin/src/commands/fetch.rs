@@ -0,0 +1,86 @@
+use crate::{
+    commands::create::{download_client, solve, SolveOpt},
+    global_download_manager, global_multi_progress,
+};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use rattler::package_cache::{download_packages_with_progress, PackageCache};
+use rattler_networking::progress::DownloadProgress;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    #[clap(flatten)]
+    solve: SolveOpt,
+
+    /// Also keep the original package archives in the cache
+    #[clap(long)]
+    keep_archives: bool,
+}
+
+/// Solves the specs and downloads the required packages into the package cache without installing
+/// them.
+pub async fn fetch(opt: Opt) -> anyhow::Result<()> {
+    let platform = opt.solve.platform()?;
+
+    let cache_dir = crate::config::cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+
+    let download_client = download_client()?;
+    let records = solve(
+        opt.solve,
+        platform,
+        Vec::new(),
+        Vec::new(),
+        &cache_dir,
+        download_client.clone(),
+    )
+    .await?;
+
+    let package_cache = PackageCache::new(cache_dir.join("pkgs"))
+        .with_retained_archives(opt.keep_archives)
+        .with_download_manager(global_download_manager());
+    let progress_bar = global_multi_progress().add(
+        ProgressBar::new(1).with_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} {prefix:20!} [{bar:40!.bright.yellow/dim.white}] {bytes:>8} {msg}")
+                .unwrap()
+                .progress_chars("━━╾─"),
+        ).with_prefix("downloading packages"),
+    );
+    let paths = download_packages_with_progress(
+        records,
+        &package_cache,
+        download_client,
+        |DownloadProgress {
+             bytes,
+             total,
+             speed,
+             eta,
+         }| {
+            progress_bar.set_length(total.unwrap_or(bytes));
+            progress_bar.set_position(bytes);
+            progress_bar.set_message(match (speed, eta) {
+                (Some(speed), Some(eta)) => {
+                    format!(
+                        "@ {}/s, {} left",
+                        HumanBytes(speed as u64),
+                        HumanDuration(eta)
+                    )
+                }
+                (Some(speed), None) => format!("@ {}/s", HumanBytes(speed as u64)),
+                _ => String::new(),
+            });
+        },
+    )
+    .await?;
+    progress_bar.finish_and_clear();
+
+    println!(
+        "{} Downloaded {} packages to {}",
+        console::style(console::Emoji("✔", "")).green(),
+        paths.len(),
+        cache_dir.join("pkgs").display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::commands::create::{
+    apply_transaction, download_client, find_damaged_packages, find_installed_packages,
+    print_transaction, KeepGoingOpt,
+};
+use crate::global_download_manager;
+use anyhow::Context;
+use futures::{stream, StreamExt, TryStreamExt};
+use rattler::{install::Transaction, package_cache::PackageCache};
+use rattler_conda_types::{
+    package::{IndexJson, PackageFile},
+    ExplicitEnvironmentEntry, ExplicitEnvironmentSpec, InstallablePackage, PackageRecord, Platform,
+    RepoDataRecord,
+};
+use rattler_networking::{retry_policies::DoNotRetryPolicy, AuthenticatedClient};
+use std::{collections::HashMap, env, path::PathBuf, str::FromStr};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The `@EXPLICIT` environment file to install, e.g. written by `rattler export --explicit`
+    file: PathBuf,
+
+    /// The platform to install for (e.g. `linux-64`). Defaults to the current platform.
+    #[clap(long)]
+    platform: Option<String>,
+
+    /// Only show the operations that would be performed
+    #[clap(long)]
+    dry_run: bool,
+
+    #[clap(flatten)]
+    keep_going: KeepGoingOpt,
+}
+
+/// Installs the packages listed in an explicit environment file without solving. Archives whose
+/// url is anchored with a hash are verified against it before they are installed.
+pub async fn install(opt: Opt) -> anyhow::Result<()> {
+    let target_prefix = env::current_dir()?.join(".prefix");
+    let environment = ExplicitEnvironmentSpec::from_path(&opt.file)
+        .with_context(|| format!("failed to read {}", opt.file.display()))?;
+    let platform = match &opt.platform {
+        Some(platform) => Platform::from_str(platform)?,
+        None => Platform::current(),
+    };
+
+    // Fail early instead of with hard to understand errors halfway through the installation.
+    environment.check_platform(platform)?;
+
+    let cache_dir = crate::config::cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+
+    let installed_packages = find_installed_packages(&target_prefix, 100)
+        .await
+        .context("failed to determine currently installed packages")?;
+
+    // The records of the packages are read from the archives, so they have to be fetched first.
+    let download_client = download_client()?;
+    let package_cache =
+        PackageCache::new(cache_dir.join("pkgs")).with_download_manager(global_download_manager());
+    let required_packages = stream::iter(&environment.packages)
+        .map(|entry| fetch_record(&package_cache, entry, download_client.clone()))
+        .buffered(50)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let required_packages = PackageRecord::sort_topologically(required_packages);
+
+    let installed_records = installed_packages
+        .iter()
+        .map(|record| record.repodata_record.package_record.clone())
+        .collect::<Vec<_>>();
+    let damaged_packages = find_damaged_packages(&target_prefix, &installed_packages).await?;
+    let mut transaction =
+        Transaction::from_current_and_desired(installed_packages, required_packages, platform)?;
+    transaction.reinstall(damaged_packages);
+
+    if opt.dry_run {
+        print_transaction(&transaction);
+        return Ok(());
+    }
+
+    apply_transaction(
+        transaction,
+        &installed_records,
+        &HashMap::new(),
+        target_prefix,
+        cache_dir,
+        download_client,
+        &opt.keep_going,
+    )
+    .await
+}
+
+/// Fetches the package of an entry into the cache and constructs its record from the `index.json`
+/// of the package. If the url of the entry is anchored with a hash the archive is verified against
+/// it.
+async fn fetch_record(
+    package_cache: &PackageCache,
+    entry: &ExplicitEnvironmentEntry,
+    client: AuthenticatedClient,
+) -> anyhow::Result<RepoDataRecord> {
+    // Reject a malformed hash anchor instead of silently skipping the verification.
+    entry.package_archive_hash()?;
+    let url = entry.url().into_owned();
+    let file_name = entry.file_name().into_owned();
+
+    let package_dir = package_cache
+        .get_or_fetch_package_with_retry(entry, client, DoNotRetryPolicy)
+        .await
+        .with_context(|| format!("failed to fetch {url}"))?;
+
+    let index_json = IndexJson::from_package_directory(package_dir)
+        .with_context(|| format!("failed to read the index.json of {file_name}"))?;
+    let package_record =
+        PackageRecord::from_index_json(index_json, None, entry.sha256(), entry.md5())?;
+
+    // The url of a package is `<channel>/<subdir>/<file name>`.
+    let channel = url.join("..")?;
+    Ok(RepoDataRecord {
+        package_record,
+        file_name,
+        channel: channel.to_string(),
+        url,
+    })
+}
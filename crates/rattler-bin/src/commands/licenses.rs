@@ -0,0 +1,49 @@
+use rattler_conda_types::LicenseReport;
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The prefix of the environment to report the licenses of
+    prefix: PathBuf,
+
+    /// Output the report as JSON
+    #[clap(long, conflicts_with = "csv")]
+    json: bool,
+
+    /// Output the report as CSV
+    #[clap(long)]
+    csv: bool,
+}
+
+pub fn licenses(opt: Opt) -> anyhow::Result<()> {
+    let report = LicenseReport::from_prefix(&opt.prefix)?;
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    } else if opt.csv {
+        print!("{}", report.to_csv());
+        return Ok(());
+    }
+
+    for (license, packages) in report.by_license() {
+        println!("{license}");
+        for package in packages {
+            println!("    {} {} {}", package.name, package.version, package.build);
+        }
+    }
+
+    let unlicensed = report.unlicensed().collect::<Vec<_>>();
+    if !unlicensed.is_empty() {
+        println!(
+            "{} {} packages do not specify a license:",
+            console::style(console::Emoji("⚠", "")).yellow(),
+            unlicensed.len()
+        );
+        for package in unlicensed {
+            println!("    {} {} {}", package.name, package.version, package.build);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use super::export::installed_pip_distributions;
+use itertools::Itertools;
+use rattler_conda_types::PrefixRecord;
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The prefix of the environment to list the packages of
+    prefix: PathBuf,
+
+    /// Only list the packages that were explicitly requested, not the ones that were installed as
+    /// dependencies
+    #[clap(long)]
+    requested: bool,
+}
+
+pub fn list(opt: Opt) -> anyhow::Result<()> {
+    let records = PrefixRecord::collect_from_prefix(&opt.prefix)?;
+    let pip_distributions = installed_pip_distributions(&opt.prefix, &records)?;
+
+    for record in records
+        .iter()
+        .filter(|r| !opt.requested || r.requested_spec.is_some())
+        .sorted_by(|a, b| {
+            a.repodata_record
+                .package_record
+                .name
+                .cmp(&b.repodata_record.package_record.name)
+        })
+    {
+        let requested = match &record.requested_spec {
+            Some(spec) => format!(" (requested: {spec})"),
+            None => String::new(),
+        };
+        let record_data = &record.repodata_record;
+        println!(
+            "{} {} {} {}{}",
+            record_data.package_record.name.as_normalized(),
+            record_data.package_record.version,
+            record_data.package_record.build,
+            record_data.channel,
+            requested
+        );
+    }
+    if opt.requested {
+        return Ok(());
+    }
+    for distribution in &pip_distributions {
+        println!("{} {} pypi", distribution.name, distribution.version);
+    }
+
+    Ok(())
+}
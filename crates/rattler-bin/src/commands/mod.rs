@@ -1 +1,16 @@
+pub mod cache;
+pub mod config;
 pub mod create;
+pub mod diff;
+pub mod export;
+pub mod fetch;
+pub mod install;
+pub mod licenses;
+pub mod list;
+pub mod relocate;
+pub mod remove;
+pub mod repodata_diff;
+pub mod sbom;
+pub mod serve;
+pub mod trim;
+pub mod update;
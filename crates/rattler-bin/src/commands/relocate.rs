@@ -0,0 +1,59 @@
+use rattler::install::{relocate_prefix, AppleCodeSignBehavior};
+use rattler_conda_types::Platform;
+use std::{env, path::PathBuf, str::FromStr};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The path the environment was installed to, which is embedded in its files
+    #[clap(long)]
+    from: String,
+
+    /// The path the environment is going to be used from. Defaults to the current location of
+    /// the environment.
+    #[clap(long)]
+    to: Option<String>,
+
+    /// The environment to relocate. Defaults to `.prefix` in the current directory.
+    #[clap(long)]
+    prefix: Option<PathBuf>,
+
+    /// The platform of the environment. Defaults to the current platform.
+    #[clap(long)]
+    platform: Option<String>,
+}
+
+/// Replaces the path the environment was installed to in all of its files that contain it, so the
+/// environment can be used from another location.
+pub fn relocate(opt: Opt) -> anyhow::Result<()> {
+    let prefix = match opt.prefix {
+        Some(prefix) => prefix,
+        None => env::current_dir()?.join(".prefix"),
+    };
+    let to = match opt.to {
+        Some(to) => to,
+        None => std::fs::canonicalize(&prefix)?
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let platform = match opt.platform {
+        Some(platform) => Platform::from_str(&platform)?,
+        None => Platform::current(),
+    };
+
+    let relocated = relocate_prefix(
+        &prefix,
+        &opt.from,
+        &to,
+        platform,
+        AppleCodeSignBehavior::Fail,
+    )?;
+
+    println!(
+        "{} Relocated {} files from {} to {to}",
+        console::style(console::Emoji("✔", "")).green(),
+        relocated.len(),
+        opt.from
+    );
+
+    Ok(())
+}
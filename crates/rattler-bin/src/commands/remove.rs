@@ -0,0 +1,70 @@
+use crate::commands::create::{
+    apply_transaction, download_client, find_installed_packages, print_transaction, KeepGoingOpt,
+};
+use anyhow::Context;
+use rattler::install::remove::{plan_remove, RemoveOptions};
+use rattler_conda_types::PackageName;
+use std::{collections::HashMap, env};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The names of the packages to remove
+    #[clap(required = true)]
+    names: Vec<String>,
+
+    /// Also remove dependencies that are no longer required
+    #[clap(long)]
+    prune: bool,
+
+    /// Only remove the given packages, even if other packages depend on them
+    #[clap(long)]
+    force: bool,
+
+    /// Only show the operations that would be performed
+    #[clap(long)]
+    dry_run: bool,
+}
+
+/// Removes packages from the environment together with the packages that depend on them.
+pub async fn remove(opt: Opt) -> anyhow::Result<()> {
+    let target_prefix = env::current_dir()?.join(".prefix");
+
+    let cache_dir = crate::config::cache_dir()?;
+    let installed_packages = find_installed_packages(&target_prefix, 100)
+        .await
+        .context("failed to determine currently installed packages")?;
+    let installed_records = installed_packages
+        .iter()
+        .map(|record| record.repodata_record.package_record.clone())
+        .collect::<Vec<_>>();
+
+    let names = opt
+        .names
+        .iter()
+        .map(|name| PackageName::try_from(name.as_str()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let transaction = plan_remove(
+        installed_packages,
+        &names,
+        RemoveOptions {
+            prune: opt.prune,
+            force: opt.force,
+        },
+    )?;
+
+    print_transaction(&transaction);
+    if opt.dry_run {
+        return Ok(());
+    }
+
+    apply_transaction(
+        transaction,
+        &installed_records,
+        &HashMap::new(),
+        target_prefix,
+        cache_dir,
+        download_client()?,
+        &KeepGoingOpt::default(),
+    )
+    .await
+}
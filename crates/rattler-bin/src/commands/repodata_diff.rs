@@ -0,0 +1,63 @@
+use anyhow::Context;
+use rattler_conda_types::RepoData;
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The old `repodata.json`
+    old: PathBuf,
+
+    /// The new `repodata.json`
+    new: PathBuf,
+
+    /// Output the differences as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+pub fn repodata_diff(opt: Opt) -> anyhow::Result<()> {
+    let read = |path: &PathBuf| {
+        RepoData::from_path(path).with_context(|| format!("failed to read {}", path.display()))
+    };
+    let diff = read(&opt.old)?.diff(&read(&opt.new)?);
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    if diff.is_empty() {
+        println!(
+            "{} The repodata contains the same packages",
+            console::style(console::Emoji("✔", "")).green(),
+        );
+        return Ok(());
+    }
+
+    for file_name in &diff.added {
+        println!("* Added: {file_name}");
+    }
+    for file_name in &diff.removed {
+        println!("* Removed: {file_name}");
+    }
+    for change in &diff.changed {
+        println!("* Changed: {}", change.file_name);
+        for dep in &change.added_depends {
+            println!("    + depends: {dep}");
+        }
+        for dep in &change.removed_depends {
+            println!("    - depends: {dep}");
+        }
+        for constraint in &change.added_constrains {
+            println!("    + constrains: {constraint}");
+        }
+        for constraint in &change.removed_constrains {
+            println!("    - constrains: {constraint}");
+        }
+        for field in &change.changed_fields {
+            println!("    ~ {}: {} -> {}", field.field, field.old, field.new);
+        }
+    }
+
+    Ok(())
+}
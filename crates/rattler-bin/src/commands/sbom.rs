@@ -0,0 +1,49 @@
+use crate::global_clock;
+use rattler_conda_types::Sbom;
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The prefix of the environment to export the software bill of materials of
+    prefix: PathBuf,
+
+    /// Output an SPDX document instead of a CycloneDX document
+    #[clap(long)]
+    spdx: bool,
+
+    /// The URI that uniquely identifies the SPDX document. Defaults to the location of the prefix
+    /// and the time the document was created.
+    #[clap(long, requires = "spdx")]
+    namespace: Option<Url>,
+
+    /// The name of the environment, defaults to the name of the prefix directory
+    #[clap(long)]
+    name: Option<String>,
+}
+
+pub fn sbom(opt: Opt) -> anyhow::Result<()> {
+    let prefix = std::fs::canonicalize(&opt.prefix)?;
+    let mut sbom = Sbom::from_prefix(&prefix, global_clock().as_ref())?;
+    if let Some(name) = opt.name {
+        sbom.name = name;
+    }
+
+    let document = if opt.spdx {
+        let namespace = match opt.namespace {
+            Some(namespace) => namespace,
+            None => {
+                // SPDX does not allow a `#` in the namespace, so the time is added as a segment.
+                Url::from_directory_path(&prefix)
+                    .map_err(|_| anyhow::anyhow!("invalid prefix {}", prefix.display()))?
+                    .join(&sbom.created.timestamp().to_string())?
+            }
+        };
+        sbom.to_spdx_json(&namespace)
+    } else {
+        sbom.to_cyclonedx_json()
+    };
+    println!("{}", serde_json::to_string_pretty(&document)?);
+
+    Ok(())
+}
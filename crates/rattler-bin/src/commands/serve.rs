@@ -0,0 +1,445 @@
+//! A long running server that solves, fetches and installs environments on behalf of clients
+//! that connect to it over a local socket.
+//!
+//! Parsing repodata is by far the most expensive part of solving an environment. The server keeps
+//! the repodata of every channel subdirectory it has seen in memory and only reloads it when the
+//! `repodata.json` on disk changed, so repeated requests (e.g. from an IDE or a build farm) only
+//! pay for solving.
+//!
+//! Clients communicate using JSON-RPC 2.0. Every request and every response is a single line of
+//! JSON. Requests without an `id` are notifications, they are handled but not answered. The
+//! supported methods are:
+//!
+//! * `solve`: solves the specs and returns the records of the packages in the environment.
+//! * `fetch`: solves the specs and downloads the required packages into the package cache.
+//! * `install`: installs the given records into a prefix, removing packages that are not part of
+//!   the records.
+
+use crate::{
+    commands::create::{
+        apply_transaction, download_client, find_installed_packages, virtual_packages, KeepGoingOpt,
+    },
+    config::RattlerConfig,
+    global_download_manager,
+};
+use anyhow::Context;
+use rattler::{
+    install::{environment_platform, Transaction},
+    package_cache::{download_packages, PackageCache},
+};
+use rattler_conda_types::{Channel, MatchSpec, PackageRecord, Platform, RepoDataRecord};
+use rattler_networking::AuthenticatedClient;
+use rattler_repodata_gateway::fetch::{
+    fetch_repo_data, CacheResult, FetchRepoDataError, FetchRepoDataOptions,
+};
+use rattler_repodata_gateway::sparse::SparseRepoData;
+use rattler_solve::{solve_environment, EnvironmentSpec, SolveOptionsSpec};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use url::Url;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The path of the socket to listen on
+    #[clap(long, default_value = "rattler.sock")]
+    socket: PathBuf,
+}
+
+/// Listens on the socket and handles the requests of clients until the process is interrupted.
+#[cfg(unix)]
+pub async fn serve(opt: Opt) -> anyhow::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+
+    // A socket file is left behind if a previous server was not shut down cleanly. Only remove it
+    // if no server is listening on it anymore.
+    if opt.socket.exists() {
+        if UnixStream::connect(&opt.socket).await.is_ok() {
+            anyhow::bail!("a server is already listening on {}", opt.socket.display());
+        }
+        std::fs::remove_file(&opt.socket)
+            .with_context(|| format!("failed to remove stale socket {}", opt.socket.display()))?;
+    }
+    let listener = UnixListener::bind(&opt.socket)
+        .with_context(|| format!("failed to listen on {}", opt.socket.display()))?;
+    let _socket = SocketFile(opt.socket.clone());
+
+    let cache_dir = crate::config::cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+    let server = Arc::new(Server {
+        cache_dir,
+        download_client: download_client()?,
+        repodata: Mutex::default(),
+    });
+
+    println!("listening on {}", opt.socket.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Some(response) = server.handle(&line).await else {
+                    continue;
+                };
+                let mut response =
+                    serde_json::to_vec(&response).expect("responses can always be serialized");
+                response.push(b'\n');
+                if writer.write_all(&response).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Listens on the socket and handles the requests of clients until the process is interrupted.
+#[cfg(not(unix))]
+pub async fn serve(_opt: Opt) -> anyhow::Result<()> {
+    anyhow::bail!("serving over a local socket is only supported on unix")
+}
+
+/// Removes the socket file when the server stops.
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A JSON-RPC request. A request without an `id` is a notification, which is not answered.
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    jsonrpc: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A JSON-RPC response. Exactly one of `result` and `error` is set.
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+/// The error of a request that failed.
+#[derive(Debug, Serialize)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+/// Deserializes a field that is present, distinguishing an `id` of `null` from a missing `id`.
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// The error codes defined by the JSON-RPC specification.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// An error code for requests that were understood but failed.
+const REQUEST_FAILED: i64 = -32000;
+
+/// The parameters of the `solve` and `fetch` methods.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SolveParams {
+    specs: Vec<String>,
+    #[serde(default)]
+    channels: Option<Vec<String>>,
+    #[serde(default)]
+    platform: Option<Platform>,
+    #[serde(default)]
+    virtual_packages: Option<Vec<String>>,
+    #[serde(default)]
+    pins: Vec<String>,
+    #[serde(default)]
+    locked_packages: Vec<RepoDataRecord>,
+    #[serde(flatten)]
+    options: SolveOptionsSpec,
+}
+
+/// The parameters of the `install` method.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstallParams {
+    prefix: PathBuf,
+    packages: Vec<RepoDataRecord>,
+    #[serde(default)]
+    platform: Option<Platform>,
+}
+
+/// The repodata of a channel subdirectory that is kept in memory between requests.
+struct WarmRepoData {
+    /// The path of the `repodata.json` the data was loaded from.
+    path: PathBuf,
+    repodata: Arc<SparseRepoData>,
+}
+
+/// The state that is shared between all requests.
+struct Server {
+    cache_dir: PathBuf,
+    download_client: AuthenticatedClient,
+    repodata: Mutex<HashMap<Url, WarmRepoData>>,
+}
+
+impl Server {
+    /// Handles a single line received from a client. Returns `None` if the line is a
+    /// notification, which must not be answered.
+    async fn handle(&self, line: &str) -> Option<Response> {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(request) => request,
+            Err(e) => return Some(Response::error(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        if request.jsonrpc.as_deref() != Some("2.0") {
+            return Some(Response::error(
+                request.id.unwrap_or_default(),
+                INVALID_REQUEST,
+                String::from("only JSON-RPC 2.0 requests are supported"),
+            ));
+        }
+
+        let response = self.handle_request(request.method, request.params).await;
+        let id = request.id?;
+        Some(match response {
+            Ok(result) => Response {
+                jsonrpc: "2.0",
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err((code, message)) => Response::error(id, code, message),
+        })
+    }
+
+    /// Calls the method of a request. Returns the result, or the error code and message if the
+    /// request failed.
+    async fn handle_request(&self, method: String, params: Value) -> Result<Value, (i64, String)> {
+        let invalid_params = |e: serde_json::Error| (INVALID_PARAMS, e.to_string());
+        let result = match method.as_str() {
+            "solve" => {
+                let params = serde_json::from_value(params).map_err(invalid_params)?;
+                self.solve(params).await.map(|records| json(&records))
+            }
+            "fetch" => {
+                let params = serde_json::from_value(params).map_err(invalid_params)?;
+                self.fetch(params).await.map(|paths| json(&paths))
+            }
+            "install" => {
+                let params = serde_json::from_value(params).map_err(invalid_params)?;
+                self.install(params).await.map(|count| json(&count))
+            }
+            method => return Err((METHOD_NOT_FOUND, format!("unknown method '{method}'"))),
+        };
+        result.map_err(|e| (REQUEST_FAILED, format!("{e:#}")))
+    }
+
+    /// Solves the specs and returns the records of the packages in the environment, sorted
+    /// topologically.
+    async fn solve(&self, params: SolveParams) -> anyhow::Result<Vec<RepoDataRecord>> {
+        let config = RattlerConfig::load()?;
+        let channel_config = config.channel_config();
+        let platform = params.platform.unwrap_or_else(Platform::current);
+        let solve_options = params.options.to_solve_options()?;
+        let specs = params
+            .specs
+            .iter()
+            .map(|spec| MatchSpec::from_str(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+        let constraints = params
+            .pins
+            .iter()
+            .map(|spec| MatchSpec::from_str(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+        let channels = channel_config
+            .resolve_channels(params.channels.unwrap_or_else(|| config.default_channels()))?;
+
+        let mut sparse_repo_datas = Vec::new();
+        for channel in channels {
+            for platform in channel.subdirs_for_platform(platform) {
+                if let Some(repodata) = self.repodata(channel.clone(), platform).await? {
+                    sparse_repo_datas.push(repodata);
+                }
+            }
+        }
+
+        let virtual_packages = virtual_packages(params.virtual_packages.as_deref(), platform)?;
+        let locked_packages = params.locked_packages;
+        let records = tokio::task::spawn_blocking(move || {
+            let package_names = specs.iter().filter_map(|spec| spec.name.clone());
+            let repodatas = SparseRepoData::load_records_recursive(
+                sparse_repo_datas.iter().map(AsRef::as_ref),
+                package_names,
+                None,
+                true,
+            )?;
+            anyhow::Ok(solve_environment(
+                EnvironmentSpec {
+                    available_packages: &repodatas,
+                    specs,
+                    constraints,
+                    locked_packages,
+                    virtual_packages,
+                },
+                solve_options,
+            )?)
+        })
+        .await??;
+
+        Ok(PackageRecord::sort_topologically(records))
+    }
+
+    /// Solves the specs and downloads the required packages into the package cache. Returns the
+    /// directories of the packages in the cache.
+    async fn fetch(&self, params: SolveParams) -> anyhow::Result<Vec<PathBuf>> {
+        let records = self.solve(params).await?;
+        let package_cache = PackageCache::new(self.cache_dir.join("pkgs"))
+            .with_download_manager(global_download_manager());
+        Ok(download_packages(records, &package_cache, self.download_client.clone()).await?)
+    }
+
+    /// Installs the packages into the prefix and returns the number of operations that were
+    /// performed.
+    async fn install(&self, params: InstallParams) -> anyhow::Result<usize> {
+        let installed_packages = find_installed_packages(&params.prefix, 100)
+            .await
+            .context("failed to determine currently installed packages")?;
+
+        // The prefix is not necessarily an environment for the current platform, so default to the
+        // platform of the packages.
+        let platform = params
+            .platform
+            .or_else(|| environment_platform(&installed_packages))
+            .or_else(|| environment_platform(&params.packages))
+            .unwrap_or_else(Platform::current);
+        let installed_records = installed_packages
+            .iter()
+            .map(|record| record.repodata_record.package_record.clone())
+            .collect::<Vec<_>>();
+        let transaction =
+            Transaction::from_current_and_desired(installed_packages, params.packages, platform)?;
+        let operations = transaction.operations.len();
+        apply_transaction(
+            transaction,
+            &installed_records,
+            &HashMap::new(),
+            params.prefix,
+            self.cache_dir.clone(),
+            self.download_client.clone(),
+            &KeepGoingOpt::default(),
+        )
+        .await?;
+        Ok(operations)
+    }
+
+    /// Returns the repodata of a channel subdirectory. The repodata is only parsed again if the
+    /// `repodata.json` in the cache changed since it was last loaded. Returns `None` if the channel
+    /// does not have the subdirectory.
+    async fn repodata(
+        &self,
+        channel: Channel,
+        platform: Platform,
+    ) -> anyhow::Result<Option<Arc<SparseRepoData>>> {
+        let url = channel.platform_url(platform);
+        let cached = match fetch_repo_data(
+            url.clone(),
+            self.download_client.clone(),
+            self.cache_dir.join("repodata"),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        {
+            Err(FetchRepoDataError::NotFound(_)) if platform != Platform::NoArch => {
+                return Ok(None)
+            }
+            result => result?,
+        };
+
+        let unchanged = matches!(
+            cached.cache_result,
+            CacheResult::CacheHit | CacheResult::CacheHitAfterFetch
+        );
+        if unchanged {
+            if let Some(warm) = self.repodata.lock().unwrap().get(&url) {
+                if warm.path == cached.repo_data_json_path {
+                    return Ok(Some(warm.repodata.clone()));
+                }
+            }
+        }
+
+        tracing::info!("loading repodata of {url}");
+        let path = cached.repo_data_json_path.clone();
+        let repodata = Arc::new(
+            tokio::task::spawn_blocking(move || {
+                SparseRepoData::new(channel, platform.to_string(), path, None)
+            })
+            .await??,
+        );
+        self.repodata.lock().unwrap().insert(
+            url,
+            WarmRepoData {
+                path: cached.repo_data_json_path,
+                repodata: repodata.clone(),
+            },
+        );
+        Ok(Some(repodata))
+    }
+}
+
+impl Response {
+    fn error(id: Value, code: i64, message: String) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(ResponseError { code, message }),
+        }
+    }
+}
+
+/// Converts a serializable result into a JSON value.
+fn json(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("results can always be serialized")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Request;
+    use serde_json::Value;
+
+    #[test]
+    fn test_request_id() {
+        let request: Request =
+            serde_json::from_str(r#"{"jsonrpc": "2.0", "id": 1, "method": "solve"}"#).unwrap();
+        assert_eq!(request.id, Some(Value::from(1)));
+
+        let request: Request =
+            serde_json::from_str(r#"{"jsonrpc": "2.0", "id": null, "method": "solve"}"#).unwrap();
+        assert_eq!(request.id, Some(Value::Null));
+
+        let request: Request =
+            serde_json::from_str(r#"{"jsonrpc": "2.0", "method": "solve"}"#).unwrap();
+        assert_eq!(request.id, None);
+    }
+}
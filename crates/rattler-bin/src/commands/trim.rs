@@ -0,0 +1,45 @@
+use crate::commands::create::{download_client, solve, SolveOpt};
+use rattler::offline_channel::write_offline_channel;
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    #[clap(flatten)]
+    solve: SolveOpt,
+
+    /// The directory to write the channel to
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+/// Solves the specs and writes a channel that only contains the packages required to install them.
+/// The resulting channel can be used without network access.
+pub async fn trim(opt: Opt) -> anyhow::Result<()> {
+    let platform = opt.solve.platform()?;
+
+    let cache_dir = crate::config::cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+
+    let download_client = download_client()?;
+    let records = solve(
+        opt.solve,
+        platform,
+        Vec::new(),
+        Vec::new(),
+        &cache_dir,
+        download_client.clone(),
+    )
+    .await?;
+
+    let package_count = records.len();
+    write_offline_channel(records, &opt.output, download_client).await?;
+
+    println!(
+        "{} Wrote {package_count} packages to {}",
+        console::style(console::Emoji("✔", "")).green(),
+        opt.output.display()
+    );
+
+    Ok(())
+}
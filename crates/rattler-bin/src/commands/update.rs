@@ -0,0 +1,116 @@
+use crate::commands::create::{
+    apply_transaction, download_client, find_damaged_packages, find_installed_packages,
+    print_transaction, solve_specs, KeepGoingOpt, SolverOpt,
+};
+use anyhow::Context;
+use rattler::install::Transaction;
+use rattler_conda_types::{PackageName, PackageRecord, PinnedSpecs};
+use rattler_solve::update::UpdateRequest;
+use std::{collections::HashMap, env};
+
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The packages to update
+    #[clap(required_unless_present = "all")]
+    packages: Vec<String>,
+
+    /// Update all packages in the environment
+    #[clap(long, conflicts_with = "packages")]
+    all: bool,
+
+    /// Remove packages that are no longer required by the specs of the environment
+    #[clap(long)]
+    prune: bool,
+
+    /// Only show the operations that would be performed
+    #[clap(long)]
+    dry_run: bool,
+
+    #[clap(flatten)]
+    keep_going: KeepGoingOpt,
+
+    #[clap(flatten)]
+    solver: SolverOpt,
+}
+
+/// Re-solves the specs of the environment to update its packages. The planned operations are shown
+/// before they are executed.
+pub async fn update(opt: Opt) -> anyhow::Result<()> {
+    let target_prefix = env::current_dir()?.join(".prefix");
+    let platform = opt.solver.platform()?;
+
+    let cache_dir = crate::config::cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+
+    // Determine the packages that are currently installed in the environment.
+    let installed_packages = find_installed_packages(&target_prefix, 100)
+        .await
+        .context("failed to determine currently installed packages")?;
+    if installed_packages.is_empty() {
+        anyhow::bail!("there is no environment at {}", target_prefix.display());
+    }
+
+    // Determine the specs to solve from the packages that were requested.
+    let request = UpdateRequest {
+        packages: opt
+            .packages
+            .iter()
+            .map(|name| PackageName::try_from(name.as_str()))
+            .collect::<Result<_, _>>()?,
+        all: opt.all,
+        prune: opt.prune,
+    };
+    let update = request.specs(&installed_packages)?;
+
+    let pinned_specs = PinnedSpecs::from_prefix(&target_prefix).with_context(|| {
+        format!(
+            "failed to read {}",
+            PinnedSpecs::path_in_prefix(&target_prefix).display()
+        )
+    })?;
+
+    let download_client = download_client()?;
+    let policy = opt.solver.policy();
+    let required_packages = solve_specs(
+        opt.solver,
+        update.specs,
+        platform,
+        update.locked_packages,
+        pinned_specs.specs,
+        &cache_dir,
+        download_client.clone(),
+    )
+    .await?;
+    let required_packages = PackageRecord::sort_topologically(required_packages);
+
+    let installed_records = installed_packages
+        .iter()
+        .map(|record| record.repodata_record.package_record.clone())
+        .collect::<Vec<_>>();
+    let damaged_packages = find_damaged_packages(&target_prefix, &installed_packages).await?;
+    let mut transaction = Transaction::from_current_and_desired_with_policy(
+        installed_packages,
+        required_packages,
+        platform,
+        &policy,
+    )?;
+    transaction.reinstall(damaged_packages);
+
+    // Always present the plan before executing it.
+    print_transaction(&transaction);
+    if opt.dry_run {
+        return Ok(());
+    }
+
+    apply_transaction(
+        transaction,
+        &installed_records,
+        &HashMap::new(),
+        target_prefix,
+        cache_dir,
+        download_client,
+        &opt.keep_going,
+    )
+    .await
+}
@@ -0,0 +1,241 @@
+//! The configuration of the `rattler` cli that is persisted in the user configuration directory.
+//!
+//! The configuration is stored as JSON in `<config dir>/rattler/config.json` and edited with the
+//! `config` subcommand. Every setting is optional, commands fall back to their defaults for the
+//! settings that are not configured.
+
+use anyhow::Context;
+use rattler_conda_types::ChannelConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// The channels that are used when neither the command line nor the configuration specifies any.
+pub const DEFAULT_CHANNELS: &[&str] = &["conda-forge"];
+
+/// The settings that are persisted in the configuration file. Unknown settings are ignored so a
+/// configuration file that was written by a newer version can still be read.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RattlerConfig {
+    /// The channels that are used when no channels are passed on the command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<String>>,
+
+    /// The url that is prefixed to channel names (e.g. `https://conda.anaconda.org`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_alias: Option<Url>,
+
+    /// The directory that stores downloaded packages and repodata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// A setting of the [`RattlerConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum ConfigKey {
+    /// See [`RattlerConfig::channels`].
+    Channels,
+
+    /// See [`RattlerConfig::channel_alias`].
+    ChannelAlias,
+
+    /// See [`RattlerConfig::cache_dir`].
+    CacheDir,
+}
+
+impl ConfigKey {
+    /// All settings, in the order in which they are listed.
+    pub const ALL: [ConfigKey; 3] = [
+        ConfigKey::Channels,
+        ConfigKey::ChannelAlias,
+        ConfigKey::CacheDir,
+    ];
+
+    /// Returns the name of the setting as it is written in the configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigKey::Channels => "channels",
+            ConfigKey::ChannelAlias => "channel_alias",
+            ConfigKey::CacheDir => "cache_dir",
+        }
+    }
+}
+
+impl RattlerConfig {
+    /// Returns the path of the configuration file.
+    pub fn path() -> anyhow::Result<PathBuf> {
+        Ok(dirs::config_dir()
+            .ok_or_else(|| {
+                anyhow::anyhow!("could not determine the config directory for current platform")
+            })?
+            .join("rattler/config.json"))
+    }
+
+    /// Reads the configuration file. Returns the default configuration if the file does not
+    /// exist.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::from_path(&Self::path()?)
+    }
+
+    /// Reads the configuration from the given file. Returns the default configuration if the file
+    /// does not exist.
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("failed to parse {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Writes the configuration to the given file, creating its directory if needed.
+    pub fn write_to_path(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Returns the configured value of the given setting, formatted one value per line. Returns
+    /// `None` if the setting is not configured.
+    pub fn get(&self, key: ConfigKey) -> Option<String> {
+        match key {
+            ConfigKey::Channels => self.channels.as_ref().map(|channels| channels.join("\n")),
+            ConfigKey::ChannelAlias => self.channel_alias.as_ref().map(Url::to_string),
+            ConfigKey::CacheDir => self.cache_dir.as_ref().map(|dir| dir.display().to_string()),
+        }
+    }
+
+    /// Validates `values` and stores them as the value of the given setting. Only
+    /// [`ConfigKey::Channels`] accepts more than one value.
+    pub fn set(&mut self, key: ConfigKey, values: Vec<String>) -> anyhow::Result<()> {
+        let single_value = || match values.as_slice() {
+            [value] => Ok(value.clone()),
+            _ => Err(anyhow::anyhow!("{} takes exactly one value", key.name())),
+        };
+
+        match key {
+            ConfigKey::Channels => {
+                if values.is_empty() {
+                    anyhow::bail!("channels takes at least one value");
+                }
+                self.channel_config()
+                    .resolve_channels(&values)
+                    .context("invalid channels")?;
+                self.channels = Some(values);
+            }
+            ConfigKey::ChannelAlias => {
+                let value = single_value()?;
+                let url =
+                    Url::parse(&value).with_context(|| format!("'{value}' is not a valid url"))?;
+                if url.cannot_be_a_base() {
+                    anyhow::bail!("'{value}' cannot be used as a channel alias");
+                }
+                self.channel_alias = Some(normalize_channel_alias(url));
+            }
+            ConfigKey::CacheDir => {
+                let path = PathBuf::from(single_value()?);
+                if !path.is_absolute() {
+                    anyhow::bail!("the cache directory must be an absolute path");
+                }
+                self.cache_dir = Some(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the value of the given setting so its default is used again.
+    pub fn unset(&mut self, key: ConfigKey) {
+        match key {
+            ConfigKey::Channels => self.channels = None,
+            ConfigKey::ChannelAlias => self.channel_alias = None,
+            ConfigKey::CacheDir => self.cache_dir = None,
+        }
+    }
+
+    /// Returns the channel configuration with the configured channel alias.
+    pub fn channel_config(&self) -> ChannelConfig {
+        let mut config = ChannelConfig::default();
+        if let Some(alias) = &self.channel_alias {
+            config.channel_alias = normalize_channel_alias(alias.clone());
+        }
+        config
+    }
+
+    /// Returns the channels to use when no channels are passed on the command line.
+    pub fn default_channels(&self) -> Vec<String> {
+        self.channels.clone().unwrap_or_else(|| {
+            DEFAULT_CHANNELS
+                .iter()
+                .map(|channel| String::from(*channel))
+                .collect()
+        })
+    }
+
+    /// Returns the configured cache directory or the default cache directory of rattler.
+    pub fn cache_dir(&self) -> anyhow::Result<PathBuf> {
+        match &self.cache_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => rattler::default_cache_dir(),
+        }
+    }
+}
+
+/// Appends a trailing `/` to the path of a channel alias. Channel names are joined onto the alias,
+/// without the slash the last segment of the alias would be replaced by the channel name.
+fn normalize_channel_alias(mut alias: Url) -> Url {
+    if !alias.path().ends_with('/') {
+        let path = format!("{}/", alias.path());
+        alias.set_path(&path);
+    }
+    alias
+}
+
+/// Returns the cache directory from the configuration file, or the default cache directory of
+/// rattler if none is configured.
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    RattlerConfig::load()?.cache_dir()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConfigKey, RattlerConfig};
+
+    #[test]
+    fn test_channel_alias_trailing_slash() {
+        let mut config = RattlerConfig::default();
+        config
+            .set(
+                ConfigKey::ChannelAlias,
+                vec![String::from("https://repo.example.com/conda")],
+            )
+            .unwrap();
+        assert_eq!(
+            config.get(ConfigKey::ChannelAlias).as_deref(),
+            Some("https://repo.example.com/conda/")
+        );
+
+        // Aliases that were written without the slash are normalized when they are used.
+        let config: RattlerConfig =
+            serde_json::from_str(r#"{"channel_alias": "https://repo.example.com/conda"}"#).unwrap();
+        assert_eq!(
+            config.channel_config().channel_alias.as_str(),
+            "https://repo.example.com/conda/"
+        );
+    }
+
+    #[test]
+    fn test_unknown_settings_are_ignored() {
+        let config: RattlerConfig =
+            serde_json::from_str(r#"{"channels": ["bioconda"], "some_new_setting": true}"#)
+                .unwrap();
+        assert_eq!(config.default_channels(), vec![String::from("bioconda")]);
+    }
+}
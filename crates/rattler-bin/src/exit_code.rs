@@ -0,0 +1,266 @@
+//! Maps the errors returned by commands to the exit status of the process so scripts that wrap
+//! `rattler` can branch on the kind of failure without parsing error messages.
+//!
+//! The exit codes are part of the interface of the cli and should not be changed:
+//!
+//! | Code | Meaning                                                                  |
+//! |------|--------------------------------------------------------------------------|
+//! | 0    | The command succeeded                                                    |
+//! | 1    | An error occurred that does not fall in any of the categories below      |
+//! | 2    | The command line arguments are invalid                                   |
+//! | 3    | A network request failed, e.g. downloading repodata or a package         |
+//! | 4    | The requested specs cannot be solved                                     |
+//! | 5    | Linking a package into the prefix failed                                 |
+//! | 6    | A package failed validation, e.g. its hash or its contents are incorrect |
+//! | 7    | A server refused a request permanently, e.g. `403` or `404`              |
+//! | 130  | The command was cancelled, e.g. with Ctrl-C                              |
+
+use rattler::{
+    gate::Cancelled,
+    install::{InstallError, InstallerError, LinkFileError, TransactionError},
+    package_cache::{DownloadPackageError, PackageCacheError},
+    validation::PackageValidationError,
+};
+use rattler_networking::retry_policies::is_retryable_status;
+use rattler_package_streaming::ExtractError;
+use rattler_repodata_gateway::fetch::FetchRepoDataError;
+use rattler_solve::SolveError;
+use reqwest::StatusCode;
+use std::error::Error;
+
+/// The text that is appended to the `--help` output to document the exit codes.
+pub const HELP: &str = "\
+Exit codes:
+  0    Success
+  1    Unknown error
+  2    Invalid command line arguments
+  3    Network error
+  4    The specs cannot be solved
+  5    Failed to link a package
+  6    A package failed validation
+  7    A server refused a request permanently (e.g. 403 or 404)
+  130  Cancelled";
+
+/// The category of an error that determines the exit code of the process.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrorCategory {
+    /// A network request failed. Retrying the command might succeed.
+    Network,
+
+    /// A server refused a request with a status that will not change by retrying, e.g.
+    /// `403 Forbidden` or `404 Not Found`.
+    Refused,
+
+    /// The requested specs cannot be solved.
+    SolveConflict,
+
+    /// Linking a package into the prefix failed.
+    Link,
+
+    /// A package failed validation.
+    Validation,
+
+    /// The operation was cancelled.
+    Cancelled,
+}
+
+impl ErrorCategory {
+    /// Returns the exit code of the process for errors of this category.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Network => 3,
+            ErrorCategory::Refused => 7,
+            ErrorCategory::SolveConflict => 4,
+            ErrorCategory::Link => 5,
+            ErrorCategory::Validation => 6,
+            ErrorCategory::Cancelled => CANCELLED,
+        }
+    }
+}
+
+/// The exit code used for errors that do not belong to any [`ErrorCategory`].
+pub const UNKNOWN: i32 = 1;
+
+/// The exit code used when the command was cancelled.
+pub const CANCELLED: i32 = 130;
+
+/// Returns the exit code of the process for the given error.
+pub fn from_error(err: &anyhow::Error) -> i32 {
+    categorize(err.as_ref()).map_or(UNKNOWN, ErrorCategory::exit_code)
+}
+
+/// Determines the category of an error by looking at the error and its sources. The outermost
+/// error that can be categorized determines the category.
+fn categorize(err: &(dyn Error + 'static)) -> Option<ErrorCategory> {
+    categorize_single(err).or_else(|| categorize(err.source()?))
+}
+
+/// Determines the category of a single error, without looking at its source. Errors that wrap
+/// another error transparently (and therefore do not expose it as their source) are unwrapped.
+fn categorize_single(err: &(dyn Error + 'static)) -> Option<ErrorCategory> {
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return Some(categorize_status(err.status()));
+    }
+    if err.is::<Cancelled>() {
+        return Some(ErrorCategory::Cancelled);
+    }
+    if err.is::<PackageValidationError>() {
+        return Some(ErrorCategory::Validation);
+    }
+    if let Some(err) = err.downcast_ref::<SolveError>() {
+        return match err {
+            SolveError::Unsolvable(_) | SolveError::PolicyViolation(_) => {
+                Some(ErrorCategory::SolveConflict)
+            }
+            _ => None,
+        };
+    }
+    if let Some(TransactionError::PolicyViolation(_)) = err.downcast_ref::<TransactionError>() {
+        return Some(ErrorCategory::SolveConflict);
+    }
+    if let Some(err) = err.downcast_ref::<FetchRepoDataError>() {
+        return match err {
+            FetchRepoDataError::HttpError(err) => Some(categorize_status(err.status())),
+            FetchRepoDataError::NotFound(_) => Some(ErrorCategory::Refused),
+            FetchRepoDataError::OciError(_) | FetchRepoDataError::TooManyRequests { .. } => {
+                Some(ErrorCategory::Network)
+            }
+            FetchRepoDataError::Cancelled => Some(ErrorCategory::Cancelled),
+            _ => None,
+        };
+    }
+    if let Some(err) = err.downcast_ref::<ExtractError>() {
+        return match err {
+            ExtractError::ReqwestError(err) => Some(categorize_status(err.status())),
+            ExtractError::HttpStatusError(_, err) => Some(categorize_status(Some(err.status()))),
+            ExtractError::OciError(_) => Some(ErrorCategory::Network),
+            ExtractError::Cancelled => Some(ErrorCategory::Cancelled),
+            _ => None,
+        };
+    }
+    if let Some(err) = err.downcast_ref::<DownloadPackageError>() {
+        return match err {
+            DownloadPackageError::HashMismatch { .. }
+            | DownloadPackageError::SizeMismatch { .. } => Some(ErrorCategory::Validation),
+            _ => None,
+        };
+    }
+    if let Some(PackageCacheError::FetchError(err)) = err.downcast_ref::<PackageCacheError>() {
+        return categorize(err.as_ref());
+    }
+    if let Some(err) = err.downcast_ref::<InstallerError>() {
+        match err {
+            InstallerError::Cancelled => return Some(ErrorCategory::Cancelled),
+            InstallerError::LinkDependencyFailed { .. } => return Some(ErrorCategory::Link),
+            _ => {}
+        }
+    }
+    if let Some(err) = err.downcast_ref::<InstallError>() {
+        return match err {
+            InstallError::Cancelled => Some(ErrorCategory::Cancelled),
+            _ => Some(ErrorCategory::Link),
+        };
+    }
+    if err.is::<LinkFileError>() {
+        return Some(ErrorCategory::Link);
+    }
+    None
+}
+
+/// Determines the category of a failed network request from the status of the response, if a
+/// response was received at all.
+fn categorize_status(status: Option<StatusCode>) -> ErrorCategory {
+    match status {
+        Some(status) if status.is_client_error() && !is_retryable_status(status) => {
+            ErrorCategory::Refused
+        }
+        _ => ErrorCategory::Network,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_error, ErrorCategory, CANCELLED, UNKNOWN};
+    use rattler::{
+        gate::Cancelled,
+        install::InstallError,
+        package_cache::{DownloadPackageError, PackageCacheError},
+    };
+    use rattler_networking::retry_policies::HttpStatusError;
+    use rattler_package_streaming::ExtractError;
+    use reqwest::StatusCode;
+    use std::sync::Arc;
+
+    fn size_mismatch() -> DownloadPackageError {
+        DownloadPackageError::SizeMismatch {
+            file_name: String::from("foo-1.0-0.conda"),
+            expected: 100,
+            actual: 50,
+        }
+    }
+
+    #[test]
+    fn test_download_errors() {
+        let validation = ErrorCategory::Validation.exit_code();
+        assert_eq!(from_error(&size_mismatch().into()), validation);
+        assert_eq!(
+            from_error(
+                &DownloadPackageError::HashMismatch {
+                    file_name: String::from("foo-1.0-0.conda"),
+                    hash: "sha256",
+                }
+                .into()
+            ),
+            validation
+        );
+
+        // The package cache wraps the errors of a fetch transparently.
+        let err = PackageCacheError::FetchError(Arc::new(size_mismatch()));
+        assert_eq!(
+            from_error(&anyhow::Error::new(err).context("failed to fetch foo")),
+            validation
+        );
+    }
+
+    #[test]
+    fn test_http_status_errors() {
+        let status_error = |err| {
+            from_error(
+                &ExtractError::HttpStatusError(
+                    "https://conda.anaconda.org/conda-forge/noarch/foo-1.0-0.conda"
+                        .parse()
+                        .unwrap(),
+                    err,
+                )
+                .into(),
+            )
+        };
+        for status in [StatusCode::FORBIDDEN, StatusCode::NOT_FOUND] {
+            assert_eq!(
+                status_error(HttpStatusError::Permanent { status }),
+                ErrorCategory::Refused.exit_code()
+            );
+        }
+        assert_eq!(
+            status_error(HttpStatusError::Retryable {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                retry_after: None
+            }),
+            ErrorCategory::Network.exit_code()
+        );
+    }
+
+    #[test]
+    fn test_cancelled() {
+        assert_eq!(from_error(&Cancelled.into()), CANCELLED);
+        assert_eq!(from_error(&InstallError::Cancelled.into()), CANCELLED);
+    }
+
+    #[test]
+    fn test_unknown() {
+        assert_eq!(
+            from_error(&anyhow::anyhow!("something went wrong")),
+            UNKNOWN
+        );
+    }
+}
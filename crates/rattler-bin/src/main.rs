@@ -1,10 +1,21 @@
 use crate::writer::IndicatifWriter;
 use clap::Parser;
 use indicatif::{MultiProgress, ProgressDrawTarget};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
+use rattler::{
+    download_manager::{
+        DownloadManager, DEFAULT_MAX_CONCURRENT_DOWNLOADS, DEFAULT_SMALL_DOWNLOAD_SIZE,
+    },
+    runtime::{Runtime, ThreadPoolRuntime},
+};
+use rattler_conda_types::{Clock, FixedClock, SystemClock};
+use rattler_networking::{cassette::Cassette, ClientOptions, HttpVersion};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tracing_subscriber::{filter::LevelFilter, util::SubscriberInitExt, EnvFilter};
 
 mod commands;
+mod config;
+mod exit_code;
 mod writer;
 
 /// Returns a global instance of [`indicatif::MultiProgress`].
@@ -21,9 +32,54 @@ pub fn global_multi_progress() -> MultiProgress {
     GLOBAL_MP.clone()
 }
 
+/// The cassette that HTTP requests are recorded to or replayed from, see `--record-http` and
+/// `--replay-http`.
+static HTTP_CASSETTE: OnceCell<Cassette> = OnceCell::new();
+
+/// Returns the cassette that HTTP requests should be recorded to or replayed from, if any.
+pub fn global_http_cassette() -> Option<Cassette> {
+    HTTP_CASSETTE.get().cloned()
+}
+
+/// The options of the client that is used to download repodata and packages, see
+/// `--pool-max-idle-per-host` and `--http2`.
+static CLIENT_OPTIONS: OnceCell<ClientOptions> = OnceCell::new();
+
+/// Returns the options of the client that is used to download repodata and packages.
+pub fn global_client_options() -> ClientOptions {
+    CLIENT_OPTIONS.get().cloned().unwrap_or_default()
+}
+
+/// The runtime on which packages are linked, see `--link-threads`.
+static LINK_RUNTIME: OnceCell<Arc<dyn Runtime>> = OnceCell::new();
+
+/// Returns the runtime on which packages should be linked, if a dedicated one was requested.
+pub fn global_link_runtime() -> Option<Arc<dyn Runtime>> {
+    LINK_RUNTIME.get().cloned()
+}
+
+/// Limits the number of packages that are downloaded at the same time, see
+/// `--concurrent-downloads` and `--concurrent-downloads-per-host`.
+static DOWNLOAD_MANAGER: OnceCell<DownloadManager> = OnceCell::new();
+
+/// Returns the download manager that should be used by all package caches.
+pub fn global_download_manager() -> DownloadManager {
+    DOWNLOAD_MANAGER
+        .get_or_init(DownloadManager::default)
+        .clone()
+}
+
+/// Determines what "now" is for repodata fetches, staleness checks and SBOMs, see `--now`.
+static CLOCK: OnceCell<Arc<dyn Clock>> = OnceCell::new();
+
+/// Returns the clock that determines the current time.
+pub fn global_clock() -> Arc<dyn Clock> {
+    CLOCK.get_or_init(|| Arc::new(SystemClock)).clone()
+}
+
 /// Command line options available through the `rattler` cli.
 #[derive(Debug, Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, after_long_help = exit_code::HELP)]
 struct Opt {
     /// The subcommand to execute
     #[clap(subcommand)]
@@ -32,12 +88,109 @@ struct Opt {
     /// Log verbose
     #[clap(short, long, global = true)]
     verbose: bool,
+
+    /// Log every HTTP request with its status, duration and size. Credentials are redacted.
+    #[clap(long, global = true)]
+    trace_http: bool,
+
+    /// Record the responses to all HTTP requests in the given directory
+    #[clap(long, global = true, conflicts_with = "replay_http")]
+    record_http: Option<PathBuf>,
+
+    /// Do not send any HTTP requests but replay the responses recorded with `--record-http`
+    #[clap(long, global = true)]
+    replay_http: Option<PathBuf>,
+
+    /// The maximum number of idle connections that are kept open per host
+    #[clap(long, global = true)]
+    pool_max_idle_per_host: Option<usize>,
+
+    /// Send TCP keep-alive probes on open connections at this interval (in seconds)
+    #[clap(long, global = true)]
+    tcp_keepalive: Option<u64>,
+
+    /// Use HTTP/2 with servers that support it, which is negotiated during the TLS handshake.
+    /// Requests to the same server are then multiplexed over a single connection. Without this
+    /// flag only HTTP/1.1 is used
+    #[clap(long, global = true)]
+    http2: bool,
+
+    /// The maximum number of packages that are downloaded at the same time
+    #[clap(long, global = true, default_value_t = DEFAULT_MAX_CONCURRENT_DOWNLOADS)]
+    concurrent_downloads: usize,
+
+    /// The maximum number of packages that are downloaded from a single host at the same time
+    #[clap(long, global = true)]
+    concurrent_downloads_per_host: Option<usize>,
+
+    /// Link packages on a dedicated pool of this many threads
+    #[clap(long, global = true)]
+    link_threads: Option<usize>,
+
+    /// Download up to this many small packages (up to 1 MiB) in the place of a single download
+    #[clap(long, global = true)]
+    batch_small_downloads: Option<u32>,
+
+    /// Use this moment as the current time instead of the time of the system, formatted as a date
+    /// (`2023-06-01`) or as an RFC 3339 timestamp. This determines the age of the repodata and the
+    /// creation time of SBOMs. Use `--exclude-newer` to ignore packages published after a moment
+    #[clap(long, global = true, value_parser = commands::create::parse_cutoff)]
+    now: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 /// Different commands supported by `rattler`.
 #[derive(Debug, clap::Subcommand)]
 enum Command {
+    /// Manages the package cache
+    Cache(commands::cache::Opt),
+
+    /// Shows and edits the persisted configuration
+    Config(commands::config::Opt),
+
     Create(commands::create::Opt),
+
+    /// Shows the differences between the packages installed in two prefixes
+    Diff(commands::diff::Opt),
+
+    /// Exports the packages installed in a prefix as an `environment.yml` file
+    Export(commands::export::Opt),
+
+    /// Downloads the packages required to install the specs into the cache without installing them
+    Fetch(commands::fetch::Opt),
+
+    /// Installs the packages of an `@EXPLICIT` environment file without solving, verifying the
+    /// archives against the hashes in the file
+    Install(commands::install::Opt),
+
+    /// Reports the licenses of the packages installed in a prefix
+    Licenses(commands::licenses::Opt),
+
+    /// Lists the packages installed in a prefix, including the ones installed with pip
+    List(commands::list::Opt),
+
+    /// Rewrites the files of an environment that contain the path it was installed to, so it can
+    /// be used from another location
+    Relocate(commands::relocate::Opt),
+
+    /// Removes packages from the environment together with the packages that depend on them
+    Remove(commands::remove::Opt),
+
+    /// Shows the differences between two `repodata.json` files
+    RepodataDiff(commands::repodata_diff::Opt),
+
+    /// Exports a software bill of materials of the packages installed in a prefix
+    Sbom(commands::sbom::Opt),
+
+    /// Solves, fetches and installs environments for clients connecting over a local socket,
+    /// keeping parsed repodata in memory between requests
+    Serve(commands::serve::Opt),
+
+    /// Writes a channel that only contains the packages required to install the specs, which
+    /// can be used without network access
+    Trim(commands::trim::Opt),
+
+    /// Re-solves the specs of the environment to update its packages
+    Update(commands::update::Opt),
 }
 
 /// Entry point of the `rattler` cli.
@@ -53,11 +206,16 @@ async fn main() -> anyhow::Result<()> {
     } else {
         LevelFilter::INFO
     };
-    let env_filter = EnvFilter::builder()
+    let mut env_filter = EnvFilter::builder()
         .with_default_directive(default_filter.into())
         .from_env()?
         // filter logs from apple codesign because they are very noisy
         .add_directive("apple_codesign=off".parse()?);
+    if opt.trace_http {
+        env_filter = env_filter.add_directive(
+            format!("{}=debug", rattler_networking::trace::HTTP_TRACE_TARGET).parse()?,
+        );
+    }
 
     // Setup the tracing subscriber
     tracing_subscriber::fmt()
@@ -67,8 +225,92 @@ async fn main() -> anyhow::Result<()> {
         .finish()
         .try_init()?;
 
+    // Record or replay HTTP interactions
+    let cassette = match (&opt.record_http, &opt.replay_http) {
+        (Some(dir), _) => Some(Cassette::record(dir)?),
+        (None, Some(dir)) => Some(Cassette::replay(dir)?),
+        (None, None) => None,
+    };
+    if let Some(cassette) = cassette {
+        let _ = HTTP_CASSETTE.set(cassette);
+    }
+
+    let _ = CLIENT_OPTIONS.set(ClientOptions {
+        pool_max_idle_per_host: opt.pool_max_idle_per_host,
+        tcp_keepalive: opt.tcp_keepalive.map(Duration::from_secs),
+        http_version: if opt.http2 {
+            HttpVersion::Negotiate
+        } else {
+            HttpVersion::Http1Only
+        },
+        http2_adaptive_window: opt.http2,
+        ..ClientOptions::default()
+    });
+
+    let mut download_manager = DownloadManager::new(opt.concurrent_downloads);
+    if let Some(limit) = opt.concurrent_downloads_per_host {
+        download_manager = download_manager.with_max_concurrent_downloads_per_host(limit);
+    }
+    if let Some(batch_size) = opt.batch_small_downloads {
+        download_manager =
+            download_manager.with_small_download_batching(DEFAULT_SMALL_DOWNLOAD_SIZE, batch_size);
+    }
+    let _ = DOWNLOAD_MANAGER.set(download_manager);
+
+    if let Some(now) = opt.now {
+        let _ = CLOCK.set(Arc::new(FixedClock(now)));
+    }
+
+    if let Some(num_threads) = opt.link_threads {
+        let _ = LINK_RUNTIME.set(Arc::new(ThreadPoolRuntime::new(num_threads)));
+    }
+
     // Dispatch the selected comment
-    match opt.command {
-        Command::Create(opts) => commands::create::create(opts).await,
+    let command = async move {
+        match opt.command {
+            Command::Cache(opts) => commands::cache::cache(opts).await,
+            Command::Config(opts) => commands::config::config(opts),
+            Command::Create(opts) => commands::create::create(opts).await,
+            Command::Diff(opts) => commands::diff::diff(opts),
+            Command::Export(opts) => commands::export::export(opts),
+            Command::Fetch(opts) => commands::fetch::fetch(opts).await,
+            Command::Install(opts) => commands::install::install(opts).await,
+            Command::Licenses(opts) => commands::licenses::licenses(opts),
+            Command::List(opts) => commands::list::list(opts),
+            Command::Relocate(opts) => commands::relocate::relocate(opts),
+            Command::Remove(opts) => commands::remove::remove(opts).await,
+            Command::RepodataDiff(opts) => commands::repodata_diff::repodata_diff(opts),
+            Command::Sbom(opts) => commands::sbom::sbom(opts),
+            Command::Serve(opts) => commands::serve::serve(opts).await,
+            Command::Trim(opts) => commands::trim::trim(opts).await,
+            Command::Update(opts) => commands::update::update(opts).await,
+        }
+    };
+
+    // Run the command until it completes or until the user interrupts it (e.g. with Ctrl-C). On
+    // interruption the command future is dropped which cancels all in-flight operations.
+    let result = tokio::select! {
+        result = command => Some(result),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+
+    // Remove the files of packages that were only partially linked because the command was
+    // interrupted or failed. This waits for the tasks that were still linking them.
+    commands::create::roll_back_interrupted_links().await;
+
+    // Exit with a code that reflects the kind of failure, see the `exit_code` module.
+    match result {
+        Some(Ok(())) => Ok(()),
+        Some(Err(err)) => {
+            eprintln!("Error: {err:?}");
+            std::process::exit(exit_code::from_error(&err))
+        }
+        None => {
+            eprintln!(
+                "{} Interrupted",
+                console::style(console::Emoji("✘", "")).red()
+            );
+            std::process::exit(exit_code::CANCELLED)
+        }
     }
 }
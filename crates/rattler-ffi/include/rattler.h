@@ -0,0 +1,50 @@
+#ifndef RATTLER_H
+#define RATTLER_H
+
+/* This file is generated by cbindgen from the rattler-ffi crate, do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Solves the environment described by the JSON encoded [`SolveRequest`] and returns the JSON
+// encoded [`SolveResult`]. Returns `NULL` if the environment could not be solved.
+//
+// The returned string must be released with [`rattler_string_free`].
+//
+// # Safety
+//
+// `request_json` must be null or point to a valid nul-terminated string.
+char *rattler_solve(const char *request_json);
+
+// Installs the packages of the JSON encoded [`SolveResult`] into the environment at `prefix`.
+// Packages that are installed in the environment but that are not part of the result are
+// removed. Returns 0 on success and -1 on failure.
+//
+// # Safety
+//
+// `result_json` and `prefix` must be null or point to valid nul-terminated strings.
+int rattler_install(const char *result_json, const char *prefix);
+
+// Returns a description of the error of the last function that failed on the calling thread, or
+// `NULL` if the last call succeeded. The string is owned by the library and remains valid until
+// the next call into the library on the same thread.
+const char *rattler_last_error(void);
+
+// Releases a string that was returned by the library. Passing `NULL` is a no-op.
+//
+// # Safety
+//
+// `s` must be null or a string returned by this library that has not been released yet.
+void rattler_string_free(char *s);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RATTLER_H */
@@ -0,0 +1,37 @@
+//! Installing a [`SolveResult`] into a prefix.
+
+use crate::solve::{cache_dir, SolveResult};
+use anyhow::Context;
+use rattler::{
+    install::{Installer, Transaction},
+    package_cache::PackageCache,
+};
+use rattler_conda_types::PrefixRecord;
+use rattler_networking::AuthenticatedClient;
+use std::path::Path;
+
+/// Updates the environment at `prefix` so it contains exactly the packages of the solved
+/// environment. Packages that are already installed are left untouched. If a package fails to
+/// install, the files of the packages that were only partially linked are removed again.
+pub async fn install(result: SolveResult, prefix: &Path) -> anyhow::Result<()> {
+    let installed = PrefixRecord::collect_from_prefix(prefix)
+        .context("failed to determine the currently installed packages")?;
+    let installed_records = installed
+        .iter()
+        .map(|record| record.repodata_record.package_record.clone())
+        .collect::<Vec<_>>();
+    let transaction =
+        Transaction::from_current_and_desired(installed, result.packages, result.platform)?;
+    if transaction.operations.is_empty() {
+        return Ok(());
+    }
+
+    let cache_dir = cache_dir(result.cache_dir.as_deref())?;
+    Installer::new(
+        PackageCache::new(cache_dir.join("pkgs")),
+        AuthenticatedClient::default(),
+    )
+    .install(prefix, transaction, &installed_records)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+//! `rattler-ffi` exposes solving and installing conda environments through a C ABI so that rattler
+//! can be embedded in languages other than Rust and Python (e.g. R, Julia or C++ tooling). The
+//! accompanying C header is `include/rattler.h`.
+//!
+//! Data is exchanged as UTF-8 encoded, nul-terminated JSON strings:
+//!
+//! * [`rattler_solve`] takes a [`SolveRequest`] and returns a [`SolveResult`].
+//! * [`rattler_install`] takes a [`SolveResult`] and installs it into a prefix.
+//!
+//! Functions that fail return `NULL` or a non-zero status. A description of the error can then be
+//! retrieved with [`rattler_last_error`]. Strings returned by the library must be released with
+//! [`rattler_string_free`].
+
+#![deny(missing_docs)]
+
+mod install;
+mod solve;
+
+pub use install::install;
+pub use solve::{solve, SolveRequest, SolveResult};
+
+use once_cell::sync::OnceCell;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use tokio::runtime::Runtime;
+
+thread_local! {
+    /// The error of the last function that failed on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Returns the runtime on which the asynchronous operations of the library are executed.
+fn runtime() -> anyhow::Result<&'static Runtime> {
+    static RUNTIME: OnceCell<Runtime> = OnceCell::new();
+    Ok(RUNTIME.get_or_try_init(Runtime::new)?)
+}
+
+/// Stores the error so it can be retrieved with [`rattler_last_error`].
+fn set_last_error(error: anyhow::Error) {
+    // A nul byte in the message would truncate it, replace them so the message remains readable.
+    let message = format!("{error:#}").replace('\0', "\\0");
+    let message = CString::new(message).expect("nul bytes have been replaced");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, converting both errors and panics into an error that is stored for
+/// [`rattler_last_error`]. Unwinding across the FFI boundary is undefined behavior, so every
+/// exported function goes through this.
+fn ffi_call<T>(f: impl FnOnce() -> anyhow::Result<T>) -> Option<T> {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+    let result = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            Err(anyhow::anyhow!("rattler panicked: {message}"))
+        }
+    };
+    result.map_err(set_last_error).ok()
+}
+
+/// Converts a string passed by the caller into a `&str`.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a valid nul-terminated string that outlives the returned value.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> anyhow::Result<&'a str> {
+    if ptr.is_null() {
+        anyhow::bail!("`{name}` must not be NULL");
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|e| anyhow::anyhow!("`{name}` is not valid UTF-8: {e}"))
+}
+
+/// Solves the environment described by the JSON encoded [`SolveRequest`] and returns the JSON
+/// encoded [`SolveResult`]. Returns `NULL` if the environment could not be solved.
+///
+/// The returned string must be released with [`rattler_string_free`].
+///
+/// # Safety
+///
+/// `request_json` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_solve(request_json: *const c_char) -> *mut c_char {
+    ffi_call(|| {
+        let request: SolveRequest = serde_json::from_str(str_arg(request_json, "request_json")?)?;
+        let result = runtime()?.block_on(solve(request))?;
+        Ok(CString::new(serde_json::to_string(&result)?)?)
+    })
+    .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Installs the packages of the JSON encoded [`SolveResult`] into the environment at `prefix`.
+/// Packages that are installed in the environment but that are not part of the result are
+/// removed. Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `result_json` and `prefix` must be null or point to valid nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_install(
+    result_json: *const c_char,
+    prefix: *const c_char,
+) -> c_int {
+    ffi_call(|| {
+        let result: SolveResult = serde_json::from_str(str_arg(result_json, "result_json")?)?;
+        let prefix = Path::new(str_arg(prefix, "prefix")?);
+        runtime()?.block_on(install(result, prefix))
+    })
+    .map_or(-1, |_| 0)
+}
+
+/// Returns a description of the error of the last function that failed on the calling thread, or
+/// `NULL` if the last call succeeded. The string is owned by the library and remains valid until
+/// the next call into the library on the same thread.
+#[no_mangle]
+pub extern "C" fn rattler_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Releases a string that was returned by the library. Passing `NULL` is a no-op.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rattler_install, rattler_last_error, rattler_solve};
+    use rattler_conda_types::{
+        prefix_record::{PathType, PathsEntry},
+        PrefixRecord,
+    };
+    use std::ffi::{CStr, CString};
+
+    fn last_error() -> String {
+        let error = rattler_last_error();
+        assert!(!error.is_null());
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let result = unsafe { rattler_solve(std::ptr::null()) };
+        assert!(result.is_null());
+        assert_eq!(last_error(), "`request_json` must not be NULL");
+
+        let request = CString::new(r#"{"specs": "python"}"#).unwrap();
+        let result = unsafe { rattler_solve(request.as_ptr()) };
+        assert!(result.is_null());
+        assert!(last_error().contains("invalid type"));
+    }
+
+    #[test]
+    fn test_solve_denied_channel() {
+        let request = CString::new(
+            r#"{"specs": [], "channels": ["conda-forge"], "denied_channels": ["conda-forge"]}"#,
+        )
+        .unwrap();
+        let result = unsafe { rattler_solve(request.as_ptr()) };
+        assert!(result.is_null());
+        assert_eq!(
+            last_error(),
+            "the channel 'conda-forge' is not allowed to be used"
+        );
+    }
+
+    #[test]
+    fn test_install_empty_environment() {
+        let prefix = tempfile::tempdir().unwrap();
+        let prefix = CString::new(prefix.path().to_str().unwrap()).unwrap();
+        let result = CString::new(r#"{"platform": "linux-64", "packages": []}"#).unwrap();
+        assert_eq!(
+            unsafe { rattler_install(result.as_ptr(), prefix.as_ptr()) },
+            0
+        );
+        assert!(rattler_last_error().is_null());
+    }
+
+    #[test]
+    fn test_uninstall_package_with_directories() {
+        let prefix = tempfile::tempdir().unwrap();
+        let record_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-meta/xz-5.2.6-h8d14728_0.json");
+        let mut record = PrefixRecord::from_path(record_path).unwrap();
+        record.paths_data.paths = vec![
+            paths_entry("share/xz/doc.txt", PathType::HardLink),
+            paths_entry("share/xz/empty", PathType::Directory),
+            paths_entry("share/xz", PathType::Directory),
+        ];
+        std::fs::create_dir_all(prefix.path().join("share/xz/empty")).unwrap();
+        std::fs::write(prefix.path().join("share/xz/doc.txt"), "xz").unwrap();
+        let conda_meta_path = record.write_to_prefix(prefix.path()).unwrap();
+
+        // Installing an empty environment removes the package, including its directories.
+        let prefix_arg = CString::new(prefix.path().to_str().unwrap()).unwrap();
+        let result = CString::new(r#"{"platform": "linux-64", "packages": []}"#).unwrap();
+        assert_eq!(
+            unsafe { rattler_install(result.as_ptr(), prefix_arg.as_ptr()) },
+            0,
+            "{}",
+            last_error()
+        );
+        assert!(!prefix.path().join("share/xz").exists());
+        assert!(prefix.path().join("share").is_dir());
+        assert!(!conda_meta_path.exists());
+    }
+
+    fn paths_entry(path: &str, path_type: PathType) -> PathsEntry {
+        PathsEntry {
+            relative_path: path.into(),
+            path_type,
+            no_link: false,
+            sha256: None,
+            sha256_in_prefix: None,
+            size_in_bytes: None,
+            prefix_placeholder: None,
+            file_mode: None,
+        }
+    }
+}
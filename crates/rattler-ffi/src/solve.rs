@@ -0,0 +1,276 @@
+//! Solving an environment from a [`SolveRequest`].
+
+use anyhow::Context;
+use futures::{StreamExt, TryStreamExt};
+use rattler_conda_types::{
+    Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, PackageRecord, Platform,
+    RepoDataRecord, Version,
+};
+use rattler_networking::AuthenticatedClient;
+use rattler_repodata_gateway::fetch::{FetchRepoDataError, FetchRepoDataOptions};
+use rattler_repodata_gateway::sparse::SparseRepoData;
+use rattler_solve::{solve_environment, EnvironmentSpec, SolveOptionsSpec};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Describes the environment to solve. This is the JSON document that is passed to
+/// `rattler_solve`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolveRequest {
+    /// The specs of the packages that should be part of the environment (e.g. `python 3.11.*`).
+    pub specs: Vec<String>,
+
+    /// The channels to fetch packages from. Defaults to `conda-forge`. The `defaults` and
+    /// `nodefaults` keywords are supported like in an `environment.yml` file.
+    #[serde(default = "default_channels")]
+    pub channels: Vec<String>,
+
+    /// Channels that must never be used. Requesting one of them is an error.
+    #[serde(default)]
+    pub denied_channels: Vec<String>,
+
+    /// The platform to solve for. Defaults to the current platform.
+    #[serde(default)]
+    pub platform: Option<Platform>,
+
+    /// The virtual packages of the system, formatted as `name=version=build` where the version
+    /// and build are optional. Defaults to the virtual packages of the platform that is solved
+    /// for.
+    #[serde(default)]
+    pub virtual_packages: Option<Vec<String>>,
+
+    /// Specs that constrain the packages in the solution without requesting them.
+    #[serde(default)]
+    pub constraints: Vec<String>,
+
+    /// The directory in which repodata and packages are cached. Defaults to the rattler cache
+    /// directory.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// The options to solve with, e.g. the solver backend or the channel priority.
+    #[serde(flatten)]
+    pub options: SolveOptionsSpec,
+}
+
+/// The packages that make up a solved environment. This is the JSON document that is returned by
+/// `rattler_solve` and that is passed to `rattler_install`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolveResult {
+    /// The platform the environment was solved for.
+    pub platform: Platform,
+
+    /// The packages in the environment, sorted topologically.
+    pub packages: Vec<RepoDataRecord>,
+
+    /// The cache directory that was used to solve the environment. Packages are installed from the
+    /// same cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+fn default_channels() -> Vec<String> {
+    vec![String::from("conda-forge")]
+}
+
+/// Returns the cache directory to use if none was specified.
+pub(crate) fn cache_dir(cache_dir: Option<&Path>) -> anyhow::Result<PathBuf> {
+    match cache_dir {
+        Some(cache_dir) => Ok(cache_dir.to_path_buf()),
+        None => rattler::default_cache_dir(),
+    }
+}
+
+/// Fetches the repodata of the channels in the request and solves its specs.
+pub async fn solve(request: SolveRequest) -> anyhow::Result<SolveResult> {
+    let platform = request.platform.unwrap_or_else(Platform::current);
+    let solve_options = request.options.to_solve_options()?;
+    let channel_config = ChannelConfig {
+        denied_channels: request.denied_channels.clone(),
+        ..ChannelConfig::default()
+    };
+
+    let specs = request
+        .specs
+        .iter()
+        .map(|spec| MatchSpec::from_str(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let constraints = request
+        .constraints
+        .iter()
+        .map(|spec| MatchSpec::from_str(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let channels = channel_config.resolve_channels(&request.channels)?;
+
+    let cache_dir = cache_dir(request.cache_dir.as_deref())?;
+    std::fs::create_dir_all(&cache_dir)
+        .with_context(|| format!("could not create cache directory {}", cache_dir.display()))?;
+
+    // Fetch the repodata of the platform and the noarch subdirectory of every channel, unless the
+    // subdirectories of the channel are restricted.
+    let client = AuthenticatedClient::default();
+    let repodata_cache = cache_dir.join("repodata");
+    let subdirs = channels
+        .iter()
+        .flat_map(|channel| {
+            channel
+                .subdirs_for_platform(platform)
+                .into_iter()
+                .map(|subdir| (channel.clone(), subdir))
+        })
+        .collect::<Vec<_>>();
+    let subdir_count = subdirs.len();
+    let sparse_repo_datas = futures::stream::iter(subdirs)
+        .map(|(channel, platform)| {
+            fetch_sparse_repo_data(channel, platform, &repodata_cache, &client)
+        })
+        .buffered(subdir_count.max(1))
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    // Only load the records that are reachable from the specs.
+    let package_names = specs
+        .iter()
+        .filter_map(|spec| spec.name.clone())
+        .collect::<Vec<_>>();
+    let repodatas = tokio::task::spawn_blocking(move || {
+        SparseRepoData::load_records_recursive(&sparse_repo_datas, package_names, None, true)
+    })
+    .await??;
+
+    let virtual_packages = match request.virtual_packages {
+        Some(virtual_packages) => virtual_packages
+            .iter()
+            .map(|spec| parse_virtual_package(spec))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => rattler_virtual_packages::VirtualPackage::for_platform(platform)?
+            .into_iter()
+            .map(GenericVirtualPackage::from)
+            .collect(),
+    };
+
+    let packages = solve_environment(
+        EnvironmentSpec {
+            available_packages: &repodatas,
+            specs,
+            constraints,
+            virtual_packages,
+            ..EnvironmentSpec::default()
+        },
+        solve_options,
+    )?;
+
+    Ok(SolveResult {
+        platform,
+        packages: PackageRecord::sort_topologically(packages),
+        cache_dir: request.cache_dir,
+    })
+}
+
+/// Downloads the repodata of a single subdirectory of a channel. Returns `None` if the channel has
+/// no such subdirectory. Every channel is required to have a noarch subdirectory.
+async fn fetch_sparse_repo_data(
+    channel: Channel,
+    platform: Platform,
+    repodata_cache: &Path,
+    client: &AuthenticatedClient,
+) -> anyhow::Result<Option<SparseRepoData>> {
+    let cached = match rattler_repodata_gateway::fetch::fetch_repo_data(
+        channel.platform_url(platform),
+        client.clone(),
+        repodata_cache.to_path_buf(),
+        FetchRepoDataOptions::default(),
+        None,
+    )
+    .await
+    {
+        Err(FetchRepoDataError::NotFound(_)) if platform != Platform::NoArch => return Ok(None),
+        result => result?,
+    };
+
+    let repodata = tokio::task::spawn_blocking(move || {
+        SparseRepoData::new(
+            channel,
+            platform.to_string(),
+            cached.repo_data_json_path,
+            None,
+        )
+    })
+    .await??;
+    Ok(Some(repodata))
+}
+
+/// Parses a virtual package formatted as `name=version=build`.
+fn parse_virtual_package(spec: &str) -> anyhow::Result<GenericVirtualPackage> {
+    let mut parts = spec.splitn(3, '=');
+    let name = parts.next().unwrap_or_default();
+    let version = parts.next().unwrap_or("0");
+    let build_string = parts.next().unwrap_or_default();
+    Ok(GenericVirtualPackage {
+        name: name.try_into()?,
+        version: Version::from_str(version)?,
+        build_string: build_string.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_virtual_package, SolveRequest};
+    use rattler_conda_types::Platform;
+    use rattler_solve::{ChannelPriority, SolverBackend};
+
+    #[test]
+    fn test_solve_request_defaults() {
+        let request: SolveRequest =
+            serde_json::from_str(r#"{"specs": ["python 3.11.*"]}"#).unwrap();
+        assert_eq!(request.channels, ["conda-forge"]);
+        assert_eq!(request.platform, None);
+        assert!(request.virtual_packages.is_none());
+        assert!(request.denied_channels.is_empty());
+        assert!(request.options.to_solve_options().is_ok());
+
+        let request: SolveRequest = serde_json::from_str(
+            r#"{"specs": [], "solver": "resolvo", "channel_priority": "strict"}"#,
+        )
+        .unwrap();
+        let options = request.options.to_solve_options().unwrap();
+        assert_eq!(options.backend, SolverBackend::Resolvo);
+        assert_eq!(options.channel_priority, ChannelPriority::Strict);
+
+        let request: SolveRequest =
+            serde_json::from_str(r#"{"specs": [], "strategy": "lowest"}"#).unwrap();
+        assert!(request.options.to_solve_options().is_err());
+
+        let request: SolveRequest =
+            serde_json::from_str(r#"{"specs": [], "platform": "osx-arm64"}"#).unwrap();
+        assert_eq!(request.platform, Some(Platform::OsxArm64));
+
+        assert!(serde_json::from_str::<SolveRequest>(r#"{"spec": []}"#).is_err());
+        assert!(
+            serde_json::from_str::<SolveRequest>(r#"{"specs": [], "solvr": "resolvo"}"#).is_err()
+        );
+
+        let request: SolveRequest =
+            serde_json::from_str(r#"{"specs": [], "prefer_arch_over_noarch": true}"#).unwrap();
+        let options = request.options.to_solve_options().unwrap();
+        assert!(options.prefer_arch_over_noarch);
+    }
+
+    #[test]
+    fn test_parse_virtual_package() {
+        let package = parse_virtual_package("__glibc=2.28").unwrap();
+        assert_eq!(package.name.as_normalized(), "__glibc");
+        assert_eq!(package.version.to_string(), "2.28");
+        assert_eq!(package.build_string, "");
+
+        let package = parse_virtual_package("__cuda=12.0=0").unwrap();
+        assert_eq!(package.build_string, "0");
+
+        assert!(parse_virtual_package("__unix").is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rattler::download_manager::{DownloadManager, DEFAULT_SMALL_DOWNLOAD_SIZE};
+use rattler_networking::ClientOptions;
+use reqwest::Client;
+use std::{net::SocketAddr, time::Duration};
+use url::Url;
+
+/// The number of files that are downloaded in every iteration.
+const FILE_COUNT: usize = 100;
+
+/// The size of every file, similar to a small noarch package.
+const FILE_SIZE: usize = 16 * 1024;
+
+/// The time the server takes to respond to a request, which simulates the latency of a remote
+/// server.
+const LATENCY: Duration = Duration::from_millis(5);
+
+static FILE: [u8; FILE_SIZE] = [0; FILE_SIZE];
+
+/// Starts a server that responds to every request with [`FILE`] after [`LATENCY`] and returns the
+/// urls of the files to download.
+fn serve_files(runtime: &tokio::runtime::Runtime) -> Vec<Url> {
+    let _guard = runtime.enter();
+    let router = axum::Router::new().route(
+        "/:file",
+        axum::routing::get(|| async {
+            tokio::time::sleep(LATENCY).await;
+            &FILE[..]
+        }),
+    );
+    let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+    let server = axum::Server::bind(&addr).serve(router.into_make_service());
+    let port = server.local_addr().port();
+    runtime.spawn(server);
+
+    (0..FILE_COUNT)
+        .map(|i| Url::parse(&format!("http://localhost:{port}/{i}.conda")).unwrap())
+        .collect()
+}
+
+/// Downloads all `urls` concurrently, limited by the `download_manager`.
+async fn download_all(client: &Client, download_manager: &DownloadManager, urls: &[Url]) {
+    futures::future::join_all(urls.iter().map(|url| async move {
+        let _permit = download_manager
+            .acquire_with_size(url, Some(FILE_SIZE as u64))
+            .await;
+        let bytes = client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(bytes.len(), FILE_SIZE);
+    }))
+    .await;
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let urls = serve_files(&runtime);
+
+    let no_pooling = ClientOptions {
+        pool_max_idle_per_host: Some(0),
+        ..ClientOptions::default()
+    }
+    .build_client()
+    .unwrap();
+    let pooled = ClientOptions {
+        tcp_keepalive: Some(Duration::from_secs(60)),
+        ..ClientOptions::default()
+    }
+    .build_client()
+    .unwrap();
+
+    let unbatched = DownloadManager::new(10);
+    let batched =
+        DownloadManager::new(10).with_small_download_batching(DEFAULT_SMALL_DOWNLOAD_SIZE, 5);
+
+    let mut group = c.benchmark_group("download small files");
+    group.bench_function("new connection per request", |b| {
+        b.iter(|| runtime.block_on(download_all(&no_pooling, &unbatched, &urls)))
+    });
+    group.bench_function("pooled connections", |b| {
+        b.iter(|| runtime.block_on(download_all(&pooled, &unbatched, &urls)))
+    });
+    group.bench_function("pooled connections, batched", |b| {
+        b.iter(|| runtime.block_on(download_all(&pooled, &batched, &urls)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
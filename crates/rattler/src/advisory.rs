@@ -0,0 +1,360 @@
+//! Checks packages against known security advisories before they are installed.
+//!
+//! Advisories are provided by an [`AdvisoryProvider`] which is queried with the name and version of
+//! every package. [`OsvDirectory`] is a built-in provider that reads advisories in the
+//! [OSV format](https://ossf.github.io/osv-schema/) from a directory.
+
+use rattler_conda_types::{PackageName, PackageRecord, Version};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A security advisory that affects a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advisory {
+    /// The identifier of the advisory, e.g. `GHSA-xxxx-xxxx-xxxx`.
+    pub id: String,
+
+    /// Other identifiers of the same advisory, e.g. CVE ids.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+
+    /// A short description of the vulnerability.
+    #[serde(default)]
+    pub summary: Option<String>,
+
+    /// Urls with more information about the vulnerability.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+/// An error that can occur when querying advisories.
+#[derive(Debug, thiserror::Error)]
+pub enum AdvisoryError {
+    /// An advisory could not be read.
+    #[error("failed to read advisory {0}")]
+    IoError(PathBuf, #[source] std::io::Error),
+
+    /// An advisory is not valid.
+    #[error("failed to parse advisory {0}")]
+    ParseError(PathBuf, #[source] serde_json::Error),
+
+    /// A custom [`AdvisoryProvider`] failed.
+    #[error(transparent)]
+    ProviderError(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Provides the known security advisories of packages.
+pub trait AdvisoryProvider: Send + Sync {
+    /// Returns the advisories that affect the given version of the package.
+    fn advisories(
+        &self,
+        name: &PackageName,
+        version: &Version,
+    ) -> Result<Vec<Advisory>, AdvisoryError>;
+}
+
+/// A package that is affected by one or more advisories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VulnerablePackage {
+    /// The record of the package.
+    pub record: PackageRecord,
+
+    /// The advisories that affect the package.
+    pub advisories: Vec<Advisory>,
+}
+
+/// Queries the `provider` for every record and returns the records that are affected by at least
+/// one advisory.
+pub fn find_vulnerable_packages(
+    provider: &dyn AdvisoryProvider,
+    records: impl IntoIterator<Item = impl AsRef<PackageRecord>>,
+) -> Result<Vec<VulnerablePackage>, AdvisoryError> {
+    let mut vulnerable = Vec::new();
+    for record in records {
+        let record = record.as_ref();
+        let advisories = provider.advisories(&record.name, record.version.version())?;
+        if !advisories.is_empty() {
+            vulnerable.push(VulnerablePackage {
+                record: record.clone(),
+                advisories,
+            });
+        }
+    }
+    Ok(vulnerable)
+}
+
+/// An [`AdvisoryProvider`] that reads advisories in the OSV format from the `*.json` files in a
+/// directory.
+///
+/// Only the affected packages of the `conda` ecosystem are taken into account. Versions are
+/// compared as conda versions, both for the `ranges` and the `versions` of an affected package.
+#[derive(Debug, Clone, Default)]
+pub struct OsvDirectory {
+    /// The advisories indexed by the normalized name of the packages they affect.
+    advisories: HashMap<String, Vec<(Advisory, OsvAffected)>>,
+}
+
+/// An OSV advisory, only the fields that are used are parsed.
+#[derive(Debug, Deserialize)]
+struct OsvEntry {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    references: Vec<OsvReference>,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvReference {
+    url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OsvAffected {
+    package: OsvPackage,
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OsvPackage {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OsvRange {
+    #[serde(rename = "type")]
+    kind: String,
+    events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OsvEvent {
+    Introduced(String),
+    Fixed(String),
+    LastAffected(String),
+    Limit(String),
+}
+
+impl OsvAffected {
+    /// Returns true if the version is affected.
+    fn affects(&self, version: &Version) -> bool {
+        let parse = |v: &str| Version::from_str(v).ok();
+        self.versions
+            .iter()
+            .any(|v| parse(v).as_ref() == Some(version))
+            || self
+                .ranges
+                .iter()
+                .filter(|range| range.kind == "ECOSYSTEM" || range.kind == "SEMVER")
+                .any(|range| {
+                    // Evaluate the events in the order of their versions, the last event that
+                    // applies to the version determines whether it is affected.
+                    let mut events = range
+                        .events
+                        .iter()
+                        .filter_map(|event| match event {
+                            OsvEvent::Introduced(v) if v == "0" => Some((None, event)),
+                            OsvEvent::Introduced(v)
+                            | OsvEvent::Fixed(v)
+                            | OsvEvent::LastAffected(v)
+                            | OsvEvent::Limit(v) => Some((Some(parse(v)?), event)),
+                        })
+                        .collect::<Vec<_>>();
+                    events.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                    let mut affected = false;
+                    for (event_version, event) in events {
+                        match (event, event_version) {
+                            (OsvEvent::Introduced(_), None) => affected = true,
+                            (OsvEvent::Introduced(_), Some(v)) if version >= &v => affected = true,
+                            (OsvEvent::Fixed(_) | OsvEvent::Limit(_), Some(v)) if version >= &v => {
+                                affected = false
+                            }
+                            (OsvEvent::LastAffected(_), Some(v)) if version > &v => {
+                                affected = false
+                            }
+                            _ => {}
+                        }
+                    }
+                    affected
+                })
+    }
+}
+
+impl OsvDirectory {
+    /// Reads all the `*.json` files in the directory. Every file contains a single advisory.
+    pub fn from_path(path: &Path) -> Result<Self, AdvisoryError> {
+        let mut directory = Self::default();
+        let entries =
+            std::fs::read_dir(path).map_err(|e| AdvisoryError::IoError(path.to_path_buf(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| AdvisoryError::IoError(path.to_path_buf(), e))?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| AdvisoryError::IoError(path.clone(), e))?;
+            let entry: OsvEntry = serde_json::from_str(&contents)
+                .map_err(|e| AdvisoryError::ParseError(path.clone(), e))?;
+            directory.add(entry);
+        }
+        Ok(directory)
+    }
+
+    fn add(&mut self, entry: OsvEntry) {
+        let advisory = Advisory {
+            id: entry.id,
+            aliases: entry.aliases,
+            summary: entry.summary,
+            references: entry.references.into_iter().map(|r| r.url).collect(),
+        };
+        for affected in entry.affected {
+            if !affected.package.ecosystem.eq_ignore_ascii_case("conda") {
+                continue;
+            }
+            let name = affected.package.name.to_lowercase();
+            self.advisories
+                .entry(name)
+                .or_default()
+                .push((advisory.clone(), affected));
+        }
+    }
+
+    /// Returns the number of packages for which advisories are known.
+    pub fn len(&self) -> usize {
+        self.advisories.len()
+    }
+
+    /// Returns true if no advisories are known.
+    pub fn is_empty(&self) -> bool {
+        self.advisories.is_empty()
+    }
+}
+
+impl AdvisoryProvider for OsvDirectory {
+    fn advisories(
+        &self,
+        name: &PackageName,
+        version: &Version,
+    ) -> Result<Vec<Advisory>, AdvisoryError> {
+        let mut advisories = Vec::new();
+        for (advisory, affected) in self
+            .advisories
+            .get(name.as_normalized())
+            .into_iter()
+            .flatten()
+        {
+            if affected.affects(version) && !advisories.contains(advisory) {
+                advisories.push(advisory.clone());
+            }
+        }
+        Ok(advisories)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_vulnerable_packages, AdvisoryProvider, OsvDirectory};
+    use rattler_conda_types::{PackageName, PackageRecord, Version};
+    use std::str::FromStr;
+
+    fn osv_directory() -> OsvDirectory {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("OSV-1.json"),
+            r#"{
+                "id": "OSV-1",
+                "aliases": ["CVE-2023-0001"],
+                "summary": "Remote code execution",
+                "references": [{"type": "ADVISORY", "url": "https://example.com/OSV-1"}],
+                "affected": [{
+                    "package": {"ecosystem": "conda", "name": "openssl"},
+                    "ranges": [{
+                        "type": "ECOSYSTEM",
+                        "events": [
+                            {"introduced": "0"},
+                            {"fixed": "1.1.1t"},
+                            {"introduced": "3.0"},
+                            {"last_affected": "3.0.7"}
+                        ]
+                    }]
+                }, {
+                    "package": {"ecosystem": "PyPI", "name": "requests"},
+                    "versions": ["2.0"]
+                }]
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("OSV-2.json"),
+            r#"{
+                "id": "OSV-2",
+                "affected": [{
+                    "package": {"ecosystem": "conda", "name": "zlib"},
+                    "versions": ["1.2.12"]
+                }]
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "not an advisory").unwrap();
+        OsvDirectory::from_path(dir.path()).unwrap()
+    }
+
+    #[test]
+    fn test_osv_directory() {
+        let directory = osv_directory();
+        assert_eq!(directory.len(), 2);
+
+        let affected = |name: &str, version: &str| {
+            !directory
+                .advisories(
+                    &PackageName::new_unchecked(name),
+                    &Version::from_str(version).unwrap(),
+                )
+                .unwrap()
+                .is_empty()
+        };
+        assert!(affected("openssl", "1.1.1s"));
+        assert!(!affected("openssl", "1.1.1t"));
+        assert!(!affected("openssl", "2.0"));
+        assert!(affected("openssl", "3.0"));
+        assert!(affected("openssl", "3.0.7"));
+        assert!(!affected("openssl", "3.0.8"));
+        assert!(affected("zlib", "1.2.12"));
+        assert!(!affected("zlib", "1.2.13"));
+        assert!(!affected("requests", "2.0"));
+    }
+
+    #[test]
+    fn test_find_vulnerable_packages() {
+        let records = [("openssl", "1.1.1s"), ("zlib", "1.2.13")].map(|(name, version)| {
+            PackageRecord::new(
+                PackageName::new_unchecked(name),
+                Version::from_str(version).unwrap(),
+                String::from("0"),
+            )
+        });
+        let vulnerable = find_vulnerable_packages(&osv_directory(), &records).unwrap();
+        assert_eq!(vulnerable.len(), 1);
+        assert_eq!(vulnerable[0].record.name.as_normalized(), "openssl");
+        assert_eq!(vulnerable[0].advisories[0].id, "OSV-1");
+        assert_eq!(vulnerable[0].advisories[0].aliases, ["CVE-2023-0001"]);
+        assert_eq!(
+            vulnerable[0].advisories[0].references,
+            ["https://example.com/OSV-1"]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Limits the number of packages that are downloaded at the same time. See [`DownloadManager`].
+//!
+//! Downloading all the packages of a large environment at once opens hundreds of simultaneous
+//! connections, which can exhaust the file descriptors of the process and overload the servers
+//! of the channels. Every download of the [`crate::package_cache::PackageCache`] first acquires a
+//! [`DownloadPermit`] from its [`DownloadManager`] and waits until a slot is available.
+//!
+//! Small packages spend most of their download time on the round trips of the request rather than
+//! on transferring data. With [`DownloadManager::with_small_download_batching`] several small
+//! downloads share a single slot, so they are sent together over the pooled (or multiplexed HTTP/2)
+//! connections of the client instead of waiting for large downloads to complete.
+
+use rattler_networking::progress::{AggregateProgress, AggregateProgressEntry, DownloadProgress};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+/// The number of packages that are downloaded concurrently by default.
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 50;
+
+/// The size up to which a download is considered small by default, see
+/// [`DownloadManager::with_small_download_batching`].
+pub const DEFAULT_SMALL_DOWNLOAD_SIZE: u64 = 1024 * 1024;
+
+/// Limits the number of concurrent downloads, both in total and per host, and combines the
+/// progress of all downloads.
+///
+/// Clones of a manager share the same limits, so a single manager can be shared between multiple
+/// tasks (or caches) to limit the total number of downloads.
+#[derive(Clone)]
+pub struct DownloadManager {
+    max_concurrent_downloads: usize,
+    downloads: Arc<Semaphore>,
+    max_concurrent_downloads_per_host: Option<usize>,
+    batching: Option<SmallDownloadBatching>,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    progress: AggregateProgress,
+    progress_func: Option<Arc<dyn Fn(DownloadProgress) + Send + Sync>>,
+}
+
+impl Default for DownloadManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT_DOWNLOADS)
+    }
+}
+
+impl fmt::Debug for DownloadManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadManager")
+            .field("max_concurrent_downloads", &self.max_concurrent_downloads)
+            .field(
+                "max_concurrent_downloads_per_host",
+                &self.max_concurrent_downloads_per_host,
+            )
+            .field("batching", &self.batching)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DownloadManager {
+    /// Constructs a manager that allows at most `max_concurrent_downloads` downloads at the same
+    /// time. A limit of zero is treated as a limit of one.
+    pub fn new(max_concurrent_downloads: usize) -> Self {
+        let max_concurrent_downloads = max_concurrent_downloads.max(1);
+        Self {
+            max_concurrent_downloads,
+            downloads: Arc::new(Semaphore::new(max_concurrent_downloads)),
+            max_concurrent_downloads_per_host: None,
+            batching: None,
+            hosts: Default::default(),
+            progress: AggregateProgress::default(),
+            progress_func: None,
+        }
+    }
+
+    /// Additionally limits the number of concurrent downloads from a single host. By default only
+    /// the total number of downloads is limited.
+    pub fn with_max_concurrent_downloads_per_host(self, max_concurrent_downloads: usize) -> Self {
+        Self {
+            max_concurrent_downloads_per_host: Some(max_concurrent_downloads.max(1)),
+            hosts: Default::default(),
+            ..self
+        }
+    }
+
+    /// Lets up to `batch_size` downloads of at most `max_size` bytes share the slot of a single
+    /// download, both for the total and the per host limit. Downloads of unknown size always
+    /// occupy a whole slot. See [`DEFAULT_SMALL_DOWNLOAD_SIZE`] for a reasonable `max_size`.
+    pub fn with_small_download_batching(self, max_size: u64, batch_size: u32) -> Self {
+        let batching = SmallDownloadBatching {
+            max_size,
+            batch_size: batch_size.max(1),
+        };
+        Self {
+            downloads: Arc::new(Semaphore::new(
+                self.max_concurrent_downloads * batching.batch_size as usize,
+            )),
+            batching: Some(batching),
+            hosts: Default::default(),
+            ..self
+        }
+    }
+
+    /// Calls `progress_func` with the combined progress of all downloads every time the progress
+    /// of one of the downloads changes.
+    pub fn with_progress(
+        self,
+        progress_func: impl Fn(DownloadProgress) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            progress_func: Some(Arc::new(progress_func)),
+            ..self
+        }
+    }
+
+    /// Returns the maximum number of concurrent downloads.
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.max_concurrent_downloads
+    }
+
+    /// Returns the combined progress of all downloads so far.
+    pub fn progress(&self) -> DownloadProgress {
+        self.progress.progress()
+    }
+
+    /// Waits until the file at `url` may be downloaded. The download should be performed while
+    /// the returned permit is alive, the slot is released when the permit is dropped.
+    pub async fn acquire(&self, url: &Url) -> DownloadPermit {
+        self.acquire_with_size(url, None).await
+    }
+
+    /// Same as [`DownloadManager::acquire`] but for a download of `size` bytes, if known. Small
+    /// downloads only occupy part of a slot, see [`DownloadManager::with_small_download_batching`].
+    pub async fn acquire_with_size(&self, url: &Url, size: Option<u64>) -> DownloadPermit {
+        // The number of permits of a slot, and the number of permits that this download occupies.
+        let (slot, permits) = match &self.batching {
+            Some(batching) if size.map_or(false, |size| size <= batching.max_size) => {
+                (batching.batch_size, 1)
+            }
+            Some(batching) => (batching.batch_size, batching.batch_size),
+            None => (1, 1),
+        };
+
+        // Wait for the host first so downloads from a busy host do not occupy the slots of
+        // downloads from other hosts.
+        let host_permit = match (self.max_concurrent_downloads_per_host, url.host_str()) {
+            (Some(limit), Some(host)) => {
+                let semaphore = self
+                    .hosts
+                    .lock()
+                    .unwrap()
+                    .entry(host.to_owned())
+                    .or_insert_with(|| Arc::new(Semaphore::new(limit * slot as usize)))
+                    .clone();
+                Some(
+                    semaphore
+                        .acquire_many_owned(permits)
+                        .await
+                        .expect("the semaphore is never closed"),
+                )
+            }
+            _ => None,
+        };
+        let permit = self
+            .downloads
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .expect("the semaphore is never closed");
+
+        DownloadPermit {
+            progress: self.progress.add_download(None),
+            aggregate: self.progress.clone(),
+            progress_func: self.progress_func.clone(),
+            _host_permit: host_permit,
+            _permit: permit,
+        }
+    }
+}
+
+/// See [`DownloadManager::with_small_download_batching`].
+#[derive(Debug, Clone, Copy)]
+struct SmallDownloadBatching {
+    max_size: u64,
+    batch_size: u32,
+}
+
+/// Allows a single download to proceed, see [`DownloadManager::acquire`].
+pub struct DownloadPermit {
+    progress: AggregateProgressEntry,
+    aggregate: AggregateProgress,
+    progress_func: Option<Arc<dyn Fn(DownloadProgress) + Send + Sync>>,
+    _host_permit: Option<OwnedSemaphorePermit>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl DownloadPermit {
+    /// Reports that `bytes` bytes of the download (of `total` bytes if known) have been
+    /// downloaded.
+    pub fn update(&self, bytes: u64, total: Option<u64>) {
+        self.progress.update(bytes, total);
+        if let Some(progress_func) = &self.progress_func {
+            progress_func(self.aggregate.progress());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DownloadManager;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    #[tokio::test]
+    async fn test_limits() {
+        let manager = DownloadManager::new(3).with_max_concurrent_downloads_per_host(2);
+        let first = Url::parse("https://first.example.com/a.conda").unwrap();
+        let second = Url::parse("https://second.example.com/b.conda").unwrap();
+
+        let _a = manager.acquire(&first).await;
+        let _b = manager.acquire(&first).await;
+
+        // The host limit is reached but other hosts can still be downloaded from.
+        let next_from_first = manager.acquire(&first);
+        tokio::pin!(next_from_first);
+        assert!(futures::poll!(next_from_first.as_mut()).is_pending());
+        let c = manager.acquire(&second).await;
+
+        // The total limit is reached.
+        let next_from_second = manager.acquire(&second);
+        tokio::pin!(next_from_second);
+        assert!(futures::poll!(next_from_second.as_mut()).is_pending());
+
+        drop(c);
+        assert!(futures::poll!(next_from_second.as_mut()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_small_download_batching() {
+        let manager = DownloadManager::new(1).with_small_download_batching(100, 3);
+        let url = Url::parse("https://conda.anaconda.org/a.conda").unwrap();
+
+        // Three small downloads share the single slot.
+        let a = manager.acquire_with_size(&url, Some(10)).await;
+        let _b = manager.acquire_with_size(&url, Some(100)).await;
+        let _c = manager.acquire_with_size(&url, Some(50)).await;
+        let small = manager.acquire_with_size(&url, Some(10));
+        tokio::pin!(small);
+        assert!(futures::poll!(small.as_mut()).is_pending());
+
+        drop(a);
+        assert!(futures::poll!(small.as_mut()).is_ready());
+
+        // Large downloads and downloads of unknown size occupy the whole slot.
+        {
+            let large = manager.acquire_with_size(&url, Some(101));
+            tokio::pin!(large);
+            assert!(futures::poll!(large.as_mut()).is_pending());
+        }
+        {
+            let unknown = manager.acquire(&url);
+            tokio::pin!(unknown);
+            assert!(futures::poll!(unknown.as_mut()).is_pending());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let manager = DownloadManager::default().with_progress({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(progress.bytes)
+        });
+        let url = Url::parse("https://conda.anaconda.org/a.conda").unwrap();
+
+        let first = manager.acquire(&url).await;
+        let second = manager.acquire(&url).await;
+        first.update(10, Some(100));
+        second.update(5, Some(50));
+        first.update(100, Some(100));
+
+        assert_eq!(*reported.lock().unwrap(), vec![10, 15, 105]);
+        assert_eq!(manager.progress().total, Some(150));
+    }
+}
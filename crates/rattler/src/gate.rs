@@ -0,0 +1,198 @@
+//! A [`Gate`] is a synchronization primitive that blocks tasks until it is opened.
+//!
+//! Unlike a one-shot event a gate can be closed again after it has been opened, which makes it
+//! possible to build multi-phase pipelines. For instance, downloads can wait on a gate that is
+//! closed while the disk is full and reopened once space has been freed.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// An error that is returned by [`Gate::wait_with_cancel`] if the wait was cancelled before the
+/// gate opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the operation was cancelled")]
+pub struct Cancelled;
+
+/// A gate that tasks can wait on until it is opened. Cloning a gate returns a handle to the same
+/// gate.
+///
+/// Opening the gate wakes up all tasks that are waiting on it. Closing the gate only affects tasks
+/// that start waiting after it was closed; tasks that were already woken up continue.
+#[derive(Debug, Clone)]
+pub struct Gate {
+    sender: Arc<watch::Sender<GateState>>,
+}
+
+/// The state of a [`Gate`]. The generation is incremented every time the gate is opened so that
+/// a task that was waiting when the gate opened is woken up even if the gate is closed again
+/// before the task observes the change.
+#[derive(Debug, Clone, Copy)]
+struct GateState {
+    open: bool,
+    generation: u64,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gate {
+    /// Constructs a new gate that is closed.
+    pub fn new() -> Self {
+        Self::with_state(false)
+    }
+
+    /// Constructs a new gate that is already open.
+    pub fn new_open() -> Self {
+        Self::with_state(true)
+    }
+
+    fn with_state(open: bool) -> Self {
+        Self {
+            sender: Arc::new(
+                watch::channel(GateState {
+                    open,
+                    generation: 0,
+                })
+                .0,
+            ),
+        }
+    }
+
+    /// Opens the gate and wakes up all the tasks that are waiting on it.
+    pub fn open(&self) {
+        self.sender.send_modify(|state| {
+            state.open = true;
+            state.generation = state.generation.wrapping_add(1);
+        });
+    }
+
+    /// Closes the gate. Tasks that wait on the gate after this call block until the gate is
+    /// opened again.
+    pub fn close(&self) {
+        self.sender.send_modify(|state| state.open = false);
+    }
+
+    /// Returns true if the gate is currently open.
+    pub fn is_open(&self) -> bool {
+        self.sender.borrow().open
+    }
+
+    /// Waits until the gate is open. Returns immediately if the gate is already open.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        let generation = receiver.borrow().generation;
+        receiver
+            .wait_for(|state| state.open || state.generation != generation)
+            .await
+            .expect("the sender is owned by the gate");
+    }
+
+    /// Waits until the gate is open or until the `token` is cancelled, whichever happens first.
+    /// If the token is already cancelled this returns [`Cancelled`] even if the gate is open.
+    pub async fn wait_with_cancel(&self, token: &CancellationToken) -> Result<(), Cancelled> {
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(Cancelled),
+            _ = self.wait() => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cancelled, Gate};
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn test_open_wakes_all_waiters() {
+        let gate = Gate::new();
+        let waiters = (0..100)
+            .map(|_| {
+                let gate = gate.clone();
+                tokio::spawn(async move { gate.wait().await })
+            })
+            .collect::<Vec<_>>();
+
+        tokio::task::yield_now().await;
+        assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+        gate.open();
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reopen() {
+        let gate = Gate::new_open();
+        gate.wait().await;
+
+        gate.close();
+        assert!(!gate.is_open());
+        let waiter = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        gate.open();
+        waiter.await.unwrap();
+        assert!(gate.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_open_and_close_wakes_waiters() {
+        let gate = Gate::new();
+        let waiters = (0..10)
+            .map(|_| {
+                let gate = gate.clone();
+                tokio::spawn(async move { gate.wait().await })
+            })
+            .collect::<Vec<_>>();
+        tokio::task::yield_now().await;
+
+        // Waiters that were blocked when the gate opened are woken up even if the gate is closed
+        // again before they run.
+        gate.open();
+        gate.close();
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+
+        let waiter = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        gate.open();
+        waiter.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_with_cancel() {
+        let gate = Gate::new();
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let gate = gate.clone();
+            let token = token.clone();
+            async move { gate.wait_with_cancel(&token).await }
+        });
+
+        token.cancel();
+        assert_eq!(waiter.await.unwrap(), Err(Cancelled));
+
+        // A cancelled token takes precedence over an open gate.
+        gate.open();
+        assert_eq!(gate.wait_with_cancel(&token).await, Err(Cancelled));
+        assert_eq!(
+            gate.wait_with_cancel(&CancellationToken::new()).await,
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Pre-flight checks that verify there is enough free disk space to download, extract and link a
+//! set of packages.
+//!
+//! Running out of disk space halfway through extracting or linking a package leaves a
+//! half-populated cache or prefix behind. Instead, [`DiskSpaceRequirements`] estimates the number
+//! of bytes that are written to the volume of the package cache and to the volume of the prefix
+//! and [`DiskSpaceRequirements::check`] compares them to the space that is available before any
+//! file is written.
+//!
+//! For packages that are already present in the cache the sizes of the files in their
+//! `info/paths.json` are used. Files that are hard linked take up no additional space if the cache
+//! and the prefix share a volume. For packages that still have to be downloaded only the size of
+//! the archive is known, the size of the extracted package and of the files that are linked from
+//! it is estimated with the [`EXTRACTED_SIZE_FACTOR`].
+
+use crate::package_cache::PackageCache;
+use rattler_conda_types::{
+    package::{PathType, PathsEntry, PathsJson},
+    PackageRecord,
+};
+use std::path::{Path, PathBuf};
+
+/// The factor by which the extracted files of a package are estimated to be larger than its
+/// compressed archive, used for packages that are not in the cache yet. Conda packages are
+/// compressed with zstd or bzip2, which typically shrink the files of a package to a third or a
+/// quarter of their size. The upper end is used so the estimate errs on the side of caution.
+pub const EXTRACTED_SIZE_FACTOR: u64 = 4;
+
+/// An error that is returned by [`DiskSpaceRequirements::check`].
+#[derive(Debug, thiserror::Error)]
+pub enum DiskSpaceError {
+    /// There is not enough free space on a volume.
+    #[error(
+        "not enough disk space on the volume of '{}': {required} bytes are required but only {available} bytes are available",
+        .path.display()
+    )]
+    InsufficientSpace {
+        /// A path on the volume that does not have enough free space.
+        path: PathBuf,
+
+        /// The number of bytes that are required.
+        required: u64,
+
+        /// The number of bytes that are available.
+        available: u64,
+    },
+
+    /// The available space of the volume that contains the path could not be determined.
+    #[error("failed to determine the available disk space of '{}'", .0.display())]
+    FailedToQueryVolume(PathBuf, #[source] std::io::Error),
+}
+
+/// The number of bytes that have to be written to the volume of the package cache and to the volume
+/// of a prefix to install a set of packages.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DiskSpaceRequirements {
+    /// The number of bytes written to the volume of the package cache by downloading and
+    /// extracting packages.
+    pub cache_bytes: u64,
+
+    /// The number of bytes written to the volume of the prefix by linking packages.
+    pub prefix_bytes: u64,
+
+    cache_path: PathBuf,
+    prefix_path: PathBuf,
+    same_volume: bool,
+}
+
+impl DiskSpaceRequirements {
+    /// Computes the disk space required to install the given `records` into `target_prefix`
+    /// using packages from `package_cache`.
+    pub fn compute<'a>(
+        records: impl IntoIterator<Item = &'a PackageRecord>,
+        package_cache: &PackageCache,
+        target_prefix: &Path,
+    ) -> Self {
+        let cache_path = package_cache.path();
+        let same_volume = match (volume_id(&cache_path), volume_id(target_prefix)) {
+            (Some(cache), Some(prefix)) => cache == prefix,
+            _ => false,
+        };
+
+        let mut requirements = Self {
+            cache_bytes: 0,
+            prefix_bytes: 0,
+            cache_path,
+            prefix_path: target_prefix.to_path_buf(),
+            same_volume,
+        };
+
+        for record in records {
+            let paths_json = package_cache.cached_package_dir(record).and_then(|dir| {
+                PathsJson::from_package_directory_with_deprecated_fallback(&dir).ok()
+            });
+            match paths_json {
+                Some(paths_json) => {
+                    requirements.prefix_bytes += paths_json
+                        .paths
+                        .iter()
+                        .map(|entry| linked_size(entry, same_volume))
+                        .sum::<u64>();
+                }
+                None => {
+                    // The archive is stored in the cache while it is extracted.
+                    let archive_size = record.size.unwrap_or(0);
+                    let extracted_size = archive_size.saturating_mul(EXTRACTED_SIZE_FACTOR);
+                    requirements.cache_bytes = requirements
+                        .cache_bytes
+                        .saturating_add(archive_size)
+                        .saturating_add(extracted_size);
+                    if !same_volume {
+                        requirements.prefix_bytes =
+                            requirements.prefix_bytes.saturating_add(extracted_size);
+                    }
+                }
+            }
+        }
+
+        requirements
+    }
+
+    /// Returns an error if the volume of the package cache or the volume of the prefix does not have
+    /// enough free space. If both are located on the same volume their requirements are combined.
+    ///
+    /// Volumes for which the available space cannot be determined on this platform are not
+    /// checked.
+    pub fn check(&self) -> Result<(), DiskSpaceError> {
+        if self.same_volume {
+            return check_volume(
+                &self.prefix_path,
+                self.cache_bytes.saturating_add(self.prefix_bytes),
+            );
+        }
+        check_volume(&self.cache_path, self.cache_bytes)?;
+        check_volume(&self.prefix_path, self.prefix_bytes)
+    }
+}
+
+/// Returns the number of bytes that linking the given entry adds to the volume of the prefix.
+fn linked_size(entry: &PathsEntry, same_volume: bool) -> u64 {
+    match entry.path_type {
+        PathType::SoftLink | PathType::Directory => 0,
+        PathType::HardLink
+            if same_volume && !entry.no_link && entry.prefix_placeholder.is_none() =>
+        {
+            0
+        }
+        PathType::HardLink => entry.size_in_bytes.unwrap_or(0),
+    }
+}
+
+/// Checks that the volume that contains `path` has at least `required` bytes available.
+fn check_volume(path: &Path, required: u64) -> Result<(), DiskSpaceError> {
+    if required == 0 {
+        return Ok(());
+    }
+    let Some(existing) = existing_ancestor(path) else {
+        return Ok(());
+    };
+    match available_space(existing) {
+        Ok(Some(available)) if available < required => Err(DiskSpaceError::InsufficientSpace {
+            path: path.to_path_buf(),
+            required,
+            available,
+        }),
+        Ok(_) => Ok(()),
+        Err(err) => Err(DiskSpaceError::FailedToQueryVolume(path.to_path_buf(), err)),
+    }
+}
+
+/// Returns the closest ancestor of `path` (including `path` itself) that exists. The cache and the
+/// prefix are usually created on demand, so their parent determines the volume they end up on.
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|ancestor| ancestor.exists())
+}
+
+/// Returns an identifier of the volume that contains `path`.
+#[cfg(unix)]
+fn volume_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(existing_ancestor(path)?).ok()?;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn volume_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Returns the number of bytes available to unprivileged users on the volume that contains `path`
+/// or `None` if this cannot be determined on the current platform.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn available_space(path: &Path) -> std::io::Result<Option<u64>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid nul-terminated string and `stat` is only read if the call
+    // succeeded.
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    Ok(Some(
+        (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64),
+    ))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DiskSpaceError, DiskSpaceRequirements, EXTRACTED_SIZE_FACTOR};
+    use crate::package_cache::PackageCache;
+    use crate::test_utils::{PackageBuilder, RecordBuilder};
+    use rattler_conda_types::package::{ArchiveIdentifier, ArchiveType};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_requirements() {
+        let archive_dir = tempdir().unwrap();
+        let archive = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "0123456789")
+            .write(archive_dir.path(), ArchiveType::Conda);
+
+        let cache_dir = tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path());
+        cache
+            .get_or_fetch(
+                ArchiveIdentifier::try_from_path(&archive).unwrap(),
+                move |destination| async move {
+                    rattler_package_streaming::tokio::fs::extract(&archive, &destination)
+                        .await
+                        .map(|_| ())
+                },
+            )
+            .await
+            .unwrap();
+
+        // The cached package is linked from the cache, the other one has to be downloaded.
+        let prefix_dir = tempdir().unwrap();
+        let prefix = prefix_dir.path().join("env");
+        let records = [
+            RecordBuilder::new("foo", "1.0")
+                .with_size(100)
+                .build()
+                .package_record,
+            RecordBuilder::new("bar", "1.0")
+                .with_size(1000)
+                .build()
+                .package_record,
+        ];
+        let requirements = DiskSpaceRequirements::compute(&records, &cache, &prefix);
+        assert_eq!(
+            requirements.cache_bytes,
+            1000 + 1000 * EXTRACTED_SIZE_FACTOR
+        );
+        if requirements.same_volume {
+            assert_eq!(requirements.prefix_bytes, 0);
+        } else {
+            assert_eq!(requirements.prefix_bytes, 10 + 1000 * EXTRACTED_SIZE_FACTOR);
+        }
+        requirements.check().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_insufficient_space() {
+        let cache_dir = tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path());
+        let records = [RecordBuilder::new("huge", "1.0")
+            .with_size(u64::MAX / 4)
+            .build()
+            .package_record];
+        let requirements = DiskSpaceRequirements::compute(&records, &cache, cache_dir.path());
+        assert!(matches!(
+            requirements.check(),
+            Err(DiskSpaceError::InsufficientSpace { .. })
+        ));
+    }
+}
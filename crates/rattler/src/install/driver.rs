@@ -1,13 +1,15 @@
-use super::InstallError;
+use super::{link::LinkMethod, InstallError, InstallHooks, InstallReport};
+use crate::runtime::{self, Runtime};
+use futures::future::{abortable, AbortHandle};
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
 use std::future::pending;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::{
     select,
     sync::mpsc::{unbounded_channel, UnboundedSender},
-    sync::oneshot,
-    task::JoinHandle,
+    sync::{oneshot, watch},
 };
 
 /// Packages can mostly be installed in isolation and therefor in parallel. However, when installing
@@ -17,16 +19,82 @@ use tokio::{
 /// process doesnt try to acquire more resources than the system has available.
 pub struct InstallDriver {
     inner: Arc<std::sync::Mutex<InstallDriverInner>>,
+    report: Arc<std::sync::Mutex<InstallReport>>,
+    hooks: Option<Arc<dyn InstallHooks>>,
     concurrency_limit: usize,
+    pending_tasks: PendingTasks,
+    runtime: Option<Arc<dyn Runtime>>,
 }
 
 struct InstallDriverInner {
-    tx: UnboundedSender<Task>,
-    join_handle: JoinHandle<()>,
+    tx: UnboundedSender<QueuedTask>,
+    abort_handle: AbortHandle,
 }
 
 type Task = Box<dyn FnOnce() + Send + 'static>;
 
+/// A task together with the runtime it should run on. Tasks without a runtime run on the runtime
+/// that is registered with [`runtime::set_runtime`].
+struct QueuedTask {
+    task: Task,
+    runtime: Option<Arc<dyn Runtime>>,
+}
+
+/// Keeps track of the tasks that have been spawned through an [`InstallDriver`] but have not
+/// finished yet, see [`InstallDriver::pending_tasks`].
+///
+/// Dropping the future that links a package does not stop the blocking tasks that it already
+/// spawned. Before the files of a partially linked package are removed, wait for these tasks to
+/// finish, otherwise they might write files after they have been removed.
+#[derive(Debug, Clone)]
+pub struct PendingTasks {
+    count: Arc<watch::Sender<usize>>,
+}
+
+impl PendingTasks {
+    fn new() -> Self {
+        Self {
+            count: Arc::new(watch::channel(0).0),
+        }
+    }
+
+    /// Registers a new task. The task is finished when the returned value is dropped.
+    fn start(&self) -> PendingTask {
+        self.count.send_modify(|count| *count += 1);
+        PendingTask {
+            count: self.count.clone(),
+        }
+    }
+
+    /// Returns the number of tasks that are queued or running.
+    pub fn len(&self) -> usize {
+        *self.count.borrow()
+    }
+
+    /// Returns true if there are no tasks queued or running.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits until all tasks have finished. Tasks that are queued but not started yet when the
+    /// [`InstallDriver`] is dropped are never started.
+    pub async fn wait(&self) {
+        let mut receiver = self.count.subscribe();
+        let _ = receiver.wait_for(|count| *count == 0).await;
+    }
+}
+
+/// A task that is registered with [`PendingTasks`] until it is dropped.
+struct PendingTask {
+    count: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for PendingTask {
+    fn drop(&mut self) {
+        self.count.send_modify(|count| *count -= 1);
+    }
+}
+
 impl Default for InstallDriver {
     fn default() -> Self {
         Self::new(100)
@@ -38,8 +106,26 @@ impl InstallDriver {
     /// the number of tasks spawned through the driver that can run concurrently. This is especially
     /// useful to make sure no filesystem limits are encountered.
     pub fn new(concurrency_limit: usize) -> Self {
-        let (tx, mut rx) = unbounded_channel::<Task>();
-        let join_handle = tokio::spawn(async move {
+        Self::new_with_optional_runtime(concurrency_limit, None)
+    }
+
+    /// Constructs a new [`InstallDriver`] that runs its tasks on the given runtime instead of the
+    /// runtime that is registered with [`runtime::set_runtime`]. At most `concurrency_limit` tasks
+    /// run concurrently.
+    ///
+    /// Linking packages is CPU and IO intensive. Use a [`runtime::ThreadPoolRuntime`] to bound the
+    /// number of threads that are used to link packages and to keep them from competing with the
+    /// thread pools of an embedding application.
+    pub fn with_runtime(concurrency_limit: usize, runtime: impl Runtime) -> Self {
+        Self::new_with_optional_runtime(concurrency_limit, Some(Arc::new(runtime)))
+    }
+
+    fn new_with_optional_runtime(
+        concurrency_limit: usize,
+        runtime: Option<Arc<dyn Runtime>>,
+    ) -> Self {
+        let (tx, mut rx) = unbounded_channel::<QueuedTask>();
+        let (driver, abort_handle) = abortable(async move {
             let mut pending_futures = FuturesUnordered::new();
             loop {
                 // Build a future to receive a new task to execute, or do not accept new tasks
@@ -53,8 +139,14 @@ impl InstallDriver {
                 // Wait for a new tasks or on of the futures that finishes.
                 select! {
                     task = next_task => {match task {
-                        Some(task) => {
-                            pending_futures.push(tokio::task::spawn_blocking(task));
+                        Some(QueuedTask { task, runtime: Some(task_runtime) }) => {
+                            pending_futures.push(
+                                runtime::spawn_blocking_on(task_runtime.as_ref(), task)
+                                    .left_future(),
+                            );
+                        }
+                        Some(QueuedTask { task, runtime: None }) => {
+                            pending_futures.push(runtime::spawn_blocking(task).right_future());
                         }
                         None => {
                             // The sender closed, this means the outer struct was dropped, which
@@ -62,35 +154,102 @@ impl InstallDriver {
                             break;
                         }
                     }},
-                    Some(result) = pending_futures.next() => {if let Err(err) = result {
-                        // If a panic occurred in the blocking task we resume the error here to make sure
-                        // its not getting lost.
-                        if let Ok(panic) = err.try_into_panic() {
-                            std::panic::resume_unwind(panic);
-                        }
-
-                        // Note: we dont handle the cancelled error here. This can be handled by a
-                        // sender/receiver pair that get closed when the task drops.
-                    }}
+                    // Panics that occur in the blocking tasks are resumed when the result is
+                    // polled, so they don't get lost.
+                    // Note: we dont handle the cancelled error here. This can be handled by a
+                    // sender/receiver pair that get closed when the task drops.
+                    Some(_) = pending_futures.next() => {}
                 }
             }
         });
+        runtime::spawn(driver.map(|_| ()));
         Self {
             inner: Arc::new(std::sync::Mutex::new(InstallDriverInner {
                 tx,
-                join_handle,
+                abort_handle,
             })),
+            report: Arc::default(),
+            hooks: None,
             concurrency_limit,
+            pending_tasks: PendingTasks::new(),
+            runtime,
+        }
+    }
+
+    /// Returns a driver that shares the concurrency limit, the report and the hooks of this driver
+    /// but runs its tasks on the given runtime. See [`super::InstallOptions::runtime`].
+    pub(crate) fn with_task_runtime(&self, runtime: Arc<dyn Runtime>) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            report: self.report.clone(),
+            hooks: self.hooks.clone(),
+            concurrency_limit: self.concurrency_limit,
+            pending_tasks: self.pending_tasks.clone(),
+            runtime: Some(runtime),
+        }
+    }
+
+    /// Registers hooks that are called while packages are linked through this driver. See
+    /// [`InstallHooks`] for more information.
+    pub fn with_hooks(self, hooks: impl InstallHooks + 'static) -> Self {
+        Self {
+            hooks: Some(Arc::new(hooks)),
+            ..self
         }
     }
 
+    /// Returns the hooks that were registered with [`InstallDriver::with_hooks`].
+    pub(crate) fn hooks(&self) -> Option<Arc<dyn InstallHooks>> {
+        self.hooks.clone()
+    }
+
+    /// Signals that all packages of a transaction have been installed into `target_prefix`. This
+    /// calls [`InstallHooks::on_transaction_complete`] of the registered hooks with the report of
+    /// this driver.
+    pub async fn complete_transaction(&self, target_prefix: &Path) -> Result<(), InstallError> {
+        let Some(hooks) = self.hooks() else {
+            return Ok(());
+        };
+        let target_prefix = target_prefix.to_owned();
+        let report = self.report();
+        self.spawn_throttled(move || {
+            hooks
+                .on_transaction_complete(&target_prefix, &report)
+                .map_err(|e| InstallError::HookFailed("on_transaction_complete", e))
+        })
+        .await
+    }
+
     /// Returns the number of tasks that can run in parallel.
     pub fn concurrency_limit(&self) -> usize {
         self.concurrency_limit
     }
 
+    /// Returns the tasks that have been spawned through this driver and have not finished yet.
+    /// The returned value remains valid after the driver has been dropped.
+    pub fn pending_tasks(&self) -> PendingTasks {
+        self.pending_tasks.clone()
+    }
+
+    /// Returns a report of all the packages that have been linked using this driver.
+    pub fn report(&self) -> InstallReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Returns a function that records a linked file in the report of this driver. The function
+    /// can be moved to the tasks that link the files.
+    pub(crate) fn link_recorder(&self) -> impl Fn(LinkMethod, u64) + Send + Sync + Clone + 'static {
+        let report = self.report.clone();
+        move |method, bytes| report.lock().unwrap().link_statistics.record(method, bytes)
+    }
+
+    /// Records that a package has been linked successfully.
+    pub(crate) fn record_linked_package(&self) {
+        self.report.lock().unwrap().linked_packages += 1;
+    }
+
     /// Spawns a blocking operation on another thread and waits for it to complete. This is similar
-    /// to calling [`tokio::task::spawn_blocking`] except that the number of concurrent tasks is
+    /// to calling [`runtime::spawn_blocking`] except that the number of concurrent tasks is
     /// limited. This is especially useful when performing filesystem operations because most
     /// platforms have a limit on the number of concurrent filesystem operations.
     pub async fn spawn_throttled<
@@ -117,12 +276,23 @@ impl InstallDriver {
     }
 
     /// Spawns a blocking operation on another thread but does not wait for it to complete. This is
-    /// similar to calling [`tokio::task::spawn_blocking`] except that the number of concurrent
+    /// similar to calling [`runtime::spawn_blocking`] except that the number of concurrent
     /// tasks is limited. This is especially useful when performing filesystem operations because
     /// most platforms have a limit on the number of concurrent filesystem operations.
     pub fn spawn_throttled_and_forget<F: FnOnce() + Send + 'static>(&self, f: F) {
+        // The task is registered until the closure is dropped, which also happens when the
+        // closure is never called because the driver was dropped first.
+        let task = self.pending_tasks.start();
+        let f = move || {
+            let _task = task;
+            f()
+        };
+        let task = QueuedTask {
+            task: Box::new(f),
+            runtime: self.runtime.clone(),
+        };
         let inner = self.inner.lock().unwrap();
-        if inner.tx.send(Box::new(f)).is_err() {
+        if inner.tx.send(task).is_err() {
             unreachable!(
                 "if a send error occurs here it means the task processor is dropped. \
                 Since this only happens when dropping this object there cannot be another call to \
@@ -134,6 +304,57 @@ impl InstallDriver {
 
 impl Drop for InstallDriverInner {
     fn drop(&mut self) {
-        self.join_handle.abort()
+        self.abort_handle.abort()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InstallDriver;
+    use crate::runtime::ThreadPoolRuntime;
+    use std::sync::{mpsc, Arc};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pending_tasks_outlive_driver() {
+        let driver = InstallDriver::new(1);
+        let pending_tasks = driver.pending_tasks();
+        assert!(pending_tasks.is_empty());
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        driver.spawn_throttled_and_forget(move || {
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+        });
+
+        // The second task is queued behind the first one and never starts because the driver is
+        // dropped before the first task finishes.
+        driver.spawn_throttled_and_forget(|| panic!("the driver has been dropped"));
+        assert_eq!(pending_tasks.len(), 2);
+
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        drop(driver);
+
+        // The running task is still pending until it finishes.
+        assert!(!pending_tasks.is_empty());
+        finish_tx.send(()).unwrap();
+        pending_tasks.wait().await;
+        assert!(pending_tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_task_runtime() {
+        let driver = InstallDriver::new(1);
+        let pool_driver = driver.with_task_runtime(Arc::new(ThreadPoolRuntime::new(1)));
+        let thread_name = || Ok(std::thread::current().name().map(str::to_owned));
+
+        let name = pool_driver.spawn_throttled(thread_name).await.unwrap();
+        assert_eq!(name.as_deref(), Some("rattler-worker-0"));
+
+        // The original driver still runs its tasks on the default runtime.
+        let name = driver.spawn_throttled(thread_name).await.unwrap();
+        assert_ne!(name.as_deref(), Some("rattler-worker-0"));
     }
 }
@@ -1,7 +1,7 @@
-use crate::install::PythonInfo;
+use crate::install::{PermissionPolicy, PythonInfo};
 use digest::Output;
 use rattler_conda_types::{
-    package::EntryPoint,
+    package::{EntryPoint, FileMode},
     prefix_record::{PathType, PathsEntry},
 };
 use rattler_digest::HashingWriter;
@@ -22,11 +22,14 @@ use std::{fs::File, io, io::Write, path::Path};
 /// here: <https://github.com/conda/conda-build/tree/master/conda_build/launcher_sources>.
 ///
 /// See [`create_unix_python_entry_point`] for the unix variant of this function.
+///
+/// The directories and files are created according to the `permissions`.
 pub fn create_windows_python_entry_point(
     target_dir: &Path,
     target_prefix: &str,
     entry_point: &EntryPoint,
     python_info: &PythonInfo,
+    permissions: PermissionPolicy,
 ) -> Result<[PathsEntry; 2], std::io::Error> {
     // Construct the path to where we will be creating the python entry point script.
     let relative_path_script_py = python_info
@@ -35,13 +38,14 @@ pub fn create_windows_python_entry_point(
 
     // Write the contents of the launcher script to disk
     let script_path = target_dir.join(&relative_path_script_py);
-    std::fs::create_dir_all(
+    permissions.create_dir_all(
         script_path
             .parent()
             .expect("since we joined with target_dir there must be a parent"),
     )?;
     let script_contents = python_entry_point_template(target_prefix, entry_point, python_info);
     let (hash, size) = write_and_hash(&script_path, script_contents)?;
+    permissions.apply_to_file(&script_path)?;
 
     // Construct a path to where we will create the python launcher executable.
     let relative_path_script_exe = python_info
@@ -50,7 +54,9 @@ pub fn create_windows_python_entry_point(
 
     // Include the bytes of the launcher directly in the binary so we can write it to disk.
     let launcher_bytes = include_bytes!("../../resources/launcher.exe");
-    std::fs::write(target_dir.join(&relative_path_script_exe), launcher_bytes)?;
+    let launcher_path = target_dir.join(&relative_path_script_exe);
+    std::fs::write(&launcher_path, launcher_bytes)?;
+    permissions.apply_to_file(&launcher_path)?;
 
     let fixed_launcher_digest = rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
         "28b001bb9a72ae7a24242bfab248d767a1ac5dec981c672a3944f7a072375e9a",
@@ -65,6 +71,8 @@ pub fn create_windows_python_entry_point(
             sha256: Some(hash),
             sha256_in_prefix: None,
             size_in_bytes: Some(size as _),
+            prefix_placeholder: Some(target_prefix.to_owned()),
+            file_mode: Some(FileMode::Text),
         },
         PathsEntry {
             relative_path: relative_path_script_exe,
@@ -73,6 +81,8 @@ pub fn create_windows_python_entry_point(
             sha256: Some(fixed_launcher_digest),
             sha256_in_prefix: None,
             size_in_bytes: Some(launcher_bytes.len() as u64),
+            prefix_placeholder: None,
+            file_mode: None,
         },
     ])
 }
@@ -84,18 +94,21 @@ pub fn create_windows_python_entry_point(
 /// python compiler which in turn invokes the correct Python function.
 ///
 /// On windows things are a bit more complicated. See [`create_windows_python_entry_point`].
+///
+/// The directories and files are created according to the `permissions`.
 pub fn create_unix_python_entry_point(
     target_dir: &Path,
     target_prefix: &str,
     entry_point: &EntryPoint,
     python_info: &PythonInfo,
+    permissions: PermissionPolicy,
 ) -> Result<PathsEntry, std::io::Error> {
     // Construct the path to where we will be creating the python entry point script.
     let relative_path = python_info.bin_dir.join(&entry_point.command);
 
     // Write the contents of the launcher script to disk
     let script_path = target_dir.join(&relative_path);
-    std::fs::create_dir_all(
+    permissions.create_dir_all(
         script_path
             .parent()
             .expect("since we joined with target_dir there must be a parent"),
@@ -106,9 +119,10 @@ pub fn create_unix_python_entry_point(
     // Make the script executable. This is only supported on Unix based filesystems.
     #[cfg(unix)]
     std::fs::set_permissions(
-        script_path,
+        &script_path,
         std::os::unix::fs::PermissionsExt::from_mode(0o775),
     )?;
+    permissions.apply_to_file(&script_path)?;
 
     Ok(PathsEntry {
         relative_path,
@@ -117,6 +131,8 @@ pub fn create_unix_python_entry_point(
         sha256: Some(hash),
         sha256_in_prefix: None,
         size_in_bytes: Some(size as _),
+        prefix_placeholder: Some(target_prefix.to_owned()),
+        file_mode: Some(FileMode::Text),
     })
 }
 
@@ -162,10 +178,92 @@ fn write_and_hash(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<(Output
 
 #[cfg(test)]
 mod test {
-    use crate::install::PythonInfo;
-    use rattler_conda_types::package::EntryPoint;
+    use crate::install::{PermissionPolicy, PythonInfo};
+    use rattler_conda_types::package::{EntryPoint, FileMode};
+    use rattler_conda_types::prefix_record::{PathType, PathsEntry};
     use rattler_conda_types::{Platform, Version};
-    use std::str::FromStr;
+    use rattler_digest::{compute_file_digest, Sha256};
+    use std::{path::Path, str::FromStr};
+
+    fn python_info(platform: Platform) -> PythonInfo {
+        PythonInfo::from_version(&Version::from_str("3.11.0").unwrap(), platform).unwrap()
+    }
+
+    /// Asserts that the recorded hash and size of the entry match the file on disk.
+    fn assert_entry_matches_file(target_dir: &Path, entry: &PathsEntry) {
+        let path = target_dir.join(&entry.relative_path);
+        assert_eq!(
+            entry.sha256,
+            Some(compute_file_digest::<Sha256>(&path).unwrap())
+        );
+        assert_eq!(
+            entry.size_in_bytes,
+            Some(std::fs::metadata(&path).unwrap().len())
+        );
+    }
+
+    #[test]
+    fn test_create_unix_entry_point() {
+        let target_dir = tempfile::tempdir().unwrap();
+        let entry = super::create_unix_python_entry_point(
+            target_dir.path(),
+            "/prefix",
+            &EntryPoint::from_str("jupyter-lab = jupyterlab.labapp:main").unwrap(),
+            &python_info(Platform::Linux64),
+            PermissionPolicy {
+                directory_mode: Some(0o2775),
+                additional_file_mode: 0o002,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(entry.relative_path, Path::new("bin/jupyter-lab"));
+        assert_eq!(entry.path_type, PathType::UnixPythonEntryPoint);
+        assert_entry_matches_file(target_dir.path(), &entry);
+
+        // The shebang contains the prefix, so the script has to be rewritten when it is moved.
+        assert_eq!(entry.prefix_placeholder.as_deref(), Some("/prefix"));
+        assert_eq!(entry.file_mode, Some(FileMode::Text));
+
+        let script = std::fs::read_to_string(target_dir.path().join("bin/jupyter-lab")).unwrap();
+        assert!(script.starts_with("#!/prefix/bin/python3.11\n"));
+        assert!(script.contains("from jupyterlab.labapp import main"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(target_dir.path().join("bin/jupyter-lab")).unwrap();
+            assert_ne!(metadata.permissions().mode() & 0o111, 0);
+            assert_ne!(metadata.permissions().mode() & 0o002, 0);
+            let metadata = std::fs::metadata(target_dir.path().join("bin")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o7777, 0o2775);
+        }
+    }
+
+    #[test]
+    fn test_create_windows_entry_point() {
+        let target_dir = tempfile::tempdir().unwrap();
+        let [script, exe] = super::create_windows_python_entry_point(
+            target_dir.path(),
+            "C:\\prefix",
+            &EntryPoint::from_str("jupyter-lab = jupyterlab.labapp:main").unwrap(),
+            &python_info(Platform::Win64),
+            PermissionPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            script.relative_path,
+            Path::new("Scripts/jupyter-lab-script.py")
+        );
+        assert_eq!(script.path_type, PathType::WindowsPythonEntryPointScript);
+        assert_entry_matches_file(target_dir.path(), &script);
+
+        // The launcher is recorded with the hash of the embedded executable.
+        assert_eq!(exe.relative_path, Path::new("Scripts/jupyter-lab.exe"));
+        assert_eq!(exe.path_type, PathType::WindowsPythonEntryPointExe);
+        assert_entry_matches_file(target_dir.path(), &exe);
+    }
 
     #[test]
     fn test_entry_point_script() {
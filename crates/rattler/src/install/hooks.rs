@@ -0,0 +1,68 @@
+//! Hooks that allow embedders to observe and influence the installation of packages.
+//!
+//! An [`InstallHooks`] implementation is registered with an [`super::InstallDriver`] using
+//! [`super::InstallDriver::with_hooks`]. [`super::link_package`] calls the package hooks of the
+//! driver before and after the files of a package are linked, which makes it possible to collect
+//! telemetry, enforce policies or generate additional files without reimplementing the installer.
+//! Because a transaction consists of many calls to [`super::link_package`], the caller signals the
+//! end of a transaction with [`super::InstallDriver::complete_transaction`].
+//!
+//! The hooks run on the blocking threads of the driver so they are free to perform (blocking)
+//! filesystem operations. Returning an error from a hook aborts the installation of the package
+//! with [`super::InstallError::HookFailed`]. If [`InstallHooks::on_package_postlink`] fails, the
+//! files that were linked are removed from the prefix again.
+
+use super::InstallReport;
+use rattler_conda_types::{package::IndexJson, prefix_record::PathsEntry, InstallablePackage};
+use std::path::Path;
+
+/// The error type that is returned by the functions of [`InstallHooks`].
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Information about the package that is passed to the package hooks of [`InstallHooks`].
+#[derive(Debug, Clone, Copy)]
+pub struct PackageHookContext<'a> {
+    /// The directory that contains the extracted package.
+    pub package_dir: &'a Path,
+
+    /// The directory of the prefix the package is linked into.
+    pub target_prefix: &'a Path,
+
+    /// The `index.json` of the package that is linked.
+    pub index_json: &'a IndexJson,
+
+    /// The package that is linked, which includes the url it was downloaded from. This is only
+    /// available if it was passed to [`super::link_package`] with
+    /// [`super::InstallOptions::package`].
+    pub package: Option<&'a dyn InstallablePackage>,
+}
+
+/// Functions that are called at specific points of an installation. All functions do nothing by
+/// default so implementations only have to implement the hooks they are interested in.
+pub trait InstallHooks: Send + Sync {
+    /// Called before any of the files of a package are linked into the prefix. Returning an error
+    /// prevents the package from being installed.
+    fn on_package_prelink(&self, _package: &PackageHookContext<'_>) -> Result<(), HookError> {
+        Ok(())
+    }
+
+    /// Called after all files of a package have been linked into the prefix. `paths` contains an
+    /// entry for every file that was linked. Returning an error removes these files again.
+    fn on_package_postlink(
+        &self,
+        _package: &PackageHookContext<'_>,
+        _paths: &[PathsEntry],
+    ) -> Result<(), HookError> {
+        Ok(())
+    }
+
+    /// Called when all packages of a transaction have been installed into `target_prefix`. The
+    /// `report` summarizes the packages that have been linked as part of the transaction.
+    fn on_transaction_complete(
+        &self,
+        _target_prefix: &Path,
+        _report: &InstallReport,
+    ) -> Result<(), HookError> {
+        Ok(())
+    }
+}
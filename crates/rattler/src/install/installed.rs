@@ -0,0 +1,172 @@
+//! Quick validation of the packages that are installed in a prefix.
+//!
+//! A package only gets a record in the `conda-meta` directory of a prefix after all of its files
+//! have been linked. If an installation fails halfway, the packages that were completely installed
+//! therefore do not have to be installed again when the installation is retried. However, files of
+//! an installed package might have been removed or truncated since, e.g. by a failed attempt to
+//! replace the package. [`verify_installed_package`] detects this without hashing any file, by
+//! checking that every file recorded in the `conda-meta` record exists and has the recorded size.
+
+use rattler_conda_types::{prefix_record::PathType, PrefixRecord};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// An error that is returned by [`verify_installed_package`] if a file of an installed package is
+/// missing or damaged.
+#[derive(Debug, thiserror::Error)]
+pub enum InstalledPackageError {
+    /// A file of the package does not exist in the prefix.
+    #[error("'{}' is missing", .0.display())]
+    MissingFile(PathBuf),
+
+    /// A file of the package does not have the size that was recorded when it was linked.
+    #[error(
+        "'{}' has a size of {actual} bytes but {expected} bytes were expected",
+        .path.display()
+    )]
+    SizeMismatch {
+        /// The path of the file relative to the prefix.
+        path: PathBuf,
+
+        /// The size that was recorded when the file was linked.
+        expected: u64,
+
+        /// The size of the file in the prefix.
+        actual: u64,
+    },
+
+    /// The metadata of a file could not be read.
+    #[error("failed to read the metadata of '{}'", .0.display())]
+    FailedToReadMetadata(PathBuf, #[source] std::io::Error),
+}
+
+/// Checks that all files of the package described by `record` exist in `prefix` and have the size
+/// they had when they were linked. The contents of the files are not hashed, so this check is
+/// cheap enough to run for every installed package.
+pub fn verify_installed_package(
+    prefix: &Path,
+    record: &PrefixRecord,
+) -> Result<(), InstalledPackageError> {
+    for entry in &record.paths_data.paths {
+        let metadata = match std::fs::symlink_metadata(prefix.join(&entry.relative_path)) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(InstalledPackageError::MissingFile(
+                    entry.relative_path.clone(),
+                ))
+            }
+            Err(e) => {
+                return Err(InstalledPackageError::FailedToReadMetadata(
+                    entry.relative_path.clone(),
+                    e,
+                ))
+            }
+        };
+
+        // The size of a soft link is the length of its target, not the size of the file it
+        // points to.
+        if matches!(entry.path_type, PathType::SoftLink | PathType::Directory) {
+            continue;
+        }
+
+        match entry.size_in_bytes {
+            Some(expected) if expected != metadata.len() => {
+                return Err(InstalledPackageError::SizeMismatch {
+                    path: entry.relative_path.clone(),
+                    expected,
+                    actual: metadata.len(),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify_installed_package, InstalledPackageError};
+    use rattler_conda_types::{
+        prefix_record::{PathType, PathsEntry, PrefixPaths},
+        PackageName, PackageRecord, PrefixRecord, RepoDataRecord, Version,
+    };
+    use std::path::PathBuf;
+
+    fn prefix_record(paths: Vec<PathsEntry>) -> PrefixRecord {
+        PrefixRecord {
+            repodata_record: RepoDataRecord {
+                package_record: PackageRecord::new(
+                    PackageName::new_unchecked("foo"),
+                    "1.0".parse::<Version>().unwrap(),
+                    String::from("0"),
+                ),
+                file_name: String::from("foo-1.0-0.tar.bz2"),
+                url: "https://conda.anaconda.org/conda-forge/noarch/foo-1.0-0.tar.bz2"
+                    .parse()
+                    .unwrap(),
+                channel: String::from("https://conda.anaconda.org/conda-forge/"),
+            },
+            package_tarball_full_path: None,
+            extracted_package_dir: None,
+            files: paths
+                .iter()
+                .map(|entry| entry.relative_path.clone())
+                .collect(),
+            paths_data: PrefixPaths {
+                paths_version: 1,
+                paths,
+            },
+            link: None,
+            requested_spec: None,
+        }
+    }
+
+    fn entry(path: &str, size_in_bytes: Option<u64>) -> PathsEntry {
+        PathsEntry {
+            relative_path: PathBuf::from(path),
+            path_type: PathType::HardLink,
+            no_link: false,
+            sha256: None,
+            sha256_in_prefix: None,
+            size_in_bytes,
+            prefix_placeholder: None,
+            file_mode: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_installed_package() {
+        let prefix = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(prefix.path().join("bin")).unwrap();
+        std::fs::write(prefix.path().join("bin/foo"), "0123456789").unwrap();
+
+        verify_installed_package(
+            prefix.path(),
+            &prefix_record(vec![entry("bin/foo", Some(10))]),
+        )
+        .unwrap();
+        verify_installed_package(prefix.path(), &prefix_record(vec![entry("bin/foo", None)]))
+            .unwrap();
+
+        assert!(matches!(
+            verify_installed_package(
+                prefix.path(),
+                &prefix_record(vec![entry("bin/foo", Some(20))])
+            ),
+            Err(InstalledPackageError::SizeMismatch {
+                expected: 20,
+                actual: 10,
+                ..
+            })
+        ));
+        assert!(matches!(
+            verify_installed_package(
+                prefix.path(),
+                &prefix_record(vec![entry("bin/foo", Some(10)), entry("bin/bar", Some(3))])
+            ),
+            Err(InstalledPackageError::MissingFile(path)) if path == PathBuf::from("bin/bar")
+        ));
+    }
+}
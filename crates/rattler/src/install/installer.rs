@@ -0,0 +1,857 @@
+//! Executes the operations of a [`Transaction`] on a prefix, see [`Installer`].
+
+use super::{
+    link_package, link_time_dependency, unlink_package, DiskSpaceError, DiskSpaceRequirements,
+    InstallDriver, InstallError, InstallOptions, InstallReport, InterpreterInfo,
+    InterpreterInfoError, LinkBarrier, LinkJournal, PartialLinks, Transaction,
+    TransactionOperation, UnlinkError,
+};
+use crate::{
+    package_cache::{PackageCache, PackageCacheError},
+    runtime,
+};
+use futures::{channel::oneshot, future::Shared, FutureExt, StreamExt};
+use itertools::Itertools;
+use rattler_conda_types::{
+    prefix_record::{Link, LinkType},
+    MatchSpec, NoArchKind, PackageName, PackageRecord, Platform, PrefixRecord, RepoDataRecord,
+};
+use rattler_networking::{retry_policies::default_retry_policy, AuthenticatedClient};
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// The maximum number of operations of a transaction that are executed concurrently.
+const MAX_CONCURRENT_OPERATIONS: usize = 50;
+
+/// An error that can occur when executing a transaction with an [`Installer`].
+#[derive(Debug, thiserror::Error)]
+pub enum InstallerError {
+    /// There is not enough disk space to execute the transaction.
+    #[error(transparent)]
+    DiskSpace(#[from] DiskSpaceError),
+
+    /// A package could not be fetched into the package cache.
+    #[error("failed to fetch {}", .0.as_source())]
+    FailedToFetch(PackageName, #[source] PackageCacheError),
+
+    /// The metadata of a package in the package cache could not be read.
+    #[error("failed to read the metadata of {}", .0.as_source())]
+    FailedToReadMetadata(PackageName, #[source] std::io::Error),
+
+    /// A package could not be linked into the prefix.
+    #[error("failed to link {}", .0.as_source())]
+    FailedToLink(PackageName, #[source] InstallError),
+
+    /// An installed package could not be removed from the prefix.
+    #[error("failed to remove {}", .0.as_source())]
+    FailedToUnlink(PackageName, #[source] UnlinkError),
+
+    /// The `conda-meta` record of an installed package could not be written.
+    #[error("failed to write the conda-meta record of {}", .0.as_source())]
+    FailedToWritePrefixRecord(PackageName, #[source] std::io::Error),
+
+    /// A package could not be linked because the package it depends on at link time failed to
+    /// install.
+    #[error("cannot link {} because {} failed to install", .package.as_source(), .dependency.as_source())]
+    LinkDependencyFailed {
+        /// The package that could not be linked
+        package: PackageName,
+
+        /// The package it depends on at link time
+        dependency: PackageName,
+    },
+
+    /// The interpreter of a noarch R or Lua package is not supported.
+    #[error(transparent)]
+    InterpreterInfo(#[from] InterpreterInfoError),
+
+    /// The hooks that run after all packages have been linked failed.
+    #[error("failed to complete the transaction")]
+    FailedToCompleteTransaction(#[source] InstallError),
+
+    /// Some operations failed or were skipped while the installer kept going, see
+    /// [`Installer::with_keep_going`]. The other operations were executed.
+    #[error("{} operations failed and {} were skipped", .0.failed.len(), .0.skipped.len())]
+    OperationsFailed(FailedOperations),
+
+    /// The operation was cancelled.
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+/// The operations that failed or were skipped while an [`Installer`] kept going.
+#[derive(Debug, Default)]
+pub struct FailedOperations {
+    /// The packages whose operation failed, together with the error.
+    pub failed: Vec<(PackageName, InstallerError)>,
+
+    /// The packages that were not installed, together with the package they depend on that failed
+    /// to install.
+    pub skipped: Vec<(PackageName, PackageName)>,
+}
+
+impl FailedOperations {
+    /// Returns true if no operation failed or was skipped.
+    pub fn is_empty(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+impl From<runtime::Cancelled> for InstallerError {
+    fn from(_: runtime::Cancelled) -> Self {
+        InstallerError::Cancelled
+    }
+}
+
+/// Functions that are called while an [`Installer`] executes a transaction, e.g. to show the
+/// progress. All functions do nothing by default so implementations only have to implement the
+/// functions they are interested in.
+pub trait InstallerReporter: Send + Sync {
+    /// Called when fetching the package of `record` into the package cache has finished, whether
+    /// it succeeded or not.
+    fn on_fetch_complete(&self, _record: &RepoDataRecord) {}
+
+    /// Called when the operation that installs or removes `package` has been executed.
+    fn on_operation_complete(&self, _package: &PackageName) {}
+
+    /// Called when `package` is not installed because `failed_dependency`, a package it depends
+    /// on, failed to install. This only happens with [`Installer::with_keep_going`].
+    fn on_operation_skipped(&self, _package: &PackageName, _failed_dependency: &PackageName) {}
+
+    /// Called when the operation of `package` failed and the installer keeps going with the other
+    /// operations, see [`Installer::with_keep_going`]. Otherwise the error is returned instead.
+    fn on_operation_failed(&self, _package: &PackageName, _error: &InstallerError) {}
+}
+
+/// A function that receives the packages that were only partially linked when the execution of a
+/// transaction was interrupted, see [`Installer::with_interrupt_handler`].
+type InterruptHandler = Arc<dyn Fn(PartialLinks) + Send + Sync>;
+
+/// Executes the operations of a [`Transaction`] on a prefix: installed packages that are no longer
+/// desired are removed and new packages are fetched into a [`PackageCache`] and linked into the
+/// prefix.
+///
+/// If an operation fails, the outstanding operations are cancelled and the files of the packages
+/// that were only partially linked are removed again before the error is returned. Packages that
+/// were completely installed or removed are left as is. With [`Installer::with_keep_going`] the
+/// other operations are executed instead.
+pub struct Installer {
+    package_cache: PackageCache,
+    client: AuthenticatedClient,
+    install_driver: InstallDriver,
+    install_options: InstallOptions,
+    requested_specs: HashMap<PackageName, String>,
+    keep_going: bool,
+    reporter: Option<Arc<dyn InstallerReporter>>,
+    interrupt_handler: Option<InterruptHandler>,
+}
+
+impl Installer {
+    /// Constructs a new installer that fetches packages into `package_cache` using `client`.
+    pub fn new(package_cache: PackageCache, client: AuthenticatedClient) -> Self {
+        Self {
+            package_cache,
+            client,
+            install_driver: InstallDriver::default(),
+            install_options: InstallOptions::default(),
+            requested_specs: HashMap::new(),
+            keep_going: false,
+            reporter: None,
+            interrupt_handler: None,
+        }
+    }
+
+    /// Sets the [`InstallDriver`] that is used to link packages, e.g. to run the linking tasks on
+    /// a dedicated runtime or to register [`super::InstallHooks`].
+    pub fn with_install_driver(self, install_driver: InstallDriver) -> Self {
+        Self {
+            install_driver,
+            ..self
+        }
+    }
+
+    /// Sets the options with which packages are linked. The python information and the platform
+    /// are always taken from the transaction.
+    pub fn with_install_options(self, install_options: InstallOptions) -> Self {
+        Self {
+            install_options,
+            ..self
+        }
+    }
+
+    /// Sets the specs with which the user explicitly requested packages. They are recorded in the
+    /// `conda-meta` records of the installed packages. Packages that replace an installed package
+    /// keep the requested spec of the package they replace.
+    pub fn with_requested_specs(self, requested_specs: HashMap<PackageName, String>) -> Self {
+        Self {
+            requested_specs,
+            ..self
+        }
+    }
+
+    /// Sets whether the other operations are executed when an operation fails. A package is then
+    /// only installed once the packages it depends on have been installed, and it is skipped if
+    /// one of them failed or was skipped. The failures are returned together as
+    /// [`InstallerError::OperationsFailed`].
+    pub fn with_keep_going(self, keep_going: bool) -> Self {
+        Self { keep_going, ..self }
+    }
+
+    /// Sets the [`InstallerReporter`] that is notified about the progress of a transaction.
+    pub fn with_reporter(self, reporter: impl InstallerReporter + 'static) -> Self {
+        Self {
+            reporter: Some(Arc::new(reporter)),
+            ..self
+        }
+    }
+
+    /// Sets a function that receives the packages that were only partially linked when the future
+    /// returned by [`Self::install`] is dropped, e.g. because the user pressed Ctrl-C. The
+    /// function should remove them with [`PartialLinks::roll_back`] before the process exits.
+    /// Without a handler these packages are left in the prefix.
+    pub fn with_interrupt_handler(
+        self,
+        interrupt_handler: impl Fn(PartialLinks) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            interrupt_handler: Some(Arc::new(interrupt_handler)),
+            ..self
+        }
+    }
+
+    /// Returns the [`InstallDriver`] that is used to link packages.
+    pub fn install_driver(&self) -> &InstallDriver {
+        &self.install_driver
+    }
+
+    /// Executes the `transaction` on the environment at `target_prefix`. The `installed` records
+    /// are the records of the packages that were installed before the transaction.
+    ///
+    /// Returns a report of the packages that were linked through the [`InstallDriver`] of this
+    /// installer.
+    pub async fn install(
+        &self,
+        target_prefix: &Path,
+        transaction: Transaction<PrefixRecord, RepoDataRecord>,
+        installed: &[PackageRecord],
+    ) -> Result<InstallReport, InstallerError> {
+        if transaction.operations.is_empty() {
+            return Ok(self.install_driver.report());
+        }
+
+        // Make sure there is enough disk space before anything is downloaded or linked.
+        DiskSpaceRequirements::compute(
+            transaction
+                .operations
+                .iter()
+                .filter_map(|op| op.record_to_install())
+                .map(|record| &record.package_record),
+            &self.package_cache,
+            target_prefix,
+        )
+        .check()?;
+
+        let install_options = InstallOptions {
+            python_info: transaction.python_info.clone(),
+            platform: Some(transaction.platform),
+            ..self.install_options.clone()
+        };
+        let link_barrier = LinkBarrier::from_transaction(&transaction, installed);
+        let link_journal = InterruptGuard {
+            link_journal: LinkJournal::new(
+                target_prefix.to_path_buf(),
+                transaction.python_info.clone(),
+                self.install_driver.pending_tasks(),
+            ),
+            interrupt_handler: self.interrupt_handler.as_ref(),
+        };
+
+        // Start the operations of packages that other packages depend on at link time first so
+        // the operations waiting on them never occupy all concurrency slots. When keeping going
+        // every package waits for its dependencies, so the operations are started in topological
+        // order instead, which also starts the packages that others depend on at link time first.
+        let mut operations = transaction.operations;
+        if self.keep_going {
+            sort_operations_topologically(&mut operations);
+        } else {
+            operations.sort_by_key(|op| {
+                !op.record_to_install()
+                    .is_some_and(|record| link_barrier.is_registered(&record.package_record.name))
+            });
+        }
+        let dependency_tracker = if self.keep_going {
+            DependencyTracker::new(&operations)
+        } else {
+            DependencyTracker::default()
+        };
+
+        let mut outcomes = futures::stream::iter(operations)
+            .map(|op| {
+                let install_options = &install_options;
+                let link_barrier = &link_barrier;
+                let link_journal = &link_journal.link_journal;
+                let dependency_tracker = &dependency_tracker;
+                async move {
+                    let package = operation_package_name(&op);
+                    let result = self
+                        .execute_operation(
+                            target_prefix,
+                            op,
+                            install_options,
+                            link_barrier,
+                            link_journal,
+                            dependency_tracker,
+                        )
+                        .await;
+
+                    // Packages that wait for this package at link time or that depend on it can
+                    // no longer be installed.
+                    let installed = matches!(result, Ok(OperationOutcome::Completed));
+                    if !installed {
+                        link_barrier.abandon(&package);
+                    }
+                    dependency_tracker.finish(&package, installed);
+                    (package, result)
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_OPERATIONS);
+
+        // Unless the installer keeps going, the first failure cancels the outstanding operations.
+        let mut failures = FailedOperations::default();
+        while let Some((package, result)) = outcomes.next().await {
+            match result {
+                Ok(OperationOutcome::Completed) => {
+                    if let Some(reporter) = &self.reporter {
+                        reporter.on_operation_complete(&package);
+                    }
+                }
+                Ok(OperationOutcome::Skipped { failed_dependency }) => {
+                    if let Some(reporter) = &self.reporter {
+                        reporter.on_operation_skipped(&package, &failed_dependency);
+                    }
+                    failures.skipped.push((package, failed_dependency));
+                }
+                Err(err) if self.keep_going => {
+                    if let Some(reporter) = &self.reporter {
+                        reporter.on_operation_failed(&package, &err);
+                    }
+                    failures.failed.push((package, err));
+                }
+                Err(err) => {
+                    drop(outcomes);
+                    link_journal
+                        .link_journal
+                        .take_partial_links()
+                        .roll_back()
+                        .await;
+                    return Err(err);
+                }
+            }
+        }
+        drop(outcomes);
+
+        // Remove the files of the packages that failed halfway.
+        if !failures.is_empty() {
+            link_journal
+                .link_journal
+                .take_partial_links()
+                .roll_back()
+                .await;
+            return Err(InstallerError::OperationsFailed(failures));
+        }
+
+        self.install_driver
+            .complete_transaction(target_prefix)
+            .await
+            .map_err(InstallerError::FailedToCompleteTransaction)?;
+
+        Ok(self.install_driver.report())
+    }
+
+    /// Executes a single operation of a transaction on the environment.
+    async fn execute_operation(
+        &self,
+        target_prefix: &Path,
+        op: TransactionOperation<PrefixRecord, RepoDataRecord>,
+        install_options: &InstallOptions,
+        link_barrier: &LinkBarrier,
+        link_journal: &LinkJournal,
+        dependency_tracker: &DependencyTracker,
+    ) -> Result<OperationOutcome, InstallerError> {
+        let install_record = op.record_to_install();
+        let remove_record = op.record_to_remove();
+
+        // Remove the existing package and fetch the new package concurrently.
+        let remove_future = async {
+            let Some(record) = remove_record else {
+                return Ok(());
+            };
+            let target_prefix = target_prefix.to_path_buf();
+            let record = record.clone();
+            runtime::spawn_blocking(move || {
+                unlink_package(&target_prefix, &record).map_err(|e| {
+                    InstallerError::FailedToUnlink(
+                        record.repodata_record.package_record.name.clone(),
+                        e,
+                    )
+                })
+            })
+            .await?
+        };
+        let fetch_future = async {
+            let Some(record) = install_record else {
+                return Ok(None);
+            };
+            let result = self
+                .package_cache
+                .get_or_fetch_from_record_with_retry(
+                    record,
+                    self.client.clone(),
+                    default_retry_policy(),
+                )
+                .await;
+            if let Some(reporter) = &self.reporter {
+                reporter.on_fetch_complete(record);
+            }
+            result
+                .map(Some)
+                .map_err(|e| InstallerError::FailedToFetch(record.package_record.name.clone(), e))
+        };
+
+        // When keeping going, the environment is only modified once the packages this package
+        // depends on have been installed. Otherwise the existing package is removed while the new
+        // package is fetched.
+        let package_dir = if self.keep_going {
+            let package_dir = fetch_future.await?;
+            if let Some(failed_dependency) = dependency_tracker
+                .failed_dependency(&operation_package_name(&op))
+                .await
+            {
+                return Ok(OperationOutcome::Skipped { failed_dependency });
+            }
+            remove_future.await?;
+            package_dir
+        } else {
+            futures::try_join!(remove_future, fetch_future)?.1
+        };
+
+        let (Some(record), Some(package_dir)) = (install_record, package_dir) else {
+            return Ok(OperationOutcome::Completed);
+        };
+        let name = &record.package_record.name;
+
+        // Wait for the package this package depends on at link time. Noarch R and Lua packages are
+        // linked relative to the version of the interpreter that is published.
+        let link_dependency = link_barrier
+            .wait_for_link_dependency(&record.package_record)
+            .await;
+        if let Some(dependency) = link_time_dependency(record.package_record.noarch) {
+            let dependency = PackageName::new_unchecked(dependency);
+            if link_barrier.is_abandoned(&dependency) {
+                return Err(InstallerError::LinkDependencyFailed {
+                    package: name.clone(),
+                    dependency,
+                });
+            }
+        }
+        let interpreter_info = match (record.package_record.noarch.kind(), link_dependency) {
+            (Some(kind @ (NoArchKind::R | NoArchKind::Lua)), Some(interpreter)) => {
+                Some(InterpreterInfo::from_version(
+                    kind,
+                    &interpreter.version,
+                    install_options.platform.unwrap_or(Platform::current()),
+                )?)
+            }
+            _ => None,
+        };
+
+        // The package cache parses the `paths.json` of a package at most once, reuse that instead
+        // of parsing it again while linking.
+        let metadata = {
+            let package_cache = self.package_cache.clone();
+            let package_dir = package_dir.clone();
+            runtime::spawn_blocking(move || package_cache.package_metadata(&package_dir))
+                .await?
+                .map_err(|e| InstallerError::FailedToReadMetadata(name.clone(), e))?
+        };
+
+        link_journal.start(&package_dir, interpreter_info.clone());
+        let paths = link_package(
+            &package_dir,
+            target_prefix,
+            &self.install_driver,
+            InstallOptions {
+                interpreter_info,
+                paths_json: Some(metadata.paths_json.clone()),
+                package: Some(Arc::new(record.clone())),
+                ..install_options.clone()
+            },
+        )
+        .await
+        .map_err(|e| InstallerError::FailedToLink(name.clone(), e))?;
+
+        // Determine the spec with which the package was requested, if any. A package that replaces
+        // an installed package was requested in the same way as the package it replaces.
+        let requested_spec = self
+            .requested_specs
+            .get(name)
+            .cloned()
+            .or_else(|| remove_record.and_then(|record| record.requested_spec.clone()));
+
+        // Files are hard linked from the package cache unless hard links have been disabled, in
+        // which case they are copied.
+        let link_type = match install_options.allow_hard_links {
+            Some(false) => LinkType::Copy,
+            _ => LinkType::HardLink,
+        };
+        let prefix_record = PrefixRecord::from_repodata_record(
+            record.clone(),
+            None,
+            Some(package_dir.clone()),
+            paths,
+            requested_spec,
+            Some(Link {
+                source: package_dir.to_string_lossy().into_owned(),
+                link_type: Some(link_type),
+            }),
+        );
+
+        // Write the conda-meta information so the package is recognized as installed by conda and
+        // other tools.
+        let prefix = target_prefix.to_path_buf();
+        let permissions = install_options.file_write_options.permissions;
+        runtime::spawn_blocking(move || permissions.write_prefix_record(&prefix_record, &prefix))
+            .await?
+            .map_err(|e| InstallerError::FailedToWritePrefixRecord(name.clone(), e))?;
+
+        link_journal.finish(&package_dir);
+        link_barrier.publish(&record.package_record);
+        Ok(OperationOutcome::Completed)
+    }
+}
+
+/// The outcome of an operation that did not fail.
+enum OperationOutcome {
+    /// The operation was executed.
+    Completed,
+
+    /// The package was not installed because a package it depends on failed to install.
+    Skipped { failed_dependency: PackageName },
+}
+
+/// Passes the packages that are still being linked to the interrupt handler of an [`Installer`]
+/// when it is dropped.
+///
+/// If the execution of a transaction is interrupted, the future that executes it is dropped,
+/// which also drops this guard. Packages that were completely installed have a `conda-meta` record
+/// and are left untouched.
+struct InterruptGuard<'i> {
+    link_journal: LinkJournal,
+    interrupt_handler: Option<&'i InterruptHandler>,
+}
+
+impl Drop for InterruptGuard<'_> {
+    fn drop(&mut self) {
+        let Some(interrupt_handler) = self.interrupt_handler else {
+            return;
+        };
+        let partial_links = self.link_journal.take_partial_links();
+        if !partial_links.is_empty() {
+            interrupt_handler(partial_links);
+        }
+    }
+}
+
+/// Returns the name of the package that is affected by an operation.
+fn operation_package_name(op: &TransactionOperation<PrefixRecord, RepoDataRecord>) -> PackageName {
+    match op.record_to_install() {
+        Some(record) => record.package_record.name.clone(),
+        None => op
+            .record_to_remove()
+            .expect("an operation either installs or removes a package")
+            .repodata_record
+            .package_record
+            .name
+            .clone(),
+    }
+}
+
+/// Sorts the operations of a transaction so that packages are installed after the packages they
+/// depend on. Operations that only remove a package come first.
+fn sort_operations_topologically(
+    operations: &mut [TransactionOperation<PrefixRecord, RepoDataRecord>],
+) {
+    let order = PackageRecord::sort_topologically(
+        operations
+            .iter()
+            .filter_map(|op| op.record_to_install())
+            .collect::<Vec<_>>(),
+    )
+    .into_iter()
+    .enumerate()
+    .map(|(idx, record)| (record.package_record.name.clone(), idx))
+    .collect::<HashMap<_, _>>();
+    operations.sort_by_key(|op| {
+        op.record_to_install()
+            .and_then(|record| order.get(&record.package_record.name))
+            .map_or(0, |idx| idx + 1)
+    });
+}
+
+/// Keeps track of which packages of a transaction were installed. When keeping going, a package
+/// is only installed once the packages it depends on have been installed, and it is skipped if one
+/// of them failed or was skipped. This prevents installing packages whose dependencies are
+/// missing.
+#[derive(Default)]
+struct DependencyTracker {
+    /// The packages of the transaction that a package depends on.
+    dependencies: HashMap<PackageName, Vec<PackageName>>,
+    senders: Mutex<HashMap<PackageName, oneshot::Sender<bool>>>,
+    receivers: HashMap<PackageName, Shared<oneshot::Receiver<bool>>>,
+}
+
+impl DependencyTracker {
+    /// Constructs a tracker for the given operations, which must be sorted topologically (see
+    /// [`sort_operations_topologically`]). A package only waits for the packages that come before
+    /// it, so packages with cyclic dependencies never wait for each other.
+    fn new(operations: &[TransactionOperation<PrefixRecord, RepoDataRecord>]) -> Self {
+        let mut tracker = Self::default();
+        for op in operations {
+            let package = operation_package_name(op);
+            if let Some(record) = op.record_to_install() {
+                let dependencies = record
+                    .package_record
+                    .depends
+                    .iter()
+                    .filter_map(|spec| MatchSpec::from_str(spec).ok().and_then(|spec| spec.name))
+                    .filter(|name| tracker.receivers.contains_key(name))
+                    .unique()
+                    .collect();
+                tracker.dependencies.insert(package.clone(), dependencies);
+            }
+            let (tx, rx) = oneshot::channel();
+            tracker
+                .senders
+                .get_mut()
+                .unwrap()
+                .insert(package.clone(), tx);
+            tracker.receivers.insert(package, rx.shared());
+        }
+        tracker
+    }
+
+    /// Waits until the packages that `package` depends on have been processed. Returns the first
+    /// dependency that was not installed.
+    async fn failed_dependency(&self, package: &PackageName) -> Option<PackageName> {
+        for dependency in self.dependencies.get(package).into_iter().flatten() {
+            if !self.receivers[dependency].clone().await.unwrap_or(false) {
+                return Some(dependency.clone());
+            }
+        }
+        None
+    }
+
+    /// Records whether the operation of the package installed it.
+    fn finish(&self, package: &PackageName, installed: bool) {
+        if let Some(tx) = self.senders.lock().unwrap().remove(package) {
+            let _ = tx.send(installed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Installer, InstallerError};
+    use crate::{
+        install::Transaction,
+        package_cache::PackageCache,
+        test_utils::{serve_directory, PackageBuilder},
+    };
+    use rattler_conda_types::{
+        package::ArchiveType, PackageRecord, Platform, PrefixRecord, RepoDataRecord,
+    };
+    use rattler_digest::{compute_file_digest, Sha256};
+    use rattler_networking::AuthenticatedClient;
+    use std::path::Path;
+
+    /// Builds the packages into a directory that is served over HTTP and returns their records.
+    fn build_records(dir: &Path, packages: &[PackageBuilder]) -> Vec<RepoDataRecord> {
+        let server_url = serve_directory(dir);
+        packages
+            .iter()
+            .map(|package| {
+                let path = package.write(dir, ArchiveType::Conda);
+                let file_name = package.file_name(ArchiveType::Conda);
+                RepoDataRecord {
+                    package_record: PackageRecord::from_index_json(
+                        package.index_json().clone(),
+                        Some(std::fs::metadata(&path).unwrap().len()),
+                        Some(compute_file_digest::<Sha256>(&path).unwrap()),
+                        None,
+                    )
+                    .unwrap(),
+                    url: server_url.join(&file_name).unwrap(),
+                    file_name,
+                    channel: server_url.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Installs the `desired` records into `prefix` with a new installer.
+    async fn install(
+        prefix: &Path,
+        cache_dir: &Path,
+        desired: Vec<RepoDataRecord>,
+    ) -> Result<(), InstallerError> {
+        install_with(
+            Installer::new(PackageCache::new(cache_dir), AuthenticatedClient::default()),
+            prefix,
+            desired,
+        )
+        .await
+    }
+
+    /// Installs the `desired` records into `prefix` with the given installer.
+    async fn install_with(
+        installer: Installer,
+        prefix: &Path,
+        desired: Vec<RepoDataRecord>,
+    ) -> Result<(), InstallerError> {
+        let installed = PrefixRecord::collect_from_prefix(prefix).unwrap();
+        let installed_records = installed
+            .iter()
+            .map(|record| record.repodata_record.package_record.clone())
+            .collect::<Vec<_>>();
+        let transaction =
+            Transaction::from_current_and_desired(installed, desired, Platform::current()).unwrap();
+        installer
+            .install(prefix, transaction, &installed_records)
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_install_and_remove() {
+        let channel_dir = tempfile::tempdir().unwrap();
+        let records = build_records(
+            channel_dir.path(),
+            &[
+                PackageBuilder::new("foo", "1.0").with_file("share/foo.txt", "foo"),
+                PackageBuilder::new("bar", "1.0").with_file("share/bar.txt", "bar"),
+            ],
+        );
+        let cache_dir = tempfile::tempdir().unwrap();
+        let prefix = tempfile::tempdir().unwrap();
+
+        install(prefix.path(), cache_dir.path(), records.clone())
+            .await
+            .unwrap();
+        assert!(prefix.path().join("share/foo.txt").is_file());
+        assert!(prefix.path().join("share/bar.txt").is_file());
+        assert_eq!(
+            PrefixRecord::collect_from_prefix(prefix.path())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        install(prefix.path(), cache_dir.path(), records[..1].to_vec())
+            .await
+            .unwrap();
+        assert!(prefix.path().join("share/foo.txt").is_file());
+        assert!(!prefix.path().join("share/bar.txt").exists());
+        let installed = PrefixRecord::collect_from_prefix(prefix.path()).unwrap();
+        assert_eq!(installed.len(), 1);
+        assert_eq!(installed[0].repodata_record, records[0]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_package_is_rolled_back() {
+        let channel_dir = tempfile::tempdir().unwrap();
+        let records = build_records(
+            channel_dir.path(),
+            &[PackageBuilder::new("foo", "1.0")
+                .with_file("share/foo.txt", "foo")
+                .with_file("share/blocked/file.txt", "blocked")],
+        );
+        let cache_dir = tempfile::tempdir().unwrap();
+        let prefix = tempfile::tempdir().unwrap();
+
+        // A file in the prefix where the package expects a directory makes linking fail after
+        // other files of the package might have been linked already.
+        std::fs::create_dir_all(prefix.path().join("share")).unwrap();
+        std::fs::write(prefix.path().join("share/blocked"), "not a directory").unwrap();
+
+        let result = install(prefix.path(), cache_dir.path(), records).await;
+        assert!(matches!(result, Err(InstallerError::FailedToLink(..))));
+        assert!(!prefix.path().join("share/foo.txt").exists());
+        assert!(prefix.path().join("share/blocked").is_file());
+        assert!(PrefixRecord::collect_from_prefix(prefix.path())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keep_going() {
+        let channel_dir = tempfile::tempdir().unwrap();
+        let records = build_records(
+            channel_dir.path(),
+            &[
+                PackageBuilder::new("foo", "1.0").with_file("share/blocked/foo.txt", "foo"),
+                PackageBuilder::new("bar", "1.0")
+                    .with_dependency("foo")
+                    .with_file("share/bar.txt", "bar"),
+                PackageBuilder::new("baz", "1.0").with_file("share/baz.txt", "baz"),
+            ],
+        );
+        let cache_dir = tempfile::tempdir().unwrap();
+        let prefix = tempfile::tempdir().unwrap();
+
+        // `foo` fails to link, so `bar` which depends on it is skipped. `baz` is still installed.
+        std::fs::create_dir_all(prefix.path().join("share")).unwrap();
+        std::fs::write(prefix.path().join("share/blocked"), "not a directory").unwrap();
+
+        let installer = Installer::new(
+            PackageCache::new(cache_dir.path()),
+            AuthenticatedClient::default(),
+        )
+        .with_keep_going(true);
+        let Err(InstallerError::OperationsFailed(failures)) =
+            install_with(installer, prefix.path(), records).await
+        else {
+            panic!("expected the operations to fail");
+        };
+        assert_eq!(failures.failed.len(), 1);
+        assert_eq!(failures.failed[0].0.as_normalized(), "foo");
+        assert!(matches!(
+            failures.failed[0].1,
+            InstallerError::FailedToLink(..)
+        ));
+        assert_eq!(
+            failures
+                .skipped
+                .iter()
+                .map(|(package, dependency)| (package.as_normalized(), dependency.as_normalized()))
+                .collect::<Vec<_>>(),
+            [("bar", "foo")]
+        );
+
+        assert!(prefix.path().join("share/baz.txt").is_file());
+        assert!(!prefix.path().join("share/bar.txt").exists());
+        let installed = PrefixRecord::collect_from_prefix(prefix.path()).unwrap();
+        assert_eq!(installed.len(), 1);
+        assert_eq!(
+            installed[0]
+                .repodata_record
+                .package_record
+                .name
+                .as_normalized(),
+            "baz"
+        );
+    }
+}
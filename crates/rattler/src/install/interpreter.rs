@@ -0,0 +1,139 @@
+use rattler_conda_types::{NoArchKind, Platform, Version};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// Information required for linking noarch R and Lua packages. The struct contains information
+/// about a specific version of the interpreter that is installed in an environment.
+///
+/// Noarch Python packages use [`super::PythonInfo`] instead.
+#[derive(Debug, Clone)]
+pub struct InterpreterInfo {
+    /// The kind of noarch packages this interpreter can run
+    pub kind: NoArchKind,
+
+    /// The major and minor version
+    pub short_version: (u64, u64),
+
+    /// The relative path to where the libraries of the interpreter are stored
+    pub library_path: PathBuf,
+
+    /// Path to the binary directory
+    pub bin_dir: PathBuf,
+}
+
+/// An error that can occur when constructing an [`InterpreterInfo`].
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum InterpreterInfoError {
+    /// The version of the interpreter does not have a major and minor component.
+    #[error("invalid interpreter version '{0}'")]
+    InvalidVersion(String),
+
+    /// Packages of the given noarch kind are not linked relative to an R or Lua interpreter.
+    #[error("noarch {0:?} packages are not linked relative to an R or Lua interpreter")]
+    UnsupportedKind(NoArchKind),
+}
+
+impl InterpreterInfo {
+    /// Returns the name of the package that provides the interpreter for noarch packages of the
+    /// given kind.
+    pub fn package_name(kind: NoArchKind) -> Option<&'static str> {
+        match kind {
+            NoArchKind::R => Some("r-base"),
+            NoArchKind::Lua => Some("lua"),
+            NoArchKind::Python | NoArchKind::Generic => None,
+        }
+    }
+
+    /// Build an instance based on the version of the interpreter package and the platform it is
+    /// installed for.
+    pub fn from_version(
+        kind: NoArchKind,
+        version: &Version,
+        platform: Platform,
+    ) -> Result<Self, InterpreterInfoError> {
+        // Determine the major, and minor versions of the version
+        let (major, minor) = version
+            .as_major_minor()
+            .ok_or_else(|| InterpreterInfoError::InvalidVersion(version.to_string()))?;
+
+        let library_path = match kind {
+            NoArchKind::R if platform.is_windows() => PathBuf::from("Lib/R/library"),
+            NoArchKind::R => PathBuf::from("lib/R/library"),
+            NoArchKind::Lua if platform.is_windows() => {
+                PathBuf::from(format!("Library/share/lua/{major}.{minor}"))
+            }
+            NoArchKind::Lua => PathBuf::from(format!("share/lua/{major}.{minor}")),
+            NoArchKind::Python | NoArchKind::Generic => {
+                return Err(InterpreterInfoError::UnsupportedKind(kind))
+            }
+        };
+
+        // Binary directory
+        let bin_dir = if platform.is_windows() {
+            PathBuf::from("Scripts")
+        } else {
+            PathBuf::from("bin")
+        };
+
+        Ok(Self {
+            kind,
+            short_version: (major, minor),
+            library_path,
+            bin_dir,
+        })
+    }
+
+    /// Returns the target location of a file in a noarch R or Lua package given its location in
+    /// its package archive.
+    pub fn get_noarch_target_path<'a>(&self, relative_path: &'a Path) -> Cow<'a, Path> {
+        let library_prefix = match self.kind {
+            NoArchKind::Lua => "lua/",
+            _ => "library/",
+        };
+        if let Ok(rest) = relative_path.strip_prefix(library_prefix) {
+            self.library_path.join(rest).into()
+        } else if let Ok(rest) = relative_path.strip_prefix("scripts/") {
+            self.bin_dir.join(rest).into()
+        } else {
+            relative_path.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InterpreterInfo;
+    use rattler_conda_types::{NoArchKind, Platform, Version};
+    use std::path::Path;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_noarch_target_path() {
+        let version = Version::from_str("4.3.1").unwrap();
+        let r = InterpreterInfo::from_version(NoArchKind::R, &version, Platform::Linux64).unwrap();
+        assert_eq!(
+            r.get_noarch_target_path(Path::new("library/jsonlite/DESCRIPTION")),
+            Path::new("lib/R/library/jsonlite/DESCRIPTION")
+        );
+        assert_eq!(
+            r.get_noarch_target_path(Path::new("scripts/rscript-tool")),
+            Path::new("bin/rscript-tool")
+        );
+
+        let version = Version::from_str("5.4.6").unwrap();
+        let lua =
+            InterpreterInfo::from_version(NoArchKind::Lua, &version, Platform::Win64).unwrap();
+        assert_eq!(
+            lua.get_noarch_target_path(Path::new("lua/lpeg/init.lua")),
+            Path::new("Library/share/lua/5.4/lpeg/init.lua")
+        );
+        assert_eq!(
+            lua.get_noarch_target_path(Path::new("info/licenses/LICENSE")),
+            Path::new("info/licenses/LICENSE")
+        );
+
+        assert!(
+            InterpreterInfo::from_version(NoArchKind::Python, &version, Platform::Linux64).is_err()
+        );
+    }
+}
@@ -1,9 +1,10 @@
 //! This module contains the logic to link a give file from the package cache into the target directory.
 //! See [`link_file`] for more information.
+use crate::install::interpreter::InterpreterInfo;
 use crate::install::python::PythonInfo;
 use memmap2::Mmap;
 use rattler_conda_types::package::{FileMode, PathType, PathsEntry, PrefixPlaceholder};
-use rattler_conda_types::{NoArchType, Platform};
+use rattler_conda_types::{NoArchKind, NoArchType, Platform, PrefixRecord};
 use rattler_digest::HashingWriter;
 use rattler_digest::Sha256;
 use std::borrow::Cow;
@@ -48,6 +49,106 @@ impl fmt::Display for LinkMethod {
     }
 }
 
+/// Controls how files that are copied or patched into the destination directory are written. These
+/// options mostly matter for very large packages or packages that are installed on network
+/// filesystems.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct FileWriteOptions {
+    /// Allocate the full size of a destination file before writing its contents. This reduces
+    /// fragmentation and lets the filesystem fail early if there is not enough space.
+    pub preallocate: bool,
+
+    /// Flush the contents of every written file to disk (`fsync`) before it is considered linked.
+    /// This is slower but ensures that the files survive a crash once the package is installed.
+    pub fsync: bool,
+
+    /// Read files that contain a prefix placeholder through a buffer instead of memory mapping
+    /// them. Memory mapped files on network filesystems (e.g. NFS or SMB) can crash the process if
+    /// the file changes or the mount drops while it is read. Files are always streamed if memory
+    /// mapping fails.
+    pub stream_prefix_replacement: bool,
+
+    /// The permissions of the directories and files that are created in the destination
+    /// directory. By default the permissions are determined by the umask of the process.
+    pub permissions: PermissionPolicy,
+}
+
+/// Controls the permissions of the directories and files that are created when linking packages,
+/// e.g. to create environments that are shared by the members of a group. Permissions are only
+/// applied on unix platforms.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct PermissionPolicy {
+    /// The mode of the directories that are created, e.g. `0o2775` to create directories that are
+    /// writable by the group and in which new files inherit the group. If this is `None`
+    /// directories are created with the default permissions. Existing directories are not
+    /// modified.
+    pub directory_mode: Option<u32>,
+
+    /// Permission bits that are added to the permissions of files that are copied or patched, e.g.
+    /// `0o020` to make them writable by the group. Hard linked files share their permissions with
+    /// the file in the package cache and are never modified.
+    pub additional_file_mode: u32,
+}
+
+impl PermissionPolicy {
+    /// Returns the permissions of a file that is copied or patched from a file with the given
+    /// permissions.
+    #[allow(unused_mut)]
+    fn file_permissions(&self, mut permissions: Permissions) -> Permissions {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | self.additional_file_mode);
+        }
+        permissions
+    }
+
+    /// Adds the [`Self::additional_file_mode`] to the permissions of the file at `path`, e.g. a
+    /// file that is generated while installing a package instead of being linked from it.
+    pub(crate) fn apply_to_file(&self, path: &Path) -> std::io::Result<()> {
+        if self.additional_file_mode == 0 {
+            return Ok(());
+        }
+        let permissions = std::fs::metadata(path)?.permissions();
+        std::fs::set_permissions(path, self.file_permissions(permissions))
+    }
+
+    /// Writes the record of an installed package to the `conda-meta` directory of the prefix (see
+    /// [`PrefixRecord::write_to_prefix`]) and applies this policy to the directory and to the
+    /// written file. Returns the path of the written file.
+    pub fn write_prefix_record(
+        &self,
+        record: &PrefixRecord,
+        prefix: &Path,
+    ) -> std::io::Result<PathBuf> {
+        self.create_dir_all(&prefix.join("conda-meta"))?;
+        let path = record.write_to_prefix(prefix)?;
+        self.apply_to_file(&path)?;
+        Ok(path)
+    }
+
+    /// Recursively creates the directory at `path` and all of its missing parents using
+    /// [`Self::directory_mode`].
+    pub(crate) fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        let Some(mode) = self.directory_mode else {
+            return std::fs::create_dir_all(path);
+        };
+        if path.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        match std::fs::create_dir(path) {
+            // Explicitly set the mode because the mode passed when creating the directory is
+            // restricted by the umask.
+            Ok(()) => set_mode(path, mode),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
 /// Errors that can occur when calling [`link_file`].
 #[derive(Debug, thiserror::Error)]
 pub enum LinkFileError {
@@ -95,6 +196,10 @@ pub enum LinkFileError {
     /// No Python version was specified when installing a noarch package.
     #[error("cannot install noarch python files because there is no python version specified ")]
     MissingPythonInfo,
+
+    /// No R or Lua version was specified when installing a noarch R or Lua package.
+    #[error("cannot install noarch {0:?} files because there is no interpreter version specified")]
+    MissingInterpreterInfo(NoArchKind),
 }
 
 /// The successful result of calling [`link_file`].
@@ -134,26 +239,27 @@ pub fn link_file(
     allow_hard_links: bool,
     target_platform: Platform,
     target_python: Option<&PythonInfo>,
+    target_interpreter: Option<&InterpreterInfo>,
     apple_codesign_behavior: AppleCodeSignBehavior,
+    write_options: FileWriteOptions,
 ) -> Result<LinkedFile, LinkFileError> {
     let source_path = package_dir.join(&path_json_entry.relative_path);
 
     // Determine the destination path
-    let destination_relative_path = if noarch_type.is_python() {
-        match target_python {
-            Some(python_info) => {
-                python_info.get_python_noarch_target_path(&path_json_entry.relative_path)
-            }
-            None => return Err(LinkFileError::MissingPythonInfo),
-        }
-    } else {
-        path_json_entry.relative_path.as_path().into()
-    };
+    let destination_relative_path = destination_relative_path(
+        noarch_type,
+        path_json_entry,
+        target_python,
+        target_interpreter,
+    )?;
     let destination_path = target_dir.join(&destination_relative_path);
 
     // Ensure that all directories up to the path exist.
     if let Some(parent) = destination_path.parent() {
-        std::fs::create_dir_all(parent).map_err(LinkFileError::FailedToCreateParentDirectory)?;
+        write_options
+            .permissions
+            .create_dir_all(parent)
+            .map_err(LinkFileError::FailedToCreateParentDirectory)?;
     }
 
     // If the file already exists it most likely means that the file is clobbered. This means that
@@ -173,11 +279,17 @@ pub fn link_file(
     {
         // Memory map the source file. This provides us with easy access to a continuous stream of
         // bytes which makes it easier to search for the placeholder prefix.
-        let source = map_or_read_source_file(&source_path)?;
+        let (source, source_len) =
+            map_or_stream_source_file(&source_path, write_options.stream_prefix_replacement)?;
 
-        // Open the destination file
+        // Open the destination file. The patched file usually has the same size as the original,
+        // so that is the size that is preallocated.
         let destination = std::fs::File::create(&destination_path)
             .map_err(LinkFileError::FailedToOpenDestinationFile)?;
+        if write_options.preallocate {
+            preallocate(&destination, source_len)
+                .map_err(LinkFileError::FailedToOpenDestinationFile)?;
+        }
         let mut destination_writer = HashingWriter::<_, rattler_digest::Sha256>::new(destination);
 
         // Convert back-slashes (\) on windows with forward-slashes (/) to avoid problems with
@@ -202,13 +314,23 @@ pub fn link_file(
         };
 
         // Replace the prefix placeholder in the file with the new placeholder
-        copy_and_replace_placholders(
-            source.as_ref(),
-            &mut destination_writer,
-            placeholder,
-            &target_prefix,
-            *file_mode,
-        )?;
+        match source {
+            MmapOrStream::Mmap(mmap) => copy_and_replace_placholders(
+                mmap.as_ref(),
+                &mut destination_writer,
+                placeholder,
+                &target_prefix,
+                *file_mode,
+            )?,
+            MmapOrStream::Stream(file) => copy_and_replace_placeholders_streaming(
+                file,
+                &mut destination_writer,
+                placeholder,
+                &target_prefix,
+                *file_mode,
+            )
+            .map_err(LinkFileError::FailedToReadSourceFile)?,
+        }
 
         let (mut file, current_hash) = destination_writer.finalize();
 
@@ -217,15 +339,30 @@ pub fn link_file(
         sha256 = Some(current_hash);
         file_size = file.stream_position().ok();
 
+        // If the patched file is smaller than the preallocated size, remove the remainder.
+        if write_options.preallocate {
+            if let Some(file_size) = file_size {
+                file.set_len(file_size)?;
+            }
+        }
+        if write_options.fsync {
+            file.sync_all()?;
+        }
+
         // We no longer need the file.
         drop(file);
 
         // Copy over filesystem permissions. We do this to ensure that the destination file has the
-        // same permissions as the source file.
+        // same permissions as the source file, extended by the permission policy.
         let metadata = std::fs::symlink_metadata(&source_path)
             .map_err(LinkFileError::FailedToReadSourceFileMetadata)?;
-        std::fs::set_permissions(&destination_path, metadata.permissions())
-            .map_err(LinkFileError::FailedToUpdateDestinationFilePermissions)?;
+        std::fs::set_permissions(
+            &destination_path,
+            write_options
+                .permissions
+                .file_permissions(metadata.permissions()),
+        )
+        .map_err(LinkFileError::FailedToUpdateDestinationFilePermissions)?;
 
         // (re)sign the binary if the file is executable
         if has_executable_permissions(&metadata.permissions())
@@ -249,10 +386,18 @@ pub fn link_file(
                     }
                 }
 
-                // The file on disk changed from the original file so the hash and file size
-                // also became invalid.
-                sha256 = None;
-                file_size = None;
+                // Signing changes the file on disk, so record the hash and size of the signed
+                // file. Otherwise the hash and size of the original file would be recorded, which
+                // makes the installed package look damaged.
+                sha256 = Some(
+                    rattler_digest::compute_file_digest::<Sha256>(&destination_path)
+                        .map_err(LinkFileError::FailedToOpenDestinationFile)?,
+                );
+                file_size = Some(
+                    std::fs::symlink_metadata(&destination_path)
+                        .map_err(LinkFileError::FailedToOpenDestinationFile)?
+                        .len(),
+                );
             }
         }
         LinkMethod::Patched(*file_mode)
@@ -263,7 +408,7 @@ pub fn link_file(
         symlink_to_destination(&source_path, &destination_path)?;
         LinkMethod::Softlink
     } else {
-        copy_to_destination(&source_path, &destination_path)?;
+        copy_to_destination(&source_path, &destination_path, write_options)?;
         LinkMethod::Copy
     };
 
@@ -297,49 +442,180 @@ pub fn link_file(
     })
 }
 
-/// Either a memory mapped file or the complete contents of a file read to memory.
-enum MmapOrBytes {
-    Mmap(Mmap),
-    Bytes(Vec<u8>),
+/// Returns the path of the file described by `path_json_entry` relative to the target directory.
+/// This only differs from the path in the package for noarch packages.
+fn destination_relative_path<'a>(
+    noarch_type: NoArchType,
+    path_json_entry: &'a PathsEntry,
+    target_python: Option<&PythonInfo>,
+    target_interpreter: Option<&InterpreterInfo>,
+) -> Result<Cow<'a, Path>, LinkFileError> {
+    Ok(match noarch_type.kind() {
+        Some(NoArchKind::Python) => match target_python {
+            Some(python_info) => {
+                python_info.get_python_noarch_target_path(&path_json_entry.relative_path)
+            }
+            None => return Err(LinkFileError::MissingPythonInfo),
+        },
+        Some(kind @ (NoArchKind::R | NoArchKind::Lua)) => {
+            match target_interpreter.filter(|info| info.kind == kind) {
+                Some(interpreter_info) => {
+                    interpreter_info.get_noarch_target_path(&path_json_entry.relative_path)
+                }
+                None => return Err(LinkFileError::MissingInterpreterInfo(kind)),
+            }
+        }
+        _ => path_json_entry.relative_path.as_path().into(),
+    })
 }
 
-impl AsRef<[u8]> for MmapOrBytes {
-    fn as_ref(&self) -> &[u8] {
-        match &self {
-            MmapOrBytes::Mmap(mmap) => mmap.as_ref(),
-            MmapOrBytes::Bytes(bytes) => bytes.as_slice(),
+/// Finds the files in the package directory that are hard links to each other, e.g. because the
+/// package archive contains hard link entries. Only files without a prefix placeholder are
+/// considered. Returns the indices into `paths` of the files of every group with more than one
+/// file, in ascending order.
+///
+/// Hard links are only detected on unix platforms, on other platforms no groups are returned.
+pub(crate) fn find_hard_link_groups(package_dir: &Path, paths: &[PathsEntry]) -> Vec<Vec<usize>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut groups = std::collections::BTreeMap::<(u64, u64), Vec<usize>>::new();
+        for (index, entry) in paths.iter().enumerate() {
+            if entry.path_type != PathType::HardLink
+                || entry.prefix_placeholder.is_some()
+                || entry.no_link
+            {
+                continue;
+            }
+            match std::fs::symlink_metadata(package_dir.join(&entry.relative_path)) {
+                Ok(metadata) if metadata.is_file() && metadata.nlink() > 1 => groups
+                    .entry((metadata.dev(), metadata.ino()))
+                    .or_default()
+                    .push(index),
+                _ => {}
+            }
         }
+        let mut groups = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups
     }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (package_dir, paths);
+        Vec::new()
+    }
+}
+
+/// Installs the file described by `path_json_entry` as a hard link to `group_leader`, a file of
+/// the same hard link group (see [`find_hard_link_groups`]) that was already linked into the
+/// `target_dir`. This preserves the hard links within a package when its files are copied into
+/// the prefix.
+pub(crate) fn link_file_to_hard_link_group(
+    noarch_type: NoArchType,
+    path_json_entry: &PathsEntry,
+    target_dir: &Path,
+    target_python: Option<&PythonInfo>,
+    target_interpreter: Option<&InterpreterInfo>,
+    group_leader: &LinkedFile,
+    permissions: PermissionPolicy,
+) -> Result<LinkedFile, LinkFileError> {
+    let destination_relative_path = destination_relative_path(
+        noarch_type,
+        path_json_entry,
+        target_python,
+        target_interpreter,
+    )?;
+    let destination_path = target_dir.join(&destination_relative_path);
+    if let Some(parent) = destination_path.parent() {
+        permissions
+            .create_dir_all(parent)
+            .map_err(LinkFileError::FailedToCreateParentDirectory)?;
+    }
+
+    let clobbered = destination_path.is_file();
+    hardlink_to_destination(
+        &target_dir.join(&group_leader.relative_path),
+        &destination_path,
+    )?;
+
+    Ok(LinkedFile {
+        clobbered,
+        sha256: group_leader.sha256,
+        file_size: group_leader.file_size,
+        relative_path: destination_relative_path.into_owned(),
+        method: LinkMethod::Hardlink,
+    })
+}
+
+/// Creates the empty directory described by `path_json_entry` in the `target_dir`. Packages list
+/// empty directories that they own with [`PathType::Directory`]. Returns the path of the directory
+/// relative to the target directory.
+pub(crate) fn create_directory(
+    noarch_type: NoArchType,
+    path_json_entry: &PathsEntry,
+    target_dir: &Path,
+    target_python: Option<&PythonInfo>,
+    target_interpreter: Option<&InterpreterInfo>,
+    permissions: PermissionPolicy,
+) -> Result<PathBuf, LinkFileError> {
+    debug_assert!(path_json_entry.path_type == PathType::Directory);
+    let destination_relative_path = destination_relative_path(
+        noarch_type,
+        path_json_entry,
+        target_python,
+        target_interpreter,
+    )?;
+    permissions
+        .create_dir_all(&target_dir.join(&destination_relative_path))
+        .map_err(LinkFileError::FailedToCreateParentDirectory)?;
+    Ok(destination_relative_path.into_owned())
+}
+
+/// Either a memory mapped file or a file that is read while it is being patched.
+enum MmapOrStream {
+    Mmap(Mmap),
+    Stream(std::fs::File),
 }
 
-/// Either memory maps, or reads the contents of the file at the specified location.
+/// Either memory maps, or opens the file at the specified location for streaming. Also returns the
+/// size of the file.
 ///
 /// This method prefers to memory map the file to reduce the memory load but if memory mapping fails
-/// it falls back to reading the contents of the file.
+/// or `stream` is true the file is read through a buffer instead.
 ///
 /// This fallback exists because we've seen that in some particular situations memory mapping is not
 /// allowed. A particular dubious case we've encountered is described in the this issue:
 /// https://github.com/prefix-dev/pixi/issues/234
-fn map_or_read_source_file(source_path: &Path) -> Result<MmapOrBytes, LinkFileError> {
-    let mut file =
-        std::fs::File::open(source_path).map_err(LinkFileError::FailedToOpenSourceFile)?;
+fn map_or_stream_source_file(
+    source_path: &Path,
+    stream: bool,
+) -> Result<(MmapOrStream, u64), LinkFileError> {
+    let file = std::fs::File::open(source_path).map_err(LinkFileError::FailedToOpenSourceFile)?;
+    let len = file
+        .metadata()
+        .map_err(LinkFileError::FailedToReadSourceFileMetadata)?
+        .len();
+    if stream {
+        return Ok((MmapOrStream::Stream(file), len));
+    }
 
     // Try to memory map the file
     let mmap = unsafe { Mmap::map(&file) };
 
-    // If memory mapping the file failed for whatever reason, try reading it directly to
-    // memory instead.
+    // If memory mapping the file failed for whatever reason, stream it instead.
     Ok(match mmap {
-        Ok(memory) => MmapOrBytes::Mmap(memory),
+        Ok(memory) => (MmapOrStream::Mmap(memory), len),
         Err(err) => {
             tracing::warn!(
-                "failed to memory map {}: {err}. Reading the file to memory instead.",
+                "failed to memory map {}: {err}. Streaming the file instead.",
                 source_path.display()
             );
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)
-                .map_err(LinkFileError::FailedToReadSourceFile)?;
-            MmapOrBytes::Bytes(bytes)
+            (MmapOrStream::Stream(file), len)
         }
     })
 }
@@ -384,17 +660,79 @@ fn symlink_to_destination(
 
 /// Copy the specified file from the source (or cached) directory. If the file already exists it is
 /// removed and the operation is retried.
-fn copy_to_destination(source_path: &Path, destination_path: &Path) -> Result<(), LinkFileError> {
+fn copy_to_destination(
+    source_path: &Path,
+    destination_path: &Path,
+    write_options: FileWriteOptions,
+) -> Result<(), LinkFileError> {
     loop {
-        match std::fs::copy(source_path, destination_path) {
+        let result = if write_options.preallocate {
+            copy_preallocated(source_path, destination_path)
+        } else {
+            std::fs::copy(source_path, destination_path).map(|_| ())
+        };
+        match result {
             Err(e) if e.kind() == ErrorKind::AlreadyExists => {
                 // If the file already exists, remove it and try again.
                 std::fs::remove_file(destination_path)?;
             }
-            Ok(_) => return Ok(()),
+            Ok(_) => break,
             Err(e) => return Err(LinkFileError::FailedToLink(LinkMethod::Copy, e)),
         }
     }
+
+    // The copy has the permissions of the source file, extend them with the permission policy.
+    if write_options.permissions.additional_file_mode != 0 {
+        let permissions = std::fs::metadata(destination_path)
+            .map_err(LinkFileError::FailedToOpenDestinationFile)?
+            .permissions();
+        std::fs::set_permissions(
+            destination_path,
+            write_options.permissions.file_permissions(permissions),
+        )
+        .map_err(LinkFileError::FailedToUpdateDestinationFilePermissions)?;
+    }
+
+    if write_options.fsync {
+        std::fs::File::open(destination_path)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Copies the file at `source_path` to `destination_path` after allocating the full size of the
+/// destination file. Like [`std::fs::copy`] this also copies the permissions of the file.
+fn copy_preallocated(source_path: &Path, destination_path: &Path) -> std::io::Result<()> {
+    let mut source = std::fs::File::open(source_path)?;
+    let metadata = source.metadata()?;
+    let mut destination = std::fs::File::create(destination_path)?;
+    preallocate(&destination, metadata.len())?;
+    std::io::copy(&mut source, &mut destination)?;
+    destination.set_permissions(metadata.permissions())
+}
+
+/// Allocates `size` bytes on disk for the given file. On Linux the space is actually reserved using
+/// `posix_fallocate`, on other platforms (or filesystems that do not support it) the length of the
+/// file is set instead.
+fn preallocate(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    if size == 0 {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        if let Ok(len) = libc::off_t::try_from(size) {
+            // SAFETY: the file descriptor is valid for the lifetime of `file`.
+            match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+                0 => return Ok(()),
+                libc::EOPNOTSUPP | libc::EINVAL => {}
+                err => return Err(std::io::Error::from_raw_os_error(err)),
+            }
+        }
+    }
+
+    file.set_len(size)
 }
 
 /// Given the contents of a file copy it to the `destination` and in the process replace the
@@ -525,6 +863,88 @@ pub fn copy_and_replace_cstring_placeholder(
     }
 }
 
+/// The number of bytes that are read at once by [`copy_and_replace_placeholders_streaming`].
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Same as [`copy_and_replace_placholders`] but reads the contents of the file from `source`
+/// through a buffer instead of requiring all of the contents in memory.
+///
+/// Only the bytes that could still be part of a placeholder are kept in memory. For binary files
+/// this includes the rest of a c-style string that contains the placeholder.
+pub fn copy_and_replace_placeholders_streaming(
+    mut source: impl Read,
+    mut destination: impl Write,
+    prefix_placeholder: &str,
+    target_prefix: &str,
+    file_mode: FileMode,
+) -> Result<(), std::io::Error> {
+    let old_prefix = prefix_placeholder.as_bytes();
+    let new_prefix = target_prefix.as_bytes();
+    if old_prefix.is_empty() {
+        std::io::copy(&mut source, &mut destination)?;
+        return Ok(());
+    }
+
+    let mut buffer = Vec::with_capacity(STREAM_BUFFER_SIZE);
+    let mut eof = false;
+    loop {
+        if !eof {
+            let read = (&mut source)
+                .take(STREAM_BUFFER_SIZE as u64)
+                .read_to_end(&mut buffer)?;
+            eof = read == 0;
+        }
+
+        // Replace all the placeholders that are completely contained in the buffer.
+        let mut pos = 0;
+        while let Some(index) = memchr::memmem::find(&buffer[pos..], old_prefix) {
+            let index = pos + index;
+            match file_mode {
+                FileMode::Text => {
+                    destination.write_all(&buffer[pos..index])?;
+                    destination.write_all(new_prefix)?;
+                    pos = index + old_prefix.len();
+                }
+                FileMode::Binary => {
+                    // The complete c-style string is required to replace it.
+                    let suffix_start = index + old_prefix.len();
+                    let end = match memchr::memchr(b'\0', &buffer[suffix_start..]) {
+                        Some(offset) => suffix_start + offset,
+                        None if eof => buffer.len(),
+                        None => break,
+                    };
+                    destination.write_all(&buffer[pos..index])?;
+                    copy_and_replace_cstring_placeholder(
+                        &buffer[index..end],
+                        &mut destination,
+                        prefix_placeholder,
+                        target_prefix,
+                    )?;
+                    pos = end;
+                }
+            }
+        }
+
+        // Keep the bytes that might be the start of a placeholder (or of an incomplete c-style
+        // string that contains one).
+        let keep = if eof {
+            0
+        } else if file_mode == FileMode::Binary
+            && memchr::memmem::find(&buffer[pos..], old_prefix).is_some()
+        {
+            buffer.len() - pos
+        } else {
+            (old_prefix.len() - 1).min(buffer.len() - pos)
+        };
+        destination.write_all(&buffer[pos..buffer.len() - keep])?;
+        buffer.drain(..buffer.len() - keep);
+
+        if eof {
+            return Ok(());
+        }
+    }
+}
+
 fn symlink(source_path: &Path, destination_path: &Path) -> std::io::Result<()> {
     #[cfg(windows)]
     return std::os::windows::fs::symlink_file(source_path, destination_path);
@@ -532,6 +952,14 @@ fn symlink(source_path: &Path, destination_path: &Path) -> std::io::Result<()> {
     return std::os::unix::fs::symlink(source_path, destination_path);
 }
 
+#[allow(unused_variables)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    #[cfg(windows)]
+    return Ok(());
+    #[cfg(unix)]
+    return std::fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(mode));
+}
+
 #[allow(unused_variables)]
 fn has_executable_permissions(permissions: &Permissions) -> bool {
     #[cfg(windows)]
@@ -542,6 +970,7 @@ fn has_executable_permissions(permissions: &Permissions) -> bool {
 
 #[cfg(test)]
 mod test {
+    use rattler_conda_types::package::FileMode;
     use rstest::rstest;
     use std::io::Cursor;
 
@@ -603,4 +1032,106 @@ mod test {
         .unwrap();
         assert_eq!(&output.into_inner(), expected_output);
     }
+
+    #[rstest]
+    #[case::text(FileMode::Text)]
+    #[case::binary(FileMode::Binary)]
+    pub fn test_copy_and_replace_placeholders_streaming(#[case] file_mode: FileMode) {
+        // Place the placeholders around the boundaries of the buffer that is used for streaming.
+        let mut input = Vec::new();
+        for offset in [
+            10,
+            super::STREAM_BUFFER_SIZE - 4,
+            super::STREAM_BUFFER_SIZE - 20,
+        ] {
+            input.resize(input.len() + offset, b'a');
+            input.extend_from_slice(b"/old/placeholder/bin/python\0");
+        }
+        input.extend_from_slice(b"/old/placeholder");
+
+        for target_prefix in ["/new", "/a/much/longer/target/prefix"] {
+            let mut expected = Vec::new();
+            super::copy_and_replace_placholders(
+                &input,
+                &mut expected,
+                "/old/placeholder",
+                target_prefix,
+                file_mode,
+            )
+            .unwrap();
+
+            let mut output = Vec::new();
+            super::copy_and_replace_placeholders_streaming(
+                input.as_slice(),
+                &mut output,
+                "/old/placeholder",
+                target_prefix,
+                file_mode,
+            )
+            .unwrap();
+            assert_eq!(output, expected, "{target_prefix}");
+        }
+    }
+
+    #[test]
+    pub fn test_copy_preallocated() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let destination = dir.path().join("destination");
+        std::fs::write(&source, "Hello, world!").unwrap();
+        std::fs::write(&destination, "existing content that is longer").unwrap();
+
+        super::copy_to_destination(
+            &source,
+            &destination,
+            super::FileWriteOptions {
+                preallocate: true,
+                fsync: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&destination).unwrap(),
+            "Hello, world!"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn test_permission_policy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let policy = super::PermissionPolicy {
+            directory_mode: Some(0o2775),
+            additional_file_mode: 0o020,
+        };
+
+        let nested = dir.path().join("a/b");
+        policy.create_dir_all(&nested).unwrap();
+        for path in [dir.path().join("a"), nested.clone()] {
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o7777, 0o2775, "{}", path.display());
+        }
+
+        let source = dir.path().join("source");
+        let destination = nested.join("destination");
+        std::fs::write(&source, "Hello, world!").unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o644)).unwrap();
+        super::copy_to_destination(
+            &source,
+            &destination,
+            super::FileWriteOptions {
+                permissions: policy,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mode = std::fs::metadata(&destination)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o664);
+    }
 }
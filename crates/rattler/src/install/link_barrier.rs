@@ -0,0 +1,210 @@
+//! Some packages can only be linked after another package in the same transaction has been linked.
+//! For instance, noarch python packages are installed relative to the python installation in the
+//! environment. The [`LinkBarrier`] allows packages that others depend on at link time to publish
+//! their record once they have been linked, which unblocks the packages that wait on them.
+
+use crate::gate::Gate;
+use rattler_conda_types::{NoArchKind, NoArchType, PackageName, PackageRecord};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use super::{InterpreterInfo, Transaction, TransactionOperation};
+
+/// Returns the name of the package that must be linked before a package with the given `noarch`
+/// type can be linked, or `None` if the package can be linked independently.
+pub fn link_time_dependency(noarch: NoArchType) -> Option<&'static str> {
+    match noarch.kind()? {
+        NoArchKind::Python => Some("python"),
+        kind => InterpreterInfo::package_name(kind),
+    }
+}
+
+/// The names of all packages that other packages can depend on at link time.
+const LINK_TIME_DEPENDENCIES: &[&str] = &["python", "r-base", "lua"];
+
+/// A map from package name to a [`Gate`] that opens once the package has been linked, together
+/// with the record of the linked package. Cloning a barrier returns a handle to the same barrier.
+///
+/// Only packages that are registered with the barrier are waited on. Waiting on a package that
+/// is not registered, for instance because it is not part of the transaction, returns immediately.
+#[derive(Debug, Clone, Default)]
+pub struct LinkBarrier {
+    entries: Arc<HashMap<PackageName, BarrierEntry>>,
+}
+
+#[derive(Debug, Default)]
+struct BarrierEntry {
+    gate: Gate,
+    record: Mutex<Option<PackageRecord>>,
+}
+
+impl LinkBarrier {
+    /// Constructs a barrier that blocks on the packages with the given names until they are
+    /// published.
+    pub fn new(names: impl IntoIterator<Item = PackageName>) -> Self {
+        Self {
+            entries: Arc::new(
+                names
+                    .into_iter()
+                    .map(|name| (name, BarrierEntry::default()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Constructs a barrier for the packages that other packages depend on at link time.
+    ///
+    /// Packages that are installed by the transaction block until they are published. Packages in
+    /// `installed` (the packages currently installed in the prefix) that are not modified by the
+    /// transaction are published immediately, so packages that depend on them can still obtain
+    /// their record.
+    pub fn from_transaction<Old, New>(
+        transaction: &Transaction<Old, New>,
+        installed: impl IntoIterator<Item = impl AsRef<PackageRecord>>,
+    ) -> Self
+    where
+        Old: AsRef<New>,
+        New: AsRef<PackageRecord>,
+    {
+        let is_link_time_dependency =
+            |name: &PackageName| LINK_TIME_DEPENDENCIES.contains(&name.as_normalized());
+
+        let mut modified = HashSet::new();
+        for operation in &transaction.operations {
+            if let Some(record) = operation.record_to_install() {
+                modified.insert(record.as_ref().name.clone());
+            }
+            if let Some(record) = operation.record_to_remove() {
+                modified.insert(AsRef::<New>::as_ref(record).as_ref().name.clone());
+            }
+        }
+
+        let unmodified = installed
+            .into_iter()
+            .filter(|record| {
+                let name = &record.as_ref().name;
+                is_link_time_dependency(name) && !modified.contains(name)
+            })
+            .map(|record| record.as_ref().clone())
+            .collect::<Vec<_>>();
+
+        let barrier = Self::new(
+            transaction
+                .operations
+                .iter()
+                .filter_map(TransactionOperation::record_to_install)
+                .map(|record| record.as_ref().name.clone())
+                .chain(unmodified.iter().map(|record| record.name.clone()))
+                .filter(is_link_time_dependency),
+        );
+        for record in &unmodified {
+            barrier.publish(record);
+        }
+        barrier
+    }
+
+    /// Returns true if linking the package with the given name unblocks other packages.
+    pub fn is_registered(&self, name: &PackageName) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Publishes the record of a package that has been linked and wakes up all the tasks that are
+    /// waiting on it. Does nothing if the package is not registered with the barrier.
+    pub fn publish(&self, record: &PackageRecord) {
+        if let Some(entry) = self.entries.get(&record.name) {
+            *entry.record.lock().unwrap() = Some(record.clone());
+            entry.gate.open();
+        }
+    }
+
+    /// Marks the package with the given name as abandoned, for instance because it failed to
+    /// install. This wakes up all the tasks that are waiting on it without providing a record, see
+    /// [`Self::is_abandoned`]. Does nothing if the package is not registered with the barrier.
+    pub fn abandon(&self, name: &PackageName) {
+        if let Some(entry) = self.entries.get(name) {
+            entry.gate.open();
+        }
+    }
+
+    /// Returns true if the package with the given name was abandoned instead of published.
+    pub fn is_abandoned(&self, name: &PackageName) -> bool {
+        self.entries
+            .get(name)
+            .is_some_and(|entry| entry.gate.is_open() && entry.record.lock().unwrap().is_none())
+    }
+
+    /// Waits until the package with the given name has been published and returns its record.
+    /// Returns `None` immediately if the package is not registered with the barrier and `None`
+    /// once the package is abandoned.
+    pub async fn wait(&self, name: &PackageName) -> Option<PackageRecord> {
+        let entry = self.entries.get(name)?;
+        entry.gate.wait().await;
+        entry.record.lock().unwrap().clone()
+    }
+
+    /// Waits until the package that must be linked before `record` has been published. Returns
+    /// immediately if the package does not depend on another package at link time.
+    pub async fn wait_for_link_dependency(&self, record: &PackageRecord) -> Option<PackageRecord> {
+        let name = PackageName::new_unchecked(link_time_dependency(record.noarch)?);
+        self.wait(&name).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LinkBarrier;
+    use rattler_conda_types::{NoArchType, PackageName, PackageRecord, Version};
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_link_barrier() {
+        let python = PackageRecord::new(
+            PackageName::new_unchecked("python"),
+            Version::from_str("3.11.0").unwrap(),
+            String::from("0"),
+        );
+        let mut requests = PackageRecord::new(
+            PackageName::new_unchecked("requests"),
+            Version::from_str("2.31.0").unwrap(),
+            String::from("pyhd8ed1ab_0"),
+        );
+        requests.noarch = NoArchType::python();
+
+        let barrier = LinkBarrier::new([python.name.clone()]);
+        assert!(barrier.is_registered(&python.name));
+        assert!(!barrier.is_registered(&requests.name));
+
+        let waiter = tokio::spawn({
+            let barrier = barrier.clone();
+            async move { barrier.wait_for_link_dependency(&requests).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        barrier.publish(&python);
+        assert_eq!(waiter.await.unwrap(), Some(python.clone()));
+
+        // Abandoning a package wakes up the waiters without a record.
+        let lua = PackageName::new_unchecked("lua");
+        let barrier = LinkBarrier::new([lua.clone()]);
+        let waiter = tokio::spawn({
+            let barrier = barrier.clone();
+            let lua = lua.clone();
+            async move { barrier.wait(&lua).await }
+        });
+        assert!(!barrier.is_abandoned(&lua));
+        barrier.abandon(&lua);
+        assert_eq!(waiter.await.unwrap(), None);
+        assert!(barrier.is_abandoned(&lua));
+
+        // Packages that are not registered do not block.
+        assert_eq!(
+            LinkBarrier::default()
+                .wait(&PackageName::new_unchecked("python"))
+                .await,
+            None
+        );
+    }
+}
@@ -14,29 +14,55 @@
 //! also contains a SHA256 hash for each file. This hash is used to verify that the file was not
 //! tampered with.
 pub mod apple_codesign;
+mod disk_space;
 mod driver;
 mod entry_point;
+mod hooks;
+mod installed;
+mod installer;
+mod interpreter;
 pub mod link;
+mod link_barrier;
+mod pip;
 mod python;
+mod relocate;
+pub mod remove;
+mod report;
 mod transaction;
+mod unlink;
 
 pub use crate::install::entry_point::python_entry_point_template;
-pub use driver::InstallDriver;
-pub use link::{link_file, LinkFileError};
-pub use transaction::{Transaction, TransactionError, TransactionOperation};
+pub use disk_space::{DiskSpaceError, DiskSpaceRequirements, EXTRACTED_SIZE_FACTOR};
+pub use driver::{InstallDriver, PendingTasks};
+pub use hooks::{HookError, InstallHooks, PackageHookContext};
+pub use installed::{verify_installed_package, InstalledPackageError};
+pub use installer::{FailedOperations, Installer, InstallerError, InstallerReporter};
+pub use interpreter::{InterpreterInfo, InterpreterInfoError};
+pub use link::{link_file, FileWriteOptions, LinkFileError, PermissionPolicy};
+pub use link_barrier::{link_time_dependency, LinkBarrier};
+pub use pip::{find_pip_distributions, find_python_distributions, PythonDistribution};
+pub use relocate::{relocate_prefix, RelocateError};
+pub use report::{FileCount, InstallReport, LinkStatistics};
+pub use transaction::{
+    environment_platform, Transaction, TransactionError, TransactionOperation,
+    TRANSACTION_SCHEMA_VERSION,
+};
+pub use unlink::{unlink_package, LinkJournal, PartialLinks, UnlinkError};
 
 use crate::install::entry_point::{
     create_unix_python_entry_point, create_windows_python_entry_point,
 };
+use crate::runtime::Runtime;
 pub use apple_codesign::AppleCodeSignBehavior;
 use futures::FutureExt;
+use link::LinkedFile;
 pub use python::PythonInfo;
-use rattler_conda_types::package::{IndexJson, LinkJson, NoArchLinks, PackageFile};
-use rattler_conda_types::prefix_record::PathsEntry;
-use rattler_conda_types::{package::PathsJson, Platform};
+use rattler_conda_types::package::{self, IndexJson, LinkJson, NoArchLinks, PackageFile};
+use rattler_conda_types::prefix_record::{self, PathsEntry};
+use rattler_conda_types::{package::PathsJson, InstallablePackage, NoArchKind, Platform};
 use std::cmp::Ordering;
 use std::collections::binary_heap::PeekMut;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 use std::io::ErrorKind;
 use std::sync::Arc;
 use std::{
@@ -81,9 +107,37 @@ pub enum InstallError {
     #[error("cannot install noarch python package because there is no python version specified")]
     MissingPythonInfo,
 
+    /// A noarch R or Lua package could not be installed because no interpreter version was
+    /// specified.
+    #[error(
+        "cannot install noarch {0:?} package because there is no interpreter version specified"
+    )]
+    MissingInterpreterInfo(NoArchKind),
+
     /// Failed to create a python entry point for a noarch package.
     #[error("failed to create Python entry point")]
     FailedToCreatePythonEntryPoint(#[source] std::io::Error),
+
+    /// Files that are listed in the `paths.json` file of the package are missing from the package.
+    /// See [`InstallOptions::skip_missing_files`] to install such packages anyway.
+    #[error(
+        "the package does not contain the following files listed in 'paths.json': {}",
+        format_paths(.0)
+    )]
+    MissingFiles(Vec<PathBuf>),
+
+    /// One of the [`InstallHooks`] registered with the [`InstallDriver`] returned an error.
+    #[error("the {0} hook failed")]
+    HookFailed(&'static str, #[source] HookError),
+}
+
+/// Formats a list of paths as a comma separated list.
+fn format_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("'{}'", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl From<JoinError> for InstallError {
@@ -170,6 +224,15 @@ pub struct InstallOptions {
     /// [`link_package`] function will return [`InstallError::MissingPythonInfo`].
     pub python_info: Option<PythonInfo>,
 
+    /// Information about the R or Lua interpreter installed within the environment. This is only
+    /// used when installing noarch R or Lua packages whose files are installed in a library
+    /// directory that depends on the interpreter.
+    ///
+    /// If you're installing a noarch R or Lua package and do not provide information about the
+    /// matching interpreter, the [`link_package`] function will return
+    /// [`InstallError::MissingInterpreterInfo`].
+    pub interpreter_info: Option<InterpreterInfo>,
+
     /// For binaries on macOS ARM64 (Apple Silicon), binaries need to be signed with an ad-hoc
     /// certificate to properly work. This field controls wether or not to do that.
     /// Code signing is only executed when the target platform is macOS ARM64. By default,
@@ -181,6 +244,30 @@ pub struct InstallOptions {
     /// the `--sign -` argument is used to sign with an ad-hoc certificate.
     /// Ad-hoc signing does not use an identity at all, and identifies exactly one instance of code.
     pub apple_codesign_behavior: AppleCodeSignBehavior,
+
+    /// Controls how files that are copied or patched into the target directory are written, e.g.
+    /// whether their size is preallocated, whether they are flushed to disk and with which
+    /// permissions files and directories are created. By default files are neither preallocated
+    /// nor explicitly flushed and permissions are determined by the umask of the process.
+    pub file_write_options: FileWriteOptions,
+
+    /// Some broken packages list files in their `paths.json` file that are not part of the
+    /// package. By default [`link_package`] refuses to install such packages and returns
+    /// [`InstallError::MissingFiles`]. If this is set the missing files are skipped with a warning
+    /// instead.
+    pub skip_missing_files: bool,
+
+    /// The runtime on which the files of the package are linked instead of the runtime of the
+    /// [`InstallDriver`]. Use a [`crate::runtime::ThreadPoolRuntime`] to bound the number of
+    /// threads that link packages, e.g. to keep them from competing with the thread pools of an
+    /// embedding application. The number of concurrent operations is still limited by the driver.
+    pub runtime: Option<Arc<dyn Runtime>>,
+
+    /// The package that is linked, e.g. a [`rattler_conda_types::RepoDataRecord`] or an entry of
+    /// an explicit environment. If the record of the package is known, it is used instead of
+    /// reading the `index.json` file unless [`InstallOptions::index_json`] is set. The package is
+    /// also passed to the hooks of the [`InstallDriver`], see [`PackageHookContext::package`].
+    pub package: Option<Arc<dyn InstallablePackage>>,
 }
 
 /// Given an extracted package archive (`package_dir`), installs its files to the `target_dir`.
@@ -203,21 +290,80 @@ pub async fn link_package(
         .ok_or(InstallError::TargetPrefixIsNotUtf8)?
         .to_owned();
 
+    // Link the files on the runtime of the options, if any.
+    let driver_with_runtime;
+    let driver = match &options.runtime {
+        Some(runtime) => {
+            driver_with_runtime = driver.with_task_runtime(runtime.clone());
+            &driver_with_runtime
+        }
+        None => driver,
+    };
+
     // Ensure target directory exists
-    tokio::fs::create_dir_all(&target_dir)
+    let dir = target_dir.to_path_buf();
+    let permissions = options.file_write_options.permissions;
+    crate::runtime::spawn_blocking_io(move || permissions.create_dir_all(&dir))
         .await
         .map_err(InstallError::FailedToCreateTargetDirectory)?;
 
     // Reuse or read the `paths.json` and `index.json` files from the package directory
     let paths_json = read_paths_json(package_dir, driver, options.paths_json);
-    let index_json = read_index_json(package_dir, driver, options.index_json);
+    let index_json = options.index_json.or_else(|| {
+        options
+            .package
+            .as_deref()
+            .and_then(InstallablePackage::package_record)
+            .map(IndexJson::from)
+    });
+    let index_json = read_index_json(package_dir, driver, index_json);
     let (paths_json, index_json) = tokio::try_join!(paths_json, index_json)?;
 
+    // Make sure all files listed in the `paths.json` are actually part of the package. Otherwise
+    // linking would fail halfway with an obscure error.
+    let paths_json = {
+        let package_dir = package_dir.to_owned();
+        let skip_missing_files = options.skip_missing_files;
+        driver
+            .spawn_throttled(move || {
+                skip_or_report_missing_files(&package_dir, paths_json, skip_missing_files)
+            })
+            .await?
+    };
+
+    // Give the hooks of the driver the opportunity to reject the package before anything is linked.
+    if let Some(hooks) = driver.hooks() {
+        let package_dir = package_dir.to_owned();
+        let target_dir = target_dir.to_owned();
+        let index_json = index_json.clone();
+        let installable_package = options.package.clone();
+        driver
+            .spawn_throttled(move || {
+                let package = PackageHookContext {
+                    package_dir: &package_dir,
+                    target_prefix: &target_dir,
+                    index_json: &index_json,
+                    package: installable_package.as_deref(),
+                };
+                hooks
+                    .on_package_prelink(&package)
+                    .map_err(|e| InstallError::HookFailed("on_package_prelink", e))
+            })
+            .await?;
+    }
+
     // Error out if this is a noarch python package but the python information is missing.
     if index_json.noarch.is_python() && options.python_info.is_none() {
         return Err(InstallError::MissingPythonInfo);
     }
 
+    // Error out if this is a noarch R or Lua package but the matching interpreter is missing.
+    if let Some(kind @ (NoArchKind::R | NoArchKind::Lua)) = index_json.noarch.kind() {
+        if !matches!(&options.interpreter_info, Some(info) if info.kind == kind) {
+            return Err(InstallError::MissingInterpreterInfo(kind));
+        }
+    }
+
     // Parse the `link.json` file and extract entry points from it.
     let link_json = if index_json.noarch.is_python() {
         read_link_json(package_dir, driver, options.link_json).await?
@@ -247,20 +393,55 @@ pub async fn link_package(
 
     // Wrap the python info in an `Arc` so we can more easily share it with async tasks.
     let python_info = options.python_info.map(Arc::new);
+    let interpreter_info = options.interpreter_info.map(Arc::new);
+
+    // Keep track of how files are linked in the report of the driver.
+    let record_link = driver.link_recorder();
+
+    // Files in a package can be hard links to each other. When the files are hard linked from the
+    // package cache these links are preserved automatically, but when the files are copied the
+    // links have to be recreated in the prefix to avoid duplicating the files.
+    let hard_link_groups = if allow_hard_links {
+        Vec::new()
+    } else {
+        let package_dir = package_dir.to_owned();
+        let paths = paths_json.paths.clone();
+        driver
+            .spawn_throttled(move || Ok(link::find_hard_link_groups(&package_dir, &paths)))
+            .await?
+    };
+    let mut hard_link_followers = hard_link_groups
+        .into_iter()
+        .filter_map(|group| Some((*group.first()?, group[1..].to_vec())))
+        .collect::<HashMap<_, _>>();
 
     // Start linking all package files in parallel
-    let mut number_of_paths_entries = 0;
-    for entry in paths_json.paths.into_iter() {
+    let mut entries = paths_json.paths.into_iter().map(Some).collect::<Vec<_>>();
+    for index in 0..entries.len() {
+        // Files that are part of a hard link group are linked together with the first file of
+        // the group.
+        let Some(entry) = entries[index].take() else {
+            continue;
+        };
+        let followers = hard_link_followers
+            .remove(&index)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|follower| Some((follower, entries[follower].take()?)))
+            .collect::<Vec<_>>();
+
         let package_dir = package_dir.to_owned();
         let target_dir = target_dir.to_owned();
         let target_prefix = target_prefix.to_owned();
         let python_info = python_info.clone();
+        let interpreter_info = interpreter_info.clone();
 
         // Spawn a task to link the specific file. Note that these tasks are throttled by the
         // driver. So even though we might spawn thousands of tasks they might not all run
         // parallel because the driver dictates that only N tasks can run in parallel at the same
         // time.
         let tx = tx.clone();
+        let record_link = record_link.clone();
         driver.spawn_throttled_and_forget(move || {
             // Return immediately if the receiver was closed. This can happen if a previous step
             // failed. In that case we do not want to continue the installation.
@@ -268,38 +449,124 @@ pub async fn link_package(
                 return;
             }
 
-            let linked_file_result = match link_file(
-                index_json.noarch,
-                &entry,
-                &package_dir,
-                &target_dir,
-                &target_prefix,
-                allow_symbolic_links && !entry.no_link,
-                allow_hard_links && !entry.no_link,
-                platform,
-                python_info.as_deref(),
-                options.apple_codesign_behavior,
-            ) {
-                Ok(result) => Ok((
-                    number_of_paths_entries,
-                    PathsEntry {
-                        relative_path: result.relative_path,
-                        path_type: entry.path_type.into(),
-                        no_link: entry.no_link,
-                        sha256: entry.sha256,
-                        sha256_in_prefix: Some(result.sha256),
-                        size_in_bytes: Some(result.file_size),
-                    },
-                )),
-                Err(e) => Err(InstallError::FailedToLink(entry.relative_path.clone(), e)),
+            let link = |entry: &package::PathsEntry| {
+                link_file(
+                    index_json.noarch,
+                    entry,
+                    &package_dir,
+                    &target_dir,
+                    &target_prefix,
+                    allow_symbolic_links && !entry.no_link,
+                    allow_hard_links && !entry.no_link,
+                    platform,
+                    python_info.as_deref(),
+                    interpreter_info.as_deref(),
+                    options.apple_codesign_behavior,
+                    options.file_write_options,
+                )
+            };
+            let to_paths_entry = |entry: &package::PathsEntry, result: LinkedFile| {
+                record_link(result.method, result.file_size);
+                PathsEntry {
+                    relative_path: result.relative_path,
+                    path_type: entry.path_type.into(),
+                    no_link: entry.no_link,
+                    sha256: entry.sha256,
+                    sha256_in_prefix: Some(result.sha256),
+                    size_in_bytes: Some(result.file_size),
+                    prefix_placeholder: entry
+                        .prefix_placeholder
+                        .as_ref()
+                        .map(|placeholder| placeholder.placeholder.clone()),
+                    file_mode: entry
+                        .prefix_placeholder
+                        .as_ref()
+                        .map(|placeholder| placeholder.file_mode),
+                }
             };
 
-            // Send the result to the main task for further processing.
-            let _ = tx.blocking_send(linked_file_result);
-        });
+            // Empty directories that are owned by the package are created instead of linked.
+            if entry.path_type == package::PathType::Directory {
+                let result = link::create_directory(
+                    index_json.noarch,
+                    &entry,
+                    &target_dir,
+                    python_info.as_deref(),
+                    interpreter_info.as_deref(),
+                    options.file_write_options.permissions,
+                )
+                .map(|relative_path| {
+                    (
+                        index,
+                        PathsEntry {
+                            relative_path,
+                            path_type: entry.path_type.into(),
+                            no_link: entry.no_link,
+                            sha256: None,
+                            sha256_in_prefix: None,
+                            size_in_bytes: None,
+                            prefix_placeholder: None,
+                            file_mode: None,
+                        },
+                    )
+                })
+                .map_err(|e| InstallError::FailedToLink(entry.relative_path.to_path_buf(), e));
+                let _ = tx.blocking_send(result);
+                return;
+            }
 
-        number_of_paths_entries += 1;
+            let leader = match link(&entry) {
+                Ok(result) => result,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(InstallError::FailedToLink(
+                        entry.relative_path.to_path_buf(),
+                        e,
+                    )));
+                    return;
+                }
+            };
+
+            // Link the other files of the hard link group to the file that was just linked. If
+            // that fails the file is linked on its own.
+            let followers = followers
+                .into_iter()
+                .map(|(index, follower)| {
+                    let result = link::link_file_to_hard_link_group(
+                        index_json.noarch,
+                        &follower,
+                        &target_dir,
+                        python_info.as_deref(),
+                        interpreter_info.as_deref(),
+                        &leader,
+                        options.file_write_options.permissions,
+                    )
+                    .or_else(|e| {
+                        tracing::debug!(
+                            "failed to hard link {} to {}: {e}",
+                            follower.relative_path,
+                            leader.relative_path.display()
+                        );
+                        link(&follower)
+                    });
+                    (index, follower, result)
+                })
+                .collect::<Vec<_>>();
+
+            // Send the results to the main task for further processing.
+            let _ = tx.blocking_send(Ok((index, to_paths_entry(&entry, leader))));
+            for (index, follower, result) in followers {
+                let result = match result {
+                    Ok(result) => Ok((index, to_paths_entry(&follower, result))),
+                    Err(e) => Err(InstallError::FailedToLink(
+                        follower.relative_path.to_path_buf(),
+                        e,
+                    )),
+                };
+                let _ = tx.blocking_send(result);
+            }
+        });
     }
+    let mut number_of_paths_entries = entries.len();
 
     // If this package is a noarch python package we also have to create entry points.
     //
@@ -340,6 +607,7 @@ pub async fn link_package(
                         &target_prefix,
                         &entry_point,
                         &python_info,
+                        permissions,
                     ) {
                         Ok([a, b]) => {
                             let _ = tx.blocking_send(Ok((number_of_paths_entries, a)));
@@ -366,6 +634,7 @@ pub async fn link_package(
                         &target_prefix,
                         &entry_point,
                         &python_info,
+                        permissions,
                     ) {
                         Ok(a) => Ok((number_of_paths_entries, a)),
                         Err(e) => Err(InstallError::FailedToCreatePythonEntryPoint(e)),
@@ -423,9 +692,88 @@ pub async fn link_package(
         "some futures where not added to the result"
     );
 
+    // Let the hooks of the driver know that all files of the package have been linked. If the
+    // hook fails the package is not installed, so its files are removed again.
+    let paths = match driver.hooks() {
+        Some(hooks) => {
+            let package_dir = package_dir.to_owned();
+            let target_dir = target_dir.to_owned();
+            let installable_package = options.package;
+            driver
+                .spawn_throttled(move || {
+                    let package = PackageHookContext {
+                        package_dir: &package_dir,
+                        target_prefix: &target_dir,
+                        index_json: &index_json,
+                        package: installable_package.as_deref(),
+                    };
+                    if let Err(e) = hooks.on_package_postlink(&package, &paths) {
+                        let linked = paths.iter().map(|entry| {
+                            let is_directory =
+                                entry.path_type == prefix_record::PathType::Directory;
+                            (entry.relative_path.as_path(), is_directory)
+                        });
+                        if let Err(e) = unlink::remove_files(&target_dir, linked) {
+                            tracing::error!(
+                                "failed to remove the files of {}: {e}",
+                                package_dir.display()
+                            );
+                        }
+                        return Err(InstallError::HookFailed("on_package_postlink", e));
+                    }
+                    Ok(paths)
+                })
+                .await?
+        }
+        None => paths,
+    };
+
+    driver.record_linked_package();
+
     Ok(paths)
 }
 
+/// Returns an error that lists all files of `paths_json` that do not exist in `package_dir`. If
+/// `skip_missing_files` is set the missing files are removed from `paths_json` instead.
+fn skip_or_report_missing_files(
+    package_dir: &Path,
+    mut paths_json: PathsJson,
+    skip_missing_files: bool,
+) -> Result<PathsJson, InstallError> {
+    // Directories are created in the target directory, they don't have to exist in the package.
+    let missing = paths_json
+        .paths
+        .iter()
+        .filter(|entry| entry.path_type != package::PathType::Directory)
+        .filter(|entry| {
+            matches!(
+                std::fs::symlink_metadata(package_dir.join(&entry.relative_path)),
+                Err(e) if e.kind() == ErrorKind::NotFound
+            )
+        })
+        .map(|entry| entry.relative_path.to_path_buf())
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        return Ok(paths_json);
+    }
+    if !skip_missing_files {
+        return Err(InstallError::MissingFiles(missing));
+    }
+
+    for path in &missing {
+        tracing::warn!(
+            "skipping '{}' because it is listed in 'paths.json' but missing from {}",
+            path.display(),
+            package_dir.display()
+        );
+    }
+    paths_json
+        .paths
+        .retain(|entry| !missing.iter().any(|path| entry.relative_path == *path));
+    Ok(paths_json)
+}
+
 /// A helper function that reads the `paths.json` file from a package unless it has already been
 /// provided, in which case it is returned immediately.
 async fn read_paths_json(
@@ -531,27 +879,31 @@ impl<T> Ord for OrderWrapper<T> {
 async fn can_create_symlinks(target_dir: &Path) -> bool {
     let uuid = uuid::Uuid::new_v4();
     let symlink_path = target_dir.join(format!("symtest_{}", uuid));
-    #[cfg(windows)]
-    let result = tokio::fs::symlink_file("./", &symlink_path).await;
-    #[cfg(unix)]
-    let result = tokio::fs::symlink("./", &symlink_path).await;
-    match result {
-        Ok(_) => {
-            if let Err(e) = tokio::fs::remove_file(&symlink_path).await {
-                tracing::warn!(
-                    "failed to delete temporary file '{}': {e}",
-                    symlink_path.display()
-                )
+    crate::runtime::spawn_blocking(move || {
+        #[cfg(windows)]
+        let result = std::os::windows::fs::symlink_file("./", &symlink_path);
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink("./", &symlink_path);
+        match result {
+            Ok(_) => {
+                if let Err(e) = std::fs::remove_file(&symlink_path) {
+                    tracing::warn!(
+                        "failed to delete temporary file '{}': {e}",
+                        symlink_path.display()
+                    )
+                }
+                true
+            }
+            Err(e) => {
+                tracing::debug!(
+                    "failed to create symlink in target directory: {e}. Disabling use of symlinks."
+                );
+                false
             }
-            true
-        }
-        Err(e) => {
-            tracing::debug!(
-                "failed to create symlink in target directory: {e}. Disabling use of symlinks."
-            );
-            false
         }
-    }
+    })
+    .await
+    .unwrap_or(false)
 }
 
 /// Returns true if it is possible to create hard links from the target directory to the package
@@ -562,12 +914,16 @@ async fn can_create_hardlinks(
     package_dir: &Path,
 ) -> bool {
     let dst_link_path = target_dir.join(format!("sentinel_{}", uuid::Uuid::new_v4()));
-    let src_link_path = match paths_json.paths.first() {
+    let src_link_path = match paths_json
+        .paths
+        .iter()
+        .find(|entry| entry.path_type == package::PathType::HardLink)
+    {
         Some(path) => package_dir.join(&path.relative_path),
         None => return false,
     };
-    tokio::task::spawn_blocking(
-        move || match std::fs::hard_link(&src_link_path, &dst_link_path) {
+    crate::runtime::spawn_blocking(move || {
+        match std::fs::hard_link(&src_link_path, &dst_link_path) {
             Ok(_) => {
                 if let Err(e) = std::fs::remove_file(&dst_link_path) {
                     tracing::warn!(
@@ -583,8 +939,8 @@ async fn can_create_hardlinks(
             );
                 false
             }
-        },
-    )
+        }
+    })
     .await
     .unwrap_or(false)
 }
@@ -620,6 +976,7 @@ mod test {
         let env = ExplicitEnvironmentSpec::from_path(&explicit_env_path).unwrap();
 
         assert_eq!(env.platform, Some(current_platform), "the platform for which the explicit lock file was created does not match the current platform");
+        env.check_platform(current_platform).unwrap();
 
         test_install_python(
             env.packages.iter().map(|p| &p.url),
@@ -742,4 +1099,369 @@ mod test {
 
         insta::assert_yaml_snapshot!(paths);
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_preserve_hard_link_groups() {
+        use crate::test_utils::PackageBuilder;
+        use rattler_conda_types::package::ArchiveType;
+        use std::os::unix::fs::MetadataExt;
+
+        let archive_dir = tempdir().unwrap();
+        let package_dir = tempdir().unwrap();
+        let environment_dir = tempdir().unwrap();
+
+        // Create a package in which two of the files are hard links to each other.
+        let archive = PackageBuilder::new("hard-links", "1.0")
+            .with_file("bin/a", "shared contents")
+            .with_file("bin/b", "shared contents")
+            .with_file("bin/c", "shared contents")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+        std::fs::remove_file(package_dir.path().join("bin/b")).unwrap();
+        std::fs::hard_link(
+            package_dir.path().join("bin/a"),
+            package_dir.path().join("bin/b"),
+        )
+        .unwrap();
+
+        // Copy the files into the environment.
+        let paths = link_package(
+            package_dir.path(),
+            environment_dir.path(),
+            &InstallDriver::default(),
+            InstallOptions {
+                allow_hard_links: Some(false),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            paths
+                .iter()
+                .map(|entry| entry.relative_path.clone())
+                .collect::<Vec<_>>(),
+            ["bin/a", "bin/b", "bin/c"]
+                .into_iter()
+                .map(std::path::PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+
+        // The hard link between the files must be preserved, but the files must not be linked to
+        // the package cache.
+        let inode = |path: &str| {
+            std::fs::metadata(environment_dir.path().join(path))
+                .unwrap()
+                .ino()
+        };
+        assert_eq!(inode("bin/a"), inode("bin/b"));
+        assert_ne!(inode("bin/a"), inode("bin/c"));
+        assert_ne!(
+            inode("bin/a"),
+            std::fs::metadata(package_dir.path().join("bin/a"))
+                .unwrap()
+                .ino()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_empty_directories() {
+        use crate::test_utils::PackageBuilder;
+        use rattler_conda_types::{
+            package::{ArchiveType, PackageFile, PathType, PathsEntry, PathsJson},
+            prefix_record, PrefixRelativePath,
+        };
+
+        let archive_dir = tempdir().unwrap();
+        let package_dir = tempdir().unwrap();
+        let environment_dir = tempdir().unwrap();
+
+        // Create a package that owns an empty directory next to a regular file.
+        let archive = PackageBuilder::new("empty-dir", "1.0")
+            .with_file("bin/a", "contents")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+        std::fs::create_dir_all(package_dir.path().join("var/empty")).unwrap();
+        let mut paths_json = PathsJson::from_package_directory(package_dir.path()).unwrap();
+        paths_json.paths.push(PathsEntry {
+            relative_path: PrefixRelativePath::new("var/empty").unwrap(),
+            no_link: false,
+            path_type: PathType::Directory,
+            prefix_placeholder: None,
+            sha256: None,
+            size_in_bytes: None,
+        });
+
+        let paths = link_package(
+            package_dir.path(),
+            environment_dir.path(),
+            &InstallDriver::default(),
+            InstallOptions {
+                paths_json: Some(paths_json),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(environment_dir.path().join("var/empty").is_dir());
+        assert!(environment_dir.path().join("bin/a").is_file());
+        let directory = paths
+            .iter()
+            .find(|entry| entry.relative_path == std::path::Path::new("var/empty"))
+            .unwrap();
+        assert_eq!(directory.path_type, prefix_record::PathType::Directory);
+        assert_eq!(directory.sha256_in_prefix, None);
+    }
+
+    #[tokio::test]
+    async fn test_missing_files() {
+        use crate::{install::InstallError, test_utils::PackageBuilder};
+        use assert_matches::assert_matches;
+        use rattler_conda_types::package::ArchiveType;
+        use std::path::PathBuf;
+
+        let archive_dir = tempdir().unwrap();
+        let package_dir = tempdir().unwrap();
+
+        // Create a package of which one of the files listed in `paths.json` is missing.
+        let archive = PackageBuilder::new("missing-files", "1.0")
+            .with_file("bin/a", "a")
+            .with_file("bin/b", "b")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+        std::fs::remove_file(package_dir.path().join("bin/b")).unwrap();
+
+        let environment_dir = tempdir().unwrap();
+        let err = link_package(
+            package_dir.path(),
+            environment_dir.path(),
+            &InstallDriver::default(),
+            InstallOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_matches!(
+            &err,
+            InstallError::MissingFiles(paths) if paths == &[PathBuf::from("bin/b")]
+        );
+        assert!(!environment_dir.path().join("bin/a").exists());
+
+        // The missing files are skipped if requested.
+        let paths = link_package(
+            package_dir.path(),
+            environment_dir.path(),
+            &InstallDriver::default(),
+            InstallOptions {
+                skip_missing_files: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(paths.len(), 1);
+        assert!(environment_dir.path().join("bin/a").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        use crate::{
+            install::{HookError, InstallError, InstallHooks, InstallReport, PackageHookContext},
+            test_utils::PackageBuilder,
+        };
+        use assert_matches::assert_matches;
+        use rattler_conda_types::{
+            package::ArchiveType, prefix_record::PathsEntry, InstallablePackage, PackageRecord,
+            RepoDataRecord,
+        };
+        use std::{
+            path::Path,
+            sync::{Arc, Mutex},
+        };
+
+        /// Records the hooks that are called, rejects packages with the name `rejected` and fails
+        /// after linking packages with the name `broken`.
+        #[derive(Default, Clone)]
+        struct RecordingHooks(Arc<Mutex<Vec<String>>>);
+
+        impl InstallHooks for RecordingHooks {
+            fn on_package_prelink(
+                &self,
+                package: &PackageHookContext<'_>,
+            ) -> Result<(), HookError> {
+                let name = package.index_json.name.as_normalized();
+                let url = package
+                    .package
+                    .map_or(String::from("unknown"), |package| package.url().to_string());
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("prelink {name} from {url}"));
+                if name == "rejected" {
+                    return Err("the package is not allowed".into());
+                }
+                Ok(())
+            }
+
+            fn on_package_postlink(
+                &self,
+                package: &PackageHookContext<'_>,
+                paths: &[PathsEntry],
+            ) -> Result<(), HookError> {
+                let name = package.index_json.name.as_normalized();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("postlink {name} {}", paths.len()));
+                if name == "broken" {
+                    assert!(package.target_prefix.join("bin/broken").is_file());
+                    return Err("the package is broken".into());
+                }
+                Ok(())
+            }
+
+            fn on_transaction_complete(
+                &self,
+                _target_prefix: &Path,
+                report: &InstallReport,
+            ) -> Result<(), HookError> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("complete {}", report.linked_packages));
+                Ok(())
+            }
+        }
+
+        let archive_dir = tempdir().unwrap();
+        let environment_dir = tempdir().unwrap();
+        let hooks = RecordingHooks::default();
+        let driver = InstallDriver::default().with_hooks(hooks.clone());
+
+        for name in ["accepted", "rejected", "broken"] {
+            let package_dir = tempdir().unwrap();
+            let package = PackageBuilder::new(name, "1.0").with_file(format!("bin/{name}"), name);
+            let archive = package.write(archive_dir.path(), ArchiveType::Conda);
+            rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+
+            // Only the record of the accepted package is passed to the hooks.
+            let repodata_record = (name == "accepted").then(|| RepoDataRecord {
+                package_record: PackageRecord::from_index_json(
+                    package.index_json().clone(),
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap(),
+                file_name: package.file_name(ArchiveType::Conda),
+                url: "https://conda.anaconda.org/conda-forge/noarch/accepted-1.0-0.conda"
+                    .parse()
+                    .unwrap(),
+                channel: String::from("conda-forge"),
+            });
+            let result = link_package(
+                package_dir.path(),
+                environment_dir.path(),
+                &driver,
+                InstallOptions {
+                    package: repodata_record
+                        .map(|record| Arc::new(record) as Arc<dyn InstallablePackage>),
+                    ..InstallOptions::default()
+                },
+            )
+            .await;
+            match name {
+                "accepted" => {
+                    result.unwrap();
+                }
+                "rejected" => assert_matches!(
+                    result,
+                    Err(InstallError::HookFailed("on_package_prelink", _))
+                ),
+                _ => assert_matches!(
+                    result,
+                    Err(InstallError::HookFailed("on_package_postlink", _))
+                ),
+            }
+        }
+        driver
+            .complete_transaction(environment_dir.path())
+            .await
+            .unwrap();
+
+        assert!(environment_dir.path().join("bin/accepted").is_file());
+        assert!(!environment_dir.path().join("bin/rejected").exists());
+        assert!(!environment_dir.path().join("bin/broken").exists());
+        assert_eq!(
+            hooks.0.lock().unwrap().as_slice(),
+            [
+                "prelink accepted from https://conda.anaconda.org/conda-forge/noarch/accepted-1.0-0.conda",
+                "postlink accepted 1",
+                "prelink rejected from unknown",
+                "prelink broken from unknown",
+                "postlink broken 1",
+                "complete 1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link_installable_packages() {
+        use crate::test_utils::PackageBuilder;
+        use rattler_conda_types::{
+            package::ArchiveType, ExplicitEnvironmentEntry, InstallablePackage, PackageRecord,
+            RepoDataRecord,
+        };
+        use std::sync::Arc;
+
+        let archive_dir = tempdir().unwrap();
+        let package_dir = tempdir().unwrap();
+        let package = PackageBuilder::new("foo", "1.0").with_file("bin/foo", "foo");
+        let archive = package.write(archive_dir.path(), ArchiveType::Conda);
+        rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+
+        let url: Url = format!(
+            "https://conda.anaconda.org/conda-forge/noarch/{}",
+            package.file_name(ArchiveType::Conda)
+        )
+        .parse()
+        .unwrap();
+        let entry = ExplicitEnvironmentEntry::from(url.clone());
+        let record = RepoDataRecord {
+            package_record: PackageRecord::from_index_json(
+                package.index_json().clone(),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+            file_name: package.file_name(ArchiveType::Conda),
+            url,
+            channel: String::from("conda-forge"),
+        };
+
+        // Both kinds of packages are linked through the same path. The `index.json` is only read
+        // for the entry of the explicit environment, the record already provides its contents.
+        let packages: [Arc<dyn InstallablePackage>; 2] = [Arc::new(entry), Arc::new(record)];
+        for (idx, package) in packages.into_iter().enumerate() {
+            if package.package_record().is_some() {
+                std::fs::remove_file(package_dir.path().join("info/index.json")).unwrap();
+            }
+            let environment_dir = tempdir().unwrap();
+            let paths = link_package(
+                package_dir.path(),
+                environment_dir.path(),
+                &InstallDriver::default(),
+                InstallOptions {
+                    package: Some(package),
+                    ..InstallOptions::default()
+                },
+            )
+            .await
+            .unwrap_or_else(|e| panic!("failed to link package {idx}: {e}"));
+            assert_eq!(paths.len(), 1);
+            assert!(environment_dir.path().join("bin/foo").is_file());
+        }
+    }
 }
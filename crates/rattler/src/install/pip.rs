@@ -0,0 +1,160 @@
+//! Functions to find Python distributions that were installed into a prefix by another installer
+//! than conda, like pip.
+
+use super::PythonInfo;
+use rattler_conda_types::PrefixRecord;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// A Python distribution found in the `site-packages` directory of a prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonDistribution {
+    /// The name of the distribution as specified in its metadata.
+    pub name: String,
+
+    /// The version of the distribution as specified in its metadata.
+    pub version: String,
+
+    /// The tool that installed the distribution (e.g. `pip` or `conda`) if known.
+    pub installer: Option<String>,
+
+    /// The path of the `.dist-info` directory relative to the prefix.
+    pub dist_info: PathBuf,
+}
+
+impl PythonDistribution {
+    /// Returns the requirement that pins this distribution to its exact version (e.g.
+    /// `requests==2.31.0`).
+    pub fn requirement(&self) -> String {
+        format!("{}=={}", self.name, self.version)
+    }
+}
+
+/// Finds all the Python distributions that have a `.dist-info` directory in the `site-packages`
+/// directory of the prefix. This includes distributions that were installed by conda packages.
+pub fn find_python_distributions(
+    prefix: &Path,
+    python_info: &PythonInfo,
+) -> Result<Vec<PythonDistribution>, std::io::Error> {
+    let entries = match std::fs::read_dir(prefix.join(&python_info.site_packages_path)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut distributions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if !file_name.to_string_lossy().ends_with(".dist-info") || !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        // The name and version are stored in the headers of the METADATA file.
+        let path = entry.path();
+        let metadata = match std::fs::read_to_string(path.join("METADATA")) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let header = |key: &str| {
+            metadata
+                .lines()
+                .take_while(|line| !line.is_empty())
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+                .map(|value| value.trim().to_owned())
+        };
+        let (Some(name), Some(version)) = (header("Name"), header("Version")) else {
+            continue;
+        };
+
+        let installer = std::fs::read_to_string(path.join("INSTALLER"))
+            .ok()
+            .map(|installer| installer.trim().to_owned())
+            .filter(|installer| !installer.is_empty());
+
+        distributions.push(PythonDistribution {
+            name,
+            version,
+            installer,
+            dist_info: python_info.site_packages_path.join(file_name),
+        });
+    }
+
+    distributions.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(distributions)
+}
+
+/// Finds the Python distributions in the prefix that were not installed by one of the conda
+/// packages in `prefix_records`. These are the distributions that were installed with pip (or
+/// another Python installer).
+pub fn find_pip_distributions(
+    prefix: &Path,
+    python_info: &PythonInfo,
+    prefix_records: &[PrefixRecord],
+) -> Result<Vec<PythonDistribution>, std::io::Error> {
+    let conda_dist_infos = prefix_records
+        .iter()
+        .flat_map(|record| record.files.iter())
+        .filter_map(|file| {
+            file.ancestors()
+                .find(|dir| dir.to_string_lossy().ends_with(".dist-info"))
+        })
+        .collect::<HashSet<_>>();
+
+    Ok(find_python_distributions(prefix, python_info)?
+        .into_iter()
+        .filter(|dist| !conda_dist_infos.contains(dist.dist_info.as_path()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_pip_distributions, find_python_distributions};
+    use crate::install::PythonInfo;
+    use rattler_conda_types::{Platform, PrefixRecord, Version};
+    use std::path::Path;
+    use std::str::FromStr;
+
+    fn write_dist_info(prefix: &Path, python_info: &PythonInfo, name: &str, installer: &str) {
+        let dist_info = prefix
+            .join(&python_info.site_packages_path)
+            .join(format!("{name}-1.0.dist-info"));
+        std::fs::create_dir_all(&dist_info).unwrap();
+        std::fs::write(
+            dist_info.join("METADATA"),
+            format!("Metadata-Version: 2.1\nName: {name}\nVersion: 1.0\n\nVersion: 2.0\n"),
+        )
+        .unwrap();
+        std::fs::write(dist_info.join("INSTALLER"), format!("{installer}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_find_pip_distributions() {
+        let prefix = tempfile::tempdir().unwrap();
+        let python_info =
+            PythonInfo::from_version(&Version::from_str("3.11").unwrap(), Platform::Linux64)
+                .unwrap();
+        write_dist_info(prefix.path(), &python_info, "requests", "conda");
+        write_dist_info(prefix.path(), &python_info, "rich", "pip");
+
+        let distributions = find_python_distributions(prefix.path(), &python_info).unwrap();
+        assert_eq!(distributions.len(), 2);
+        assert_eq!(distributions[0].requirement(), "requests==1.0");
+        assert_eq!(distributions[0].installer.as_deref(), Some("conda"));
+
+        // Pretend the requests distribution was installed by a conda package.
+        let mut record: PrefixRecord = serde_json::from_str(
+            r#"{"name": "requests", "version": "1.0", "build": "0", "build_number": 0,
+                "fn": "requests-1.0-0.tar.bz2", "url": "https://example.com/requests-1.0-0.tar.bz2",
+                "channel": "https://example.com"}"#,
+        )
+        .unwrap();
+        record.files = vec![distributions[0].dist_info.join("METADATA")];
+
+        let pip_distributions =
+            find_pip_distributions(prefix.path(), &python_info, &[record]).unwrap();
+        assert_eq!(pip_distributions, vec![distributions[1].clone()]);
+    }
+}
@@ -1,10 +1,11 @@
 use rattler_conda_types::{Platform, Version};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
 /// Information required for linking no-arch python packages. The struct contains information about
 /// a specific Python version that is installed in an environment.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PythonInfo {
     /// The major and minor version
     pub short_version: (u64, u64),
@@ -0,0 +1,368 @@
+//! Moves an existing environment to another directory.
+//!
+//! When a package is installed, the placeholder prefix in its text and binary files is replaced
+//! with the path of the prefix. The `conda-meta` records remember which files were patched this
+//! way (see [`PathsEntry::prefix_placeholder`]), so after the files of an environment have been
+//! copied or unpacked somewhere else, [`relocate_prefix`] can replace the old path of the prefix
+//! with the new one. This makes it possible to build an environment once and ship it as an
+//! archive, similar to `conda-pack`.
+
+use super::{
+    apple_codesign::{codesign, AppleCodeSignBehavior},
+    link::{copy_and_replace_placholders, LinkFileError},
+};
+use rattler_conda_types::{
+    package::FileMode,
+    prefix_record::{PathType, PathsEntry},
+    Platform, PrefixRecord,
+};
+use rattler_digest::Sha256;
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// An error that can occur when relocating a prefix with [`relocate_prefix`].
+#[derive(Debug, thiserror::Error)]
+pub enum RelocateError {
+    /// Failed to read the `conda-meta` records of the prefix.
+    #[error("failed to read the installed packages")]
+    FailedToReadPrefix(#[source] std::io::Error),
+
+    /// Failed to rewrite a file of the prefix.
+    #[error("failed to rewrite '{}'", .0.display())]
+    FailedToRewriteFile(PathBuf, #[source] std::io::Error),
+
+    /// The new prefix does not fit in a binary file because it is longer than the old prefix.
+    #[error(
+        "cannot relocate binary file '{}' because the new prefix is longer than the old prefix",
+        .0.display()
+    )]
+    PrefixTooLong(PathBuf),
+
+    /// Failed to sign a rewritten binary.
+    #[error("failed to sign '{}'", .0.display())]
+    FailedToSign(PathBuf, #[source] LinkFileError),
+
+    /// Failed to write the updated `conda-meta` record of a package.
+    #[error("failed to write the record of {0}")]
+    FailedToWriteRecord(String, #[source] std::io::Error),
+}
+
+/// Replaces the `old_prefix` with `new_prefix` in all files of the environment at `prefix` that
+/// had their placeholder prefix replaced when they were installed. Returns the paths (relative to
+/// the prefix) of the files that were rewritten.
+///
+/// `prefix` is the directory that currently contains the environment. This is usually equal to
+/// `new_prefix` but it can differ if the environment is prepared in one directory (e.g. to create
+/// an archive of it) and used from another directory later.
+///
+/// The recorded hashes and sizes of the rewritten files are updated in the `conda-meta` records.
+/// Binary files can only be relocated to a prefix that is at most as long as the old prefix,
+/// otherwise [`RelocateError::PrefixTooLong`] is returned. This is checked for all files before
+/// any file is modified.
+pub fn relocate_prefix(
+    prefix: &Path,
+    old_prefix: &str,
+    new_prefix: &str,
+    platform: Platform,
+    apple_codesign_behavior: AppleCodeSignBehavior,
+) -> Result<Vec<PathBuf>, RelocateError> {
+    // The installer writes prefixes with forward-slashes on windows, see `link_file`.
+    let (old_prefix, new_prefix) = if platform.is_windows() {
+        (
+            Cow::Owned(old_prefix.replace('\\', "/")),
+            Cow::Owned(new_prefix.replace('\\', "/")),
+        )
+    } else {
+        (Cow::Borrowed(old_prefix), Cow::Borrowed(new_prefix))
+    };
+
+    let mut records =
+        PrefixRecord::collect_from_prefix(prefix).map_err(RelocateError::FailedToReadPrefix)?;
+
+    // Make sure the binary files can be relocated before anything is modified.
+    if new_prefix.len() > old_prefix.len() {
+        if let Some(entry) = records
+            .iter()
+            .flat_map(|record| &record.paths_data.paths)
+            .find(|entry| is_relocatable(entry) && entry.file_mode == Some(FileMode::Binary))
+        {
+            return Err(RelocateError::PrefixTooLong(entry.relative_path.clone()));
+        }
+    }
+
+    let mut relocated = Vec::new();
+    for record in &mut records {
+        let mut changed = false;
+        for entry in &mut record.paths_data.paths {
+            let Some(file_mode) = relocatable_mode(entry) else {
+                continue;
+            };
+            if relocate_file(
+                prefix,
+                entry,
+                file_mode,
+                &old_prefix,
+                &new_prefix,
+                platform,
+                apple_codesign_behavior,
+            )? {
+                relocated.push(entry.relative_path.clone());
+                changed = true;
+            }
+        }
+
+        if changed {
+            record
+                .write_to_prefix(prefix)
+                .map_err(|e| RelocateError::FailedToWriteRecord(record.file_name(), e))?;
+        }
+    }
+
+    Ok(relocated)
+}
+
+/// Returns true if the prefix was written into the file of the entry when it was installed.
+///
+/// Soft links are skipped, rewriting one would replace the link with a modified copy of its
+/// target.
+fn is_relocatable(entry: &PathsEntry) -> bool {
+    entry.prefix_placeholder.is_some() && entry.path_type != PathType::SoftLink
+}
+
+/// Returns how the prefix is embedded in the file of the entry, or `None` if the file does not
+/// contain the prefix. Entries that have a placeholder but no file mode are treated as text
+/// files, like conda does.
+fn relocatable_mode(entry: &PathsEntry) -> Option<FileMode> {
+    if !is_relocatable(entry) {
+        return None;
+    }
+    Some(entry.file_mode.unwrap_or_else(|| {
+        tracing::warn!(
+            "'{}' has a prefix placeholder but no file mode, assuming it is a text file",
+            entry.relative_path.display()
+        );
+        FileMode::Text
+    }))
+}
+
+/// Replaces the `old_prefix` in a single file. Returns true if the file contained the prefix and
+/// was rewritten.
+fn relocate_file(
+    prefix: &Path,
+    entry: &mut PathsEntry,
+    file_mode: FileMode,
+    old_prefix: &str,
+    new_prefix: &str,
+    platform: Platform,
+    apple_codesign_behavior: AppleCodeSignBehavior,
+) -> Result<bool, RelocateError> {
+    let relative_path = entry.relative_path.clone();
+    let path = prefix.join(&relative_path);
+    let rewrite_error = |e| RelocateError::FailedToRewriteFile(relative_path.clone(), e);
+
+    let contents = std::fs::read(&path).map_err(rewrite_error)?;
+    if memchr::memmem::find(&contents, old_prefix.as_bytes()).is_none() {
+        return Ok(false);
+    }
+
+    let mut relocated = Vec::with_capacity(contents.len());
+    copy_and_replace_placholders(&contents, &mut relocated, old_prefix, new_prefix, file_mode)
+        .map_err(rewrite_error)?;
+
+    // Write the new contents next to the file and move it into place. This makes sure the file is
+    // never left half-written and breaks any hard link to the original file.
+    let permissions = std::fs::metadata(&path)
+        .map_err(rewrite_error)?
+        .permissions();
+    let parent = path.parent().unwrap_or(prefix);
+    let mut file = tempfile::NamedTempFile::new_in(parent).map_err(rewrite_error)?;
+    file.write_all(&relocated).map_err(rewrite_error)?;
+    std::fs::set_permissions(file.path(), permissions).map_err(rewrite_error)?;
+    file.persist(&path).map_err(|e| rewrite_error(e.error))?;
+
+    let mut sha256 = rattler_digest::compute_bytes_digest::<Sha256>(&relocated);
+    if platform == Platform::OsxArm64
+        && file_mode == FileMode::Binary
+        && apple_codesign_behavior != AppleCodeSignBehavior::DoNothing
+        && is_executable(&path)
+    {
+        match codesign(&path) {
+            Ok(()) => {
+                sha256 =
+                    rattler_digest::compute_file_digest::<Sha256>(&path).map_err(rewrite_error)?;
+            }
+            Err(e) if apple_codesign_behavior == AppleCodeSignBehavior::Fail => {
+                return Err(RelocateError::FailedToSign(relative_path, e));
+            }
+            Err(_) => {}
+        }
+    }
+
+    entry.sha256_in_prefix = Some(sha256);
+    entry.size_in_bytes = Some(std::fs::metadata(&path).map_err(rewrite_error)?.len());
+    Ok(true)
+}
+
+/// Returns true if the file at the given path is executable.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{relocate_prefix, RelocateError};
+    use crate::install::AppleCodeSignBehavior;
+    use rattler_conda_types::{
+        package::FileMode,
+        prefix_record::{PathType, PathsEntry},
+        Platform, PrefixRecord, RepoDataRecord,
+    };
+    use std::path::{Path, PathBuf};
+
+    fn entry(path: &str, file_mode: Option<FileMode>) -> PathsEntry {
+        PathsEntry {
+            relative_path: PathBuf::from(path),
+            path_type: PathType::HardLink,
+            no_link: false,
+            sha256: None,
+            sha256_in_prefix: None,
+            size_in_bytes: None,
+            prefix_placeholder: file_mode.map(|_| String::from("/opt/placeholder")),
+            file_mode,
+        }
+    }
+
+    fn write_record(prefix: &Path, paths: Vec<PathsEntry>) {
+        let repodata_record: RepoDataRecord = serde_json::from_value(serde_json::json!({
+            "name": "foo",
+            "version": "1.0",
+            "build": "0",
+            "build_number": 0,
+            "depends": [],
+            "subdir": "linux-64",
+            "fn": "foo-1.0-0.tar.bz2",
+            "url": "https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-0.tar.bz2",
+            "channel": "https://conda.anaconda.org/conda-forge/",
+        }))
+        .unwrap();
+        PrefixRecord::from_repodata_record(repodata_record, None, None, paths, None, None)
+            .write_to_prefix(prefix)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_relocate_prefix() {
+        let prefix = tempfile::tempdir().unwrap();
+        let old_prefix = "/home/user/envs/old-environment";
+        let new_prefix = "/opt/envs/new";
+
+        std::fs::write(
+            prefix.path().join("script"),
+            format!("#!{old_prefix}/bin/python\nprint('{old_prefix}')\n"),
+        )
+        .unwrap();
+        std::fs::write(
+            prefix.path().join("binary"),
+            format!("\x7fELF\0{old_prefix}/lib\0end"),
+        )
+        .unwrap();
+        std::fs::write(prefix.path().join("data"), old_prefix).unwrap();
+        std::fs::write(prefix.path().join("legacy"), old_prefix).unwrap();
+        let mut legacy = entry("legacy", Some(FileMode::Text));
+        legacy.file_mode = None;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("script", prefix.path().join("link")).unwrap();
+        let mut link = entry("link", Some(FileMode::Text));
+        link.path_type = PathType::SoftLink;
+        write_record(
+            prefix.path(),
+            vec![
+                entry("script", Some(FileMode::Text)),
+                entry("binary", Some(FileMode::Binary)),
+                entry("data", None),
+                legacy,
+                link,
+            ],
+        );
+
+        let relocated = relocate_prefix(
+            prefix.path(),
+            old_prefix,
+            new_prefix,
+            Platform::Linux64,
+            AppleCodeSignBehavior::DoNothing,
+        )
+        .unwrap();
+        assert_eq!(
+            relocated,
+            vec![
+                PathBuf::from("script"),
+                PathBuf::from("binary"),
+                PathBuf::from("legacy")
+            ]
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(prefix.path().join("script")).unwrap(),
+            format!("#!{new_prefix}/bin/python\nprint('{new_prefix}')\n")
+        );
+        let binary = std::fs::read(prefix.path().join("binary")).unwrap();
+        let padding = "\0".repeat(old_prefix.len() - new_prefix.len());
+        assert_eq!(
+            binary,
+            format!("\x7fELF\0{new_prefix}/lib{padding}\0end").into_bytes()
+        );
+
+        // Files without a placeholder are left alone.
+        assert_eq!(
+            std::fs::read_to_string(prefix.path().join("data")).unwrap(),
+            old_prefix
+        );
+
+        // Files without a file mode are relocated as text files.
+        assert_eq!(
+            std::fs::read_to_string(prefix.path().join("legacy")).unwrap(),
+            new_prefix
+        );
+
+        // Soft links stay links.
+        #[cfg(unix)]
+        assert!(std::fs::symlink_metadata(prefix.path().join("link"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        // The records contain the hashes and sizes of the rewritten files.
+        let records = PrefixRecord::collect_from_prefix(prefix.path()).unwrap();
+        let binary_entry = &records[0].paths_data.paths[1];
+        assert_eq!(binary_entry.size_in_bytes, Some(binary.len() as u64));
+        assert_eq!(
+            binary_entry.sha256_in_prefix,
+            Some(rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(&binary))
+        );
+
+        // A binary file cannot hold a longer prefix.
+        assert!(matches!(
+            relocate_prefix(
+                prefix.path(),
+                new_prefix,
+                old_prefix,
+                Platform::Linux64,
+                AppleCodeSignBehavior::DoNothing,
+            ),
+            Err(RelocateError::PrefixTooLong(path)) if path == Path::new("binary")
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+//! Determines which packages to remove from an environment when the user asks to remove specific
+//! packages. Packages that depend on a removed package are broken once it is removed so they are
+//! removed as well, unless they were explicitly requested by the user. Dependencies that are no
+//! longer required by any of the remaining packages can optionally be pruned.
+
+use super::{environment_platform, Transaction, TransactionError};
+use rattler_conda_types::{MatchSpec, PackageName, Platform, PrefixRecord, RepoDataRecord};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+
+/// Options that control which packages are removed by [`remove`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOptions {
+    /// Also remove the dependencies of the removed packages that are no longer required by any of
+    /// the remaining packages and that were not explicitly requested.
+    pub prune: bool,
+
+    /// Only remove the given packages, even if that breaks packages that depend on them.
+    pub force: bool,
+}
+
+/// An error that can occur when determining the packages to remove.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoveError {
+    /// A package that should be removed is not installed.
+    #[error("package '{}' is not installed", .0.as_source())]
+    NotInstalled(PackageName),
+
+    /// A package that should be removed is required by a package that was explicitly requested.
+    #[error("cannot remove '{}' because it is required by '{}'", .package.as_source(), .required_by.as_source())]
+    RequiredBy {
+        /// The package that should be removed
+        package: PackageName,
+
+        /// The explicitly requested package that (transitively) depends on the package
+        required_by: PackageName,
+    },
+
+    /// Failed to read the packages that are installed in the prefix.
+    #[error("failed to read the installed packages")]
+    FailedToReadPrefix(#[source] std::io::Error),
+
+    /// Failed to construct the transaction.
+    #[error(transparent)]
+    TransactionError(#[from] TransactionError),
+}
+
+/// Determines the operations required to remove the packages with the given `names` from the
+/// environment at `prefix`.
+///
+/// The packages that depend on the removed packages are removed as well. If one of them was
+/// explicitly requested (it has a [`PrefixRecord::requested_spec`]) an error is returned instead,
+/// unless [`RemoveOptions::force`] is set, in which case only the given packages are removed.
+///
+/// The platform of the transaction is the platform of the installed packages (see
+/// [`environment_platform`]), which is not necessarily the current platform.
+pub fn remove(
+    prefix: &Path,
+    names: &[PackageName],
+    options: RemoveOptions,
+) -> Result<Transaction<PrefixRecord, RepoDataRecord>, RemoveError> {
+    let installed =
+        PrefixRecord::collect_from_prefix(prefix).map_err(RemoveError::FailedToReadPrefix)?;
+    plan_remove(installed, names, options)
+}
+
+/// Same as [`remove`] but operates on the given installed packages instead of reading them from a
+/// prefix.
+pub fn plan_remove(
+    installed: Vec<PrefixRecord>,
+    names: &[PackageName],
+    options: RemoveOptions,
+) -> Result<Transaction<PrefixRecord, RepoDataRecord>, RemoveError> {
+    // Removing packages never changes the platform of an environment. An environment that only
+    // contains noarch packages is compatible with any platform.
+    let platform = environment_platform(&installed).unwrap_or_else(Platform::current);
+    let removed = packages_to_remove(&installed, names, options)?;
+    let desired = installed
+        .iter()
+        .filter(|record| !removed.contains(&record.repodata_record.package_record.name))
+        .map(|record| record.repodata_record.clone())
+        .collect::<Vec<_>>();
+    Ok(Transaction::from_current_and_desired(
+        installed, desired, platform,
+    )?)
+}
+
+/// Returns the names of all the packages to remove.
+fn packages_to_remove(
+    installed: &[PrefixRecord],
+    names: &[PackageName],
+    options: RemoveOptions,
+) -> Result<HashSet<PackageName>, RemoveError> {
+    let records = installed
+        .iter()
+        .map(|record| (&record.repodata_record.package_record.name, record))
+        .collect::<HashMap<_, _>>();
+    for name in names {
+        if !records.contains_key(name) {
+            return Err(RemoveError::NotInstalled(name.clone()));
+        }
+    }
+
+    // Determine for every package which packages depend on it.
+    let dependencies = records
+        .iter()
+        .map(|(&name, record)| (name, dependency_names(record)))
+        .collect::<HashMap<_, _>>();
+    let mut dependents: HashMap<&PackageName, Vec<&PackageName>> = HashMap::new();
+    for (&name, deps) in &dependencies {
+        for dep in deps {
+            if let Some((&dep, _)) = records.get_key_value(dep) {
+                dependents.entry(dep).or_default().push(name);
+            }
+        }
+    }
+
+    let is_requested = |name: &PackageName| records[name].requested_spec.is_some();
+
+    // Remove the packages that (transitively) depend on the removed packages.
+    let mut removed = names.iter().cloned().collect::<HashSet<_>>();
+    if !options.force {
+        let mut queue = names.iter().collect::<Vec<_>>();
+        while let Some(package) = queue.pop() {
+            for &dependent in dependents.get(package).into_iter().flatten() {
+                if removed.contains(dependent) {
+                    continue;
+                }
+                if is_requested(dependent) {
+                    return Err(RemoveError::RequiredBy {
+                        package: package.clone(),
+                        required_by: dependent.clone(),
+                    });
+                }
+                removed.insert(dependent.clone());
+                queue.push(dependent);
+            }
+        }
+    }
+
+    // Remove the dependencies of removed packages that are no longer required.
+    if options.prune {
+        loop {
+            let remaining = records
+                .keys()
+                .filter(|&&name| !removed.contains(name))
+                .collect::<Vec<_>>();
+            let required = remaining
+                .iter()
+                .flat_map(|&&name| &dependencies[name])
+                .collect::<HashSet<_>>();
+            let orphans = removed
+                .iter()
+                .flat_map(|name| &dependencies[name])
+                .filter(|&dep| {
+                    records.contains_key(dep)
+                        && !removed.contains(dep)
+                        && !required.contains(dep)
+                        && !is_requested(dep)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            if orphans.is_empty() {
+                break;
+            }
+            removed.extend(orphans);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Returns the names of the packages the record depends on.
+fn dependency_names(record: &PrefixRecord) -> Vec<PackageName> {
+    record
+        .repodata_record
+        .package_record
+        .depends
+        .iter()
+        .filter_map(|dep| MatchSpec::from_str(dep).ok()?.name)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{packages_to_remove, plan_remove, RemoveError, RemoveOptions};
+    use crate::install::TransactionOperation;
+    use rattler_conda_types::{
+        PackageName, PackageRecord, Platform, PrefixRecord, RepoDataRecord, Version,
+    };
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    fn prefix_record(name: &str, depends: &[&str], requested: bool) -> PrefixRecord {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked(name),
+            Version::from_str("1.0").unwrap(),
+            String::from("0"),
+        );
+        package_record.depends = depends.iter().map(|&dep| dep.to_owned()).collect();
+        PrefixRecord {
+            repodata_record: RepoDataRecord {
+                package_record,
+                file_name: format!("{name}-1.0-0.tar.bz2"),
+                url: url::Url::parse("https://conda.anaconda.org/conda-forge/noarch/").unwrap(),
+                channel: String::from("conda-forge"),
+            },
+            package_tarball_full_path: None,
+            extracted_package_dir: None,
+            files: Vec::new(),
+            paths_data: Default::default(),
+            link: None,
+            requested_spec: requested.then(|| name.to_owned()),
+        }
+    }
+
+    fn installed() -> Vec<PrefixRecord> {
+        // app (requested) -> lib -> base
+        // tool -> lib
+        // other (requested) -> base
+        vec![
+            prefix_record("app", &["lib >=1"], true),
+            prefix_record("tool", &["lib"], false),
+            prefix_record("lib", &["base"], false),
+            prefix_record("base", &[], false),
+            prefix_record("other", &["base"], true),
+            prefix_record("unused", &[], false),
+        ]
+    }
+
+    fn remove(names: &[&str], options: RemoveOptions) -> Result<Vec<String>, RemoveError> {
+        let names = names
+            .iter()
+            .map(|&name| PackageName::new_unchecked(name))
+            .collect::<Vec<_>>();
+        let removed = packages_to_remove(&installed(), &names, options)?;
+        let mut removed = removed
+            .iter()
+            .map(|name| name.as_normalized().to_owned())
+            .collect::<Vec<_>>();
+        removed.sort();
+        Ok(removed)
+    }
+
+    #[test]
+    fn test_remove_dependents() {
+        assert!(matches!(
+            remove(&["lib"], RemoveOptions::default()),
+            Err(RemoveError::RequiredBy { .. })
+        ));
+        assert_eq!(
+            remove(
+                &["lib"],
+                RemoveOptions {
+                    force: true,
+                    ..RemoveOptions::default()
+                }
+            )
+            .unwrap(),
+            ["lib"]
+        );
+        assert_eq!(remove(&["app"], RemoveOptions::default()).unwrap(), ["app"]);
+    }
+
+    #[test]
+    fn test_remove_from_cross_platform_prefix() {
+        // An environment that was created for another platform than the current one.
+        let platform = if Platform::current() == Platform::OsxArm64 {
+            Platform::Linux64
+        } else {
+            Platform::OsxArm64
+        };
+        let installed = installed()
+            .into_iter()
+            .map(|mut record| {
+                record.repodata_record.package_record.subdir = platform.to_string();
+                record
+            })
+            .collect::<Vec<_>>();
+
+        let transaction = plan_remove(
+            installed,
+            &[PackageName::new_unchecked("app")],
+            RemoveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(transaction.platform, platform);
+        assert!(matches!(
+            transaction.operations.as_slice(),
+            [TransactionOperation::Remove(record)]
+                if record.repodata_record.package_record.name.as_normalized() == "app"
+        ));
+    }
+
+    #[test]
+    fn test_remove_prune() {
+        let prune = RemoveOptions {
+            prune: true,
+            ..RemoveOptions::default()
+        };
+
+        // `lib` is still required by `tool`.
+        assert_eq!(remove(&["app"], prune).unwrap(), ["app"]);
+
+        // `base` is still required by `other`, `unused` was not a dependency.
+        assert_eq!(
+            remove(&["app", "tool"], prune).unwrap(),
+            ["app", "lib", "tool"]
+        );
+        assert_eq!(
+            remove(&["app", "tool", "other"], prune)
+                .unwrap()
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            HashSet::from(["app", "lib", "tool", "other", "base"].map(String::from))
+        );
+    }
+}
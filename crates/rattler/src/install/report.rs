@@ -0,0 +1,120 @@
+//! Statistics about how the files of installed packages were linked into a prefix.
+//!
+//! Files that are hard linked or soft linked from the package cache take up (almost) no additional
+//! disk space, whereas copied files and files in which the prefix placeholder was replaced are
+//! stored in the prefix in full. The [`InstallReport`] accumulates these numbers for all packages
+//! that are linked through the same [`super::InstallDriver`], which makes it possible to explain
+//! why an environment is (or isn't) cheap to create.
+
+use super::link::LinkMethod;
+
+/// The number of files and the total number of bytes of these files.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct FileCount {
+    /// The number of files
+    pub files: usize,
+
+    /// The combined size of the files in bytes
+    pub bytes: u64,
+}
+
+impl FileCount {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Statistics about the way files were linked into a prefix.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct LinkStatistics {
+    /// Files that were hard linked to the package cache.
+    pub hard_linked: FileCount,
+
+    /// Files that were soft linked to the package cache.
+    pub soft_linked: FileCount,
+
+    /// Files that were copied from the package cache as is.
+    pub copied: FileCount,
+
+    /// Files in which the prefix placeholder was replaced with the path of the prefix.
+    pub patched: FileCount,
+}
+
+impl LinkStatistics {
+    /// Records that a file of `bytes` bytes was linked using the given method.
+    pub fn record(&mut self, method: LinkMethod, bytes: u64) {
+        match method {
+            LinkMethod::Hardlink => self.hard_linked.add(bytes),
+            LinkMethod::Softlink => self.soft_linked.add(bytes),
+            LinkMethod::Copy => self.copied.add(bytes),
+            LinkMethod::Patched(_) => self.patched.add(bytes),
+        }
+    }
+
+    /// Returns the total number of files and bytes that were linked.
+    pub fn total(&self) -> FileCount {
+        FileCount {
+            files: self.hard_linked.files
+                + self.soft_linked.files
+                + self.copied.files
+                + self.patched.files,
+            bytes: self.hard_linked.bytes
+                + self.soft_linked.bytes
+                + self.copied.bytes
+                + self.patched.bytes,
+        }
+    }
+
+    /// Returns the number of bytes that the linked files take up on disk in addition to the
+    /// package cache. Hard links and soft links share their contents with the package cache so
+    /// only copied and patched files contribute.
+    pub fn additional_disk_usage(&self) -> u64 {
+        self.copied.bytes + self.patched.bytes
+    }
+}
+
+/// A summary of the packages that were linked through an [`super::InstallDriver`]. Use
+/// [`super::InstallDriver::report`] to obtain the report.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InstallReport {
+    /// The number of packages that were successfully linked.
+    pub linked_packages: usize,
+
+    /// How the files of the packages were linked. This includes files of packages that failed to
+    /// link.
+    pub link_statistics: LinkStatistics,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileCount, LinkStatistics};
+    use crate::install::link::LinkMethod;
+    use rattler_conda_types::package::FileMode;
+
+    #[test]
+    fn test_link_statistics() {
+        let mut statistics = LinkStatistics::default();
+        statistics.record(LinkMethod::Hardlink, 1000);
+        statistics.record(LinkMethod::Hardlink, 500);
+        statistics.record(LinkMethod::Softlink, 10);
+        statistics.record(LinkMethod::Copy, 200);
+        statistics.record(LinkMethod::Patched(FileMode::Text), 30);
+
+        assert_eq!(
+            statistics.hard_linked,
+            FileCount {
+                files: 2,
+                bytes: 1500
+            }
+        );
+        assert_eq!(
+            statistics.total(),
+            FileCount {
+                files: 5,
+                bytes: 1740
+            }
+        );
+        assert_eq!(statistics.additional_disk_usage(), 230);
+    }
+}
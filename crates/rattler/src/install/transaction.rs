@@ -1,8 +1,18 @@
 use std::collections::HashSet;
 
+use crate::advisory::{AdvisoryError, AdvisoryProvider, VulnerablePackage};
 use crate::install::python::PythonInfoError;
 use crate::install::PythonInfo;
-use rattler_conda_types::{PackageRecord, Platform};
+use rattler_conda_types::{
+    PackageRecord, Platform, Policy, PolicyViolation, PrefixRecord, RepoDataRecord,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The version of the format in which a [`Transaction`] is serialized. It is stored alongside
+/// the transaction and incremented whenever the format changes in an incompatible way.
+pub const TRANSACTION_SCHEMA_VERSION: u32 = 1;
 
 /// Error that occurred during creation of a Transaction
 #[derive(Debug, thiserror::Error)]
@@ -10,10 +20,30 @@ pub enum TransactionError {
     /// An error that happens if the python version could not be parsed.
     #[error(transparent)]
     PythonInfoError(#[from] PythonInfoError),
+
+    /// Some of the desired packages were built for a different platform than the platform of the
+    /// transaction.
+    #[error("the following packages are not compatible with {platform}: {}", .packages.join(", "))]
+    IncompatibleSubdir {
+        /// The platform of the transaction.
+        platform: Platform,
+
+        /// The packages that are not compatible, formatted as `name-version-build (subdir)`.
+        packages: Vec<String>,
+    },
+
+    /// The records of the packages that are installed in the prefix could not be read.
+    #[error("failed to read the installed packages from the prefix")]
+    FailedToReadPrefix(#[source] std::io::Error),
+
+    /// Some of the desired packages are not allowed by the policy of the transaction.
+    #[error("the policy does not allow: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    PolicyViolation(Vec<PolicyViolation>),
 }
 
 /// Describes an operation to perform
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionOperation<Old, New> {
     /// The given package record should be installed
     Install(New),
@@ -95,6 +125,28 @@ impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New>
         let current_iter = current.into_iter();
         let desired_iter = desired.into_iter();
 
+        // Refuse to mix packages of different platforms into one environment.
+        let incompatible = desired_iter
+            .clone()
+            .filter(|r| !is_compatible_subdir(&r.as_ref().subdir, platform))
+            .map(|r| {
+                let r = r.as_ref();
+                format!(
+                    "{}-{}-{} ({})",
+                    r.name.as_normalized(),
+                    r.version,
+                    r.build,
+                    r.subdir
+                )
+            })
+            .collect::<Vec<_>>();
+        if !incompatible.is_empty() {
+            return Err(TransactionError::IncompatibleSubdir {
+                platform,
+                packages: incompatible,
+            });
+        }
+
         // Determine the python version used in the current situation.
         let current_python_info = find_python_info(current_iter.clone(), platform)?;
         let desired_python_info = find_python_info(desired_iter.clone(), platform)?;
@@ -155,6 +207,151 @@ impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New>
             platform,
         })
     }
+
+    /// Adds an operation to reinstall each of the given installed packages that the transaction
+    /// leaves untouched. This repairs packages whose files were damaged, e.g. by an interrupted
+    /// installation. Packages that the transaction already changes or removes are ignored.
+    pub fn reinstall(&mut self, records: impl IntoIterator<Item = Old>) {
+        let touched = self
+            .operations
+            .iter()
+            .filter_map(TransactionOperation::record_to_remove)
+            .map(|record| record.as_ref().name.clone())
+            .collect::<HashSet<_>>();
+        for record in records {
+            if !touched.contains(&record.as_ref().name) {
+                self.operations
+                    .push(TransactionOperation::Reinstall(record));
+            }
+        }
+    }
+}
+
+impl<Old: AsRef<PackageRecord>> Transaction<Old, RepoDataRecord> {
+    /// Same as [`Transaction::from_current_and_desired`] but refuses to construct a transaction
+    /// if any of the desired packages is not allowed by the `policy`. This makes sure that packages
+    /// that were not selected by a solver that enforces the same policy (e.g. packages from a lock
+    /// file) cannot violate the policy either.
+    pub fn from_current_and_desired_with_policy<
+        CurIter: IntoIterator<Item = Old>,
+        NewIter: IntoIterator<Item = RepoDataRecord>,
+    >(
+        current: CurIter,
+        desired: NewIter,
+        platform: Platform,
+        policy: &Policy,
+    ) -> Result<Self, TransactionError>
+    where
+        CurIter::IntoIter: Clone,
+        NewIter::IntoIter: Clone,
+    {
+        let desired = desired.into_iter();
+        let violations = policy.check_records(desired.clone().collect::<Vec<_>>().iter());
+        if !violations.is_empty() {
+            return Err(TransactionError::PolicyViolation(violations));
+        }
+        Self::from_current_and_desired(current, desired, platform)
+    }
+}
+
+impl<New: AsRef<PackageRecord>> Transaction<PrefixRecord, New> {
+    /// Constructs a [`Transaction`] that brings the environment at `prefix` into the `desired`
+    /// state. The packages that are currently installed are read from the `conda-meta` directory
+    /// of the prefix. Installed packages that are also desired and did not change are left alone,
+    /// so only the differences have to be applied.
+    pub fn from_prefix<NewIter: IntoIterator<Item = New>>(
+        prefix: &Path,
+        desired: NewIter,
+        platform: Platform,
+    ) -> Result<Self, TransactionError>
+    where
+        NewIter::IntoIter: Clone,
+    {
+        let installed = PrefixRecord::collect_from_prefix(prefix)
+            .map_err(TransactionError::FailedToReadPrefix)?;
+        Self::from_current_and_desired(installed, desired, platform)
+    }
+}
+
+impl<Old: AsRef<PackageRecord>, New> Transaction<Old, New> {
+    /// Returns the records of `current` that are not touched by any of the operations of this
+    /// transaction. These are the installed packages that are kept as they are.
+    pub fn unchanged_packages<'a>(
+        &self,
+        current: impl IntoIterator<Item = &'a Old>,
+    ) -> impl Iterator<Item = &'a Old>
+    where
+        Old: 'a,
+    {
+        let touched = self
+            .operations
+            .iter()
+            .filter_map(TransactionOperation::record_to_remove)
+            .map(|record| record.as_ref().name.clone())
+            .collect::<HashSet<_>>();
+        current
+            .into_iter()
+            .filter(move |record| !touched.contains(&record.as_ref().name))
+    }
+}
+
+impl<Old: AsRef<New>, New: AsRef<PackageRecord>> Transaction<Old, New> {
+    /// Returns the packages installed by the transaction that are affected by a known advisory.
+    pub fn find_vulnerable_packages(
+        &self,
+        provider: &dyn AdvisoryProvider,
+    ) -> Result<Vec<VulnerablePackage>, AdvisoryError> {
+        crate::advisory::find_vulnerable_packages(
+            provider,
+            self.operations
+                .iter()
+                .filter_map(TransactionOperation::record_to_install),
+        )
+    }
+}
+
+/// The serialized form of a [`Transaction`], which includes the [`TRANSACTION_SCHEMA_VERSION`].
+#[derive(Serialize, Deserialize)]
+struct SerializedTransaction<Operations> {
+    schema_version: u32,
+    operations: Operations,
+    python_info: Option<PythonInfo>,
+    current_python_info: Option<PythonInfo>,
+    platform: Platform,
+}
+
+impl<Old: Serialize, New: Serialize> Serialize for Transaction<Old, New> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedTransaction {
+            schema_version: TRANSACTION_SCHEMA_VERSION,
+            operations: &self.operations,
+            python_info: self.python_info.clone(),
+            current_python_info: self.current_python_info.clone(),
+            platform: self.platform,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, Old: Deserialize<'de>, New: Deserialize<'de>> Deserialize<'de> for Transaction<Old, New> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let transaction =
+            SerializedTransaction::<Vec<TransactionOperation<Old, New>>>::deserialize(
+                deserializer,
+            )?;
+        if transaction.schema_version != TRANSACTION_SCHEMA_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported transaction schema version {}, expected {}",
+                transaction.schema_version, TRANSACTION_SCHEMA_VERSION
+            )));
+        }
+        Ok(Self {
+            operations: transaction.operations,
+            python_info: transaction.python_info,
+            current_python_info: transaction.current_python_info,
+            platform: transaction.platform,
+        })
+    }
 }
 
 /// Determine the version of Python used by a set of packages. Returns `None` if none of the
@@ -170,6 +367,28 @@ fn find_python_info(
         .map_or(Ok(None), |info| info.map(Some))
 }
 
+/// Returns the platform of an environment that consists of the given records, i.e. the subdir of
+/// the first record that is not a `noarch` package. Returns `None` if there are no such records,
+/// in which case the environment is compatible with any platform.
+///
+/// Use this to determine the platform of an existing prefix, which is not necessarily the current
+/// platform (e.g. an environment that was created for another platform with `--platform`).
+pub fn environment_platform(
+    records: impl IntoIterator<Item = impl AsRef<PackageRecord>>,
+) -> Option<Platform> {
+    records.into_iter().find_map(|record| {
+        Platform::from_str(&record.as_ref().subdir)
+            .ok()
+            .filter(|platform| *platform != Platform::NoArch)
+    })
+}
+
+/// Returns true if a package from the given subdirectory can be installed on the platform. Records
+/// without a subdirectory are assumed to be compatible.
+fn is_compatible_subdir(subdir: &str, platform: Platform) -> bool {
+    subdir.is_empty() || subdir == platform.as_str() || subdir == Platform::NoArch.as_str()
+}
+
 /// Returns true if the specified record refers to Python.
 fn is_python_record(record: &PackageRecord) -> bool {
     record.name.as_normalized() == "python"
@@ -193,3 +412,247 @@ fn describe_same_content(from: &PackageRecord, to: &PackageRecord) -> bool {
     // Otherwise, just check that the name, version and build string match
     from.name == to.name && from.version == to.version && from.build == to.build
 }
+
+#[cfg(test)]
+mod test {
+    use super::{environment_platform, Transaction, TransactionError, TransactionOperation};
+    use crate::test_utils::RecordBuilder;
+    use rattler_conda_types::{
+        prefix_record::PrefixPaths, Platform, Policy, PrefixRecord, RepoDataRecord,
+    };
+
+    #[test]
+    fn test_transaction_serde_roundtrip() {
+        let transaction = Transaction::from_current_and_desired(
+            vec![
+                RecordBuilder::new("python", "3.10.0").build(),
+                RecordBuilder::new("zlib", "1.2").build(),
+            ],
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                RecordBuilder::new("bzip2", "1.0").build(),
+            ],
+            Platform::Linux64,
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&transaction).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["platform"], "linux-64");
+
+        let roundtrip: Transaction<RepoDataRecord, RepoDataRecord> =
+            serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(roundtrip.operations.len(), transaction.operations.len());
+        assert!(matches!(
+            &roundtrip.operations[0],
+            TransactionOperation::Remove(record) if record.package_record.name.as_normalized() == "zlib"
+        ));
+        assert_eq!(
+            roundtrip.python_info.as_ref().unwrap().short_version,
+            (3, 11)
+        );
+        assert_eq!(serde_json::to_value(&roundtrip).unwrap(), json);
+
+        let mut json = json;
+        json["schema_version"] = 2.into();
+        assert!(
+            serde_json::from_value::<Transaction<RepoDataRecord, RepoDataRecord>>(json).is_err()
+        );
+    }
+
+    #[test]
+    fn test_incompatible_subdir() {
+        let windows_record = RecordBuilder::new("vc", "14.3")
+            .with_subdir("win-64")
+            .build();
+        let noarch_record = RecordBuilder::new("tzdata", "2023c")
+            .with_subdir("noarch")
+            .build();
+
+        let err = Transaction::<RepoDataRecord, _>::from_current_and_desired(
+            vec![],
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                windows_record,
+                noarch_record.clone(),
+            ],
+            Platform::Linux64,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            &err,
+            TransactionError::IncompatibleSubdir { platform: Platform::Linux64, packages }
+                if packages == &["vc-14.3-0 (win-64)"]
+        ));
+        assert_eq!(
+            err.to_string(),
+            "the following packages are not compatible with linux-64: vc-14.3-0 (win-64)"
+        );
+
+        assert!(Transaction::<RepoDataRecord, _>::from_current_and_desired(
+            vec![],
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                noarch_record
+            ],
+            Platform::Linux64,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_environment_platform() {
+        let noarch_record = RecordBuilder::new("tzdata", "2023c")
+            .with_subdir("noarch")
+            .build();
+        let osx_record = RecordBuilder::new("python", "3.11.0")
+            .with_subdir("osx-arm64")
+            .build();
+
+        assert_eq!(environment_platform(Vec::<RepoDataRecord>::new()), None);
+        assert_eq!(environment_platform([&noarch_record]), None);
+        assert_eq!(
+            environment_platform([&noarch_record, &osx_record]),
+            Some(Platform::OsxArm64)
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy {
+            denied_packages: vec!["openssl".parse().unwrap()],
+            ..Policy::default()
+        };
+
+        let err = Transaction::from_current_and_desired_with_policy(
+            vec![RecordBuilder::new("python", "3.11.0").build()],
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                RecordBuilder::new("openssl", "3.1").build(),
+            ],
+            Platform::Linux64,
+            &policy,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the policy does not allow: openssl-3.1-0 is denied"
+        );
+
+        let transaction = Transaction::from_current_and_desired_with_policy(
+            vec![RecordBuilder::new("openssl", "3.1").build()],
+            vec![RecordBuilder::new("python", "3.11.0").build()],
+            Platform::Linux64,
+            &policy,
+        )
+        .unwrap();
+        assert_eq!(transaction.operations.len(), 2);
+    }
+
+    #[test]
+    fn test_from_prefix() {
+        let installed = vec![
+            RecordBuilder::new("python", "3.11.0").build(),
+            RecordBuilder::new("zlib", "1.2").build(),
+            RecordBuilder::new("xz", "5.2").build(),
+        ];
+
+        let prefix = tempfile::tempdir().unwrap();
+        let conda_meta = prefix.path().join("conda-meta");
+        std::fs::create_dir_all(&conda_meta).unwrap();
+        for record in installed.iter().cloned() {
+            let path = conda_meta.join(format!(
+                "{}-{}-{}.json",
+                record.package_record.name.as_normalized(),
+                record.package_record.version,
+                record.package_record.build
+            ));
+            PrefixRecord {
+                repodata_record: record,
+                package_tarball_full_path: None,
+                extracted_package_dir: None,
+                files: Vec::new(),
+                paths_data: PrefixPaths {
+                    paths_version: 1,
+                    paths: Vec::new(),
+                },
+                link: None,
+                requested_spec: None,
+            }
+            .write_to_path(path, true)
+            .unwrap();
+        }
+
+        let transaction = Transaction::from_prefix(
+            prefix.path(),
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                RecordBuilder::new("zlib", "1.3").build(),
+                RecordBuilder::new("bzip2", "1.0").build(),
+            ],
+            Platform::Linux64,
+        )
+        .unwrap();
+
+        let mut operations = transaction
+            .operations
+            .iter()
+            .map(|op| match op {
+                TransactionOperation::Install(new) => {
+                    format!("install {}", new.package_record.name.as_normalized())
+                }
+                TransactionOperation::Change { new, .. } => {
+                    format!("change {}", new.package_record.name.as_normalized())
+                }
+                TransactionOperation::Reinstall(old) => format!(
+                    "reinstall {}",
+                    old.repodata_record.package_record.name.as_normalized()
+                ),
+                TransactionOperation::Remove(old) => format!(
+                    "remove {}",
+                    old.repodata_record.package_record.name.as_normalized()
+                ),
+            })
+            .collect::<Vec<_>>();
+        operations.sort();
+        assert_eq!(operations, ["change zlib", "install bzip2", "remove xz"]);
+
+        // Only python is left untouched.
+        let installed = PrefixRecord::collect_from_prefix(prefix.path()).unwrap();
+        let unchanged = transaction
+            .unchanged_packages(&installed)
+            .map(|record| record.repodata_record.package_record.name.as_normalized())
+            .collect::<Vec<_>>();
+        assert_eq!(unchanged, ["python"]);
+    }
+
+    #[test]
+    fn test_reinstall() {
+        let mut transaction = Transaction::from_current_and_desired(
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                RecordBuilder::new("zlib", "1.2").build(),
+            ],
+            vec![
+                RecordBuilder::new("python", "3.11.0").build(),
+                RecordBuilder::new("zlib", "1.3").build(),
+            ],
+            Platform::Linux64,
+        )
+        .unwrap();
+        assert_eq!(transaction.operations.len(), 1);
+
+        // zlib is already changed by the transaction so only python is reinstalled.
+        transaction.reinstall(vec![
+            RecordBuilder::new("python", "3.11.0").build(),
+            RecordBuilder::new("zlib", "1.2").build(),
+        ]);
+        assert_eq!(transaction.operations.len(), 2);
+        assert!(matches!(
+            &transaction.operations[1],
+            TransactionOperation::Reinstall(record) if record.package_record.name.as_normalized() == "python"
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+//! Removes the files of packages from a prefix. Use [`unlink_package`] to remove an installed
+//! package and a [`LinkJournal`] to remove the files of packages that were only partially linked
+//! because a transaction failed or was interrupted.
+
+use super::{InterpreterInfo, PendingTasks, PythonInfo};
+use crate::runtime;
+use itertools::Itertools;
+use rattler_conda_types::{
+    package::{IndexJson, PackageFile, PathType, PathsJson},
+    prefix_record, PrefixRecord,
+};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// An error that can occur when removing the files of a package from a prefix.
+#[derive(Debug, thiserror::Error)]
+pub enum UnlinkError {
+    /// A file of the package could not be removed.
+    #[error("failed to delete '{}'", .0.display())]
+    FailedToDeleteFile(PathBuf, #[source] std::io::Error),
+
+    /// An empty directory of the package could not be removed.
+    #[error("failed to delete directory '{}'", .0.display())]
+    FailedToDeleteDirectory(PathBuf, #[source] std::io::Error),
+
+    /// The metadata of a partially linked package could not be read from the package cache.
+    #[error("failed to read the metadata of '{}'", .0.display())]
+    FailedToReadMetadata(PathBuf, #[source] std::io::Error),
+}
+
+/// Removes the files of an installed package and its `conda-meta` record from the prefix.
+///
+/// Files that no longer exist are ignored. Directories that are owned by the package are only
+/// removed if they are empty, nested directories first, so directories that still contain files
+/// of other packages are left alone.
+///
+/// This function performs blocking IO.
+pub fn unlink_package(target_prefix: &Path, record: &PrefixRecord) -> Result<(), UnlinkError> {
+    // TODO: Take into account any clobbered files, they need to be restored.
+    remove_files(
+        target_prefix,
+        record.paths_data.paths.iter().map(|entry| {
+            let is_directory = entry.path_type == prefix_record::PathType::Directory;
+            (entry.relative_path.as_path(), is_directory)
+        }),
+    )?;
+
+    let conda_meta_path = target_prefix.join("conda-meta").join(record.file_name());
+    remove_file(&conda_meta_path).map_err(|e| UnlinkError::FailedToDeleteFile(conda_meta_path, e))
+}
+
+/// Removes the given files from the prefix. Every entry consists of a path relative to the prefix
+/// and whether it refers to a directory. Directories are removed after all files if they are empty,
+/// nested directories first.
+///
+/// A file that cannot be removed does not stop the removal of the other files, the first error
+/// that occurred is returned once all entries have been processed.
+pub(super) fn remove_files<P: AsRef<Path>>(
+    target_prefix: &Path,
+    entries: impl IntoIterator<Item = (P, bool)>,
+) -> Result<(), UnlinkError> {
+    let (directories, files): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|(_, is_directory)| *is_directory);
+
+    let mut first_error = None;
+    for (relative_path, _) in files {
+        let relative_path = relative_path.as_ref();
+        if let Err(e) = remove_file(&target_prefix.join(relative_path)) {
+            first_error.get_or_insert(UnlinkError::FailedToDeleteFile(
+                relative_path.to_path_buf(),
+                e,
+            ));
+        }
+    }
+
+    for (relative_path, _) in directories
+        .into_iter()
+        .sorted_by_key(|(path, _)| std::cmp::Reverse(path.as_ref().components().count()))
+    {
+        let relative_path = relative_path.as_ref();
+        if let Err(e) = remove_empty_directory(&target_prefix.join(relative_path)) {
+            first_error.get_or_insert(UnlinkError::FailedToDeleteDirectory(
+                relative_path.to_path_buf(),
+                e,
+            ));
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Removes the file at `path`. Files that do not exist are ignored.
+fn remove_file(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Removes the directory at `path` if it is empty. Directories that are not empty or that do not
+/// exist are ignored.
+fn remove_empty_directory(path: &Path) -> std::io::Result<()> {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => match entries.next() {
+            None => std::fs::remove_dir(path),
+            Some(_) => Ok(()),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Keeps track of the packages that are in the process of being linked into a prefix.
+///
+/// Call [`Self::start`] before a package is linked and [`Self::finish`] once its `conda-meta`
+/// record has been written. If the transaction fails or is interrupted, the packages that were
+/// only partially linked at that point can be obtained with [`Self::take_partial_links`] and
+/// removed from the prefix with [`PartialLinks::roll_back`]. Packages that were completely
+/// installed are left untouched. This ensures that the prefix does not contain files that are not
+/// owned by any package.
+#[derive(Debug)]
+pub struct LinkJournal {
+    target_prefix: PathBuf,
+    python_info: Option<PythonInfo>,
+    pending_tasks: PendingTasks,
+    in_progress: Mutex<HashMap<PathBuf, Option<InterpreterInfo>>>,
+}
+
+impl LinkJournal {
+    /// Constructs a new journal for the packages that are linked into `target_prefix` by the
+    /// tasks in `pending_tasks`, see [`super::InstallDriver::pending_tasks`].
+    pub fn new(
+        target_prefix: PathBuf,
+        python_info: Option<PythonInfo>,
+        pending_tasks: PendingTasks,
+    ) -> Self {
+        Self {
+            target_prefix,
+            python_info,
+            pending_tasks,
+            in_progress: Mutex::default(),
+        }
+    }
+
+    /// Records that the package in the given cache directory is being linked, optionally relative
+    /// to an R or Lua interpreter.
+    pub fn start(&self, package_dir: &Path, interpreter_info: Option<InterpreterInfo>) {
+        self.in_progress
+            .lock()
+            .unwrap()
+            .insert(package_dir.to_path_buf(), interpreter_info);
+    }
+
+    /// Records that the package in the given cache directory has been completely installed.
+    pub fn finish(&self, package_dir: &Path) {
+        self.in_progress.lock().unwrap().remove(package_dir);
+    }
+
+    /// Takes the packages that have been started but not finished.
+    pub fn take_partial_links(&self) -> PartialLinks {
+        PartialLinks {
+            target_prefix: self.target_prefix.clone(),
+            python_info: self.python_info.clone(),
+            pending_tasks: self.pending_tasks.clone(),
+            in_progress: std::mem::take(&mut *self.in_progress.lock().unwrap()),
+        }
+    }
+}
+
+/// Packages that were partially linked into a prefix, see [`LinkJournal::take_partial_links`].
+#[derive(Debug)]
+pub struct PartialLinks {
+    target_prefix: PathBuf,
+    python_info: Option<PythonInfo>,
+    pending_tasks: PendingTasks,
+    in_progress: HashMap<PathBuf, Option<InterpreterInfo>>,
+}
+
+impl PartialLinks {
+    /// Returns true if no package was partially linked.
+    pub fn is_empty(&self) -> bool {
+        self.in_progress.is_empty()
+    }
+
+    /// Removes the files of all the partially linked packages from the prefix. This first waits
+    /// for the tasks that were still linking files of these packages, so no files are written
+    /// after they have been removed. Failures are logged, the remaining packages are still removed.
+    pub async fn roll_back(self) {
+        if self.is_empty() {
+            return;
+        }
+        self.pending_tasks.wait().await;
+        let _ = runtime::spawn_blocking(move || {
+            for (package_dir, interpreter_info) in &self.in_progress {
+                tracing::warn!(
+                    "removing partially linked package {}",
+                    package_dir.display()
+                );
+                if let Err(e) = self.roll_back_package(package_dir, interpreter_info.as_ref()) {
+                    tracing::error!(
+                        "failed to remove partially linked package {}: {e}",
+                        package_dir.display()
+                    );
+                }
+            }
+        })
+        .await;
+    }
+
+    /// Removes the files of a partially linked package from the prefix.
+    fn roll_back_package(
+        &self,
+        package_dir: &Path,
+        interpreter_info: Option<&InterpreterInfo>,
+    ) -> Result<(), UnlinkError> {
+        let metadata_error = |e| UnlinkError::FailedToReadMetadata(package_dir.to_path_buf(), e);
+        let index_json = IndexJson::from_package_directory(package_dir).map_err(metadata_error)?;
+        let paths_json = PathsJson::from_package_directory_with_deprecated_fallback(package_dir)
+            .map_err(metadata_error)?;
+        let entries = paths_json.paths.into_iter().map(|entry| {
+            let relative_path = match (&self.python_info, interpreter_info) {
+                (Some(python_info), _) if index_json.noarch.is_python() => python_info
+                    .get_python_noarch_target_path(&entry.relative_path)
+                    .into_owned(),
+                (_, Some(interpreter_info)) => interpreter_info
+                    .get_noarch_target_path(&entry.relative_path)
+                    .into_owned(),
+                _ => entry.relative_path.into_path_buf(),
+            };
+            (relative_path, entry.path_type == PathType::Directory)
+        });
+        remove_files(&self.target_prefix, entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{unlink_package, LinkJournal};
+    use crate::install::{link_package, InstallDriver, InstallOptions};
+    use crate::test_utils::PackageBuilder;
+    use rattler_conda_types::{package::ArchiveType, PrefixRecord, RepoDataRecord};
+
+    /// Builds a package with the given files and extracts it into a temporary package cache.
+    fn extract_package(package: &PackageBuilder) -> tempfile::TempDir {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = package.write(archive_dir.path(), ArchiveType::Conda);
+        let package_dir = tempfile::tempdir().unwrap();
+        rattler_package_streaming::fs::extract(&archive, package_dir.path()).unwrap();
+        package_dir
+    }
+
+    #[tokio::test]
+    async fn test_unlink_package() {
+        let package = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "foo")
+            .with_file("share/foo/data.txt", "data")
+            .with_file("share/shared.txt", "shared");
+        let package_dir = extract_package(&package);
+        let prefix = tempfile::tempdir().unwrap();
+        let paths = link_package(
+            package_dir.path(),
+            prefix.path(),
+            &InstallDriver::default(),
+            InstallOptions::default(),
+        )
+        .await
+        .unwrap();
+        std::fs::write(prefix.path().join("share/other.txt"), "other").unwrap();
+
+        let record = PrefixRecord::from_repodata_record(
+            RepoDataRecord {
+                package_record: rattler_conda_types::PackageRecord::from_index_json(
+                    package.index_json().clone(),
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap(),
+                file_name: package.file_name(ArchiveType::Conda),
+                url: url::Url::parse("https://example.com/foo-1.0-0.conda").unwrap(),
+                channel: String::from("https://example.com"),
+            },
+            None,
+            None,
+            paths,
+            None,
+            None,
+        );
+        let conda_meta_path = record.write_to_prefix(prefix.path()).unwrap();
+
+        unlink_package(prefix.path(), &record).unwrap();
+        assert!(!conda_meta_path.exists());
+        assert!(!prefix.path().join("bin/foo").exists());
+        assert!(!prefix.path().join("share/foo/data.txt").exists());
+        assert!(!prefix.path().join("share/shared.txt").exists());
+        assert!(prefix.path().join("share/other.txt").is_file());
+
+        // Unlinking a package whose files are already gone succeeds.
+        record.write_to_prefix(prefix.path()).unwrap();
+        unlink_package(prefix.path(), &record).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_roll_back_partial_links() {
+        let package = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "foo")
+            .with_file("share/foo/data.txt", "data");
+        let package_dir = extract_package(&package);
+        let prefix = tempfile::tempdir().unwrap();
+        let install_driver = InstallDriver::default();
+        let journal = LinkJournal::new(
+            prefix.path().to_path_buf(),
+            None,
+            install_driver.pending_tasks(),
+        );
+
+        journal.start(package_dir.path(), None);
+        link_package(
+            package_dir.path(),
+            prefix.path(),
+            &install_driver,
+            InstallOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let partial_links = journal.take_partial_links();
+        assert!(!partial_links.is_empty());
+        assert!(journal.take_partial_links().is_empty());
+        partial_links.roll_back().await;
+        assert!(!prefix.path().join("bin/foo").exists());
+        assert!(!prefix.path().join("share/foo/data.txt").exists());
+
+        // Packages that finished linking are not rolled back.
+        journal.start(package_dir.path(), None);
+        link_package(
+            package_dir.path(),
+            prefix.path(),
+            &install_driver,
+            InstallOptions::default(),
+        )
+        .await
+        .unwrap();
+        journal.finish(package_dir.path());
+        journal.take_partial_links().roll_back().await;
+        assert!(prefix.path().join("bin/foo").is_file());
+    }
+}
@@ -12,10 +12,18 @@
 
 use std::path::PathBuf;
 
+pub mod advisory;
+pub mod download_manager;
+pub mod gate;
 pub mod install;
+pub mod offline_channel;
 pub mod package_cache;
+pub mod runtime;
 pub mod validation;
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
 /// A helper function that returns a [`Channel`] instance that points to an empty channel on disk
 /// that is bundled with this repository.
 #[cfg(any(doctest, test))]
@@ -34,6 +42,17 @@ pub(crate) fn get_test_data_dir() -> PathBuf {
     std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data")
 }
 
+/// Returns true if `name` is a single, normal path component. Names from repodata or urls must be
+/// checked with this function before they are joined to a directory, otherwise a name like
+/// `../../foo` or `/etc/foo` could escape that directory.
+pub(crate) fn is_plain_file_name(name: &str) -> bool {
+    let mut components = std::path::Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    ) && !name.contains(['/', '\\'])
+}
+
 /// Returns the default cache directory used by rattler.
 pub fn default_cache_dir() -> anyhow::Result<PathBuf> {
     Ok(dirs::cache_dir()
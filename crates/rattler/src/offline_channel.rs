@@ -0,0 +1,361 @@
+//! Functionality to write a self-contained channel that can be used without network access. See
+//! [`write_offline_channel`].
+//!
+//! An offline channel contains the `repodata.json` of every subdirectory and the package archives
+//! of a set of records, usually the result of solving an environment. This makes it possible to
+//! recreate the environment on machines that do not have access to the original channels (e.g.
+//! air-gapped deployments).
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use fxhash::{FxHashMap, FxHashSet};
+use rattler_conda_types::{package::ArchiveType, ChannelInfo, Platform, RepoData, RepoDataRecord};
+use rattler_digest::{digest::Digest, Md5, Sha256};
+use rattler_networking::AuthenticatedClient;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+/// The maximum number of packages that are downloaded concurrently.
+const MAX_CONCURRENT_DOWNLOADS: usize = 50;
+
+/// An error that can occur when writing an offline channel.
+#[derive(Debug, thiserror::Error)]
+pub enum WriteOfflineChannelError {
+    /// The archive of a package could not be downloaded.
+    #[error("failed to download {0}")]
+    DownloadError(Url, #[source] reqwest::Error),
+
+    /// The archive of a package could not be downloaded from an OCI registry.
+    #[error("failed to download {0}")]
+    OciError(Url, #[source] rattler_networking::oci::OciError),
+
+    /// The contents of a downloaded archive do not match the hash in the repodata.
+    #[error("the {hash} hash of {file_name} does not match the hash in the repodata")]
+    HashMismatch {
+        /// The filename of the package
+        file_name: String,
+
+        /// The kind of hash that did not match (e.g. `sha256`)
+        hash: &'static str,
+    },
+
+    /// The subdirectory or filename of a record is not a plain name and would be written outside of
+    /// the output directory.
+    #[error("'{0}' is not a valid subdirectory or filename")]
+    InvalidPath(String),
+
+    /// An IO error occurred
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// Writes a channel to `output_dir` that only contains the packages described by `records`.
+///
+/// For every subdirectory a `repodata.json` is written that references the records, and the archive
+/// of every package is downloaded next to it. The `noarch` subdirectory is always created because
+/// conda clients require it to be present. The hashes of the downloaded archives are verified
+/// against the hashes stored in the records.
+///
+/// To create a channel that contains everything that is needed to install an environment, pass the
+/// result of solving the environment as `records`.
+pub async fn write_offline_channel(
+    records: impl IntoIterator<Item = RepoDataRecord>,
+    output_dir: &Path,
+    client: AuthenticatedClient,
+) -> Result<(), WriteOfflineChannelError> {
+    let records = records.into_iter().collect::<Vec<_>>();
+    for record in &records {
+        for name in [&record.package_record.subdir, &record.file_name] {
+            if !crate::is_plain_file_name(name) {
+                return Err(WriteOfflineChannelError::InvalidPath(name.clone()));
+            }
+        }
+    }
+
+    // Group the records by subdirectory.
+    let mut subdirs: BTreeMap<String, RepoData> = BTreeMap::new();
+    subdirs.insert(
+        Platform::NoArch.to_string(),
+        empty_repo_data(Platform::NoArch.as_str()),
+    );
+    for record in &records {
+        let repo_data = subdirs
+            .entry(record.package_record.subdir.clone())
+            .or_insert_with(|| empty_repo_data(&record.package_record.subdir));
+        let packages = match ArchiveType::try_from(&record.file_name) {
+            Some(ArchiveType::Conda) => &mut repo_data.conda_packages,
+            _ => &mut repo_data.packages,
+        };
+        packages.insert(record.file_name.clone(), record.package_record.clone());
+    }
+
+    for subdir in subdirs.keys() {
+        tokio::fs::create_dir_all(output_dir.join(subdir)).await?;
+    }
+
+    // Download all the package archives into their subdirectory.
+    futures::stream::iter(records.iter())
+        .map(|record| {
+            let destination = output_dir
+                .join(&record.package_record.subdir)
+                .join(&record.file_name);
+            download_package_archive(&client, record, destination)
+        })
+        .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+        .try_collect::<()>()
+        .await?;
+
+    // Write the repodata last so an interrupted run does not leave a channel that references
+    // missing archives.
+    for (subdir, repo_data) in subdirs {
+        let repo_data = serde_json::to_string_pretty(&repo_data).map_err(std::io::Error::from)?;
+        tokio::fs::write(output_dir.join(subdir).join("repodata.json"), repo_data).await?;
+    }
+
+    Ok(())
+}
+
+/// Constructs a [`RepoData`] for the given subdirectory that does not contain any packages.
+fn empty_repo_data(subdir: &str) -> RepoData {
+    RepoData {
+        info: Some(ChannelInfo {
+            subdir: subdir.to_owned(),
+            base_url: None,
+        }),
+        packages: FxHashMap::default(),
+        conda_packages: FxHashMap::default(),
+        removed: FxHashSet::default(),
+        version: Some(1),
+    }
+}
+
+/// Downloads the archive of the package described by `record` to `destination` and verifies its
+/// hash. The archive is streamed to a temporary file next to `destination` which is only renamed
+/// once the hash has been verified.
+async fn download_package_archive(
+    client: &AuthenticatedClient,
+    record: &RepoDataRecord,
+    destination: PathBuf,
+) -> Result<(), WriteOfflineChannelError> {
+    let mut temp_path = destination.clone().into_os_string();
+    temp_path.push(".partial");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = async {
+        let mut stream = open_package_archive(client, record).await?;
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut sha256 = Sha256::new();
+        let mut md5 = Md5::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            sha256.update(&chunk);
+            md5.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let hash_mismatch = |hash| WriteOfflineChannelError::HashMismatch {
+            file_name: record.file_name.clone(),
+            hash,
+        };
+        if let Some(expected) = &record.package_record.sha256 {
+            if &sha256.finalize() != expected {
+                return Err(hash_mismatch("sha256"));
+            }
+        } else if let Some(expected) = &record.package_record.md5 {
+            if &md5.finalize() != expected {
+                return Err(hash_mismatch("md5"));
+            }
+        }
+
+        tokio::fs::rename(&temp_path, &destination).await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Opens the archive of the package described by `record` as a stream of chunks.
+async fn open_package_archive(
+    client: &AuthenticatedClient,
+    record: &RepoDataRecord,
+) -> Result<
+    impl Stream<Item = Result<Bytes, WriteOfflineChannelError>> + Unpin,
+    WriteOfflineChannelError,
+> {
+    if record.url.scheme() == "file" {
+        let path = record.url.to_file_path().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid file url {}", record.url),
+            )
+        })?;
+        let file = tokio::fs::File::open(path).await?;
+        return Ok(tokio_util::io::ReaderStream::new(file)
+            .map_err(WriteOfflineChannelError::from)
+            .left_stream());
+    }
+
+    let url = record.url.clone();
+    let response = if record.url.scheme() == "oci" {
+        rattler_networking::oci::get_blob(client, &record.url)
+            .await
+            .map_err(|err| WriteOfflineChannelError::OciError(record.url.clone(), err))?
+    } else {
+        client
+            .send(client.get(record.url.clone()))
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| WriteOfflineChannelError::DownloadError(record.url.clone(), err))?
+    };
+    Ok(response
+        .bytes_stream()
+        .map_err(move |err| WriteOfflineChannelError::DownloadError(url.clone(), err))
+        .right_stream())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_offline_channel, WriteOfflineChannelError};
+    use crate::test_utils::{serve_directory, PackageBuilder};
+    use rattler_conda_types::{
+        package::ArchiveType, PackageRecord, Platform, RepoData, RepoDataRecord,
+    };
+    use rattler_digest::{compute_file_digest, Sha256};
+    use rattler_networking::AuthenticatedClient;
+    use std::path::Path;
+
+    /// Builds a package in `dir` and returns a record that refers to it through `server_url`.
+    fn build_record(
+        dir: &Path,
+        server_url: &url::Url,
+        package: PackageBuilder,
+        archive_type: ArchiveType,
+    ) -> RepoDataRecord {
+        let path = package.write(dir, archive_type);
+        let file_name = package.file_name(archive_type);
+        RepoDataRecord {
+            package_record: PackageRecord::from_index_json(
+                package.index_json().clone(),
+                Some(std::fs::metadata(&path).unwrap().len()),
+                Some(compute_file_digest::<Sha256>(&path).unwrap()),
+                None,
+            )
+            .unwrap(),
+            url: server_url.join(&file_name).unwrap(),
+            file_name,
+            channel: server_url.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_offline_channel() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let server_url = serve_directory(source_dir.path());
+        let records = vec![
+            build_record(
+                source_dir.path(),
+                &server_url,
+                PackageBuilder::new("foo", "1.0").with_dependency("bar"),
+                ArchiveType::Conda,
+            ),
+            build_record(
+                source_dir.path(),
+                &server_url,
+                PackageBuilder::new("bar", "2.0").with_file("bar.txt", "bar"),
+                ArchiveType::TarBz2,
+            ),
+        ];
+
+        let output_dir = tempfile::tempdir().unwrap();
+        write_offline_channel(
+            records.clone(),
+            output_dir.path(),
+            AuthenticatedClient::default(),
+        )
+        .await
+        .unwrap();
+
+        let subdir = output_dir.path().join(Platform::current().as_str());
+        let repo_data = RepoData::from_path(subdir.join("repodata.json")).unwrap();
+        assert_eq!(
+            repo_data.conda_packages.get(&records[0].file_name),
+            Some(&records[0].package_record)
+        );
+        assert_eq!(
+            repo_data.packages.get(&records[1].file_name),
+            Some(&records[1].package_record)
+        );
+        for record in &records {
+            assert!(subdir.join(&record.file_name).is_file());
+        }
+
+        let noarch = RepoData::from_path(output_dir.path().join("noarch/repodata.json")).unwrap();
+        assert!(noarch.packages.is_empty() && noarch.conda_packages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hash_mismatch() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let server_url = serve_directory(source_dir.path());
+        let mut record = build_record(
+            source_dir.path(),
+            &server_url,
+            PackageBuilder::new("foo", "1.0"),
+            ArchiveType::Conda,
+        );
+        record.package_record.sha256 = Some(rattler_digest::compute_bytes_digest::<Sha256>(""));
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let result =
+            write_offline_channel([record], output_dir.path(), AuthenticatedClient::default())
+                .await;
+        assert!(matches!(
+            result,
+            Err(WriteOfflineChannelError::HashMismatch { hash: "sha256", .. })
+        ));
+        let subdir = output_dir.path().join(Platform::current().as_str());
+        assert_eq!(std::fs::read_dir(subdir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_path() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let server_url = serve_directory(source_dir.path());
+        let record = build_record(
+            source_dir.path(),
+            &server_url,
+            PackageBuilder::new("foo", "1.0"),
+            ArchiveType::Conda,
+        );
+
+        for (subdir, file_name) in [
+            ("../escape", record.file_name.clone()),
+            ("noarch", format!("../{}", record.file_name)),
+            ("noarch", String::from("/tmp/foo.conda")),
+        ] {
+            let mut record = record.clone();
+            record.package_record.subdir = subdir.to_owned();
+            record.file_name = file_name;
+
+            let output_dir = tempfile::tempdir().unwrap();
+            let result =
+                write_offline_channel([record], output_dir.path(), AuthenticatedClient::default())
+                    .await;
+            assert!(matches!(
+                result,
+                Err(WriteOfflineChannelError::InvalidPath(_))
+            ));
+            assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 0);
+        }
+    }
+}
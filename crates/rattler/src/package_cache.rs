@@ -1,24 +1,37 @@
 //! This module provides functionality to cache extracted Conda packages. See [`PackageCache`].
 
-use crate::validation::validate_package_directory;
-use chrono::Utc;
+use crate::download_manager::{DownloadManager, DownloadPermit};
+use crate::validation::{
+    validate_package_directory, validate_package_directory_with_mode, PackageValidationError,
+    ValidationMode,
+};
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
 use fxhash::FxHashMap;
 use itertools::Itertools;
-use rattler_conda_types::{package::ArchiveIdentifier, PackageRecord};
+use rattler_conda_types::{
+    package::{ArchiveIdentifier, IndexJson, PackageFile, PathsJson},
+    InstallablePackage, PackageArchiveHash, PackageRecord, RepoDataRecord,
+};
+use rattler_digest::{Md5Hash, Sha256Hash};
 use rattler_networking::{
-    retry_policies::{DoNotRetryPolicy, RetryDecision, RetryPolicy},
+    progress::{DownloadProgress, SpeedEstimator},
+    retry_policies::{
+        retry_delay, DoNotRetryPolicy, HttpRetryPolicy, HttpStatusError, RetryDecision, RetryPolicy,
+    },
     AuthenticatedClient,
 };
-use rattler_package_streaming::ExtractError;
-use reqwest::StatusCode;
+use rattler_package_streaming::{ExtractError, ExtractResult};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
 use std::error::Error;
 use std::{
     fmt::{Display, Formatter},
     future::Future,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
-use tokio::sync::broadcast;
+use tokio::{io::AsyncWriteExt, sync::broadcast};
 use tracing::Instrument;
 use url::Url;
 
@@ -28,19 +41,79 @@ use url::Url;
 /// left up to the user when the package is requested. If the package is found in the cache it is
 /// returned immediately. However, if the cache is stale a user defined function is called to
 /// populate the cache. This separates the corners between caching and fetching of the content.
+///
+/// Additional read-only caches can be added with [`PackageCache::with_read_only_caches`], e.g. a
+/// system-wide cache that is shared between multiple users. Packages are taken from these caches
+/// if they contain them, otherwise they are fetched into the writable cache.
 #[derive(Clone)]
 pub struct PackageCache {
     inner: Arc<Mutex<PackageCacheInner>>,
 }
 
+/// Determines how the directories of packages in a [`PackageCache`] are named.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CacheLayout {
+    /// Packages are stored in a directory named after the name, version and build string of the
+    /// package (e.g. `python-3.11.0-h1_0`). This is the layout that is also used by conda.
+    #[default]
+    Name,
+
+    /// Packages are stored in a directory named after the sha256 hash of their archive (e.g.
+    /// `sha256/<hash>`). Identical archives that are served by different channels or under
+    /// different filenames share a single cache entry. Packages for which the hash is not known
+    /// are stored using the [`CacheLayout::Name`] layout.
+    ///
+    /// Packages that were previously stored using the [`CacheLayout::Name`] layout are moved
+    /// to their new location the first time they are requested.
+    Sha256,
+}
+
 /// Provides a unique identifier for packages in the cache.
 /// TODO: This could not be unique over multiple subdir. How to handle?
-/// TODO: Wouldn't it be better to cache based on hashes?
 #[derive(Debug, Hash, Clone, Eq, PartialEq)]
 pub struct CacheKey {
     name: String,
     version: String,
     build_string: String,
+    sha256: Option<Sha256Hash>,
+}
+
+impl CacheKey {
+    /// Adds the sha256 hash of the archive of the package to the key. The hash is used by the
+    /// [`CacheLayout::Sha256`] layout.
+    pub fn with_sha256(self, sha256: Sha256Hash) -> Self {
+        Self {
+            sha256: Some(sha256),
+            ..self
+        }
+    }
+
+    /// Returns the sha256 hash of the archive of the package if it is known.
+    pub fn sha256(&self) -> Option<&Sha256Hash> {
+        self.sha256.as_ref()
+    }
+
+    /// Returns the key of the `package`, which is derived from its record if it is known and from
+    /// the file name of its archive otherwise. Returns `None` if the file name of the archive is
+    /// not the file name of a conda package.
+    pub fn for_package(package: &impl InstallablePackage) -> Option<Self> {
+        let key = match package.package_record() {
+            Some(record) => Self::from(record),
+            None => Self::from(ArchiveIdentifier::try_from_filename(&package.file_name())?),
+        };
+        Some(match package.sha256() {
+            Some(sha256) => key.with_sha256(sha256),
+            None => key,
+        })
+    }
+
+    /// Returns the path of the directory of the package relative to the root of the cache.
+    fn relative_path(&self, layout: CacheLayout) -> PathBuf {
+        match (layout, &self.sha256) {
+            (CacheLayout::Sha256, Some(sha256)) => Path::new("sha256").join(format!("{sha256:x}")),
+            _ => PathBuf::from(self.to_string()),
+        }
+    }
 }
 
 impl From<ArchiveIdentifier> for CacheKey {
@@ -49,6 +122,7 @@ impl From<ArchiveIdentifier> for CacheKey {
             name: pkg.name,
             version: pkg.version,
             build_string: pkg.build_string,
+            sha256: None,
         }
     }
 }
@@ -59,6 +133,7 @@ impl From<&PackageRecord> for CacheKey {
             name: record.name.as_normalized().to_string(),
             version: record.version.to_string(),
             build_string: record.build.to_string(),
+            sha256: record.sha256,
         }
     }
 }
@@ -69,10 +144,31 @@ impl Display for CacheKey {
     }
 }
 
+/// The maximum number of packages whose parsed metadata is kept in memory by a [`PackageCache`].
+const MAX_CACHED_METADATA: usize = 1024;
+
 #[derive(Default)]
 struct PackageCacheInner {
     path: PathBuf,
-    packages: FxHashMap<CacheKey, Arc<Mutex<Package>>>,
+    read_only_paths: Vec<PathBuf>,
+    retain_archives: bool,
+    layout: CacheLayout,
+    download_manager: DownloadManager,
+    packages: FxHashMap<PathBuf, Arc<Mutex<Package>>>,
+    metadata: FxHashMap<PathBuf, CachedMetadata>,
+}
+
+impl PackageCacheInner {
+    /// Remembers the metadata of the package in `package_dir`. If the metadata of too many
+    /// packages is already remembered an arbitrary entry is discarded first.
+    fn remember_metadata(&mut self, package_dir: PathBuf, metadata: CachedMetadata) {
+        if self.metadata.len() >= MAX_CACHED_METADATA && !self.metadata.contains_key(&package_dir) {
+            if let Some(evicted) = self.metadata.keys().next().cloned() {
+                self.metadata.remove(&evicted);
+            }
+        }
+        self.metadata.insert(package_dir, metadata);
+    }
 }
 
 #[derive(Default)]
@@ -81,6 +177,40 @@ struct Package {
     inflight: Option<broadcast::Sender<Result<PathBuf, PackageCacheError>>>,
 }
 
+/// The parsed metadata of a package in the cache, see [`PackageCache::package_metadata`].
+#[derive(Debug, Clone)]
+pub struct PackageMetadata {
+    /// The contents of the `info/index.json` file of the package.
+    pub index_json: IndexJson,
+
+    /// The contents of the `info/paths.json` file of the package, or the information
+    /// reconstructed from the deprecated files of very old packages.
+    pub paths_json: PathsJson,
+}
+
+/// Parsed metadata of a package together with the modification times of the files it was parsed
+/// from. If the files are modified the metadata is parsed again.
+struct CachedMetadata {
+    fingerprint: MetadataFingerprint,
+    metadata: Arc<PackageMetadata>,
+}
+
+/// The modification times of the `info/index.json` and `info/paths.json` files of a package.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct MetadataFingerprint([Option<SystemTime>; 2]);
+
+impl MetadataFingerprint {
+    fn of(package_dir: &Path) -> Self {
+        Self(
+            [IndexJson::package_path(), PathsJson::package_path()].map(|path| {
+                std::fs::metadata(package_dir.join(path))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            }),
+        )
+    }
+}
+
 /// An error that might be returned from one of the caching function of the [`PackageCache`].
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum PackageCacheError {
@@ -89,23 +219,209 @@ pub enum PackageCacheError {
     FetchError(#[from] Arc<dyn std::error::Error + Send + Sync + 'static>),
 }
 
+/// The name of the directory in the cache to which [`CorruptPackageAction::Quarantine`] moves
+/// corrupt packages.
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+/// What [`PackageCache::verify`] does with packages that fail validation.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorruptPackageAction {
+    /// Only report the package.
+    #[default]
+    Report,
+
+    /// Remove the package from the cache so it is fetched again the next time it is requested.
+    Remove,
+
+    /// Move the package to the [`QUARANTINE_DIR`] of the cache so it can be inspected later. The
+    /// package is fetched again the next time it is requested.
+    Quarantine,
+}
+
+/// The result of [`PackageCache::verify`].
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CacheVerificationReport {
+    /// The directories of the packages that passed validation, sorted.
+    pub valid: Vec<PathBuf>,
+
+    /// The packages that failed validation, sorted by their directory.
+    pub corrupt: Vec<CorruptPackage>,
+}
+
+/// A package in the cache that failed validation.
+#[derive(Debug, Clone, Serialize)]
+pub struct CorruptPackage {
+    /// The directory of the package in the cache.
+    pub path: PathBuf,
+
+    /// A description of why validation failed.
+    pub error: String,
+
+    /// The action that was taken. This is [`CorruptPackageAction::Report`] if repairing the
+    /// package failed, see `repair_error`.
+    pub action: CorruptPackageAction,
+
+    /// The location the package was moved to if it was quarantined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantined_to: Option<PathBuf>,
+
+    /// The error that occurred while removing or quarantining the package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair_error: Option<String>,
+}
+
+impl CacheVerificationReport {
+    /// Returns true if all packages in the cache passed validation.
+    pub fn is_valid(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
 impl PackageCache {
     /// Constructs a new [`PackageCache`] located at the specified path.
     pub fn new(path: impl Into<PathBuf>) -> Self {
         Self {
             inner: Arc::new(Mutex::new(PackageCacheInner {
                 path: path.into(),
+                read_only_paths: Vec::new(),
+                retain_archives: false,
+                layout: CacheLayout::default(),
+                download_manager: DownloadManager::default(),
                 packages: Default::default(),
+                metadata: Default::default(),
             })),
         }
     }
 
+    /// Sets whether the original archives of packages that are downloaded into the cache are
+    /// retained next to the extracted packages. Retained archives can be used for mirroring or to
+    /// create offline channels. Archives are only retained for packages that are downloaded after
+    /// retention was enabled. By default archives are not retained.
+    pub fn with_retained_archives(self, retain: bool) -> Self {
+        self.inner.lock().unwrap().retain_archives = retain;
+        self
+    }
+
+    /// Adds caches that are searched for packages before the package is fetched into this cache,
+    /// e.g. a system-wide cache that is shared between multiple users. The caches are searched in
+    /// order. Nothing is ever written to or removed from these caches: packages that are not
+    /// present or that are invalid are fetched into this cache instead.
+    pub fn with_read_only_caches(self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.inner.lock().unwrap().read_only_paths.extend(paths);
+        self
+    }
+
+    /// Sets the layout of the directories of the packages in the cache. See [`CacheLayout`]. The
+    /// default is [`CacheLayout::Name`].
+    pub fn with_layout(self, layout: CacheLayout) -> Self {
+        self.inner.lock().unwrap().layout = layout;
+        self
+    }
+
+    /// Sets the [`DownloadManager`] that limits the number of packages that are downloaded into
+    /// the cache at the same time. The manager can be shared with other caches to limit the total
+    /// number of downloads. By default at most
+    /// [`crate::download_manager::DEFAULT_MAX_CONCURRENT_DOWNLOADS`] packages are downloaded at the
+    /// same time.
+    pub fn with_download_manager(self, download_manager: DownloadManager) -> Self {
+        self.inner.lock().unwrap().download_manager = download_manager;
+        self
+    }
+
+    /// Returns the [`DownloadManager`] that is used to download packages into the cache.
+    pub fn download_manager(&self) -> DownloadManager {
+        self.inner.lock().unwrap().download_manager.clone()
+    }
+
+    /// Returns the path of the retained archive with the given filename (e.g.
+    /// `python-3.11.0-h1_0.conda`) or `None` if the archive is not present in the cache or in one
+    /// of the read-only caches. `None` is also returned if `file_name` is not a plain filename.
+    pub fn archive_path(&self, file_name: &str) -> Option<PathBuf> {
+        if !crate::is_plain_file_name(file_name) {
+            return None;
+        }
+        let inner = self.inner.lock().unwrap();
+        std::iter::once(&inner.path)
+            .chain(inner.read_only_paths.iter())
+            .map(|path| path.join(file_name))
+            .find(|path| path.is_file())
+    }
+
+    /// Returns the path of the writable cache.
+    pub fn path(&self) -> PathBuf {
+        self.inner.lock().unwrap().path.clone()
+    }
+
+    /// Returns the directory of the extracted package if it is already present in this cache or
+    /// in one of the read-only caches. Unlike [`Self::get_or_fetch`] the contents of the directory
+    /// are not validated, only the presence of its `info/paths.json` file is checked.
+    pub fn cached_package_dir(&self, pkg: impl Into<CacheKey>) -> Option<PathBuf> {
+        let cache_key = pkg.into();
+        let inner = self.inner.lock().unwrap();
+        std::iter::once(&inner.path)
+            .chain(inner.read_only_paths.iter())
+            .flat_map(|path| {
+                [CacheLayout::Sha256, CacheLayout::Name]
+                    .map(|layout| path.join(cache_key.relative_path(layout)))
+            })
+            .find(|path| path.join(PathsJson::package_path()).is_file())
+    }
+
+    /// Returns the parsed `info/index.json` and `info/paths.json` files of the package in
+    /// `package_dir`, e.g. a directory returned by [`Self::get_or_fetch`].
+    ///
+    /// The files are parsed at most once per process. Packages that were validated by this cache
+    /// are not parsed again at all, their files were already parsed during validation. The parsed
+    /// files are discarded when the files are modified, e.g. because the package was extracted
+    /// again. The parsed files of at most 1024 packages are kept in memory.
+    pub fn package_metadata(&self, package_dir: &Path) -> std::io::Result<Arc<PackageMetadata>> {
+        let fingerprint = MetadataFingerprint::of(package_dir);
+        if let Some(cached) = self.inner.lock().unwrap().metadata.get(package_dir) {
+            if cached.fingerprint == fingerprint {
+                return Ok(cached.metadata.clone());
+            }
+        }
+
+        let metadata = Arc::new(PackageMetadata {
+            index_json: IndexJson::from_package_directory(package_dir)?,
+            paths_json: PathsJson::from_package_directory_with_deprecated_fallback(package_dir)?,
+        });
+        self.inner.lock().unwrap().remember_metadata(
+            package_dir.to_path_buf(),
+            CachedMetadata {
+                fingerprint,
+                metadata: metadata.clone(),
+            },
+        );
+        Ok(metadata)
+    }
+
+    /// Returns the location where the archive with the given filename should be retained or `None`
+    /// if archives are not retained. Archives whose filename is not a plain filename (e.g. one that
+    /// contains `..` or a path separator) are never retained because they would be written outside
+    /// of the cache.
+    fn archive_destination(&self, file_name: &str) -> Option<PathBuf> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.retain_archives {
+            return None;
+        }
+        if !crate::is_plain_file_name(file_name) {
+            tracing::warn!(
+                "not retaining the archive '{file_name}' because it is not a plain filename"
+            );
+            return None;
+        }
+        Some(inner.path.join(file_name))
+    }
+
     /// Returns the directory that contains the specified package.
     ///
     /// If the package was previously successfully fetched and stored in the cache the directory
     /// containing the data is returned immediately. If the package was not previously fetch the
-    /// filesystem is checked to see if a directory with valid package content exists. Otherwise,
-    /// the user provided `fetch` function is called to populate the cache.
+    /// filesystem is checked to see if a directory with valid package content exists, first in the
+    /// read-only caches and then in this cache. Otherwise, the user provided `fetch` function is
+    /// called to populate the cache.
     ///
     /// If the package is already being fetched by another task/thread the request is coalesced. No
     /// duplicate fetch is performed.
@@ -121,12 +437,38 @@ impl PackageCache {
     {
         let cache_key = pkg.into();
 
-        // Get the package entry
-        let (package, pkg_cache_dir) = {
+        // Get the package entry. Packages are identified by their directory so that packages with
+        // the same hash share an entry when the cache is keyed by hash.
+        let (package, pkg_cache_dir, legacy_dir, read_only_dirs, quarantine_dir) = {
             let mut inner = self.inner.lock().unwrap();
-            let destination = inner.path.join(cache_key.to_string());
-            let package = inner.packages.entry(cache_key).or_default().clone();
-            (package, destination)
+            let relative_path = cache_key.relative_path(inner.layout);
+            let destination = inner.path.join(&relative_path);
+            let quarantine_dir = inner.path.join(QUARANTINE_DIR).join(relative_path);
+            let legacy_dir = inner.path.join(cache_key.relative_path(CacheLayout::Name));
+            let legacy_dir = (legacy_dir != destination).then_some(legacy_dir);
+
+            // The read-only caches might use either layout.
+            let read_only_dirs = inner
+                .read_only_paths
+                .iter()
+                .flat_map(|path| {
+                    [CacheLayout::Sha256, CacheLayout::Name]
+                        .map(|layout| path.join(cache_key.relative_path(layout)))
+                })
+                .unique()
+                .collect::<Vec<_>>();
+            let package = inner
+                .packages
+                .entry(destination.clone())
+                .or_default()
+                .clone();
+            (
+                package,
+                destination,
+                legacy_dir,
+                read_only_dirs,
+                quarantine_dir,
+            )
         };
 
         let mut rx = {
@@ -147,12 +489,18 @@ impl PackageCache {
                 inner.inflight = Some(tx.clone());
 
                 let package = package.clone();
-                tokio::spawn(async move {
-                    let result = validate_or_fetch_to_cache(pkg_cache_dir.clone(), fetch)
-                        .instrument(
-                            tracing::debug_span!("validating", path = %pkg_cache_dir.display()),
-                        )
-                        .await;
+                let cache = self.inner.clone();
+                crate::runtime::spawn(async move {
+                    let result = validate_or_fetch_to_cache(
+                        cache_key,
+                        pkg_cache_dir.clone(),
+                        legacy_dir,
+                        read_only_dirs,
+                        quarantine_dir,
+                        fetch,
+                    )
+                    .instrument(tracing::debug_span!("validating", path = %pkg_cache_dir.display()))
+                    .await;
 
                     {
                         // only sync code in this block
@@ -160,9 +508,16 @@ impl PackageCache {
                         package.inflight = None;
 
                         match result {
-                            Ok(_) => {
-                                package.path.replace(pkg_cache_dir.clone());
-                                let _ = tx.send(Ok(pkg_cache_dir));
+                            Ok((path, metadata)) => {
+                                // Remember the metadata that was parsed during validation.
+                                if let Some(metadata) = metadata {
+                                    cache
+                                        .lock()
+                                        .unwrap()
+                                        .remember_metadata(path.clone(), metadata);
+                                }
+                                package.path.replace(path.clone());
+                                let _ = tx.send(Ok(path));
                             }
                             Err(e) => {
                                 let _ = tx.send(Err(e));
@@ -192,89 +547,372 @@ impl PackageCache {
             .await
     }
 
+    /// Returns the directory that contains the specified package.
+    ///
+    /// Same as [`Self::get_or_fetch_from_url`] but the downloaded archive is verified against the
+    /// `expected_hash`, e.g. the hash anchor of an entry in an explicit environment file. An
+    /// archive that does not match is removed and [`DownloadPackageError::HashMismatch`] is
+    /// returned.
+    pub async fn get_or_fetch_from_url_with_hash(
+        &self,
+        pkg: impl Into<CacheKey>,
+        url: Url,
+        client: AuthenticatedClient,
+        expected_hash: PackageArchiveHash,
+    ) -> Result<PathBuf, PackageCacheError> {
+        let file_name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_owned();
+        let archive_path = self.archive_destination(&file_name);
+        let download_manager = self.download_manager();
+        self.get_or_fetch(pkg, move |destination| async move {
+            let result = fetch_and_extract(
+                &download_manager,
+                client,
+                &url,
+                None,
+                archive_path.as_deref(),
+                &destination,
+            )
+            .await
+            .map_err(|e| DownloadPackageError::ExtractError(file_name.clone(), e))?;
+            let (sha256, md5) = match expected_hash {
+                PackageArchiveHash::Sha256(sha256) => (Some(sha256), None),
+                PackageArchiveHash::Md5(md5) => (None, Some(md5)),
+            };
+            verify_archive(
+                &result,
+                None,
+                sha256.as_ref(),
+                md5.as_ref(),
+                &file_name,
+                archive_path.as_deref(),
+                &destination,
+            )
+            .await
+        })
+        .await
+    }
+
     /// Returns the directory that contains the specified package.
     ///
     /// This is a convenience wrapper around `get_or_fetch` which fetches the package from the given
-    /// URL if the package could not be found in the cache.
+    /// URL if the package could not be found in the cache. Failed downloads are retried according
+    /// to `retry_policy`, but not if the server asks to wait longer than
+    /// [`rattler_networking::retry_policies::DEFAULT_MAX_RETRY_AFTER`].
     pub async fn get_or_fetch_from_url_with_retry(
         &self,
         pkg: impl Into<CacheKey>,
         url: Url,
         client: AuthenticatedClient,
-        retry_policy: impl RetryPolicy + Send + 'static,
+        retry_policy: impl RetryPolicy + Send + Sync + 'static,
     ) -> Result<PathBuf, PackageCacheError> {
+        let archive_path = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|file_name| self.archive_destination(file_name));
+        let download_manager = self.download_manager();
+        let retry_policy = HttpRetryPolicy::new(retry_policy);
         self.get_or_fetch(pkg, move |destination| async move {
-            let mut current_try = 0;
-            loop {
-                current_try += 1;
-                tracing::debug!("downloading {} to {}", &url, destination.display());
-                let result = rattler_package_streaming::reqwest::tokio::extract(
-                    client.clone(),
-                    url.clone(),
-                    &destination,
-                )
-                .await;
-
-                // Extract any potential error
-                let Err(err) = result else { return Ok(()); };
-
-                // Only retry on certain errors.
-                if !matches!(
-                    &err,
-                    ExtractError::IoError(_) | ExtractError::CouldNotCreateDestination(_)
-                ) && !matches!(&err, ExtractError::ReqwestError(err) if
-                    err.is_timeout() ||
-                    err.is_connect() ||
-                    err
-                        .status()
-                        .map(|status| status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT)
-                        .unwrap_or(false)
-                ) {
-                    return Err(err);
-                }
+            fetch_and_extract_with_retry(
+                &download_manager,
+                client,
+                &url,
+                None,
+                archive_path.as_deref(),
+                &destination,
+                &retry_policy,
+            )
+            .await
+            .map(|_| ())
+        })
+        .await
+    }
 
-                // Determine whether or not to retry based on the retry policy
-                let execute_after = match retry_policy.should_retry(current_try) {
-                    RetryDecision::Retry { execute_after } => execute_after,
-                    RetryDecision::DoNotRetry => return Err(err),
-                };
-                let duration = (execute_after - Utc::now()).to_std().expect("the retry duration is out of range");
+    /// Returns the directory that contains the package described by `record`.
+    ///
+    /// Same as [`Self::get_or_fetch_from_url_with_retry`] but the downloaded archive is verified
+    /// against the size and hashes of the record. An archive that does not match is downloaded once
+    /// more before [`DownloadPackageError::SizeMismatch`] or
+    /// [`DownloadPackageError::HashMismatch`] is returned.
+    pub async fn get_or_fetch_from_record_with_retry(
+        &self,
+        record: &RepoDataRecord,
+        client: AuthenticatedClient,
+        retry_policy: impl RetryPolicy + Send + Sync + 'static,
+    ) -> Result<PathBuf, PackageCacheError> {
+        self.get_or_fetch_package_with_retry(record, client, retry_policy)
+            .await
+    }
 
-                // Wait for a second to let the remote service restore itself. This increases the
-                // chance of success.
-                tracing::warn!(
-                    "failed to download and extract {} to {}: {}. Retry #{}, Sleeping {:?} until the next attempt...",
-                    &url,
-                    destination.display(),
-                    err,
-                    current_try,
-                    duration
-                );
-                tokio::time::sleep(duration).await;
-            }
+    /// Returns the directory that contains the `package`, e.g. a [`RepoDataRecord`] or an
+    /// entry of an explicit environment.
+    ///
+    /// Same as [`Self::get_or_fetch_from_record_with_retry`] but the archive is only verified
+    /// against the size and hashes that are known for the package.
+    /// [`DownloadPackageError::UnknownPackage`] is returned if the name, version and build string
+    /// of the package cannot be determined from its record or from the file name of its url.
+    pub async fn get_or_fetch_package_with_retry<P: InstallablePackage + Clone + 'static>(
+        &self,
+        package: &P,
+        client: AuthenticatedClient,
+        retry_policy: impl RetryPolicy + Send + Sync + 'static,
+    ) -> Result<PathBuf, PackageCacheError> {
+        let cache_key = CacheKey::for_package(package).ok_or_else(|| {
+            PackageCacheError::FetchError(Arc::new(DownloadPackageError::UnknownPackage(
+                package.url().to_string(),
+            )))
+        })?;
+        let package = package.clone();
+        let archive_path = self.archive_destination(&package.file_name());
+        let download_manager = self.download_manager();
+        let retry_policy = HttpRetryPolicy::new(retry_policy);
+        self.get_or_fetch(cache_key, move |destination| async move {
+            download_package(
+                &download_manager,
+                &package,
+                client,
+                &retry_policy,
+                archive_path.as_deref(),
+                &destination,
+            )
+            .await
         })
         .await
     }
 }
 
+impl PackageCache {
+    /// Validates every package that is stored in this cache using the given [`ValidationMode`].
+    /// Packages are validated concurrently. Packages that fail validation are handled according
+    /// to `action`. The read-only caches are not verified.
+    pub async fn verify(
+        &self,
+        mode: ValidationMode,
+        action: CorruptPackageAction,
+    ) -> std::io::Result<CacheVerificationReport> {
+        let root = self.inner.lock().unwrap().path.clone();
+        let package_dirs = {
+            let root = root.clone();
+            crate::runtime::spawn_blocking(move || cached_package_dirs(&root))
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Interrupted, e))??
+        };
+
+        let concurrency = std::thread::available_parallelism().map_or(4, usize::from);
+        let results = futures::stream::iter(package_dirs)
+            .map(|dir| {
+                crate::runtime::spawn_blocking(move || {
+                    let result = validate_package_directory_with_mode(&dir, mode);
+                    (dir, result)
+                })
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut report = CacheVerificationReport::default();
+        for (dir, result) in results.into_iter().flatten() {
+            match result {
+                Ok(_) => report.valid.push(dir),
+                Err(e) => {
+                    let error = std::iter::successors(Some(&e as &dyn Error), |e| (*e).source())
+                        .format(": ")
+                        .to_string();
+                    tracing::warn!("{} is corrupt: {error}", dir.display());
+                    report
+                        .corrupt
+                        .push(self.repair_package(&root, dir, error, action));
+                }
+            }
+        }
+        report.valid.sort();
+        report.corrupt.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(report)
+    }
+
+    /// Removes or quarantines a corrupt package and forgets about it, so it is fetched again the
+    /// next time it is requested.
+    fn repair_package(
+        &self,
+        root: &Path,
+        path: PathBuf,
+        error: String,
+        action: CorruptPackageAction,
+    ) -> CorruptPackage {
+        let mut quarantined_to = None;
+        let result = match action {
+            CorruptPackageAction::Report => Ok(()),
+            CorruptPackageAction::Remove => std::fs::remove_dir_all(&path),
+            CorruptPackageAction::Quarantine => {
+                // Keep the layout of the cache so packages with the same name don't collide.
+                let relative_path = path.strip_prefix(root).unwrap_or(&path);
+                let destination = root.join(QUARANTINE_DIR).join(relative_path);
+                let _ = std::fs::remove_dir_all(&destination);
+                let result = destination
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| std::fs::rename(&path, &destination));
+                quarantined_to = Some(destination);
+                result
+            }
+        };
+        if action != CorruptPackageAction::Report {
+            let mut inner = self.inner.lock().unwrap();
+            inner.packages.remove(&path);
+            inner.metadata.remove(&path);
+        }
+
+        match result {
+            Ok(()) => CorruptPackage {
+                path,
+                error,
+                action,
+                quarantined_to,
+                repair_error: None,
+            },
+            Err(e) => CorruptPackage {
+                path,
+                error,
+                action: CorruptPackageAction::Report,
+                quarantined_to: None,
+                repair_error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Returns the directories of the packages that are stored in the cache at `root`, using any
+/// [`CacheLayout`]. Only directories that contain an `info/index.json` file are packages. Files
+/// (e.g. retained archives), hidden directories (e.g. the [`QUARANTINE_DIR`]) and partially
+/// written `.partial` directories are skipped.
+fn cached_package_dirs(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let subdirs = |dir: &Path| -> std::io::Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            let skipped = file_name.starts_with('.')
+                || file_name == QUARANTINE_DIR
+                || file_name.ends_with(".partial");
+            if !skipped && entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+        Ok(dirs)
+    };
+    let is_package_dir = |dir: &Path| dir.join("info/index.json").is_file();
+
+    let mut package_dirs = Vec::new();
+    for dir in match subdirs(root) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        result => result?,
+    } {
+        if dir.file_name().is_some_and(|name| name == "sha256") {
+            package_dirs.extend(subdirs(&dir)?.into_iter().filter(|dir| is_package_dir(dir)));
+        } else if is_package_dir(&dir) {
+            package_dirs.push(dir);
+        }
+    }
+    Ok(package_dirs)
+}
+
 /// Validates that the package that is currently stored is a valid package and otherwise calls the
-/// `fetch` method to populate the cache.
+/// `fetch` method to populate the cache. Returns the directory that contains the package and, if
+/// the package was validated, the metadata that was parsed while validating it.
+///
+/// The directories in `read_only_dirs` are checked first. A valid package in one of them is used
+/// as is, these directories are never modified.
+///
+/// If the package is not stored at `path` but it is stored at `legacy_path` (the location of the
+/// package in a previous layout of the cache) it is moved to `path` first.
+///
+/// A directory that contains a different package than `expected` (e.g. because it was copied into
+/// the cache under the wrong name) is never used. If it is stored at `path` it is moved to
+/// `quarantine_dir` so it can be inspected later.
 async fn validate_or_fetch_to_cache<F, Fut, E>(
+    expected: CacheKey,
     path: PathBuf,
+    legacy_path: Option<PathBuf>,
+    read_only_dirs: Vec<PathBuf>,
+    quarantine_dir: PathBuf,
     fetch: F,
-) -> Result<(), PackageCacheError>
+) -> Result<(PathBuf, Option<CachedMetadata>), PackageCacheError>
 where
     F: FnOnce(PathBuf) -> Fut + Send,
     Fut: Future<Output = Result<(), E>> + 'static,
     E: std::error::Error + Send + Sync + 'static,
 {
+    for read_only_dir in read_only_dirs {
+        if !read_only_dir.is_dir() {
+            continue;
+        }
+        let dir = read_only_dir.clone();
+        let expected = expected.clone();
+        match crate::runtime::spawn_blocking(move || validate_and_read_metadata(&dir, &expected))
+            .await
+        {
+            Ok(Ok(metadata)) => {
+                tracing::debug!("using {} from a read-only cache", read_only_dir.display());
+                return Ok((read_only_dir, Some(metadata)));
+            }
+            Ok(Err(e)) => tracing::warn!(
+                "ignoring invalid package in read-only cache {}: {e}",
+                read_only_dir.display()
+            ),
+            Err(_) => {}
+        }
+    }
+
+    // Migrate the package from its previous location, but only if it is the archive with the
+    // expected hash. If the hash cannot be verified or moving fails the package is fetched again
+    // instead.
+    if let (Some(legacy_path), Some(sha256)) = (legacy_path, expected.sha256().copied()) {
+        if !path.is_dir() && legacy_path.is_dir() {
+            let dir = legacy_path.clone();
+            let matches =
+                crate::runtime::spawn_blocking(move || legacy_package_matches(&dir, &sha256))
+                    .await
+                    .unwrap_or(false);
+            if !matches {
+                tracing::debug!(
+                    "not moving {} because its sha256 hash cannot be verified",
+                    legacy_path.display()
+                );
+            } else if let Err(e) = migrate_package_dir(&legacy_path, &path).await {
+                tracing::warn!(
+                    "failed to move {} to {}: {e}",
+                    legacy_path.display(),
+                    path.display()
+                );
+            }
+        }
+    }
+
     // If the directory already exists validate the contents of the package
     if path.is_dir() {
         let path_inner = path.clone();
-        match tokio::task::spawn_blocking(move || validate_package_directory(&path_inner)).await {
-            Ok(Ok(_)) => {
+        match crate::runtime::spawn_blocking(move || {
+            validate_and_read_metadata(&path_inner, &expected)
+        })
+        .await
+        {
+            Ok(Ok(metadata)) => {
                 tracing::debug!("validation succeeded");
-                return Ok(());
+                return Ok((path, Some(metadata)));
+            }
+            Ok(Err(e @ PackageValidationError::PackageMismatch { .. })) => {
+                tracing::warn!("validation failed: {e}");
+                if let Err(e) = quarantine_package_dir(&path, &quarantine_dir).await {
+                    tracing::warn!(
+                        "failed to move {} to {}: {e}",
+                        path.display(),
+                        quarantine_dir.display()
+                    );
+                }
             }
             Ok(Err(e)) => {
                 tracing::warn!("validation failed: {e}",);
@@ -286,37 +924,539 @@ where
                     );
                 }
             }
-            Err(e) => {
-                if let Ok(panic) = e.try_into_panic() {
-                    std::panic::resume_unwind(panic)
-                }
-            }
+            Err(_) => {}
         }
     }
 
     // Otherwise, defer to populate method to fill our cache.
-    fetch(path)
+    fetch(path.clone())
         .await
-        .map_err(|e| PackageCacheError::FetchError(Arc::new(e)))
+        .map_err(|e| PackageCacheError::FetchError(Arc::new(e)))?;
+    Ok((path, None))
+}
+
+/// Validates that `package_dir` contains the `expected` package and returns the metadata that was
+/// parsed while doing so.
+fn validate_and_read_metadata(
+    package_dir: &Path,
+    expected: &CacheKey,
+) -> Result<CachedMetadata, PackageValidationError> {
+    let fingerprint = MetadataFingerprint::of(package_dir);
+    let (index_json, paths_json) = validate_package_directory(package_dir)?;
+
+    let found = format!(
+        "{}-{}-{}",
+        index_json.name.as_normalized(),
+        index_json.version,
+        index_json.build
+    );
+    let expected = expected.to_string();
+    if found != expected {
+        return Err(PackageValidationError::PackageMismatch { expected, found });
+    }
+    Ok(CachedMetadata {
+        fingerprint,
+        metadata: Arc::new(PackageMetadata {
+            index_json,
+            paths_json,
+        }),
+    })
+}
+
+/// Moves the directory of a package that does not contain the expected package to the quarantine
+/// directory of the cache. A previously quarantined package at the same location is replaced.
+async fn quarantine_package_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    let dir = to.to_path_buf();
+    match crate::runtime::spawn_blocking_io(move || std::fs::remove_dir_all(dir)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    migrate_package_dir(from, to).await
+}
+
+/// Returns true if the extracted package in `legacy_dir` was extracted from the archive with the
+/// given sha256 hash. An extracted package does not contain the hash of its archive, so this
+/// relies on the `info/repodata_record.json` file that conda writes to the packages in its cache.
+/// Packages without it cannot be verified.
+fn legacy_package_matches(legacy_dir: &Path, sha256: &Sha256Hash) -> bool {
+    let Ok(contents) = std::fs::read_to_string(legacy_dir.join("info/repodata_record.json")) else {
+        return false;
+    };
+    match serde_json::from_str::<RepoDataRecord>(&contents) {
+        Ok(record) => record.package_record.sha256.as_ref() == Some(sha256),
+        Err(_) => false,
+    }
+}
+
+/// Moves the directory of a package from its location in a previous layout of the cache.
+async fn migrate_package_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    tracing::debug!("moving {} to {}", from.display(), to.display());
+    let (from, to) = (from.to_path_buf(), to.to_path_buf());
+    crate::runtime::spawn_blocking_io(move || {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(from, to)
+    })
+    .await
+}
+
+/// An error that can occur when downloading a package with [`download_packages`].
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadPackageError {
+    /// The archive could not be downloaded or extracted.
+    #[error("failed to download and extract {0}")]
+    ExtractError(String, #[source] ExtractError),
+
+    /// The contents of the downloaded archive do not match the expected hash, e.g. the hash in the
+    /// repodata.
+    #[error("the {hash} hash of {file_name} does not match the expected hash")]
+    HashMismatch {
+        /// The filename of the package
+        file_name: String,
+
+        /// The kind of hash that did not match (e.g. `sha256`)
+        hash: &'static str,
+    },
+
+    /// The size of the downloaded archive does not match the size in the repodata, e.g. because
+    /// the download was truncated.
+    #[error("{file_name} is {actual} bytes but {expected} bytes were expected")]
+    SizeMismatch {
+        /// The filename of the package
+        file_name: String,
+
+        /// The size of the archive according to the repodata
+        expected: u64,
+
+        /// The size of the downloaded archive
+        actual: u64,
+    },
+
+    /// The package does not refer to a conda package, so it cannot be stored in the cache.
+    #[error("{0} does not refer to a conda package")]
+    UnknownPackage(String),
+
+    /// An IO error occurred
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// Downloads the packages described by `records` into the `cache` without installing them in a
+/// prefix. Returns the directories that contain the extracted packages in the same order as
+/// `records`.
+///
+/// The sizes and hashes of the downloaded archives are verified against the records and archives
+/// that do not match are downloaded again, see [`DownloadPackageError::SizeMismatch`] and
+/// [`DownloadPackageError::HashMismatch`]. Packages that are already present in the cache are not
+/// downloaded again. If the cache retains archives (see [`PackageCache::with_retained_archives`])
+/// the original archive files are also stored in the cache.
+pub async fn download_packages(
+    records: impl IntoIterator<Item = RepoDataRecord>,
+    cache: &PackageCache,
+    client: AuthenticatedClient,
+) -> Result<Vec<PathBuf>, PackageCacheError> {
+    download_packages_with_progress(records, cache, client, |_| {}).await
+}
+
+/// Same as [`download_packages`] but reports the combined progress of all downloads to
+/// `progress_func` every time a package has been added to the cache.
+///
+/// Progress is measured using the size of the archives as specified in the records. The total is
+/// only known if the size of every record is known.
+pub async fn download_packages_with_progress(
+    records: impl IntoIterator<Item = RepoDataRecord>,
+    cache: &PackageCache,
+    client: AuthenticatedClient,
+    mut progress_func: impl FnMut(DownloadProgress),
+) -> Result<Vec<PathBuf>, PackageCacheError> {
+    let records = records.into_iter().collect::<Vec<_>>();
+    let total = records
+        .iter()
+        .map(|record| record.package_record.size)
+        .sum::<Option<u64>>();
+
+    let mut speed_estimator = SpeedEstimator::default();
+    speed_estimator.record(0);
+    progress_func(speed_estimator.progress(total));
+
+    let download_manager = cache.download_manager();
+    let retry_policy = HttpRetryPolicy::new(DoNotRetryPolicy);
+    futures::stream::iter(records)
+        .map(|record| {
+            let client = client.clone();
+            let download_manager = download_manager.clone();
+            let retry_policy = retry_policy.clone();
+            let archive_path = cache.archive_destination(&record.file_name);
+            let cache_key = CacheKey::from(&record.package_record);
+            let size = record.package_record.size.unwrap_or(0);
+            cache
+                .get_or_fetch(cache_key, move |destination| async move {
+                    download_package(
+                        &download_manager,
+                        &record,
+                        client,
+                        &retry_policy,
+                        archive_path.as_deref(),
+                        &destination,
+                    )
+                    .await
+                })
+                .map_ok(move |path| (size, path))
+        })
+        .buffered(download_manager.max_concurrent_downloads())
+        .map_ok(|(size, path)| {
+            speed_estimator.record(speed_estimator.bytes() + size);
+            progress_func(speed_estimator.progress(total));
+            path
+        })
+        .try_collect()
+        .await
+}
+
+/// The number of times a package is downloaded by [`download_packages`] and
+/// [`PackageCache::get_or_fetch_from_record_with_retry`] before giving up when the downloaded
+/// archive does not match its record, e.g. because the download was truncated or a mirror served
+/// a corrupt file.
+const MAX_DOWNLOAD_ATTEMPTS: usize = 2;
+
+/// Downloads and extracts the archive of the `package` to `destination` and verifies the size and
+/// hashes of the archive, as far as they are known. If `archive_path` is specified the archive is
+/// retained at that location. A previously retained archive is reused if its hash matches the
+/// package. An archive that does not match the package is downloaded again.
+async fn download_package(
+    download_manager: &DownloadManager,
+    package: &impl InstallablePackage,
+    client: AuthenticatedClient,
+    retry_policy: &HttpRetryPolicy,
+    archive_path: Option<&Path>,
+    destination: &Path,
+) -> Result<(), DownloadPackageError> {
+    let url = package.url();
+    let file_name = package.file_name();
+    let (size, sha256, md5) = (package.size(), package.sha256(), package.md5());
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = match archive_path {
+            Some(archive_path) if archive_matches_hash(archive_path, sha256, md5).await => {
+                extract_archive(archive_path, destination).await
+            }
+            _ => {
+                fetch_and_extract_with_retry(
+                    download_manager,
+                    client.clone(),
+                    &url,
+                    size,
+                    archive_path,
+                    destination,
+                    retry_policy,
+                )
+                .await
+            }
+        }
+        .map_err(|e| DownloadPackageError::ExtractError(file_name.to_string(), e))?;
+
+        match verify_archive(
+            &result,
+            size,
+            sha256.as_ref(),
+            md5.as_ref(),
+            &file_name,
+            archive_path,
+            destination,
+        )
+        .await
+        {
+            Err(
+                err @ (DownloadPackageError::SizeMismatch { .. }
+                | DownloadPackageError::HashMismatch { .. }),
+            ) if attempt < MAX_DOWNLOAD_ATTEMPTS => {
+                tracing::warn!("{err}, downloading {url} again");
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Verifies that the extracted archive matches the expected size and hashes. The sha256 hash takes
+/// precedence over the md5 hash. If the archive does not match, the extracted package and the
+/// retained archive are removed so they are not picked up from the cache later on.
+async fn verify_archive(
+    result: &ExtractResult,
+    size: Option<u64>,
+    sha256: Option<&Sha256Hash>,
+    md5: Option<&Md5Hash>,
+    file_name: &str,
+    archive_path: Option<&Path>,
+    destination: &Path,
+) -> Result<(), DownloadPackageError> {
+    let error = match (size, sha256, md5) {
+        (Some(size), _, _) if size != result.size => Some(DownloadPackageError::SizeMismatch {
+            file_name: file_name.to_owned(),
+            expected: size,
+            actual: result.size,
+        }),
+        (_, Some(sha256), _) if sha256 != &result.sha256 => {
+            Some(DownloadPackageError::HashMismatch {
+                file_name: file_name.to_owned(),
+                hash: "sha256",
+            })
+        }
+        (_, None, Some(md5)) if md5 != &result.md5 => Some(DownloadPackageError::HashMismatch {
+            file_name: file_name.to_owned(),
+            hash: "md5",
+        }),
+        _ => None,
+    };
+    if let Some(error) = error {
+        let destination = destination.to_path_buf();
+        let archive_path = archive_path.map(Path::to_path_buf);
+        let _ = crate::runtime::spawn_blocking(move || {
+            let _ = std::fs::remove_dir_all(destination);
+            if let Some(archive_path) = archive_path {
+                let _ = std::fs::remove_file(archive_path);
+            }
+        })
+        .await;
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Returns true if the archive at `path` exists and its hash matches the expected hash. The sha256
+/// hash is preferred, the md5 hash is only used if the sha256 hash is unknown.
+async fn archive_matches_hash(
+    path: &Path,
+    sha256: Option<Sha256Hash>,
+    md5: Option<Md5Hash>,
+) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    let path = path.to_path_buf();
+    crate::runtime::spawn_blocking(move || match (sha256, md5) {
+        (Some(sha256), _) => rattler_digest::compute_file_digest::<rattler_digest::Sha256>(&path)
+            .map_or(false, |hash| hash == sha256),
+        (None, Some(md5)) => rattler_digest::compute_file_digest::<rattler_digest::Md5>(&path)
+            .map_or(false, |hash| hash == md5),
+        (None, None) => false,
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Downloads and extracts the archive at `url` like [`fetch_and_extract`]. Failures that are
+/// likely to be transient, e.g. timeouts or server errors, are retried according to
+/// `retry_policy`, which also decides how long a `Retry-After` of the server is honored.
+async fn fetch_and_extract_with_retry(
+    download_manager: &DownloadManager,
+    client: AuthenticatedClient,
+    url: &Url,
+    size: Option<u64>,
+    archive_path: Option<&Path>,
+    destination: &Path,
+    retry_policy: &HttpRetryPolicy,
+) -> Result<ExtractResult, ExtractError> {
+    let mut current_try = 0;
+    loop {
+        current_try += 1;
+        tracing::debug!("downloading {} to {}", &url, destination.display());
+        let result = fetch_and_extract(
+            download_manager,
+            client.clone(),
+            url,
+            size,
+            archive_path,
+            destination,
+        )
+        .await;
+
+        // Extract any potential error
+        let err = match result {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+
+        // Only retry on certain errors. Never retry sooner than the server asked for and give up
+        // if it asks to wait too long.
+        let decision = match &err {
+            ExtractError::IoError(_) | ExtractError::CouldNotCreateDestination(_) => {
+                retry_policy.should_retry(current_try)
+            }
+            ExtractError::HttpStatusError(_, status_err) => {
+                retry_policy.should_retry_status(current_try, status_err)
+            }
+            ExtractError::ReqwestError(reqwest_err) => match reqwest_err
+                .status()
+                .and_then(|status| HttpStatusError::from_parts(status, &HeaderMap::new()))
+            {
+                Some(status_err) => retry_policy.should_retry_status(current_try, &status_err),
+                None => retry_policy.should_retry_error(current_try, reqwest_err),
+            },
+            _ => RetryDecision::DoNotRetry,
+        };
+        let execute_after = match decision {
+            RetryDecision::Retry { execute_after } => execute_after,
+            RetryDecision::DoNotRetry => return Err(err),
+        };
+        let duration = retry_delay(execute_after);
+
+        // Wait for a second to let the remote service restore itself. This increases the
+        // chance of success.
+        tracing::warn!(
+            "failed to download and extract {} to {}: {}. Retry #{}, Sleeping {:?} until the next attempt...",
+            &url,
+            destination.display(),
+            err,
+            current_try,
+            duration
+        );
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Downloads the package archive at `url` and extracts it to `destination`. If `archive_path` is
+/// specified the archive is first downloaded to that location and extracted from there, otherwise
+/// the archive is extracted while it is being downloaded.
+///
+/// The download waits for a permit from the `download_manager` and reports its progress to it.
+/// When the archive is downloaded first, the permit is released before the archive is extracted
+/// so extraction does not occupy a download slot. `size` is the size of the archive if it is known
+/// in advance, e.g. from the repodata.
+async fn fetch_and_extract(
+    download_manager: &DownloadManager,
+    client: AuthenticatedClient,
+    url: &Url,
+    size: Option<u64>,
+    archive_path: Option<&Path>,
+    destination: &Path,
+) -> Result<ExtractResult, ExtractError> {
+    let permit = download_manager.acquire_with_size(url, size).await;
+    match archive_path {
+        Some(archive_path) => {
+            let downloaded = download_archive(&client, url, size, &permit, archive_path).await;
+
+            // Bytes of a failed attempt should not count towards the combined progress.
+            if downloaded.is_err() {
+                permit.update(0, size);
+            }
+
+            // Extracting the archive does not occupy a download slot.
+            drop(permit);
+            downloaded?;
+            extract_archive(archive_path, destination).await
+        }
+        None => {
+            // The streaming extraction does not report its progress, so the whole archive is
+            // reported at once when it completes.
+            let result = rattler_package_streaming::reqwest::tokio::extract(
+                client,
+                url.clone(),
+                destination,
+            )
+            .await;
+            match (&result, size) {
+                (Ok(_), Some(size)) => permit.update(size, Some(size)),
+                (Ok(_), None) => {}
+                // Bytes of a failed attempt should not count towards the combined progress.
+                (Err(_), _) => permit.update(0, size),
+            }
+            result
+        }
+    }
+}
+
+/// Downloads the archive at `url` to `destination`. The archive is first written to a temporary
+/// file which is moved into place once the download completes.
+async fn download_archive(
+    client: &AuthenticatedClient,
+    url: &Url,
+    size: Option<u64>,
+    permit: &DownloadPermit,
+    destination: &Path,
+) -> Result<(), ExtractError> {
+    let partial_path = destination.with_extension("partial");
+    if url.scheme() == "file" {
+        let path = url.to_file_path().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid file url {url}"),
+            )
+        })?;
+        let partial_path = partial_path.clone();
+        crate::runtime::spawn_blocking_io(move || std::fs::copy(path, partial_path)).await?;
+    } else {
+        let mut response = if url.scheme() == "oci" {
+            rattler_networking::oci::get_blob(client, url)
+                .await
+                .map_err(ExtractError::OciError)?
+        } else {
+            client
+                .send(client.get(url.clone()))
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(ExtractError::ReqwestError)?
+        };
+        let total = response.content_length().or(size);
+        let mut bytes = 0;
+        let mut file = tokio::fs::File::create(&partial_path).await?;
+        while let Some(chunk) = response.chunk().await.map_err(ExtractError::ReqwestError)? {
+            file.write_all(&chunk).await?;
+            bytes += chunk.len() as u64;
+            permit.update(bytes, total);
+        }
+        file.flush().await?;
+    }
+    let destination = destination.to_path_buf();
+    crate::runtime::spawn_blocking_io(move || std::fs::rename(partial_path, destination)).await?;
+    Ok(())
+}
+
+/// Extracts the archive at `archive_path` to `destination` on a blocking task of the
+/// [`crate::runtime`].
+async fn extract_archive(
+    archive_path: &Path,
+    destination: &Path,
+) -> Result<ExtractResult, ExtractError> {
+    let (archive_path, destination) = (archive_path.to_path_buf(), destination.to_path_buf());
+    crate::runtime::spawn_blocking(move || {
+        rattler_package_streaming::fs::extract(&archive_path, &destination)
+    })
+    .await
+    .unwrap_or(Err(ExtractError::Cancelled))
 }
 
 #[cfg(test)]
 mod test {
-    use super::PackageCache;
-    use crate::{get_test_data_dir, validation::validate_package_directory};
+    use super::{
+        download_packages, download_packages_with_progress, CacheKey, CacheLayout,
+        CorruptPackageAction, DownloadPackageError, PackageCache, PackageCacheError,
+        QUARANTINE_DIR,
+    };
+    use crate::{
+        get_test_data_dir,
+        test_utils::{serve_directory, PackageBuilder},
+        validation::{validate_package_directory, ValidationMode},
+    };
     use assert_matches::assert_matches;
     use axum::{
         extract::State,
-        http::{Request, StatusCode},
+        http::{header::RETRY_AFTER, Request, StatusCode},
         middleware,
         middleware::Next,
-        response::Response,
+        response::{IntoResponse, Response},
         routing::get_service,
         Router,
     };
-    use rattler_conda_types::package::{ArchiveIdentifier, PackageFile, PathsJson};
+    use rattler_conda_types::{
+        package::{ArchiveIdentifier, ArchiveType, IndexJson, PackageFile, PathsJson},
+        PackageArchiveHash, PackageName, PackageRecord, RepoDataRecord,
+    };
+    use rattler_digest::{compute_file_digest, Sha256};
     use rattler_networking::{
-        retry_policies::{DoNotRetryPolicy, ExponentialBackoffBuilder},
+        retry_policies::{DoNotRetryPolicy, ExponentialBackoffBuilder, HttpRetryPolicy},
         AuthenticatedClient,
     };
     use std::{fs::File, net::SocketAddr, path::Path, sync::Arc};
@@ -366,6 +1506,319 @@ mod test {
         assert_eq!(current_paths, paths);
     }
 
+    #[tokio::test]
+    pub async fn test_package_metadata() {
+        let archive_dir = tempdir().unwrap();
+        let archive_path = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "foo")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        let identifier = ArchiveIdentifier::try_from_path(&archive_path).unwrap();
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let package_dir = cache
+            .get_or_fetch(identifier.clone(), move |destination| async move {
+                rattler_package_streaming::tokio::fs::extract(&archive_path, &destination)
+                    .await
+                    .map(|_| ())
+            })
+            .await
+            .unwrap();
+
+        // The metadata is parsed once and then reused.
+        let metadata = cache.package_metadata(&package_dir).unwrap();
+        assert_eq!(metadata.index_json.name.as_normalized(), "foo");
+        assert_eq!(metadata.paths_json.paths.len(), 1);
+        assert!(Arc::ptr_eq(
+            &metadata,
+            &cache.package_metadata(&package_dir).unwrap()
+        ));
+
+        // Packages that are validated by a new cache already have their metadata parsed.
+        let cache = PackageCache::new(packages_dir.path());
+        let package_dir = cache
+            .get_or_fetch(identifier, |_| async {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "the package should not be fetched",
+                ))
+            })
+            .await
+            .unwrap();
+        let metadata = cache.package_metadata(&package_dir).unwrap();
+        assert!(Arc::ptr_eq(
+            &metadata,
+            &cache.package_metadata(&package_dir).unwrap()
+        ));
+
+        // Modifying the package invalidates the parsed metadata.
+        std::fs::remove_file(package_dir.join(PathsJson::package_path())).unwrap();
+        assert!(cache.package_metadata(&package_dir).is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_package_mismatch_is_quarantined() {
+        let archive_dir = tempdir().unwrap();
+        let foo = PackageBuilder::new("foo", "1.0").write(archive_dir.path(), ArchiveType::Conda);
+        let bar = PackageBuilder::new("bar", "1.0").write(archive_dir.path(), ArchiveType::Conda);
+
+        // Store the contents of foo in the directory of bar.
+        let packages_dir = tempdir().unwrap();
+        let bar_dir = packages_dir.path().join("bar-1.0-0");
+        rattler_package_streaming::tokio::fs::extract(&foo, &bar_dir)
+            .await
+            .unwrap();
+
+        let cache = PackageCache::new(packages_dir.path());
+        let package_dir = cache
+            .get_or_fetch(
+                ArchiveIdentifier::try_from_path(&bar).unwrap(),
+                move |destination| async move {
+                    rattler_package_streaming::tokio::fs::extract(&bar, &destination)
+                        .await
+                        .map(|_| ())
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(package_dir, bar_dir);
+        assert_eq!(
+            IndexJson::from_package_directory(&package_dir)
+                .unwrap()
+                .name
+                .as_normalized(),
+            "bar"
+        );
+
+        // The mismatched package was moved out of the way.
+        let quarantined = packages_dir.path().join(QUARANTINE_DIR).join("bar-1.0-0");
+        assert_eq!(
+            IndexJson::from_package_directory(quarantined)
+                .unwrap()
+                .name
+                .as_normalized(),
+            "foo"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_sha256_layout() {
+        let archive_dir = tempdir().unwrap();
+        let archive_path = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "foo")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        let sha256 = compute_file_digest::<Sha256>(&archive_path).unwrap();
+        let fetch = |archive: std::path::PathBuf| {
+            move |destination: std::path::PathBuf| async move {
+                rattler_package_streaming::tokio::fs::extract(&archive, &destination)
+                    .await
+                    .map(|_| ())
+            }
+        };
+
+        // A package stored using the name based layout whose hash cannot be verified is not
+        // moved, the package is fetched again instead.
+        let packages_dir = tempdir().unwrap();
+        let identifier = ArchiveIdentifier::try_from_path(&archive_path).unwrap();
+        let legacy_dir = PackageCache::new(packages_dir.path())
+            .get_or_fetch(identifier.clone(), fetch(archive_path.clone()))
+            .await
+            .unwrap();
+        let package_dir = PackageCache::new(packages_dir.path())
+            .with_layout(CacheLayout::Sha256)
+            .get_or_fetch(
+                CacheKey::from(identifier.clone()).with_sha256(sha256),
+                fetch(archive_path.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(legacy_dir.exists());
+        validate_package_directory(&package_dir).unwrap();
+
+        // Store the package using the name based layout with the record that conda writes to its
+        // cache.
+        let packages_dir = tempdir().unwrap();
+        let legacy_dir = PackageCache::new(packages_dir.path())
+            .get_or_fetch(identifier.clone(), fetch(archive_path.clone()))
+            .await
+            .unwrap();
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+            String::from("0"),
+        );
+        package_record.sha256 = Some(sha256);
+        let record = RepoDataRecord {
+            package_record,
+            file_name: identifier.to_file_name(),
+            url: "https://example.com/noarch/foo.conda".parse().unwrap(),
+            channel: String::from("https://example.com"),
+        };
+        serde_json::to_writer(
+            File::create(legacy_dir.join("info/repodata_record.json")).unwrap(),
+            &record,
+        )
+        .unwrap();
+
+        // The package is moved to its new location without fetching it again.
+        let cache = PackageCache::new(packages_dir.path()).with_layout(CacheLayout::Sha256);
+        let package_dir = cache
+            .get_or_fetch(CacheKey::from(identifier).with_sha256(sha256), |_| async {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "the package should not be fetched",
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            package_dir,
+            packages_dir
+                .path()
+                .join("sha256")
+                .join(format!("{sha256:x}"))
+        );
+        assert!(!legacy_dir.exists());
+        validate_package_directory(&package_dir).unwrap();
+
+        // A package with a different name but the same hash shares the cache entry.
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("renamed"),
+            "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+            String::from("0"),
+        );
+        record.sha256 = Some(sha256);
+        let renamed_dir = cache
+            .get_or_fetch(&record, |_| async {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "the package should not be fetched",
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(renamed_dir, package_dir);
+    }
+
+    #[tokio::test]
+    pub async fn test_read_only_cache() {
+        let archive_dir = tempdir().unwrap();
+        let fetch = |archive: std::path::PathBuf| {
+            move |destination: std::path::PathBuf| async move {
+                rattler_package_streaming::tokio::fs::extract(&archive, &destination)
+                    .await
+                    .map(|_| ())
+            }
+        };
+        let foo = PackageBuilder::new("foo", "1.0")
+            .with_file("bin/foo", "foo")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        let bar = PackageBuilder::new("bar", "1.0")
+            .with_file("bin/bar", "bar")
+            .write(archive_dir.path(), ArchiveType::Conda);
+        let foo_identifier = ArchiveIdentifier::try_from_path(&foo).unwrap();
+        let bar_identifier = ArchiveIdentifier::try_from_path(&bar).unwrap();
+
+        // Populate the shared cache with foo.
+        let shared_dir = tempdir().unwrap();
+        let shared_foo_dir = PackageCache::new(shared_dir.path())
+            .get_or_fetch(foo_identifier.clone(), fetch(foo))
+            .await
+            .unwrap();
+
+        // foo is taken from the shared cache, bar is fetched into the user cache.
+        let user_dir = tempdir().unwrap();
+        let cache = PackageCache::new(user_dir.path())
+            .with_read_only_caches([shared_dir.path().to_path_buf()]);
+        let foo_dir = cache
+            .get_or_fetch(foo_identifier, |_| async {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "the package should not be fetched",
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(foo_dir, shared_foo_dir);
+        let bar_dir = cache
+            .get_or_fetch(bar_identifier, fetch(bar))
+            .await
+            .unwrap();
+        assert!(bar_dir.starts_with(user_dir.path()));
+        validate_package_directory(&bar_dir).unwrap();
+
+        // Nothing was written to the shared cache.
+        assert_eq!(std::fs::read_dir(shared_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_verify() {
+        let archive_dir = tempdir().unwrap();
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let mut package_dirs = Vec::new();
+        for name in ["bar", "foo"] {
+            let archive = PackageBuilder::new(name, "1.0")
+                .with_file(format!("bin/{name}"), name)
+                .write(archive_dir.path(), ArchiveType::Conda);
+            let identifier = ArchiveIdentifier::try_from_path(&archive).unwrap();
+            let package_dir = cache
+                .get_or_fetch(identifier, move |destination| async move {
+                    rattler_package_streaming::tokio::fs::extract(&archive, &destination)
+                        .await
+                        .map(|_| ())
+                })
+                .await
+                .unwrap();
+            package_dirs.push(package_dir);
+        }
+
+        // Corrupt one of the packages.
+        std::fs::write(package_dirs[1].join("bin/foo"), "corrupt").unwrap();
+
+        // Directories that are not packages are not verified.
+        std::fs::create_dir_all(packages_dir.path().join("baz-1.0-0.partial/info")).unwrap();
+        std::fs::write(
+            packages_dir
+                .path()
+                .join("baz-1.0-0.partial/info/index.json"),
+            "{}",
+        )
+        .unwrap();
+        std::fs::create_dir_all(packages_dir.path().join("not-a-package/bin")).unwrap();
+
+        let report = cache
+            .verify(ValidationMode::Full, CorruptPackageAction::Report)
+            .await
+            .unwrap();
+        assert_eq!(report.valid, [package_dirs[0].clone()]);
+        assert_eq!(report.corrupt.len(), 1);
+        assert_eq!(report.corrupt[0].path, package_dirs[1]);
+        assert_eq!(report.corrupt[0].action, CorruptPackageAction::Report);
+        assert!(package_dirs[1].is_dir());
+
+        // Quarantining moves the package out of the way.
+        let report = cache
+            .verify(ValidationMode::Full, CorruptPackageAction::Quarantine)
+            .await
+            .unwrap();
+        let quarantined_to = report.corrupt[0].quarantined_to.clone().unwrap();
+        assert_eq!(
+            quarantined_to,
+            packages_dir.path().join(QUARANTINE_DIR).join("foo-1.0-0")
+        );
+        assert!(quarantined_to.is_dir());
+        assert!(!package_dirs[1].exists());
+
+        // Quarantined packages are not verified again.
+        let report = cache
+            .verify(ValidationMode::Full, CorruptPackageAction::Quarantine)
+            .await
+            .unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.valid, [package_dirs[0].clone()]);
+    }
+
     /// A helper middleware function that fails the first two requests.
     async fn fail_the_first_two_requests<B>(
         State(count): State<Arc<Mutex<i32>>>,
@@ -456,4 +1909,309 @@ mod test {
             assert_eq!(*request_count_lock, 3, "Expected there to be 3 requests");
         }
     }
+
+    /// A helper middleware function that asks the client to retry the first request after a
+    /// second.
+    async fn throttle_the_first_request<B>(
+        State(count): State<Arc<Mutex<i32>>>,
+        req: Request<B>,
+        next: Next<B>,
+    ) -> Response {
+        let count = {
+            let mut count = count.lock().await;
+            *count += 1;
+            *count
+        };
+
+        if count == 1 {
+            return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "1")]).into_response();
+        }
+        next.run(req).await
+    }
+
+    /// A helper middleware function that asks the client to retry every request after a day.
+    async fn throttle_for_a_day<B>(
+        State(count): State<Arc<Mutex<i32>>>,
+        _req: Request<B>,
+        _next: Next<B>,
+    ) -> Response {
+        *count.lock().await += 1;
+        (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "86400")]).into_response()
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_after() {
+        let request_count = Arc::new(Mutex::new(0));
+        let router = Router::new()
+            .route_service("/*key", get_service(ServeDir::new(get_test_data_dir())))
+            .layer(middleware::from_fn_with_state(
+                request_count.clone(),
+                throttle_the_first_request,
+            ));
+        let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let server = axum::Server::bind(&addr).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let archive_name = "ros-noetic-rosbridge-suite-0.11.14-py39h6fdeb60_14.tar.bz2";
+        let server_url = Url::parse(&format!("http://localhost:{}", addr.port())).unwrap();
+
+        // The download is retried, but not before the time requested by the server.
+        let start = std::time::Instant::now();
+        cache
+            .get_or_fetch_from_url_with_retry(
+                ArchiveIdentifier::try_from_filename(archive_name).unwrap(),
+                server_url.join(archive_name).unwrap(),
+                AuthenticatedClient::default(),
+                HttpRetryPolicy::default(),
+            )
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(*request_count.lock().await, 2);
+
+        // A permanent error is not retried.
+        let result = cache
+            .get_or_fetch_from_url_with_retry(
+                ArchiveIdentifier::try_from_filename("missing-1.0-0.tar.bz2").unwrap(),
+                server_url.join("missing-1.0-0.tar.bz2").unwrap(),
+                AuthenticatedClient::default(),
+                ExponentialBackoffBuilder::default().build_with_max_retries(3),
+            )
+            .await;
+        assert_matches!(result, Err(_));
+        assert_eq!(*request_count.lock().await, 3);
+    }
+
+    #[tokio::test]
+    pub async fn test_oversized_retry_after_is_not_retried() {
+        let request_count = Arc::new(Mutex::new(0));
+        let router = Router::new()
+            .route_service("/*key", get_service(ServeDir::new(get_test_data_dir())))
+            .layer(middleware::from_fn_with_state(
+                request_count.clone(),
+                throttle_for_a_day,
+            ));
+        let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let server = axum::Server::bind(&addr).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let archive_name = "ros-noetic-rosbridge-suite-0.11.14-py39h6fdeb60_14.tar.bz2";
+        let server_url = Url::parse(&format!("http://localhost:{}", addr.port())).unwrap();
+
+        // The server asks to wait longer than the maximum, so the download fails right away.
+        let start = std::time::Instant::now();
+        let result = cache
+            .get_or_fetch_from_url_with_retry(
+                ArchiveIdentifier::try_from_filename(archive_name).unwrap(),
+                server_url.join(archive_name).unwrap(),
+                AuthenticatedClient::default(),
+                ExponentialBackoffBuilder::default().build_with_max_retries(3),
+            )
+            .await;
+        assert_matches!(result, Err(_));
+        assert!(start.elapsed() < std::time::Duration::from_secs(60));
+        assert_eq!(*request_count.lock().await, 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_download_packages() {
+        let channel_dir = tempdir().unwrap();
+        let server_url = serve_directory(channel_dir.path());
+        let package = PackageBuilder::new("foo", "1.0").with_file("foo.txt", "foo");
+        let archive_path = package.write(channel_dir.path(), ArchiveType::Conda);
+        let file_name = package.file_name(ArchiveType::Conda);
+        let record = RepoDataRecord {
+            package_record: PackageRecord::from_index_json(
+                package.index_json().clone(),
+                Some(std::fs::metadata(&archive_path).unwrap().len()),
+                Some(compute_file_digest::<Sha256>(&archive_path).unwrap()),
+                None,
+            )
+            .unwrap(),
+            url: server_url.join(&file_name).unwrap(),
+            file_name: file_name.clone(),
+            channel: server_url.to_string(),
+        };
+
+        // Download the package and keep the archive
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path()).with_retained_archives(true);
+        let mut last_progress = None;
+        let paths = download_packages_with_progress(
+            [record.clone()],
+            &cache,
+            AuthenticatedClient::default(),
+            |progress| last_progress = Some(progress),
+        )
+        .await
+        .unwrap();
+        assert_eq!(paths, vec![packages_dir.path().join("foo-1.0-0")]);
+        let last_progress = last_progress.unwrap();
+        assert_eq!(Some(last_progress.bytes), record.package_record.size);
+        assert_eq!(last_progress.total, record.package_record.size);
+        assert!(validate_package_directory(&paths[0]).is_ok());
+        assert_eq!(
+            cache.archive_path(&file_name),
+            Some(packages_dir.path().join(&file_name))
+        );
+
+        // A package whose hash does not match the record is rejected
+        let mut record = record;
+        record.package_record.name = PackageName::new_unchecked("bar");
+        let mut mismatched = record.clone();
+        mismatched.package_record.sha256 = Some(rattler_digest::compute_bytes_digest::<Sha256>(""));
+        let PackageCacheError::FetchError(err) =
+            download_packages([mismatched], &cache, AuthenticatedClient::default())
+                .await
+                .unwrap_err();
+        assert_matches!(
+            err.downcast_ref::<DownloadPackageError>(),
+            Some(DownloadPackageError::HashMismatch { hash: "sha256", .. })
+        );
+        assert!(!packages_dir.path().join("bar-1.0-0").exists());
+
+        // A truncated download is rejected as well
+        let size = record.package_record.size.unwrap();
+        record.package_record.size = Some(size + 1);
+        let PackageCacheError::FetchError(err) =
+            download_packages([record], &cache, AuthenticatedClient::default())
+                .await
+                .unwrap_err();
+        assert_matches!(
+            err.downcast_ref::<DownloadPackageError>(),
+            Some(DownloadPackageError::SizeMismatch { expected, actual, .. })
+                if *expected == size + 1 && *actual == size
+        );
+        assert!(!packages_dir.path().join("bar-1.0-0").exists());
+        assert!(!packages_dir.path().join(&file_name).exists());
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_from_url_with_hash() {
+        let channel_dir = tempdir().unwrap();
+        let server_url = serve_directory(channel_dir.path());
+        let package = PackageBuilder::new("foo", "1.0").with_file("foo.txt", "foo");
+        let archive_path = package.write(channel_dir.path(), ArchiveType::Conda);
+        let url = server_url
+            .join(&package.file_name(ArchiveType::Conda))
+            .unwrap();
+        let sha256 = compute_file_digest::<Sha256>(&archive_path).unwrap();
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+
+        // An archive that matches the anchor is extracted.
+        let path = cache
+            .get_or_fetch_from_url_with_hash(
+                CacheKey::from(
+                    &PackageRecord::from_index_json(package.index_json().clone(), None, None, None)
+                        .unwrap(),
+                ),
+                url.clone(),
+                AuthenticatedClient::default(),
+                PackageArchiveHash::Sha256(sha256),
+            )
+            .await
+            .unwrap();
+        assert!(validate_package_directory(&path).is_ok());
+
+        // An archive that does not match is rejected and removed.
+        let mut index_json = package.index_json().clone();
+        index_json.name = PackageName::new_unchecked("bar");
+        let result = cache
+            .get_or_fetch_from_url_with_hash(
+                CacheKey::from(
+                    &PackageRecord::from_index_json(index_json, None, None, None).unwrap(),
+                ),
+                url,
+                AuthenticatedClient::default(),
+                PackageArchiveHash::Md5(
+                    rattler_digest::compute_bytes_digest::<rattler_digest::Md5>(""),
+                ),
+            )
+            .await;
+        assert_matches!(result, Err(_));
+        assert!(!packages_dir.path().join("bar-1.0-0").exists());
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_from_record_with_retry() {
+        let channel_dir = tempdir().unwrap();
+        let server_url = serve_directory(channel_dir.path());
+        let package = PackageBuilder::new("foo", "1.0").with_file("foo.txt", "foo");
+        let archive_path = package.write(channel_dir.path(), ArchiveType::Conda);
+        let file_name = package.file_name(ArchiveType::Conda);
+        let size = std::fs::metadata(&archive_path).unwrap().len();
+        let mut record = RepoDataRecord {
+            package_record: PackageRecord::from_index_json(
+                package.index_json().clone(),
+                Some(size),
+                Some(compute_file_digest::<Sha256>(&archive_path).unwrap()),
+                None,
+            )
+            .unwrap(),
+            url: server_url.join(&file_name).unwrap(),
+            file_name,
+            channel: server_url.to_string(),
+        };
+
+        // A package that matches its record is extracted.
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let path = cache
+            .get_or_fetch_from_record_with_retry(
+                &record,
+                AuthenticatedClient::default(),
+                DoNotRetryPolicy,
+            )
+            .await
+            .unwrap();
+        assert!(validate_package_directory(&path).is_ok());
+
+        // A package whose size does not match its record is rejected and removed.
+        record.package_record.name = PackageName::new_unchecked("bar");
+        record.package_record.size = Some(size + 1);
+        let PackageCacheError::FetchError(err) = cache
+            .get_or_fetch_from_record_with_retry(
+                &record,
+                AuthenticatedClient::default(),
+                DoNotRetryPolicy,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err.downcast_ref::<DownloadPackageError>(),
+            Some(DownloadPackageError::SizeMismatch { expected, actual, .. })
+                if *expected == size + 1 && *actual == size
+        );
+        assert!(!packages_dir.path().join("bar-1.0-0").exists());
+    }
+
+    #[test]
+    fn test_archive_destination_rejects_non_plain_names() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path()).with_retained_archives(true);
+        assert_eq!(
+            cache.archive_destination("foo-1.0-0.conda"),
+            Some(packages_dir.path().join("foo-1.0-0.conda"))
+        );
+        for file_name in [
+            "",
+            ".",
+            "..",
+            "../foo-1.0-0.conda",
+            "sub/foo-1.0-0.conda",
+            "sub\\foo-1.0-0.conda",
+            "/tmp/foo-1.0-0.conda",
+        ] {
+            assert_eq!(cache.archive_destination(file_name), None, "{file_name}");
+            assert_eq!(cache.archive_path(file_name), None, "{file_name}");
+        }
+    }
 }
@@ -0,0 +1,256 @@
+//! A small facade over the async runtime that rattler uses to run background tasks.
+//!
+//! Rattler spawns tasks in the background, e.g. to fetch packages into the [`crate::package_cache`]
+//! or to run blocking filesystem operations during an installation. Instead of calling
+//! `tokio::spawn` directly, these tasks are handed to the [`Runtime`] that is registered with
+//! [`set_runtime`]. This makes it possible to embed rattler in applications that use a different
+//! executor.
+//!
+//! If no runtime has been registered, tasks are spawned on the tokio runtime of the calling
+//! context if there is one, and on dedicated threads otherwise.
+//!
+//! Filesystem operations, including the extraction of package archives, are run with
+//! [`spawn_blocking`]. Note that downloading packages and repodata, including writing a downloaded
+//! archive to disk while it is being received, still requires the tokio reactor because it is
+//! required by `reqwest`.
+
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use once_cell::sync::OnceCell;
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Executes the background tasks of rattler.
+pub trait Runtime: Send + Sync + 'static {
+    /// Runs the future to completion in the background.
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Runs a function that blocks the current thread, e.g. because it performs filesystem
+    /// operations, on a thread where blocking is allowed.
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send + 'static>);
+}
+
+/// A [`Runtime`] that spawns tasks on the tokio runtime of the calling context. Spawning a task
+/// outside of a tokio runtime panics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send + 'static>) {
+        tokio::task::spawn_blocking(f);
+    }
+}
+
+/// A [`Runtime`] that does not depend on an executor. Every task is run on a dedicated thread,
+/// futures are driven to completion with [`futures::executor::block_on`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadRuntime;
+
+impl Runtime for ThreadRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        std::thread::spawn(move || futures::executor::block_on(future));
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send + 'static>) {
+        std::thread::spawn(f);
+    }
+}
+
+/// A [`Runtime`] that runs all tasks on a fixed number of dedicated threads. Futures are driven to
+/// completion with [`futures::executor::block_on`] on one of the threads.
+///
+/// Use this runtime to bound the number of CPUs that rattler uses and to keep rattler's work from
+/// competing with the thread pools of an embedding application, e.g. by passing it to
+/// [`crate::install::InstallDriver::with_runtime`]. The threads exit when the runtime is dropped
+/// and all pending tasks have completed.
+pub struct ThreadPoolRuntime {
+    sender: Mutex<mpsc::Sender<Box<dyn FnOnce() + Send + 'static>>>,
+    num_threads: usize,
+}
+
+impl ThreadPoolRuntime {
+    /// Constructs a new runtime with the given number of threads. At least one thread is created.
+    pub fn new(num_threads: usize) -> Self {
+        let num_threads = num_threads.max(1);
+        let (sender, receiver) = mpsc::channel::<Box<dyn FnOnce() + Send + 'static>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..num_threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("rattler-worker-{index}"))
+                .spawn(move || loop {
+                    // Release the lock before running the task so other threads can pick up
+                    // tasks in the meantime.
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        Ok(task) => task(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to spawn a worker thread");
+        }
+        Self {
+            sender: Mutex::new(sender),
+            num_threads,
+        }
+    }
+
+    /// Returns the number of threads of this runtime.
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+}
+
+impl std::fmt::Debug for ThreadPoolRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadPoolRuntime")
+            .field("num_threads", &self.num_threads)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Runtime for ThreadPoolRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        self.spawn_blocking(Box::new(move || futures::executor::block_on(future)));
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send + 'static>) {
+        // The worker threads only stop when the sender is dropped, so sending cannot fail.
+        let _ = self.sender.lock().unwrap().send(f);
+    }
+}
+
+/// The runtime that has been registered with [`set_runtime`].
+static RUNTIME: OnceCell<Box<dyn Runtime>> = OnceCell::new();
+
+/// An error that is returned by [`set_runtime`] if a runtime has already been registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("a runtime has already been registered")]
+pub struct RuntimeAlreadySet;
+
+/// Registers the runtime on which rattler spawns its background tasks. The runtime can only be
+/// registered once and should be registered before rattler is used.
+pub fn set_runtime(runtime: impl Runtime) -> Result<(), RuntimeAlreadySet> {
+    RUNTIME
+        .set(Box::new(runtime))
+        .map_err(|_| RuntimeAlreadySet)
+}
+
+/// Calls `f` with the runtime that tasks should be spawned on.
+fn with_runtime<R>(f: impl FnOnce(&dyn Runtime) -> R) -> R {
+    match RUNTIME.get() {
+        Some(runtime) => f(runtime.as_ref()),
+        None if tokio::runtime::Handle::try_current().is_ok() => f(&TokioRuntime),
+        None => f(&ThreadRuntime),
+    }
+}
+
+/// An error that is returned when a task was dropped by the runtime before it completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the task was cancelled")]
+pub struct Cancelled;
+
+/// Runs the future to completion in the background. Unlike `tokio::spawn` no handle is returned,
+/// use a channel to communicate the result of the future.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    with_runtime(|runtime| runtime.spawn(future.boxed()));
+}
+
+/// Runs the blocking function `f` on a thread where blocking is allowed and returns its result.
+/// A panic in `f` is resumed when the result is awaited.
+pub fn spawn_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> impl Future<Output = Result<T, Cancelled>> {
+    with_runtime(|runtime| spawn_blocking_on(runtime, f))
+}
+
+/// Like [`spawn_blocking`] but runs `f` on the given runtime instead of the registered one.
+pub fn spawn_blocking_on<T: Send + 'static>(
+    runtime: &dyn Runtime,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> impl Future<Output = Result<T, Cancelled>> {
+    let (tx, rx) = oneshot::channel::<Result<T, Box<dyn Any + Send>>>();
+    runtime.spawn_blocking(Box::new(move || {
+        if !tx.is_canceled() {
+            let _ = tx.send(catch_unwind(AssertUnwindSafe(f)));
+        }
+    }));
+    async move {
+        match rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(panic)) => std::panic::resume_unwind(panic),
+            Err(_) => Err(Cancelled),
+        }
+    }
+}
+
+/// Runs the blocking io operation `f` with [`spawn_blocking`]. A cancelled operation is returned
+/// as an [`std::io::ErrorKind::Interrupted`] error.
+pub(crate) async fn spawn_blocking_io<T: Send + 'static>(
+    f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    spawn_blocking(f).await.unwrap_or_else(|Cancelled| {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            Cancelled,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        spawn, spawn_blocking, spawn_blocking_on, Runtime, ThreadPoolRuntime, ThreadRuntime,
+    };
+
+    #[test]
+    fn test_spawn_without_tokio() {
+        let result = futures::executor::block_on(spawn_blocking(|| 1 + 1));
+        assert_eq!(result, Ok(2));
+
+        let (tx, rx) = futures::channel::oneshot::channel();
+        spawn(async move {
+            tx.send(spawn_blocking(|| "done").await).unwrap();
+        });
+        assert_eq!(futures::executor::block_on(rx).unwrap(), Ok("done"));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_blocking_resumes_panics() {
+        let result = tokio::spawn(spawn_blocking(|| panic!("boom"))).await;
+        assert!(result.unwrap_err().is_panic());
+    }
+
+    #[test]
+    fn test_thread_runtime() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        ThreadRuntime.spawn_blocking(Box::new(move || tx.send(1).unwrap()));
+        assert_eq!(rx.recv().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_thread_pool_runtime() {
+        let runtime = ThreadPoolRuntime::new(2);
+        let thread_names = (0..8)
+            .map(|_| {
+                spawn_blocking_on(&runtime, || {
+                    std::thread::current().name().unwrap().to_owned()
+                })
+            })
+            .collect::<Vec<_>>();
+        for name in futures::executor::block_on(futures::future::join_all(thread_names)) {
+            assert!(["rattler-worker-0", "rattler-worker-1"].contains(&name.unwrap().as_str()));
+        }
+
+        let (tx, rx) = futures::channel::oneshot::channel();
+        runtime.spawn(Box::pin(async move { tx.send(1).unwrap() }));
+        assert_eq!(futures::executor::block_on(rx), Ok(1));
+    }
+}
@@ -0,0 +1,170 @@
+//! Utilities to write hermetic integration tests.
+//!
+//! [`PackageBuilder`] programmatically creates tiny but valid `.tar.bz2` and `.conda` packages and
+//! [`serve_directory`] serves a directory over HTTP on localhost. Together they allow testing the
+//! fetch, validate and link code paths without relying on packages from the network or large test
+//! fixtures.
+//! [`RecordBuilder`] constructs the repodata records of packages for tests that do not need the
+//! archives themselves.
+//!
+//! This module is only available with the `test-utils` feature, add it to the `dev-dependencies`
+//! of a crate to use it in tests.
+
+use rattler_conda_types::{
+    package::{ArchiveType, IndexJson, PathType, PathsEntry, PathsJson},
+    PackageName, PrefixRelativePath, Version,
+};
+use rattler_package_streaming::write::{
+    write_conda_package, write_tar_bz2_package, CompressionLevel,
+};
+use std::{
+    fs::File,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+pub use rattler_conda_types::test_utils::RecordBuilder;
+
+/// Builds a minimal conda package from a set of files.
+pub struct PackageBuilder {
+    index: IndexJson,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl PackageBuilder {
+    /// Constructs a new builder for a package with the given name and version. The package has
+    /// build string `0` and is built for the current platform.
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            index: IndexJson::new(
+                PackageName::new_unchecked(name),
+                version.parse::<Version>().unwrap(),
+                String::from("0"),
+            ),
+            files: Vec::new(),
+        }
+    }
+
+    /// Sets the build string and build number of the package.
+    pub fn with_build(mut self, build: &str, build_number: u64) -> Self {
+        self.index.build = build.to_owned();
+        self.index.build_number = build_number;
+        self
+    }
+
+    /// Adds a dependency to the package.
+    pub fn with_dependency(mut self, spec: &str) -> Self {
+        self.index.depends.push(spec.to_owned());
+        self
+    }
+
+    /// Adds a file with the given contents to the package. The path is relative to the root of
+    /// the environment the package is installed in.
+    pub fn with_file(mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.push((path.into(), contents.into()));
+        self
+    }
+
+    /// Returns the `index.json` of the package.
+    pub fn index_json(&self) -> &IndexJson {
+        &self.index
+    }
+
+    /// Returns the filename of the package archive with the given type.
+    pub fn file_name(&self, archive_type: ArchiveType) -> String {
+        format!(
+            "{}-{}-{}{}",
+            self.index.name.as_normalized(),
+            self.index.version,
+            self.index.build,
+            archive_type.extension()
+        )
+    }
+
+    /// Writes the package archive with the given type to `output_dir`. Returns the path to the
+    /// archive.
+    pub fn write(&self, output_dir: &Path, archive_type: ArchiveType) -> PathBuf {
+        let package_dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+
+        // Write all the files and create the entries of the `paths.json` file.
+        let mut entries = Vec::new();
+        for (path, contents) in &self.files {
+            let destination = package_dir.path().join(path);
+            std::fs::create_dir_all(destination.parent().unwrap()).unwrap();
+            std::fs::write(&destination, contents).unwrap();
+            paths.push(destination);
+            entries.push(PathsEntry {
+                relative_path: PrefixRelativePath::new(path.clone()).unwrap(),
+                no_link: false,
+                path_type: PathType::HardLink,
+                prefix_placeholder: None,
+                sha256: Some(
+                    rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(contents),
+                ),
+                size_in_bytes: Some(contents.len() as u64),
+            });
+        }
+
+        // Write the metadata of the package.
+        let info_dir = package_dir.path().join("info");
+        std::fs::create_dir_all(&info_dir).unwrap();
+        let paths_json = PathsJson {
+            paths: entries,
+            paths_version: 1,
+        };
+        std::fs::write(
+            info_dir.join("paths.json"),
+            serde_json::to_string_pretty(&paths_json).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(
+            info_dir.join("index.json"),
+            serde_json::to_string_pretty(&self.index).unwrap(),
+        )
+        .unwrap();
+        paths.push(info_dir.join("paths.json"));
+        paths.push(info_dir.join("index.json"));
+
+        // Create the archive
+        let file_name = self.file_name(archive_type);
+        let archive_path = output_dir.join(&file_name);
+        let writer = File::create(&archive_path).unwrap();
+        match archive_type {
+            ArchiveType::TarBz2 => write_tar_bz2_package(
+                writer,
+                package_dir.path(),
+                &paths,
+                CompressionLevel::Lowest,
+                None,
+            ),
+            ArchiveType::Conda => write_conda_package(
+                writer,
+                package_dir.path(),
+                &paths,
+                CompressionLevel::Lowest,
+                file_name.strip_suffix(archive_type.extension()).unwrap(),
+                None,
+            ),
+        }
+        .unwrap();
+
+        archive_path
+    }
+}
+
+/// Serves the files in `dir` over HTTP. The server listens on localhost on a random port which
+/// makes it possible to run multiple tests in parallel. Returns the url of the root of the
+/// directory.
+pub fn serve_directory(dir: &Path) -> Url {
+    let service = axum::routing::get_service(tower_http::services::ServeDir::new(dir));
+    let router = axum::Router::new().route_service("/*key", service);
+
+    let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+    let server = axum::Server::bind(&addr).serve(router.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    Url::parse(&format!("http://localhost:{}/", addr.port())).unwrap()
+}
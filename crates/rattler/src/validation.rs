@@ -41,6 +41,17 @@ pub enum PackageValidationError {
     /// An error occurred while reading the `index.json` file.
     #[error("failed to read 'index.json'")]
     ReadIndexJsonError(#[source] std::io::Error),
+
+    /// The `index.json` file describes a different package than the one that was expected, e.g.
+    /// because a directory was copied into a package cache under the wrong name.
+    #[error("the directory contains {found} but {expected} was expected")]
+    PackageMismatch {
+        /// The name, version and build string of the expected package.
+        expected: String,
+
+        /// The name, version and build string of the package described by the `index.json` file.
+        found: String,
+    },
 }
 
 /// An error that indicates that a specific file in a package archive directory seems to be corrupted.
@@ -75,6 +86,17 @@ pub enum PackageEntryValidationError {
     HashMismatch(String, String),
 }
 
+/// Determines how thoroughly the files of a package are validated.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ValidationMode {
+    /// Only check that the files exist, have the expected type and the expected size.
+    Fast,
+
+    /// Also check that the sha256 hashes of the files match.
+    #[default]
+    Full,
+}
+
 /// Determine whether the files in the specified directory match what is expected according to the
 /// `info/paths.json` file in the same directory.
 ///
@@ -85,6 +107,14 @@ pub enum PackageEntryValidationError {
 /// about the files in the archive.
 pub fn validate_package_directory(
     package_dir: &Path,
+) -> Result<(IndexJson, PathsJson), PackageValidationError> {
+    validate_package_directory_with_mode(package_dir, ValidationMode::Full)
+}
+
+/// Same as [`validate_package_directory`] but uses the given [`ValidationMode`].
+pub fn validate_package_directory_with_mode(
+    package_dir: &Path,
+    mode: ValidationMode,
 ) -> Result<(IndexJson, PathsJson), PackageValidationError> {
     // Validate that there is a valid IndexJson
     let index_json = IndexJson::from_package_directory(package_dir)
@@ -108,7 +138,7 @@ pub fn validate_package_directory(
     };
 
     // Validate all the entries
-    validate_package_directory_from_paths(package_dir, &paths)
+    validate_entries(package_dir, &paths, mode)
         .map_err(|(path, err)| PackageValidationError::CorruptedEntry(path, err))?;
 
     Ok((index_json, paths))
@@ -120,9 +150,18 @@ pub fn validate_package_directory_from_paths(
     package_dir: &Path,
     paths: &PathsJson,
 ) -> Result<(), (PathBuf, PackageEntryValidationError)> {
-    // Check every entry in the PathsJson object
+    validate_entries(package_dir, paths, ValidationMode::Full)
+}
+
+/// Checks every entry in the [`PathsJson`] object using the given [`ValidationMode`].
+fn validate_entries(
+    package_dir: &Path,
+    paths: &PathsJson,
+    mode: ValidationMode,
+) -> Result<(), (PathBuf, PackageEntryValidationError)> {
     for entry in paths.paths.iter() {
-        validate_package_entry(package_dir, entry).map_err(|e| (entry.relative_path.clone(), e))?;
+        validate_package_entry(package_dir, entry, mode)
+            .map_err(|e| (entry.relative_path.to_path_buf(), e))?;
     }
 
     Ok(())
@@ -132,6 +171,7 @@ pub fn validate_package_directory_from_paths(
 fn validate_package_entry(
     package_dir: &Path,
     entry: &PathsEntry,
+    mode: ValidationMode,
 ) -> Result<(), PackageEntryValidationError> {
     let path = package_dir.join(&entry.relative_path);
 
@@ -146,7 +186,7 @@ fn validate_package_entry(
 
     // Validate based on the type of path
     match entry.path_type {
-        PathType::HardLink => validate_package_hard_link_entry(path, entry, metadata),
+        PathType::HardLink => validate_package_hard_link_entry(path, entry, metadata, mode),
         PathType::SoftLink => validate_package_soft_link_entry(path, entry, metadata),
         PathType::Directory => validate_package_directory_entry(path, entry, metadata),
     }
@@ -157,6 +197,7 @@ fn validate_package_hard_link_entry(
     path: PathBuf,
     entry: &PathsEntry,
     metadata: Metadata,
+    mode: ValidationMode,
 ) -> Result<(), PackageEntryValidationError> {
     debug_assert!(entry.path_type == PathType::HardLink);
 
@@ -171,7 +212,7 @@ fn validate_package_hard_link_entry(
     }
 
     // Check the SHA256 hash of the file
-    if let Some(expected_hash) = &entry.sha256 {
+    if let (ValidationMode::Full, Some(expected_hash)) = (mode, &entry.sha256) {
         // Determine the hash of the file on disk
         let hash = compute_file_digest::<rattler_digest::Sha256>(&path)?;
 
@@ -227,7 +268,8 @@ fn validate_package_directory_entry(
 mod test {
     use super::{
         validate_package_directory, validate_package_directory_from_paths,
-        PackageEntryValidationError, PackageValidationError,
+        validate_package_directory_with_mode, PackageEntryValidationError, PackageValidationError,
+        ValidationMode,
     };
     use assert_matches::assert_matches;
     use rattler_conda_types::package::{PackageFile, PathType, PathsJson};
@@ -286,6 +328,11 @@ mod test {
                 PackageEntryValidationError::HashMismatch(_, _)
             )) if path == entry.relative_path
         );
+
+        // The size of the file did not change so a fast validation does not detect it.
+        assert!(
+            validate_package_directory_with_mode(temp_dir.path(), ValidationMode::Fast).is_ok()
+        );
     }
 
     #[rstest]
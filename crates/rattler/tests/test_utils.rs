@@ -0,0 +1,58 @@
+//! Tests the fixtures of [`rattler::test_utils`] through the public API of the crate.
+
+use rattler::{
+    install::{link_package, InstallDriver},
+    package_cache::PackageCache,
+    test_utils::{serve_directory, PackageBuilder},
+    validation::validate_package_directory,
+};
+use rattler_conda_types::package::{ArchiveIdentifier, ArchiveType};
+use rattler_networking::AuthenticatedClient;
+use rstest::rstest;
+
+#[rstest]
+#[case::tar_bz2(ArchiveType::TarBz2)]
+#[case::conda(ArchiveType::Conda)]
+#[tokio::test]
+async fn test_fetch_and_link_built_package(#[case] archive_type: ArchiveType) {
+    let channel_dir = tempfile::tempdir().unwrap();
+    let package = PackageBuilder::new("foo", "1.0")
+        .with_build("h1234_1", 1)
+        .with_dependency("bar >=1.0")
+        .with_file("bin/foo", "#!/bin/sh\necho foo\n")
+        .with_file("share/foo/data.txt", "some data");
+    package.write(channel_dir.path(), archive_type);
+    let server_url = serve_directory(channel_dir.path());
+
+    // Fetch the package into the cache
+    let file_name = package.file_name(archive_type);
+    let cache_dir = tempfile::tempdir().unwrap();
+    let package_dir = PackageCache::new(cache_dir.path())
+        .get_or_fetch_from_url(
+            ArchiveIdentifier::try_from_filename(&file_name).unwrap(),
+            server_url.join(&file_name).unwrap(),
+            AuthenticatedClient::default(),
+        )
+        .await
+        .unwrap();
+
+    let (index_json, paths_json) = validate_package_directory(&package_dir).unwrap();
+    assert_eq!(&index_json, package.index_json());
+    assert_eq!(paths_json.paths.len(), 2);
+
+    // Link the package into a prefix
+    let prefix = tempfile::tempdir().unwrap();
+    let paths = link_package(
+        &package_dir,
+        prefix.path(),
+        &InstallDriver::default(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(paths.len(), 2);
+    assert_eq!(
+        std::fs::read_to_string(prefix.path().join("share/foo/data.txt")).unwrap(),
+        "some data"
+    );
+}
@@ -0,0 +1,16 @@
+//! Parses arbitrary strings as match specs. Specs are read from environment files and command
+//! lines so parsing them must never panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rattler_conda_types::{MatchSpec, NamelessMatchSpec};
+use std::str::FromStr;
+
+fuzz_target!(|input: &str| {
+    if let Ok(spec) = MatchSpec::from_str(input) {
+        let _ = spec.to_string();
+    }
+    if let Ok(spec) = NamelessMatchSpec::from_str(input) {
+        let _ = spec.to_string();
+    }
+});
@@ -0,0 +1,12 @@
+//! Parses arbitrary bytes as a single record of a `repodata.json` file.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rattler_conda_types::PackageRecord;
+
+fuzz_target!(|input: &[u8]| {
+    if let Ok(record) = serde_json::from_slice::<PackageRecord>(input) {
+        let _ = record.to_string();
+        let _ = serde_json::to_string(&record);
+    }
+});
@@ -0,0 +1,14 @@
+//! Parses arbitrary bytes as a `repodata.json` file and converts it into records. Repodata is
+//! downloaded from channels that cannot be trusted, so this must never panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rattler_conda_types::{Channel, ChannelConfig, RepoData};
+
+fuzz_target!(|input: &[u8]| {
+    if let Ok(repo_data) = serde_json::from_slice::<RepoData>(input) {
+        let channel = Channel::from_str("conda-forge", &ChannelConfig::default())
+            .expect("conda-forge is a valid channel");
+        let _ = repo_data.into_repo_data_records(&channel);
+    }
+});
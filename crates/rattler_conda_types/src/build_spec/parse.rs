@@ -37,20 +37,16 @@ impl BuildNumberSpec {
             _ => unreachable!(),
         };
 
-        let (rest, build_num) = digit1(input)
-            .map(|(rest, digits): (&str, &str)| {
-                (
-                    rest,
-                    digits
-                        .parse::<BuildNumber>()
-                        .expect("nom found at least 1 digit(s)"),
-                )
+        // The digits might not fit in a build number, which is reported as an invalid build number
+        // as well.
+        let (rest, build_num) = digit1::<_, nom::error::Error<&str>>(input)
+            .ok()
+            .and_then(|(rest, digits): (&str, &str)| {
+                Some((rest, digits.parse::<BuildNumber>().ok()?))
             })
-            .map_err(|_: nom::Err<nom::error::Error<&str>>| {
-                nom::Err::Error(ParseBuildNumberSpecError::InvalidBuildNumber(
-                    ParseBuildNumberError,
-                ))
-            })?;
+            .ok_or(nom::Err::Error(
+                ParseBuildNumberSpecError::InvalidBuildNumber(ParseBuildNumberError),
+            ))?;
 
         match op {
             Some(op) => Ok((rest, BuildNumberSpec::new(op, build_num))),
@@ -192,5 +188,9 @@ mod test {
             BuildNumberSpec::parser(">=build3").finish(),
             Err(_)
         ));
+        assert!(matches!(
+            BuildNumberSpec::parser(">=99999999999999999999999").finish(),
+            Err(_)
+        ));
     }
 }
@@ -26,6 +26,37 @@ pub struct ChannelConfig {
     ///
     /// The default value is: <https://conda.anaconda.org>
     pub channel_alias: Url,
+
+    /// The channels that the [`DEFAULTS_CHANNEL`] keyword refers to. See
+    /// [`ChannelConfig::resolve_channels`].
+    ///
+    /// The default value are the `main` and `r` channels of <https://repo.anaconda.com/pkgs> (and
+    /// `msys2` on Windows), like conda.
+    #[serde(default = "default_channels")]
+    pub default_channels: Vec<String>,
+
+    /// Channels that must never be used, not even when they are part of the
+    /// [`Self::default_channels`]. Channels are matched by their base url.
+    #[serde(default)]
+    pub denied_channels: Vec<String>,
+}
+
+/// The keyword that refers to the [`ChannelConfig::default_channels`].
+pub const DEFAULTS_CHANNEL: &str = "defaults";
+
+/// The keyword that excludes the [`ChannelConfig::default_channels`], even if they are requested
+/// with [`DEFAULTS_CHANNEL`]. This is commonly used in `environment.yml` files.
+pub const NODEFAULTS_CHANNEL: &str = "nodefaults";
+
+fn default_channels() -> Vec<String> {
+    let mut channels = vec![
+        String::from("https://repo.anaconda.com/pkgs/main"),
+        String::from("https://repo.anaconda.com/pkgs/r"),
+    ];
+    if cfg!(windows) {
+        channels.push(String::from("https://repo.anaconda.com/pkgs/msys2"));
+    }
+    channels
 }
 
 impl Default for ChannelConfig {
@@ -33,10 +64,83 @@ impl Default for ChannelConfig {
         ChannelConfig {
             channel_alias: Url::from_str("https://conda.anaconda.org")
                 .expect("could not parse default channel alias"),
+            default_channels: default_channels(),
+            denied_channels: Vec::new(),
         }
     }
 }
 
+/// An error that can occur when resolving channels with [`ChannelConfig::resolve_channels`].
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum ResolveChannelsError {
+    /// A channel could not be parsed.
+    #[error("invalid channel '{0}'")]
+    ParseChannelError(String, #[source] ParseChannelError),
+
+    /// A channel that was requested is on the deny list.
+    #[error("the channel '{0}' is not allowed to be used")]
+    DeniedChannel(String),
+}
+
+impl ChannelConfig {
+    /// Resolves the channels as specified by a user, e.g. the `channels` of an `environment.yml`
+    /// file, in order of priority.
+    ///
+    /// The [`DEFAULTS_CHANNEL`] keyword is replaced by the [`Self::default_channels`] and
+    /// [`NODEFAULTS_CHANNEL`] removes them again. Default channels that are on the deny list are
+    /// skipped. Requesting any other channel on the deny list is an error. Duplicate channels are
+    /// only returned once.
+    pub fn resolve_channels(
+        &self,
+        channels: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Vec<Channel>, ResolveChannelsError> {
+        let channels = channels
+            .into_iter()
+            .map(|channel| channel.as_ref().trim().to_owned())
+            .collect::<Vec<_>>();
+        let no_defaults = channels.iter().any(|c| c == NODEFAULTS_CHANNEL);
+
+        let parse = |channel: &str| {
+            Channel::from_str(channel, self)
+                .map_err(|e| ResolveChannelsError::ParseChannelError(channel.to_owned(), e))
+        };
+
+        let mut result: Vec<Channel> = Vec::new();
+        for name in &channels {
+            if name == NODEFAULTS_CHANNEL {
+                continue;
+            } else if name == DEFAULTS_CHANNEL {
+                if no_defaults {
+                    continue;
+                }
+                for default_channel in &self.default_channels {
+                    let channel = parse(default_channel)?;
+                    if !self.is_denied(&channel) && !result.contains(&channel) {
+                        result.push(channel);
+                    }
+                }
+            } else {
+                let channel = parse(name)?;
+                if self.is_denied(&channel) {
+                    return Err(ResolveChannelsError::DeniedChannel(name.clone()));
+                }
+                if !result.contains(&channel) {
+                    result.push(channel);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Returns true if the channel is on the deny list, see [`Self::denied_channels`].
+    pub fn is_denied(&self, channel: &Channel) -> bool {
+        self.denied_channels.iter().any(|denied| {
+            Channel::from_str(denied, self).is_ok_and(|denied| denied.base_url == channel.base_url)
+        })
+    }
+}
+
 /// `Channel`s are the primary source of package information.
 #[derive(Debug, Clone, Serialize, Eq, PartialEq, Hash)]
 pub struct Channel {
@@ -165,6 +269,24 @@ impl Channel {
         &self.base_url
     }
 
+    /// Returns the url of the given subdirectory of the channel. The name of the subdirectory
+    /// usually comes from channel data that cannot be trusted, if it is not a valid relative url
+    /// it is percent-encoded instead.
+    pub fn subdir_url(&self, subdir: &str) -> Url {
+        if subdir.is_empty() {
+            return self.base_url.clone();
+        }
+        self.base_url
+            .join(&format!("{subdir}/"))
+            .unwrap_or_else(|_| {
+                let mut url = self.base_url.clone();
+                if let Ok(mut segments) = url.path_segments_mut() {
+                    segments.pop_if_empty().push(subdir).push("");
+                }
+                url
+            })
+    }
+
     /// Returns the Urls for the given platform
     pub fn platform_url(&self, platform: Platform) -> Url {
         self.base_url()
@@ -190,6 +312,26 @@ impl Channel {
         }
     }
 
+    /// Returns the subdirectories of the channel to fetch when solving for the given platform.
+    ///
+    /// By default these are the subdirectory of the platform and the `noarch` subdirectory. If the
+    /// platforms of the channel are restricted explicitly (e.g. `conda-forge[linux-64]`) only the
+    /// subdirectories that are also part of the restriction are returned.
+    pub fn subdirs_for_platform(&self, platform: Platform) -> Vec<Platform> {
+        let subdirs = if platform == Platform::NoArch {
+            vec![Platform::NoArch]
+        } else {
+            vec![platform, Platform::NoArch]
+        };
+        match &self.platforms {
+            Some(platforms) => subdirs
+                .into_iter()
+                .filter(|subdir| platforms.contains(subdir))
+                .collect(),
+            None => subdirs,
+        }
+    }
+
     /// Returns the canonical name of the channel
     pub fn canonical_name(&self) -> String {
         self.base_url.to_string()
@@ -282,9 +424,10 @@ fn parse_scheme(channel: &str) -> Option<&str> {
     }
 }
 
-/// Returns true if the specified string is considered to be a path
+/// Returns true if the specified string is considered to be a path. Only the start of the string
+/// is considered so names that contain slashes (e.g. `conda-forge/label/rust_dev`) are not paths.
 fn is_path(path: &str) -> bool {
-    lazy_regex::regex!(r"(\./|\.\.|~|/|[a-zA-Z]:[/\\]|\\\\|//)").is_match(path)
+    lazy_regex::regex!(r"^(\./|\.\.|~|/|[a-zA-Z]:[/\\]|\\\\|//)").is_match(path)
 }
 
 /// Normalizes a file path by eliminating `..` and `.`.
@@ -328,7 +471,7 @@ fn absolute_path(path: &Path) -> Cow<'_, Path> {
 
 #[cfg(test)]
 mod tests {
-    use crate::channel::{absolute_path, normalize_path, parse_platforms};
+    use crate::channel::{absolute_path, normalize_path, parse_platforms, ResolveChannelsError};
     use crate::{ParseChannelError, ParsePlatformError};
     use smallvec::smallvec;
     use std::path::{Path, PathBuf};
@@ -431,6 +574,50 @@ mod tests {
         assert_eq!(channel, Channel::from_name("conda-forge/", None, &config));
     }
 
+    #[test]
+    fn parse_label_by_name() {
+        let config = ChannelConfig::default();
+
+        let channel = Channel::from_str("conda-forge/label/rust_dev", &config).unwrap();
+        assert_eq!(
+            channel.base_url,
+            Url::from_str("https://conda.anaconda.org/conda-forge/label/rust_dev/").unwrap()
+        );
+        assert_eq!(channel.name.as_deref(), Some("conda-forge/label/rust_dev"));
+        assert_eq!(channel.platforms, None);
+        assert_eq!(
+            channel.platform_url(Platform::Linux64).to_string(),
+            "https://conda.anaconda.org/conda-forge/label/rust_dev/linux-64/"
+        );
+
+        // Labels can be nested and combined with platforms
+        let channel =
+            Channel::from_str("conda-forge/label/rust_dev/nightly[noarch]", &config).unwrap();
+        assert_eq!(
+            channel.base_url,
+            Url::from_str("https://conda.anaconda.org/conda-forge/label/rust_dev/nightly/")
+                .unwrap()
+        );
+        assert_eq!(
+            channel.name.as_deref(),
+            Some("conda-forge/label/rust_dev/nightly")
+        );
+        assert_eq!(channel.platforms, Some(smallvec![Platform::NoArch]));
+        assert_eq!(
+            channel.platform_url(Platform::NoArch).to_string(),
+            "https://conda.anaconda.org/conda-forge/label/rust_dev/nightly/noarch/"
+        );
+
+        assert_eq!(
+            Channel::from_str(
+                "https://conda.anaconda.org/conda-forge/label/rust_dev",
+                &config
+            )
+            .unwrap(),
+            Channel::from_str("conda-forge/label/rust_dev", &config).unwrap()
+        );
+    }
+
     #[test]
     fn parse_from_url() {
         let config = ChannelConfig::default();
@@ -525,4 +712,75 @@ mod tests {
         assert_eq!(channel.name.as_deref(), Some("pkgs/main"));
         assert_eq!(channel.platforms, Some(smallvec![platform]));
     }
+
+    #[test]
+    fn test_subdirs_for_platform() {
+        let config = ChannelConfig::default();
+
+        let channel = Channel::from_str("conda-forge", &config).unwrap();
+        assert_eq!(
+            channel.subdirs_for_platform(Platform::Linux64),
+            [Platform::Linux64, Platform::NoArch]
+        );
+        assert_eq!(
+            channel.subdirs_for_platform(Platform::NoArch),
+            [Platform::NoArch]
+        );
+
+        let channel = Channel::from_str("conda-forge[linux-64]", &config).unwrap();
+        assert_eq!(
+            channel.subdirs_for_platform(Platform::Linux64),
+            [Platform::Linux64]
+        );
+        assert!(channel.subdirs_for_platform(Platform::Win64).is_empty());
+
+        let channel = Channel::from_str("conda-forge[linux-64,noarch]", &config).unwrap();
+        assert_eq!(
+            channel.subdirs_for_platform(Platform::Win64),
+            [Platform::NoArch]
+        );
+    }
+
+    #[test]
+    fn test_resolve_channels() {
+        let config = ChannelConfig {
+            denied_channels: vec![String::from("https://repo.anaconda.com/pkgs/r")],
+            ..ChannelConfig::default()
+        };
+        let base_urls = |channels: Vec<Channel>| {
+            channels
+                .into_iter()
+                .map(|c| c.base_url.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // `defaults` expands to the default channels without the denied ones.
+        let channels = config
+            .resolve_channels(["conda-forge", "defaults", "conda-forge"])
+            .unwrap();
+        assert_eq!(
+            base_urls(channels)[..2],
+            [
+                "https://conda.anaconda.org/conda-forge/",
+                "https://repo.anaconda.com/pkgs/main/"
+            ]
+        );
+
+        // `nodefaults` removes the default channels.
+        let channels = config
+            .resolve_channels(["defaults", "conda-forge", "nodefaults"])
+            .unwrap();
+        assert_eq!(
+            base_urls(channels),
+            ["https://conda.anaconda.org/conda-forge/"]
+        );
+
+        // Explicitly requesting a denied channel is an error.
+        assert_eq!(
+            config.resolve_channels(["https://repo.anaconda.com/pkgs/r/"]),
+            Err(ResolveChannelsError::DeniedChannel(String::from(
+                "https://repo.anaconda.com/pkgs/r/"
+            )))
+        );
+    }
 }
@@ -10,7 +10,7 @@
 //!
 use crate::{
     utils::serde::{LossyUrl, VecSkipNone},
-    Version,
+    Timestamp, Version,
 };
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none, DisplayFromStr, OneOrMany, Same};
@@ -115,7 +115,7 @@ pub struct ChannelDataPackage {
     pub text_prefix: bool,
 
     /// Last update time
-    pub timestamp: Option<u64>,
+    pub timestamp: Option<Timestamp>,
 
     /// Latest version
     #[serde_as(as = "Option<DisplayFromStr>")]
@@ -0,0 +1,36 @@
+//! Defines the [`Clock`] trait, which determines what "now" is for the parts of rattler that
+//! depend on the current time.
+//!
+//! Reading the system time ad hoc makes the results of solves, staleness checks and SBOMs change
+//! from one run to the next. Everything that depends on the current time takes a [`Clock`]
+//! instead, which defaults to the [`SystemClock`]. Use a [`FixedClock`] to pin "now" to a specific
+//! moment, e.g. to reproduce a solve in CI.
+
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A [`Clock`] that reads the time of the system. This is the default clock.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A [`Clock`] that always returns the same moment.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
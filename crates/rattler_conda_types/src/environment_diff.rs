@@ -0,0 +1,163 @@
+//! Defines [`EnvironmentDiff`] which describes the differences between two environments.
+
+use crate::{PackageRecord, PrefixRecord};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The differences between the packages of two environments. Packages are identified by their
+/// name. See [`EnvironmentDiff::from_records`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvironmentDiff {
+    /// Packages that are only present in the new environment, sorted by name.
+    pub added: Vec<PackageRecord>,
+
+    /// Packages that are only present in the old environment, sorted by name.
+    pub removed: Vec<PackageRecord>,
+
+    /// Packages that are present in both environments but with a different version or build,
+    /// sorted by name.
+    pub changed: Vec<PackageChange>,
+}
+
+/// A package that is present in both environments but with a different version or build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageChange {
+    /// The record of the package in the old environment.
+    pub old: PackageRecord,
+
+    /// The record of the package in the new environment.
+    pub new: PackageRecord,
+}
+
+/// Describes how the version of a package changed between two environments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageChangeKind {
+    /// The new version is higher than the old version.
+    Upgrade,
+
+    /// The new version is lower than the old version.
+    Downgrade,
+
+    /// The version is the same but the build differs.
+    Rebuild,
+}
+
+impl PackageChange {
+    /// Returns how the version of the package changed.
+    pub fn kind(&self) -> PackageChangeKind {
+        match self.new.version.version().cmp(self.old.version.version()) {
+            Ordering::Greater => PackageChangeKind::Upgrade,
+            Ordering::Less => PackageChangeKind::Downgrade,
+            Ordering::Equal => PackageChangeKind::Rebuild,
+        }
+    }
+}
+
+impl EnvironmentDiff {
+    /// Computes the differences between the packages in the `old` environment and the packages in
+    /// the `new` environment.
+    pub fn from_records(
+        old: impl IntoIterator<Item = impl AsRef<PackageRecord>>,
+        new: impl IntoIterator<Item = impl AsRef<PackageRecord>>,
+    ) -> Self {
+        let by_name = |records: Vec<PackageRecord>| {
+            records
+                .into_iter()
+                .map(|record| (record.name.as_normalized().to_owned(), record))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut old = by_name(old.into_iter().map(|r| r.as_ref().clone()).collect());
+        let new = by_name(new.into_iter().map(|r| r.as_ref().clone()).collect());
+
+        let mut diff = Self::default();
+        for (name, new) in new {
+            match old.remove(&name) {
+                None => diff.added.push(new),
+                Some(old) => {
+                    if old.version.version() != new.version.version() || old.build != new.build {
+                        diff.changed.push(PackageChange { old, new });
+                    }
+                }
+            }
+        }
+        diff.removed = old.into_values().collect();
+
+        diff
+    }
+
+    /// Computes the differences between the packages installed in the `old` prefix and the
+    /// packages installed in the `new` prefix.
+    pub fn from_prefixes(old: &Path, new: &Path) -> Result<Self, std::io::Error> {
+        Ok(Self::from_records(
+            PrefixRecord::collect_from_prefix(old)?,
+            PrefixRecord::collect_from_prefix(new)?,
+        ))
+    }
+
+    /// Returns true if both environments contain the same packages.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EnvironmentDiff, PackageChangeKind};
+    use crate::test_utils::RecordBuilder;
+
+    #[test]
+    fn test_environment_diff() {
+        let old = [
+            RecordBuilder::new("python", "3.10.0")
+                .with_build("h1_0", 0)
+                .build(),
+            RecordBuilder::new("numpy", "1.24.0")
+                .with_build("py310_0", 0)
+                .build(),
+            RecordBuilder::new("libzlib", "1.2.13")
+                .with_build("h1_0", 0)
+                .build(),
+            RecordBuilder::new("tzdata", "2023c")
+                .with_build("h0_0", 0)
+                .build(),
+            RecordBuilder::new("bzip2", "1.0.8")
+                .with_build("h1_0", 0)
+                .build(),
+        ];
+        let new = [
+            RecordBuilder::new("python", "3.11.0")
+                .with_build("h1_0", 0)
+                .build(),
+            RecordBuilder::new("numpy", "1.24.0")
+                .with_build("py311_0", 0)
+                .build(),
+            RecordBuilder::new("libzlib", "1.2.12")
+                .with_build("h1_0", 0)
+                .build(),
+            RecordBuilder::new("tzdata", "2023c")
+                .with_build("h0_0", 0)
+                .build(),
+            RecordBuilder::new("pip", "23.0")
+                .with_build("pyh0_0", 0)
+                .build(),
+        ];
+
+        let diff = EnvironmentDiff::from_records(&old, &new);
+        assert_eq!(diff.added, vec![new[4].package_record.clone()]);
+        assert_eq!(diff.removed, vec![old[4].package_record.clone()]);
+        assert_eq!(
+            diff.changed
+                .iter()
+                .map(|change| (change.new.name.as_normalized(), change.kind()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("libzlib", PackageChangeKind::Downgrade),
+                ("numpy", PackageChangeKind::Rebuild),
+                ("python", PackageChangeKind::Upgrade),
+            ]
+        );
+
+        assert!(EnvironmentDiff::from_records(&old, &old).is_empty());
+    }
+}
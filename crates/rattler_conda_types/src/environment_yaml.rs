@@ -0,0 +1,195 @@
+//! An `environment.yml` file describes the packages that make up a Conda environment. It is the
+//! format produced by `conda env export` and consumed by `conda env create`.
+//!
+//! Next to conda packages an environment file can also contain a `pip` section that lists the
+//! Python distributions that should be installed with pip.
+//!
+//! ```yaml
+//! name: my-env
+//! channels:
+//!   - conda-forge
+//! dependencies:
+//!   - python 3.11.*
+//!   - pip
+//!   - pip:
+//!     - requests==2.31.0
+//! ```
+
+use crate::{MatchSpec, ParseMatchSpecError};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::Read, path::Path, str::FromStr};
+
+/// A representation of an `environment.yml` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvironmentYaml {
+    /// The name of the environment
+    pub name: Option<String>,
+
+    /// The channels that are used to resolve the conda dependencies, in order of priority. This
+    /// can contain the `defaults` and `nodefaults` keywords, use
+    /// [`crate::ChannelConfig::resolve_channels`] to resolve them.
+    pub channels: Vec<String>,
+
+    /// The conda packages that make up the environment.
+    pub dependencies: Vec<MatchSpec>,
+
+    /// The requirements of the Python distributions that are installed with pip (e.g.
+    /// `requests==2.31.0`).
+    pub pip_dependencies: Vec<String>,
+}
+
+/// An error that can occur when parsing an [`EnvironmentYaml`].
+#[derive(Debug, thiserror::Error)]
+pub enum ParseEnvironmentYamlError {
+    /// The file is not valid yaml or does not have the expected structure.
+    #[error(transparent)]
+    ParseError(#[from] serde_yaml::Error),
+
+    /// One of the conda dependencies is not a valid match spec.
+    #[error("invalid dependency '{0}'")]
+    InvalidMatchSpec(String, #[source] ParseMatchSpecError),
+
+    /// An IO error occurred
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// The raw yaml structure of an `environment.yml` file.
+#[derive(Serialize, Deserialize)]
+struct RawEnvironmentYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    channels: Vec<String>,
+
+    #[serde(default)]
+    dependencies: Vec<RawDependency>,
+}
+
+/// An entry in the `dependencies` section is either a conda match spec or a `pip` section.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Conda(String),
+    Pip { pip: Vec<String> },
+}
+
+impl EnvironmentYaml {
+    /// Parses an `environment.yml` file from a reader.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, ParseEnvironmentYamlError> {
+        let mut str = String::new();
+        reader.read_to_string(&mut str)?;
+        Self::from_str(&str)
+    }
+
+    /// Parses an `environment.yml` file from a file.
+    pub fn from_path(path: &Path) -> Result<Self, ParseEnvironmentYamlError> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Converts this instance to the contents of an `environment.yml` file.
+    pub fn to_yaml_string(&self) -> String {
+        let mut dependencies = self
+            .dependencies
+            .iter()
+            .map(|spec| RawDependency::Conda(spec.to_string()))
+            .collect::<Vec<_>>();
+        if !self.pip_dependencies.is_empty() {
+            dependencies.push(RawDependency::Pip {
+                pip: self.pip_dependencies.clone(),
+            });
+        }
+
+        serde_yaml::to_string(&RawEnvironmentYaml {
+            name: self.name.clone(),
+            channels: self.channels.clone(),
+            dependencies,
+        })
+        .expect("serializing an environment.yml cannot fail")
+    }
+
+    /// Writes the contents of this instance to the file at the specified location.
+    pub fn to_path(&self, path: &Path) -> Result<(), std::io::Error> {
+        std::fs::write(path, self.to_yaml_string())
+    }
+}
+
+impl FromStr for EnvironmentYaml {
+    type Err = ParseEnvironmentYamlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw: RawEnvironmentYaml = serde_yaml::from_str(s)?;
+
+        let mut dependencies = Vec::new();
+        let mut pip_dependencies = Vec::new();
+        for dependency in raw.dependencies {
+            match dependency {
+                RawDependency::Conda(spec) => dependencies.push(
+                    MatchSpec::from_str(&spec)
+                        .map_err(|e| ParseEnvironmentYamlError::InvalidMatchSpec(spec, e))?,
+                ),
+                RawDependency::Pip { pip } => pip_dependencies.extend(pip),
+            }
+        }
+
+        Ok(Self {
+            name: raw.name,
+            channels: raw.channels,
+            dependencies,
+            pip_dependencies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EnvironmentYaml, ParseEnvironmentYamlError};
+    use std::str::FromStr;
+
+    const ENVIRONMENT_YAML: &str = r#"name: my-env
+channels:
+  - conda-forge
+dependencies:
+  - python 3.11.*
+  - pip
+  - pip:
+      - requests==2.31.0
+      - rich>=13
+"#;
+
+    #[test]
+    fn test_parse() {
+        let environment = EnvironmentYaml::from_str(ENVIRONMENT_YAML).unwrap();
+        assert_eq!(environment.name.as_deref(), Some("my-env"));
+        assert_eq!(environment.channels, vec!["conda-forge"]);
+        assert_eq!(
+            environment
+                .dependencies
+                .iter()
+                .map(|spec| spec.name.as_ref().unwrap().as_normalized())
+                .collect::<Vec<_>>(),
+            vec!["python", "pip"]
+        );
+        assert_eq!(
+            environment.pip_dependencies,
+            vec!["requests==2.31.0", "rich>=13"]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let environment = EnvironmentYaml::from_str(ENVIRONMENT_YAML).unwrap();
+        let yaml = environment.to_yaml_string();
+        insta::assert_snapshot!(yaml);
+        assert_eq!(EnvironmentYaml::from_str(&yaml).unwrap(), environment);
+    }
+
+    #[test]
+    fn test_invalid_dependency() {
+        assert!(matches!(
+            EnvironmentYaml::from_str("dependencies:\n  - python >=>3\n"),
+            Err(ParseEnvironmentYamlError::InvalidMatchSpec(..))
+        ));
+    }
+}
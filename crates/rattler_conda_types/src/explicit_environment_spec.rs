@@ -7,11 +7,18 @@
 //! but instead directly refer to the download location of the package. This makes them useful
 //! to quickly install an environment.
 //!
-//! To create an explicit environment file, you can use the `conda env export` command.
+//! To create an explicit environment file, you can use the `conda list --explicit` command or
+//! format an [`ExplicitEnvironmentSpec`] with [`ExplicitEnvironmentSpec::from_records`].
 
-use crate::{ParsePlatformError, Platform};
+use crate::{package::NonCondaArchiveType, ParsePlatformError, Platform, RepoDataRecord};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Read, path::Path, str::FromStr};
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    io::Read,
+    path::Path,
+    str::FromStr,
+};
 use url::Url;
 
 /// An [`ExplicitEnvironmentSpec`] represents an explicit environment specification. Packages are
@@ -67,9 +74,14 @@ impl FromStr for PackageArchiveHash {
     type Err = ParsePackageArchiveHashError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        return if let Some(sha) = s.strip_prefix("sha256:") {
+        return if let Some(sha) = s
+            .strip_prefix("sha256:")
+            .or_else(|| s.strip_prefix("sha256="))
+        {
             // If the string starts with sha256 we parse as Sha256
             parse_sha256(sha)
+        } else if let Some(md5) = s.strip_prefix("md5:").or_else(|| s.strip_prefix("md5=")) {
+            parse_md5(md5)
         } else if s.len() == 64 {
             // If the string is 64 characters is length we parse as Sha256
             parse_sha256(s)
@@ -95,8 +107,54 @@ impl FromStr for PackageArchiveHash {
     }
 }
 
+impl PackageArchiveHash {
+    /// Returns the SHA256 hash if this is a SHA256 hash.
+    pub fn sha256(&self) -> Option<&rattler_digest::Sha256Hash> {
+        match self {
+            PackageArchiveHash::Sha256(hash) => Some(hash),
+            PackageArchiveHash::Md5(_) => None,
+        }
+    }
+
+    /// Returns the MD5 hash if this is an MD5 hash.
+    pub fn md5(&self) -> Option<&rattler_digest::Md5Hash> {
+        match self {
+            PackageArchiveHash::Md5(hash) => Some(hash),
+            PackageArchiveHash::Sha256(_) => None,
+        }
+    }
+}
+
+impl Display for PackageArchiveHash {
+    /// Formats the hash the way it is written as the anchor of a url in an explicit environment
+    /// file. SHA256 hashes are prefixed with `sha256:`, MD5 hashes are written as is.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageArchiveHash::Md5(hash) => write!(f, "{hash:x}"),
+            PackageArchiveHash::Sha256(hash) => write!(f, "sha256:{hash:x}"),
+        }
+    }
+}
+
 impl ExplicitEnvironmentEntry {
+    /// Constructs an entry for the package described by the record. The url is anchored with the
+    /// SHA256 hash of the package archive, or with the MD5 hash if the SHA256 hash is unknown.
+    pub fn from_record(record: &RepoDataRecord) -> Self {
+        let hash = match (record.package_record.sha256, record.package_record.md5) {
+            (Some(sha256), _) => Some(PackageArchiveHash::Sha256(sha256)),
+            (None, Some(md5)) => Some(PackageArchiveHash::Md5(md5)),
+            (None, None) => None,
+        };
+        let mut url = record.url.clone();
+        url.set_fragment(hash.map(|hash| hash.to_string()).as_deref());
+        Self { url }
+    }
+
     /// If the url contains a hash section, that hash refers to the hash of the package archive.
+    ///
+    /// The hash can be written as `<md5>`, `<sha256>`, `md5:<md5>`, `md5=<md5>`,
+    /// `sha256:<sha256>` or `sha256=<sha256>`. Entries that were parsed as part of an
+    /// [`ExplicitEnvironmentSpec`] are guaranteed to contain a valid hash if they contain one.
     pub fn package_archive_hash(
         &self,
     ) -> Result<Option<PackageArchiveHash>, ParsePackageArchiveHashError> {
@@ -129,6 +187,14 @@ pub enum ParseExplicitEnvironmentSpecError {
     #[error("failed to parse url '{0}'")]
     InvalidUrl(String, #[source] url::ParseError),
 
+    /// The hash anchor of a url could not be parsed
+    #[error("invalid hash anchor in '{0}'")]
+    InvalidPackageArchiveHash(String, #[source] ParsePackageArchiveHashError),
+
+    /// The url refers to an archive that is not a conda package (e.g. a Python wheel)
+    #[error("'{0}' refers to {1} which is not a conda package, {}", .1.suggestion())]
+    NotACondaPackage(String, NonCondaArchiveType),
+
     /// The platform string could not be parsed
     #[error(transparent)]
     InvalidPlatform(#[from] ParsePlatformError),
@@ -138,7 +204,91 @@ pub enum ParseExplicitEnvironmentSpecError {
     IoError(#[from] std::io::Error),
 }
 
+/// An error that is returned by [`ExplicitEnvironmentSpec::check_platform`] if an explicit
+/// environment cannot be installed on a platform.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ExplicitEnvironmentPlatformError {
+    /// The platform in the header of the file does not match.
+    #[error("the environment was created for {found} and cannot be installed on {target}")]
+    EnvironmentPlatform {
+        /// The platform in the header of the file
+        found: Platform,
+        /// The platform the environment is installed on
+        target: Platform,
+    },
+
+    /// The subdirectory of a package does not match.
+    #[error("'{url}' is a package for {found} and cannot be installed on {target}")]
+    PackagePlatform {
+        /// The url of the package
+        url: Url,
+        /// The platform of the subdirectory of the package
+        found: Platform,
+        /// The platform the environment is installed on
+        target: Platform,
+    },
+}
+
+impl ExplicitEnvironmentEntry {
+    /// Returns the platform of the subdirectory the package is stored in, e.g. `linux-64` for
+    /// `https://conda.anaconda.org/conda-forge/linux-64/zlib-1.2.13-hd590300_5.conda`. Returns
+    /// `None` if the url does not follow the layout of a channel.
+    pub fn platform(&self) -> Option<Platform> {
+        let mut segments = self.url.path_segments()?;
+        segments.next_back()?;
+        Platform::from_str(segments.next_back()?).ok()
+    }
+}
+
 impl ExplicitEnvironmentSpec {
+    /// Checks that the environment can be installed on the `target` platform, e.g.
+    /// [`Platform::current`]. Both the platform in the header of the file and the subdirectory of
+    /// every package are checked, `noarch` packages can be installed on any platform.
+    ///
+    /// Installing an environment for another platform fails with errors that are hard to
+    /// understand, so this should be checked before anything is installed. Callers that know what
+    /// they are doing (e.g. when installing into an emulated environment) can skip the check.
+    pub fn check_platform(&self, target: Platform) -> Result<(), ExplicitEnvironmentPlatformError> {
+        if let Some(found) = self.platform {
+            if found != target {
+                return Err(ExplicitEnvironmentPlatformError::EnvironmentPlatform {
+                    found,
+                    target,
+                });
+            }
+        }
+
+        for package in &self.packages {
+            match package.platform() {
+                Some(found) if found != target && found != Platform::NoArch => {
+                    return Err(ExplicitEnvironmentPlatformError::PackagePlatform {
+                        url: package.url.clone(),
+                        found,
+                        target,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Constructs an explicit environment from the records of its packages. The records must
+    /// already be in installation order, see [`crate::PackageRecord::sort_topologically`].
+    pub fn from_records<'a>(
+        records: impl IntoIterator<Item = &'a RepoDataRecord>,
+        platform: Option<Platform>,
+    ) -> Self {
+        Self {
+            platform,
+            packages: records
+                .into_iter()
+                .map(ExplicitEnvironmentEntry::from_record)
+                .collect(),
+        }
+    }
+
     /// Parses an explicit environment file from a reader.
     pub fn from_reader(mut reader: impl Read) -> Result<Self, ParseExplicitEnvironmentSpecError> {
         let mut str = String::new();
@@ -160,6 +310,13 @@ impl FromStr for ExplicitEnvironmentSpec {
         let mut is_explicit = false;
         let mut packages = Vec::new();
         for line in s.lines() {
+            // Ignore surrounding whitespace (including the `\r` of CRLF line endings) and blank
+            // lines.
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
             // Skip lines starting with a #
             if let Some(comment_line) = line.strip_prefix('#') {
                 // Unless that comment line is `# platform: `. Because then we're interested in the
@@ -167,19 +324,40 @@ impl FromStr for ExplicitEnvironmentSpec {
                 if let Some(platform_str) = comment_line.trim_start().strip_prefix("platform:") {
                     platform = Some(Platform::from_str(platform_str.trim())?);
                 }
-            } else if line.trim() == "@EXPLICIT" {
+            } else if line == "@EXPLICIT" {
                 is_explicit = true
             } else if !is_explicit {
                 return Err(ParseExplicitEnvironmentSpecError::MissingExplicitTag);
             } else {
+                // The url may be followed by a comment.
+                let url_str = match line.split_once(char::is_whitespace) {
+                    Some((url, rest)) if rest.trim_start().starts_with('#') => url,
+                    _ => line,
+                };
+
                 // Parse the line as an explicit URL
-                packages.push(
-                    Url::parse(line.trim())
-                        .map_err(|e| {
-                            ParseExplicitEnvironmentSpecError::InvalidUrl(line.trim().to_owned(), e)
-                        })?
-                        .into(),
-                );
+                let url = Url::parse(url_str).map_err(|e| {
+                    ParseExplicitEnvironmentSpecError::InvalidUrl(url_str.to_owned(), e)
+                })?;
+
+                // Reject archives that are commonly mistaken for conda packages.
+                if let Some(archive_type) = NonCondaArchiveType::try_from(url.path()) {
+                    return Err(ParseExplicitEnvironmentSpecError::NotACondaPackage(
+                        url_str.to_owned(),
+                        archive_type,
+                    ));
+                }
+
+                // Make sure the hash anchor is valid so it can be used to verify the download.
+                let entry = ExplicitEnvironmentEntry::from(url);
+                entry.package_archive_hash().map_err(|e| {
+                    ParseExplicitEnvironmentSpecError::InvalidPackageArchiveHash(
+                        url_str.to_owned(),
+                        e,
+                    )
+                })?;
+
+                packages.push(entry);
             }
         }
 
@@ -191,12 +369,31 @@ impl FromStr for ExplicitEnvironmentSpec {
     }
 }
 
+impl Display for ExplicitEnvironmentSpec {
+    /// Formats the environment as an explicit environment file.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(platform) = self.platform {
+            writeln!(f, "# platform: {platform}")?;
+        }
+        writeln!(f, "@EXPLICIT")?;
+        for package in &self.packages {
+            writeln!(f, "{}", package.url)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{ExplicitEnvironmentSpec, ParseExplicitEnvironmentSpecError};
+    use super::{
+        ExplicitEnvironmentPlatformError, ExplicitEnvironmentSpec,
+        ParseExplicitEnvironmentSpecError,
+    };
     use crate::{
         explicit_environment_spec::{PackageArchiveHash, ParsePackageArchiveHashError},
-        get_test_data_dir, ExplicitEnvironmentEntry,
+        get_test_data_dir,
+        package::NonCondaArchiveType,
+        ExplicitEnvironmentEntry, Platform, RepoDataRecord,
     };
     use assert_matches::assert_matches;
     use hex_literal::hex;
@@ -213,6 +410,114 @@ mod test {
         insta::assert_yaml_snapshot!(path, env)
     }
 
+    #[test]
+    fn test_write_from_records() {
+        let mut record: RepoDataRecord = serde_json::from_str(
+            r#"{
+                "name": "zlib",
+                "version": "1.2.13",
+                "build": "hd590300_5",
+                "build_number": 5,
+                "depends": [],
+                "subdir": "linux-64",
+                "md5": "f36c115f1ee199da648e0597ec2047ad",
+                "sha256": "3de4ff6d3e0a45aedbd0e7e8ccaafd5a1e3a63a4e2a36dac9c2de0b8f3a3fd0a",
+                "fn": "zlib-1.2.13-hd590300_5.conda",
+                "url": "https://conda.anaconda.org/conda-forge/linux-64/zlib-1.2.13-hd590300_5.conda",
+                "channel": "https://conda.anaconda.org/conda-forge/"
+            }"#,
+        )
+        .unwrap();
+        let (sha256, md5) = (record.package_record.sha256, record.package_record.md5);
+        let mut env = ExplicitEnvironmentSpec::from_records([&record], Some(Platform::Linux64));
+
+        // Records without a sha256 hash are anchored with their md5 hash.
+        record.package_record.sha256 = None;
+        env.packages
+            .push(ExplicitEnvironmentEntry::from_record(&record));
+        let text = env.to_string();
+        assert_eq!(
+            text,
+            "# platform: linux-64\n\
+             @EXPLICIT\n\
+             https://conda.anaconda.org/conda-forge/linux-64/zlib-1.2.13-hd590300_5.conda#sha256:3de4ff6d3e0a45aedbd0e7e8ccaafd5a1e3a63a4e2a36dac9c2de0b8f3a3fd0a\n\
+             https://conda.anaconda.org/conda-forge/linux-64/zlib-1.2.13-hd590300_5.conda#f36c115f1ee199da648e0597ec2047ad\n"
+        );
+
+        // The written file can be parsed again.
+        let parsed = ExplicitEnvironmentSpec::from_str(&text).unwrap();
+        assert_eq!(parsed.platform, Some(Platform::Linux64));
+        assert_matches!(
+            parsed.packages[0].package_archive_hash(),
+            Ok(Some(PackageArchiveHash::Sha256(hash))) if Some(hash) == sha256
+        );
+        assert_matches!(
+            parsed.packages[1].package_archive_hash(),
+            Ok(Some(PackageArchiveHash::Md5(hash))) if Some(hash) == md5
+        );
+    }
+
+    #[test]
+    fn test_parse_anchors_and_whitespace() {
+        let env = ExplicitEnvironmentSpec::from_str(
+            "# platform: linux-64\r\n\
+             \r\n\
+             @EXPLICIT\r\n\
+             \r\n\
+             \thttps://conda.anaconda.org/conda-forge/linux-64/a-1.0-0.conda#md5=d7c89558ba9fa0495403155b64376d81  \r\n\
+             https://conda.anaconda.org/conda-forge/linux-64/b-1.0-0.conda#sha256=315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3 # a comment\r\n\
+             https://conda.anaconda.org/conda-forge/linux-64/c-1.0-0.conda\r\n",
+        )
+        .unwrap();
+        assert_eq!(env.platform, Some(Platform::Linux64));
+        assert_eq!(env.packages.len(), 3);
+        assert_matches!(
+            env.packages[0].package_archive_hash(),
+            Ok(Some(hash)) if hash.md5().unwrap()[..] == hex!("d7c89558ba9fa0495403155b64376d81")
+        );
+        assert_matches!(
+            env.packages[1].package_archive_hash(),
+            Ok(Some(hash)) if hash.sha256().unwrap()[..] == hex!("315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3")
+        );
+        assert_eq!(
+            env.packages[1].url.path(),
+            "/conda-forge/linux-64/b-1.0-0.conda"
+        );
+        assert_matches!(env.packages[2].package_archive_hash(), Ok(None));
+
+        assert_matches!(
+            ExplicitEnvironmentSpec::from_str(
+                "@EXPLICIT\nhttps://conda.anaconda.org/conda-forge/linux-64/a-1.0-0.conda#md5=nothex"
+            ),
+            Err(ParseExplicitEnvironmentSpecError::InvalidPackageArchiveHash(..))
+        );
+    }
+
+    #[test]
+    fn test_check_platform() {
+        let env = ExplicitEnvironmentSpec::from_str(
+            "@EXPLICIT\n\
+             https://conda.anaconda.org/conda-forge/linux-64/a-1.0-0.conda\n\
+             https://conda.anaconda.org/conda-forge/noarch/b-1.0-0.conda\n",
+        )
+        .unwrap();
+        assert!(env.check_platform(Platform::Linux64).is_ok());
+        assert_matches!(
+            env.check_platform(Platform::OsxArm64),
+            Err(ExplicitEnvironmentPlatformError::PackagePlatform {
+                found: Platform::Linux64,
+                ..
+            })
+        );
+
+        let env = ExplicitEnvironmentSpec::from_str("# platform: win-64\n@EXPLICIT\n").unwrap();
+        let err = env.check_platform(Platform::Linux64).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the environment was created for win-64 and cannot be installed on linux-64"
+        );
+    }
+
     #[test]
     fn test_parse_empty() {
         assert_matches!(
@@ -237,6 +542,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_non_conda_package() {
+        let url = "https://files.pythonhosted.org/packages/py3/r/requests/requests-2.31.0-py3-none-any.whl";
+        let err = ExplicitEnvironmentSpec::from_str(&format!("@EXPLICIT\n{url}")).unwrap_err();
+        assert_matches!(
+            &err,
+            ParseExplicitEnvironmentSpecError::NotACondaPackage(u, NonCondaArchiveType::PythonWheel) if u == url
+        );
+        assert!(err.to_string().contains("install it with pip"));
+
+        assert_matches!(
+            ExplicitEnvironmentSpec::from_str(
+                "@EXPLICIT\nhttps://registry.npmjs.org/left-pad/-/left-pad-1.3.0.tgz"
+            ),
+            Err(ParseExplicitEnvironmentSpecError::NotACondaPackage(
+                _,
+                NonCondaArchiveType::NpmTarball
+            ))
+        );
+    }
+
     #[test]
     fn test_parse_invalid_platform() {
         assert_matches!(
@@ -0,0 +1,152 @@
+//! Defines the [`InstallablePackage`] trait, which describes a package that can be downloaded and
+//! installed into an environment independent of where the description comes from.
+
+use crate::{
+    package::ArchiveIdentifier, ExplicitEnvironmentEntry, NoArchType, PackageName, PackageRecord,
+    RepoDataRecord,
+};
+use rattler_digest::{Md5Hash, Sha256Hash};
+use std::{borrow::Cow, fmt::Debug};
+use url::Url;
+
+/// A package that can be downloaded and installed into an environment, e.g. a [`RepoDataRecord`]
+/// that was selected by a solver or an [`ExplicitEnvironmentEntry`].
+///
+/// Not all information is known for every kind of package. An entry of an explicit environment
+/// only consists of a url and optionally a hash, everything else is read from the package itself
+/// once it has been downloaded.
+pub trait InstallablePackage: Debug + Send + Sync {
+    /// Returns the name of the package, or `None` if it cannot be determined without reading the
+    /// package.
+    fn name(&self) -> Option<PackageName>;
+
+    /// Returns the url from which the archive of the package is downloaded.
+    fn url(&self) -> Cow<'_, Url>;
+
+    /// Returns the file name of the archive of the package.
+    fn file_name(&self) -> Cow<'_, str> {
+        match self.url() {
+            Cow::Borrowed(url) => Cow::Borrowed(file_name_of_url(url)),
+            Cow::Owned(url) => Cow::Owned(file_name_of_url(&url).to_owned()),
+        }
+    }
+
+    /// Returns the SHA256 hash of the archive of the package if it is known.
+    fn sha256(&self) -> Option<Sha256Hash>;
+
+    /// Returns the MD5 hash of the archive of the package if it is known.
+    fn md5(&self) -> Option<Md5Hash>;
+
+    /// Returns the size of the archive of the package in bytes if it is known.
+    fn size(&self) -> Option<u64>;
+
+    /// Returns how the package is installed if it is a `noarch` package, or `None` if this can
+    /// only be determined by reading the package.
+    fn noarch(&self) -> Option<NoArchType>;
+
+    /// Returns the record of the package if it is known without reading the package.
+    fn package_record(&self) -> Option<&PackageRecord> {
+        None
+    }
+}
+
+/// Returns the last segment of the path of the url.
+fn file_name_of_url(url: &Url) -> &str {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+}
+
+impl InstallablePackage for RepoDataRecord {
+    fn name(&self) -> Option<PackageName> {
+        Some(self.package_record.name.clone())
+    }
+
+    fn url(&self) -> Cow<'_, Url> {
+        Cow::Borrowed(&self.url)
+    }
+
+    fn file_name(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.file_name)
+    }
+
+    fn sha256(&self) -> Option<Sha256Hash> {
+        self.package_record.sha256
+    }
+
+    fn md5(&self) -> Option<Md5Hash> {
+        self.package_record.md5
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.package_record.size
+    }
+
+    fn noarch(&self) -> Option<NoArchType> {
+        Some(self.package_record.noarch)
+    }
+
+    fn package_record(&self) -> Option<&PackageRecord> {
+        Some(&self.package_record)
+    }
+}
+
+impl InstallablePackage for ExplicitEnvironmentEntry {
+    fn name(&self) -> Option<PackageName> {
+        let identifier = ArchiveIdentifier::try_from_url(&self.url)?;
+        PackageName::try_from(identifier.name).ok()
+    }
+
+    /// Returns the url of the entry without the hash anchor.
+    fn url(&self) -> Cow<'_, Url> {
+        if self.url.fragment().is_none() {
+            return Cow::Borrowed(&self.url);
+        }
+        let mut url = self.url.clone();
+        url.set_fragment(None);
+        Cow::Owned(url)
+    }
+
+    fn sha256(&self) -> Option<Sha256Hash> {
+        self.package_archive_hash().ok()??.sha256().copied()
+    }
+
+    fn md5(&self) -> Option<Md5Hash> {
+        self.package_archive_hash().ok()??.md5().copied()
+    }
+
+    fn size(&self) -> Option<u64> {
+        None
+    }
+
+    fn noarch(&self) -> Option<NoArchType> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InstallablePackage;
+    use crate::ExplicitEnvironmentEntry;
+    use url::Url;
+
+    #[test]
+    fn test_explicit_environment_entry() {
+        let entry = ExplicitEnvironmentEntry::from(
+            Url::parse("https://conda.anaconda.org/conda-forge/noarch/tzdata-2023c-h71feb2d_0.conda#939e3e74d8be4dac89ce83b20de2492a").unwrap(),
+        );
+        assert_eq!(entry.name().unwrap().as_normalized(), "tzdata");
+        assert_eq!(
+            entry.url().as_str(),
+            "https://conda.anaconda.org/conda-forge/noarch/tzdata-2023c-h71feb2d_0.conda"
+        );
+        assert_eq!(entry.file_name(), "tzdata-2023c-h71feb2d_0.conda");
+        assert_eq!(
+            format!("{:x}", entry.md5().unwrap()),
+            "939e3e74d8be4dac89ce83b20de2492a"
+        );
+        assert_eq!(entry.sha256(), None);
+        assert_eq!(entry.noarch(), None);
+        assert!(entry.package_record().is_none());
+    }
+}
@@ -5,13 +5,21 @@
 mod build_spec;
 mod channel;
 mod channel_data;
+mod clock;
+mod environment_diff;
+mod environment_yaml;
 mod explicit_environment_spec;
+mod installable_package;
+mod license_report;
 mod match_spec;
 mod no_arch_type;
 mod platform;
+mod policy;
 mod repo_data;
 mod repo_data_record;
 mod run_export;
+mod sbom;
+mod timestamp;
 mod utils;
 mod version;
 pub mod version_spec;
@@ -19,29 +27,49 @@ pub mod version_spec;
 mod generic_virtual_package;
 pub mod package;
 mod package_name;
+mod pinned_specs;
 pub mod prefix_record;
+mod prefix_relative_path;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 pub use build_spec::{BuildNumber, BuildNumberSpec, ParseBuildNumberSpecError};
-pub use channel::{Channel, ChannelConfig, ParseChannelError};
+pub use channel::{
+    Channel, ChannelConfig, ParseChannelError, ResolveChannelsError, DEFAULTS_CHANNEL,
+    NODEFAULTS_CHANNEL,
+};
 pub use channel_data::{ChannelData, ChannelDataPackage};
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use environment_diff::{EnvironmentDiff, PackageChange, PackageChangeKind};
+pub use environment_yaml::{EnvironmentYaml, ParseEnvironmentYamlError};
 pub use explicit_environment_spec::{
-    ExplicitEnvironmentEntry, ExplicitEnvironmentSpec, PackageArchiveHash,
-    ParseExplicitEnvironmentSpecError, ParsePackageArchiveHashError,
+    ExplicitEnvironmentEntry, ExplicitEnvironmentPlatformError, ExplicitEnvironmentSpec,
+    PackageArchiveHash, ParseExplicitEnvironmentSpecError, ParsePackageArchiveHashError,
 };
 pub use generic_virtual_package::GenericVirtualPackage;
+pub use installable_package::InstallablePackage;
+pub use license_report::{LicenseReport, LicenseSource, PackageLicense};
 pub use match_spec::matcher::StringMatcher;
 pub use match_spec::parse::ParseMatchSpecError;
-pub use match_spec::{MatchSpec, NamelessMatchSpec};
+pub use match_spec::{ChannelSpec, MatchSpec, NamelessMatchSpec, ParseChannelSpecError};
 pub use no_arch_type::{NoArchKind, NoArchType};
 pub use package_name::{InvalidPackageNameError, PackageName};
+pub use pinned_specs::{ParsePinnedSpecsError, PinnedSpecs};
 pub use platform::{Arch, ParseArchError, ParsePlatformError, Platform};
+pub use policy::{Policy, PolicyViolation};
 pub use prefix_record::PrefixRecord;
+pub use prefix_relative_path::{InvalidPrefixRelativePathError, PrefixRelativePath};
+pub use repo_data::diff::{FieldChange, PackageRecordDiff, RepoDataDiff};
 pub use repo_data::patches::{PackageRecordPatch, PatchInstructions, RepoDataPatch};
 pub use repo_data::{
     compute_package_url, ChannelInfo, ConvertSubdirError, PackageRecord, RepoData,
+    SubdirMismatchError,
 };
 pub use repo_data_record::RepoDataRecord;
 pub use run_export::RunExportKind;
+pub use sbom::{Sbom, SbomPackage, CYCLONEDX_SPEC_VERSION, SPDX_VERSION};
+pub use timestamp::Timestamp;
 pub use version::{
     Component, ParseVersionError, ParseVersionErrorKind, StrictVersion, Version, VersionWithSource,
 };
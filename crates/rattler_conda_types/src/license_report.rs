@@ -0,0 +1,267 @@
+//! Defines [`LicenseReport`] which lists the licenses of the packages in an environment.
+
+use crate::package::{AboutJson, PackageFile};
+use crate::{PrefixRecord, RepoDataRecord};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The licenses of the packages in an environment, e.g. for legal review.
+///
+/// The license of a package is reported as it is specified by the package. For most packages this
+/// is an SPDX license expression like `BSD-3-Clause` or `MIT AND Apache-2.0`, but it is not
+/// validated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseReport {
+    /// The licenses of the packages, sorted by the name of the package.
+    pub packages: Vec<PackageLicense>,
+}
+
+/// The license of a single package in a [`LicenseReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageLicense {
+    /// The name of the package.
+    pub name: String,
+
+    /// The version of the package.
+    pub version: String,
+
+    /// The build string of the package.
+    pub build: String,
+
+    /// The channel the package was installed from.
+    pub channel: String,
+
+    /// The license of the package, if known.
+    pub license: Option<String>,
+
+    /// The license family of the package (e.g. `BSD`), if known.
+    pub license_family: Option<String>,
+
+    /// The file from which the license was read.
+    pub source: LicenseSource,
+}
+
+/// Describes where the license of a package was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LicenseSource {
+    /// The license was read from the `index.json` file of the package, which is also the source of
+    /// the license in the repodata.
+    #[serde(rename = "index.json")]
+    IndexJson,
+
+    /// The license was read from the `about.json` file of the extracted package.
+    #[serde(rename = "about.json")]
+    AboutJson,
+
+    /// The package does not specify a license.
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl PackageLicense {
+    /// Reads the license of a package from its repodata.
+    pub(crate) fn from_repodata_record(record: &RepoDataRecord) -> Self {
+        let package_record = &record.package_record;
+        let source = if package_record.license.is_some() {
+            LicenseSource::IndexJson
+        } else {
+            LicenseSource::Unknown
+        };
+        Self {
+            name: package_record.name.as_normalized().to_owned(),
+            version: package_record.version.to_string(),
+            build: package_record.build.clone(),
+            channel: record.channel.clone(),
+            license: package_record.license.clone(),
+            license_family: package_record.license_family.clone(),
+            source,
+        }
+    }
+
+    /// Reads the license of an installed package. If the `index.json` of the package does not
+    /// specify a license, the license is read from the `about.json` of the extracted package.
+    pub(crate) fn from_prefix_record(record: &PrefixRecord) -> Self {
+        let mut package = Self::from_repodata_record(&record.repodata_record);
+        if package.license.is_none() {
+            let about = record
+                .extracted_package_dir
+                .as_deref()
+                .and_then(|dir| AboutJson::from_package_directory(dir).ok());
+            if let Some(about) = about.filter(|about| about.license.is_some()) {
+                package.license = about.license;
+                package.license_family = package.license_family.or(about.license_family);
+                package.source = LicenseSource::AboutJson;
+            }
+        }
+        package
+    }
+}
+
+impl LicenseReport {
+    /// Constructs a report from the records of a solve result. Only the licenses in the repodata
+    /// are available for these records.
+    pub fn from_repodata_records(
+        records: impl IntoIterator<Item = impl Borrow<RepoDataRecord>>,
+    ) -> Self {
+        Self::from_packages(
+            records
+                .into_iter()
+                .map(|record| PackageLicense::from_repodata_record(record.borrow()))
+                .collect(),
+        )
+    }
+
+    /// Constructs a report from the records of the packages installed in a prefix. If the
+    /// `index.json` of a package does not specify a license, the license is read from the
+    /// `about.json` of the extracted package, if it is still available.
+    pub fn from_prefix_records(records: &[PrefixRecord]) -> Self {
+        Self::from_packages(
+            records
+                .iter()
+                .map(PackageLicense::from_prefix_record)
+                .collect(),
+        )
+    }
+
+    /// Constructs a report of the packages installed in the prefix.
+    pub fn from_prefix(prefix: &Path) -> Result<Self, std::io::Error> {
+        Ok(Self::from_prefix_records(
+            &PrefixRecord::collect_from_prefix(prefix)?,
+        ))
+    }
+
+    fn from_packages(mut packages: Vec<PackageLicense>) -> Self {
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { packages }
+    }
+
+    /// Returns the packages grouped by their license. Packages without a license are not included,
+    /// see [`Self::unlicensed`].
+    pub fn by_license(&self) -> BTreeMap<&str, Vec<&PackageLicense>> {
+        let mut licenses = BTreeMap::<_, Vec<_>>::new();
+        for package in &self.packages {
+            if let Some(license) = &package.license {
+                licenses.entry(license.as_str()).or_default().push(package);
+            }
+        }
+        licenses
+    }
+
+    /// Returns the packages that do not specify a license.
+    pub fn unlicensed(&self) -> impl Iterator<Item = &PackageLicense> + '_ {
+        self.packages
+            .iter()
+            .filter(|package| package.license.is_none())
+    }
+
+    /// Formats the report as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("name,version,build,channel,license,license_family,source\n");
+        for package in &self.packages {
+            let source = match package.source {
+                LicenseSource::IndexJson => "index.json",
+                LicenseSource::AboutJson => "about.json",
+                LicenseSource::Unknown => "unknown",
+            };
+            let fields = [
+                package.name.as_str(),
+                &package.version,
+                &package.build,
+                &package.channel,
+                package.license.as_deref().unwrap_or_default(),
+                package.license_family.as_deref().unwrap_or_default(),
+                source,
+            ];
+            let fields = fields.map(|field| {
+                if field.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.to_owned()
+                }
+            });
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LicenseReport, LicenseSource};
+    use crate::{test_utils::RecordBuilder, PrefixRecord};
+
+    #[test]
+    fn test_license_report() {
+        let report = LicenseReport::from_repodata_records([
+            RecordBuilder::new("foo", "1.0").with_license("MIT").build(),
+            RecordBuilder::new("bar", "1.0")
+                .with_license("BSD-3-Clause, with exceptions")
+                .build(),
+            RecordBuilder::new("baz", "1.0").build(),
+            RecordBuilder::new("qux", "1.0").with_license("MIT").build(),
+        ]);
+
+        assert_eq!(
+            report
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            ["bar", "baz", "foo", "qux"]
+        );
+        let by_license = report.by_license();
+        assert_eq!(by_license.len(), 2);
+        assert_eq!(by_license["MIT"].len(), 2);
+        assert_eq!(
+            report
+                .unlicensed()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            ["baz"]
+        );
+
+        let csv = report.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("name,version,build,channel,license,license_family,source")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("bar,1.0,0,https://conda.anaconda.org/conda-forge/,\"BSD-3-Clause, with exceptions\",,index.json")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("baz,1.0,0,https://conda.anaconda.org/conda-forge/,,,unknown")
+        );
+    }
+
+    #[test]
+    fn test_license_from_about_json() {
+        let package_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(package_dir.path().join("info")).unwrap();
+        std::fs::write(
+            package_dir.path().join("info/about.json"),
+            r#"{"license": "Apache-2.0", "license_family": "Apache"}"#,
+        )
+        .unwrap();
+
+        let prefix_record = PrefixRecord {
+            repodata_record: RecordBuilder::new("foo", "1.0").build(),
+            package_tarball_full_path: None,
+            extracted_package_dir: Some(package_dir.path().to_path_buf()),
+            files: Vec::new(),
+            paths_data: Default::default(),
+            link: None,
+            requested_spec: None,
+        };
+        let report = LicenseReport::from_prefix_records(&[prefix_record]);
+        let package = &report.packages[0];
+        assert_eq!(package.license.as_deref(), Some("Apache-2.0"));
+        assert_eq!(package.license_family.as_deref(), Some("Apache"));
+        assert_eq!(package.source, LicenseSource::AboutJson);
+    }
+}
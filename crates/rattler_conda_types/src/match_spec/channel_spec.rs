@@ -0,0 +1,262 @@
+use crate::{Channel, ChannelConfig, ParseChannelError, Platform, RepoDataRecord};
+use smallvec::smallvec;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The channel part of a [`super::MatchSpec`], e.g. `conda-forge` in `conda-forge::python` or
+/// `conda-forge/label/rust_dev` in `conda-forge/label/rust_dev::rust`.
+///
+/// Next to the name or url of the channel a [`ChannelSpec`] can also pin the label of the channel
+/// and the subdirectory (platform) of the package, e.g. `pkgs/main/linux-64::python` only matches
+/// `linux-64` packages from the `pkgs/main` channel.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ChannelSpec {
+    /// The name or url of the channel without its label and subdir, e.g. `conda-forge`,
+    /// `pkgs/main` or `https://conda.anaconda.org/conda-forge`. The value `*` matches any channel.
+    pub channel: String,
+
+    /// The label of the channel, e.g. `rust_dev` in `conda-forge/label/rust_dev`.
+    pub label: Option<String>,
+
+    /// The subdirectory of the channel the package must come from, e.g. `linux-64`.
+    pub subdir: Option<String>,
+}
+
+/// An error that can occur when parsing a [`ChannelSpec`].
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum ParseChannelSpecError {
+    /// The channel spec does not contain a channel.
+    #[error("the channel is empty")]
+    EmptyChannel,
+}
+
+impl ChannelSpec {
+    /// Constructs a spec that matches packages from the given subdirectory of any channel.
+    pub fn any_channel(subdir: impl Into<String>) -> Self {
+        Self {
+            channel: String::from("*"),
+            label: None,
+            subdir: Some(subdir.into()),
+        }
+    }
+
+    /// Returns true if the spec matches any channel.
+    pub fn is_any_channel(&self) -> bool {
+        self.channel == "*"
+    }
+
+    /// Returns true if the record comes from the channel, label and subdirectory of this spec.
+    pub fn matches(&self, record: &RepoDataRecord) -> bool {
+        self.matches_subdir(&record.package_record.subdir) && self.matches_channel(&record.channel)
+    }
+
+    /// Returns true if the given subdirectory matches the subdirectory of this spec.
+    pub fn matches_subdir(&self, subdir: &str) -> bool {
+        self.subdir
+            .as_deref()
+            .map_or(true, |expected| expected == subdir)
+    }
+
+    /// Returns true if the given channel, which is either the name or the url of a channel (see
+    /// [`RepoDataRecord::channel`]), matches the channel and label of this spec.
+    ///
+    /// A channel name matches a url if the url ends with that name, e.g. `conda-forge` matches
+    /// `https://conda.anaconda.org/conda-forge/`.
+    pub fn matches_channel(&self, channel: &str) -> bool {
+        let channel = channel.trim_end_matches('/');
+        let label_suffix = self.label().map(|label| format!("/label/{label}"));
+
+        if self.is_any_channel() {
+            return label_suffix.map_or(true, |suffix| channel.ends_with(&suffix));
+        }
+
+        let expected = format!(
+            "{}{}",
+            self.channel.trim_end_matches('/'),
+            label_suffix.unwrap_or_default()
+        );
+        channel == expected
+            || (!expected.contains("://") && channel.ends_with(&format!("/{expected}")))
+    }
+
+    /// Resolves the channel of this spec to a [`Channel`]. If the spec pins a subdir that is a
+    /// known platform, the channel is restricted to that platform.
+    pub fn to_channel(&self, config: &ChannelConfig) -> Result<Channel, ParseChannelError> {
+        let channel = match self.label() {
+            Some(label) => format!("{}/label/{label}", self.channel.trim_end_matches('/')),
+            None => self.channel.clone(),
+        };
+        let mut channel = Channel::from_str(channel, config)?;
+        if let Some(platform) = self
+            .subdir
+            .as_deref()
+            .and_then(|subdir| Platform::from_str(subdir).ok())
+        {
+            channel.platforms = Some(smallvec![platform]);
+        }
+        Ok(channel)
+    }
+
+    /// Returns the label of the channel. The `main` label is the default label of a channel so it
+    /// is treated as if no label was specified.
+    fn label(&self) -> Option<&str> {
+        self.label.as_deref().filter(|label| *label != "main")
+    }
+}
+
+impl FromStr for ChannelSpec {
+    type Err = ParseChannelSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_end_matches('/');
+
+        // The last component is the subdir if it refers to a known platform.
+        let (rest, subdir) = match s.rsplit_once('/') {
+            Some((rest, last)) if Platform::from_str(last).is_ok() => (rest, Some(last)),
+            _ => (s, None),
+        };
+
+        // A label is added to the channel with `/label/<label>`.
+        let (channel, label) = match rest.rsplit_once("/label/") {
+            Some((channel, label)) if !label.is_empty() && !label.contains('/') => {
+                (channel, Some(label))
+            }
+            _ => (rest, None),
+        };
+
+        if channel.is_empty() {
+            return Err(ParseChannelSpecError::EmptyChannel);
+        }
+
+        Ok(Self {
+            channel: channel.to_owned(),
+            label: label.map(ToOwned::to_owned),
+            subdir: subdir.map(ToOwned::to_owned),
+        })
+    }
+}
+
+impl Display for ChannelSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.channel)?;
+        if let Some(label) = &self.label {
+            write!(f, "/label/{label}")?;
+        }
+        if let Some(subdir) = &self.subdir {
+            write!(f, "/{subdir}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelSpec, ParseChannelSpecError};
+    use crate::{ChannelConfig, Platform};
+    use std::str::FromStr;
+
+    fn spec(channel: &str, label: Option<&str>, subdir: Option<&str>) -> ChannelSpec {
+        ChannelSpec {
+            channel: channel.to_owned(),
+            label: label.map(ToOwned::to_owned),
+            subdir: subdir.map(ToOwned::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ChannelSpec::from_str("conda-forge").unwrap(),
+            spec("conda-forge", None, None)
+        );
+        assert_eq!(
+            ChannelSpec::from_str("conda-forge/label/rust_dev").unwrap(),
+            spec("conda-forge", Some("rust_dev"), None)
+        );
+        assert_eq!(
+            ChannelSpec::from_str("pkgs/main/linux-64").unwrap(),
+            spec("pkgs/main", None, Some("linux-64"))
+        );
+        assert_eq!(
+            ChannelSpec::from_str("pkgs/main").unwrap(),
+            spec("pkgs/main", None, None)
+        );
+        assert_eq!(
+            ChannelSpec::from_str("conda-forge/label/rust_dev/noarch").unwrap(),
+            spec("conda-forge", Some("rust_dev"), Some("noarch"))
+        );
+        assert_eq!(
+            ChannelSpec::from_str("*/linux-64").unwrap(),
+            ChannelSpec::any_channel("linux-64")
+        );
+        assert_eq!(
+            ChannelSpec::from_str("https://conda.anaconda.org/conda-forge/osx-arm64/").unwrap(),
+            spec(
+                "https://conda.anaconda.org/conda-forge",
+                None,
+                Some("osx-arm64")
+            )
+        );
+        assert_eq!(
+            ChannelSpec::from_str("/linux-64"),
+            Err(ParseChannelSpecError::EmptyChannel)
+        );
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for s in [
+            "conda-forge",
+            "conda-forge/label/rust_dev",
+            "pkgs/main/linux-64",
+            "conda-forge/label/rust_dev/noarch",
+            "*/linux-64",
+        ] {
+            assert_eq!(ChannelSpec::from_str(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_matches_channel() {
+        let conda_forge = spec("conda-forge", None, None);
+        assert!(conda_forge.matches_channel("https://conda.anaconda.org/conda-forge/"));
+        assert!(conda_forge.matches_channel("conda-forge"));
+        assert!(!conda_forge.matches_channel("https://conda.anaconda.org/bioconda/"));
+        assert!(!conda_forge.matches_channel("https://conda.anaconda.org/my-conda-forge/"));
+        assert!(!conda_forge.matches_channel("https://conda.anaconda.org/conda-forge/label/dev"));
+
+        let rust_dev = spec("conda-forge", Some("rust_dev"), None);
+        assert!(rust_dev.matches_channel("https://conda.anaconda.org/conda-forge/label/rust_dev/"));
+        assert!(!rust_dev.matches_channel("https://conda.anaconda.org/conda-forge/"));
+
+        let main_label = spec("conda-forge", Some("main"), None);
+        assert!(main_label.matches_channel("https://conda.anaconda.org/conda-forge/"));
+
+        let url = spec("https://repo.anaconda.com/pkgs/main", None, None);
+        assert!(url.matches_channel("https://repo.anaconda.com/pkgs/main/"));
+        assert!(!url.matches_channel("https://mirror.example.com/pkgs/main/"));
+
+        let any = ChannelSpec::any_channel("linux-64");
+        assert!(any.matches_channel("https://conda.anaconda.org/conda-forge/"));
+        assert!(any.matches_subdir("linux-64"));
+        assert!(!any.matches_subdir("noarch"));
+    }
+
+    #[test]
+    fn test_to_channel() {
+        let config = ChannelConfig::default();
+        let channel = ChannelSpec::from_str("conda-forge/label/rust_dev/linux-64")
+            .unwrap()
+            .to_channel(&config)
+            .unwrap();
+        assert_eq!(
+            channel.base_url.as_str(),
+            "https://conda.anaconda.org/conda-forge/label/rust_dev/"
+        );
+        assert_eq!(
+            channel.platforms.as_deref(),
+            Some([Platform::Linux64].as_slice())
+        );
+    }
+}
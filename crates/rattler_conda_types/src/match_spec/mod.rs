@@ -5,9 +5,11 @@ use serde_with::{serde_as, skip_serializing_none, DisplayFromStr};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
 
+mod channel_spec;
 pub mod matcher;
 pub mod parse;
 
+pub use channel_spec::{ChannelSpec, ParseChannelSpecError};
 use matcher::StringMatcher;
 
 /// A [`MatchSpec`] is, fundamentally, a query language for conda packages. Any of the fields that
@@ -49,6 +51,9 @@ use matcher::StringMatcher;
 ///    canonical string representation, the canonical channel name will always be used.
 /// 6. If `channel` is an exact value and `subdir` is an exact value, `subdir` is appended to
 ///    `channel` with a `/` separator.  Otherwise, `subdir` is included in the key-value brackets.
+///    A label of the channel is added to the channel with `/label/<label>`, e.g.
+///    `conda-forge/label/rust_dev::rust`. The channel, label and subdir are stored in a
+///    [`ChannelSpec`].
 /// 7. Key-value brackets can be delimited by comma, space, or comma+space.  Value can optionally
 ///    be wrapped in single or double quotes, but must be wrapped if `value` contains a comma,
 ///    space, or equal sign.  The canonical format uses comma delimiters and single quotes.
@@ -64,7 +69,7 @@ use matcher::StringMatcher;
 /// # Examples:
 ///
 /// ```rust
-/// use rattler_conda_types::{MatchSpec, VersionSpec, StringMatcher, PackageName};
+/// use rattler_conda_types::{ChannelSpec, MatchSpec, VersionSpec, StringMatcher, PackageName};
 /// use std::str::FromStr;
 ///
 /// let spec = MatchSpec::from_str("foo 1.0 py27_0").unwrap();
@@ -80,19 +85,25 @@ use matcher::StringMatcher;
 /// let spec = MatchSpec::from_str(r#"conda-forge::foo[version="1.0.*"]"#).unwrap();
 /// assert_eq!(spec.name, Some(PackageName::new_unchecked("foo")));
 /// assert_eq!(spec.version, Some(VersionSpec::from_str("1.0.*").unwrap()));
-/// assert_eq!(spec.channel, Some("conda-forge".to_string()));
+/// assert_eq!(spec.channel, Some(ChannelSpec::from_str("conda-forge").unwrap()));
 ///
 /// let spec = MatchSpec::from_str("conda-forge/linux-64::foo>=1.0").unwrap();
 /// assert_eq!(spec.name, Some(PackageName::new_unchecked("foo")));
 /// assert_eq!(spec.version, Some(VersionSpec::from_str(">=1.0").unwrap()));
-/// assert_eq!(spec.channel, Some("conda-forge".to_string()));
-/// assert_eq!(spec.subdir, Some("linux-64".to_string()));
+/// let channel = spec.channel.unwrap();
+/// assert_eq!(channel.channel, "conda-forge");
+/// assert_eq!(channel.subdir, Some("linux-64".to_string()));
 ///
 /// let spec = MatchSpec::from_str("*/linux-64::foo>=1.0").unwrap();
 /// assert_eq!(spec.name, Some(PackageName::new_unchecked("foo")));
 /// assert_eq!(spec.version, Some(VersionSpec::from_str(">=1.0").unwrap()));
-/// assert_eq!(spec.channel, Some("*".to_string()));
-/// assert_eq!(spec.subdir, Some("linux-64".to_string()));
+/// assert_eq!(spec.channel, Some(ChannelSpec::any_channel("linux-64")));
+///
+/// let spec = MatchSpec::from_str("conda-forge/label/rust_dev::rust").unwrap();
+/// assert_eq!(spec.name, Some(PackageName::new_unchecked("rust")));
+/// let channel = spec.channel.unwrap();
+/// assert_eq!(channel.channel, "conda-forge");
+/// assert_eq!(channel.label, Some("rust_dev".to_string()));
 ///
 /// let spec = MatchSpec::from_str(r#"foo[build="py2*"]"#).unwrap();
 /// assert_eq!(spec.name, Some(PackageName::new_unchecked("foo")));
@@ -124,10 +135,9 @@ pub struct MatchSpec {
     pub build_number: Option<BuildNumberSpec>,
     /// Match the specific filename of the package
     pub file_name: Option<String>,
-    /// The channel of the package
-    pub channel: Option<String>,
-    /// The subdir of the channel
-    pub subdir: Option<String>,
+    /// The channel (and optionally the label and subdir of the channel) of the package
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub channel: Option<ChannelSpec>,
     /// The namespace of the package (currently not used)
     pub namespace: Option<String>,
     /// The md5 hash of the package
@@ -136,17 +146,17 @@ pub struct MatchSpec {
     /// The sha256 hash of the package
     #[serde_as(as = "Option<SerializableHash::<rattler_digest::Sha256>>")]
     pub sha256: Option<Sha256Hash>,
+    /// The license of the package (e.g. `BSD-3-Clause`), which must match exactly
+    pub license: Option<String>,
 }
 
 impl Display for MatchSpec {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if let Some(channel) = &self.channel {
-            // TODO: namespace
-            write!(f, "{}", channel)?;
-        }
-
-        if let Some(subdir) = &self.subdir {
-            write!(f, "/{}", subdir)?;
+        match (&self.channel, &self.namespace) {
+            (Some(channel), Some(namespace)) => write!(f, "{channel}:{namespace}:")?,
+            (Some(channel), None) => write!(f, "{channel}::")?,
+            (None, Some(namespace)) => write!(f, "{namespace}:")?,
+            (None, None) => {}
         }
 
         match &self.name {
@@ -154,12 +164,6 @@ impl Display for MatchSpec {
             None => write!(f, "*")?,
         }
 
-        if let Some(namespace) = &self.namespace {
-            write!(f, ":{}:", namespace)?;
-        } else if self.channel.is_some() || self.subdir.is_some() {
-            write!(f, "::")?;
-        }
-
         if let Some(version) = &self.version {
             write!(f, " {}", version)?;
         }
@@ -178,6 +182,10 @@ impl Display for MatchSpec {
             keys.push(format!("sha256={sha256:x}"));
         }
 
+        if let Some(license) = &self.license {
+            keys.push(format!("license=\"{license}\""));
+        }
+
         if !keys.is_empty() {
             write!(f, "[{}]", keys.join(", "))?;
         }
@@ -213,6 +221,12 @@ impl MatchSpec {
             }
         }
 
+        if let Some(channel) = self.channel.as_ref() {
+            if !channel.matches_subdir(&record.subdir) {
+                return false;
+            }
+        }
+
         if let Some(md5_spec) = self.md5.as_ref() {
             if Some(md5_spec) != record.md5.as_ref() {
                 return false;
@@ -225,6 +239,12 @@ impl MatchSpec {
             }
         }
 
+        if let Some(license) = self.license.as_ref() {
+            if Some(license) != record.license.as_ref() {
+                return false;
+            }
+        }
+
         true
     }
 
@@ -238,10 +258,10 @@ impl MatchSpec {
                 build_number: self.build_number,
                 file_name: self.file_name,
                 channel: self.channel,
-                subdir: self.subdir,
                 namespace: self.namespace,
                 md5: self.md5,
                 sha256: self.sha256,
+                license: self.license,
             },
         )
     }
@@ -263,10 +283,9 @@ pub struct NamelessMatchSpec {
     pub build_number: Option<BuildNumberSpec>,
     /// Match the specific filename of the package
     pub file_name: Option<String>,
-    /// The channel of the package
-    pub channel: Option<String>,
-    /// The subdir of the channel
-    pub subdir: Option<String>,
+    /// The channel (and optionally the label and subdir of the channel) of the package
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub channel: Option<ChannelSpec>,
     /// The namespace of the package (currently not used)
     pub namespace: Option<String>,
     /// The md5 hash of the package
@@ -275,6 +294,8 @@ pub struct NamelessMatchSpec {
     /// The sha256 hash of the package
     #[serde_as(as = "Option<SerializableHash::<rattler_digest::Sha256>>")]
     pub sha256: Option<Sha256Hash>,
+    /// The license of the package (e.g. `BSD-3-Clause`), which must match exactly
+    pub license: Option<String>,
 }
 
 impl NamelessMatchSpec {
@@ -292,6 +313,12 @@ impl NamelessMatchSpec {
             }
         }
 
+        if let Some(channel) = self.channel.as_ref() {
+            if !channel.matches_subdir(&record.subdir) {
+                return false;
+            }
+        }
+
         if let Some(md5_spec) = self.md5.as_ref() {
             if Some(md5_spec) != record.md5.as_ref() {
                 return false;
@@ -304,6 +331,12 @@ impl NamelessMatchSpec {
             }
         }
 
+        if let Some(license) = self.license.as_ref() {
+            if Some(license) != record.license.as_ref() {
+                return false;
+            }
+        }
+
         true
     }
 }
@@ -329,6 +362,10 @@ impl Display for NamelessMatchSpec {
             keys.push(format!("sha256={sha256:x}"));
         }
 
+        if let Some(license) = &self.license {
+            keys.push(format!("license=\"{license}\""));
+        }
+
         if !keys.is_empty() {
             write!(f, "[{}]", keys.join(", "))?;
         }
@@ -345,10 +382,10 @@ impl From<MatchSpec> for NamelessMatchSpec {
             build_number: spec.build_number,
             file_name: spec.file_name,
             channel: spec.channel,
-            subdir: spec.subdir,
             namespace: spec.namespace,
             md5: spec.md5,
             sha256: spec.sha256,
+            license: spec.license,
         }
     }
 }
@@ -363,10 +400,10 @@ impl MatchSpec {
             build_number: spec.build_number,
             file_name: spec.file_name,
             channel: spec.channel,
-            subdir: spec.subdir,
             namespace: spec.namespace,
             md5: spec.md5,
             sha256: spec.sha256,
+            license: spec.license,
         }
     }
 }
@@ -460,4 +497,29 @@ mod tests {
         let spec = MatchSpec::from_str("mamba[version==1.0, md5=dede6252c964db3f3e41c7d30d07f6bf, sha256=aaac4bc9c6916ecc0e33137431645b029ade22190c7144eead61446dcbcc6f97]").unwrap();
         assert!(!spec.matches(&record));
     }
+
+    #[test]
+    fn test_subdir_match() {
+        let record = PackageRecord {
+            subdir: String::from("linux-64"),
+            ..PackageRecord::new(
+                PackageName::new_unchecked("python"),
+                Version::from_str("3.11.0").unwrap(),
+                String::from("0"),
+            )
+        };
+
+        assert!(MatchSpec::from_str("pkgs/main/linux-64::python")
+            .unwrap()
+            .matches(&record));
+        assert!(MatchSpec::from_str("conda-forge::python")
+            .unwrap()
+            .matches(&record));
+        assert!(!MatchSpec::from_str("pkgs/main/noarch::python")
+            .unwrap()
+            .matches(&record));
+        assert!(!MatchSpec::from_str("python[subdir=osx-64]")
+            .unwrap()
+            .matches(&record));
+    }
 }
@@ -1,5 +1,5 @@
 use super::matcher::{StringMatcher, StringMatcherParseError};
-use super::MatchSpec;
+use super::{ChannelSpec, MatchSpec, ParseChannelSpecError};
 use crate::build_spec::{BuildNumberSpec, ParseBuildNumberSpecError};
 use crate::package::ArchiveType;
 use crate::version_spec::version_tree::{recognize_constraint, recognize_version};
@@ -42,6 +42,10 @@ pub enum ParseMatchSpecError {
     #[error("invalid channel")]
     ParseChannelError(#[from] ParseChannelError),
 
+    /// Invalid channel spec provided in match spec
+    #[error("invalid channel spec")]
+    ParseChannelSpecError(#[from] ParseChannelSpecError),
+
     /// Invalid key in match spec
     #[error("invalid bracket key: {0}")]
     InvalidBracketKey(String),
@@ -218,7 +222,20 @@ fn parse_bracket_vec_into_components(
                         .ok_or(ParseMatchSpecError::InvalidHashDigest)?,
                 )
             }
+            "license" => match_spec.license = Some(value.to_string()),
             "fn" => match_spec.file_name = Some(value.to_string()),
+            "channel" => {
+                let subdir = match_spec.channel.take().and_then(|channel| channel.subdir);
+                let mut channel = ChannelSpec::from_str(value)?;
+                channel.subdir = channel.subdir.or(subdir);
+                match_spec.channel = Some(channel);
+            }
+            "subdir" => {
+                match_spec
+                    .channel
+                    .get_or_insert_with(|| ChannelSpec::any_channel(value))
+                    .subdir = Some(value.to_string())
+            }
             _ => Err(ParseMatchSpecError::InvalidBracketKey(key.to_owned()))?,
         }
     }
@@ -375,33 +392,43 @@ fn parse(input: &str) -> Result<MatchSpec, ParseMatchSpecError> {
     // 4. Strip off parens portion
     // TODO: What is this? I've never seen in
 
-    // 5. Strip of '::' channel and namespace
-    let mut input_split = input.split(':').fuse();
-    let (input, namespace, channel_str) = match (
-        input_split.next(),
-        input_split.next(),
-        input_split.next(),
-        input_split.next(),
-    ) {
-        (Some(input), None, _, _) => (input, None, None),
-        (Some(namespace), Some(input), None, _) => (input, Some(namespace), None),
-        (Some(channel_str), Some(namespace), Some(input), None) => {
-            (input, Some(namespace), Some(channel_str))
-        }
-        _ => return Err(ParseMatchSpecError::InvalidNumberOfColons),
-    };
+    // 5. Strip of '::' channel and namespace. The channel can be a url which itself contains
+    // colons.
+    let (input, namespace, channel_str) =
+        if let Some((channel_str, input)) = input.rsplit_once("::") {
+            (input, None, Some(channel_str))
+        } else {
+            let mut input_split = input.split(':').fuse();
+            match (
+                input_split.next(),
+                input_split.next(),
+                input_split.next(),
+                input_split.next(),
+            ) {
+                (Some(input), None, _, _) => (input, None, None),
+                (Some(namespace), Some(input), None, _) => (input, Some(namespace), None),
+                (Some(channel_str), Some(namespace), Some(input), None) => {
+                    (input, Some(namespace), Some(channel_str))
+                }
+                _ => return Err(ParseMatchSpecError::InvalidNumberOfColons),
+            }
+        };
 
     nameless_match_spec.namespace = namespace
         .map(ToOwned::to_owned)
         .or(nameless_match_spec.namespace);
 
+    // Any channel or subdir in the brackets overrides the channel in front of the name.
     if let Some(channel_str) = channel_str {
-        if let Some((channel, subdir)) = channel_str.rsplit_once('/') {
-            nameless_match_spec.channel = Some(channel.to_string());
-            nameless_match_spec.subdir = Some(subdir.to_string());
-        } else {
-            nameless_match_spec.channel = Some(channel_str.to_string());
+        let mut channel = ChannelSpec::from_str(channel_str)?;
+        if let Some(bracket_channel) = nameless_match_spec.channel.take() {
+            if !bracket_channel.is_any_channel() {
+                channel.channel = bracket_channel.channel;
+                channel.label = bracket_channel.label;
+            }
+            channel.subdir = bracket_channel.subdir.or(channel.subdir);
         }
+        nameless_match_spec.channel = Some(channel);
     }
 
     // Step 6. Strip off the package name from the input
@@ -473,7 +500,10 @@ mod tests {
         split_version_and_build, strip_brackets, BracketVec, MatchSpec, ParseMatchSpecError,
     };
     use crate::match_spec::parse::parse_bracket_list;
-    use crate::{BuildNumberSpec, NamelessMatchSpec, VersionSpec};
+    use crate::{
+        get_test_data_dir, BuildNumberSpec, ChannelSpec, NamelessMatchSpec, PackageRecord, Version,
+        VersionSpec,
+    };
     use smallvec::smallvec;
 
     #[test]
@@ -573,24 +603,90 @@ mod tests {
         let spec = MatchSpec::from_str("conda-forge::foo[version=\"1.0.*\"]").unwrap();
         assert_eq!(spec.name, Some("foo".parse().unwrap()));
         assert_eq!(spec.version, Some(VersionSpec::from_str("1.0.*").unwrap()));
-        assert_eq!(spec.channel, Some("conda-forge".to_string()));
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec::from_str("conda-forge").unwrap())
+        );
 
         let spec = MatchSpec::from_str("conda-forge::foo[version=1.0.*]").unwrap();
         assert_eq!(spec.name, Some("foo".parse().unwrap()));
         assert_eq!(spec.version, Some(VersionSpec::from_str("1.0.*").unwrap()));
-        assert_eq!(spec.channel, Some("conda-forge".to_string()));
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec::from_str("conda-forge").unwrap())
+        );
 
         let spec =
             MatchSpec::from_str(r#"conda-forge::foo[version=1.0.*, build_number=">6"]"#).unwrap();
         assert_eq!(spec.name, Some("foo".parse().unwrap()));
         assert_eq!(spec.version, Some(VersionSpec::from_str("1.0.*").unwrap()));
-        assert_eq!(spec.channel, Some("conda-forge".to_string()));
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec::from_str("conda-forge").unwrap())
+        );
         assert_eq!(
             spec.build_number,
             Some(BuildNumberSpec::from_str(">6").unwrap())
         );
     }
 
+    #[test]
+    fn test_channel_spec() {
+        let spec = MatchSpec::from_str("conda-forge/label/rust_dev::rust").unwrap();
+        assert_eq!(spec.name, Some("rust".parse().unwrap()));
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec {
+                channel: String::from("conda-forge"),
+                label: Some(String::from("rust_dev")),
+                subdir: None,
+            })
+        );
+        assert_eq!(spec.to_string(), "conda-forge/label/rust_dev::rust");
+
+        let spec = MatchSpec::from_str("pkgs/main/linux-64::python >=3.8").unwrap();
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec {
+                channel: String::from("pkgs/main"),
+                label: None,
+                subdir: Some(String::from("linux-64")),
+            })
+        );
+        assert_eq!(spec.to_string(), "pkgs/main/linux-64::python >=3.8");
+
+        let spec =
+            MatchSpec::from_str("https://conda.anaconda.org/conda-forge/noarch::python").unwrap();
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec {
+                channel: String::from("https://conda.anaconda.org/conda-forge"),
+                label: None,
+                subdir: Some(String::from("noarch")),
+            })
+        );
+
+        // Brackets override the channel and subdir in front of the name.
+        let spec = MatchSpec::from_str("conda-forge::python[subdir=linux-64]").unwrap();
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec {
+                channel: String::from("conda-forge"),
+                label: None,
+                subdir: Some(String::from("linux-64")),
+            })
+        );
+        let spec = MatchSpec::from_str("python[channel=conda-forge/label/dev]").unwrap();
+        assert_eq!(
+            spec.channel,
+            Some(ChannelSpec {
+                channel: String::from("conda-forge"),
+                label: Some(String::from("dev")),
+                subdir: None,
+            })
+        );
+    }
+
     #[test]
     fn test_hash_spec() {
         let spec = MatchSpec::from_str("conda-forge::foo[md5=1234567890]");
@@ -618,6 +714,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_license_spec() {
+        let spec = MatchSpec::from_str(r#"foo[license="MIT OR Apache-2.0"]"#).unwrap();
+        assert_eq!(spec.license.as_deref(), Some("MIT OR Apache-2.0"));
+        assert_eq!(MatchSpec::from_str(&spec.to_string()).unwrap(), spec);
+
+        assert_matches!(
+            MatchSpec::from_str("foo[licence=MIT]"),
+            Err(ParseMatchSpecError::InvalidBracketKey(key)) if key == "licence"
+        );
+    }
+
     #[test]
     fn test_parse_bracket_list() {
         assert_eq!(
@@ -705,4 +813,61 @@ mod tests {
             .collect();
         insta::assert_yaml_snapshot!("parsed matchspecs", evaluated);
     }
+
+    /// Returns the lines of a file in the test data directory, skipping comments and empty lines.
+    fn read_test_data_lines(file_name: &str) -> Vec<String> {
+        std::fs::read_to_string(get_test_data_dir().join(file_name))
+            .unwrap()
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#') && !line.trim().is_empty())
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    /// Parses all the `depends` and `constrains` of the packages in a conda-forge channel and
+    /// checks that formatting the parsed specs results in the same spec.
+    #[test]
+    fn test_parse_corpus() {
+        let mut failures = Vec::new();
+        for spec_str in read_test_data_lines("match_specs.txt") {
+            let spec = match MatchSpec::from_str(&spec_str) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    failures.push(format!("{spec_str}: {e}"));
+                    continue;
+                }
+            };
+            match MatchSpec::from_str(&spec.to_string()) {
+                Ok(reparsed) if reparsed == spec => {}
+                Ok(reparsed) => failures.push(format!(
+                    "{spec_str}: formatted as '{spec}' which parses as '{reparsed}'"
+                )),
+                Err(e) => failures.push(format!("{spec_str}: formatted as '{spec}': {e}")),
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    /// Checks that a sample of the specs in `match_specs.txt` match the same packages as they do
+    /// in Conda.
+    #[test]
+    fn test_matches_like_conda() {
+        let mut failures = Vec::new();
+        for line in read_test_data_lines("match_spec_conda_matches.txt") {
+            let [spec_str, version, build, expected]: [&str; 4] =
+                line.split('\t').collect::<Vec<_>>().try_into().unwrap();
+            let spec = MatchSpec::from_str(spec_str).unwrap();
+            let record = PackageRecord::new(
+                spec.name.clone().unwrap(),
+                Version::from_str(version).unwrap(),
+                build.to_owned(),
+            );
+            if spec.matches(&record).to_string() != expected {
+                failures.push(format!(
+                    "{spec_str} should match {version} {build}: {expected}"
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
 }
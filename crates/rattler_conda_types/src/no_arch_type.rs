@@ -1,7 +1,7 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Noarch packages are packages that are not architecture specific and therefore only have to be
-/// built once. Noarch packages are either generic or Python.
+/// built once. Noarch packages are either generic or specific to an interpreter (Python, R or Lua).
 ///
 /// This type describes the exact form in which the `noarch` was specified in a package record. Use
 /// the [`NoArchType`] and [`NoArchKind`] for a higher level API.
@@ -17,6 +17,12 @@ pub enum RawNoArchType {
 
     /// A noarch python package.
     Python,
+
+    /// A noarch R package.
+    R,
+
+    /// A noarch Lua package.
+    Lua,
 }
 
 /// Noarch packages are packages that are not architecture specific and therefore only have to be
@@ -40,6 +46,8 @@ impl NoArchType {
                 Some(NoArchKind::Generic)
             }
             Some(RawNoArchType::Python) => Some(NoArchKind::Python),
+            Some(RawNoArchType::R) => Some(NoArchKind::R),
+            Some(RawNoArchType::Lua) => Some(NoArchKind::Lua),
         }
     }
 
@@ -58,6 +66,16 @@ impl NoArchType {
         Self(Some(RawNoArchType::Python))
     }
 
+    /// Constructs an R noarch instance.
+    pub fn r() -> Self {
+        Self(Some(RawNoArchType::R))
+    }
+
+    /// Constructs a Lua noarch instance.
+    pub fn lua() -> Self {
+        Self(Some(RawNoArchType::Lua))
+    }
+
     /// Constructs a Generic noarch instance.
     pub fn generic() -> Self {
         Self(Some(RawNoArchType::GenericV2))
@@ -74,6 +92,8 @@ impl From<Option<NoArchKind>> for NoArchType {
     fn from(noarch: Option<NoArchKind>) -> Self {
         NoArchType(noarch.map(|noarch| match noarch {
             NoArchKind::Python => RawNoArchType::Python,
+            NoArchKind::R => RawNoArchType::R,
+            NoArchKind::Lua => RawNoArchType::Lua,
             NoArchKind::Generic => RawNoArchType::GenericV2,
         }))
     }
@@ -103,6 +123,17 @@ pub enum NoArchKind {
     /// <https://docs.conda.io/projects/conda/en/latest/user-guide/concepts/packages.html#noarch-python>
     Python,
 
+    /// A noarch R package contains R source code that is installed into the R library of the
+    /// environment (`lib/R/library`). Files in the `library/` directory of the package are linked
+    /// into the library and files in the `scripts/` directory are linked into the binary directory.
+    R,
+
+    /// A noarch Lua package contains Lua modules that are installed into the module directory of
+    /// the Lua version in the environment (`share/lua/<version>`). Files in the `lua/` directory of
+    /// the package are linked into the module directory and files in the `scripts/` directory are
+    /// linked into the binary directory.
+    Lua,
+
     /// Noarch generic packages allow users to distribute docs, datasets, and source code in conda
     /// packages.
     Generic,
@@ -125,6 +156,8 @@ impl<'de> Deserialize<'de> for NoArchType {
         #[serde(rename_all = "lowercase")]
         enum NoArchTypeSerde {
             Python,
+            R,
+            Lua,
             Generic,
         }
 
@@ -133,6 +166,8 @@ impl<'de> Deserialize<'de> for NoArchType {
             NoArchSerde::OldFormat(true) => Some(RawNoArchType::GenericV1),
             NoArchSerde::OldFormat(false) => None,
             NoArchSerde::NewFormat(NoArchTypeSerde::Python) => Some(RawNoArchType::Python),
+            NoArchSerde::NewFormat(NoArchTypeSerde::R) => Some(RawNoArchType::R),
+            NoArchSerde::NewFormat(NoArchTypeSerde::Lua) => Some(RawNoArchType::Lua),
             NoArchSerde::NewFormat(NoArchTypeSerde::Generic) => Some(RawNoArchType::GenericV2),
         })))
     }
@@ -148,6 +183,8 @@ impl Serialize for NoArchType {
             Some(RawNoArchType::GenericV1) => true.serialize(serializer),
             Some(RawNoArchType::GenericV2) => "generic".serialize(serializer),
             Some(RawNoArchType::Python) => "python".serialize(serializer),
+            Some(RawNoArchType::R) => "r".serialize(serializer),
+            Some(RawNoArchType::Lua) => "lua".serialize(serializer),
         }
     }
 }
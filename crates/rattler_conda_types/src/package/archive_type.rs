@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use std::path::Path;
 
 /// Describes the type of package archive. This can be derived from the file extension of a package.
@@ -37,3 +38,54 @@ impl ArchiveType {
         }
     }
 }
+
+/// Describes a type of archive that is not a Conda package but that is easily mistaken for one,
+/// for instance when a package url is copied from another package index. Recognizing these
+/// archives allows reporting an actionable error instead of a generic one.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NonCondaArchiveType {
+    /// A Python wheel (`.whl`).
+    PythonWheel,
+
+    /// A Python source distribution (`.tar.gz` or `.zip`).
+    PythonSourceDistribution,
+
+    /// An npm package tarball (`.tgz`).
+    NpmTarball,
+}
+
+impl NonCondaArchiveType {
+    /// Tries to determine the type of a non-Conda archive from its filename.
+    pub fn try_from(path: impl AsRef<Path>) -> Option<NonCondaArchiveType> {
+        let path = path.as_ref().to_string_lossy();
+        if path.ends_with(".whl") {
+            Some(NonCondaArchiveType::PythonWheel)
+        } else if path.ends_with(".tar.gz") || path.ends_with(".zip") {
+            Some(NonCondaArchiveType::PythonSourceDistribution)
+        } else if path.ends_with(".tgz") {
+            Some(NonCondaArchiveType::NpmTarball)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a suggestion on how the archive can be installed instead.
+    pub fn suggestion(self) -> &'static str {
+        match self {
+            NonCondaArchiveType::PythonWheel | NonCondaArchiveType::PythonSourceDistribution => {
+                "install it with pip instead, for instance by adding it to the `pip` section of an environment.yml file"
+            }
+            NonCondaArchiveType::NpmTarball => "install it with npm instead",
+        }
+    }
+}
+
+impl Display for NonCondaArchiveType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NonCondaArchiveType::PythonWheel => "a Python wheel",
+            NonCondaArchiveType::PythonSourceDistribution => "a Python source distribution",
+            NonCondaArchiveType::NpmTarball => "an npm package",
+        })
+    }
+}
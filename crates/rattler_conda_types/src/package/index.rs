@@ -1,7 +1,11 @@
-use std::path::Path;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
 
 use super::PackageFile;
-use crate::{NoArchType, PackageName, VersionWithSource};
+use crate::{NoArchType, PackageName, PackageRecord, Platform, Timestamp, VersionWithSource};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none, OneOrMany};
 
@@ -59,8 +63,7 @@ pub struct IndexJson {
     pub subdir: Option<String>,
 
     /// The timestamp when this package was created
-    #[serde_as(as = "Option<crate::utils::serde::Timestamp>")]
-    pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    pub timestamp: Option<Timestamp>,
 
     /// Track features are nowadays only used to downweight packages (ie. give them less priority). To
     /// that effect, the number of track features is counted (number of commas) and the package is downweighted
@@ -73,6 +76,82 @@ pub struct IndexJson {
     pub version: VersionWithSource,
 }
 
+impl IndexJson {
+    /// Constructs an `IndexJson` with the bare minimum values for a package that is built for the
+    /// current platform.
+    pub fn new(name: PackageName, version: impl Into<VersionWithSource>, build: String) -> Self {
+        let platform = Platform::current();
+        Self {
+            arch: platform.arch().map(|arch| arch.to_string()),
+            build,
+            build_number: 0,
+            constrains: Vec::new(),
+            depends: Vec::new(),
+            features: None,
+            license: None,
+            license_family: None,
+            name,
+            noarch: NoArchType::none(),
+            platform: platform.only_platform().map(ToOwned::to_owned),
+            subdir: Some(platform.to_string()),
+            timestamp: None,
+            track_features: Vec::new(),
+            version: version.into(),
+        }
+    }
+
+    /// Writes the contents of this instance to the file at the specified location, e.g. the
+    /// `info/index.json` file of a package that is being built.
+    pub fn write_to_path(
+        &self,
+        path: impl AsRef<Path>,
+        pretty: bool,
+    ) -> Result<(), std::io::Error> {
+        self.write_to(File::create(path)?, pretty)
+    }
+
+    /// Writes the contents of this instance as JSON to the given writer. The writer is flushed
+    /// explicitly, so an error that occurs while writing the last buffered bytes is returned
+    /// instead of being ignored when the buffer is dropped.
+    pub fn write_to(
+        &self,
+        writer: impl std::io::Write,
+        pretty: bool,
+    ) -> Result<(), std::io::Error> {
+        let mut writer = BufWriter::new(writer);
+        if pretty {
+            serde_json::to_writer_pretty(&mut writer, self)?
+        } else {
+            serde_json::to_writer(&mut writer, self)?
+        }
+        writer.flush()
+    }
+}
+
+impl From<&PackageRecord> for IndexJson {
+    /// Constructs the `index.json` of the package described by a record. The information that is
+    /// only stored in the repodata, like the hashes of the archive, is dropped.
+    fn from(record: &PackageRecord) -> Self {
+        Self {
+            arch: record.arch.clone(),
+            build: record.build.clone(),
+            build_number: record.build_number,
+            constrains: record.constrains.clone(),
+            depends: record.depends.clone(),
+            features: record.features.clone(),
+            license: record.license.clone(),
+            license_family: record.license_family.clone(),
+            name: record.name.clone(),
+            noarch: record.noarch,
+            platform: record.platform.clone(),
+            subdir: Some(record.subdir.clone()),
+            timestamp: record.timestamp,
+            track_features: record.track_features.clone(),
+            version: record.version.clone(),
+        }
+    }
+}
+
 impl PackageFile for IndexJson {
     fn package_path() -> &'static Path {
         Path::new("info/index.json")
@@ -86,6 +165,52 @@ impl PackageFile for IndexJson {
 #[cfg(test)]
 mod test {
     use super::{IndexJson, PackageFile};
+    use crate::{NoArchType, PackageName, PackageRecord};
+
+    #[test]
+    pub fn test_write_index_json() {
+        let mut index = IndexJson::new(
+            PackageName::new_unchecked("foo"),
+            "1.0".parse::<crate::Version>().unwrap(),
+            String::from("py_0"),
+        );
+        index.noarch = NoArchType::python();
+        index.depends.push(String::from("python >=3.8"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        index.write_to_path(&path, true).unwrap();
+        assert_eq!(IndexJson::from_path(&path).unwrap(), index);
+
+        // Converting to a record and back retains all the information.
+        let record = PackageRecord::from_index_json(index.clone(), None, None, None).unwrap();
+        assert_eq!(IndexJson::from(&record), index);
+    }
+
+    #[test]
+    pub fn test_write_index_json_flush_error() {
+        /// A writer that fails to write any bytes.
+        struct FullDisk;
+
+        impl std::io::Write for FullDisk {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // The JSON fits in the buffer, so the error only occurs when it is flushed.
+        let index = IndexJson::new(
+            PackageName::new_unchecked("foo"),
+            "1.0".parse::<crate::Version>().unwrap(),
+            String::from("py_0"),
+        );
+        let err = index.write_to(FullDisk, false).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+    }
 
     #[test]
     pub fn test_reconstruct_index_json() {
@@ -1,6 +1,7 @@
 use super::PackageFile;
 use crate::package::has_prefix::HasPrefixEntry;
 use crate::package::{Files, HasPrefix, NoLink, NoSoftlink};
+use crate::{InvalidPrefixRelativePathError, PrefixRelativePath};
 use rattler_digest::serde::SerializableHash;
 use rattler_macros::sorted;
 use serde::{Deserialize, Serialize, Serializer};
@@ -73,7 +74,9 @@ impl PathsJson {
     /// - The `no_softlink` file contains files that should not be soft-linked.
     /// - The `path_type` is a function to determine which type of file a specific path is.
     ///   Typically you would implement this with a function to check the filesystem.
-    pub fn from_deprecated<E>(
+    ///
+    /// An error is returned if one of the files is not a valid [`PrefixRelativePath`].
+    pub fn from_deprecated<E: From<InvalidPrefixRelativePathError>>(
         files: Files,
         has_prefix: Option<HasPrefix>,
         no_link: Option<NoLink>,
@@ -105,24 +108,20 @@ impl PathsJson {
             paths: files
                 .files
                 .into_iter()
-                .map(|path| {
+                .map(|path| -> Result<PathsEntry, E> {
                     let prefix = has_prefix.get(&path);
-                    let path_type = path_type(&path);
-
-                    match path_type {
-                        Ok(path_type) => Ok(PathsEntry {
-                            path_type,
-                            prefix_placeholder: prefix.map(|entry| PrefixPlaceholder {
-                                file_mode: entry.file_mode,
-                                placeholder: entry.prefix.as_ref().to_owned(),
-                            }),
-                            no_link: no_link.contains(&path),
-                            sha256: None,
-                            size_in_bytes: None,
-                            relative_path: path,
+                    let path_type = path_type(&path)?;
+                    Ok(PathsEntry {
+                        path_type,
+                        prefix_placeholder: prefix.map(|entry| PrefixPlaceholder {
+                            file_mode: entry.file_mode,
+                            placeholder: entry.prefix.as_ref().to_owned(),
                         }),
-                        Err(e) => Err(e),
-                    }
+                        no_link: no_link.contains(&path),
+                        sha256: None,
+                        size_in_bytes: None,
+                        relative_path: PrefixRelativePath::new(path)?,
+                    })
                 })
                 .collect::<Result<_, _>>()?,
             paths_version: 1,
@@ -190,9 +189,10 @@ pub struct PathsEntry {
     // The alphabetical order of the fields is important for the serialization of the struct.
     // ['_path', 'no_link', 'path_type', 'prefix_placeholder', 'sha256', 'size_in_bytes']
     // rename can't be sorted by the macro yet.
-    /// The relative path from the root of the package
+    /// The relative path from the root of the package. Paths that are absolute or that refer to a
+    /// parent directory are rejected when the file is parsed.
     #[serde(rename = "_path")]
-    pub relative_path: PathBuf,
+    pub relative_path: PrefixRelativePath,
 
     /// Whether or not this file should be linked or not when installing the package.
     #[serde(
@@ -256,6 +256,7 @@ fn is_no_link_default(value: &bool) -> bool {
 #[cfg(test)]
 mod test {
     use crate::package::PackageFile;
+    use crate::PrefixRelativePath;
 
     use super::{PathsEntry, PathsJson};
 
@@ -273,6 +274,16 @@ mod test {
         insta::assert_yaml_snapshot!(paths_json);
     }
 
+    #[test]
+    pub fn test_reject_paths_outside_of_prefix() {
+        for path in ["../../etc/passwd", "/etc/passwd"] {
+            let paths_json = format!(
+                r#"{{"paths": [{{"_path": "{path}", "path_type": "hardlink"}}], "paths_version": 1}}"#
+            );
+            assert!(PathsJson::from_str(&paths_json).is_err(), "{path}");
+        }
+    }
+
     #[test]
     pub fn test_reconstruct_paths_json() {
         let package_dir = tempfile::tempdir().unwrap();
@@ -312,7 +323,7 @@ mod test {
         let mut paths = vec![];
         for i in 0..15 {
             paths.push(PathsEntry {
-                relative_path: format!("path_{}", i).into(),
+                relative_path: PrefixRelativePath::new(format!("path_{}", i)).unwrap(),
                 path_type: super::PathType::HardLink,
                 prefix_placeholder: None,
                 no_link: false,
@@ -0,0 +1,97 @@
+//! The `conda-meta/pinned` file of a prefix contains specs that constrain the packages that can be
+//! installed into that prefix. Every line of the file contains a single match spec. Empty lines and
+//! lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Never update python beyond 3.11
+//! python 3.11.*
+//! numpy <2
+//! ```
+
+use crate::{MatchSpec, ParseMatchSpecError};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The specs that are pinned in a prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinnedSpecs {
+    /// The pinned specs in the order in which they appear in the file.
+    pub specs: Vec<MatchSpec>,
+}
+
+/// An error that can occur when reading the `conda-meta/pinned` file.
+#[derive(Debug, thiserror::Error)]
+pub enum ParsePinnedSpecsError {
+    /// One of the lines is not a valid match spec.
+    #[error("invalid pinned spec '{0}'")]
+    InvalidMatchSpec(String, #[source] ParseMatchSpecError),
+
+    /// An IO error occurred
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+impl PinnedSpecs {
+    /// Returns the path of the file that contains the pinned specs of a prefix.
+    pub fn path_in_prefix(prefix: &Path) -> PathBuf {
+        prefix.join("conda-meta").join("pinned")
+    }
+
+    /// Reads the pinned specs of the given prefix. Returns an empty set of specs if the prefix does
+    /// not contain a `conda-meta/pinned` file.
+    pub fn from_prefix(prefix: &Path) -> Result<Self, ParsePinnedSpecsError> {
+        match std::fs::read_to_string(Self::path_in_prefix(prefix)) {
+            Ok(contents) => Self::from_str(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl FromStr for PinnedSpecs {
+    type Err = ParsePinnedSpecsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let specs = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                MatchSpec::from_str(line)
+                    .map_err(|e| ParsePinnedSpecsError::InvalidMatchSpec(line.to_owned(), e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { specs })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PinnedSpecs;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_pinned_specs() {
+        let pinned = PinnedSpecs::from_str(
+            "# Never update python beyond 3.11\npython 3.11.*\n\n  numpy <2\n",
+        )
+        .unwrap();
+        let specs = pinned
+            .specs
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(specs, ["python 3.11.*", "numpy <2"]);
+
+        assert!(PinnedSpecs::from_str("python ==").is_err());
+    }
+
+    #[test]
+    fn test_missing_pinned_file() {
+        let prefix = tempfile::tempdir().unwrap();
+        assert_eq!(
+            PinnedSpecs::from_prefix(prefix.path()).unwrap(),
+            PinnedSpecs::default()
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Rules that restrict which packages may end up in a solution, e.g. to forbid packages with a
+//! copyleft license or packages from untrusted channels.
+//!
+//! The solvers of `rattler_solve` never select records that violate the [`Policy`] of a solver
+//! task, and a spec that requests a denied package or a solution that contains a violating package
+//! (e.g. because it is pinned) results in an error. Transactions that are planned with a policy
+//! refuse to install violating packages as well, so the policy is never violated silently.
+
+use crate::{MatchSpec, RepoDataRecord, StringMatcher};
+use thiserror::Error;
+
+/// Rules that determine which packages are allowed to be part of a solution. The default policy
+/// allows all packages.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Packages whose name matches any of these matchers are never installed, e.g. `openssl` or
+    /// `*-gpl`.
+    pub denied_packages: Vec<StringMatcher>,
+
+    /// If set, only packages with one of these license families (e.g. `MIT` or `BSD`) are
+    /// installed. License families are compared case-insensitively. Packages that do not specify
+    /// a license family are not allowed.
+    pub allowed_license_families: Option<Vec<String>>,
+
+    /// If set, only packages that are downloaded from one of these hosts (e.g.
+    /// `conda.anaconda.org`) are installed.
+    pub allowed_channel_hosts: Option<Vec<String>>,
+}
+
+/// Describes why a package is not allowed by a [`Policy`].
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum PolicyViolation {
+    /// The name of the package is denied.
+    #[error("{package} is denied")]
+    DeniedPackage {
+        /// The package, formatted as a spec or as `name-version-build`.
+        package: String,
+    },
+
+    /// The license family of the package is not allowed.
+    #[error(
+        "{package} has license family '{}' which is not allowed",
+        .license_family.as_deref().unwrap_or("unknown")
+    )]
+    DisallowedLicenseFamily {
+        /// The package, formatted as `name-version-build`.
+        package: String,
+
+        /// The license family of the package, if any.
+        license_family: Option<String>,
+    },
+
+    /// The package comes from a host that is not allowed.
+    #[error("{package} is downloaded from '{url}' which is not an allowed host")]
+    DisallowedChannelHost {
+        /// The package, formatted as `name-version-build`.
+        package: String,
+
+        /// The url of the package.
+        url: String,
+    },
+}
+
+impl Policy {
+    /// Returns true if this policy allows every package.
+    pub fn is_empty(&self) -> bool {
+        self.denied_packages.is_empty()
+            && self.allowed_license_families.is_none()
+            && self.allowed_channel_hosts.is_none()
+    }
+
+    /// Checks whether the given record is allowed by this policy.
+    pub fn check(&self, record: &RepoDataRecord) -> Result<(), PolicyViolation> {
+        let package_record = &record.package_record;
+        let package = || {
+            format!(
+                "{}-{}-{}",
+                package_record.name.as_normalized(),
+                package_record.version,
+                package_record.build
+            )
+        };
+
+        if self.is_denied(package_record.name.as_normalized()) {
+            return Err(PolicyViolation::DeniedPackage { package: package() });
+        }
+
+        if let Some(allowed) = &self.allowed_license_families {
+            let license_family = package_record.license_family.as_deref();
+            if !license_family.is_some_and(|family| {
+                allowed
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(family))
+            }) {
+                return Err(PolicyViolation::DisallowedLicenseFamily {
+                    package: package(),
+                    license_family: license_family.map(ToOwned::to_owned),
+                });
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_channel_hosts {
+            let host = record.url.host_str();
+            if !host.is_some_and(|host| allowed.iter().any(|allowed| allowed == host)) {
+                return Err(PolicyViolation::DisallowedChannelHost {
+                    package: package(),
+                    url: record.url.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the given record is allowed by this policy.
+    pub fn allows(&self, record: &RepoDataRecord) -> bool {
+        self.check(record).is_ok()
+    }
+
+    /// Returns the violations of the specs that request a package whose name is denied.
+    pub fn check_specs(&self, specs: &[MatchSpec]) -> Vec<PolicyViolation> {
+        specs
+            .iter()
+            .filter(|spec| {
+                spec.name
+                    .as_ref()
+                    .is_some_and(|name| self.is_denied(name.as_normalized()))
+            })
+            .map(|spec| PolicyViolation::DeniedPackage {
+                package: spec.to_string(),
+            })
+            .collect()
+    }
+
+    /// Returns the violations of the given records, e.g. of the records of a solution.
+    pub fn check_records<'a>(
+        &self,
+        records: impl IntoIterator<Item = &'a RepoDataRecord>,
+    ) -> Vec<PolicyViolation> {
+        records
+            .into_iter()
+            .filter_map(|record| self.check(record).err())
+            .collect()
+    }
+
+    /// Returns true if a package with the given name is denied.
+    fn is_denied(&self, name: &str) -> bool {
+        self.denied_packages
+            .iter()
+            .any(|matcher| matcher.matches(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Policy, PolicyViolation};
+    use crate::{test_utils::RecordBuilder, MatchSpec};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy {
+            denied_packages: vec!["openssl".parse().unwrap(), "*-gpl".parse().unwrap()],
+            allowed_license_families: Some(vec![String::from("MIT"), String::from("BSD")]),
+            allowed_channel_hosts: Some(vec![String::from("conda.anaconda.org")]),
+        };
+
+        assert!(Policy::default().is_empty());
+        assert!(Policy::default().allows(
+            &RecordBuilder::new("openssl", "1.0")
+                .with_channel("https://example.com/conda-forge/")
+                .build()
+        ));
+
+        assert!(policy.allows(
+            &RecordBuilder::new("zlib", "1.0")
+                .with_license_family("mit")
+                .build()
+        ));
+        assert_eq!(
+            policy.check(
+                &RecordBuilder::new("readline-gpl", "1.0")
+                    .with_license_family("MIT")
+                    .build()
+            ),
+            Err(PolicyViolation::DeniedPackage {
+                package: String::from("readline-gpl-1.0-0")
+            })
+        );
+        assert!(matches!(
+            policy.check(
+                &RecordBuilder::new("readline", "1.0")
+                    .with_license_family("GPL3")
+                    .build()
+            ),
+            Err(PolicyViolation::DisallowedLicenseFamily { license_family: Some(family), .. })
+                if family == "GPL3"
+        ));
+        assert!(matches!(
+            policy.check(&RecordBuilder::new("readline", "1.0").build()),
+            Err(PolicyViolation::DisallowedLicenseFamily {
+                license_family: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            policy.check(
+                &RecordBuilder::new("zlib", "1.0")
+                    .with_license_family("MIT")
+                    .with_channel("https://example.com/conda-forge/")
+                    .build()
+            ),
+            Err(PolicyViolation::DisallowedChannelHost { .. })
+        ));
+
+        let violations = policy.check_specs(&[
+            MatchSpec::from_str("python >=3.10").unwrap(),
+            MatchSpec::from_str("openssl").unwrap(),
+        ]);
+        assert_eq!(
+            violations,
+            vec![PolicyViolation::DeniedPackage {
+                package: String::from("openssl")
+            }]
+        );
+    }
+}
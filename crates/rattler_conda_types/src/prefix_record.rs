@@ -1,13 +1,14 @@
 //! Defines the `[PrefixRecord]` struct.
 
+use crate::package::FileMode;
 use crate::repo_data_record::RepoDataRecord;
-use crate::PackageRecord;
+use crate::{PackageRecord, PrefixRelativePath};
 use rattler_digest::serde::SerializableHash;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_with::serde_as;
 use std::fs::File;
-use std::io::{BufWriter, Read};
+use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
@@ -52,8 +53,12 @@ impl From<Vec<PathsEntry>> for PrefixPaths {
 #[serde_as]
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct PathsEntry {
-    /// The relative path from the root of the package
-    #[serde(rename = "_path")]
+    /// The relative path from the root of the package. The path is validated to be a
+    /// [`PrefixRelativePath`] when the record is deserialized.
+    #[serde(
+        rename = "_path",
+        deserialize_with = "deserialize_prefix_relative_path"
+    )]
     pub relative_path: PathBuf,
 
     /// How the file was installed into the environment
@@ -79,6 +84,16 @@ pub struct PathsEntry {
     /// The size of the file in bytes
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub size_in_bytes: Option<u64>,
+
+    /// The placeholder prefix in the package that was replaced by the path of the prefix when
+    /// the file was installed. Files with a placeholder contain the path of the prefix and have to
+    /// be rewritten when the prefix is moved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix_placeholder: Option<String>,
+
+    /// Whether the placeholder was replaced in a text or in a binary file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_mode: Option<FileMode>,
 }
 
 /// Information about a single file installed for a package.
@@ -136,15 +151,16 @@ pub struct PrefixRecord {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub extracted_package_dir: Option<PathBuf>,
 
-    /// A sorted list of all files included in this package
-    #[serde(default)]
+    /// A sorted list of all files included in this package. The paths are validated to be
+    /// [`PrefixRelativePath`]s when the record is deserialized.
+    #[serde(default, deserialize_with = "deserialize_prefix_relative_paths")]
     pub files: Vec<PathBuf>,
 
     /// Information about how files have been linked when installing the package.
     #[serde(default)]
     pub paths_data: PrefixPaths,
 
-    /// TODO: I dont understand this field
+    /// Information about where the package was linked from and how its files were linked.
     pub link: Option<Link>,
 
     /// The spec that was used when this package was installed. Note that this field is not updated if the
@@ -153,6 +169,65 @@ pub struct PrefixRecord {
 }
 
 impl PrefixRecord {
+    /// Constructs a record for a package that has been linked into a prefix. `paths` describes
+    /// the files that were linked, the sorted list of [`PrefixRecord::files`] is derived from it.
+    pub fn from_repodata_record(
+        repodata_record: RepoDataRecord,
+        package_tarball_full_path: Option<PathBuf>,
+        extracted_package_dir: Option<PathBuf>,
+        paths: Vec<PathsEntry>,
+        requested_spec: Option<String>,
+        link: Option<Link>,
+    ) -> Self {
+        let mut files: Vec<_> = paths
+            .iter()
+            .map(|entry| entry.relative_path.clone())
+            .collect();
+        files.sort();
+
+        Self {
+            repodata_record,
+            package_tarball_full_path,
+            extracted_package_dir,
+            files,
+            paths_data: paths.into(),
+            link,
+            requested_spec,
+        }
+    }
+
+    /// Returns the name of the file in the `conda-meta` directory of a prefix that stores this
+    /// record, e.g. `python-3.11.0-h7a1cb2a_2.json`.
+    pub fn file_name(&self) -> String {
+        let package_record = &self.repodata_record.package_record;
+        format!(
+            "{}-{}-{}.json",
+            package_record.name.as_normalized(),
+            package_record.version,
+            package_record.build
+        )
+    }
+
+    /// Writes this record to the `conda-meta` directory of the given prefix, creating the
+    /// directory if it does not exist yet. Returns the path of the written file.
+    ///
+    /// The record is first written to a temporary file which is flushed to disk and then moved
+    /// into place. An interrupted write therefore never leaves a truncated record behind, which
+    /// conda and other tools would consider to be an installed package.
+    pub fn write_to_prefix(&self, prefix: &Path) -> Result<PathBuf, std::io::Error> {
+        let conda_meta = prefix.join("conda-meta");
+        std::fs::create_dir_all(&conda_meta)?;
+
+        let path = conda_meta.join(self.file_name());
+        let partial_path = path.with_extension("json.partial");
+        let file = File::create(&partial_path)?;
+        self.write_to(&file, true)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(partial_path, &path)?;
+        Ok(path)
+    }
+
     /// Parses a `paths.json` file from a reader.
     pub fn from_reader(mut reader: impl Read) -> Result<Self, std::io::Error> {
         let mut str = String::new();
@@ -165,6 +240,25 @@ impl PrefixRecord {
         Self::from_reader(File::open(path.as_ref())?)
     }
 
+    /// Reads all the records from the `conda-meta` directory of the given prefix. Returns an empty
+    /// list if the prefix does not contain a `conda-meta` directory.
+    pub fn collect_from_prefix(prefix: &Path) -> Result<Vec<Self>, std::io::Error> {
+        let conda_meta = match std::fs::read_dir(prefix.join("conda-meta")) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut records = Vec::new();
+        for entry in conda_meta {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                records.push(Self::from_path(&path)?);
+            }
+        }
+        Ok(records)
+    }
+
     /// Writes the contents of this instance to the file at the specified location.
     pub fn write_to_path(self, path: impl AsRef<Path>, pretty: bool) -> Result<(), std::io::Error> {
         self.write_to(File::create(path)?, pretty)
@@ -176,12 +270,13 @@ impl PrefixRecord {
         writer: impl std::io::Write,
         pretty: bool,
     ) -> Result<(), std::io::Error> {
+        let mut writer = BufWriter::new(writer);
         if pretty {
-            serde_json::to_writer_pretty(BufWriter::new(writer), self)?
+            serde_json::to_writer_pretty(&mut writer, self)?
         } else {
-            serde_json::to_writer(BufWriter::new(writer), self)?
+            serde_json::to_writer(&mut writer, self)?
         }
-        Ok(())
+        writer.flush()
     }
 }
 
@@ -193,13 +288,13 @@ impl FromStr for PrefixRecord {
     }
 }
 
-/// A record of a single file that was installed into the prefix
+/// Describes where a package was linked from and how its files were linked into the prefix.
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
 pub struct Link {
-    /// The path to the file source that was installed
+    /// The path of the extracted package the files were linked from
     pub source: String,
 
-    /// The link type that was used to install the file
+    /// The link type that was used to install the files of the package
     #[serde(rename = "type")]
     pub link_type: Option<LinkType>,
 }
@@ -219,6 +314,26 @@ pub enum LinkType {
     Directory = 4,
 }
 
+/// Deserializes a path that must be a [`PrefixRelativePath`]. The files of a record are removed
+/// when the package is uninstalled, so a path outside of the prefix is rejected.
+fn deserialize_prefix_relative_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PathBuf, D::Error> {
+    PrefixRelativePath::deserialize(deserializer).map(PrefixRelativePath::into_path_buf)
+}
+
+/// Deserializes a list of paths that must all be [`PrefixRelativePath`]s.
+fn deserialize_prefix_relative_paths<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PathBuf>, D::Error> {
+    Vec::<PrefixRelativePath>::deserialize(deserializer).map(|paths| {
+        paths
+            .into_iter()
+            .map(PrefixRelativePath::into_path_buf)
+            .collect()
+    })
+}
+
 /// Returns the default value for the "no_link" value of a [`PathsEntry`]
 fn no_link_default() -> bool {
     false
@@ -243,8 +358,11 @@ impl AsRef<PackageRecord> for PrefixRecord {
 
 #[cfg(test)]
 mod test {
+    use super::{Link, LinkType};
     use crate::get_test_data_dir;
     use rstest::rstest;
+    use std::path::PathBuf;
+    use std::str::FromStr;
 
     #[rstest]
     #[case::xz_5_2_6_h8d14728_0("xz-5.2.6-h8d14728_0.json")]
@@ -262,4 +380,67 @@ mod test {
         let prefix_record = super::PrefixRecord::from_path(path).unwrap();
         insta::assert_yaml_snapshot!(path_name.replace('.', "_"), prefix_record);
     }
+
+    #[test]
+    fn test_collect_from_prefix() {
+        let records = super::PrefixRecord::collect_from_prefix(&get_test_data_dir()).unwrap();
+        assert_eq!(records.len(), 10);
+
+        let empty_prefix = tempfile::tempdir().unwrap();
+        assert!(
+            super::PrefixRecord::collect_from_prefix(empty_prefix.path())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_write_to_prefix() {
+        let path = get_test_data_dir().join("conda-meta/xz-5.2.6-h8d14728_0.json");
+        let record = super::PrefixRecord::from_path(path).unwrap();
+        let record = super::PrefixRecord::from_repodata_record(
+            record.repodata_record,
+            None,
+            Some(PathBuf::from("/pkgs/xz-5.2.6-h8d14728_0")),
+            record.paths_data.paths.into_iter().rev().collect(),
+            Some(String::from("xz")),
+            Some(Link {
+                source: String::from("/pkgs/xz-5.2.6-h8d14728_0"),
+                link_type: Some(LinkType::HardLink),
+            }),
+        );
+        assert!(record.files.windows(2).all(|w| w[0] <= w[1]));
+
+        let prefix = tempfile::tempdir().unwrap();
+        let written = record.write_to_prefix(prefix.path()).unwrap();
+        assert_eq!(
+            written,
+            prefix.path().join("conda-meta/xz-5.2.6-h8d14728_0.json")
+        );
+        let read = super::PrefixRecord::from_path(written).unwrap();
+        assert_eq!(read.files, record.files);
+        assert_eq!(read.paths_data, record.paths_data);
+        assert_eq!(read.link, record.link);
+        assert_eq!(read.requested_spec.as_deref(), Some("xz"));
+        assert!(!prefix
+            .path()
+            .join("conda-meta/xz-5.2.6-h8d14728_0.json.partial")
+            .exists());
+    }
+
+    #[rstest]
+    #[case::absolute("/etc/passwd")]
+    #[case::parent_directory("lib/../../etc/passwd")]
+    fn test_reject_paths_outside_prefix(#[case] path: &str) {
+        let record_path = get_test_data_dir().join("conda-meta/xz-5.2.6-h8d14728_0.json");
+        let mut record: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(record_path).unwrap()).unwrap();
+
+        let mut with_path = record.clone();
+        with_path["paths_data"]["paths"][0]["_path"] = path.into();
+        assert!(super::PrefixRecord::from_str(&with_path.to_string()).is_err());
+
+        record["files"][0] = path.into();
+        assert!(super::PrefixRecord::from_str(&record.to_string()).is_err());
+    }
 }
@@ -0,0 +1,197 @@
+//! Defines [`PrefixRelativePath`], a path that is guaranteed to stay within a prefix.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// A path relative to the root of a package or a prefix, e.g. the path of a file in `paths.json`.
+///
+/// The path is guaranteed to be relative and to not contain any `..` components. Joining it to a
+/// directory therefore always results in a path inside that directory. The path is validated when
+/// it is constructed or deserialized.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "PathBuf", into = "PathBuf")]
+pub struct PrefixRelativePath(PathBuf);
+
+/// An error that is returned when a path is not a valid [`PrefixRelativePath`].
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum InvalidPrefixRelativePathError {
+    /// The path is empty.
+    #[error("the path is empty")]
+    Empty,
+
+    /// The path is absolute.
+    #[error("'{}' is not a relative path", .0.display())]
+    Absolute(PathBuf),
+
+    /// The path contains a `..` component and could refer to a location outside of the prefix.
+    #[error("'{}' refers to a parent directory", .0.display())]
+    ParentDirectory(PathBuf),
+}
+
+impl PrefixRelativePath {
+    /// Constructs a new instance after validating that the path is relative and does not contain
+    /// any `..` components.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, InvalidPrefixRelativePathError> {
+        let path = path.into();
+        if path.as_os_str().is_empty() {
+            return Err(InvalidPrefixRelativePathError::Empty);
+        }
+
+        for component in path.components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => {
+                    return Err(InvalidPrefixRelativePathError::Absolute(path))
+                }
+                Component::ParentDir => {
+                    return Err(InvalidPrefixRelativePathError::ParentDirectory(path))
+                }
+                Component::CurDir | Component::Normal(_) => {}
+            }
+        }
+
+        // Paths in packages use both separators, regardless of the current platform.
+        let str = path.to_string_lossy();
+        let has_drive_letter =
+            matches!(str.as_bytes(), [drive, b':', ..] if drive.is_ascii_alphabetic());
+        if str.starts_with(['/', '\\']) || has_drive_letter {
+            return Err(InvalidPrefixRelativePathError::Absolute(path));
+        }
+        if str.split(['/', '\\']).any(|segment| segment == "..") {
+            return Err(InvalidPrefixRelativePathError::ParentDirectory(path));
+        }
+
+        Ok(Self(path))
+    }
+
+    /// Returns the path as a [`Path`].
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Converts this instance into a [`PathBuf`].
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl Deref for PrefixRelativePath {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for PrefixRelativePath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl TryFrom<PathBuf> for PrefixRelativePath {
+    type Error = InvalidPrefixRelativePathError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&str> for PrefixRelativePath {
+    type Error = InvalidPrefixRelativePathError;
+
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        Self::new(path)
+    }
+}
+
+impl From<PrefixRelativePath> for PathBuf {
+    fn from(path: PrefixRelativePath) -> Self {
+        path.0
+    }
+}
+
+impl PartialEq<Path> for PrefixRelativePath {
+    fn eq(&self, other: &Path) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<PathBuf> for PrefixRelativePath {
+    fn eq(&self, other: &PathBuf) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<PrefixRelativePath> for PathBuf {
+    fn eq(&self, other: &PrefixRelativePath) -> bool {
+        self == &other.0
+    }
+}
+
+impl Display for PrefixRelativePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+impl From<InvalidPrefixRelativePathError> for std::io::Error {
+    fn from(err: InvalidPrefixRelativePathError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidPrefixRelativePathError, PrefixRelativePath};
+    use std::path::Path;
+
+    #[test]
+    fn test_prefix_relative_path() {
+        assert_eq!(
+            PrefixRelativePath::new("lib/python3.11/site-packages/foo.py")
+                .unwrap()
+                .as_path(),
+            Path::new("lib/python3.11/site-packages/foo.py")
+        );
+        assert!(PrefixRelativePath::new("./bin/foo").is_ok());
+        assert!(PrefixRelativePath::new("bin/foo..bar").is_ok());
+
+        assert_eq!(
+            PrefixRelativePath::new(""),
+            Err(InvalidPrefixRelativePathError::Empty)
+        );
+        for path in [
+            "/etc/passwd",
+            "\\Windows\\System32",
+            "C:\\Windows",
+            "C:/Windows",
+        ] {
+            assert!(
+                matches!(
+                    PrefixRelativePath::new(path),
+                    Err(InvalidPrefixRelativePathError::Absolute(_))
+                ),
+                "{path}"
+            );
+        }
+        for path in ["../etc/passwd", "bin/../../etc", "bin\\..\\..\\etc", ".."] {
+            assert!(
+                matches!(
+                    PrefixRelativePath::new(path),
+                    Err(InvalidPrefixRelativePathError::ParentDirectory(_))
+                ),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let path: PrefixRelativePath = serde_json::from_str("\"bin/foo\"").unwrap();
+        assert_eq!(serde_json::to_string(&path).unwrap(), "\"bin/foo\"");
+        assert!(serde_json::from_str::<PrefixRelativePath>("\"../foo\"").is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Defines [`RepoDataDiff`] which describes the differences between two snapshots of the
+//! repodata of a channel subdirectory.
+
+use super::{PackageRecord, RepoData};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The differences between two snapshots of a [`RepoData`]. Packages are identified by their
+/// filename. See [`RepoData::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RepoDataDiff {
+    /// The filenames of the packages that are only present in the new repodata, sorted.
+    pub added: Vec<String>,
+
+    /// The filenames of the packages that are only present in the old repodata, sorted.
+    pub removed: Vec<String>,
+
+    /// The packages that are present in both snapshots but whose records differ, sorted by
+    /// filename.
+    pub changed: Vec<PackageRecordDiff>,
+}
+
+/// The differences between the records of a package that is present in both snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackageRecordDiff {
+    /// The filename of the package.
+    pub file_name: String,
+
+    /// Dependencies that were added to the package.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_depends: Vec<String>,
+
+    /// Dependencies that were removed from the package.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_depends: Vec<String>,
+
+    /// Constraints that were added to the package.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_constrains: Vec<String>,
+
+    /// Constraints that were removed from the package.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_constrains: Vec<String>,
+
+    /// The other fields of the record that changed, sorted by name.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_fields: Vec<FieldChange>,
+}
+
+/// A field of a [`PackageRecord`] that has a different value in the new snapshot. Values are
+/// represented the way they are stored in `repodata.json`. A field that is absent is `null`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    /// The name of the field as it appears in `repodata.json`.
+    pub field: String,
+
+    /// The value in the old snapshot.
+    pub old: serde_json::Value,
+
+    /// The value in the new snapshot.
+    pub new: serde_json::Value,
+}
+
+impl RepoData {
+    /// Computes the differences between this (old) repodata and the `new` repodata. Both the
+    /// `.tar.bz2` and the `.conda` packages are compared.
+    pub fn diff(&self, new: &RepoData) -> RepoDataDiff {
+        let packages = |repodata: &RepoData| {
+            repodata
+                .packages
+                .iter()
+                .chain(repodata.conda_packages.iter())
+                .map(|(file_name, record)| (file_name.clone(), record.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut old = packages(self);
+        let new = packages(new);
+
+        let mut diff = RepoDataDiff::default();
+        for (file_name, new) in new {
+            match old.remove(&file_name) {
+                None => diff.added.push(file_name),
+                Some(old) => {
+                    if old != new {
+                        diff.changed
+                            .push(PackageRecordDiff::from_records(file_name, &old, &new));
+                    }
+                }
+            }
+        }
+        diff.removed = old.into_keys().collect();
+
+        diff
+    }
+}
+
+impl RepoDataDiff {
+    /// Returns true if both snapshots contain the same packages with the same records.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl PackageRecordDiff {
+    /// Computes the differences between two records of the package with the given filename.
+    pub fn from_records(file_name: String, old: &PackageRecord, new: &PackageRecord) -> Self {
+        let (added_depends, removed_depends) = list_changes(&old.depends, &new.depends);
+        let (added_constrains, removed_constrains) = list_changes(&old.constrains, &new.constrains);
+
+        // Compare the remaining fields in their serialized form so every field is covered.
+        let fields = |record: &PackageRecord| match serde_json::to_value(record) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        };
+        let old_fields = fields(old);
+        let new_fields = fields(new);
+        let names = old_fields
+            .keys()
+            .chain(new_fields.keys())
+            .filter(|name| *name != "depends" && *name != "constrains")
+            .collect::<BTreeSet<_>>();
+        let changed_fields = names
+            .into_iter()
+            .filter_map(|name| {
+                let old = old_fields.get(name).cloned().unwrap_or_default();
+                let new = new_fields.get(name).cloned().unwrap_or_default();
+                (old != new).then(|| FieldChange {
+                    field: name.clone(),
+                    old,
+                    new,
+                })
+            })
+            .collect();
+
+        Self {
+            file_name,
+            added_depends,
+            removed_depends,
+            added_constrains,
+            removed_constrains,
+            changed_fields,
+        }
+    }
+}
+
+/// Returns the entries that were added to and removed from a list, sorted.
+fn list_changes(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let old = old.iter().collect::<BTreeSet<_>>();
+    let new = new.iter().collect::<BTreeSet<_>>();
+    (
+        new.difference(&old).map(|s| (*s).clone()).collect(),
+        old.difference(&new).map(|s| (*s).clone()).collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::RepoData;
+
+    #[test]
+    fn test_repodata_diff() {
+        let old: RepoData = serde_json::from_str(
+            r#"{
+                "packages": {
+                    "a-1.0-0.tar.bz2": {"name": "a", "version": "1.0", "build": "0", "build_number": 0, "depends": ["b >=1", "c"], "subdir": "noarch"},
+                    "b-1.0-0.tar.bz2": {"name": "b", "version": "1.0", "build": "0", "build_number": 0, "depends": [], "subdir": "noarch"}
+                },
+                "packages.conda": {
+                    "c-1.0-0.conda": {"name": "c", "version": "1.0", "build": "0", "build_number": 0, "depends": [], "subdir": "noarch", "license": "MIT"}
+                }
+            }"#,
+        )
+        .unwrap();
+        let new: RepoData = serde_json::from_str(
+            r#"{
+                "packages": {
+                    "a-1.0-0.tar.bz2": {"name": "a", "version": "1.0", "build": "0", "build_number": 0, "depends": ["b >=1", "d"], "constrains": ["e <2"], "subdir": "noarch"},
+                    "b-2.0-0.tar.bz2": {"name": "b", "version": "2.0", "build": "0", "build_number": 0, "depends": [], "subdir": "noarch"}
+                },
+                "packages.conda": {
+                    "c-1.0-0.conda": {"name": "c", "version": "1.0", "build": "0", "build_number": 0, "depends": [], "subdir": "noarch", "license": "BSD-3-Clause"}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, ["b-2.0-0.tar.bz2"]);
+        assert_eq!(diff.removed, ["b-1.0-0.tar.bz2"]);
+        assert_eq!(diff.changed.len(), 2);
+
+        let a = &diff.changed[0];
+        assert_eq!(a.file_name, "a-1.0-0.tar.bz2");
+        assert_eq!(a.added_depends, ["d"]);
+        assert_eq!(a.removed_depends, ["c"]);
+        assert_eq!(a.added_constrains, ["e <2"]);
+        assert!(a.changed_fields.is_empty());
+
+        let c = &diff.changed[1];
+        assert_eq!(c.changed_fields.len(), 1);
+        assert_eq!(c.changed_fields[0].field, "license");
+        assert_eq!(c.changed_fields[0].old, "MIT");
+        assert_eq!(c.changed_fields[0].new, "BSD-3-Clause");
+
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -1,9 +1,12 @@
 //! Defines [`RepoData`]. `RepoData` stores information of all packages present in a subdirectory
 //! of a channel. It provides indexing functionality.
 
+pub mod diff;
 pub mod patches;
 mod topological_sort;
 
+pub(crate) use topological_sort::package_name_from_match_spec;
+
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
@@ -21,7 +24,7 @@ use rattler_macros::sorted;
 
 use crate::{
     build_spec::BuildNumber, package::IndexJson, utils::serde::DeserializeFromStrUnchecked,
-    Channel, NoArchType, PackageName, Platform, RepoDataRecord, VersionWithSource,
+    Channel, NoArchType, PackageName, Platform, RepoDataRecord, Timestamp, VersionWithSource,
 };
 
 /// [`RepoData`] is an index of package binaries available on in a subdirectory of a Conda channel.
@@ -65,6 +68,33 @@ pub struct ChannelInfo {
     pub base_url: Option<String>,
 }
 
+impl ChannelInfo {
+    /// Verifies that this information describes the `expected` subdir. An empty subdir is
+    /// always accepted.
+    pub fn verify_subdir(&self, expected: &str) -> Result<(), SubdirMismatchError> {
+        if self.subdir.is_empty() || self.subdir == expected {
+            Ok(())
+        } else {
+            Err(SubdirMismatchError {
+                expected: expected.to_owned(),
+                found: self.subdir.clone(),
+            })
+        }
+    }
+}
+
+/// An error that is returned when the subdir in the `info` section of a repodata file does not
+/// match the subdir the repodata was requested for.
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+#[error("expected repodata for subdir '{expected}' but the repodata describes '{found}'")]
+pub struct SubdirMismatchError {
+    /// The subdir that was requested.
+    pub expected: String,
+
+    /// The subdir that is described by the repodata.
+    pub found: String,
+}
+
 /// A single record in the Conda repodata. A single record refers to a single binary distribution
 /// of a package on a Conda channel.
 #[serde_as]
@@ -136,9 +166,9 @@ pub struct PackageRecord {
     #[serde(default)]
     pub subdir: String,
 
-    /// The date this entry was created.
-    #[serde_as(as = "Option<crate::utils::serde::Timestamp>")]
-    pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    /// The date this entry was created. The raw value, in seconds or milliseconds since the epoch,
+    /// is kept as well.
+    pub timestamp: Option<Timestamp>,
 
     /// Track features are nowadays only used to downweight packages (ie. give them less priority). To
     /// that effect, the number of track features is counted (number of commas) and the package is downweighted
@@ -156,6 +186,12 @@ pub struct PackageRecord {
     //pub package_type: ?
 }
 
+impl AsRef<PackageRecord> for PackageRecord {
+    fn as_ref(&self) -> &PackageRecord {
+        self
+    }
+}
+
 impl Display for PackageRecord {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if self.build.is_empty() {
@@ -184,6 +220,38 @@ impl RepoData {
         self.info.as_ref().and_then(|i| i.base_url.as_deref())
     }
 
+    /// Returns the `subdir` specified in the `info` section of the repodata.
+    pub fn subdir(&self) -> Option<&str> {
+        self.info
+            .as_ref()
+            .map(|i| i.subdir.as_str())
+            .filter(|subdir| !subdir.is_empty())
+    }
+
+    /// Verifies that the `info` section of the repodata describes the `expected` subdir. A
+    /// repodata file without an `info` section (or without a subdir in it) is always accepted.
+    ///
+    /// This can be used to detect misconfigured mirrors that serve the repodata of a different
+    /// subdirectory than the one that was requested.
+    pub fn verify_subdir(&self, expected: &str) -> Result<(), SubdirMismatchError> {
+        match &self.info {
+            Some(info) => info.verify_subdir(expected),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes all packages that were published after `cutoff`, which turns this instance into a
+    /// view of the channel as it existed at that moment. This can be used to reproduce an
+    /// environment as it would have been solved in the past.
+    ///
+    /// Packages without a timestamp are kept, see [`PackageRecord::is_newer_than`].
+    pub fn exclude_newer_than(&mut self, cutoff: &chrono::DateTime<chrono::Utc>) {
+        self.packages
+            .retain(|_, record| !record.is_newer_than(cutoff));
+        self.conda_packages
+            .retain(|_, record| !record.is_newer_than(cutoff));
+    }
+
     /// Builds a [`Vec<RepoDataRecord>`] from the packages in a [`RepoData`] given the source of the
     /// data.
     pub fn into_repo_data_records(self, channel: &Channel) -> Vec<RepoDataRecord> {
@@ -195,10 +263,7 @@ impl RepoData {
         for (filename, package_record) in self.packages.into_iter().chain(self.conda_packages) {
             records.push(RepoDataRecord {
                 url: compute_package_url(
-                    &channel
-                        .base_url()
-                        .join(&package_record.subdir)
-                        .expect("cannot join channel base_url and subdir"),
+                    &channel.subdir_url(&package_record.subdir),
                     base_url.as_deref(),
                     &filename,
                 ),
@@ -212,6 +277,10 @@ impl RepoData {
 }
 
 /// Computes the URL for a package.
+///
+/// The `base_url` and `filename` come from the repodata and can therefore not be trusted. A
+/// `base_url` that is not a valid url is ignored and a `filename` that is not a valid relative url
+/// is percent-encoded.
 pub fn compute_package_url(
     repo_data_base_url: &Url,
     base_url: Option<&str>,
@@ -223,15 +292,15 @@ pub fn compute_package_url(
             Err(url::ParseError::RelativeUrlWithoutBase) if !base_url.starts_with('/') => {
                 add_trailing_slash(repo_data_base_url)
                     .join(base_url)
-                    .expect("failed to join base_url with channel")
+                    .unwrap_or_else(|_| repo_data_base_url.clone())
             }
             Err(url::ParseError::RelativeUrlWithoutBase) => {
                 let mut url = repo_data_base_url.clone();
                 url.set_path(base_url);
                 url
             }
-            Err(e) => unreachable!("{e}"),
-            Ok(base_url) => base_url,
+            Ok(base_url) if !base_url.cannot_be_a_base() => base_url,
+            Ok(_) | Err(_) => repo_data_base_url.clone(),
         },
     };
 
@@ -239,9 +308,12 @@ pub fn compute_package_url(
     if !path.ends_with('/') {
         absolute_url.set_path(&format!("{path}/"))
     }
-    absolute_url
-        .join(filename)
-        .expect("failed to join base_url and filename")
+    absolute_url.join(filename).unwrap_or_else(|_| {
+        if let Ok(mut segments) = absolute_url.path_segments_mut() {
+            segments.pop_if_empty().push(filename);
+        }
+        absolute_url
+    })
 }
 
 fn add_trailing_slash(url: &Url) -> Cow<Url> {
@@ -291,6 +363,34 @@ impl PackageRecord {
     pub fn sort_topologically<T: AsRef<PackageRecord> + Clone>(records: Vec<T>) -> Vec<T> {
         topological_sort::sort_topologically(records)
     }
+
+    /// Returns true if the package was published after `cutoff`. Packages without a timestamp
+    /// (which is common for very old packages) are assumed to have always existed.
+    pub fn is_newer_than(&self, cutoff: &chrono::DateTime<chrono::Utc>) -> bool {
+        self.timestamp
+            .as_ref()
+            .map_or(false, |timestamp| timestamp.datetime() > cutoff)
+    }
+
+    /// Returns the timestamp exactly as it is stored in the record, either in seconds or in
+    /// milliseconds since the epoch. Use [`PackageRecord::timestamp`] for the parsed date.
+    pub fn raw_timestamp(&self) -> Option<i64> {
+        self.timestamp.as_ref().map(Timestamp::raw)
+    }
+
+    /// Formats the timestamp of the record in a human readable form, e.g.
+    /// `2023-11-14 22:13:20 UTC`.
+    pub fn formatted_timestamp(&self) -> Option<String> {
+        self.timestamp.as_ref().map(ToString::to_string)
+    }
+
+    /// Formats the timestamp of the record with a custom format string. See
+    /// [`chrono::format::strftime`] for the supported specifiers.
+    pub fn format_timestamp(&self, format: &str) -> Option<String> {
+        self.timestamp
+            .as_ref()
+            .map(|timestamp| timestamp.format(format).to_string())
+    }
 }
 
 /// An error that can occur when parsing a platform from a string.
@@ -413,10 +513,10 @@ fn sort_set_alphabetically<S: serde::Serializer>(
 
 #[cfg(test)]
 mod test {
-    use crate::repo_data::{compute_package_url, determine_subdir};
+    use crate::repo_data::{compute_package_url, determine_subdir, SubdirMismatchError};
     use fxhash::FxHashSet;
 
-    use crate::{Channel, ChannelConfig, RepoData};
+    use crate::{Channel, ChannelConfig, PackageRecord, RepoData};
 
     // isl-0.12.2-1.tar.bz2
     // gmp-5.1.2-6.tar.bz2
@@ -446,6 +546,66 @@ mod test {
         insta::assert_yaml_snapshot!(repodata);
     }
 
+    #[test]
+    fn test_exclude_newer_than() {
+        let record = |version: &str, timestamp: Option<i64>| {
+            serde_json::json!({
+                "name": "foo",
+                "version": version,
+                "build": "0",
+                "build_number": 0,
+                "depends": [],
+                "timestamp": timestamp,
+            })
+        };
+        let mut repodata: RepoData = serde_json::from_value(serde_json::json!({
+            "packages": {
+                "foo-0.1-0.tar.bz2": record("0.1", None),
+                "foo-1.0-0.tar.bz2": record("1.0", Some(1_600_000_000_000)),
+                "foo-2.0-0.tar.bz2": record("2.0", Some(1_700_000_000_000)),
+            },
+            "packages.conda": {
+                "foo-2.0-0.conda": record("2.0", Some(1_700_000_000)),
+            },
+        }))
+        .unwrap();
+
+        let cutoff = chrono::DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        repodata.exclude_newer_than(&cutoff);
+
+        let mut filenames = repodata.packages.keys().collect::<Vec<_>>();
+        filenames.sort();
+        assert_eq!(filenames, ["foo-0.1-0.tar.bz2", "foo-1.0-0.tar.bz2"]);
+        assert!(repodata.conda_packages.is_empty());
+    }
+
+    #[test]
+    fn test_timestamp_helpers() {
+        let record: PackageRecord = serde_json::from_value(serde_json::json!({
+            "name": "foo",
+            "version": "1.0",
+            "build": "0",
+            "build_number": 0,
+            "timestamp": 1_700_000_000_000i64,
+        }))
+        .unwrap();
+        assert_eq!(record.raw_timestamp(), Some(1_700_000_000_000));
+        assert_eq!(
+            record.formatted_timestamp().as_deref(),
+            Some("2023-11-14 22:13:20 UTC")
+        );
+        assert_eq!(
+            record.format_timestamp("%Y-%m-%d").as_deref(),
+            Some("2023-11-14")
+        );
+
+        // A timestamp in milliseconds is written back in milliseconds, even if it is a whole second.
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["timestamp"], 1_700_000_000_000i64);
+    }
+
     #[test]
     fn test_serialize_packages() {
         // load test data
@@ -493,6 +653,55 @@ mod test {
         insta::assert_yaml_snapshot!(file_urls);
     }
 
+    #[test]
+    fn test_untrusted_package_urls() {
+        let channel = Channel::from_str("conda-forge", &ChannelConfig::default()).unwrap();
+        let subdir_url = channel.subdir_url("linux-64");
+        assert_eq!(
+            subdir_url.as_str(),
+            "https://conda.anaconda.org/conda-forge/linux-64/"
+        );
+
+        // Invalid base urls are ignored and invalid filenames are percent-encoded instead of
+        // panicking.
+        for base_url in [Some("http://[::1"), Some("mailto:foo@example.com"), None] {
+            assert_eq!(
+                compute_package_url(&subdir_url, base_url, "foo-1.0-0.conda").as_str(),
+                "https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-0.conda"
+            );
+        }
+        assert!(compute_package_url(&subdir_url, None, "//[foo")
+            .as_str()
+            .starts_with("https://conda.anaconda.org/conda-forge/linux-64/"));
+        assert!(channel
+            .subdir_url("//[")
+            .as_str()
+            .starts_with("https://conda.anaconda.org/conda-forge/"));
+    }
+
+    #[test]
+    fn test_verify_subdir() {
+        let repodata: RepoData = serde_json::from_str(
+            r#"{"info": {"subdir": "linux-64"}, "packages": {}, "packages.conda": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(repodata.subdir(), Some("linux-64"));
+        assert!(repodata.verify_subdir("linux-64").is_ok());
+        assert_eq!(
+            repodata.verify_subdir("osx-arm64"),
+            Err(SubdirMismatchError {
+                expected: String::from("osx-arm64"),
+                found: String::from("linux-64"),
+            })
+        );
+
+        // Repodata without an info section cannot be verified so its accepted.
+        let repodata: RepoData =
+            serde_json::from_str(r#"{"packages": {}, "packages.conda": {}}"#).unwrap();
+        assert_eq!(repodata.subdir(), None);
+        assert!(repodata.verify_subdir("osx-arm64").is_ok());
+    }
+
     #[test]
     fn test_base_url() {
         let channel = Channel::from_str("conda-forge", &ChannelConfig::default()).unwrap();
@@ -0,0 +1,583 @@
+//! Defines [`Sbom`], a software bill of materials of an environment that can be exported as a
+//! [CycloneDX](https://cyclonedx.org) or [SPDX](https://spdx.dev) document.
+
+use crate::license_report::PackageLicense;
+use crate::package::ArchiveType;
+use crate::repo_data::package_name_from_match_spec;
+use crate::{Clock, PrefixRecord, RepoDataRecord};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::path::Path;
+use url::{form_urlencoded, Url};
+
+/// The version of the CycloneDX specification of the documents created by
+/// [`Sbom::to_cyclonedx_json`].
+pub const CYCLONEDX_SPEC_VERSION: &str = "1.5";
+
+/// The version of the SPDX specification of the documents created by [`Sbom::to_spdx_json`].
+pub const SPDX_VERSION: &str = "SPDX-2.3";
+
+/// The identifiers from the [SPDX license list](https://spdx.org/licenses/) that are recognized in
+/// the licenses of packages. Licenses that use other identifiers are reported as `LicenseRef-`
+/// licenses, which is always valid.
+const SPDX_LICENSE_IDS: &[&str] = &[
+    "0BSD",
+    "AFL-2.1",
+    "AFL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "Artistic-1.0",
+    "Artistic-2.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "bzip2-1.0.6",
+    "CC-BY-4.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CDDL-1.1",
+    "CECILL-2.1",
+    "curl",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.2",
+    "FTL",
+    "GFDL-1.3-only",
+    "GFDL-1.3-or-later",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "HPND",
+    "ICU",
+    "IJG",
+    "ISC",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "Libpng",
+    "libtiff",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MS-PL",
+    "NCSA",
+    "OFL-1.1",
+    "OpenSSL",
+    "PostgreSQL",
+    "PSF-2.0",
+    "Python-2.0",
+    "Ruby",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "Vim",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "ZPL-2.1",
+];
+
+/// The license exceptions from the [SPDX license list](https://spdx.org/licenses/exceptions-index.html)
+/// that are recognized after a `WITH` operator.
+const SPDX_EXCEPTION_IDS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "OpenSSL-exception",
+];
+
+/// A software bill of materials (SBOM) of an environment. It lists the packages in the environment
+/// together with the url they were downloaded from, their hashes and their licenses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbom {
+    /// The name of the environment that is described by the SBOM.
+    pub name: String,
+
+    /// The time at which the SBOM was created.
+    pub created: DateTime<Utc>,
+
+    /// The packages in the environment, sorted by name.
+    pub packages: Vec<SbomPackage>,
+}
+
+/// A single package in an [`Sbom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SbomPackage {
+    /// The record of the package.
+    pub record: RepoDataRecord,
+
+    /// The license of the package, if known. See [`crate::LicenseReport`].
+    pub license: Option<String>,
+}
+
+impl SbomPackage {
+    /// Returns the [package url](https://github.com/package-url/purl-spec) of the package, e.g.
+    /// `pkg:conda/numpy@1.26.0?build=py311h0b4df5a_0&channel=https%3A%2F%2Fconda.anaconda.org%2Fconda-forge%2F&subdir=linux-64&type=conda`.
+    pub fn purl(&self) -> String {
+        let package_record = &self.record.package_record;
+        let mut qualifiers = form_urlencoded::Serializer::new(String::new());
+        qualifiers.append_pair("build", &package_record.build);
+        qualifiers.append_pair("channel", &self.record.channel);
+        if !package_record.subdir.is_empty() {
+            qualifiers.append_pair("subdir", &package_record.subdir);
+        }
+        if let Some(archive_type) = ArchiveType::try_from(&self.record.file_name) {
+            qualifiers.append_pair("type", archive_type.extension().trim_start_matches('.'));
+        }
+        format!(
+            "pkg:conda/{}@{}?{}",
+            package_record.name.as_normalized(),
+            package_record.version,
+            qualifiers.finish()
+        )
+    }
+}
+
+impl Sbom {
+    /// Constructs an SBOM from the records of a solve result. The SBOM is created at the current
+    /// time of the `clock`.
+    pub fn from_repodata_records(
+        name: impl Into<String>,
+        records: impl IntoIterator<Item = impl Borrow<RepoDataRecord>>,
+        clock: &dyn Clock,
+    ) -> Self {
+        Self::from_packages(
+            name.into(),
+            clock,
+            records
+                .into_iter()
+                .map(|record| {
+                    let record = record.borrow();
+                    SbomPackage {
+                        license: PackageLicense::from_repodata_record(record).license,
+                        record: record.clone(),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Constructs an SBOM from the records of the packages installed in a prefix. The SBOM is
+    /// created at the current time of the `clock`.
+    pub fn from_prefix_records(
+        name: impl Into<String>,
+        records: &[PrefixRecord],
+        clock: &dyn Clock,
+    ) -> Self {
+        Self::from_packages(
+            name.into(),
+            clock,
+            records
+                .iter()
+                .map(|record| SbomPackage {
+                    license: PackageLicense::from_prefix_record(record).license,
+                    record: record.repodata_record.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Constructs an SBOM of the packages installed in the prefix. The SBOM is named after the
+    /// directory of the prefix and created at the current time of the `clock`.
+    pub fn from_prefix(prefix: &Path, clock: &dyn Clock) -> Result<Self, std::io::Error> {
+        let name = prefix
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::from_prefix_records(
+            name,
+            &PrefixRecord::collect_from_prefix(prefix)?,
+            clock,
+        ))
+    }
+
+    fn from_packages(name: String, clock: &dyn Clock, mut packages: Vec<SbomPackage>) -> Self {
+        packages.sort_by(|a, b| {
+            a.record
+                .package_record
+                .name
+                .cmp(&b.record.package_record.name)
+        });
+        Self {
+            name,
+            created: clock.now(),
+            packages,
+        }
+    }
+
+    /// Returns for every package the indices of the packages in the SBOM that it depends on.
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let indices = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| (package.record.package_record.name.as_normalized(), idx))
+            .collect::<HashMap<_, _>>();
+        self.packages
+            .iter()
+            .map(|package| {
+                package
+                    .record
+                    .package_record
+                    .depends
+                    .iter()
+                    .filter_map(|spec| indices.get(package_name_from_match_spec(spec)).copied())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Formats the SBOM as a CycloneDX JSON document.
+    pub fn to_cyclonedx_json(&self) -> Value {
+        let purls = self
+            .packages
+            .iter()
+            .map(SbomPackage::purl)
+            .collect::<Vec<_>>();
+        let components = self
+            .packages
+            .iter()
+            .zip(&purls)
+            .map(|(package, purl)| {
+                let package_record = &package.record.package_record;
+                let mut hashes = Vec::new();
+                if let Some(sha256) = package_record.sha256 {
+                    hashes.push(json!({"alg": "SHA-256", "content": format!("{sha256:x}")}));
+                }
+                if let Some(md5) = package_record.md5 {
+                    hashes.push(json!({"alg": "MD5", "content": format!("{md5:x}")}));
+                }
+                let licenses = package
+                    .license
+                    .iter()
+                    .map(|license| json!({"license": {"name": license}}))
+                    .collect::<Vec<_>>();
+                json!({
+                    "type": "library",
+                    "bom-ref": purl,
+                    "name": package_record.name.as_normalized(),
+                    "version": package_record.version.to_string(),
+                    "purl": purl,
+                    "hashes": hashes,
+                    "licenses": licenses,
+                    "externalReferences": [
+                        {"type": "distribution", "url": package.record.url.as_str()}
+                    ],
+                })
+            })
+            .collect::<Vec<_>>();
+        let dependencies = self
+            .dependencies()
+            .into_iter()
+            .zip(&purls)
+            .map(|(depends, purl)| {
+                json!({
+                    "ref": purl,
+                    "dependsOn": depends.into_iter().map(|idx| &purls[idx]).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": CYCLONEDX_SPEC_VERSION,
+            "version": 1,
+            "metadata": {
+                "timestamp": self.created.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "component": {"type": "application", "name": self.name},
+            },
+            "components": components,
+            "dependencies": dependencies,
+        })
+    }
+
+    /// Formats the SBOM as an SPDX JSON document. The `namespace` must uniquely identify the
+    /// document. SPDX does not allow a `#` in the namespace, so the fragment of the url is
+    /// removed.
+    ///
+    /// SPDX requires licenses to be valid SPDX license expressions. A license of a package that is
+    /// not a valid expression (e.g. `BSD`) is declared as a `LicenseRef-` license whose text is
+    /// added to the `hasExtractedLicensingInfos` of the document. The concluded license is left as
+    /// `NOASSERTION`.
+    pub fn to_spdx_json(&self, namespace: &Url) -> Value {
+        let mut namespace = namespace.clone();
+        namespace.set_fragment(None);
+
+        let mut extracted_licenses = Vec::new();
+        let mut declared_license = |license: Option<&str>| -> String {
+            let Some(license) = license.map(str::trim).filter(|l| !l.is_empty()) else {
+                return String::from("NOASSERTION");
+            };
+            if let Some(expression) = spdx_license_expression(license) {
+                return expression;
+            }
+            let license_ref = spdx_license_ref(license);
+            if !extracted_licenses
+                .iter()
+                .any(|info: &Value| info["licenseId"] == license_ref.as_str())
+            {
+                extracted_licenses.push(json!({
+                    "licenseId": license_ref,
+                    "name": license,
+                    "extractedText": license,
+                }));
+            }
+            license_ref
+        };
+
+        let spdx_ids = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| {
+                let name = package
+                    .record
+                    .package_record
+                    .name
+                    .as_normalized()
+                    .replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "-");
+                format!("SPDXRef-Package-{idx}-{name}")
+            })
+            .collect::<Vec<_>>();
+        let packages = self
+            .packages
+            .iter()
+            .zip(&spdx_ids)
+            .map(|(package, spdx_id)| {
+                let package_record = &package.record.package_record;
+                let mut checksums = Vec::new();
+                if let Some(sha256) = package_record.sha256 {
+                    checksums.push(
+                        json!({"algorithm": "SHA256", "checksumValue": format!("{sha256:x}")}),
+                    );
+                }
+                if let Some(md5) = package_record.md5 {
+                    checksums
+                        .push(json!({"algorithm": "MD5", "checksumValue": format!("{md5:x}")}));
+                }
+                json!({
+                    "SPDXID": spdx_id,
+                    "name": package_record.name.as_normalized(),
+                    "versionInfo": package_record.version.to_string(),
+                    "downloadLocation": package.record.url.as_str(),
+                    "filesAnalyzed": false,
+                    "checksums": checksums,
+                    "licenseConcluded": "NOASSERTION",
+                    "licenseDeclared": declared_license(package.license.as_deref()),
+                    "copyrightText": "NOASSERTION",
+                    "externalRefs": [{
+                        "referenceCategory": "PACKAGE-MANAGER",
+                        "referenceType": "purl",
+                        "referenceLocator": package.purl(),
+                    }],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut relationships = spdx_ids
+            .iter()
+            .map(|spdx_id| {
+                json!({
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": spdx_id,
+                })
+            })
+            .collect::<Vec<_>>();
+        for (depends, spdx_id) in self.dependencies().into_iter().zip(&spdx_ids) {
+            relationships.extend(depends.into_iter().map(|idx| {
+                json!({
+                    "spdxElementId": spdx_id,
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": spdx_ids[idx],
+                })
+            }));
+        }
+
+        let mut document = json!({
+            "spdxVersion": SPDX_VERSION,
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.name,
+            "documentNamespace": namespace.as_str(),
+            "creationInfo": {
+                "created": self.created.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "creators": [concat!("Tool: rattler-", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        });
+        if !extracted_licenses.is_empty() {
+            document["hasExtractedLicensingInfos"] = Value::Array(extracted_licenses);
+        }
+        document
+    }
+}
+
+/// Returns the license as an SPDX license expression with the canonical spelling of the license
+/// identifiers, e.g. `MIT AND Apache-2.0` for `mit and apache-2.0`. Returns `None` if the license
+/// is not a valid expression of the licenses in [`SPDX_LICENSE_IDS`].
+fn spdx_license_expression(license: &str) -> Option<String> {
+    /// The token that is expected next while parsing the expression.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Expect {
+        /// A license identifier or an opening parenthesis.
+        License,
+        /// A license exception identifier after `WITH`.
+        Exception,
+        /// An operator or a closing parenthesis after a license identifier.
+        AfterLicense,
+        /// An operator other than `WITH` or a closing parenthesis.
+        AfterExpression,
+    }
+
+    let canonical = |ids: &[&'static str], id: &str| -> Option<&'static str> {
+        ids.iter()
+            .copied()
+            .find(|known| known.eq_ignore_ascii_case(id))
+    };
+
+    let spaced = license.replace('(', " ( ").replace(')', " ) ");
+    let (mut tokens, mut depth, mut expect) = (Vec::new(), 0usize, Expect::License);
+    for token in spaced.split_whitespace() {
+        let upper = token.to_ascii_uppercase();
+        let (token, next) = match (expect, upper.as_str()) {
+            (Expect::License, "(") => {
+                depth += 1;
+                (String::from("("), Expect::License)
+            }
+            (Expect::License, _) => {
+                let (id, plus) = match token.strip_suffix('+') {
+                    Some(id) => (id, "+"),
+                    None => (token, ""),
+                };
+                let id = canonical(SPDX_LICENSE_IDS, id)?;
+                (format!("{id}{plus}"), Expect::AfterLicense)
+            }
+            (Expect::Exception, _) => (
+                canonical(SPDX_EXCEPTION_IDS, token)?.to_owned(),
+                Expect::AfterExpression,
+            ),
+            (Expect::AfterLicense, "WITH") => (upper, Expect::Exception),
+            (Expect::AfterLicense | Expect::AfterExpression, "AND" | "OR") => {
+                (upper, Expect::License)
+            }
+            (Expect::AfterLicense | Expect::AfterExpression, ")") if depth > 0 => {
+                depth -= 1;
+                (String::from(")"), Expect::AfterExpression)
+            }
+            _ => return None,
+        };
+        tokens.push(token);
+        expect = next;
+    }
+
+    let complete = matches!(expect, Expect::AfterLicense | Expect::AfterExpression);
+    (complete && depth == 0).then(|| tokens.join(" ").replace("( ", "(").replace(" )", ")"))
+}
+
+/// Returns the `LicenseRef-` identifier that is used for a license that is not a valid SPDX
+/// license expression. Characters that are not allowed in an identifier are replaced with `-`.
+fn spdx_license_ref(license: &str) -> String {
+    let id = license
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("LicenseRef-{id}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{spdx_license_expression, spdx_license_ref, Sbom};
+    use crate::{package::ArchiveType, test_utils::RecordBuilder, FixedClock};
+    use chrono::{TimeZone, Utc};
+    use rattler_digest::{compute_bytes_digest, Sha256};
+
+    fn sbom() -> Sbom {
+        Sbom::from_repodata_records(
+            "test",
+            [
+                RecordBuilder::new("python", "1.0")
+                    .with_build("h123_0", 0)
+                    .with_dependency("libzlib >=1.2.13")
+                    .with_license("MIT")
+                    .with_sha256(compute_bytes_digest::<Sha256>("python"))
+                    .with_archive_type(ArchiveType::Conda)
+                    .build(),
+                RecordBuilder::new("libzlib", "1.0")
+                    .with_build("h123_0", 0)
+                    .with_dependency("__glibc >=2.17")
+                    .with_license("MIT")
+                    .with_sha256(compute_bytes_digest::<Sha256>("libzlib"))
+                    .with_archive_type(ArchiveType::Conda)
+                    .build(),
+            ],
+            &FixedClock(Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_purl() {
+        assert_eq!(
+            sbom().packages[0].purl(),
+            "pkg:conda/libzlib@1.0?build=h123_0&channel=https%3A%2F%2Fconda.anaconda.org%2Fconda-forge%2F&subdir=linux-64&type=conda"
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        insta::assert_yaml_snapshot!(sbom().to_cyclonedx_json());
+    }
+
+    #[test]
+    fn test_spdx() {
+        let mut sbom = sbom();
+        sbom.packages[1].license = Some(String::from("BSD-like (custom)"));
+        insta::assert_yaml_snapshot!(
+            sbom.to_spdx_json(&"https://example.com/spdx/test#document".parse().unwrap()),
+            {".creationInfo.creators" => "[creators]"}
+        );
+    }
+
+    #[test]
+    fn test_spdx_license_expression() {
+        assert_eq!(spdx_license_expression("MIT").as_deref(), Some("MIT"));
+        assert_eq!(
+            spdx_license_expression("mit and (apache-2.0 OR bsd-3-clause)").as_deref(),
+            Some("MIT AND (Apache-2.0 OR BSD-3-Clause)")
+        );
+        assert_eq!(
+            spdx_license_expression("GPL-3.0-or-later WITH GCC-exception-3.1").as_deref(),
+            Some("GPL-3.0-or-later WITH GCC-exception-3.1")
+        );
+        assert_eq!(spdx_license_expression("BSD"), None);
+        assert_eq!(spdx_license_expression("MIT AND"), None);
+        assert_eq!(spdx_license_expression("(MIT"), None);
+        assert_eq!(spdx_license_expression("MIT WITH MIT"), None);
+        assert_eq!(
+            spdx_license_ref("BSD-like (custom)"),
+            "LicenseRef-BSD-like--custom-"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Utilities to write tests that need repodata records.
+//!
+//! [`RecordBuilder`] constructs [`RepoDataRecord`]s of packages that do not have to exist, e.g. to
+//! test the solver, a transaction or a report without loading repodata.
+//!
+//! This module is only available with the `test-utils` feature, add it to the `dev-dependencies`
+//! of a crate to use it in tests.
+
+use crate::{package::ArchiveType, PackageName, PackageRecord, RepoDataRecord, Version};
+use rattler_digest::Sha256Hash;
+
+/// Builds the [`RepoDataRecord`] of a package.
+pub struct RecordBuilder {
+    record: PackageRecord,
+    channel: String,
+    archive_type: ArchiveType,
+}
+
+impl RecordBuilder {
+    /// Constructs a new builder for a package with the given name and version. The package has
+    /// build string `0`, is built for `linux-64` and is part of the `conda-forge` channel.
+    pub fn new(name: &str, version: &str) -> Self {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked(name),
+            version.parse::<Version>().unwrap(),
+            String::from("0"),
+        );
+        record.subdir = String::from("linux-64");
+        Self {
+            record,
+            channel: String::from("https://conda.anaconda.org/conda-forge/"),
+            archive_type: ArchiveType::TarBz2,
+        }
+    }
+
+    /// Sets the build string and build number of the package.
+    pub fn with_build(mut self, build: &str, build_number: u64) -> Self {
+        self.record.build = build.to_owned();
+        self.record.build_number = build_number;
+        self
+    }
+
+    /// Adds a dependency to the package.
+    pub fn with_dependency(mut self, spec: &str) -> Self {
+        self.record.depends.push(spec.to_owned());
+        self
+    }
+
+    /// Sets the subdirectory of the channel the package is part of, e.g. `noarch`.
+    pub fn with_subdir(mut self, subdir: &str) -> Self {
+        self.record.subdir = subdir.to_owned();
+        self
+    }
+
+    /// Sets the license of the package.
+    pub fn with_license(mut self, license: &str) -> Self {
+        self.record.license = Some(license.to_owned());
+        self
+    }
+
+    /// Sets the license family of the package.
+    pub fn with_license_family(mut self, license_family: &str) -> Self {
+        self.record.license_family = Some(license_family.to_owned());
+        self
+    }
+
+    /// Sets the size of the package archive.
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.record.size = Some(size);
+        self
+    }
+
+    /// Sets the SHA256 hash of the package archive.
+    pub fn with_sha256(mut self, sha256: Sha256Hash) -> Self {
+        self.record.sha256 = Some(sha256);
+        self
+    }
+
+    /// Sets the url of the channel the package is part of. The url must end with a slash.
+    pub fn with_channel(mut self, channel: &str) -> Self {
+        self.channel = channel.to_owned();
+        self
+    }
+
+    /// Sets the type of the package archive, which determines its filename.
+    pub fn with_archive_type(mut self, archive_type: ArchiveType) -> Self {
+        self.archive_type = archive_type;
+        self
+    }
+
+    /// Returns the record of the package. The url of the package archive is derived from the
+    /// channel, the subdirectory and the filename.
+    pub fn build(self) -> RepoDataRecord {
+        let file_name = format!(
+            "{}-{}-{}{}",
+            self.record.name.as_normalized(),
+            self.record.version,
+            self.record.build,
+            self.archive_type.extension()
+        );
+        RepoDataRecord {
+            url: format!("{}{}/{}", self.channel, self.record.subdir, file_name)
+                .parse()
+                .unwrap(),
+            channel: self.channel,
+            file_name,
+            package_record: self.record,
+        }
+    }
+}
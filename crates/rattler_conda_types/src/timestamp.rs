@@ -0,0 +1,138 @@
+use chrono::{DateTime, Utc};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+
+/// The largest number of seconds since the epoch that can still be represented by a four digit
+/// year (9999-12-31T23:59:59Z). Larger raw values are interpreted as milliseconds.
+const MAX_SECONDS: i64 = 253_402_300_799;
+
+/// The timestamp of a package as it is stored in the repodata or in an `index.json` file.
+///
+/// Depending on the tool that created the package, the value is stored either in seconds or in
+/// milliseconds since the epoch. The unit is detected when the timestamp is parsed and the raw
+/// value is kept next to the parsed date, so serializing a `Timestamp` writes back exactly the
+/// value that was read.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Timestamp {
+    datetime: DateTime<Utc>,
+    raw: i64,
+}
+
+impl Timestamp {
+    /// Constructs a timestamp from a raw value in seconds or milliseconds since the epoch. Returns
+    /// `None` if the value is out of range.
+    pub fn from_raw(raw: i64) -> Option<Self> {
+        let (secs, nsecs) = if raw > MAX_SECONDS {
+            (
+                raw.div_euclid(1_000),
+                raw.rem_euclid(1_000) as u32 * 1_000_000,
+            )
+        } else {
+            (raw, 0)
+        };
+        DateTime::<Utc>::from_timestamp(secs, nsecs).map(|datetime| Self { datetime, raw })
+    }
+
+    /// Returns the value exactly as it was stored, either in seconds or in milliseconds since the
+    /// epoch.
+    pub fn raw(&self) -> i64 {
+        self.raw
+    }
+
+    /// Returns true if the raw value is stored in milliseconds instead of seconds.
+    pub fn is_millis(&self) -> bool {
+        self.raw > MAX_SECONDS
+    }
+
+    /// Returns the date and time this timestamp refers to.
+    pub fn datetime(&self) -> &DateTime<Utc> {
+        &self.datetime
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    /// Converts a date into a timestamp that is stored in milliseconds. Dates that are too early to
+    /// be stored unambiguously in milliseconds are stored in seconds instead. The date itself is
+    /// kept as is, even if it is more precise than the raw value.
+    fn from(datetime: DateTime<Utc>) -> Self {
+        let millis = datetime.timestamp_millis();
+        let raw = if millis > MAX_SECONDS {
+            millis
+        } else {
+            datetime.timestamp()
+        };
+        Self { datetime, raw }
+    }
+}
+
+impl From<Timestamp> for DateTime<Utc> {
+    fn from(value: Timestamp) -> Self {
+        value.datetime
+    }
+}
+
+impl Deref for Timestamp {
+    type Target = DateTime<Utc>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.datetime
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.datetime.format("%Y-%m-%d %H:%M:%S UTC"))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = i64::deserialize(deserializer)?;
+        Self::from_raw(raw)
+            .ok_or_else(|| D::Error::custom("got invalid timestamp, timestamp out of range"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timestamp;
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn test_unit_is_detected() {
+        let seconds = Timestamp::from_raw(1_700_000_000).unwrap();
+        let millis = Timestamp::from_raw(1_700_000_000_000).unwrap();
+        assert!(!seconds.is_millis());
+        assert!(millis.is_millis());
+        assert_eq!(seconds.datetime(), millis.datetime());
+        assert_ne!(seconds, millis);
+        assert_eq!(millis.to_string(), "2023-11-14 22:13:20 UTC");
+
+        let with_millis = Timestamp::from_raw(1_700_000_000_123).unwrap();
+        assert_eq!(with_millis.timestamp_subsec_millis(), 123);
+    }
+
+    #[test]
+    fn test_raw_value_is_preserved() {
+        for raw in ["1700000000", "1700000000000", "1700000000123"] {
+            let timestamp: Timestamp = serde_json::from_str(raw).unwrap();
+            assert_eq!(timestamp.raw().to_string(), raw);
+            assert_eq!(serde_json::to_string(&timestamp).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_from_datetime() {
+        let datetime: DateTime<Utc> = "2023-11-14T22:13:20.123456Z".parse().unwrap();
+        let timestamp = Timestamp::from(datetime);
+        assert_eq!(timestamp.raw(), 1_700_000_000_123);
+        assert_eq!(DateTime::<Utc>::from(timestamp), datetime);
+    }
+}
@@ -1,5 +1,3 @@
-use chrono::{DateTime, Utc};
-use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::de::DeserializeAsWrap;
 use serde_with::ser::SerializeAsWrap;
@@ -72,51 +70,6 @@ impl<'de> DeserializeAs<'de, String> for MultiLineString {
     }
 }
 
-pub(crate) struct Timestamp;
-
-impl<'de> DeserializeAs<'de, chrono::DateTime<chrono::Utc>> for Timestamp {
-    fn deserialize_as<D>(deserializer: D) -> Result<chrono::DateTime<chrono::Utc>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let timestamp = i64::deserialize(deserializer)?;
-
-        // Convert from milliseconds to seconds
-        let microseconds = if timestamp > 253_402_300_799 {
-            timestamp * 1_000
-        } else {
-            timestamp * 1_000_000
-        };
-
-        // Convert the timestamp to a UTC timestamp
-        Ok(chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(
-            chrono::NaiveDateTime::from_timestamp_micros(microseconds)
-                .ok_or_else(|| D::Error::custom("got invalid timestamp, timestamp out of range"))?,
-            chrono::Utc,
-        ))
-    }
-}
-
-impl SerializeAs<chrono::DateTime<chrono::Utc>> for Timestamp {
-    fn serialize_as<S>(source: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        // Convert the date to a timestamp
-        let timestamp: i64 = source.timestamp_millis();
-
-        // Determine the precision of the timestamp.
-        let timestamp = if timestamp % 1000 == 0 {
-            timestamp / 1000
-        } else {
-            timestamp
-        };
-
-        // Serialize the timestamp
-        timestamp.serialize(serializer)
-    }
-}
-
 /// Used with serde_with to serialize a collection as a sorted collection.
 #[derive(Default)]
 pub(crate) struct Ordered<T>(PhantomData<T>);
@@ -192,6 +192,13 @@ mod test {
         );
         assert_eq!(
             Constraint::from_str(">1.2.*"),
+            Ok(Constraint::Comparison(
+                RangeOperator::Greater,
+                Version::from_str("1.2").unwrap()
+            ))
+        );
+        assert_eq!(
+            Constraint::from_str(">1.2*"),
             Ok(Constraint::Comparison(
                 RangeOperator::GreaterEquals,
                 Version::from_str("1.2").unwrap()
@@ -151,7 +151,9 @@ fn logical_constraint_parser(input: &str) -> IResult<&str, Constraint, ParseCons
         ("*" | ".*", Some(VersionOperators::Range(RangeOperator::GreaterEquals))) => {
             VersionOperators::Range(RangeOperator::GreaterEquals)
         }
-        ("*" | ".*", Some(VersionOperators::Range(RangeOperator::Greater))) => {
+        // Conda compares with the version including the `*`, which sorts below all versions
+        // that start with the same components. Only `.*` is ignored, see the arm below.
+        ("*", Some(VersionOperators::Range(RangeOperator::Greater))) => {
             VersionOperators::Range(RangeOperator::GreaterEquals)
         }
         ("*" | ".*", Some(VersionOperators::Exact(EqualityOperator::NotEquals))) => {
@@ -285,7 +285,7 @@ impl TryFrom<RepoDataRecord> for CondaLockedDependencyBuilder {
             license_family: record.package_record.license_family,
             noarch: record.package_record.noarch,
             size: record.package_record.size,
-            timestamp: record.package_record.timestamp,
+            timestamp: record.package_record.timestamp.map(Into::into),
         })
     }
 }
@@ -540,7 +540,7 @@ mod tests {
             locked_dep.as_conda().unwrap().size
         );
         assert_eq!(
-            record.package_record.timestamp,
+            record.package_record.timestamp.map(|t| *t.datetime()),
             locked_dep.as_conda().unwrap().timestamp
         );
 
@@ -569,6 +569,9 @@ mod tests {
         );
         assert_eq!(record.package_record.noarch, locked_package.noarch);
         assert_eq!(record.package_record.size, locked_package.size);
-        assert_eq!(record.package_record.timestamp, locked_package.timestamp);
+        assert_eq!(
+            record.package_record.timestamp.map(|t| *t.datetime()),
+            locked_package.timestamp
+        );
     }
 }
@@ -133,7 +133,7 @@ impl TryFrom<LockedDependency> for RepoDataRecord {
                 sha256,
                 size: value.size,
                 subdir: value.subdir.unwrap_or(platform.to_string()),
-                timestamp: value.timestamp,
+                timestamp: value.timestamp.map(Into::into),
                 track_features: value.track_features,
                 version,
             },
@@ -3,7 +3,7 @@
 //! Most names were kept the same as in the models file. So you can refer to those exactly.
 //! However, some types were added to enforce a bit more type safety.
 use indexmap::IndexMap;
-use rattler_conda_types::{MatchSpec, PackageName};
+use rattler_conda_types::{EnvironmentDiff, MatchSpec, PackageName};
 use rattler_conda_types::{NoArchType, ParsePlatformError, Platform, RepoDataRecord};
 use serde::{Deserialize, Serialize, Serializer};
 use serde_with::serde_as;
@@ -18,8 +18,8 @@ mod hash;
 mod pip;
 mod utils;
 
-use crate::conda::ConversionError;
-pub use conda::CondaLockedDependency;
+pub use conda::{CondaLockedDependency, ConversionError};
+pub use content_hash::CalculateContentHashError;
 pub use hash::PackageHashes;
 pub use pip::PipLockedDependency;
 
@@ -309,12 +309,25 @@ impl CondaLock {
             .map(|pkg| pkg.try_into())
             .collect()
     }
+
+    /// Computes the differences between the conda packages locked for `platform` in this
+    /// lock-file and the conda packages locked for the same platform in `other`.
+    pub fn diff(
+        &self,
+        other: &CondaLock,
+        platform: Platform,
+    ) -> Result<EnvironmentDiff, ConversionError> {
+        Ok(EnvironmentDiff::from_records(
+            self.get_conda_packages_by_platform(platform)?,
+            other.get_conda_packages_by_platform(platform)?,
+        ))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::CondaLock;
-    use crate::LockedDependency;
+    use crate::{LockedDependency, LockedDependencyKind};
     use insta::assert_yaml_snapshot;
     use rattler_conda_types::{Platform, RepoDataRecord, VersionWithSource};
     use serde_yaml::from_str;
@@ -381,6 +394,36 @@ mod test {
             })
     }
 
+    #[test]
+    fn test_diff() {
+        // The lock-file does not contain the build strings that are required to compare packages.
+        let mut conda_lock = CondaLock::from_path(Path::new(&lock_file_path())).unwrap();
+        for package in conda_lock.package.iter_mut() {
+            if let LockedDependencyKind::Conda(conda) = &mut package.kind {
+                conda.build.get_or_insert_with(|| String::from("0"));
+            }
+        }
+        assert!(conda_lock
+            .diff(&conda_lock, Platform::Linux64)
+            .unwrap()
+            .is_empty());
+
+        // Remove a package from the lock-file
+        let mut other = conda_lock.clone();
+        let idx = other
+            .package
+            .iter()
+            .position(|p| p.platform == Platform::Linux64 && p.is_conda())
+            .unwrap();
+        let removed = other.package.remove(idx);
+
+        let diff = conda_lock.diff(&other, Platform::Linux64).unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name.as_normalized(), removed.name);
+    }
+
     #[test]
     fn test_locked_dependency() {
         let yaml = r#"
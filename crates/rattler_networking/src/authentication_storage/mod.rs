@@ -1,4 +1,5 @@
 //! This module provides a way to store and retrieve authentication information for a given host.
 pub mod authentication;
 pub mod fallback_storage;
+pub mod netrc;
 pub mod storage;
@@ -0,0 +1,169 @@
+//! Read-only access to the credentials in a `.netrc` file.
+//!
+//! Many tools (e.g. `curl`, `pip` and `conda`) read the credentials of a host from the `.netrc`
+//! file in the home directory of the user. The location of the file can be overridden with the
+//! `NETRC` environment variable.
+use std::{collections::HashMap, path::Path};
+
+use super::authentication::Authentication;
+
+/// The credentials stored in a `.netrc` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Netrc {
+    /// The credentials of every `machine` entry, by host.
+    machines: HashMap<String, Authentication>,
+
+    /// The credentials of the `default` entry. These are not returned by [`Netrc::get`] because
+    /// they would be sent to every host without stored credentials.
+    default: Option<Authentication>,
+}
+
+impl Netrc {
+    /// Reads the `.netrc` file from the path in the `NETRC` environment variable, or from the
+    /// home directory of the user (`_netrc` on Windows). Returns `None` if there is no such file.
+    pub fn from_env() -> Option<Self> {
+        let path = match std::env::var_os("NETRC") {
+            Some(path) => path.into(),
+            None => {
+                let file_name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+                dirs::home_dir()?.join(file_name)
+            }
+        };
+
+        match Self::from_path(&path) {
+            Ok(netrc) => Some(netrc),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                tracing::warn!("failed to read {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Reads the `.netrc` file at the given path.
+    pub fn from_path(path: &Path) -> Result<Self, std::io::Error> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parses the contents of a `.netrc` file. Entries without both a `login` and a `password`
+    /// are ignored, as are macro definitions.
+    pub fn parse(contents: &str) -> Self {
+        enum Entry {
+            Machine(String),
+            Default,
+        }
+
+        let mut netrc = Self::default();
+        let mut current: Option<(Entry, Option<String>, Option<String>)> = None;
+        let mut finish = |entry: Option<(Entry, Option<String>, Option<String>)>| {
+            if let Some((entry, Some(username), Some(password))) = entry {
+                let authentication = Authentication::BasicHTTP { username, password };
+                match entry {
+                    Entry::Machine(host) => {
+                        netrc.machines.entry(host).or_insert(authentication);
+                    }
+                    Entry::Default => netrc.default = Some(authentication),
+                }
+            }
+        };
+
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" => {
+                        finish(current.take());
+                        current = tokens
+                            .next()
+                            .map(|host| (Entry::Machine(host.to_string()), None, None));
+                    }
+                    "default" => {
+                        finish(current.take());
+                        current = Some((Entry::Default, None, None));
+                    }
+                    "login" => {
+                        if let (Some((_, username, _)), Some(value)) = (&mut current, tokens.next())
+                        {
+                            *username = Some(value.to_string());
+                        }
+                    }
+                    "password" => {
+                        if let (Some((_, _, password)), Some(value)) = (&mut current, tokens.next())
+                        {
+                            *password = Some(value.to_string());
+                        }
+                    }
+                    "account" => {
+                        tokens.next();
+                    }
+                    "macdef" => {
+                        // A macro definition continues until the next empty line.
+                        finish(current.take());
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        finish(current);
+
+        netrc
+    }
+
+    /// Returns the credentials of the `machine` entry for the given host.
+    pub fn get(&self, host: &str) -> Option<&Authentication> {
+        self.machines.get(host)
+    }
+
+    /// Returns the credentials of the `default` entry, if any.
+    pub fn default_credentials(&self) -> Option<&Authentication> {
+        self.default.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Netrc;
+    use crate::Authentication;
+
+    fn basic(username: &str, password: &str) -> Authentication {
+        Authentication::BasicHTTP {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_netrc() {
+        let netrc = Netrc::parse(
+            "machine conda.example.com login alice password secret\n\
+             \n\
+             machine repo.example.com\n  login bob\n  account ignored\n  password hunter2\n\
+             macdef init\n  machine evil.example.com login eve password eve\n\n\
+             machine incomplete.example.com login carol\n\
+             default login anonymous password guest\n",
+        );
+
+        assert_eq!(
+            netrc.get("conda.example.com"),
+            Some(&basic("alice", "secret"))
+        );
+        assert_eq!(
+            netrc.get("repo.example.com"),
+            Some(&basic("bob", "hunter2"))
+        );
+        assert_eq!(netrc.get("evil.example.com"), None);
+        assert_eq!(netrc.get("incomplete.example.com"), None);
+        assert_eq!(
+            netrc.default_credentials(),
+            Some(&basic("anonymous", "guest"))
+        );
+        assert_eq!(Netrc::parse("").get("conda.example.com"), None);
+    }
+}
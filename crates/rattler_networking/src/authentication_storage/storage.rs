@@ -9,7 +9,7 @@ use std::{
 use keyring::Entry;
 use reqwest::{IntoUrl, Url};
 
-use super::{authentication::Authentication, fallback_storage};
+use super::{authentication::Authentication, fallback_storage, netrc::Netrc};
 
 /// A struct that implements storage and access of authentication
 /// information
@@ -22,20 +22,30 @@ pub struct AuthenticationStorage {
     /// Fallback Storage that will be used if the is no key store application available.
     pub fallback_storage: fallback_storage::FallbackStorage,
 
+    /// Credentials from a `.netrc` file that are used for hosts without stored credentials.
+    pub netrc: Option<Netrc>,
+
     /// A cache so that we don't have to access the keyring all the time
     cache: Arc<Mutex<HashMap<String, Option<Authentication>>>>,
 }
 
 impl AuthenticationStorage {
-    /// Create a new authentication storage with the given store key
+    /// Create a new authentication storage with the given store key. The `.netrc` file of the
+    /// user is used for hosts without stored credentials, see [`Netrc::from_env`].
     pub fn new(store_key: &str, fallback_folder: &Path) -> AuthenticationStorage {
         let fallback_location = fallback_folder.join(format!("{}_auth_store.json", store_key));
         AuthenticationStorage {
             store_key: store_key.to_string(),
             fallback_storage: fallback_storage::FallbackStorage::new(fallback_location),
+            netrc: Netrc::from_env(),
             cache: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// Replaces the `.netrc` credentials that are used for hosts without stored credentials.
+    pub fn with_netrc(self, netrc: Option<Netrc>) -> Self {
+        Self { netrc, ..self }
+    }
 }
 
 /// An error that can occur when accessing the authentication storage
@@ -133,6 +143,11 @@ impl AuthenticationStorage {
         }
     }
 
+    /// Returns the credentials for the given host from the `.netrc` file, if any.
+    fn get_from_netrc(&self, host: &str) -> Option<Authentication> {
+        self.netrc.as_ref()?.get(host).cloned()
+    }
+
     /// Delete the authentication information for the given host
     pub fn delete(&self, host: &str) -> Result<(), AuthenticationStorageError> {
         {
@@ -159,6 +174,9 @@ impl AuthenticationStorage {
     /// E.g. if credentials are stored for `*.prefix.dev` and the
     /// given URL is `https://repo.prefix.dev`, the credentials
     /// for `*.prefix.dev` will be returned.
+    ///
+    /// If no credentials are stored for the host, the credentials
+    /// from the `.netrc` file are used.
     pub fn get_by_url<U: IntoUrl>(
         &self,
         url: U,
@@ -179,7 +197,10 @@ impl AuthenticationStorage {
             };
 
             match credentials {
-                Ok(None) => Ok((url, None)),
+                Ok(None) => {
+                    let credentials = self.get_from_netrc(host);
+                    Ok((url, credentials))
+                }
                 Ok(Some(credentials)) => Ok((url, Some(credentials))),
                 Err(e) => {
                     tracing::warn!("Error retrieving credentials for {}: {}", host, e);
@@ -191,3 +212,37 @@ impl AuthenticationStorage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AuthenticationStorage;
+    use crate::{authentication_storage::netrc::Netrc, Authentication};
+
+    #[test]
+    fn test_get_by_url_falls_back_to_netrc() {
+        let fallback_folder = tempfile::tempdir().unwrap();
+        let storage = AuthenticationStorage::new("rattler_netrc_test", fallback_folder.path())
+            .with_netrc(Some(Netrc::parse(
+                "machine conda.example.com login alice password secret\n\
+                 default login anonymous password guest\n",
+            )));
+
+        let (url, credentials) = storage
+            .get_by_url("https://conda.example.com/channel/noarch/repodata.json")
+            .unwrap();
+        assert_eq!(url.host_str(), Some("conda.example.com"));
+        assert_eq!(
+            credentials,
+            Some(Authentication::BasicHTTP {
+                username: "alice".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+
+        // The `default` entry is not sent to hosts without a `machine` entry.
+        let (_, credentials) = storage
+            .get_by_url("https://other.example.com/channel/noarch/repodata.json")
+            .unwrap();
+        assert_eq!(credentials, None);
+    }
+}
@@ -0,0 +1,413 @@
+//! Recording and replaying the HTTP interactions of an [`crate::AuthenticatedClient`].
+//!
+//! A [`Cassette`] is a directory that contains the responses to requests that were sent through a
+//! client. In [`CassetteMode::Record`] every request is sent to the server and its response is
+//! written to the cassette. In [`CassetteMode::Replay`] no requests are sent at all, instead the
+//! recorded responses are returned. This makes it possible to run integration tests
+//! deterministically and to debug issues against the exact state of the channels of a user.
+//!
+//! Requests are matched on their method and url. Credentials are removed from the url before it
+//! is stored (see [`crate::trace::redact_known_secrets_from_url`]), so cassettes can be shared.
+//! The directory contains an `interactions.json` index and one file per response body.
+
+use crate::trace::redact_known_secrets_from_url;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use reqwest::{Body, Client, Method, Request, Response, ResponseBuilderExt, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
+
+/// The name of the file in a cassette directory that lists the recorded interactions.
+const INDEX_FILE_NAME: &str = "interactions.json";
+
+/// Whether a [`Cassette`] records new interactions or replays recorded ones.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CassetteMode {
+    /// Send requests to the server and record their responses.
+    Record,
+
+    /// Do not send any requests but return the recorded responses instead.
+    Replay,
+}
+
+/// A single recorded request and its response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: Url,
+    status: u16,
+    headers: Vec<(String, String)>,
+
+    /// The name of the file in the cassette that contains the body of the response.
+    body: String,
+}
+
+/// A directory of recorded HTTP interactions. Use [`crate::AuthenticatedClient::with_cassette`] to
+/// record or replay the requests of a client.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    inner: Arc<CassetteInner>,
+}
+
+#[derive(Debug)]
+struct CassetteInner {
+    dir: PathBuf,
+    mode: CassetteMode,
+
+    /// The recorded interactions. The lock is held while the index is written so concurrent
+    /// recordings cannot overwrite the index with an older list.
+    interactions: Mutex<Vec<Interaction>>,
+
+    /// The number used for the name of the next body file.
+    next_body: AtomicUsize,
+}
+
+impl Cassette {
+    /// Opens a cassette that records interactions into the given directory. Interactions that
+    /// were recorded before are kept, unless the same request is recorded again.
+    pub fn record(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let interactions = match read_index(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            result => result?,
+        };
+        Ok(Self::new(dir, CassetteMode::Record, interactions))
+    }
+
+    /// Opens a cassette that replays the interactions that were recorded in the given directory.
+    pub fn replay(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        let interactions = read_index(&dir)?;
+        Ok(Self::new(dir, CassetteMode::Replay, interactions))
+    }
+
+    fn new(dir: PathBuf, mode: CassetteMode, interactions: Vec<Interaction>) -> Self {
+        let next_body = interactions
+            .iter()
+            .filter_map(|interaction| {
+                interaction
+                    .body
+                    .strip_suffix(".body")?
+                    .parse::<usize>()
+                    .ok()
+            })
+            .map(|n| n + 1)
+            .max()
+            .unwrap_or(0);
+        Self {
+            inner: Arc::new(CassetteInner {
+                dir,
+                mode,
+                interactions: Mutex::new(interactions),
+                next_body: AtomicUsize::new(next_body),
+            }),
+        }
+    }
+
+    /// Returns the directory that contains the recorded interactions.
+    pub fn dir(&self) -> &Path {
+        &self.inner.dir
+    }
+
+    /// Returns whether the cassette records or replays interactions.
+    pub fn mode(&self) -> CassetteMode {
+        self.inner.mode
+    }
+
+    /// Executes the request with the given client or replays its recorded response.
+    ///
+    /// The body of the response is streamed in both modes. When recording, the body is written to
+    /// the cassette while the caller reads it and the interaction is only added to the index once
+    /// the whole body was read.
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        request: Request,
+    ) -> reqwest::Result<Response> {
+        let method = request.method().clone();
+        let url = redact_known_secrets_from_url(request.url());
+        match self.inner.mode {
+            CassetteMode::Replay => Ok(self.replay_response(&method, &url).await),
+            CassetteMode::Record => {
+                let response = client.execute(request).await?;
+                let status = response.status();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .filter(|(name, _)| !is_body_framing_header(name.as_str()))
+                    .filter_map(|(name, value)| {
+                        Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+                    })
+                    .collect::<Vec<_>>();
+                let content_length = response.content_length();
+                let body_file = format!(
+                    "{}.body",
+                    self.inner.next_body.fetch_add(1, Ordering::Relaxed)
+                );
+                let interaction = Interaction {
+                    method: method.to_string(),
+                    url: url.clone(),
+                    status: status.as_u16(),
+                    headers: headers.clone(),
+                    body: body_file,
+                };
+                let body = self.record_body(interaction, response.bytes_stream());
+                Ok(build_response(
+                    &url,
+                    status,
+                    &headers,
+                    content_length,
+                    Body::wrap_stream(body),
+                ))
+            }
+        }
+    }
+
+    /// Returns the recorded response to the request. Requests that were not recorded result in a
+    /// `404 Not Found` response.
+    async fn replay_response(&self, method: &Method, url: &Url) -> Response {
+        let interaction = self
+            .inner
+            .interactions
+            .lock()
+            .await
+            .iter()
+            .find(|interaction| interaction.method == method.as_str() && &interaction.url == url)
+            .cloned();
+        let Some(interaction) = interaction else {
+            tracing::warn!("no response to {method} {url} was recorded");
+            return build_response(url, StatusCode::NOT_FOUND, &[], Some(0), Body::from(""));
+        };
+
+        let path = self.inner.dir.join(&interaction.body);
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!("failed to read the recorded response to {method} {url}: {e}");
+                return build_response(url, StatusCode::NOT_FOUND, &[], Some(0), Body::from(""));
+            }
+        };
+        let content_length = file.metadata().await.ok().map(|metadata| metadata.len());
+        build_response(
+            url,
+            StatusCode::from_u16(interaction.status).unwrap_or(StatusCode::OK),
+            &interaction.headers,
+            content_length,
+            Body::wrap_stream(ReaderStream::new(file)),
+        )
+    }
+
+    /// Returns a stream that yields the chunks of `body` and writes them to the body file of the
+    /// interaction. When the stream is exhausted the interaction is added to the index. If the body
+    /// cannot be written the response is still passed on, it is just not recorded.
+    fn record_body(
+        &self,
+        interaction: Interaction,
+        body: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin + 'static,
+    ) -> impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static {
+        enum Recording {
+            NotStarted,
+            Writing(tokio::fs::File),
+            Failed,
+        }
+
+        let cassette = self.clone();
+        let state = (body, Recording::NotStarted, Some(interaction));
+        futures::stream::unfold(state, move |(mut body, mut recording, mut interaction)| {
+            let cassette = cassette.clone();
+            async move {
+                let pending = interaction.as_ref()?;
+                let path = cassette.inner.dir.join(&pending.body);
+                if let Recording::NotStarted = recording {
+                    recording = match tokio::fs::File::create(&path).await {
+                        Ok(file) => Recording::Writing(file),
+                        Err(e) => {
+                            cassette.warn_not_recorded(pending, &e);
+                            Recording::Failed
+                        }
+                    };
+                }
+
+                match body.next().await {
+                    Some(Ok(chunk)) => {
+                        if let Recording::Writing(file) = &mut recording {
+                            if let Err(e) = file.write_all(&chunk).await {
+                                cassette.warn_not_recorded(pending, &e);
+                                let _ = tokio::fs::remove_file(&path).await;
+                                recording = Recording::Failed;
+                            }
+                        }
+                        Some((Ok(chunk), (body, recording, interaction)))
+                    }
+                    Some(Err(e)) => {
+                        if let Recording::Writing(file) = recording {
+                            drop(file);
+                            let _ = tokio::fs::remove_file(&path).await;
+                        }
+                        Some((Err(e), (body, Recording::Failed, None)))
+                    }
+                    None => {
+                        let interaction = interaction.take()?;
+                        if let Recording::Writing(mut file) = recording {
+                            let result = match file.flush().await {
+                                Ok(()) => cassette.store(interaction.clone()).await,
+                                Err(e) => Err(e),
+                            };
+                            if let Err(e) = result {
+                                cassette.warn_not_recorded(&interaction, &e);
+                            }
+                        }
+                        None
+                    }
+                }
+            }
+        })
+    }
+
+    /// Adds an interaction whose body was written to the cassette to the index, replacing earlier
+    /// recordings of the same request.
+    async fn store(&self, interaction: Interaction) -> io::Result<()> {
+        let mut interactions = self.inner.interactions.lock().await;
+        let previous = match interactions.iter_mut().find(|recorded| {
+            recorded.method == interaction.method && recorded.url == interaction.url
+        }) {
+            Some(recorded) => Some(std::mem::replace(recorded, interaction)),
+            None => {
+                interactions.push(interaction);
+                None
+            }
+        };
+
+        let index = serde_json::to_vec_pretty(&*interactions)?;
+        tokio::fs::write(self.inner.dir.join(INDEX_FILE_NAME), index).await?;
+
+        if let Some(previous) = previous {
+            let _ = tokio::fs::remove_file(self.inner.dir.join(previous.body)).await;
+        }
+        Ok(())
+    }
+
+    fn warn_not_recorded(&self, interaction: &Interaction, err: &io::Error) {
+        tracing::warn!(
+            "failed to record the response to {} {} in {}: {err}",
+            interaction.method,
+            interaction.url,
+            self.inner.dir.display()
+        );
+    }
+}
+
+/// Reads the recorded interactions of a cassette.
+fn read_index(dir: &Path) -> io::Result<Vec<Interaction>> {
+    let index = std::fs::read(dir.join(INDEX_FILE_NAME))?;
+    Ok(serde_json::from_slice(&index)?)
+}
+
+/// Returns true for headers that describe how the body was transferred. These no longer apply to
+/// the recorded body.
+fn is_body_framing_header(name: &str) -> bool {
+    name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("transfer-encoding")
+}
+
+/// Constructs a response from recorded parts.
+fn build_response(
+    url: &Url,
+    status: StatusCode,
+    headers: &[(String, String)],
+    content_length: Option<u64>,
+    body: Body,
+) -> Response {
+    let mut builder = http::Response::builder().status(status).url(url.clone());
+    if let Some(content_length) = content_length {
+        builder = builder.header("content-length", content_length);
+    }
+    for (name, value) in headers {
+        builder = builder.header(name, value);
+    }
+    match builder.body(body) {
+        Ok(response) => Response::from(response),
+        // Only invalid recorded headers can cause this, return the response without them.
+        Err(_) => Response::from(
+            http::Response::builder()
+                .status(status)
+                .url(url.clone())
+                .body(Body::from(""))
+                .expect("a response without headers is always valid"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cassette, CassetteMode};
+    use crate::AuthenticatedClient;
+    use reqwest::{Client, StatusCode};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Starts a server that answers a single request with the given body.
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"abc\"\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        format!("http://{address}/conda-forge/noarch/repodata.json")
+    }
+
+    fn client(cassette: Cassette) -> AuthenticatedClient {
+        AuthenticatedClient::from_client(
+            Client::builder().no_proxy().build().unwrap(),
+            Default::default(),
+        )
+        .with_cassette(cassette)
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = serve_once("{\"packages\": {}}");
+
+        // Record the response of the server.
+        let recorder = client(Cassette::record(dir.path()).unwrap());
+        let response = recorder.send(recorder.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The interaction is only recorded once the body was read.
+        assert!(!dir.path().join(super::INDEX_FILE_NAME).exists());
+        assert_eq!(response.text().await.unwrap(), "{\"packages\": {}}");
+        assert!(dir.path().join(super::INDEX_FILE_NAME).exists());
+
+        // The server is gone, the response is replayed from the cassette.
+        let cassette = Cassette::replay(dir.path()).unwrap();
+        assert_eq!(cassette.mode(), CassetteMode::Replay);
+        let replayer = client(cassette);
+        let response = replayer.send(replayer.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["etag"], "\"abc\"");
+        assert_eq!(response.url().as_str(), url);
+        assert_eq!(response.text().await.unwrap(), "{\"packages\": {}}");
+
+        // Requests that were not recorded are not found.
+        let response = replayer
+            .send(replayer.get(url.replace("noarch", "linux-64")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
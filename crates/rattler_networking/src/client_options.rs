@@ -0,0 +1,82 @@
+//! Tuning of the connections that a [`reqwest::Client`] opens.
+//!
+//! Fetching repodata and installing environments sends many small requests to the same few hosts.
+//! Every new connection costs a TCP and TLS handshake, which quickly dominates the time it takes to
+//! download a small noarch package. [`ClientOptions`] controls how connections are kept alive and
+//! reused between requests, and whether requests are multiplexed over a single HTTP/2 connection.
+//! HTTP/2 is negotiated with the server during the TLS handshake (ALPN), so servers that do not
+//! support it are still reached over HTTP/1.1. With the `native-tls` backend ALPN requires the
+//! `native-tls-alpn` feature of `reqwest`, which the `native-tls` feature of this crate enables.
+//! The options only take effect if all requests are sent through the same client, so a single
+//! client should be shared (cloned) between all downloads.
+
+use reqwest::{Client, ClientBuilder};
+use std::time::Duration;
+
+/// Options that control how a [`reqwest::Client`] manages its connections. Use
+/// [`ClientOptions::default`] to keep the defaults of `reqwest`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ClientOptions {
+    /// The maximum number of idle connections that are kept open per host. Idle connections are
+    /// reused by subsequent requests to the same host. If this is `None` the number of idle
+    /// connections is not limited.
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// How long an idle connection is kept open before it is closed. If this is `None` the default
+    /// of `reqwest` (90 seconds) is used.
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// The interval at which TCP keep-alive probes are sent on open connections. Keep-alive
+    /// prevents idle connections from being dropped by proxies and load balancers. If this is
+    /// `None` no keep-alive probes are sent.
+    pub tcp_keepalive: Option<Duration>,
+
+    /// Which HTTP versions are used to connect to servers.
+    pub http_version: HttpVersion,
+
+    /// Adapt the flow control window of HTTP/2 connections to the measured bandwidth, which
+    /// increases the throughput of many concurrent requests over a single connection.
+    pub http2_adaptive_window: bool,
+}
+
+/// The HTTP versions that a client uses, see [`ClientOptions::http_version`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum HttpVersion {
+    /// Offer both HTTP/2 and HTTP/1.1 to HTTPS servers during the TLS handshake (ALPN) and use
+    /// HTTP/2 if the server supports it. Plain HTTP servers are reached over HTTP/1.1.
+    #[default]
+    Negotiate,
+
+    /// Only use HTTP/1.1.
+    Http1Only,
+
+    /// Only use HTTP/2, without negotiating the protocol first. This only works with servers that
+    /// are known to support HTTP/2, including plain HTTP servers.
+    Http2PriorKnowledge,
+}
+
+impl ClientOptions {
+    /// Applies the options to the given builder.
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        match self.http_version {
+            HttpVersion::Negotiate => {}
+            HttpVersion::Http1Only => builder = builder.http1_only(),
+            HttpVersion::Http2PriorKnowledge => builder = builder.http2_prior_knowledge(),
+        }
+        builder
+            .tcp_keepalive(self.tcp_keepalive)
+            .http2_adaptive_window(self.http2_adaptive_window)
+    }
+
+    /// Builds a client with these options. Compression of responses is disabled because package
+    /// archives and compressed repodata are already compressed.
+    pub fn build_client(&self) -> reqwest::Result<Client> {
+        self.apply(Client::builder().no_gzip()).build()
+    }
+}
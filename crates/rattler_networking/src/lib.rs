@@ -5,11 +5,21 @@
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
+#[cfg(feature = "blocking")]
+use crate::retry_policies::{retry_delay, HttpRetryPolicy, HttpStatusError, RetryDecision};
 pub use authentication_storage::{authentication::Authentication, storage::AuthenticationStorage};
+use cassette::Cassette;
+pub use client_options::{ClientOptions, HttpVersion};
 use reqwest::{Client, IntoUrl, Method, Url};
+use trace::TracedRequest;
 
 pub mod authentication_storage;
+pub mod cassette;
+pub mod client_options;
+pub mod oci;
+pub mod progress;
 pub mod retry_policies;
+pub mod trace;
 
 /// A client that can be used to make authenticated requests, based on the [`reqwest::Client`].
 /// By default it uses the fallback storage in the default [`default_auth_store_fallback_directory`].
@@ -20,6 +30,13 @@ pub struct AuthenticatedClient {
 
     /// The authentication storage
     auth_storage: AuthenticationStorage,
+
+    /// Whether requests sent with [`AuthenticatedClient::send`] are logged
+    http_trace: bool,
+
+    /// The cassette that requests sent with [`AuthenticatedClient::send`] are recorded to or
+    /// replayed from
+    cassette: Option<Cassette>,
 }
 
 /// Returns the default auth storage directory used by rattler.
@@ -49,6 +66,23 @@ impl AuthenticatedClient {
         AuthenticatedClient {
             client,
             auth_storage,
+            http_trace: false,
+            cassette: None,
+        }
+    }
+
+    /// Enables or disables logging of the requests that are sent with
+    /// [`AuthenticatedClient::send`]. See the [`trace`] module for what is logged.
+    pub fn with_http_trace(self, http_trace: bool) -> Self {
+        Self { http_trace, ..self }
+    }
+
+    /// Records the responses to the requests that are sent with [`AuthenticatedClient::send`] to
+    /// the cassette, or replays them from it. See the [`cassette`] module for more information.
+    pub fn with_cassette(self, cassette: Cassette) -> Self {
+        Self {
+            cassette: Some(cassette),
+            ..self
         }
     }
 }
@@ -85,6 +119,46 @@ impl AuthenticatedClient {
         }
     }
 
+    /// Sends a request that was created by this client (see [`reqwest::RequestBuilder::send`]).
+    /// If tracing is enabled the request and its response are logged with redacted credentials.
+    /// If a cassette is set the response is recorded to or replayed from it.
+    ///
+    /// Requests are sent only once. Retrying requests that failed with a transient error is left
+    /// to the caller, which can use the [`retry_policies::HttpRetryPolicy`] to decide whether and
+    /// when to retry.
+    pub async fn send(
+        &self,
+        request_builder: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        if !self.http_trace && self.cassette.is_none() {
+            return request_builder.send().await;
+        }
+
+        let request = request_builder.build()?;
+        let traced = self.http_trace.then(|| {
+            (
+                TracedRequest::new(request.method(), request.url(), request.headers()),
+                request.url().clone(),
+                std::time::Instant::now(),
+            )
+        });
+        let result = match &self.cassette {
+            Some(cassette) => cassette.execute(&self.client, request).await,
+            None => self.client.execute(request).await,
+        };
+        if let Some((traced, url, start)) = traced {
+            match &result {
+                Ok(response) => traced.log_response(
+                    response.status(),
+                    response.content_length(),
+                    start.elapsed(),
+                ),
+                Err(err) => traced.log_error(&url, err, start.elapsed()),
+            }
+        }
+        result
+    }
+
     /// Authenticate the given URL with the given authentication information
     fn authenticate_url(&self, url: Url, auth: &Option<Authentication>) -> Url {
         if let Some(credentials) = auth {
@@ -135,6 +209,13 @@ pub struct AuthenticatedClientBlocking {
 
     /// The authentication storage
     auth_storage: AuthenticationStorage,
+
+    /// Whether requests sent with [`AuthenticatedClientBlocking::send`] are logged
+    http_trace: bool,
+
+    /// The policy that determines whether requests sent with
+    /// [`AuthenticatedClientBlocking::send`] are retried
+    retry_policy: Option<HttpRetryPolicy>,
 }
 
 #[cfg(feature = "blocking")]
@@ -147,6 +228,23 @@ impl AuthenticatedClientBlocking {
         AuthenticatedClientBlocking {
             client,
             auth_storage,
+            http_trace: false,
+            retry_policy: None,
+        }
+    }
+
+    /// Enables or disables logging of the requests that are sent with
+    /// [`AuthenticatedClientBlocking::send`]. See the [`trace`] module for what is logged.
+    pub fn with_http_trace(self, http_trace: bool) -> Self {
+        Self { http_trace, ..self }
+    }
+
+    /// Retries requests sent with [`AuthenticatedClientBlocking::send`] that failed with a
+    /// transient error according to the given policy. By default requests are not retried.
+    pub fn with_retry_policy(self, retry_policy: HttpRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
         }
     }
 }
@@ -184,6 +282,72 @@ impl AuthenticatedClientBlocking {
         }
     }
 
+    /// Sends a request that was created by this client (see
+    /// [`reqwest::blocking::RequestBuilder::send`]). If tracing is enabled the request and its
+    /// response are logged with redacted credentials.
+    ///
+    /// If a retry policy is set, requests that fail with a transient error are retried (see
+    /// [`HttpRetryPolicy`]). The response of the last attempt is returned, so the caller still has
+    /// to check its status. Requests with a body that cannot be cloned are never retried.
+    pub fn send(
+        &self,
+        request_builder: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let Some(retry_policy) = &self.retry_policy else {
+            return self.send_once(request_builder);
+        };
+
+        let mut n_past_retries = 0;
+        loop {
+            let Some(attempt) = request_builder.try_clone() else {
+                return self.send_once(request_builder);
+            };
+            let result = self.send_once(attempt);
+            let decision = match &result {
+                Ok(response) => {
+                    match HttpStatusError::from_parts(response.status(), response.headers()) {
+                        Some(err) => retry_policy.should_retry_status(n_past_retries, &err),
+                        None => RetryDecision::DoNotRetry,
+                    }
+                }
+                Err(err) => retry_policy.should_retry_error(n_past_retries, err),
+            };
+            let RetryDecision::Retry { execute_after } = decision else {
+                return result;
+            };
+
+            let delay = retry_delay(execute_after);
+            tracing::warn!("request failed, retry #{} in {delay:?}", n_past_retries + 1);
+            std::thread::sleep(delay);
+            n_past_retries += 1;
+        }
+    }
+
+    /// Sends a request once, see [`AuthenticatedClientBlocking::send`].
+    fn send_once(
+        &self,
+        request_builder: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        if !self.http_trace {
+            return request_builder.send();
+        }
+
+        let request = request_builder.build()?;
+        let traced = TracedRequest::new(request.method(), request.url(), request.headers());
+        let url = request.url().clone();
+        let start = std::time::Instant::now();
+        let result = self.client.execute(request);
+        match &result {
+            Ok(response) => traced.log_response(
+                response.status(),
+                response.content_length(),
+                start.elapsed(),
+            ),
+            Err(err) => traced.log_error(&url, err, start.elapsed()),
+        }
+        result
+    }
+
     /// Authenticate the given URL with the given authentication information
     fn authenticate_url(&self, url: Url, auth: &Option<Authentication>) -> Url {
         if let Some(credentials) = auth {
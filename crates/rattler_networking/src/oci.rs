@@ -0,0 +1,319 @@
+//! Support for channels that are stored as artifacts in an OCI registry (e.g.
+//! `oci://ghcr.io/channel-mirrors/conda-forge`).
+//!
+//! The layout follows the one used by [conda-oci-mirror](https://github.com/channel-mirrors/conda-oci-mirror):
+//!
+//! * The `repodata.json` of a subdirectory is stored in the repository
+//!   `<channel>/<subdir>/repodata.json` with the tag `latest`.
+//! * A package is stored in the repository `<channel>/<subdir>/<name>` with the tag
+//!   `<version>-<build>`. Characters that are not allowed in tags are escaped.
+//!
+//! Use [`get_blob`] to download the file that an `oci://` url refers to.
+
+use reqwest::{
+    header::{ACCEPT, WWW_AUTHENTICATE},
+    Response, StatusCode, Url,
+};
+use serde::Deserialize;
+
+use crate::AuthenticatedClient;
+
+/// The media type of an OCI image manifest.
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// The media type of a layer that contains a `repodata.json` file.
+const REPODATA_MEDIA_TYPE: &str = "application/vnd.conda.repodata.v1+json";
+
+/// The media type of a layer that contains a `.tar.bz2` package archive.
+const TAR_BZ2_MEDIA_TYPE: &str = "application/vnd.conda.package.v1";
+
+/// The media type of a layer that contains a `.conda` package archive.
+const CONDA_MEDIA_TYPE: &str = "application/vnd.conda.package.v2";
+
+/// An error that can occur when downloading a file from an OCI registry.
+#[derive(Debug, thiserror::Error)]
+pub enum OciError {
+    /// The url does not refer to a `repodata.json` file or a package archive in an OCI registry.
+    #[error("'{0}' is not a valid OCI url")]
+    InvalidUrl(Url),
+
+    /// The registry requires authentication but did not specify how to obtain a token.
+    #[error("the registry did not provide a valid authentication challenge")]
+    InvalidChallenge,
+
+    /// The manifest does not contain a layer with the expected media type.
+    #[error("the manifest of {0} does not contain a layer with media type '{1}'")]
+    MissingLayer(String, &'static str),
+
+    /// A request to the registry failed.
+    #[error(transparent)]
+    ReqwestError(#[from] reqwest::Error),
+
+    /// The registry returned a response that could not be parsed.
+    #[error("the registry returned an invalid response")]
+    InvalidResponse(#[from] serde_json::Error),
+}
+
+/// Identifies a single file stored in an OCI registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciReference {
+    /// The url of the registry (e.g. `https://ghcr.io/`)
+    pub registry_url: Url,
+
+    /// The repository that contains the file (e.g. `channel-mirrors/conda-forge/linux-64/zlib`)
+    pub repository: String,
+
+    /// The tag that identifies the file in the repository (e.g. `1.2.13-hd590300_5`)
+    pub tag: String,
+
+    /// The media type of the layer that contains the file
+    pub media_type: &'static str,
+}
+
+impl OciReference {
+    /// Converts an `oci://` url that refers to a `repodata.json` file or a package archive into the
+    /// reference of the file in the registry. Returns `None` if the url cannot be converted.
+    ///
+    /// Registries are accessed over https except for registries running on `localhost`.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        if url.scheme() != "oci" {
+            return None;
+        }
+
+        let host = url.host_str()?;
+        let scheme = if host == "localhost" || host == "127.0.0.1" {
+            "http"
+        } else {
+            "https"
+        };
+        let mut registry_url = Url::parse(&format!("{scheme}://{host}/")).ok()?;
+        registry_url.set_port(url.port()).ok()?;
+
+        let (subdir_path, file_name) = url.path().trim_matches('/').rsplit_once('/')?;
+        if file_name == "repodata.json" {
+            return Some(Self {
+                registry_url,
+                repository: format!("{subdir_path}/repodata.json"),
+                tag: String::from("latest"),
+                media_type: REPODATA_MEDIA_TYPE,
+            });
+        }
+
+        let (identifier, media_type) = if let Some(identifier) = file_name.strip_suffix(".conda") {
+            (identifier, CONDA_MEDIA_TYPE)
+        } else {
+            (file_name.strip_suffix(".tar.bz2")?, TAR_BZ2_MEDIA_TYPE)
+        };
+        let mut parts = identifier.rsplitn(3, '-');
+        let (build, version, name) = (parts.next()?, parts.next()?, parts.next()?);
+
+        Some(Self {
+            registry_url,
+            repository: format!("{subdir_path}/{}", escape_name(name)),
+            tag: escape_tag(&format!("{version}-{build}")),
+            media_type,
+        })
+    }
+
+    /// Returns the url of the manifest of the file.
+    pub fn manifest_url(&self) -> Url {
+        self.registry_url
+            .join(&format!("v2/{}/manifests/{}", self.repository, self.tag))
+            .expect("repository and tag are valid url fragments")
+    }
+
+    /// Returns the url of the blob with the given digest in the repository of the file.
+    pub fn blob_url(&self, digest: &str) -> Url {
+        self.registry_url
+            .join(&format!("v2/{}/blobs/{digest}", self.repository))
+            .expect("repository and digest are valid url fragments")
+    }
+}
+
+/// Repository names must start with a lowercase letter or digit, therefore names that start with
+/// an underscore (e.g. `_libgcc_mutex`) are prefixed with `zzz`.
+fn escape_name(name: &str) -> String {
+    if name.starts_with('_') {
+        format!("zzz{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Tags can only contain letters, digits, `_`, `.` and `-`, other characters that can occur in
+/// versions and build strings are replaced.
+fn escape_tag(tag: &str) -> String {
+    tag.replace('+', "__p__")
+        .replace('!', "__e__")
+        .replace('=', "__eq__")
+}
+
+/// The parts of an OCI image manifest that are used to find the layer that contains a file.
+#[derive(Deserialize)]
+struct Manifest {
+    layers: Vec<Layer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Layer {
+    media_type: String,
+    digest: String,
+}
+
+/// The response of a token endpoint of a registry.
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+/// Sends a request for the file that the `oci://` url refers to and returns the response.
+///
+/// The manifest of the file is fetched to find the digest of the layer that contains it. If the
+/// registry requires authentication an anonymous bearer token is requested from the token
+/// endpoint advertised by the registry.
+pub async fn get_blob(client: &AuthenticatedClient, url: &Url) -> Result<Response, OciError> {
+    let reference = OciReference::from_url(url).ok_or_else(|| OciError::InvalidUrl(url.clone()))?;
+
+    let manifest_request = |token: Option<&str>| {
+        let request = client
+            .get(reference.manifest_url())
+            .header(ACCEPT, MANIFEST_MEDIA_TYPE);
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    };
+
+    // Request the manifest, if the registry requires authentication, request a token and try again.
+    let mut token = None;
+    let mut response = client.send(manifest_request(None)).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(OciError::InvalidChallenge)?;
+        token = Some(fetch_token(client, challenge).await?);
+        response = client.send(manifest_request(token.as_deref())).await?;
+    }
+    let manifest: Manifest = serde_json::from_slice(&response.error_for_status()?.bytes().await?)?;
+
+    let layer = manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type == reference.media_type)
+        .ok_or_else(|| OciError::MissingLayer(url.to_string(), reference.media_type))?;
+
+    let request = client.get(reference.blob_url(&layer.digest));
+    let request = match &token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    Ok(client.send(request).await?.error_for_status()?)
+}
+
+/// Requests a token from the token endpoint described by the `WWW-Authenticate` challenge of a
+/// registry (e.g. `Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="..."`).
+async fn fetch_token(client: &AuthenticatedClient, challenge: &str) -> Result<String, OciError> {
+    let params = challenge
+        .strip_prefix("Bearer ")
+        .map(parse_challenge_params)
+        .ok_or(OciError::InvalidChallenge)?;
+    let realm = params
+        .iter()
+        .find(|(key, _)| *key == "realm")
+        .and_then(|(_, realm)| Url::parse(realm).ok())
+        .ok_or(OciError::InvalidChallenge)?;
+
+    let query = params
+        .iter()
+        .filter(|(key, _)| *key == "service" || *key == "scope")
+        .collect::<Vec<_>>();
+    let response = client
+        .send(client.get(realm).query(&query))
+        .await?
+        .error_for_status()?;
+    let token: TokenResponse = serde_json::from_slice(&response.bytes().await?)?;
+    Ok(token.token)
+}
+
+/// Parses the comma separated `key="value"` parameters of an authentication challenge.
+fn parse_challenge_params(params: &str) -> Vec<(&str, &str)> {
+    let mut result = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        result.push((key.trim(), value));
+        rest = remainder.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_challenge_params, OciReference};
+    use reqwest::Url;
+
+    #[test]
+    fn test_reference_from_url() {
+        let reference = OciReference::from_url(
+            &Url::parse("oci://ghcr.io/channel-mirrors/conda-forge/linux-64/repodata.json")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            reference.manifest_url().as_str(),
+            "https://ghcr.io/v2/channel-mirrors/conda-forge/linux-64/repodata.json/manifests/latest"
+        );
+
+        let reference = OciReference::from_url(
+            &Url::parse(
+                "oci://ghcr.io/channel-mirrors/conda-forge/linux-64/_openmp_mutex-4.5-2_gnu.tar.bz2",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            reference.repository,
+            "channel-mirrors/conda-forge/linux-64/zzz_openmp_mutex"
+        );
+        assert_eq!(reference.tag, "4.5-2_gnu");
+        assert_eq!(reference.media_type, "application/vnd.conda.package.v1");
+
+        let reference = OciReference::from_url(
+            &Url::parse("oci://localhost:5000/conda-forge/noarch/foo-1!2.0+local-pyh_0.conda")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            reference.blob_url("sha256:abc").as_str(),
+            "http://localhost:5000/v2/conda-forge/noarch/foo/blobs/sha256:abc"
+        );
+        assert_eq!(reference.tag, "1__e__2.0__p__local-pyh_0");
+        assert_eq!(reference.media_type, "application/vnd.conda.package.v2");
+
+        assert_eq!(
+            OciReference::from_url(&Url::parse("https://ghcr.io/conda-forge/noarch/").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_challenge_params() {
+        assert_eq!(
+            parse_challenge_params(
+                r#"realm="https://ghcr.io/token",service="ghcr.io",scope="repository:foo:pull,push""#
+            ),
+            vec![
+                ("realm", "https://ghcr.io/token"),
+                ("service", "ghcr.io"),
+                ("scope", "repository:foo:pull,push"),
+            ]
+        );
+    }
+}
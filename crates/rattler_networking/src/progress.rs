@@ -0,0 +1,205 @@
+//! Types to report the progress of downloads, including the current speed and the estimated time
+//! remaining.
+//!
+//! The speed is computed as a rolling average over a short window by a [`SpeedEstimator`] so that
+//! every frontend reports the same numbers. Use [`AggregateProgress`] to combine the progress of
+//! multiple concurrent downloads into a single [`DownloadProgress`].
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The default duration over which the download speed is averaged.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+
+/// A struct that provides information about download progress.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadProgress {
+    /// The number of bytes already downloaded
+    pub bytes: u64,
+
+    /// The total number of bytes to download. Or `None` if this is not known. This can happen
+    /// if the server does not supply a `Content-Length` header.
+    pub total: Option<u64>,
+
+    /// The average download speed in bytes per second over the last few seconds, or `None` if not
+    /// enough progress has been made to compute it.
+    pub speed: Option<f64>,
+
+    /// The estimated time until the download completes, or `None` if either the total size or
+    /// the speed is unknown.
+    pub eta: Option<Duration>,
+}
+
+/// Computes a rolling average of the download speed from the number of bytes that have been
+/// downloaded at different points in time.
+#[derive(Debug, Clone)]
+pub struct SpeedEstimator {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Default for SpeedEstimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl SpeedEstimator {
+    /// Constructs a new estimator that averages the speed over the given window.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records that `bytes` bytes have been downloaded in total so far.
+    pub fn record(&mut self, bytes: u64) {
+        self.record_at(Instant::now(), bytes)
+    }
+
+    fn record_at(&mut self, now: Instant, bytes: u64) {
+        self.samples.push_back((now, bytes));
+
+        // Drop the samples that fall outside of the window but keep the last sample before the
+        // window so the average covers the entire window.
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Returns the number of bytes that were last recorded.
+    pub fn bytes(&self) -> u64 {
+        self.samples.back().map_or(0, |(_, bytes)| *bytes)
+    }
+
+    /// Returns the average speed in bytes per second over the window.
+    pub fn speed(&self) -> Option<f64> {
+        let (first_time, first_bytes) = self.samples.front()?;
+        let (last_time, last_bytes) = self.samples.back()?;
+        let elapsed = last_time.duration_since(*first_time);
+        if elapsed.is_zero() {
+            return None;
+        }
+        Some(last_bytes.saturating_sub(*first_bytes) as f64 / elapsed.as_secs_f64())
+    }
+
+    /// Returns the progress of a download of `total` bytes based on the recorded samples.
+    pub fn progress(&self, total: Option<u64>) -> DownloadProgress {
+        let bytes = self.bytes();
+        let speed = self.speed();
+        let eta = match (total, speed) {
+            (Some(total), Some(speed)) if speed > 0.0 => Some(Duration::from_secs_f64(
+                total.saturating_sub(bytes) as f64 / speed,
+            )),
+            _ => None,
+        };
+        DownloadProgress {
+            bytes,
+            total,
+            speed,
+            eta,
+        }
+    }
+}
+
+/// Combines the progress of multiple concurrent downloads. Every download is tracked by an
+/// [`AggregateProgressEntry`] obtained from [`AggregateProgress::add_download`].
+#[derive(Debug, Clone, Default)]
+pub struct AggregateProgress {
+    inner: Arc<Mutex<AggregateProgressInner>>,
+}
+
+#[derive(Debug, Default)]
+struct AggregateProgressInner {
+    downloads: Vec<(u64, Option<u64>)>,
+    estimator: SpeedEstimator,
+}
+
+impl AggregateProgressInner {
+    fn bytes(&self) -> u64 {
+        self.downloads.iter().map(|(bytes, _)| bytes).sum()
+    }
+}
+
+/// Tracks the progress of a single download that is part of an [`AggregateProgress`].
+#[derive(Debug, Clone)]
+pub struct AggregateProgressEntry {
+    aggregate: AggregateProgress,
+    index: usize,
+}
+
+impl AggregateProgress {
+    /// Adds a download of `total` bytes (if known) and returns the entry to report its progress.
+    pub fn add_download(&self, total: Option<u64>) -> AggregateProgressEntry {
+        let mut inner = self.inner.lock().unwrap();
+        inner.downloads.push((0, total));
+        AggregateProgressEntry {
+            aggregate: self.clone(),
+            index: inner.downloads.len() - 1,
+        }
+    }
+
+    /// Returns the combined progress of all downloads. The total is only known if the total of
+    /// every download is known.
+    pub fn progress(&self) -> DownloadProgress {
+        let inner = self.inner.lock().unwrap();
+        let total = inner.downloads.iter().map(|(_, total)| *total).sum();
+        inner.estimator.progress(total)
+    }
+}
+
+impl AggregateProgressEntry {
+    /// Updates the progress of this download.
+    pub fn update(&self, bytes: u64, total: Option<u64>) {
+        let mut inner = self.aggregate.inner.lock().unwrap();
+        inner.downloads[self.index] = (bytes, total);
+        let bytes = inner.bytes();
+        inner.estimator.record(bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AggregateProgress, SpeedEstimator};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_speed_and_eta() {
+        let start = Instant::now();
+        let mut estimator = SpeedEstimator::new(Duration::from_secs(2));
+        assert_eq!(estimator.speed(), None);
+
+        estimator.record_at(start, 0);
+        estimator.record_at(start + Duration::from_secs(1), 100);
+        let progress = estimator.progress(Some(1000));
+        assert_eq!(progress.bytes, 100);
+        assert_eq!(progress.speed, Some(100.0));
+        assert_eq!(progress.eta, Some(Duration::from_secs(9)));
+
+        // Samples outside of the window no longer contribute to the speed.
+        estimator.record_at(start + Duration::from_secs(2), 200);
+        estimator.record_at(start + Duration::from_secs(4), 1000);
+        assert_eq!(estimator.speed(), Some(400.0));
+        assert_eq!(estimator.progress(None).eta, None);
+    }
+
+    #[test]
+    fn test_aggregate_progress() {
+        let aggregate = AggregateProgress::default();
+        let first = aggregate.add_download(Some(100));
+        let second = aggregate.add_download(Some(50));
+        first.update(40, Some(100));
+        second.update(10, Some(50));
+
+        let progress = aggregate.progress();
+        assert_eq!(progress.bytes, 50);
+        assert_eq!(progress.total, Some(150));
+
+        second.update(20, None);
+        assert_eq!(aggregate.progress().total, None);
+    }
+}
@@ -2,7 +2,24 @@
 //! implementations.
 //!
 //! This module also provides the [`DoNotRetryPolicy`] which is useful if you do not want to retry
-//! anything.
+//! anything, and the [`HttpRetryPolicy`] which is used by [`crate::AuthenticatedClientBlocking`]
+//! and by callers of [`crate::AuthenticatedClient`] to retry requests that failed with a
+//! transient error.
+//!
+//! Servers signal that they are overloaded by responding with `429 Too Many Requests` or
+//! `503 Service Unavailable`, often together with a `Retry-After` header that tells the client
+//! how long to wait. Retrying such a request sooner only makes matters worse, so the
+//! [`HttpRetryPolicy`] never retries before the time requested by the server. Responses with a
+//! status that will not change by retrying (e.g. `403 Forbidden` or `404 Not Found`) are never
+//! retried.
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use std::{fmt, sync::Arc, time::Duration};
+use thiserror::Error;
 
 pub use retry_policies::{policies::*, Jitter, RetryDecision, RetryPolicy};
 
@@ -21,3 +38,348 @@ impl RetryPolicy for DoNotRetryPolicy {
 pub fn default_retry_policy() -> ExponentialBackoff {
     ExponentialBackoff::builder().build_with_max_retries(3)
 }
+
+/// The longest `Retry-After` that is honored by default. Servers that ask to wait longer than
+/// this are not retried, the error is returned instead.
+pub const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// An HTTP response with an error status, classified by whether retrying the request might
+/// succeed.
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum HttpStatusError {
+    /// The server is temporarily unable to handle the request (e.g. `429 Too Many Requests` or
+    /// `503 Service Unavailable`). The request might succeed when it is retried.
+    #[error("the server responded with {status}, the request can be retried")]
+    Retryable {
+        /// The status of the response.
+        status: StatusCode,
+
+        /// How long the server asked to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+
+    /// The request failed and retrying it will not change that (e.g. `403 Forbidden` or
+    /// `404 Not Found`).
+    #[error("the server responded with {status}")]
+    Permanent {
+        /// The status of the response.
+        status: StatusCode,
+    },
+}
+
+impl HttpStatusError {
+    /// Classifies the status and headers of a response. Returns `None` if the status does not
+    /// indicate an error.
+    pub fn from_parts(status: StatusCode, headers: &HeaderMap) -> Option<Self> {
+        Self::from_parts_at(status, headers, Utc::now())
+    }
+
+    /// Like [`Self::from_parts`] but a `Retry-After` date is converted to a duration relative to
+    /// `now` instead of the time of the system.
+    pub fn from_parts_at(
+        status: StatusCode,
+        headers: &HeaderMap,
+        now: DateTime<Utc>,
+    ) -> Option<Self> {
+        if !status.is_client_error() && !status.is_server_error() {
+            return None;
+        }
+
+        if is_retryable_status(status) {
+            Some(Self::Retryable {
+                status,
+                retry_after: parse_retry_after(headers, now),
+            })
+        } else {
+            Some(Self::Permanent { status })
+        }
+    }
+
+    /// Classifies a response. Returns `None` if the status of the response does not indicate an
+    /// error.
+    pub fn from_response(response: &reqwest::Response) -> Option<Self> {
+        Self::from_parts(response.status(), response.headers())
+    }
+
+    /// Returns the status of the response.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Retryable { status, .. } | Self::Permanent { status } => *status,
+        }
+    }
+
+    /// Returns true if retrying the request might succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Retryable { .. })
+    }
+
+    /// Returns how long the server asked to wait before retrying the request.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Retryable { retry_after, .. } => *retry_after,
+            Self::Permanent { .. } => None,
+        }
+    }
+}
+
+/// Returns true if a response with the given status is worth retrying. These are
+/// `408 Request Timeout`, `429 Too Many Requests` and the server errors that indicate a temporary
+/// problem (`500`, `502`, `503` and `504`).
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Parses the `Retry-After` header, which either contains a number of seconds or an HTTP date.
+/// Dates in the past result in a duration of zero.
+pub fn parse_retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// A [`RetryPolicy`] for HTTP requests. Transient failures (timeouts, connection errors and
+/// responses with a retryable status) are retried with the backoff of the wrapped policy, but
+/// never sooner than the server asked for with the `Retry-After` header. Responses with a
+/// permanent error status are not retried.
+#[derive(Clone)]
+pub struct HttpRetryPolicy {
+    backoff: Arc<dyn RetryPolicy + Send + Sync>,
+    max_retry_after: Duration,
+}
+
+impl Default for HttpRetryPolicy {
+    fn default() -> Self {
+        Self::new(default_retry_policy())
+    }
+}
+
+impl fmt::Debug for HttpRetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpRetryPolicy")
+            .field("max_retry_after", &self.max_retry_after)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpRetryPolicy {
+    /// Constructs a policy that determines the number of retries and the time between them with
+    /// the given `backoff` policy.
+    pub fn new(backoff: impl RetryPolicy + Send + Sync + 'static) -> Self {
+        Self {
+            backoff: Arc::new(backoff),
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        }
+    }
+
+    /// Sets the longest `Retry-After` that is honored. If the server asks to wait longer, the
+    /// request is not retried.
+    pub fn with_max_retry_after(self, max_retry_after: Duration) -> Self {
+        Self {
+            max_retry_after,
+            ..self
+        }
+    }
+
+    /// Determines whether a request that failed with the given error status should be retried.
+    pub fn should_retry_status(
+        &self,
+        n_past_retries: u32,
+        error: &HttpStatusError,
+    ) -> RetryDecision {
+        self.should_retry_status_at(n_past_retries, error, Utc::now())
+    }
+
+    /// Determines whether a request that failed before a response was received should be
+    /// retried. Only timeouts and connection errors are retried.
+    pub fn should_retry_error(&self, n_past_retries: u32, error: &reqwest::Error) -> RetryDecision {
+        if error.is_timeout() || error.is_connect() {
+            self.backoff.should_retry(n_past_retries)
+        } else {
+            RetryDecision::DoNotRetry
+        }
+    }
+
+    /// Like [`Self::should_retry_status`] but the `Retry-After` of the error is counted from `now`
+    /// instead of the time of the system.
+    pub fn should_retry_status_at(
+        &self,
+        n_past_retries: u32,
+        error: &HttpStatusError,
+        now: DateTime<Utc>,
+    ) -> RetryDecision {
+        let HttpStatusError::Retryable { retry_after, .. } = error else {
+            return RetryDecision::DoNotRetry;
+        };
+
+        let retry_after = match retry_after {
+            Some(retry_after) if *retry_after > self.max_retry_after => {
+                return RetryDecision::DoNotRetry
+            }
+            Some(retry_after) => chrono::Duration::from_std(*retry_after)
+                .unwrap_or_else(|_| chrono::Duration::zero()),
+            None => chrono::Duration::zero(),
+        };
+
+        match self.backoff.should_retry(n_past_retries) {
+            RetryDecision::Retry { execute_after } => RetryDecision::Retry {
+                execute_after: execute_after.max(now + retry_after),
+            },
+            RetryDecision::DoNotRetry => RetryDecision::DoNotRetry,
+        }
+    }
+}
+
+impl RetryPolicy for HttpRetryPolicy {
+    fn should_retry(&self, n_past_retries: u32) -> RetryDecision {
+        self.backoff.should_retry(n_past_retries)
+    }
+}
+
+/// Returns how long to wait until the retry that was decided on should be executed.
+pub fn retry_delay(execute_after: DateTime<Utc>) -> Duration {
+    retry_delay_at(execute_after, Utc::now())
+}
+
+/// Returns how long to wait from `now` until the retry that was decided on should be executed.
+pub fn retry_delay_at(execute_after: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (execute_after - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse_retry_after, retry_delay_at, HttpRetryPolicy, HttpStatusError, RetryDecision,
+    };
+    use chrono::{TimeZone, Utc};
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+    use std::time::Duration;
+
+    fn retry_after(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        assert_eq!(
+            parse_retry_after(&retry_after("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(&retry_after("Wed, 21 Oct 2015 07:30:00 GMT"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(&retry_after("Wed, 21 Oct 2015 07:00:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(&retry_after("soon"), now), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn test_classify_status() {
+        assert_eq!(
+            HttpStatusError::from_parts(StatusCode::OK, &HeaderMap::new()),
+            None
+        );
+        assert_eq!(
+            HttpStatusError::from_parts(StatusCode::TOO_MANY_REQUESTS, &retry_after("3")),
+            Some(HttpStatusError::Retryable {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some(Duration::from_secs(3))
+            })
+        );
+        assert!(
+            HttpStatusError::from_parts(StatusCode::BAD_GATEWAY, &HeaderMap::new())
+                .unwrap()
+                .is_retryable()
+        );
+        for status in [StatusCode::FORBIDDEN, StatusCode::NOT_FOUND] {
+            assert_eq!(
+                HttpStatusError::from_parts(status, &retry_after("3")),
+                Some(HttpStatusError::Permanent { status })
+            );
+        }
+
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        assert_eq!(
+            HttpStatusError::from_parts_at(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &retry_after("Wed, 21 Oct 2015 07:30:00 GMT"),
+                now
+            ),
+            Some(HttpStatusError::Retryable {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                retry_after: Some(Duration::from_secs(120))
+            })
+        );
+        assert_eq!(
+            retry_delay_at(now + chrono::Duration::seconds(5), now),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            retry_delay_at(now - chrono::Duration::seconds(5), now),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_should_retry_status() {
+        // The backoff policy schedules retries against the time of the system.
+        let now = Utc::now();
+        let policy = HttpRetryPolicy::default().with_max_retry_after(Duration::from_secs(60));
+        let retryable = |retry_after| HttpStatusError::Retryable {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            retry_after,
+        };
+
+        // The backoff of the default policy is much shorter than the requested 30 seconds.
+        assert!(matches!(
+            policy.should_retry_status_at(0, &retryable(Some(Duration::from_secs(30))), now),
+            RetryDecision::Retry { execute_after } if execute_after >= now + chrono::Duration::seconds(30)
+        ));
+        assert!(matches!(
+            policy.should_retry_status_at(0, &retryable(None), now),
+            RetryDecision::Retry { .. }
+        ));
+        assert!(matches!(
+            policy.should_retry_status_at(0, &retryable(Some(Duration::from_secs(120))), now),
+            RetryDecision::DoNotRetry
+        ));
+        assert!(matches!(
+            policy.should_retry_status_at(3, &retryable(None), now),
+            RetryDecision::DoNotRetry
+        ));
+        assert!(matches!(
+            policy.should_retry_status_at(
+                0,
+                &HttpStatusError::Permanent {
+                    status: StatusCode::NOT_FOUND
+                },
+                now
+            ),
+            RetryDecision::DoNotRetry
+        ));
+    }
+}
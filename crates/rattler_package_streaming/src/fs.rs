@@ -1,6 +1,6 @@
 //! Functions to extracting or stream a Conda package from a file on disk.
 
-use crate::{ExtractError, ExtractResult};
+use crate::{ExtractError, ExtractOptions, ExtractResult};
 use rattler_conda_types::package::ArchiveType;
 use std::fs::File;
 use std::path::Path;
@@ -47,8 +47,44 @@ pub fn extract_conda(archive: &Path, destination: &Path) -> Result<ExtractResult
 ///     .unwrap();
 /// ```
 pub fn extract(archive: &Path, destination: &Path) -> Result<ExtractResult, ExtractError> {
-    match ArchiveType::try_from(archive).ok_or(ExtractError::UnsupportedArchiveType)? {
+    match crate::archive_type(archive)? {
         ArchiveType::TarBz2 => extract_tar_bz2(archive, destination),
         ArchiveType::Conda => extract_conda(archive, destination),
     }
 }
+
+/// Extracts the contents a package archive at the specified path to a directory. The type of
+/// package is determined based on the file extension of the archive path. The `options` allow
+/// cancelling the extraction and reporting its progress.
+///
+/// ```rust,no_run
+/// # use std::path::Path;
+/// # use std::sync::Arc;
+/// use rattler_package_streaming::{fs::extract_with_options, CancellationFlag, ExtractOptions};
+/// let cancellation = CancellationFlag::new();
+/// let options = ExtractOptions {
+///     cancellation: Some(cancellation.clone()),
+///     progress: Some(Arc::new(|progress| {
+///         println!("extracted {}", progress.path.display())
+///     })),
+/// };
+/// let _ = extract_with_options(
+///     Path::new("conda-forge/win-64/python-3.11.0-hcf16a7b_0_cpython.conda"),
+///     Path::new("/tmp"),
+///     &options)
+///     .unwrap();
+/// ```
+pub fn extract_with_options(
+    archive: &Path,
+    destination: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractResult, ExtractError> {
+    let archive_type = crate::archive_type(archive)?;
+    let file = File::open(archive)?;
+    match archive_type {
+        ArchiveType::TarBz2 => {
+            crate::read::extract_tar_bz2_with_options(file, destination, options)
+        }
+        ArchiveType::Conda => crate::read::extract_conda_with_options(file, destination, options),
+    }
+}
@@ -2,7 +2,12 @@
 
 //! This crate provides the ability to extract a Conda package archive or specific parts of it.
 
+use rattler_conda_types::package::{ArchiveType, NonCondaArchiveType};
 use rattler_digest::{Md5Hash, Sha256Hash};
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 pub mod read;
 pub mod seek;
@@ -38,9 +43,23 @@ pub enum ExtractError {
     #[error(transparent)]
     ReqwestError(::reqwest::Error),
 
+    #[cfg(feature = "reqwest")]
+    #[error(transparent)]
+    OciError(rattler_networking::oci::OciError),
+
+    #[cfg(feature = "reqwest")]
+    #[error("failed to download {0}")]
+    HttpStatusError(
+        url::Url,
+        #[source] rattler_networking::retry_policies::HttpStatusError,
+    ),
+
     #[error("unsupported package archive format")]
     UnsupportedArchiveType,
 
+    #[error("the archive is {0} which is not a conda package, {}", .0.suggestion())]
+    NotACondaPackage(NonCondaArchiveType),
+
     #[error("the task was cancelled")]
     Cancelled,
 }
@@ -53,4 +72,87 @@ pub struct ExtractResult {
 
     /// The Md5 hash of the extracted archive.
     pub md5: Md5Hash,
+
+    /// The size of the extracted archive in bytes.
+    pub size: u64,
+}
+
+/// A flag that cancels an extraction when it is set. Cloning the flag returns a handle to the same
+/// flag, which makes it possible to cancel an extraction from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationFlag(Arc<AtomicBool>);
+
+impl CancellationFlag {
+    /// Constructs a new flag that is not set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the flag. Extractions that use the flag stop with [`ExtractError::Cancelled`] before
+    /// extracting the next entry of the archive.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the flag has been set.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Describes the progress of an extraction after an entry of the archive has been extracted.
+#[derive(Debug, Clone)]
+pub struct ExtractProgress {
+    /// The path of the entry that was extracted, relative to the destination.
+    pub path: PathBuf,
+
+    /// The size of the entry in bytes.
+    pub size: u64,
+
+    /// The number of entries that have been extracted so far, including this one.
+    pub entries_extracted: usize,
+
+    /// The total size of the entries that have been extracted so far in bytes.
+    pub bytes_extracted: u64,
+}
+
+/// A function that is called after every entry of an archive has been extracted.
+pub type ExtractProgressFunc = Arc<dyn Fn(&ExtractProgress) + Send + Sync>;
+
+/// Options that control the extraction of a package archive.
+#[derive(Clone, Default)]
+pub struct ExtractOptions {
+    /// If set, the extraction is cancelled when the flag is set.
+    pub cancellation: Option<CancellationFlag>,
+
+    /// If set, this function is called after every entry of the archive has been extracted.
+    pub progress: Option<ExtractProgressFunc>,
+}
+
+impl Debug for ExtractOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtractOptions")
+            .field("cancellation", &self.cancellation)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl ExtractOptions {
+    /// Returns an error if the extraction has been cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), ExtractError> {
+        match &self.cancellation {
+            Some(cancellation) if cancellation.is_cancelled() => Err(ExtractError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Determines the type of the package archive at `path` from its filename. Returns an error that
+/// describes the archive if it is a known type of archive that is not a Conda package.
+pub(crate) fn archive_type(path: &Path) -> Result<ArchiveType, ExtractError> {
+    ArchiveType::try_from(path).ok_or_else(|| match NonCondaArchiveType::try_from(path) {
+        Some(non_conda_type) => ExtractError::NotACondaPackage(non_conda_type),
+        None => ExtractError::UnsupportedArchiveType,
+    })
 }
@@ -1,7 +1,7 @@
 //! Functions that enable extracting or streaming a Conda package for objects that implement the
 //! [`std::io::Read`] trait.
 
-use super::{ExtractError, ExtractResult};
+use super::{ExtractError, ExtractOptions, ExtractProgress, ExtractResult};
 use std::{ffi::OsStr, io::Read, path::Path};
 use zip::read::read_zipfile_from_stream;
 
@@ -19,64 +19,173 @@ pub(crate) fn stream_tar_zst(
     Ok(tar::Archive::new(zstd::stream::read::Decoder::new(reader)?))
 }
 
+/// A reader that counts the number of bytes that are read from it.
+struct CountingReader<R> {
+    reader: R,
+    bytes_read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.reader.read(buf)?;
+        self.bytes_read += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+/// Computes the hashes and the size of an archive while it is being read.
+type ArchiveReader<R> = rattler_digest::HashingReader<
+    rattler_digest::HashingReader<CountingReader<R>, rattler_digest::Sha256>,
+    rattler_digest::Md5,
+>;
+
+/// Wraps the reader of an archive in readers that compute the hashes and the size of the archive
+/// while it is being read.
+fn archive_reader<R: Read>(reader: R) -> ArchiveReader<R> {
+    rattler_digest::HashingReader::new(rattler_digest::HashingReader::new(CountingReader {
+        reader,
+        bytes_read: 0,
+    }))
+}
+
+/// Reads the remainder of the archive and returns its hashes and size.
+fn finish_archive_reader<R: Read>(
+    mut reader: ArchiveReader<R>,
+) -> Result<ExtractResult, ExtractError> {
+    // Read the file to the end to make sure the hash is properly computed.
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+
+    let (sha256_reader, md5) = reader.finalize();
+    let (counting_reader, sha256) = sha256_reader.finalize();
+    Ok(ExtractResult {
+        sha256,
+        md5,
+        size: counting_reader.bytes_read,
+    })
+}
+
+/// Keeps track of the progress of an extraction across the tar archives of a package.
+#[derive(Default)]
+struct ProgressState {
+    entries_extracted: usize,
+    bytes_extracted: u64,
+}
+
+/// Unpacks the entries of a tar archive one by one. This is similar to [`tar::Archive::unpack`]
+/// but checks for cancellation and reports progress after every entry.
+fn unpack_tar(
+    mut archive: tar::Archive<impl Read>,
+    destination: &Path,
+    options: &ExtractOptions,
+    state: &mut ProgressState,
+) -> Result<(), ExtractError> {
+    // Like `tar::Archive::unpack`, canonicalize the destination to make sure entries cannot be
+    // extracted outside of it through symlinks.
+    let destination = &destination
+        .canonicalize()
+        .unwrap_or_else(|_| destination.to_path_buf());
+
+    let mut report = |entry: &tar::Entry<'_, _>| -> Result<(), ExtractError> {
+        let size = entry.header().size()?;
+        state.entries_extracted += 1;
+        state.bytes_extracted += size;
+        if let Some(progress) = &options.progress {
+            progress(&ExtractProgress {
+                path: entry.path()?.into_owned(),
+                size,
+                entries_extracted: state.entries_extracted,
+                bytes_extracted: state.bytes_extracted,
+            });
+        }
+        Ok(())
+    };
+
+    // Directories are unpacked last, so that their permissions do not prevent files from being
+    // written into them.
+    let mut directories = Vec::new();
+    for entry in archive.entries()? {
+        options.check_cancelled()?;
+        let mut entry = entry?;
+        if entry.header().entry_type() == tar::EntryType::Directory {
+            directories.push(entry);
+        } else {
+            entry.unpack_in(destination)?;
+            report(&entry)?;
+        }
+    }
+    for mut directory in directories {
+        options.check_cancelled()?;
+        directory.unpack_in(destination)?;
+        report(&directory)?;
+    }
+
+    Ok(())
+}
+
 /// Extracts the contents a `.tar.bz2` package archive.
 pub fn extract_tar_bz2(
     reader: impl Read,
     destination: &Path,
+) -> Result<ExtractResult, ExtractError> {
+    extract_tar_bz2_with_options(reader, destination, &ExtractOptions::default())
+}
+
+/// Extracts the contents a `.tar.bz2` package archive. The `options` allow cancelling the
+/// extraction and reporting its progress.
+pub fn extract_tar_bz2_with_options(
+    reader: impl Read,
+    destination: &Path,
+    options: &ExtractOptions,
 ) -> Result<ExtractResult, ExtractError> {
     std::fs::create_dir_all(destination).map_err(ExtractError::CouldNotCreateDestination)?;
 
     // Wrap the reading in aditional readers that will compute the hashes of the file while its
     // being read.
-    let sha256_reader = rattler_digest::HashingReader::<_, rattler_digest::Sha256>::new(reader);
-    let mut md5_reader =
-        rattler_digest::HashingReader::<_, rattler_digest::Md5>::new(sha256_reader);
+    let mut md5_reader = archive_reader(reader);
 
     // Unpack the archive
-    stream_tar_bz2(&mut md5_reader).unpack(destination)?;
-
-    // Get the hashes
-    let (sha256_reader, md5) = md5_reader.finalize();
-    let (_, sha256) = sha256_reader.finalize();
+    unpack_tar(
+        stream_tar_bz2(&mut md5_reader),
+        destination,
+        options,
+        &mut ProgressState::default(),
+    )?;
 
-    Ok(ExtractResult { sha256, md5 })
+    finish_archive_reader(md5_reader)
 }
 
 /// Extracts the contents of a `.conda` package archive.
 pub fn extract_conda(reader: impl Read, destination: &Path) -> Result<ExtractResult, ExtractError> {
+    extract_conda_with_options(reader, destination, &ExtractOptions::default())
+}
+
+/// Extracts the contents of a `.conda` package archive. The `options` allow cancelling the
+/// extraction and reporting its progress.
+pub fn extract_conda_with_options(
+    reader: impl Read,
+    destination: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractResult, ExtractError> {
     // Construct the destination path if it doesnt exist yet
     std::fs::create_dir_all(destination).map_err(ExtractError::CouldNotCreateDestination)?;
 
     // Wrap the reading in aditional readers that will compute the hashes of the file while its
     // being read.
-    let sha256_reader = rattler_digest::HashingReader::<_, rattler_digest::Sha256>::new(reader);
-    let mut md5_reader =
-        rattler_digest::HashingReader::<_, rattler_digest::Md5>::new(sha256_reader);
+    let mut md5_reader = archive_reader(reader);
 
     // Iterate over all entries in the zip-file and extract them one-by-one
+    let mut state = ProgressState::default();
     while let Some(file) = read_zipfile_from_stream(&mut md5_reader)? {
+        options.check_cancelled()?;
         if file
             .mangled_name()
             .file_name()
             .map(OsStr::to_string_lossy)
             .map_or(false, |file_name| file_name.ends_with(".tar.zst"))
         {
-            stream_tar_zst(file)?.unpack(destination)?;
+            unpack_tar(stream_tar_zst(file)?, destination, options, &mut state)?;
         }
     }
 
-    // Read the file to the end to make sure the hash is properly computed.
-    let mut buf = [0; 1 << 14];
-    loop {
-        let bytes_read = md5_reader.read(&mut buf)?;
-        if bytes_read == 0 {
-            break;
-        }
-    }
-
-    // Get the hashes
-    let (sha256_reader, md5) = md5_reader.finalize();
-    let (_, sha256) = sha256_reader.finalize();
-
-    Ok(ExtractResult { sha256, md5 })
+    finish_archive_reader(md5_reader)
 }
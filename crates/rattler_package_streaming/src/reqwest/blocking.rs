@@ -3,7 +3,6 @@
 use crate::{ExtractError, ExtractResult};
 use rattler_conda_types::package::ArchiveType;
 use rattler_networking::AuthenticatedClientBlocking;
-use reqwest::blocking::Response;
 use reqwest::IntoUrl;
 use std::path::Path;
 
@@ -27,10 +26,9 @@ pub fn extract_tar_bz2(
 ) -> Result<ExtractResult, ExtractError> {
     // Send the request for the file
     let response = client
-        .get(url)
-        .send()
-        .and_then(Response::error_for_status)
+        .send(client.get(url))
         .map_err(ExtractError::ReqwestError)?;
+    super::check_status(response.url(), response.status(), response.headers())?;
 
     // The `response` is used to stream in the package data
     crate::read::extract_tar_bz2(response, destination)
@@ -56,10 +54,9 @@ pub fn extract_conda(
 ) -> Result<ExtractResult, ExtractError> {
     // Send the request for the file
     let response = client
-        .get(url)
-        .send()
-        .and_then(Response::error_for_status)
+        .send(client.get(url))
         .map_err(ExtractError::ReqwestError)?;
+    super::check_status(response.url(), response.status(), response.headers())?;
 
     // The `response` is used to stream in the package data
     crate::read::extract_conda(response, destination)
@@ -89,9 +86,7 @@ pub fn extract(
         .map_err(reqwest::Error::from)
         .map_err(ExtractError::ReqwestError)?;
 
-    match ArchiveType::try_from(Path::new(url.path()))
-        .ok_or(ExtractError::UnsupportedArchiveType)?
-    {
+    match crate::archive_type(Path::new(url.path()))? {
         ArchiveType::TarBz2 => extract_tar_bz2(client, url, destination),
         ArchiveType::Conda => extract_conda(client, url, destination),
     }
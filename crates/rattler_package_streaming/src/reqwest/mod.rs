@@ -7,3 +7,24 @@ pub mod tokio;
 pub mod blocking;
 #[cfg(feature = "blocking")]
 pub use blocking::{extract, extract_conda, extract_tar_bz2};
+
+/// Returns an [`crate::ExtractError::HttpStatusError`] if the response to the request for `url` has an
+/// error status. Credentials are removed from the url that is stored in the error.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub(crate) fn check_status(
+    url: &url::Url,
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+) -> Result<(), crate::ExtractError> {
+    use rattler_networking::{
+        retry_policies::HttpStatusError, trace::redact_known_secrets_from_url,
+    };
+
+    match HttpStatusError::from_parts(status, headers) {
+        Some(err) => Err(crate::ExtractError::HttpStatusError(
+            redact_known_secrets_from_url(url),
+            err,
+        )),
+        None => Ok(()),
+    }
+}
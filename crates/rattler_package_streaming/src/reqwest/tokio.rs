@@ -5,7 +5,6 @@ use crate::{ExtractError, ExtractResult};
 use futures_util::stream::TryStreamExt;
 use rattler_conda_types::package::ArchiveType;
 use rattler_networking::AuthenticatedClient;
-use reqwest::Response;
 use std::path::Path;
 use tokio::io::BufReader;
 use tokio_util::either::Either;
@@ -24,12 +23,18 @@ async fn get_reader(
         Ok(Either::Left(BufReader::new(file)))
     } else {
         // Send the request for the file
-        let response = client
-            .get(url.clone())
-            .send()
-            .await
-            .and_then(Response::error_for_status)
-            .map_err(ExtractError::ReqwestError)?;
+        let response = if url.scheme() == "oci" {
+            rattler_networking::oci::get_blob(&client, &url)
+                .await
+                .map_err(ExtractError::OciError)?
+        } else {
+            let response = client
+                .send(client.get(url.clone()))
+                .await
+                .map_err(ExtractError::ReqwestError)?;
+            super::check_status(&url, response.status(), response.headers())?;
+            response
+        };
 
         // Get the response as a stream
         Ok(Either::Right(StreamReader::new(
@@ -117,9 +122,7 @@ pub async fn extract(
     url: Url,
     destination: &Path,
 ) -> Result<ExtractResult, ExtractError> {
-    match ArchiveType::try_from(Path::new(url.path()))
-        .ok_or(ExtractError::UnsupportedArchiveType)?
-    {
+    match crate::archive_type(Path::new(url.path()))? {
         ArchiveType::TarBz2 => extract_tar_bz2(client, url, destination).await,
         ArchiveType::Conda => extract_conda(client, url, destination).await,
     }
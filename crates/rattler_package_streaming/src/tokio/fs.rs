@@ -1,7 +1,6 @@
 //! Functions to extracting or stream a Conda package from a file on disk.
 
-use crate::{ExtractError, ExtractResult};
-use rattler_conda_types::package::ArchiveType;
+use crate::{CancellationFlag, ExtractError, ExtractOptions, ExtractResult};
 use std::path::Path;
 
 /// Extracts the contents a `.tar.bz2` package archive at the specified path to a directory.
@@ -87,9 +86,58 @@ pub async fn extract_conda(
 ///     .unwrap();
 /// # }
 /// ```
+///
+/// If the returned future is dropped before it completes, the extraction is cancelled.
 pub async fn extract(archive: &Path, destination: &Path) -> Result<ExtractResult, ExtractError> {
-    match ArchiveType::try_from(archive).ok_or(ExtractError::UnsupportedArchiveType)? {
-        ArchiveType::TarBz2 => extract_tar_bz2(archive, destination).await,
-        ArchiveType::Conda => extract_conda(archive, destination).await,
+    extract_with_options(archive, destination, ExtractOptions::default()).await
+}
+
+/// Extracts the contents a package archive at the specified path to a directory. The type of
+/// package is determined based on the file extension of the archive path. The `options` allow
+/// cancelling the extraction and reporting its progress.
+///
+/// The extraction is performed on a blocking task. If the returned future is dropped before it
+/// completes, the extraction is cancelled before the next entry of the archive is extracted.
+pub async fn extract_with_options(
+    archive: &Path,
+    destination: &Path,
+    options: ExtractOptions,
+) -> Result<ExtractResult, ExtractError> {
+    // Cancel the extraction when this future is dropped.
+    let mut options = options;
+    let cancellation = options
+        .cancellation
+        .get_or_insert_with(CancellationFlag::new)
+        .clone();
+    let mut cancel_on_drop = CancelOnDrop(Some(cancellation));
+
+    // Spawn a block task to perform the extraction
+    let destination = destination.to_owned();
+    let archive = archive.to_owned();
+    let result = tokio::task::spawn_blocking(move || {
+        crate::fs::extract_with_options(&archive, &destination, &options)
+    })
+    .await;
+    cancel_on_drop.0 = None;
+
+    match result {
+        Ok(result) => result,
+        Err(err) => {
+            if let Ok(reason) = err.try_into_panic() {
+                std::panic::resume_unwind(reason);
+            }
+            Err(ExtractError::Cancelled)
+        }
+    }
+}
+
+/// Sets the flag when dropped, unless it has been taken.
+struct CancelOnDrop(Option<CancellationFlag>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancellation) = &self.0 {
+            cancellation.cancel();
+        }
     }
 }
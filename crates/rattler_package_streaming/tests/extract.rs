@@ -1,8 +1,15 @@
-use rattler_package_streaming::read::{extract_conda, extract_tar_bz2};
+use rattler_conda_types::package::NonCondaArchiveType;
+use rattler_package_streaming::{
+    fs::extract_with_options,
+    read::{extract_conda, extract_tar_bz2},
+    write::{write_conda_package, write_tar_bz2_package, CompressionLevel},
+    CancellationFlag, ExtractError, ExtractOptions, ExtractProgress,
+};
 use rstest::rstest;
 use rstest_reuse::{self, *};
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 fn test_data_dir() -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data")
@@ -109,6 +116,12 @@ fn test_extract_conda(#[case] input: &str, #[case] sha256: &str, #[case] md5: &s
 
     assert_eq!(&format!("{:x}", result.sha256), sha256);
     assert_eq!(&format!("{:x}", result.md5), md5);
+    assert_eq!(
+        result.size,
+        std::fs::metadata(test_data_dir().join(file_path))
+            .unwrap()
+            .len()
+    );
 }
 
 #[apply(conda_archives)]
@@ -147,6 +160,12 @@ fn test_extract_tar_bz2(#[case] input: &str, #[case] sha256: &str, #[case] md5:
 
     assert_eq!(&format!("{:x}", result.sha256), sha256);
     assert_eq!(&format!("{:x}", result.md5), md5);
+    assert_eq!(
+        result.size,
+        std::fs::metadata(test_data_dir().join(file_path))
+            .unwrap()
+            .len()
+    );
 }
 
 #[cfg(feature = "tokio")]
@@ -239,3 +258,118 @@ async fn test_extract_url_async(#[case] url: &str, #[case] sha256: &str, #[case]
     assert_eq!(&format!("{:x}", result.sha256), sha256);
     assert_eq!(&format!("{:x}", result.md5), md5);
 }
+
+#[test]
+fn test_extract_non_conda_archive() {
+    let temp_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("non-conda");
+    let result = rattler_package_streaming::fs::extract(
+        Path::new("requests-2.31.0-py3-none-any.whl"),
+        &temp_dir,
+    );
+    assert!(matches!(
+        result,
+        Err(ExtractError::NotACondaPackage(
+            NonCondaArchiveType::PythonWheel
+        ))
+    ));
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("install it with pip"));
+}
+
+/// Writes a small package with a few files to `dir` and returns the path of the archive.
+fn write_test_package(dir: &Path, file_name: &str) -> PathBuf {
+    let package_dir = dir.join("package");
+    let paths = [
+        "info/index.json",
+        "bin/foo",
+        "lib/libfoo.so",
+        "share/foo/data.txt",
+    ]
+    .map(|path| {
+        let path = package_dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, path.to_string_lossy().as_bytes()).unwrap();
+        path
+    });
+
+    let archive = dir.join(file_name);
+    let writer = File::create(&archive).unwrap();
+    if let Some(out_name) = file_name.strip_suffix(".conda") {
+        write_conda_package(
+            writer,
+            &package_dir,
+            &paths,
+            CompressionLevel::Lowest,
+            out_name,
+            None,
+        )
+        .unwrap();
+    } else {
+        write_tar_bz2_package(writer, &package_dir, &paths, CompressionLevel::Lowest, None)
+            .unwrap();
+    }
+    archive
+}
+
+#[rstest]
+#[case::conda("test-1.0-0.conda")]
+#[case::tar_bz2("test-1.0-0.tar.bz2")]
+fn test_extract_progress(#[case] input: &str) {
+    let package_dir = tempfile::tempdir().unwrap();
+    let archive = write_test_package(package_dir.path(), input);
+    let temp_dir = tempfile::tempdir().unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let options = ExtractOptions {
+        cancellation: None,
+        progress: Some(Arc::new({
+            let events = events.clone();
+            move |progress: &ExtractProgress| events.lock().unwrap().push(progress.clone())
+        })),
+    };
+    extract_with_options(&archive, temp_dir.path(), &options).unwrap();
+
+    // Every file in the destination has been reported.
+    let events = events.lock().unwrap();
+    let files = walkdir::WalkDir::new(temp_dir.path())
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|entry| entry.file_type().is_file())
+        .count();
+    assert!(files > 0);
+    assert!(events.len() >= files);
+    for (idx, event) in events.iter().enumerate() {
+        assert_eq!(event.entries_extracted, idx + 1);
+        assert!(temp_dir.path().join(&event.path).exists());
+    }
+    assert_eq!(
+        events.last().unwrap().bytes_extracted,
+        events.iter().map(|event| event.size).sum::<u64>()
+    );
+}
+
+#[rstest]
+#[case::conda("test-1.0-0.conda")]
+#[case::tar_bz2("test-1.0-0.tar.bz2")]
+fn test_extract_cancellation(#[case] input: &str) {
+    // Cancel the extraction after the first entry has been extracted.
+    let package_dir = tempfile::tempdir().unwrap();
+    let archive = write_test_package(package_dir.path(), input);
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cancellation = CancellationFlag::new();
+    let extracted = Arc::new(Mutex::new(0));
+    let options = ExtractOptions {
+        cancellation: Some(cancellation.clone()),
+        progress: Some(Arc::new({
+            let extracted = extracted.clone();
+            move |_: &ExtractProgress| {
+                *extracted.lock().unwrap() += 1;
+                cancellation.cancel();
+            }
+        })),
+    };
+    let result = extract_with_options(&archive, temp_dir.path(), &options);
+    assert!(matches!(result, Err(ExtractError::Cancelled)));
+    assert_eq!(*extracted.lock().unwrap(), 1);
+}
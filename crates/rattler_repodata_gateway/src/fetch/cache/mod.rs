@@ -1,6 +1,7 @@
 mod cache_headers;
 
 pub use cache_headers::CacheHeaders;
+use chrono::{DateTime, Utc};
 use rattler_digest::{serde::SerializableHash, Blake2b256};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::serde_as;
@@ -40,6 +41,11 @@ pub struct RepoDataState {
     )]
     pub blake2_hash: Option<blake2::digest::Output<Blake2b256>>,
 
+    /// Whether the repodata.json on disk was transcoded from msgpack. Its hash then differs from
+    /// the hash of the repodata.json on the server, so it cannot be updated with JLAP.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub transcoded: bool,
+
     /// Whether or not zst is available for the subdirectory
     pub has_zst: Option<Expiring<bool>>,
 
@@ -51,6 +57,15 @@ pub struct RepoDataState {
 
     /// State information related to JLAP
     pub jlap: Option<JLAPState>,
+
+    /// The moment the server was last asked for the repodata, either to download it or to check
+    /// that the cached repodata is still up to date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime<Utc>>,
+
+    /// The value of the `Date` header of the last response of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_date: Option<DateTime<Utc>>,
 }
 
 impl RepoDataState {
@@ -65,6 +80,23 @@ impl RepoDataState {
         Ok(Self::from_str(&content)?)
     }
 
+    /// Returns when the cached repodata was fetched and when it was last modified on the server.
+    pub fn freshness(&self) -> RepoDataFreshness {
+        RepoDataFreshness {
+            // Caches that were written before the fetch time was recorded were at least fetched
+            // when the repodata.json file was written.
+            fetched_at: self
+                .fetched_at
+                .unwrap_or_else(|| DateTime::from(self.cache_last_modified)),
+            server_date: self.server_date,
+            last_modified: self
+                .cache_headers
+                .last_modified
+                .as_deref()
+                .and_then(parse_http_date),
+        }
+    }
+
     /// Save the cache state to the specified file.
     pub fn to_path(&self, path: &Path) -> Result<(), std::io::Error> {
         let file = File::create(path)?;
@@ -80,6 +112,33 @@ impl FromStr for RepoDataState {
     }
 }
 
+/// Describes how recent cached repodata is. See [`RepoDataState::freshness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepoDataFreshness {
+    /// The moment the server was last asked for the repodata.
+    pub fetched_at: DateTime<Utc>,
+
+    /// The time of the server when it last sent a response for the repodata, if known.
+    pub server_date: Option<DateTime<Utc>>,
+
+    /// The moment the repodata was last modified on the server, if known.
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl RepoDataFreshness {
+    /// Returns how long ago the repodata was fetched from the server.
+    pub fn age(&self, now: DateTime<Utc>) -> std::time::Duration {
+        (now - self.fetched_at).to_std().unwrap_or_default()
+    }
+}
+
+/// Parses the value of an HTTP header that contains a date, e.g. `Mon, 13 Feb 2023 13:49:56 GMT`.
+pub(crate) fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
 /// Used inside of the `RepoDataState` to store information related to our JLAP state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JLAPState {
@@ -180,7 +239,8 @@ fn serialize_blake2_hash<S: Serializer>(
 #[cfg(test)]
 mod test {
     use super::RepoDataState;
-    use std::str::FromStr;
+    use chrono::{DateTime, Utc};
+    use std::{str::FromStr, time::Duration};
 
     const JSON_STATE_ONE: &str = r#"{
         "cache_control": "public, max-age=1200",
@@ -223,4 +283,46 @@ mod test {
     pub fn test_parse_repo_data_state_two() {
         insta::assert_yaml_snapshot!(RepoDataState::from_str(JSON_STATE_TWO).unwrap())
     }
+
+    #[test]
+    pub fn test_freshness() {
+        let state = RepoDataState::from_str(JSON_STATE_ONE).unwrap();
+        let freshness = state.freshness();
+
+        // Without a recorded fetch time the modification time of the cache is used.
+        assert_eq!(
+            freshness.fetched_at,
+            DateTime::<Utc>::from(state.cache_last_modified)
+        );
+        assert_eq!(freshness.server_date, None);
+        assert_eq!(
+            freshness.last_modified,
+            Some(
+                DateTime::parse_from_rfc3339("2023-02-13T13:49:56Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+        assert_eq!(
+            freshness.age(freshness.fetched_at + chrono::Duration::hours(2)),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            freshness.age(freshness.fetched_at - chrono::Duration::hours(2)),
+            Duration::ZERO
+        );
+
+        // The fetch time and server date survive a roundtrip through the state file.
+        let fetched_at = DateTime::parse_from_rfc3339("2023-02-14T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let state = RepoDataState {
+            fetched_at: Some(fetched_at),
+            server_date: Some(fetched_at),
+            ..state
+        };
+        let roundtrip = RepoDataState::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        assert_eq!(roundtrip.freshness().fetched_at, fetched_at);
+        assert_eq!(roundtrip.freshness().server_date, Some(fetched_at));
+    }
 }
@@ -0,0 +1,122 @@
+//! Negotiation of the format in which repodata is transferred.
+//!
+//! Some servers (like Quetz or prefix.dev) can serve repodata as msgpack instead of JSON, which is
+//! considerably smaller and faster to parse. The format is negotiated with the `Accept` header of
+//! the request, servers that do not support msgpack simply ignore the header and respond with
+//! JSON. The cache always stores JSON, so a msgpack response is transcoded to JSON before it is
+//! stored.
+
+use reqwest::{header::CONTENT_TYPE, Response};
+use std::io::{Read, Write};
+
+/// The value of the `Accept` header that is sent to negotiate the format of the repodata. msgpack
+/// is preferred over JSON.
+pub(crate) const ACCEPT_REPO_DATA: &str = "application/msgpack, application/json;q=0.9, */*;q=0.8";
+
+/// The value of the `Accept-Encoding` header that is sent when the format of the repodata is
+/// negotiated. Both encodings are decoded on the fly while the response is streamed to disk.
+pub(crate) const ACCEPT_ENCODING_REPO_DATA: &str = "gzip, zstd";
+
+/// The format in which the server sent the repodata.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum RepoDataFormat {
+    /// The repodata is encoded as JSON.
+    #[default]
+    Json,
+
+    /// The repodata is encoded as msgpack.
+    MsgPack,
+}
+
+impl<'a> From<&'a Response> for RepoDataFormat {
+    fn from(response: &'a Response) -> Self {
+        response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(RepoDataFormat::Json, RepoDataFormat::from_content_type)
+    }
+}
+
+impl RepoDataFormat {
+    /// Determines the format from the value of a `Content-Type` header. Anything that is not
+    /// recognized as msgpack is assumed to be JSON.
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                RepoDataFormat::MsgPack
+            }
+            _ => RepoDataFormat::Json,
+        }
+    }
+}
+
+/// Transcodes msgpack encoded repodata read from `reader` to JSON written to `writer`. The data is
+/// transcoded without deserializing it into an intermediate representation.
+pub(crate) fn transcode_msgpack_to_json(
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<(), std::io::Error> {
+    let mut deserializer = rmp_serde::Deserializer::new(reader);
+    let mut serializer = serde_json::Serializer::new(writer);
+    serde_transcode::transcode(&mut deserializer, &mut serializer)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{transcode_msgpack_to_json, RepoDataFormat};
+
+    #[test]
+    fn test_from_content_type() {
+        assert_eq!(
+            RepoDataFormat::from_content_type("application/msgpack"),
+            RepoDataFormat::MsgPack
+        );
+        assert_eq!(
+            RepoDataFormat::from_content_type("Application/X-MsgPack; charset=binary"),
+            RepoDataFormat::MsgPack
+        );
+        assert_eq!(
+            RepoDataFormat::from_content_type("application/json"),
+            RepoDataFormat::Json
+        );
+        assert_eq!(
+            RepoDataFormat::from_content_type("application/octet-stream"),
+            RepoDataFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_transcode_msgpack_to_json() {
+        let repo_data = serde_json::json!({
+            "info": { "subdir": "linux-64" },
+            "packages": {
+                "foo-1.0-0.tar.bz2": {
+                    "name": "foo",
+                    "version": "1.0",
+                    "build": "0",
+                    "build_number": 0,
+                    "depends": ["bar >=1"],
+                    "size": 1234
+                }
+            },
+            "packages.conda": {},
+            "repodata_version": 1
+        });
+        let msgpack = rmp_serde::to_vec_named(&repo_data).unwrap();
+
+        let mut json = Vec::new();
+        transcode_msgpack_to_json(msgpack.as_slice(), &mut json).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap(),
+            repo_data
+        );
+    }
+}
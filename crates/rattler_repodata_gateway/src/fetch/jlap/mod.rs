@@ -145,6 +145,12 @@ pub enum JLAPError {
     /// This should be seldom and might indicate an error on the server.
     ChecksumParse,
 
+    #[error("The hash of the patched repodata does not match the latest hash of the JLAP file")]
+    /// Error returned when the hash of the `repodata.json` after applying the patches does not
+    /// match the latest hash listed in the footer of the JLAP response. The patched file is not
+    /// written to disk in that case.
+    HashMismatch,
+
     #[error("The JLAP response was empty and we unable to parse it")]
     /// Error return if we cannot find anything inside the actual JLAP response.
     /// This indicates that we need to reset the values for JLAP in our cache.
@@ -274,7 +280,8 @@ impl<'a> JLAPResponse<'a> {
     }
 
     /// Applies patches to a `repo_data_json_path` file provided using the `hash` value to
-    /// find the correct ones to apply.
+    /// find the correct ones to apply. The patched file is only written if its hash matches the
+    /// latest hash in the footer of the response.
     pub async fn apply(
         &self,
         repo_data_json_path: &Path,
@@ -284,7 +291,13 @@ impl<'a> JLAPResponse<'a> {
         let current_idx = self.patches.iter().position(|patch| patch.from == hash);
 
         if let Some(idx) = current_idx {
-            apply_jlap_patches(self.patches.clone(), idx, repo_data_json_path).await
+            apply_jlap_patches(
+                self.patches.clone(),
+                idx,
+                repo_data_json_path,
+                self.footer.latest,
+            )
+            .await
         } else {
             Err(JLAPError::NoHashFound)
         }
@@ -363,9 +376,11 @@ fn get_bytes_offset(lines: &Vec<&str>) -> u64 {
 /// This method first makes a request to fetch JLAP data we need. It relies on the information we
 /// pass via the `repo_data_state` argument to retrieve the correct response.
 ///
-/// After this, it will apply JSON patches to the file located at `repo_data_json_path`.
-/// At the end, we compare the new `blake2b` hash with what was listed in the JLAP metadata to
-/// ensure the file is correct.
+/// After this, it will apply JSON patches to the file located at `repo_data_json_path`. Only the
+/// patches that follow the `blake2b` hash of the current file are applied, if that hash is not part
+/// of the response [`JLAPError::NoHashFound`] is returned and the file is left untouched. The
+/// patched file is hashed and only written to disk if the hash matches the latest hash listed in
+/// the JLAP footer, otherwise [`JLAPError::HashMismatch`] is returned.
 ///
 /// The return value is the updated [`JLAPState`]
 pub async fn patch_repo_data(
@@ -425,7 +440,7 @@ async fn fetch_jlap(
         HeaderValue::from_str(range).unwrap(),
     );
 
-    request_builder.headers(headers).send().await
+    client.send(request_builder.headers(headers)).await
 }
 
 /// Fetches the JLAP response but also retries in the case of a RANGE_NOT_SATISFIABLE error
@@ -503,11 +518,13 @@ where
 /// 1. Opening and parsing the current repodata file
 /// 2. Applying patches to this repodata file
 /// 3. Re-ordering the repo data
-/// 4. Saving this repodata file to disk
+/// 4. Verifying that the hash of the result matches `expected_hash`
+/// 5. Saving this repodata file to disk
 async fn apply_jlap_patches(
     patches: Arc<[Patch]>,
     start_index: usize,
     repo_data_path: &Path,
+    expected_hash: Output<Blake2b256>,
 ) -> Result<(), JLAPError> {
     // Open and read the current repodata into a JSON doc
     let repo_data_contents = match tokio::fs::read_to_string(repo_data_path).await {
@@ -549,6 +566,12 @@ async fn apply_jlap_patches(
 
         // We need to add an extra newline character to the end of our string so the hashes match
         updated_json.insert(updated_json.len(), '\n');
+
+        let hash = rattler_digest::compute_bytes_digest::<Blake2b256>(&updated_json);
+        if hash != expected_hash {
+            tracing::debug!("hash mismatch: {:x} != {:x}", hash, expected_hash);
+            return Err(JLAPError::HashMismatch);
+        }
         Ok(updated_json.into_bytes())
     })
     .await
@@ -600,7 +623,7 @@ fn blake2b_256_hash_with_key(data: &[u8], key: &[u8]) -> Output<Blake2bMac256> {
 
 #[cfg(test)]
 mod test {
-    use super::patch_repo_data;
+    use super::{patch_repo_data, JLAPError};
     use std::path::PathBuf;
 
     use crate::fetch::cache::RepoDataState;
@@ -619,7 +642,7 @@ mod test {
   "cache_control": "public, max-age=30",
   "mtime_ns": 1685509481332236078,
   "size": 38317593,
-  "blake2_hash": "580100cb35459305eaaa31feeebacb06aad6422257754226d832e504666fc1c6",
+  "blake2_hash": "8725c2293d9d852333fbf7aab202fefc9b753df580d8d3e59b5689d46874e03f",
   "has_zst": {
     "value": true,
     "last_checked": "2023-05-21T12:14:21.904003Z"
@@ -641,7 +664,7 @@ mod test {
   "cache_control": "public, max-age=30",
   "mtime_ns": 1685509481332236078,
   "size": 38317593,
-  "blake2_hash": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9",
+  "blake2_hash": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4",
   "has_zst": {
     "value": true,
     "last_checked": "2023-05-21T12:14:21.904003Z"
@@ -655,11 +678,11 @@ mod test {
     "last_checked": "2023-05-21T12:14:21.903512Z"
   },
   "jlap": {
-    "iv": "5ec4a4fc3afd07b398ed78ffbd30ce3ef7c1f935f0e0caffc61455352ceedeff",
+    "iv": "ca122f9811d55559ce82bc0358f1f91dad3396bd2f1cfee76cd8ff8cf45632d4",
     "pos": 738,
     "footer": {
       "url": "repodata.json",
-      "latest": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9"
+      "latest": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4"
     }
   }
 }"#;
@@ -671,7 +694,7 @@ mod test {
   "cache_control": "public, max-age=30",
   "mtime_ns": 1685509481332236078,
   "size": 38317593,
-  "blake2_hash": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9",
+  "blake2_hash": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4",
   "has_zst": {
     "value": true,
     "last_checked": "2023-05-21T12:14:21.904003Z"
@@ -685,11 +708,11 @@ mod test {
     "last_checked": "2023-05-21T12:14:21.903512Z"
   },
   "jlap": {
-    "iv": "7d6e2b5185cf5e14f852355dc79eeba1233550d974f274f1eaf7db21c7b2c4e8",
+    "iv": "252e0d94a1a6017fafd76a1a6da3d6251e35da9fb1b542952ce684d88d679756",
     "pos": 1341,
     "footer": {
       "url": "repodata.json",
-      "latest": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9"
+      "latest": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4"
     }
   }
 }"#;
@@ -701,7 +724,7 @@ mod test {
   "cache_control": "public, max-age=30",
   "mtime_ns": 1685509481332236078,
   "size": 38317593,
-  "blake2_hash": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9",
+  "blake2_hash": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4",
   "has_zst": {
     "value": true,
     "last_checked": "2023-05-21T12:14:21.904003Z"
@@ -715,11 +738,11 @@ mod test {
     "last_checked": "2023-05-21T12:14:21.903512Z"
   },
   "jlap": {
-    "iv": "7d6e2b5185cf5e14f852355dc79eeba1233550d974f274f1eaf7db21c7b2c4e8",
+    "iv": "252e0d94a1a6017fafd76a1a6da3d6251e35da9fb1b542952ce684d88d679756",
     "pos": 9999,
     "footer": {
       "url": "repodata.json",
-      "latest": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9"
+      "latest": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4"
     }
   }
 }"#;
@@ -872,21 +895,27 @@ mod test {
 "#;
 
     const FAKE_REPO_DATA_UPDATE_ONE_HASH: &str =
-        "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9";
+        "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4";
 
     const FAKE_REPO_DATA_UPDATE_TWO_HASH: &str =
-        "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9";
+        "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4";
 
     const FAKE_JLAP_DATA_INITIAL: &str = r#"0000000000000000000000000000000000000000000000000000000000000000
-{"to": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9", "from": "580100cb35459305eaaa31feeebacb06aad6422257754226d832e504666fc1c6", "patch": [{"op": "add", "path": "/packages.conda/zstd-1.5.5-hc035e20_0.conda", "value": {"build": "hc035e20_0","build_number": 0,"depends": ["libcxx >=14.0.6","lz4-c >=1.9.4,<1.10.0a0","xz >=5.2.10,<6.0a0","zlib >=1.2.13,<1.3.0a0"],"license": "BSD-3-Clause AND GPL-2.0-or-later","license_family": "BSD","md5": "5e0b7ddb1b7dc6b630e1f9a03499c19c","name": "zstd","sha256": "5b192501744907b841de036bb89f5a2776b4cac5795ccc25dcaebeac784db038","size": 622467,"subdir": "osx-64","timestamp": 1681304595869, "version": "1.5.5"}}]}
-{"url": "repodata.json", "latest": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9"}
-5cf5bb373f361fe30d41891399d148f9c9dd0cc5f381e64f8fa3e7febd7269f0"#;
+{"to": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4", "from": "8725c2293d9d852333fbf7aab202fefc9b753df580d8d3e59b5689d46874e03f", "patch": [{"op": "add", "path": "/packages.conda/zstd-1.5.5-hc035e20_0.conda", "value": {"build": "hc035e20_0","build_number": 0,"depends": ["libcxx >=14.0.6","lz4-c >=1.9.4,<1.10.0a0","xz >=5.2.10,<6.0a0","zlib >=1.2.13,<1.3.0a0"],"license": "BSD-3-Clause AND GPL-2.0-or-later","license_family": "BSD","md5": "5e0b7ddb1b7dc6b630e1f9a03499c19c","name": "zstd","sha256": "5b192501744907b841de036bb89f5a2776b4cac5795ccc25dcaebeac784db038","size": 622467,"subdir": "osx-64","timestamp": 1681304595869, "version": "1.5.5"}}]}
+{"url": "repodata.json", "latest": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4"}
+c0d9bbfd573a3e47b79317c727f27b2798b495fb0e086262a9f53ac3f1f9ccc1"#;
 
     const FAKE_JLAP_DATA_UPDATE_ONE: &str = r#"0000000000000000000000000000000000000000000000000000000000000000
-{"to": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9", "from": "580100cb35459305eaaa31feeebacb06aad6422257754226d832e504666fc1c6", "patch": [{"op": "add", "path": "/packages.conda/zstd-1.5.5-hc035e20_0.conda", "value": {"build": "hc035e20_0","build_number": 0,"depends": ["libcxx >=14.0.6","lz4-c >=1.9.4,<1.10.0a0","xz >=5.2.10,<6.0a0","zlib >=1.2.13,<1.3.0a0"],"license": "BSD-3-Clause AND GPL-2.0-or-later","license_family": "BSD","md5": "5e0b7ddb1b7dc6b630e1f9a03499c19c","name": "zstd","sha256": "5b192501744907b841de036bb89f5a2776b4cac5795ccc25dcaebeac784db038","size": 622467,"subdir": "osx-64","timestamp": 1681304595869, "version": "1.5.5"}}]}
-{"to": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9", "from": "9b76165ba998f77b2f50342006192bf28817dad474d78d760ab12cc0260e3ed9", "patch": [{"op": "add", "path": "/packages.conda/zstd-static-1.4.5-hb1e8313_0.conda", "value": {"build": "hb1e8313_0", "build_number": 0, "depends": ["libcxx >=10.0.0", "zstd 1.4.5 h41d2c2f_0"], "license": "BSD 3-Clause", "md5": "5447986040e0b73d6c681a4d8f615d6c", "name": "zstd-static", "sha256": "3759ab53ff8320d35c6db00d34059ba99058eeec1cbdd0da968c5e12f73f7658", "size": 13930, "subdir": "osx-64", "timestamp": 1595965109852, "version": "1.4.5"}}]}
-{"url": "repodata.json", "latest": "160b529c5f72b9755f951c1b282705d49d319a5f2f80b33fb1a670d02ddeacf9"}
-5a4c42192a69299198bd8cfc85146d725d0dcc24a4e50f6eab383bc37cab2d2d"#;
+{"to": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4", "from": "8725c2293d9d852333fbf7aab202fefc9b753df580d8d3e59b5689d46874e03f", "patch": [{"op": "add", "path": "/packages.conda/zstd-1.5.5-hc035e20_0.conda", "value": {"build": "hc035e20_0","build_number": 0,"depends": ["libcxx >=14.0.6","lz4-c >=1.9.4,<1.10.0a0","xz >=5.2.10,<6.0a0","zlib >=1.2.13,<1.3.0a0"],"license": "BSD-3-Clause AND GPL-2.0-or-later","license_family": "BSD","md5": "5e0b7ddb1b7dc6b630e1f9a03499c19c","name": "zstd","sha256": "5b192501744907b841de036bb89f5a2776b4cac5795ccc25dcaebeac784db038","size": 622467,"subdir": "osx-64","timestamp": 1681304595869, "version": "1.5.5"}}]}
+{"to": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4", "from": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4", "patch": [{"op": "add", "path": "/packages.conda/zstd-static-1.4.5-hb1e8313_0.conda", "value": {"build": "hb1e8313_0", "build_number": 0, "depends": ["libcxx >=10.0.0", "zstd 1.4.5 h41d2c2f_0"], "license": "BSD 3-Clause", "md5": "5447986040e0b73d6c681a4d8f615d6c", "name": "zstd-static", "sha256": "3759ab53ff8320d35c6db00d34059ba99058eeec1cbdd0da968c5e12f73f7658", "size": 13930, "subdir": "osx-64", "timestamp": 1595965109852, "version": "1.4.5"}}]}
+{"url": "repodata.json", "latest": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4"}
+174f505d561c0692a2e70ced4229a99dcb6535688154696747ba1a785e425f26"#;
+
+    /// A JLAP file whose footer lists a different hash than the result of applying its patch.
+    const FAKE_JLAP_DATA_WRONG_FOOTER: &str = r#"0000000000000000000000000000000000000000000000000000000000000000
+{"to": "15d43c07b9ca1da076bf107684229055bd1ef02617de640ceec1681149772ae4", "from": "8725c2293d9d852333fbf7aab202fefc9b753df580d8d3e59b5689d46874e03f", "patch": [{"op": "add", "path": "/packages.conda/zstd-1.5.5-hc035e20_0.conda", "value": {"build": "hc035e20_0","build_number": 0,"depends": ["libcxx >=14.0.6","lz4-c >=1.9.4,<1.10.0a0","xz >=5.2.10,<6.0a0","zlib >=1.2.13,<1.3.0a0"],"license": "BSD-3-Clause AND GPL-2.0-or-later","license_family": "BSD","md5": "5e0b7ddb1b7dc6b630e1f9a03499c19c","name": "zstd","sha256": "5b192501744907b841de036bb89f5a2776b4cac5795ccc25dcaebeac784db038","size": 622467,"subdir": "osx-64","timestamp": 1681304595869, "version": "1.5.5"}}]}
+{"url": "repodata.json", "latest": "5600cfa376925e7c192a85b893ca2494cf9bc6bb1c4191ce73be8aefd250e9e4"}
+195ecbf28508146a6da09068da01bb64738ef944b35b3d20e4c809be51f9541d"#;
 
     /// Provides all the necessary environment setup for a test
     struct TestEnvironment {
@@ -986,7 +1015,7 @@ mod test {
         FAKE_JLAP_DATA_INITIAL,
         FAKE_STATE_DATA_INITIAL,
         738,
-        "5ec4a4fc3afd07b398ed78ffbd30ce3ef7c1f935f0e0caffc61455352ceedeff",
+        "ca122f9811d55559ce82bc0358f1f91dad3396bd2f1cfee76cd8ff8cf45632d4",
         FAKE_REPO_DATA_UPDATE_ONE_HASH
     )]
     #[case::patch_repo_data_with_a_partial_jlap_response_and_a_previous_jlap_cache_state(
@@ -995,7 +1024,7 @@ mod test {
         FAKE_JLAP_DATA_UPDATE_ONE,
         FAKE_STATE_DATA_UPDATE_ONE,
         1341,
-        "7d6e2b5185cf5e14f852355dc79eeba1233550d974f274f1eaf7db21c7b2c4e8",
+        "252e0d94a1a6017fafd76a1a6da3d6251e35da9fb1b542952ce684d88d679756",
         FAKE_REPO_DATA_UPDATE_TWO_HASH
     )]
     #[case::patch_repo_data_with_no_new_patches_to_apply(
@@ -1004,7 +1033,7 @@ mod test {
         FAKE_JLAP_DATA_UPDATE_ONE,
         FAKE_STATE_DATA_UPDATE_TWO,
         1341,
-        "7d6e2b5185cf5e14f852355dc79eeba1233550d974f274f1eaf7db21c7b2c4e8",
+        "252e0d94a1a6017fafd76a1a6da3d6251e35da9fb1b542952ce684d88d679756",
         FAKE_REPO_DATA_UPDATE_TWO_HASH
     )]
     #[case::patch_repo_data_trigger_range_not_satisfiable_recovery_workflow(
@@ -1013,7 +1042,7 @@ mod test {
         FAKE_JLAP_DATA_UPDATE_ONE,
         FAKE_STATE_DATA_OUT_OF_BOUNDS_POSITION,
         1341,
-        "7d6e2b5185cf5e14f852355dc79eeba1233550d974f274f1eaf7db21c7b2c4e8",
+        "252e0d94a1a6017fafd76a1a6da3d6251e35da9fb1b542952ce684d88d679756",
         FAKE_REPO_DATA_UPDATE_TWO_HASH
     )]
     #[tokio::test]
@@ -1059,4 +1088,29 @@ mod test {
             parse_digest_from_hex::<Blake2b256>(expected_hash).unwrap()
         );
     }
+
+    #[tokio::test]
+    pub async fn test_patch_repo_data_hash_mismatch() {
+        let test_env = TestEnvironment::new(
+            FAKE_REPO_DATA_INITIAL,
+            FAKE_JLAP_DATA_WRONG_FOOTER,
+            FAKE_STATE_DATA_INITIAL,
+        )
+        .await;
+
+        let result = patch_repo_data(
+            &test_env.client,
+            test_env.server_url,
+            test_env.repo_data_state,
+            &test_env.cache_repo_data,
+        )
+        .await;
+        assert!(matches!(result, Err(JLAPError::HashMismatch)));
+
+        // The cached repodata is left untouched.
+        let repo_data = tokio::fs::read_to_string(test_env.cache_repo_data)
+            .await
+            .unwrap();
+        assert_eq!(repo_data, FAKE_REPO_DATA_INITIAL);
+    }
 }
@@ -1,20 +1,28 @@
 //! This module provides functionality to download and cache `repodata.json` from a remote location.
 
 use crate::utils::{AsyncEncoding, Encoding, LockedFile};
-use cache::{CacheHeaders, Expiring, RepoDataState};
+use cache::{parse_http_date, Expiring, RepoDataState};
 use cache_control::{Cachability, CacheControl};
 use futures::{future::ready, FutureExt, TryStreamExt};
 use humansize::{SizeFormatter, DECIMAL};
+use rattler_conda_types::{Clock, SystemClock};
 use rattler_digest::{compute_file_digest, Blake2b256, HashingWriter};
-use rattler_networking::AuthenticatedClient;
+use rattler_networking::{
+    progress::SpeedEstimator,
+    retry_policies::{
+        parse_retry_after, retry_delay, HttpRetryPolicy, HttpStatusError, RetryDecision,
+    },
+    AuthenticatedClient,
+};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Response, StatusCode,
 };
 use std::{
-    io::ErrorKind,
+    io::{BufReader, BufWriter, ErrorKind, Write},
     path::{Path, PathBuf},
-    time::SystemTime,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 use tempfile::NamedTempFile;
 use tokio_util::io::StreamReader;
@@ -22,7 +30,18 @@ use tracing::instrument;
 use url::Url;
 
 mod cache;
+mod format;
 pub mod jlap;
+mod multi_request;
+
+pub use cache::{CacheHeaders, RepoDataFreshness};
+pub use format::RepoDataFormat;
+pub use multi_request::{
+    MultiRequestRepoDataBuilder, DEFAULT_MAX_CONCURRENT_REQUESTS,
+    DEFAULT_MAX_CONCURRENT_REQUESTS_PER_HOST, DEFAULT_MAX_RATE_LIMIT_RETRIES,
+};
+
+pub use rattler_networking::progress::DownloadProgress;
 
 /// Type alias for function to report progress while downloading repodata
 pub type ProgressFunc = Box<dyn FnMut(DownloadProgress) + Send + Sync>;
@@ -51,9 +70,18 @@ pub enum FetchRepoDataError {
     #[error(transparent)]
     FailedToDownloadRepoData(std::io::Error),
 
+    #[error("failed to decode msgpack repodata")]
+    FailedToDecodeMsgPack(#[source] std::io::Error),
+
     #[error("repodata not found")]
     NotFound(#[from] RepoDataNotFoundError),
 
+    #[error("the server is rate limiting requests")]
+    TooManyRequests { retry_after: Option<Duration> },
+
+    #[error(transparent)]
+    OciError(rattler_networking::oci::OciError),
+
     #[error("failed to create temporary file for repodata.json")]
     FailedToCreateTemporaryFile(#[source] std::io::Error),
 
@@ -138,6 +166,38 @@ impl Variant {
     }
 }
 
+/// A compressed variant of the repodata file that channels can provide next to the uncompressed
+/// file. Compressed variants are much smaller and are decompressed while they are downloaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompressedVariant {
+    /// The repodata compressed with zstandard, e.g. `repodata.json.zst`.
+    Zst,
+
+    /// The repodata compressed with bzip2, e.g. `repodata.json.bz2`.
+    Bz2,
+}
+
+impl CompressedVariant {
+    /// The compressed variants in the order in which they are preferred by default.
+    pub const DEFAULT_PREFERENCE: [CompressedVariant; 2] =
+        [CompressedVariant::Zst, CompressedVariant::Bz2];
+
+    /// Returns the extension that is appended to the file name of the repodata file.
+    pub fn extension(self) -> &'static str {
+        match self {
+            CompressedVariant::Zst => "zst",
+            CompressedVariant::Bz2 => "bz2",
+        }
+    }
+
+    fn encoding(self) -> Encoding {
+        match self {
+            CompressedVariant::Zst => Encoding::Zst,
+            CompressedVariant::Bz2 => Encoding::Bz2,
+        }
+    }
+}
+
 /// Additional knobs that allow you to tweak the behavior of [`fetch_repo_data`].
 #[derive(Clone)]
 pub struct FetchRepoDataOptions {
@@ -150,6 +210,28 @@ pub struct FetchRepoDataOptions {
 
     /// When enabled repodata can be fetched incrementally using JLAP
     pub jlap_enabled: bool,
+
+    /// The compressed variants of the repodata that are downloaded if they are available, in
+    /// order of preference. The uncompressed file is downloaded if none of them is available. An
+    /// empty list always downloads the uncompressed file. Defaults to
+    /// [`CompressedVariant::DEFAULT_PREFERENCE`].
+    pub compression_preference: Vec<CompressedVariant>,
+
+    /// When enabled the server is asked to send the repodata as msgpack instead of JSON through
+    /// the `Accept` header of the request. Servers that do not support msgpack respond with JSON.
+    /// See [`RepoDataFormat`] for more information.
+    pub negotiate_format: bool,
+
+    /// Determines whether the request for the repodata is retried when it fails with a transient
+    /// error, e.g. a timeout or `503 Service Unavailable`. A `429 Too Many Requests` response is
+    /// not retried but returned as [`FetchRepoDataError::TooManyRequests`], so the caller can
+    /// back off from the host as a whole (like [`MultiRequestRepoDataBuilder`] does).
+    pub retry_policy: HttpRetryPolicy,
+
+    /// Determines the moment that is recorded as the time the repodata was fetched (see
+    /// [`RepoDataFreshness`]). Defaults to the [`SystemClock`]. The HTTP cache and retries are
+    /// always validated and scheduled against the time of the system.
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Default for FetchRepoDataOptions {
@@ -158,21 +240,14 @@ impl Default for FetchRepoDataOptions {
             cache_action: Default::default(),
             variant: Variant::default(),
             jlap_enabled: true,
+            compression_preference: CompressedVariant::DEFAULT_PREFERENCE.to_vec(),
+            negotiate_format: true,
+            retry_policy: HttpRetryPolicy::default(),
+            clock: Arc::new(SystemClock),
         }
     }
 }
 
-/// A struct that provides information about download progress.
-#[derive(Debug, Clone)]
-pub struct DownloadProgress {
-    /// The number of bytes already downloaded
-    pub bytes: u64,
-
-    /// The total number of bytes to download. Or `None` if this is not known. This can happen
-    /// if the server does not supply a `Content-Length` header.
-    pub total: Option<u64>,
-}
-
 /// The result of [`fetch_repo_data`].
 #[derive(Debug)]
 pub struct CachedRepoData {
@@ -211,6 +286,7 @@ async fn repodata_from_file(
     out_path: PathBuf,
     cache_state_path: PathBuf,
     lock_file: LockedFile,
+    clock: &dyn Clock,
 ) -> Result<CachedRepoData, FetchRepoDataError> {
     // copy file from subdir_url to out_path
     if let Err(e) = tokio::fs::copy(&subdir_url.to_file_path().unwrap(), &out_path).await {
@@ -237,10 +313,13 @@ async fn repodata_from_file(
         },
         cache_last_modified: SystemTime::now(),
         blake2_hash: None,
+        transcoded: false,
         has_zst: None,
         has_bz2: None,
         has_jlap: None,
         jlap: None,
+        fetched_at: Some(clock.now()),
+        server_date: None,
     };
 
     // write the cache state
@@ -260,6 +339,93 @@ async fn repodata_from_file(
     })
 }
 
+/// handle oci:// urls. Registries do not provide cache headers, so the cached state that is written
+/// is never considered up to date. It can still be used with [`CacheAction::ForceCacheOnly`].
+#[allow(clippy::too_many_arguments)]
+async fn repodata_from_oci(
+    subdir_url: Url,
+    client: AuthenticatedClient,
+    cache_path: &Path,
+    out_path: PathBuf,
+    cache_state_path: PathBuf,
+    lock_file: LockedFile,
+    had_cache: bool,
+    progress: Option<ProgressFunc>,
+    clock: &dyn Clock,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    let response = match rattler_networking::oci::get_blob(&client, &subdir_url).await {
+        Ok(response) => response,
+        Err(rattler_networking::oci::OciError::ReqwestError(err))
+            if err.status() == Some(StatusCode::NOT_FOUND) =>
+        {
+            return Err(FetchRepoDataError::NotFound(RepoDataNotFoundError::from(
+                err,
+            )));
+        }
+        Err(err) => return Err(FetchRepoDataError::OciError(err)),
+    };
+
+    // Stream the content to the cache
+    let (temp_file, blake2_hash) =
+        stream_and_decode_to_file(
+            response,
+            Encoding::Passthrough,
+            RepoDataFormat::Json,
+            cache_path,
+            progress,
+        )
+        .await?;
+    let repo_data_destination_path = out_path.clone();
+    let repo_data_json_metadata = tokio::task::spawn_blocking(move || {
+        let file = temp_file
+            .persist(repo_data_destination_path)
+            .map_err(FetchRepoDataError::FailedToPersistTemporaryFile)?;
+        file.metadata()
+            .map_err(FetchRepoDataError::FailedToGetMetadata)
+    })
+    .await??;
+
+    let new_cache_state = RepoDataState {
+        url: subdir_url,
+        cache_size: repo_data_json_metadata.len(),
+        cache_headers: CacheHeaders {
+            etag: None,
+            last_modified: None,
+            cache_control: None,
+        },
+        cache_last_modified: repo_data_json_metadata
+            .modified()
+            .map_err(FetchRepoDataError::FailedToGetMetadata)?,
+        blake2_hash: Some(blake2_hash),
+        transcoded: false,
+        has_zst: None,
+        has_bz2: None,
+        has_jlap: None,
+        jlap: None,
+        fetched_at: Some(clock.now()),
+        server_date: None,
+    };
+
+    let new_cache_state = tokio::task::spawn_blocking(move || {
+        new_cache_state
+            .to_path(&cache_state_path)
+            .map(|_| new_cache_state)
+            .map_err(FetchRepoDataError::FailedToWriteCacheState)
+    })
+    .await??;
+
+    Ok(CachedRepoData {
+        lock_file,
+        repo_data_json_path: out_path,
+        cache_state: new_cache_state,
+        cache_result: if had_cache {
+            CacheResult::CacheOutdated
+        } else {
+            CacheResult::CacheNotPresent
+        },
+    })
+}
+
 /// Fetch the repodata.json file for the given subdirectory. The result is cached on disk using the
 /// HTTP cache headers returned from the server.
 ///
@@ -271,13 +437,14 @@ async fn repodata_from_file(
 /// This method implements several different methods to download the repodata.json file from the
 /// remote:
 ///
-/// * If a `repodata.json.zst` file is available in the same directory that file is downloaded
-///   and decompressed.
-/// * If a `repodata.json.bz2` file is available in the same directory that file is downloaded
-///   and decompressed.
-/// * Otherwise the regular `repodata.json` file is downloaded.
+/// * If a compressed variant (e.g. `repodata.json.zst` or `repodata.json.bz2`) is available in
+///   the same directory that file is downloaded and decompressed. If multiple variants are
+///   available the first one in [`FetchRepoDataOptions::compression_preference`] is used.
+/// * Otherwise the regular `repodata.json` file is downloaded. If
+///   [`FetchRepoDataOptions::negotiate_format`] is enabled the server may send it as msgpack
+///   instead, in which case it is transcoded to JSON.
 ///
-/// The checks to see if a `.zst` and/or `.bz2` file exist are performed by doing a HEAD request to
+/// The checks to see if the compressed variants exist are performed by doing a HEAD request to
 /// the respective URLs. The result of these are cached.
 #[instrument(err, skip_all, fields(subdir_url, cache_path = %cache_path.display()))]
 pub async fn fetch_repo_data(
@@ -290,11 +457,11 @@ pub async fn fetch_repo_data(
     let subdir_url = normalize_subdir_url(subdir_url);
 
     // Compute the cache key from the url
-    let cache_key = crate::utils::url_to_cache_filename(
-        &subdir_url
-            .join(options.variant.file_name())
-            .expect("file name is valid"),
-    );
+    let repo_data_url = subdir_url
+        .join(options.variant.file_name())
+        .expect("file name is valid");
+    let cache_key = crate::utils::url_to_cache_filename(&repo_data_url);
+    let legacy_cache_key = crate::utils::legacy_url_to_cache_filename(&repo_data_url);
     let repo_data_json_path = cache_path.join(format!("{}.json", cache_key));
     let cache_state_path = cache_path.join(format!("{}.info.json", cache_key));
 
@@ -312,6 +479,7 @@ pub async fn fetch_repo_data(
             repo_data_json_path,
             cache_state_path,
             lock_file,
+            options.clock.as_ref(),
         )
         .await;
     } else {
@@ -324,6 +492,7 @@ pub async fn fetch_repo_data(
         let owned_cache_path = cache_path.clone();
         let owned_cache_key = cache_key.clone();
         let cache_state = tokio::task::spawn_blocking(move || {
+            migrate_legacy_cache_entry(&owned_cache_path, &legacy_cache_key, &owned_cache_key);
             validate_cached_state(&owned_cache_path, &owned_subdir_url, &owned_cache_key)
         })
         .await?;
@@ -376,24 +545,41 @@ pub async fn fetch_repo_data(
         None
     };
 
+    if subdir_url.scheme() == "oci" {
+        // Registries do not provide cache headers, so a cached repodata.json that is not usable as
+        // is must be downloaded again.
+        return repodata_from_oci(
+            subdir_url.join(options.variant.file_name()).unwrap(),
+            client,
+            &cache_path,
+            repo_data_json_path,
+            cache_state_path,
+            lock_file,
+            cache_state.is_some(),
+            progress,
+            options.clock.as_ref(),
+        )
+        .await;
+    }
+
     // Determine the availability of variants based on the cache or by querying the remote.
-    let variant_availability = check_variant_availability(
-        &client,
-        &subdir_url,
-        cache_state.as_ref(),
-        options.variant.file_name(),
-    )
-    .await;
+    let variant_availability =
+        check_variant_availability(&client, &subdir_url, cache_state.as_ref(), &options).await;
 
     // Now that the caches have been refreshed determine whether or not we can use one of the
     // variants. We don't check the expiration here since we just refreshed it.
-    let has_zst = variant_availability.has_zst();
-    let has_bz2 = variant_availability.has_bz2();
+    let compression = options
+        .compression_preference
+        .iter()
+        .copied()
+        .find(|&variant| variant_availability.has_variant(variant));
     let has_jlap = variant_availability.has_jlap();
 
     // We first attempt to make a JLAP request; if it fails for any reason, we continue on with
-    // a normal request.
-    let jlap_state = if has_jlap && cache_state.is_some() && options.jlap_enabled {
+    // a normal request. Repodata that was transcoded from msgpack does not match the hashes of the
+    // patches, so it is downloaded in full instead.
+    let can_patch = cache_state.as_ref().map_or(false, |state| !state.transcoded);
+    let jlap_state = if has_jlap && can_patch && options.jlap_enabled {
         let repo_data_state = cache_state.as_ref().unwrap();
         match jlap::patch_repo_data(
             &client,
@@ -411,6 +597,7 @@ pub async fn fetch_repo_data(
                     has_bz2: variant_availability.has_bz2,
                     has_jlap: variant_availability.has_jlap,
                     jlap: Some(state),
+                    fetched_at: Some(options.clock.now()),
                     .. cache_state.expect("we must have had a cache, otherwise we wouldn't know the previous state of the cache")
                 };
 
@@ -439,16 +626,15 @@ pub async fn fetch_repo_data(
     };
 
     // Determine which variant to download
-    let repo_data_url = if has_zst {
-        subdir_url
-            .join(&format!("{}.zst", options.variant.file_name()))
-            .unwrap()
-    } else if has_bz2 {
-        subdir_url
-            .join(&format!("{}.bz2", options.variant.file_name()))
-            .unwrap()
-    } else {
-        subdir_url.join(options.variant.file_name()).unwrap()
+    let repo_data_url = match compression {
+        Some(variant) => subdir_url
+            .join(&format!(
+                "{}.{}",
+                options.variant.file_name(),
+                variant.extension()
+            ))
+            .unwrap(),
+        None => subdir_url.join(options.variant.file_name()).unwrap(),
     };
 
     // Construct the HTTP request
@@ -465,22 +651,44 @@ pub async fn fetch_repo_data(
     //
     // To do this we manually set the request header to accept gzip encoding and we use the
     // [`AsyncEncoding`] trait to perform the decoding on the fly.
-    headers.insert(
-        reqwest::header::ACCEPT_ENCODING,
-        HeaderValue::from_static("gzip"),
-    );
+    //
+    // The format of the uncompressed `repodata.json` can be negotiated with the server. An already
+    // compressed variant is always requested as is.
+    let negotiate_format = options.negotiate_format && compression.is_none();
+    if negotiate_format {
+        headers.insert(
+            reqwest::header::ACCEPT,
+            HeaderValue::from_static(format::ACCEPT_REPO_DATA),
+        );
+        headers.insert(
+            reqwest::header::ACCEPT_ENCODING,
+            HeaderValue::from_static(format::ACCEPT_ENCODING_REPO_DATA),
+        );
+    } else {
+        headers.insert(
+            reqwest::header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip"),
+        );
+    }
 
     // Add previous cache headers if we have them
     if let Some(cache_headers) = cache_state.as_ref().map(|state| &state.cache_headers) {
         cache_headers.add_to_request(&mut headers)
     }
     // Send the request and wait for a reply
-    let response = match request_builder.headers(headers).send().await {
+    let request_builder = request_builder.headers(headers);
+    let response = match send_with_retries(&client, request_builder, &options).await {
         Ok(response) if response.status() == StatusCode::NOT_FOUND => {
             return Err(FetchRepoDataError::NotFound(
                 RepoDataNotFoundError::HttpError(response.error_for_status().unwrap_err()),
             ));
         }
+        Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+            // Like the retries, the host is backed off from against the time of the system.
+            return Err(FetchRepoDataError::TooManyRequests {
+                retry_after: parse_retry_after(response.headers(), chrono::Utc::now()),
+            });
+        }
         Ok(response) => response.error_for_status()?,
         Err(e) => {
             return Err(FetchRepoDataError::HttpError(e));
@@ -498,6 +706,8 @@ pub async fn fetch_repo_data(
             has_bz2: variant_availability.has_bz2,
             has_jlap: variant_availability.has_jlap,
             jlap: jlap_state,
+            fetched_at: Some(options.clock.now()),
+            server_date: server_date(&response),
             .. cache_state.expect("we must have had a cache, otherwise we wouldn't know the previous state of the cache")
         };
 
@@ -519,17 +729,20 @@ pub async fn fetch_repo_data(
 
     // Get cache headers from the response
     let cache_headers = CacheHeaders::from(&response);
+    let server_date = server_date(&response);
+
+    // Determine the format the server chose to send the repodata in
+    let format = if negotiate_format {
+        RepoDataFormat::from(&response)
+    } else {
+        RepoDataFormat::Json
+    };
 
     // Stream the content to a temporary file
     let (temp_file, blake2_hash) = stream_and_decode_to_file(
         response,
-        if has_zst {
-            Encoding::Zst
-        } else if has_bz2 {
-            Encoding::Bz2
-        } else {
-            Encoding::Passthrough
-        },
+        compression.map_or(Encoding::Passthrough, CompressedVariant::encoding),
+        format,
         &cache_path,
         progress,
     )
@@ -559,10 +772,13 @@ pub async fn fetch_repo_data(
             .map_err(FetchRepoDataError::FailedToGetMetadata)?,
         cache_size: repo_data_json_metadata.len(),
         blake2_hash: Some(blake2_hash),
+        transcoded: format == RepoDataFormat::MsgPack,
         has_zst: variant_availability.has_zst,
         has_bz2: variant_availability.has_bz2,
         has_jlap: variant_availability.has_jlap,
         jlap: jlap_state,
+        fetched_at: Some(options.clock.now()),
+        server_date,
     };
 
     let new_cache_state = tokio::task::spawn_blocking(move || {
@@ -585,23 +801,75 @@ pub async fn fetch_repo_data(
     })
 }
 
+/// Returns the value of the `Date` header of the response, if it is present and valid.
+fn server_date(response: &Response) -> Option<chrono::DateTime<chrono::Utc>> {
+    response
+        .headers()
+        .get(reqwest::header::DATE)?
+        .to_str()
+        .ok()
+        .and_then(parse_http_date)
+}
+
+/// Sends the request for the repodata and retries it according to the retry policy of the
+/// `options` if it fails with a transient error. Responses with `429 Too Many Requests` are never
+/// retried here, see [`FetchRepoDataOptions::retry_policy`].
+async fn send_with_retries(
+    client: &AuthenticatedClient,
+    request_builder: reqwest::RequestBuilder,
+    options: &FetchRepoDataOptions,
+) -> reqwest::Result<Response> {
+    let mut n_past_retries = 0;
+    loop {
+        let Some(attempt) = request_builder.try_clone() else {
+            return client.send(request_builder).await;
+        };
+        let result = client.send(attempt).await;
+        let decision = match &result {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                RetryDecision::DoNotRetry
+            }
+            Ok(response) => match HttpStatusError::from_response(response) {
+                Some(err) => options
+                    .retry_policy
+                    .should_retry_status(n_past_retries, &err),
+                None => RetryDecision::DoNotRetry,
+            },
+            Err(err) => options.retry_policy.should_retry_error(n_past_retries, err),
+        };
+        let RetryDecision::Retry { execute_after } = decision else {
+            return result;
+        };
+
+        let delay = retry_delay(execute_after);
+        tracing::warn!(
+            "failed to fetch repodata, retry #{} in {}",
+            n_past_retries + 1,
+            humantime::format_duration(delay)
+        );
+        tokio::time::sleep(delay).await;
+        n_past_retries += 1;
+    }
+}
+
 /// Streams and decodes the response to a new temporary file in the given directory. While writing
-/// to disk it also computes the BLAKE2 hash of the file.
+/// to disk it also computes the BLAKE2 hash of the file. Repodata in the msgpack format is
+/// transcoded to JSON.
 #[instrument(skip_all)]
 async fn stream_and_decode_to_file(
     response: Response,
     content_encoding: Encoding,
+    format: RepoDataFormat,
     temp_dir: &Path,
     mut progress_func: Option<ProgressFunc>,
 ) -> Result<(NamedTempFile, blake2::digest::Output<Blake2b256>), FetchRepoDataError> {
     // Determine the length of the response in bytes and notify the listener that a download is
     // starting. The response may be compressed. Decompression happens below.
     let content_size = response.content_length();
+    let mut speed_estimator = SpeedEstimator::default();
+    speed_estimator.record(0);
     if let Some(progress_func) = progress_func.as_mut() {
-        progress_func(DownloadProgress {
-            bytes: 0,
-            total: content_size,
-        });
+        progress_func(speed_estimator.progress(content_size));
     }
 
     // Determine the encoding of the response
@@ -619,11 +887,9 @@ async fn stream_and_decode_to_file(
     let total_bytes_mut = &mut total_bytes;
     let bytes_stream = bytes_stream.inspect_ok(move |bytes| {
         *total_bytes_mut += bytes.len() as u64;
+        speed_estimator.record(*total_bytes_mut);
         if let Some(progress_func) = progress_func.as_mut() {
-            progress_func(DownloadProgress {
-                bytes: *total_bytes_mut,
-                total: content_size,
-            });
+            progress_func(speed_estimator.progress(content_size));
         }
     });
 
@@ -637,9 +903,10 @@ async fn stream_and_decode_to_file(
         tokio::io::BufReader::new(decoded_byte_stream).decode(content_encoding);
 
     tracing::trace!(
-        "decoding repodata (content: {:?}, transfer: {:?})",
+        "decoding repodata (content: {:?}, transfer: {:?}, format: {:?})",
         content_encoding,
-        transfer_encoding
+        transfer_encoding,
+        format
     );
 
     // Construct a temporary file
@@ -659,6 +926,16 @@ async fn stream_and_decode_to_file(
     // Finalize the hash
     let (_, hash) = hashing_file_writer.finalize();
 
+    // The cache always stores JSON, transcode msgpack before it is persisted.
+    let (temp_file, hash) = match format {
+        RepoDataFormat::Json => (temp_file, hash),
+        RepoDataFormat::MsgPack => {
+            let temp_dir = temp_dir.to_path_buf();
+            tokio::task::spawn_blocking(move || transcode_msgpack_file(temp_file, &temp_dir))
+                .await??
+        }
+    };
+
     tracing::debug!(
         "downloaded {}, decoded that into {}, BLAKE2 hash: {:x}",
         SizeFormatter::new(total_bytes, DECIMAL),
@@ -669,6 +946,35 @@ async fn stream_and_decode_to_file(
     Ok((temp_file, hash))
 }
 
+/// Transcodes a temporary file that contains msgpack encoded repodata to a new temporary file in the
+/// given directory that contains the same repodata encoded as JSON. Returns the new file and its
+/// BLAKE2 hash.
+fn transcode_msgpack_file(
+    msgpack_file: NamedTempFile,
+    temp_dir: &Path,
+) -> Result<(NamedTempFile, blake2::digest::Output<Blake2b256>), FetchRepoDataError> {
+    let reader = BufReader::new(
+        msgpack_file
+            .reopen()
+            .map_err(FetchRepoDataError::FailedToDownloadRepoData)?,
+    );
+
+    let json_file =
+        NamedTempFile::new_in(temp_dir).map_err(FetchRepoDataError::FailedToCreateTemporaryFile)?;
+    let file = json_file
+        .as_file()
+        .try_clone()
+        .map_err(FetchRepoDataError::FailedToCreateTemporaryFile)?;
+    let mut hashing_file_writer = HashingWriter::<_, Blake2b256>::new(BufWriter::new(file));
+
+    format::transcode_msgpack_to_json(reader, &mut hashing_file_writer)
+        .and_then(|_| hashing_file_writer.flush())
+        .map_err(FetchRepoDataError::FailedToDecodeMsgPack)?;
+
+    let (_, hash) = hashing_file_writer.finalize();
+    Ok((json_file, hash))
+}
+
 /// Describes the availability of certain `repodata.json`.
 #[derive(Debug)]
 pub struct VariantAvailability {
@@ -694,6 +1000,15 @@ impl VariantAvailability {
             .unwrap_or(false)
     }
 
+    /// Returns true if the given compressed variant is available, regardless of when it was
+    /// checked
+    pub fn has_variant(&self, variant: CompressedVariant) -> bool {
+        match variant {
+            CompressedVariant::Zst => self.has_zst(),
+            CompressedVariant::Bz2 => self.has_bz2(),
+        }
+    }
+
     /// Returns true if there is a JLAP variant available, regardless of when it was checked
     pub fn has_jlap(&self) -> bool {
         self.has_jlap
@@ -705,11 +1020,15 @@ impl VariantAvailability {
 
 /// Determine the availability of `repodata.json` variants (like a `.zst` or `.bz2`) by checking
 /// a cache or the internet.
+///
+/// Only the compressed variants in [`FetchRepoDataOptions::compression_preference`] are checked,
+/// and a variant is not checked if a variant that is preferred over it is already known to be
+/// available.
 pub async fn check_variant_availability(
     client: &AuthenticatedClient,
     subdir_url: &Url,
     cache_state: Option<&RepoDataState>,
-    filename: &str,
+    options: &FetchRepoDataOptions,
 ) -> VariantAvailability {
     // Determine from the cache which variant are available. This is currently cached for a maximum
     // of 14 days.
@@ -727,47 +1046,46 @@ pub async fn check_variant_availability(
         .and_then(|value| value.value(expiration_duration))
         .copied();
 
-    // Create a future to possibly refresh the zst state.
-    let zst_repodata_url = subdir_url.join(&format!("{filename}.zst")).unwrap();
-    let bz2_repodata_url = subdir_url.join(&format!("{filename}.bz2")).unwrap();
+    let filename = options.variant.file_name();
     let jlap_repodata_url = subdir_url.join(jlap::JLAP_FILE_NAME).unwrap();
 
-    let zst_future = match has_zst {
-        Some(_) => {
-            // The last cached value was valid, so we simply copy that
-            ready(cache_state.and_then(|state| state.has_zst.clone())).left_future()
-        }
-        None => async {
+    // A compressed variant only has to be checked if it might be downloaded. That is the case if
+    // it is enabled and no variant that is preferred over it is known to be available.
+    let cached_availability = |variant| match variant {
+        CompressedVariant::Zst => has_zst,
+        CompressedVariant::Bz2 => has_bz2,
+    };
+    let needs_check = |variant| {
+        let mut preferred = options
+            .compression_preference
+            .iter()
+            .take_while(|&&preferred| preferred != variant);
+        options.compression_preference.contains(&variant)
+            && !preferred.any(|&preferred| cached_availability(preferred) == Some(true))
+    };
+    let check_variant = |variant: CompressedVariant| {
+        let url = subdir_url
+            .join(&format!("{filename}.{}", variant.extension()))
+            .unwrap();
+        let cached = cache_state.and_then(|state| match variant {
+            CompressedVariant::Zst => state.has_zst.clone(),
+            CompressedVariant::Bz2 => state.has_bz2.clone(),
+        });
+        let needs_check = cached_availability(variant).is_none() && needs_check(variant);
+        async move {
+            if !needs_check {
+                // Either the last cached value is still valid or the variant is not going to be
+                // used, so we simply copy the value from the last time we checked.
+                return cached;
+            }
             Some(Expiring {
-                value: check_valid_download_target(&zst_repodata_url, client).await,
+                value: check_valid_download_target(&url, client).await,
                 last_checked: chrono::Utc::now(),
             })
         }
-        .right_future(),
-    };
-
-    // Create a future to determine if bz2 is available. We only check this if we dont already know that
-    // zst is available because if that's available we're going to use that anyway.
-    let bz2_future = if has_zst != Some(true) {
-        // If the zst variant might not be available we need to check whether bz2 is available.
-        async {
-            match has_bz2 {
-                Some(_) => {
-                    // The last cached value was value so we simply copy that.
-                    cache_state.and_then(|state| state.has_bz2.clone())
-                }
-                None => Some(Expiring {
-                    value: check_valid_download_target(&bz2_repodata_url, client).await,
-                    last_checked: chrono::Utc::now(),
-                }),
-            }
-        }
-        .left_future()
-    } else {
-        // If we already know that zst is available we simply copy the availability value from the last
-        // time we checked.
-        ready(cache_state.and_then(|state| state.has_zst.clone())).right_future()
     };
+    let zst_future = check_variant(CompressedVariant::Zst);
+    let bz2_future = check_variant(CompressedVariant::Bz2);
 
     let jlap_future = match has_jlap {
         Some(_) => {
@@ -809,7 +1127,7 @@ async fn check_valid_download_target(url: &Url, client: &AuthenticatedClient) ->
         exists
     } else {
         // Otherwise, perform a HEAD request to determine whether the url seems valid.
-        match client.head(url.clone()).send().await {
+        match client.send(client.head(url.clone())).await {
             Ok(response) => {
                 if response.status().is_success() {
                     tracing::debug!("'{url}' seems to be available");
@@ -856,6 +1174,61 @@ enum ValidatedCacheState {
     UpToDate(RepoDataState),
 }
 
+/// Moves the cache entry of an older version of rattler, which named the files of an entry after
+/// [`crate::utils::legacy_url_to_cache_filename`], to `cache_key` if no entry with that key exists
+/// yet. This keeps the repodata that was downloaded before usable instead of downloading it again
+/// and leaving the old files behind. The moved entry is validated like any other entry, so an
+/// entry of a different url whose legacy key collided is simply replaced.
+fn migrate_legacy_cache_entry(cache_path: &Path, legacy_cache_key: &str, cache_key: &str) {
+    let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
+    let legacy_repo_data_json_path = cache_path.join(format!("{legacy_cache_key}.json"));
+    if repo_data_json_path.exists() || !legacy_repo_data_json_path.is_file() {
+        return;
+    }
+
+    // Older versions lock the legacy key while they read or write the entry.
+    let legacy_lock_file_path = cache_path.join(format!("{legacy_cache_key}.lock"));
+    let legacy_lock = match LockedFile::open_rw(&legacy_lock_file_path, "legacy repodata cache") {
+        Ok(lock) => lock,
+        Err(e) => {
+            tracing::warn!("failed to lock legacy repodata cache entry '{legacy_cache_key}': {e}");
+            return;
+        }
+    };
+
+    tracing::debug!("moving legacy repodata cache entry '{legacy_cache_key}' to '{cache_key}'");
+    let moves = [
+        (
+            cache_path.join(format!("{legacy_cache_key}.info.json")),
+            cache_path.join(format!("{cache_key}.info.json")),
+        ),
+        (legacy_repo_data_json_path, repo_data_json_path),
+    ];
+    for (from, to) in moves {
+        match std::fs::rename(&from, &to) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                tracing::warn!(
+                    "failed to move '{}' to '{}': {e}",
+                    from.display(),
+                    to.display()
+                );
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    // The legacy key is not used anymore, so its lock file would be left behind. The lock is
+    // released first because an open file cannot be removed on every platform.
+    drop(legacy_lock);
+    if let Err(e) = std::fs::remove_file(&legacy_lock_file_path) {
+        tracing::debug!(
+            "failed to remove '{}': {e}",
+            legacy_lock_file_path.display()
+        );
+    }
+}
+
 /// Tries to determine if the cache state for the repodata.json for the given `subdir_url` is
 /// considered to be up-to-date.
 ///
@@ -958,6 +1331,8 @@ fn validate_cached_state(
     }
 
     // Determine the age of the cache
+    // Like the HTTP cache headers it is compared against, the age of the cache is determined with
+    // the time of the system and not with the clock of the options.
     let cache_age = match SystemTime::now().duration_since(cache_last_modified) {
         Ok(duration) => duration,
         Err(e) => {
@@ -1010,16 +1385,22 @@ fn validate_cached_state(
 #[cfg(test)]
 mod test {
     use super::{
-        fetch_repo_data, CacheResult, CachedRepoData, DownloadProgress, FetchRepoDataOptions,
+        fetch_repo_data, CacheAction, CacheResult, CachedRepoData, CompressedVariant,
+        DownloadProgress, FetchRepoDataOptions,
     };
     use crate::fetch::{FetchRepoDataError, RepoDataNotFoundError};
     use crate::utils::simple_channel_server::SimpleChannelServer;
     use crate::utils::Encoding;
     use assert_matches::assert_matches;
+    use chrono::TimeZone;
     use hex_literal::hex;
-    use rattler_networking::{AuthenticatedClient, AuthenticationStorage};
+    use rattler_conda_types::FixedClock;
+    use rattler_networking::{
+        retry_policies::{DoNotRetryPolicy, HttpRetryPolicy},
+        AuthenticatedClient, AuthenticationStorage,
+    };
     use reqwest::Client;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::Arc;
     use tempfile::TempDir;
@@ -1131,6 +1512,69 @@ mod test {
         );
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_fetched_at_uses_clock() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path());
+
+        let now = chrono::Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server.url(),
+            AuthenticatedClient::default(),
+            cache_dir.into_path(),
+            FetchRepoDataOptions {
+                clock: Arc::new(FixedClock(now)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.cache_state.fetched_at, Some(now));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_cache_is_valid_with_past_clock() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path());
+
+        // Fill the cache.
+        let cache_dir = TempDir::new().unwrap();
+        fetch_repo_data(
+            server.url(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // A clock in the past, e.g. to reproduce a solve, does not invalidate the cache.
+        let now = chrono::Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let CachedRepoData { cache_result, .. } = fetch_repo_data(
+            server.url(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                cache_action: CacheAction::ForceCacheOnly,
+                clock: Arc::new(FixedClock(now)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_matches!(cache_result, CacheResult::CacheHit);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_cache_works() {
@@ -1191,6 +1635,64 @@ mod test {
         assert_matches!(cache_result, CacheResult::CacheOutdated);
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_legacy_cache_entry_is_migrated() {
+        // Create a directory with some repodata.
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path());
+
+        // Fill the cache and move the entry to the name an older version would have used.
+        let cache_dir = TempDir::new().unwrap();
+        let CachedRepoData {
+            repo_data_json_path,
+            ..
+        } = fetch_repo_data(
+            server.url(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let cache_state_path = repo_data_json_path.with_extension("info.json");
+        let legacy_cache_key = crate::utils::legacy_url_to_cache_filename(
+            &server.url().join("repodata.json").unwrap(),
+        );
+        let legacy_repo_data_json_path = cache_dir.path().join(format!("{legacy_cache_key}.json"));
+        let legacy_cache_state_path = cache_dir
+            .path()
+            .join(format!("{legacy_cache_key}.info.json"));
+        std::fs::rename(&repo_data_json_path, &legacy_repo_data_json_path).unwrap();
+        std::fs::rename(&cache_state_path, &legacy_cache_state_path).unwrap();
+
+        // The legacy entry is moved to the new name and used.
+        let CachedRepoData { cache_result, .. } = fetch_repo_data(
+            server.url(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_matches!(
+            cache_result,
+            CacheResult::CacheHit | CacheResult::CacheHitAfterFetch
+        );
+        assert!(repo_data_json_path.is_file());
+        assert!(cache_state_path.is_file());
+        assert!(!legacy_repo_data_json_path.exists());
+        assert!(!legacy_cache_state_path.exists());
+        assert!(!cache_dir
+            .path()
+            .join(format!("{legacy_cache_key}.lock"))
+            .exists());
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_zst_works() {
@@ -1325,6 +1827,81 @@ mod test {
         );
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_compression_preference() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.bz2"),
+            Encoding::Bz2,
+        )
+        .await
+        .unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+
+        let server = SimpleChannelServer::new(subdir_path.path());
+        let fetch = |cache_path: PathBuf, compression_preference: Vec<CompressedVariant>| {
+            fetch_repo_data(
+                server.url(),
+                AuthenticatedClient::default(),
+                cache_path,
+                FetchRepoDataOptions {
+                    compression_preference,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+
+        // The first available variant in the order of preference is downloaded.
+        let result = fetch(
+            TempDir::new().unwrap().into_path(),
+            vec![CompressedVariant::Bz2, CompressedVariant::Zst],
+        )
+        .await
+        .unwrap();
+        assert!(result.cache_state.url.path().ends_with("repodata.json.bz2"));
+
+        // Without any compression the plain JSON is downloaded.
+        let result = fetch(TempDir::new().unwrap().into_path(), Vec::new())
+            .await
+            .unwrap();
+        assert!(result.cache_state.url.path().ends_with("repodata.json"));
+        assert_eq!(
+            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+
+        // Fill a cache that knows that the zst variant is available but does not know about the
+        // bz2 variant, because it was never needed.
+        let cache_path = TempDir::new().unwrap().into_path();
+        let result = fetch(cache_path.clone(), vec![CompressedVariant::Zst])
+            .await
+            .unwrap();
+        assert!(result.cache_state.url.path().ends_with("repodata.json.zst"));
+        assert!(result.cache_state.has_bz2.is_none());
+        drop(result);
+
+        // Without zst the availability of the bz2 variant is checked even though the cached
+        // availability of the zst variant is still fresh.
+        let result = fetch(cache_path, vec![CompressedVariant::Bz2])
+            .await
+            .unwrap();
+        assert!(result.cache_state.url.path().ends_with("repodata.json.bz2"));
+        assert_eq!(
+            result.cache_state.has_bz2.as_ref().map(|state| state.value),
+            Some(true)
+        );
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_gzip_transfer_encoding() {
@@ -1399,6 +1976,127 @@ mod test {
         assert_eq!(last_download_progress.load(Ordering::SeqCst), 1110);
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_msgpack_is_negotiated() {
+        use axum::http::header::{ACCEPT, CONTENT_TYPE};
+        use axum::http::HeaderMap;
+        use axum::response::IntoResponse;
+
+        let repo_data: serde_json::Value = serde_json::from_str(FAKE_REPO_DATA).unwrap();
+        let msgpack = rmp_serde::to_vec_named(&repo_data).unwrap();
+
+        // A server that only sends msgpack if the client asks for it.
+        let handler = move |headers: HeaderMap| {
+            let msgpack = msgpack.clone();
+            async move {
+                let accepts_msgpack = headers
+                    .get(ACCEPT)
+                    .and_then(|value| value.to_str().ok())
+                    .map_or(false, |value| value.contains("application/msgpack"));
+                if accepts_msgpack {
+                    ([(CONTENT_TYPE, "application/msgpack")], msgpack).into_response()
+                } else {
+                    ([(CONTENT_TYPE, "application/json")], FAKE_REPO_DATA).into_response()
+                }
+            }
+        };
+        let app = axum::Router::new().route("/repodata.json", axum::routing::get(handler));
+        let addr = std::net::SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let server = axum::Server::bind(&addr).serve(app.into_make_service());
+        let server_url =
+            Url::parse(&format!("http://localhost:{}", server.local_addr().port())).unwrap();
+        tokio::spawn(server);
+
+        for negotiate_format in [true, false] {
+            let cache_dir = TempDir::new().unwrap();
+            let result = fetch_repo_data(
+                server_url.clone(),
+                AuthenticatedClient::default(),
+                cache_dir.into_path(),
+                FetchRepoDataOptions {
+                    negotiate_format,
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+            // Either way the cache contains the same JSON.
+            let cached = std::fs::read_to_string(&result.repo_data_json_path).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(&cached).unwrap(),
+                repo_data
+            );
+            assert_eq!(negotiate_format, cached != FAKE_REPO_DATA);
+            assert_eq!(negotiate_format, result.cache_state.transcoded);
+        }
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_server_errors_are_retried() {
+        use axum::http::StatusCode;
+        use axum::response::IntoResponse;
+
+        // A server that fails the first request for the repodata.
+        let requests = Arc::new(AtomicU64::new(0));
+        let handler = {
+            let requests = requests.clone();
+            move || async move {
+                if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    StatusCode::SERVICE_UNAVAILABLE.into_response()
+                } else {
+                    FAKE_REPO_DATA.into_response()
+                }
+            }
+        };
+        let app = axum::Router::new().route("/repodata.json", axum::routing::get(handler));
+        let addr = std::net::SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let server = axum::Server::bind(&addr).serve(app.into_make_service());
+        let server_url =
+            Url::parse(&format!("http://localhost:{}", server.local_addr().port())).unwrap();
+        tokio::spawn(server);
+
+        // The failed request is retried.
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server_url.clone(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Without retries the error is returned.
+        requests.store(0, Ordering::SeqCst);
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server_url,
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                retry_policy: HttpRetryPolicy::new(DoNotRetryPolicy),
+                ..Default::default()
+            },
+            None,
+        )
+        .await;
+        assert_matches!(
+            result,
+            Err(FetchRepoDataError::HttpError(err))
+                if err.status() == Some(reqwest::StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_repodata_not_found() {
@@ -1452,4 +2150,137 @@ mod test {
             ))
         ));
     }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_fetch_repo_data_from_oci() {
+        use axum::{
+            extract::State,
+            http::{header, HeaderMap, StatusCode},
+            response::IntoResponse,
+            routing::get,
+            Router,
+        };
+
+        // A minimal registry that requires a bearer token for all requests.
+        let authorized = |headers: &HeaderMap| {
+            headers.get(header::AUTHORIZATION).map(|v| v.as_bytes()) == Some(b"Bearer secret")
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let router = Router::new()
+            .route(
+                "/token",
+                get(|| async { r#"{"token": "secret"}"# }),
+            )
+            .route(
+                "/v2/channel/noarch/repodata.json/manifests/latest",
+                get(move |State(port): State<u16>, headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        let challenge = format!(
+                            r#"Bearer realm="http://localhost:{port}/token",service="registry""#
+                        );
+                        return (
+                            StatusCode::UNAUTHORIZED,
+                            [(header::WWW_AUTHENTICATE, challenge)],
+                        )
+                            .into_response();
+                    }
+                    r#"{"layers": [{"mediaType": "application/vnd.conda.repodata.v1+json", "digest": "sha256:1234"}]}"#
+                        .into_response()
+                }),
+            )
+            .route(
+                "/v2/channel/noarch/repodata.json/blobs/sha256:1234",
+                get(move |headers: HeaderMap| async move {
+                    if authorized(&headers) {
+                        FAKE_REPO_DATA.into_response()
+                    } else {
+                        StatusCode::UNAUTHORIZED.into_response()
+                    }
+                }),
+            )
+            .with_state(port);
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            Url::parse(&format!("oci://localhost:{port}/channel/noarch")).unwrap(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        drop(result);
+
+        // The cache action is honored, registries do not provide cache headers so the cached
+        // repodata is never up to date.
+        let fetch_noarch = |cache_action| {
+            fetch_repo_data(
+                Url::parse(&format!("oci://localhost:{port}/channel/noarch")).unwrap(),
+                AuthenticatedClient::default(),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+        let result = fetch_noarch(CacheAction::ForceCacheOnly).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+        drop(result);
+        assert_matches!(
+            fetch_noarch(CacheAction::UseCacheOnly).await,
+            Err(FetchRepoDataError::NoCacheAvailable)
+        );
+        let result = fetch_noarch(CacheAction::CacheOrFetch).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheOutdated);
+        drop(result);
+        let result = fetch_noarch(CacheAction::NoCache).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        drop(result);
+
+        // Without a cache nothing is downloaded when only the cache may be used.
+        let empty_cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            Url::parse(&format!("oci://localhost:{port}/channel/noarch")).unwrap(),
+            AuthenticatedClient::default(),
+            empty_cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                cache_action: CacheAction::ForceCacheOnly,
+                ..Default::default()
+            },
+            None,
+        )
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::NoCacheAvailable));
+
+        // A subdirectory that is not present in the registry is reported as not found.
+        let result = fetch_repo_data(
+            Url::parse(&format!("oci://localhost:{port}/channel/linux-64")).unwrap(),
+            AuthenticatedClient::default(),
+            cache_dir.path().to_owned(),
+            Default::default(),
+            None,
+        )
+        .await;
+        assert_matches!(
+            result,
+            Err(FetchRepoDataError::NotFound(
+                RepoDataNotFoundError::HttpError(_)
+            ))
+        );
+    }
 }
@@ -0,0 +1,472 @@
+//! Fetch the repodata of multiple subdirectories at the same time.
+//!
+//! Servers like anaconda.org throttle clients that send too many requests at the same time. The
+//! [`MultiRequestRepoDataBuilder`] limits the number of concurrent requests per host while still
+//! fetching from different hosts in parallel. When a server responds with `429 Too Many Requests`
+//! the request is retried after the delay that the server requested through its `Retry-After`
+//! header.
+
+use super::{
+    fetch_repo_data, CachedRepoData, CompressedVariant, FetchRepoDataError, FetchRepoDataOptions,
+    ProgressFunc,
+};
+use rattler_networking::{retry_policies::DEFAULT_MAX_RETRY_AFTER, AuthenticatedClient};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Semaphore, time::Instant};
+use url::Url;
+
+/// The default maximum number of requests that are in flight at the same time.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 50;
+
+/// The default maximum number of requests that are in flight at the same time for a single host.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS_PER_HOST: usize = 5;
+
+/// The default number of times a request is retried when the server responds with
+/// `429 Too Many Requests`.
+pub const DEFAULT_MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+/// Builds a set of requests to fetch the repodata of multiple subdirectories concurrently.
+///
+/// ```no_run
+/// # use rattler_networking::AuthenticatedClient;
+/// # use rattler_repodata_gateway::fetch::MultiRequestRepoDataBuilder;
+/// # use url::Url;
+/// # async fn example() {
+/// let results = MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), "./cache")
+///     .with_max_concurrent_requests_per_host(2)
+///     .add_subdir(Url::parse("https://conda.anaconda.org/conda-forge/linux-64/").unwrap())
+///     .add_subdir(Url::parse("https://conda.anaconda.org/conda-forge/noarch/").unwrap())
+///     .fetch()
+///     .await;
+/// # }
+/// ```
+pub struct MultiRequestRepoDataBuilder {
+    client: AuthenticatedClient,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    max_concurrent_requests: usize,
+    max_concurrent_requests_per_host: usize,
+    max_rate_limit_retries: usize,
+    subdirs: Vec<(Url, Option<ProgressFunc>)>,
+}
+
+impl MultiRequestRepoDataBuilder {
+    /// Constructs a new builder that fetches repodata with the given client and stores it in the
+    /// cache at `cache_path`.
+    pub fn new(client: AuthenticatedClient, cache_path: impl Into<PathBuf>) -> Self {
+        Self {
+            client,
+            cache_path: cache_path.into(),
+            options: FetchRepoDataOptions::default(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            max_concurrent_requests_per_host: DEFAULT_MAX_CONCURRENT_REQUESTS_PER_HOST,
+            max_rate_limit_retries: DEFAULT_MAX_RATE_LIMIT_RETRIES,
+            subdirs: Vec::new(),
+        }
+    }
+
+    /// Sets the options that are used to fetch the repodata of every subdirectory.
+    pub fn with_options(self, options: FetchRepoDataOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Sets the compressed variants of the repodata that are downloaded if they are available, in
+    /// order of preference. See [`FetchRepoDataOptions::compression_preference`].
+    pub fn with_compression_preference(
+        mut self,
+        compression_preference: Vec<CompressedVariant>,
+    ) -> Self {
+        self.options.compression_preference = compression_preference;
+        self
+    }
+
+    /// Sets the maximum number of requests that are in flight at the same time.
+    pub fn with_max_concurrent_requests(self, max_concurrent_requests: usize) -> Self {
+        Self {
+            max_concurrent_requests: max_concurrent_requests.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of requests that are in flight at the same time for a single host.
+    pub fn with_max_concurrent_requests_per_host(
+        self,
+        max_concurrent_requests_per_host: usize,
+    ) -> Self {
+        Self {
+            max_concurrent_requests_per_host: max_concurrent_requests_per_host.max(1),
+            ..self
+        }
+    }
+
+    /// Sets how often a request is retried when the server responds with
+    /// `429 Too Many Requests`.
+    pub fn with_max_rate_limit_retries(self, max_rate_limit_retries: usize) -> Self {
+        Self {
+            max_rate_limit_retries,
+            ..self
+        }
+    }
+
+    /// Adds the subdirectory at the given url to the set of subdirectories to fetch.
+    pub fn add_subdir(self, subdir_url: Url) -> Self {
+        self.add_subdir_with_progress(subdir_url, None)
+    }
+
+    /// Adds the subdirectory at the given url to the set of subdirectories to fetch and reports
+    /// the progress of downloading it to `progress`.
+    pub fn add_subdir_with_progress(
+        mut self,
+        subdir_url: Url,
+        progress: Option<ProgressFunc>,
+    ) -> Self {
+        self.subdirs.push((subdir_url, progress));
+        self
+    }
+
+    /// Fetches the repodata of all subdirectories. The results are returned in the order in which
+    /// the subdirectories were added, regardless of which request finishes first, so the channel
+    /// priority of the solver follows the order of the channels.
+    pub async fn fetch(self) -> Vec<(Url, Result<CachedRepoData, FetchRepoDataError>)> {
+        let global = Arc::new(Semaphore::new(self.max_concurrent_requests));
+
+        // Create a limiter for every host. Urls without a host (e.g. `file://` urls) are only
+        // subject to the global limit.
+        let mut hosts = HashMap::new();
+        let requests = self
+            .subdirs
+            .into_iter()
+            .map(|(subdir_url, progress)| {
+                let host = host_key(&subdir_url).map(|key| {
+                    hosts
+                        .entry(key)
+                        .or_insert_with(|| {
+                            Arc::new(HostLimiter::new(self.max_concurrent_requests_per_host))
+                        })
+                        .clone()
+                });
+                let request = RateLimitedRequest {
+                    client: self.client.clone(),
+                    cache_path: self.cache_path.clone(),
+                    options: self.options.clone(),
+                    max_rate_limit_retries: self.max_rate_limit_retries,
+                    global: global.clone(),
+                    host,
+                };
+                async move {
+                    let result = request.fetch(subdir_url.clone(), progress).await;
+                    (subdir_url, result)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        futures::future::join_all(requests).await
+    }
+}
+
+/// Returns the key that identifies the host of a url, if it has one.
+fn host_key(url: &Url) -> Option<(String, Option<u16>)> {
+    url.host_str()
+        .map(|host| (host.to_lowercase(), url.port_or_known_default()))
+}
+
+/// Limits the requests that are sent to a single host.
+struct HostLimiter {
+    semaphore: Semaphore,
+
+    /// The moment before which no requests should be sent to the host because it asked us to back
+    /// off.
+    retry_at: Mutex<Option<Instant>>,
+}
+
+impl HostLimiter {
+    fn new(max_concurrent_requests: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(max_concurrent_requests),
+            retry_at: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the host no longer asks us to back off.
+    async fn wait_for_retry_after(&self) {
+        loop {
+            let retry_at = *self.retry_at.lock().unwrap();
+            match retry_at {
+                Some(retry_at) if retry_at > Instant::now() => {
+                    tokio::time::sleep_until(retry_at).await
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Records that the host asked us to wait for `delay` before sending another request.
+    fn back_off(&self, delay: Duration) {
+        let retry_at = Instant::now() + delay;
+        let mut current = self.retry_at.lock().unwrap();
+        if current.map_or(true, |current| current < retry_at) {
+            *current = Some(retry_at);
+        }
+    }
+}
+
+/// A single request of a [`MultiRequestRepoDataBuilder`].
+struct RateLimitedRequest {
+    client: AuthenticatedClient,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    max_rate_limit_retries: usize,
+    global: Arc<Semaphore>,
+    host: Option<Arc<HostLimiter>>,
+}
+
+impl RateLimitedRequest {
+    async fn fetch(
+        self,
+        subdir_url: Url,
+        progress: Option<ProgressFunc>,
+    ) -> Result<CachedRepoData, FetchRepoDataError> {
+        // The progress function is moved into every attempt, so share it between them.
+        let progress = progress.map(|progress| Arc::new(Mutex::new(progress)));
+
+        let mut attempt = 0;
+        loop {
+            // Acquire the permit of the host first so requests that wait for a busy host do not
+            // hold on to a global permit.
+            let _host_permit = match &self.host {
+                Some(host) => {
+                    let permit = host
+                        .semaphore
+                        .acquire()
+                        .await
+                        .expect("semaphore is never closed");
+                    host.wait_for_retry_after().await;
+                    Some(permit)
+                }
+                None => None,
+            };
+            let _global_permit = self
+                .global
+                .acquire()
+                .await
+                .expect("semaphore is never closed");
+
+            let attempt_progress = progress.clone().map(|progress| -> ProgressFunc {
+                Box::new(move |p| (progress.lock().unwrap())(p))
+            });
+            let result = fetch_repo_data(
+                subdir_url.clone(),
+                self.client.clone(),
+                self.cache_path.clone(),
+                self.options.clone(),
+                attempt_progress,
+            )
+            .await;
+
+            match result {
+                Err(FetchRepoDataError::TooManyRequests { retry_after })
+                    if attempt < self.max_rate_limit_retries =>
+                {
+                    // Without a `Retry-After` header back off exponentially.
+                    let delay = retry_after.unwrap_or_else(|| Duration::from_secs(1 << attempt));
+                    if delay > DEFAULT_MAX_RETRY_AFTER {
+                        return Err(FetchRepoDataError::TooManyRequests { retry_after });
+                    }
+
+                    tracing::warn!(
+                        "{} is rate limiting requests, retrying in {}",
+                        subdir_url,
+                        humantime::format_duration(delay)
+                    );
+                    attempt += 1;
+                    match &self.host {
+                        Some(host) => host.back_off(delay),
+                        None => tokio::time::sleep(delay).await,
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MultiRequestRepoDataBuilder;
+    use crate::fetch::FetchRepoDataError;
+    use assert_matches::assert_matches;
+    use axum::{extract::Path, http::StatusCode, response::IntoResponse, routing::get, Router};
+    use rattler_networking::AuthenticatedClient;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use url::Url;
+
+    const EMPTY_REPO_DATA: &str = r#"{"packages": {}, "packages.conda": {}}"#;
+
+    /// Spawns a server that serves empty repodata for every subdirectory. The server responds with
+    /// `429 Too Many Requests` to the first `throttled` requests. Returns the port of the server
+    /// and the maximum number of requests that were in flight at the same time.
+    fn spawn_server(throttled: usize) -> (u16, Arc<AtomicUsize>) {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let max = max_in_flight.clone();
+        let router = Router::new().route(
+            "/:channel/:subdir/repodata.json",
+            get(move || {
+                let in_flight = in_flight.clone();
+                let max_in_flight = max.clone();
+                let requests = requests.clone();
+                async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) < throttled {
+                        return (StatusCode::TOO_MANY_REQUESTS, [("Retry-After", "1")])
+                            .into_response();
+                    }
+
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    EMPTY_REPO_DATA.into_response()
+                }
+            }),
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+        (port, max_in_flight)
+    }
+
+    #[tokio::test]
+    pub async fn test_limits_requests_per_host() {
+        let (port, max_in_flight) = spawn_server(0);
+
+        let cache_dir = TempDir::new().unwrap();
+        let mut builder =
+            MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), cache_dir.path())
+                .with_max_concurrent_requests_per_host(2);
+        let mut urls = Vec::new();
+        for channel in 0..6 {
+            let url = Url::parse(&format!(
+                "http://localhost:{port}/channel-{channel}/noarch/"
+            ))
+            .unwrap();
+            urls.push(url.clone());
+            builder = builder.add_subdir(url);
+        }
+
+        let results = builder.fetch().await;
+        assert_eq!(
+            results.iter().map(|(url, _)| url).collect::<Vec<_>>(),
+            urls.iter().collect::<Vec<_>>()
+        );
+        for (_, result) in results {
+            result.unwrap();
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    pub async fn test_hosts_are_fetched_in_parallel() {
+        let (port, max_in_flight) = spawn_server(0);
+
+        // `localhost` and `127.0.0.1` refer to the same server but are different hosts.
+        let cache_dir = TempDir::new().unwrap();
+        let results =
+            MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), cache_dir.path())
+                .with_max_concurrent_requests_per_host(1)
+                .add_subdir(Url::parse(&format!("http://localhost:{port}/a/noarch/")).unwrap())
+                .add_subdir(Url::parse(&format!("http://localhost:{port}/b/noarch/")).unwrap())
+                .add_subdir(Url::parse(&format!("http://127.0.0.1:{port}/a/noarch/")).unwrap())
+                .add_subdir(Url::parse(&format!("http://127.0.0.1:{port}/b/noarch/")).unwrap())
+                .fetch()
+                .await;
+        for (_, result) in results {
+            result.unwrap();
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    pub async fn test_results_are_in_channel_order() {
+        // The first channel responds slower than the second one.
+        let router = Router::new().route(
+            "/:channel/:subdir/repodata.json",
+            get(|Path((channel, _)): Path<(String, String)>| async move {
+                if channel == "slow" {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                EMPTY_REPO_DATA
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+
+        let slow = Url::parse(&format!("http://localhost:{port}/slow/noarch/")).unwrap();
+        let fast = Url::parse(&format!("http://localhost:{port}/fast/noarch/")).unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let results =
+            MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), cache_dir.path())
+                .add_subdir(slow.clone())
+                .add_subdir(fast.clone())
+                .fetch()
+                .await;
+        assert_eq!(
+            results.iter().map(|(url, _)| url).collect::<Vec<_>>(),
+            [&slow, &fast]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_after_too_many_requests() {
+        let (port, _) = spawn_server(1);
+        let url = Url::parse(&format!("http://localhost:{port}/channel/noarch/")).unwrap();
+
+        // The request that was throttled is retried.
+        let cache_dir = TempDir::new().unwrap();
+        let mut results =
+            MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), cache_dir.path())
+                .add_subdir(url.clone())
+                .fetch()
+                .await;
+        let (_, result) = results.pop().unwrap();
+        result.unwrap();
+
+        // Without retries the error is returned with the delay requested by the server.
+        let (port, _) = spawn_server(1);
+        let url = Url::parse(&format!("http://localhost:{port}/channel/noarch/")).unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let mut results =
+            MultiRequestRepoDataBuilder::new(AuthenticatedClient::default(), cache_dir.path())
+                .with_max_rate_limit_retries(0)
+                .add_subdir(url)
+                .fetch()
+                .await;
+        let (_, result) = results.pop().unwrap();
+        assert_matches!(
+            result,
+            Err(FetchRepoDataError::TooManyRequests { retry_after: Some(retry_after) })
+                if retry_after == Duration::from_secs(1)
+        );
+    }
+}
@@ -1,10 +1,13 @@
 //! This module provides the [`SparseRepoData`] which is a struct to enable only sparsely loading records
 //! from a `repodata.json` file.
 
+use crate::fetch::RepoDataFreshness;
+use chrono::{DateTime, Utc};
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
 use itertools::Itertools;
 use rattler_conda_types::{
     compute_package_url, Channel, ChannelInfo, PackageName, PackageRecord, RepoDataRecord,
+    SubdirMismatchError,
 };
 use serde::{
     de::{Error, MapAccess, Visitor},
@@ -35,6 +38,12 @@ pub struct SparseRepoData {
     /// A function that can be used to patch the package record after it has been parsed.
     /// This is mainly used to add `pip` to `python` if desired
     patch_record_fn: Option<fn(&mut PackageRecord)>,
+
+    /// If set, records that were published after this moment are ignored.
+    cutoff: Option<DateTime<Utc>>,
+
+    /// When the repodata was fetched from the server, if known.
+    freshness: Option<RepoDataFreshness>,
 }
 
 /// A struct that holds a memory map of a `repodata.json` file and also a self-referential field which
@@ -55,6 +64,10 @@ impl SparseRepoData {
     /// Construct an instance of self from a file on disk and a [`Channel`].
     /// The `patch_function` can be used to patch the package record after it has been parsed
     /// (e.g. to add `pip` to `python`).
+    ///
+    /// If the `info` section of the repodata describes a different subdir than `subdir` an error
+    /// of kind [`io::ErrorKind::InvalidData`] is returned. This catches misconfigured mirrors that
+    /// serve the repodata of the wrong subdirectory.
     pub fn new(
         channel: Channel,
         subdir: impl Into<String>,
@@ -63,7 +76,7 @@ impl SparseRepoData {
     ) -> Result<Self, io::Error> {
         let file = std::fs::File::open(path)?;
         let memory_map = unsafe { memmap2::Mmap::map(&file) }?;
-        Ok(SparseRepoData {
+        let repo_data = SparseRepoData {
             inner: SparseRepoDataInnerTryBuilder {
                 memory_map,
                 repo_data_builder: |memory_map| serde_json::from_slice(memory_map.as_ref()),
@@ -72,7 +85,57 @@ impl SparseRepoData {
             subdir: subdir.into(),
             channel,
             patch_record_fn: patch_function,
-        })
+            cutoff: None,
+            freshness: None,
+        };
+        repo_data
+            .verify_subdir()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(repo_data)
+    }
+
+    /// Restricts the records to the ones that were published at or before `cutoff`. This provides a
+    /// view of the channel as it existed at that moment, which can be used to reproduce an
+    /// environment as it was solved in the past or to find the channel update that broke an
+    /// environment. Records without a timestamp are never excluded.
+    pub fn with_cutoff(self, cutoff: DateTime<Utc>) -> Self {
+        Self {
+            cutoff: Some(cutoff),
+            ..self
+        }
+    }
+
+    /// Returns the moment after which published records are ignored, see [`Self::with_cutoff`].
+    pub fn cutoff(&self) -> Option<&DateTime<Utc>> {
+        self.cutoff.as_ref()
+    }
+
+    /// Records when the repodata was fetched from the server, usually obtained from
+    /// [`crate::fetch::CachedRepoData::cache_state`].
+    pub fn with_freshness(self, freshness: RepoDataFreshness) -> Self {
+        Self {
+            freshness: Some(freshness),
+            ..self
+        }
+    }
+
+    /// Returns when the repodata was fetched from the server, see [`Self::with_freshness`].
+    pub fn freshness(&self) -> Option<&RepoDataFreshness> {
+        self.freshness.as_ref()
+    }
+
+    /// Returns the information from the `info` section of the repodata, if present.
+    pub fn info(&self) -> Option<&ChannelInfo> {
+        self.inner.borrow_repo_data().info.as_ref()
+    }
+
+    /// Verifies that the `info` section of the repodata matches the subdir this instance was
+    /// constructed for.
+    pub fn verify_subdir(&self) -> Result<(), SubdirMismatchError> {
+        match self.info() {
+            Some(info) => info.verify_subdir(&self.subdir),
+            None => Ok(()),
+        }
     }
 
     /// Returns an iterator over all package names in this repodata file.
@@ -100,6 +163,7 @@ impl SparseRepoData {
             &self.channel,
             &self.subdir,
             self.patch_record_fn,
+            self.cutoff.as_ref(),
         )?;
         let mut conda_records = parse_records(
             package_name,
@@ -108,6 +172,7 @@ impl SparseRepoData {
             &self.channel,
             &self.subdir,
             self.patch_record_fn,
+            self.cutoff.as_ref(),
         )?;
         records.append(&mut conda_records);
         Ok(records)
@@ -162,6 +227,7 @@ impl SparseRepoData {
                     &repo_data.channel,
                     &repo_data.subdir,
                     patch_function,
+                    repo_data.cutoff.as_ref(),
                 )?;
                 let mut conda_records = parse_records(
                     &next_package,
@@ -170,6 +236,7 @@ impl SparseRepoData {
                     &repo_data.channel,
                     &repo_data.subdir,
                     patch_function,
+                    repo_data.cutoff.as_ref(),
                 )?;
                 records.append(&mut conda_records);
 
@@ -201,6 +268,11 @@ impl SparseRepoData {
     pub fn subdir(&self) -> &str {
         &self.subdir
     }
+
+    /// Returns the channel from which this repodata was loaded
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
 }
 
 /// A serde compatible struct that only sparsely parses a repodata.json file.
@@ -229,6 +301,7 @@ fn parse_records<'i>(
     channel: &Channel,
     subdir: &str,
     patch_function: Option<fn(&mut PackageRecord)>,
+    cutoff: Option<&DateTime<Utc>>,
 ) -> io::Result<Vec<RepoDataRecord>> {
     let channel_name = channel.canonical_name();
 
@@ -237,16 +310,16 @@ fn parse_records<'i>(
     let mut result = Vec::with_capacity(package_indices.len());
     for (key, raw_json) in &packages[package_indices] {
         let mut package_record: PackageRecord = serde_json::from_str(raw_json.get())?;
+        if cutoff.map_or(false, |cutoff| package_record.is_newer_than(cutoff)) {
+            continue;
+        }
         // Overwrite subdir if its empty
         if package_record.subdir.is_empty() {
             package_record.subdir = subdir.to_owned();
         }
         result.push(RepoDataRecord {
             url: compute_package_url(
-                &channel
-                    .base_url
-                    .join(&format!("{}/", &package_record.subdir))
-                    .expect("failed determine repo_base_url"),
+                &channel.subdir_url(&package_record.subdir),
                 base_url,
                 key.filename,
             ),
@@ -400,7 +473,7 @@ impl<'de> TryFrom<&'de str> for PackageFilename<'de> {
 
 #[cfg(test)]
 mod test {
-    use super::{load_repo_data_recursively, PackageFilename};
+    use super::{load_repo_data_recursively, PackageFilename, SparseRepoData};
     use itertools::Itertools;
     use rattler_conda_types::{Channel, ChannelConfig, PackageName, RepoData, RepoDataRecord};
     use rstest::rstest;
@@ -442,6 +515,60 @@ mod test {
         .unwrap()
     }
 
+    #[test]
+    fn test_subdir_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repodata.json");
+        std::fs::write(
+            &path,
+            r#"{"info": {"subdir": "osx-64"}, "packages": {}, "packages.conda": {}}"#,
+        )
+        .unwrap();
+        let channel = Channel::from_str("conda-forge", &ChannelConfig::default()).unwrap();
+
+        let sparse = SparseRepoData::new(channel.clone(), "osx-64", &path, None).unwrap();
+        assert_eq!(sparse.info().unwrap().subdir, "osx-64");
+
+        let err = SparseRepoData::new(channel, "linux-64", &path, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repodata.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "packages": {
+                    "foo-0.1-0.tar.bz2": {"name": "foo", "version": "0.1", "build": "0", "build_number": 0, "depends": []},
+                    "foo-1.0-0.tar.bz2": {"name": "foo", "version": "1.0", "build": "0", "build_number": 0, "depends": [], "timestamp": 1600000000000},
+                    "foo-2.0-0.tar.bz2": {"name": "foo", "version": "2.0", "build": "0", "build_number": 0, "depends": [], "timestamp": 1700000000000}
+                },
+                "packages.conda": {}
+            }"#,
+        )
+        .unwrap();
+        let channel = Channel::from_str("conda-forge", &ChannelConfig::default()).unwrap();
+        let cutoff = chrono::DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        let sparse = SparseRepoData::new(channel, "linux-64", &path, None)
+            .unwrap()
+            .with_cutoff(cutoff);
+        let versions = sparse
+            .load_records(&PackageName::new_unchecked("foo"))
+            .unwrap()
+            .into_iter()
+            .map(|record| record.package_record.version.to_string())
+            .sorted()
+            .collect_vec();
+        assert_eq!(versions, ["0.1", "1.0"]);
+    }
+
     #[tokio::test]
     async fn test_empty_sparse_load() {
         let sparse_empty_data = load_sparse(Vec::<String>::new(), false).await;
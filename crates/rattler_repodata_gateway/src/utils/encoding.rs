@@ -16,6 +16,8 @@ impl<'a> From<&'a reqwest::Response> for Encoding {
     fn from(res: &'a reqwest::Response) -> Self {
         if is_response_encoded_with(res, "gzip") {
             Encoding::GZip
+        } else if is_response_encoded_with(res, "zstd") {
+            Encoding::Zst
         } else {
             Encoding::Passthrough
         }
@@ -10,14 +10,81 @@ pub(crate) mod simple_channel_server;
 
 mod flock;
 
-/// Convert a URL to a cache filename
+/// The maximum length of the human-readable part of a cache filename.
+const MAX_READABLE_CACHE_KEY_LENGTH: usize = 64;
+
+/// Convert a URL to a cache filename.
+///
+/// The filename consists of a human-readable part derived from the host and path of the url, e.g.
+/// `conda.anaconda.org-conda-forge-linux-64`, followed by a hash of the full url. The hash ensures
+/// that urls that only differ in their scheme, port, credentials or in characters that are
+/// replaced in the readable part map to different files.
 pub(crate) fn url_to_cache_filename(url: &Url) -> String {
-    // Start Rant:
-    // This function mimics behavior from Mamba which itself mimics this behavior from Conda.
-    // However, I find this function absolutely ridiculous, it contains all sort of weird edge
-    // cases and returns a hash that could very easily collide with other files. And why? Why not
-    // simply return a little more descriptive? Like a URL encoded string?
-    // End Rant.
+    let mut url_str = url.to_string();
+
+    // Ensure there is a slash if the URL is empty or doesnt refer to json file
+    if url_str.is_empty() || (!url_str.ends_with('/') && !url_str.ends_with(".json")) {
+        url_str.push('/')
+    }
+
+    // `repodata.json` is the default file of a subdirectory, other files are part of the key.
+    let url_str = match url_str.strip_suffix("repodata.json") {
+        Some(subdir_url) if subdir_url.ends_with('/') => subdir_url,
+        _ => &url_str,
+    };
+
+    // Construct a readable name from the host and the path segments of the url. Conda tokens
+    // (`/t/<token>/`) are left out so they are never written to disk as part of a filename.
+    let mut readable = String::new();
+    let mut path_segments = Vec::new();
+    let mut iter = url.path_segments().into_iter().flatten();
+    while let Some(segment) = iter.next() {
+        if segment == "t" {
+            iter.next();
+        } else {
+            path_segments.push(segment);
+        }
+    }
+    let segments = url
+        .host_str()
+        .into_iter()
+        .chain(path_segments)
+        .filter(|segment| !segment.is_empty() && *segment != "repodata.json");
+    for segment in segments {
+        if !readable.is_empty() {
+            readable.push('-');
+        }
+        readable.extend(segment.chars().map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        }));
+    }
+    if readable.len() > MAX_READABLE_CACHE_KEY_LENGTH {
+        // Keep the end of the name which contains the channel and the platform.
+        readable = readable[readable.len() - MAX_READABLE_CACHE_KEY_LENGTH..].to_owned();
+    }
+
+    // Compute the hash of the full url.
+    let hash = rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(url_str);
+    let mut result = readable;
+    if !result.is_empty() {
+        result.push('-');
+    }
+    for x in &hash[0..8] {
+        write!(result, "{:02x}", x).unwrap();
+    }
+    result
+}
+
+/// Convert a URL to the cache filename that was used before [`url_to_cache_filename`] included a
+/// readable part and a longer hash. This mimics the behavior of conda and mamba.
+///
+/// This is only used to find cache entries that were written by older versions so they can be
+/// moved to their new filename.
+pub(crate) fn legacy_url_to_cache_filename(url: &Url) -> String {
     let mut url_str = url.to_string();
 
     // Ensure there is a slash if the URL is empty or doesnt refer to json file
@@ -29,9 +96,7 @@ pub(crate) fn url_to_cache_filename(url: &Url) -> String {
     let url_str = url_str.strip_suffix("/repodata.json").unwrap_or(&url_str);
 
     // Compute the MD5 hash of the resulting URL string
-    let hash = rattler_digest::compute_bytes_digest::<md5::Md5>(url_str);
-
-    // Convert the hash to an MD5 hash.
+    let hash = rattler_digest::compute_bytes_digest::<rattler_digest::Md5>(url_str);
     let mut result = String::with_capacity(8);
     for x in &hash[0..4] {
         write!(result, "{:02x}", x).unwrap();
@@ -41,13 +106,71 @@ pub(crate) fn url_to_cache_filename(url: &Url) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::url_to_cache_filename;
+    use super::{
+        legacy_url_to_cache_filename, url_to_cache_filename, MAX_READABLE_CACHE_KEY_LENGTH,
+    };
     use url::Url;
 
     #[test]
     fn test_url_to_cache_filename() {
         assert_eq!(
             url_to_cache_filename(&Url::parse("http://test.com/1234/").unwrap()),
+            "test.com-1234-cc1de81396d8037a"
+        );
+
+        // The default file of a subdirectory does not change the key.
+        let key = url_to_cache_filename(
+            &Url::parse("https://conda.anaconda.org/conda-forge/linux-64/repodata.json").unwrap(),
+        );
+        assert!(key.starts_with("conda.anaconda.org-conda-forge-linux-64-"));
+        assert_eq!(
+            key,
+            url_to_cache_filename(
+                &Url::parse("https://conda.anaconda.org/conda-forge/linux-64/").unwrap()
+            )
+        );
+
+        // Channels that only differ in their port or label must not collide.
+        let keys = [
+            "https://example.com/channel/linux-64/",
+            "https://example.com:8443/channel/linux-64/",
+            "https://example.com/channel/label/dev/linux-64/",
+            "https://example.com/channel/linux-64/repodata_from_packages.json",
+        ]
+        .map(|url| url_to_cache_filename(&Url::parse(url).unwrap()));
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{key}");
+        }
+
+        // Conda tokens are not part of the readable name but still change the hash.
+        let key = url_to_cache_filename(
+            &Url::parse("https://conda.anaconda.org/t/secret-token/conda-forge/linux-64/").unwrap(),
+        );
+        assert!(!key.contains("secret-token"), "{key}");
+        assert!(key.starts_with("conda.anaconda.org-conda-forge-linux-64-"));
+        assert_ne!(
+            key,
+            url_to_cache_filename(
+                &Url::parse("https://conda.anaconda.org/conda-forge/linux-64/").unwrap()
+            )
+        );
+
+        // Long urls are truncated from the front.
+        let key = url_to_cache_filename(
+            &Url::parse(&format!(
+                "https://example.com/{}/linux-64/",
+                "a".repeat(100)
+            ))
+            .unwrap(),
+        );
+        assert!(key.ends_with(&format!("aaaa-linux-64-{}", &key[key.len() - 16..])));
+        assert_eq!(key.len(), MAX_READABLE_CACHE_KEY_LENGTH + 17);
+    }
+
+    #[test]
+    fn test_legacy_url_to_cache_filename() {
+        assert_eq!(
+            legacy_url_to_cache_filename(&Url::parse("http://test.com/1234/").unwrap()),
             "302f0a61"
         );
     }
@@ -45,7 +45,7 @@ impl SimpleChannelServer {
 
         // Spawn the server. Let go of the JoinHandle, we can use the graceful shutdown trigger to
         // stop the server.
-        let _ = tokio::spawn(server);
+        tokio::spawn(server);
 
         Self {
             local_addr: addr,
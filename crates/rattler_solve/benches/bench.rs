@@ -20,10 +20,10 @@ fn conda_json_path_noarch() -> String {
     )
 }
 
-fn read_sparse_repodata(path: &str) -> SparseRepoData {
+fn read_sparse_repodata(path: &str, subdir: &str) -> SparseRepoData {
     SparseRepoData::new(
         Channel::from_str("dummy", &ChannelConfig::default()).unwrap(),
-        "dummy".to_string(),
+        subdir.to_string(),
         path,
         None,
     )
@@ -46,8 +46,8 @@ fn bench_solve_environment(c: &mut Criterion, specs: Vec<&str>) {
     let json_file_noarch = conda_json_path_noarch();
 
     let sparse_repo_datas = vec![
-        read_sparse_repodata(&json_file),
-        read_sparse_repodata(&json_file_noarch),
+        read_sparse_repodata(&json_file, "linux-64"),
+        read_sparse_repodata(&json_file_noarch, "noarch"),
     ];
 
     let names = specs.iter().map(|s| s.name.clone().unwrap());
@@ -59,11 +59,8 @@ fn bench_solve_environment(c: &mut Criterion, specs: Vec<&str>) {
         b.iter(|| {
             rattler_solve::libsolv_c::Solver
                 .solve(black_box(SolverTask {
-                    available_packages: &available_packages,
-                    locked_packages: vec![],
-                    pinned_packages: vec![],
-                    virtual_packages: vec![],
                     specs: specs.clone(),
+                    ..SolverTask::new(&available_packages)
                 }))
                 .unwrap()
         })
@@ -72,13 +69,10 @@ fn bench_solve_environment(c: &mut Criterion, specs: Vec<&str>) {
     #[cfg(feature = "resolvo")]
     group.bench_function("resolvo", |b| {
         b.iter(|| {
-            rattler_solve::resolvo::Solver
+            rattler_solve::resolvo::Solver::default()
                 .solve(black_box(SolverTask {
-                    available_packages: &available_packages,
-                    locked_packages: vec![],
-                    pinned_packages: vec![],
-                    virtual_packages: vec![],
                     specs: specs.clone(),
+                    ..SolverTask::new(&available_packages)
                 }))
                 .unwrap()
         })
@@ -0,0 +1,244 @@
+//! Provides [`solve_environment`], which solves the specs of an environment with a backend and
+//! options that are selected at runtime.
+//!
+//! Tools that let users solve environments (e.g. a command line interface, a server or bindings
+//! for other languages) should use this instead of constructing a [`SolverTask`] for a specific
+//! backend themselves, so that all of them honor the same [`SolveOptions`].
+
+use crate::{
+    ChannelPriority, DependencyMode, ParseSolveOptionError, Policy, SolveError, SolveStrategy,
+    SolverImpl, SolverTask,
+};
+use chrono::{DateTime, Utc};
+use rattler_conda_types::{GenericVirtualPackage, MatchSpec, RepoDataRecord, StringMatcher};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Selects the backend that solves an environment.
+///
+/// The default is the libsolv backend if it is enabled, and the resolvo backend otherwise.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SolverBackend {
+    /// The [`crate::libsolv_c::Solver`].
+    #[cfg(feature = "libsolv_c")]
+    #[default]
+    LibsolvC,
+
+    /// The [`crate::resolvo::Solver`].
+    #[cfg(feature = "resolvo")]
+    #[cfg_attr(not(feature = "libsolv_c"), default)]
+    Resolvo,
+}
+
+impl FromStr for SolverBackend {
+    type Err = ParseSolveOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "libsolv_c")]
+            "libsolv" => Ok(SolverBackend::LibsolvC),
+            #[cfg(feature = "resolvo")]
+            "resolvo" => Ok(SolverBackend::Resolvo),
+            _ => Err(ParseSolveOptionError {
+                option: "solver",
+                value: s.to_owned(),
+                expected: &[
+                    #[cfg(feature = "libsolv_c")]
+                    "libsolv",
+                    #[cfg(feature = "resolvo")]
+                    "resolvo",
+                ],
+            }),
+        }
+    }
+}
+
+/// The options with which an environment is solved. These map onto the fields of a
+/// [`SolverTask`] that do not describe the environment itself.
+#[derive(Debug, Clone, Default)]
+pub struct SolveOptions {
+    /// The backend that solves the environment.
+    pub backend: SolverBackend,
+
+    /// See [`SolverTask::strategy`].
+    pub strategy: SolveStrategy,
+
+    /// See [`SolverTask::channel_priority`].
+    pub channel_priority: ChannelPriority,
+
+    /// See [`SolverTask::prefer_arch_over_noarch`].
+    pub prefer_arch_over_noarch: bool,
+
+    /// See [`SolverTask::dependency_mode`].
+    pub dependency_mode: DependencyMode,
+
+    /// See [`SolverTask::policy`].
+    pub policy: Policy,
+
+    /// See [`SolverTask::exclude_newer`].
+    pub exclude_newer: Option<DateTime<Utc>>,
+}
+
+/// The [`SolveOptions`] as they are passed to tools that solve environments on behalf of other
+/// programs, e.g. in a JSON request. Every option is optional and is given as a string, use
+/// [`SolveOptionsSpec::to_solve_options`] to parse them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SolveOptionsSpec {
+    /// The solver backend to use: `libsolv` or `resolvo`. Defaults to `libsolv`.
+    #[serde(default)]
+    pub solver: Option<String>,
+
+    /// How the order of the channels influences the selected packages: `strict`, `flexible` or
+    /// `disabled`. Defaults to `disabled`, which is the only priority that `libsolv` supports.
+    #[serde(default)]
+    pub channel_priority: Option<String>,
+
+    /// Which candidates of a package are preferred: `highest` or `lowest-version`. Defaults to
+    /// `highest`, which is the only strategy that `libsolv` supports.
+    #[serde(default)]
+    pub strategy: Option<String>,
+
+    /// See [`SolveOptions::prefer_arch_over_noarch`].
+    #[serde(default)]
+    pub prefer_arch_over_noarch: bool,
+
+    /// Packages whose name matches one of these patterns are never installed (e.g. `openssl` or
+    /// `*-gpl`).
+    #[serde(default)]
+    pub denied_packages: Vec<String>,
+
+    /// If set, only packages with one of these license families are installed.
+    #[serde(default)]
+    pub allowed_license_families: Option<Vec<String>>,
+
+    /// If set, only packages that are downloaded from one of these hosts are installed.
+    #[serde(default)]
+    pub allowed_channel_hosts: Option<Vec<String>>,
+}
+
+impl SolveOptionsSpec {
+    /// Parses the options. Options that are not set keep the value of [`SolveOptions::default`].
+    pub fn to_solve_options(&self) -> Result<SolveOptions, InvalidSolveOptionsError> {
+        let defaults = SolveOptions::default();
+        Ok(SolveOptions {
+            backend: parse_or(self.solver.as_deref(), defaults.backend)?,
+            strategy: parse_or(self.strategy.as_deref(), defaults.strategy)?,
+            channel_priority: parse_or(
+                self.channel_priority.as_deref(),
+                defaults.channel_priority,
+            )?,
+            prefer_arch_over_noarch: self.prefer_arch_over_noarch,
+            policy: Policy {
+                denied_packages: self
+                    .denied_packages
+                    .iter()
+                    .map(|pattern| {
+                        StringMatcher::from_str(pattern).map_err(|_| {
+                            InvalidSolveOptionsError::InvalidPackagePattern(pattern.clone())
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                allowed_license_families: self.allowed_license_families.clone(),
+                allowed_channel_hosts: self.allowed_channel_hosts.clone(),
+            },
+            ..defaults
+        })
+    }
+}
+
+/// Parses an optional value, falling back to `default` if there is none.
+fn parse_or<T: FromStr>(value: Option<&str>, default: T) -> Result<T, T::Err> {
+    value.map_or(Ok(default), T::from_str)
+}
+
+/// An error that is returned by [`SolveOptionsSpec::to_solve_options`].
+#[derive(Debug, Clone, thiserror::Error, Eq, PartialEq)]
+pub enum InvalidSolveOptionsError {
+    /// The solver, channel priority or strategy is not supported.
+    #[error(transparent)]
+    InvalidOption(#[from] ParseSolveOptionError),
+
+    /// A pattern of [`SolveOptionsSpec::denied_packages`] is neither a valid glob nor a valid
+    /// regex.
+    #[error("'{0}' is not a valid package name pattern")]
+    InvalidPackagePattern(String),
+}
+
+/// The packages an environment is solved from and the requirements it has to satisfy.
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentSpec<'a> {
+    /// The records of the available packages, grouped by channel subdirectory in order of channel
+    /// priority. See [`SolverTask::available_packages`].
+    pub available_packages: &'a [Vec<RepoDataRecord>],
+
+    /// See [`SolverTask::specs`].
+    pub specs: Vec<MatchSpec>,
+
+    /// See [`SolverTask::constraints`].
+    pub constraints: Vec<MatchSpec>,
+
+    /// See [`SolverTask::locked_packages`].
+    pub locked_packages: Vec<RepoDataRecord>,
+
+    /// See [`SolverTask::virtual_packages`].
+    pub virtual_packages: Vec<GenericVirtualPackage>,
+}
+
+/// Solves the environment with the backend and the options in `options`. Returns the records of
+/// the packages in the environment.
+pub fn solve_environment(
+    environment: EnvironmentSpec<'_>,
+    options: SolveOptions,
+) -> Result<Vec<RepoDataRecord>, SolveError> {
+    let task = SolverTask {
+        locked_packages: environment.locked_packages,
+        virtual_packages: environment.virtual_packages,
+        specs: environment.specs,
+        constraints: environment.constraints,
+        strategy: options.strategy,
+        channel_priority: options.channel_priority,
+        prefer_arch_over_noarch: options.prefer_arch_over_noarch,
+        dependency_mode: options.dependency_mode,
+        policy: options.policy,
+        exclude_newer: options.exclude_newer,
+        ..SolverTask::new(environment.available_packages)
+    };
+    match options.backend {
+        #[cfg(feature = "libsolv_c")]
+        SolverBackend::LibsolvC => crate::libsolv_c::Solver.solve(task),
+        #[cfg(feature = "resolvo")]
+        SolverBackend::Resolvo => crate::resolvo::Solver::default().solve(task),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidSolveOptionsError, SolveOptionsSpec};
+    use crate::{ChannelPriority, SolveStrategy};
+
+    #[test]
+    fn test_solve_options_spec() {
+        let spec: SolveOptionsSpec = serde_json::from_str("{}").unwrap();
+        let options = spec.to_solve_options().unwrap();
+        assert_eq!(options.channel_priority, ChannelPriority::Disabled);
+        assert!(!options.prefer_arch_over_noarch);
+        assert!(options.policy.is_empty());
+
+        let spec: SolveOptionsSpec = serde_json::from_str(
+            r#"{"channel_priority": "strict", "strategy": "lowest-version",
+                "prefer_arch_over_noarch": true, "denied_packages": ["*-gpl"]}"#,
+        )
+        .unwrap();
+        let options = spec.to_solve_options().unwrap();
+        assert_eq!(options.channel_priority, ChannelPriority::Strict);
+        assert_eq!(options.strategy, SolveStrategy::LowestVersion);
+        assert!(options.prefer_arch_over_noarch);
+        assert_eq!(options.policy.denied_packages.len(), 1);
+
+        let spec: SolveOptionsSpec = serde_json::from_str(r#"{"strategy": "lowest"}"#).unwrap();
+        assert!(matches!(
+            spec.to_solve_options(),
+            Err(InvalidSolveOptionsError::InvalidOption(_))
+        ));
+    }
+}
@@ -3,13 +3,29 @@
 
 #![deny(missing_docs)]
 
+#[cfg(any(feature = "libsolv_c", feature = "resolvo"))]
+mod environment;
 #[cfg(feature = "libsolv_c")]
 pub mod libsolv_c;
 #[cfg(feature = "resolvo")]
 pub mod resolvo;
+pub mod update;
+mod virtual_packages;
+mod why_not;
 
-use rattler_conda_types::{GenericVirtualPackage, MatchSpec, RepoDataRecord};
+#[cfg(any(feature = "libsolv_c", feature = "resolvo"))]
+pub use environment::{
+    solve_environment, EnvironmentSpec, InvalidSolveOptionsError, SolveOptions, SolveOptionsSpec,
+    SolverBackend,
+};
+pub use rattler_conda_types::{Policy, PolicyViolation};
+pub use why_not::{why_not, CandidateConflicts, Conflict, WhyNot};
+
+use chrono::{DateTime, Utc};
+use rattler_conda_types::{GenericVirtualPackage, MatchSpec, PackageName, RepoDataRecord};
+use std::collections::HashSet;
 use std::fmt;
+use std::str::FromStr;
 
 /// Represents a solver implementation, capable of solving [`SolverTask`]s
 pub trait SolverImpl {
@@ -42,6 +58,10 @@ pub enum SolveError {
     /// Error when converting matchspec
     #[error(transparent)]
     ParseMatchSpecError(#[from] rattler_conda_types::ParseMatchSpecError),
+
+    /// The specs request packages that are not allowed by the [`Policy`] of the task, or the
+    /// solution contains such packages.
+    PolicyViolation(Vec<PolicyViolation>),
 }
 
 impl fmt::Display for SolveError {
@@ -60,6 +80,17 @@ impl fmt::Display for SolveError {
             SolveError::ParseMatchSpecError(e) => {
                 write!(f, "Error parsing match spec: {}", e)
             }
+            SolveError::PolicyViolation(violations) => {
+                write!(
+                    f,
+                    "The policy does not allow: {}",
+                    violations
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
         }
     }
 }
@@ -77,7 +108,10 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     /// locked version it will prefer that variant instead. This is useful to reduce the number of
     /// packages that are updated when installing new packages.
     ///
-    /// Usually you add the currently installed packages or packages from a lock-file here.
+    /// Usually you add the currently installed packages or packages from a lock-file here. A
+    /// locked package is only replaced when the specs require a different variant. To keep
+    /// installed packages from changing at all (like conda's `--freeze-installed`) add them to
+    /// [`Self::pinned_packages`] instead.
     pub locked_packages: Vec<RepoDataRecord>,
 
     /// Records of packages that are previously selected and CANNOT be changed.
@@ -92,7 +126,338 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     pub virtual_packages: Vec<GenericVirtualPackage>,
 
     /// The specs we want to solve
+    ///
+    /// A spec that pins its package to a channel, e.g. `conda-forge/label/rust_dev::rust`, only
+    /// selects candidates from that channel (and label and subdir).
     pub specs: Vec<MatchSpec>,
+
+    /// Additional constraints on the packages in the solution, for instance the pins read from the
+    /// `conda-meta/pinned` file of a prefix.
+    ///
+    /// Unlike [`Self::specs`] these do not cause a package to be installed. However, if a package
+    /// with the same name as a constraint ends up in the solution it must satisfy the constraint.
+    /// Records of that package that do not match the constraint are never selected, not even when
+    /// they are part of the [`Self::locked_packages`]. The [`Self::pinned_packages`] are not
+    /// affected by the constraints.
+    pub constraints: Vec<MatchSpec>,
+
+    /// The strategy to use when selecting between the candidates of a package.
+    pub strategy: SolveStrategy,
+
+    /// Determines whether the order of the channels in [`Self::available_packages`] influences
+    /// which candidates are selected.
+    pub channel_priority: ChannelPriority,
+
+    /// When both a `noarch` build and an architecture specific build of the same version of a
+    /// package satisfy the requirements, prefer the architecture specific build like conda does.
+    ///
+    /// This is currently only supported by the `resolvo` backend.
+    pub prefer_arch_over_noarch: bool,
+
+    /// Determines whether the packages of the specs and their dependencies are part of the
+    /// solution.
+    pub dependency_mode: DependencyMode,
+
+    /// Rules that restrict which packages may be part of the solution. Records that are not
+    /// allowed by the policy are never selected, not even when they are part of the
+    /// [`Self::locked_packages`].
+    pub policy: Policy,
+
+    /// If set, records that were published after this moment are never selected, not even when
+    /// they are part of the [`Self::locked_packages`]. This makes a solve reproducible when new
+    /// packages are published to the channels. Records without a timestamp are always selectable.
+    pub exclude_newer: Option<DateTime<Utc>>,
+}
+
+impl<TAvailablePackagesIterator> SolverTask<TAvailablePackagesIterator> {
+    /// Constructs a task for the given available packages with all other fields set to their
+    /// defaults. Use struct update syntax to only set the fields that matter, e.g.
+    /// `SolverTask { specs, ..SolverTask::new(available_packages) }`.
+    pub fn new(available_packages: TAvailablePackagesIterator) -> Self {
+        Self {
+            available_packages,
+            locked_packages: Vec::new(),
+            pinned_packages: Vec::new(),
+            virtual_packages: Vec::new(),
+            specs: Vec::new(),
+            constraints: Vec::new(),
+            strategy: SolveStrategy::default(),
+            channel_priority: ChannelPriority::default(),
+            prefer_arch_over_noarch: false,
+            dependency_mode: DependencyMode::default(),
+            policy: Policy::default(),
+            exclude_newer: None,
+        }
+    }
+}
+
+/// Determines which candidates of a package the solver prefers.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SolveStrategy {
+    /// Prefer the highest versions of packages. This is the default.
+    #[default]
+    Highest,
+
+    /// Prefer the lowest versions of packages that still satisfy all constraints. This is similar
+    /// to `cargo -Z minimal-versions` and is useful to verify the lower bounds of the requirements
+    /// of a package.
+    ///
+    /// This strategy is currently only supported by the `resolvo` backend.
+    LowestVersion,
+}
+
+impl FromStr for SolveStrategy {
+    type Err = ParseSolveOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "highest" => Ok(SolveStrategy::Highest),
+            "lowest-version" => Ok(SolveStrategy::LowestVersion),
+            _ => Err(ParseSolveOptionError {
+                option: "solve strategy",
+                value: s.to_owned(),
+                expected: &["highest", "lowest-version"],
+            }),
+        }
+    }
+}
+
+/// Determines how the priority of the channels influences the candidates of a package that the
+/// solver selects, like the `channel_priority` setting of conda.
+///
+/// The priority of a channel is determined by the order of [`SolverTask::available_packages`]:
+/// the channel of the first record is the channel with the highest priority. The channel of a
+/// record is identified by [`RepoDataRecord::channel`], so the repodata of multiple subdirs of the
+/// same channel share the same priority.
+///
+/// Channel priorities other than [`ChannelPriority::Disabled`] are currently only supported by
+/// the `resolvo` backend.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ChannelPriority {
+    /// Only the candidates from the channel with the highest priority that contains the package
+    /// are considered, the candidates from the other channels are ignored even if they have a
+    /// higher version.
+    Strict,
+
+    /// Candidates from a channel with a higher priority are preferred over the candidates from
+    /// channels with a lower priority, even if those have a higher version. The candidates from
+    /// the channels with a lower priority are only selected if no candidate from a channel with a
+    /// higher priority satisfies the requirements.
+    Flexible,
+
+    /// The channel of a candidate is ignored, the highest version is preferred no matter which
+    /// channel it comes from. This is the default.
+    #[default]
+    Disabled,
+}
+
+impl FromStr for ChannelPriority {
+    type Err = ParseSolveOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(ChannelPriority::Strict),
+            "flexible" => Ok(ChannelPriority::Flexible),
+            "disabled" => Ok(ChannelPriority::Disabled),
+            _ => Err(ParseSolveOptionError {
+                option: "channel priority",
+                value: s.to_owned(),
+                expected: &["strict", "flexible", "disabled"],
+            }),
+        }
+    }
+}
+
+/// An error that is returned when one of the options of a solve, like a [`ChannelPriority`],
+/// cannot be parsed from a string.
+#[derive(Debug, Clone, thiserror::Error, Eq, PartialEq)]
+#[error("'{value}' is not a {option}, use {}", .expected.join(", "))]
+pub struct ParseSolveOptionError {
+    option: &'static str,
+    value: String,
+    expected: &'static [&'static str],
+}
+
+/// Determines which packages end up in the solution, similar to the `--only-deps` and `--no-deps`
+/// flags of conda.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DependencyMode {
+    /// Install the packages of the specs and all their dependencies. This is the default.
+    #[default]
+    All,
+
+    /// Install the dependencies of the specs but not the packages of the specs themselves.
+    ///
+    /// A package of a spec is still installed if another package in the solution depends on it,
+    /// e.g. when solving for `python 3.11.* flask` only `flask` is left out. Packages that are
+    /// part of the [`SolverTask::locked_packages`] are never left out.
+    ///
+    /// The solver ignores the constraints of the packages of the specs, because they are not
+    /// installed. This also applies to a package of a spec that is still installed because
+    /// another package depends on it.
+    OnlyDeps,
+
+    /// Install the packages of the specs without their dependencies. The packages in
+    /// [`SolverTask::locked_packages`] that do not match a spec are kept as is.
+    ///
+    /// The solver ignores the dependencies of the specs, so they do not have to be available.
+    /// The constraints of the packages of the specs still apply.
+    NoDeps,
+}
+
+/// Applies a [`DependencyMode`] to the input and the solution of a [`SolverTask`].
+pub(crate) struct DependencyModeFilter {
+    mode: DependencyMode,
+    spec_names: HashSet<PackageName>,
+    locked_packages: Vec<RepoDataRecord>,
+}
+
+impl DependencyModeFilter {
+    /// Captures the parts of the task that are needed to filter its solution.
+    pub(crate) fn new<T>(task: &SolverTask<T>) -> Self {
+        if task.dependency_mode == DependencyMode::All {
+            return Self {
+                mode: DependencyMode::All,
+                spec_names: HashSet::new(),
+                locked_packages: Vec::new(),
+            };
+        }
+        Self {
+            mode: task.dependency_mode,
+            spec_names: task
+                .specs
+                .iter()
+                .filter_map(|spec| spec.name.clone())
+                .collect(),
+            locked_packages: task.locked_packages.clone(),
+        }
+    }
+
+    /// Returns the names of the packages whose dependencies the solver must ignore.
+    pub(crate) fn ignored_dependencies(&self) -> impl Iterator<Item = &PackageName> + '_ {
+        (self.mode == DependencyMode::NoDeps)
+            .then_some(&self.spec_names)
+            .into_iter()
+            .flatten()
+    }
+
+    /// Returns the names of the packages whose constraints the solver must ignore.
+    pub(crate) fn ignored_constraints(&self) -> impl Iterator<Item = &PackageName> + '_ {
+        (self.mode == DependencyMode::OnlyDeps)
+            .then_some(&self.spec_names)
+            .into_iter()
+            .flatten()
+    }
+
+    /// Removes the packages from the solution that should not be installed.
+    pub(crate) fn apply(self, mut solution: Vec<RepoDataRecord>) -> Vec<RepoDataRecord> {
+        match self.mode {
+            DependencyMode::All => solution,
+            DependencyMode::OnlyDeps => {
+                // The names of the packages that are required by another package in the solution.
+                let required = solution
+                    .iter()
+                    .flat_map(|record| {
+                        record
+                            .package_record
+                            .depends
+                            .iter()
+                            .filter_map(|dep| MatchSpec::from_str(dep).ok()?.name)
+                            .filter(|name| name != &record.package_record.name)
+                    })
+                    .collect::<HashSet<_>>();
+                solution.retain(|record| {
+                    let name = &record.package_record.name;
+                    !self.spec_names.contains(name)
+                        || required.contains(name)
+                        || self.locked_packages.contains(record)
+                });
+                solution
+            }
+            DependencyMode::NoDeps => {
+                solution.retain(|record| self.spec_names.contains(&record.package_record.name));
+                solution.extend(
+                    self.locked_packages
+                        .into_iter()
+                        .filter(|record| !self.spec_names.contains(&record.package_record.name)),
+                );
+                solution
+            }
+        }
+    }
+}
+
+/// Returns the constraints that restrict the candidates of a package: the constraints of a task
+/// and the specs that pin their package to a channel (e.g. `conda-forge::python`). Candidates of
+/// such a package from another channel can never be selected.
+pub(crate) fn candidate_constraints(
+    specs: &[MatchSpec],
+    constraints: &[MatchSpec],
+) -> Vec<MatchSpec> {
+    constraints
+        .iter()
+        .chain(specs.iter().filter(|spec| spec.channel.is_some()))
+        .cloned()
+        .collect()
+}
+
+/// Returns an error if any of the specs requests a package that is denied by the policy.
+pub(crate) fn check_specs_policy(specs: &[MatchSpec], policy: &Policy) -> Result<(), SolveError> {
+    let violations = policy.check_specs(specs);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(SolveError::PolicyViolation(violations))
+    }
+}
+
+/// Returns an error if any of the records of a solution is not allowed by the policy. This can
+/// only happen for records that the solver is not free to choose, like pinned packages.
+pub(crate) fn check_solution_policy(
+    solution: &[RepoDataRecord],
+    policy: &Policy,
+) -> Result<(), SolveError> {
+    let violations = policy.check_records(solution);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(SolveError::PolicyViolation(violations))
+    }
+}
+
+/// Returns true if the solver may select the record: it satisfies the constraints, it is allowed by
+/// the policy and it was not published after `exclude_newer`.
+pub(crate) fn is_selectable(
+    record: &RepoDataRecord,
+    constraints: &[MatchSpec],
+    policy: &Policy,
+    exclude_newer: Option<&DateTime<Utc>>,
+) -> bool {
+    satisfies_constraints(record, constraints)
+        && policy.allows(record)
+        && !is_excluded_newer(record, exclude_newer)
+}
+
+/// Returns true if the record was published after `exclude_newer`.
+pub(crate) fn is_excluded_newer(
+    record: &RepoDataRecord,
+    exclude_newer: Option<&DateTime<Utc>>,
+) -> bool {
+    exclude_newer.map_or(false, |cutoff| record.package_record.is_newer_than(cutoff))
+}
+
+/// Returns true if the record satisfies all the constraints that apply to a package with its name.
+pub(crate) fn satisfies_constraints(record: &RepoDataRecord, constraints: &[MatchSpec]) -> bool {
+    constraints
+        .iter()
+        .filter(|constraint| constraint.name.as_ref() == Some(&record.package_record.name))
+        .all(|constraint| {
+            constraint.matches(&record.package_record)
+                && constraint
+                    .channel
+                    .as_ref()
+                    .map_or(true, |channel| channel.matches(record))
+        })
 }
 
 /// A representation of a collection of [`RepoDataRecord`] usable by a [`SolverImpl`]
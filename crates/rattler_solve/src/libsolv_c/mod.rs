@@ -1,7 +1,11 @@
 //! Provides an solver implementation based on the [`rattler_libsolv_c`] crate.
 
+use crate::{
+    candidate_constraints, check_solution_policy, check_specs_policy, is_excluded_newer,
+    is_selectable, virtual_packages::describe_virtual_packages, ChannelPriority,
+    DependencyModeFilter, SolveError, SolveStrategy, SolverTask,
+};
 use crate::{IntoRepoData, SolverRepoData};
-use crate::{SolveError, SolverTask};
 pub use input::cache_repodata;
 use input::{add_repodata_records, add_solv_file, add_virtual_packages};
 pub use libc_byte_slice::LibcByteSlice;
@@ -14,6 +18,7 @@ use wrapper::{
     pool::{Pool, Verbosity},
     repo::Repo,
     solve_goal::SolveGoal,
+    solve_problem::SolveProblem,
 };
 
 mod input;
@@ -76,6 +81,8 @@ fn c_string<T: AsRef<str>>(str: T) -> CString {
 }
 
 /// A [`Solver`] implemented using the `libsolv` library
+///
+/// Unlike the `resolvo` backend this solver does not record any solve statistics.
 #[derive(Default)]
 pub struct Solver;
 
@@ -90,6 +97,29 @@ impl super::SolverImpl for Solver {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<Vec<RepoDataRecord>, SolveError> {
+        if task.strategy != SolveStrategy::Highest {
+            return Err(SolveError::UnsupportedOperations(vec![format!(
+                "the {:?} solve strategy",
+                task.strategy
+            )]));
+        }
+        if task.channel_priority != ChannelPriority::Disabled {
+            return Err(SolveError::UnsupportedOperations(vec![format!(
+                "the {:?} channel priority",
+                task.channel_priority
+            )]));
+        }
+        if task.prefer_arch_over_noarch {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "preferring architecture specific packages over noarch packages".to_string(),
+            ]));
+        }
+
+        check_specs_policy(&task.specs, &task.policy)?;
+        let dependency_mode = DependencyModeFilter::new(&task);
+        let constraints = candidate_constraints(&task.specs, &task.constraints);
+        let exclude_newer = task.exclude_newer.as_ref();
+
         // Construct a default libsolv pool
         let pool = Pool::default();
 
@@ -109,7 +139,23 @@ impl super::SolverImpl for Solver {
         // Create repos for all channel + platform combinations
         let mut repo_mapping = HashMap::new();
         let mut all_repodata_records = Vec::new();
-        for repodata in task.available_packages.into_iter().map(IntoRepoData::into) {
+        for mut repodata in task.available_packages.into_iter().map(IntoRepoData::into) {
+            // Remove the records that do not satisfy the constraints or the policy, or that were
+            // published after the cutoff. The cached .solv file contains all records so it can no
+            // longer be used.
+            if !constraints.is_empty()
+                || !task.policy.is_empty()
+                || repodata
+                    .records
+                    .iter()
+                    .any(|record| is_excluded_newer(record, exclude_newer))
+            {
+                repodata.records.retain(|record| {
+                    is_selectable(record, &constraints, &task.policy, exclude_newer)
+                });
+                repodata.solv_file = None;
+            }
+
             if repodata.records.is_empty() {
                 continue;
             }
@@ -132,12 +178,18 @@ impl super::SolverImpl for Solver {
         }
 
         // Create a special pool for records that are already installed or locked.
+        let locked_records = task
+            .locked_packages
+            .iter()
+            .filter(|record| is_selectable(record, &constraints, &task.policy, exclude_newer))
+            .collect::<Vec<_>>();
         let repo = Repo::new(&pool, "locked");
-        let installed_solvables = add_repodata_records(&pool, &repo, &task.locked_packages);
+        let installed_solvables =
+            add_repodata_records(&pool, &repo, locked_records.iter().copied());
 
         // Also add the installed records to the repodata
         repo_mapping.insert(repo.id(), repo_mapping.len());
-        all_repodata_records.push(task.locked_packages.iter().collect());
+        all_repodata_records.push(locked_records);
 
         // Create a special pool for records that are pinned and cannot be changed.
         let repo = Repo::new(&pool, "pinned");
@@ -147,6 +199,18 @@ impl super::SolverImpl for Solver {
         repo_mapping.insert(repo.id(), repo_mapping.len());
         all_repodata_records.push(task.pinned_packages.iter().collect());
 
+        // Drop the dependencies and constraints that the solver must ignore.
+        for name in dependency_mode.ignored_dependencies() {
+            if let Some(name) = pool.find_interned_str(name.as_normalized()) {
+                pool.clear_requires(name);
+            }
+        }
+        for name in dependency_mode.ignored_constraints() {
+            if let Some(name) = pool.find_interned_str(name.as_normalized()) {
+                pool.clear_constraints(name);
+            }
+        }
+
         // Create datastructures for solving
         pool.create_whatprovides();
 
@@ -174,7 +238,16 @@ impl super::SolverImpl for Solver {
         solver.set_flag(SolverFlag::allow_uninstall(), true);
         solver.set_flag(SolverFlag::allow_downgrade(), true);
 
-        let transaction = solver.solve(&mut goal).map_err(SolveError::Unsolvable)?;
+        let transaction = solver.solve(&mut goal).map_err(|(mut problems, rules)| {
+            let virtual_package_hints = describe_virtual_packages(
+                rules
+                    .iter()
+                    .filter_map(SolveProblem::conflicting_package_name),
+                &task.virtual_packages,
+            );
+            problems.extend(virtual_package_hints);
+            SolveError::Unsolvable(problems)
+        })?;
 
         let required_records = get_required_packages(
             &pool,
@@ -191,7 +264,9 @@ impl super::SolverImpl for Solver {
             )
         })?;
 
-        Ok(required_records)
+        let solution = dependency_mode.apply(required_records);
+        check_solution_policy(&solution, &task.policy)?;
+        Ok(solution)
     }
 }
 
@@ -1,6 +1,7 @@
 use super::{
     super::{c_string, wrapper::ffi::Id},
     ffi,
+    keys::SOLVABLE_CONSTRAINS,
     repo::Repo,
     solvable::SolvableId,
     solver::Solver,
@@ -114,6 +115,53 @@ impl Pool {
         solvables.swap(s1.0 as _, s2.0 as _);
     }
 
+    /// Removes the requirements of all solvables with the given name, so the solver ignores their
+    /// dependencies. Must be called before [`Pool::create_whatprovides`].
+    pub fn clear_requires(&self, name: StringId) {
+        let pool = self.as_ref();
+
+        // Safe because `pool.solvables` is the start of an array of length `pool.nsolvables`
+        let solvables =
+            unsafe { std::slice::from_raw_parts_mut(pool.solvables, pool.nsolvables as _) };
+
+        for solvable in solvables
+            .iter_mut()
+            .filter(|solvable| solvable.name == name.0)
+        {
+            solvable.requires = 0;
+        }
+    }
+
+    /// Removes the constraints of all solvables with the given name, so the solver ignores them.
+    /// Must be called after all repos have been added to the pool.
+    pub fn clear_constraints(&self, name: StringId) {
+        let constrains = self
+            .find_interned_str(SOLVABLE_CONSTRAINS)
+            .expect("the constrains key is always interned");
+        let pool = self.as_ref();
+
+        // Safe because `pool.solvables` is the start of an array of length `pool.nsolvables`
+        let solvables =
+            unsafe { std::slice::from_raw_parts_mut(pool.solvables, pool.nsolvables as _) };
+
+        let mut repos = Vec::new();
+        for solvable in solvables
+            .iter_mut()
+            .filter(|solvable| solvable.name == name.0)
+        {
+            // Safe because the solvable belongs to this pool
+            unsafe { ffi::solvable_unset(&mut *solvable, constrains.0) };
+            if !repos.contains(&solvable.repo) {
+                repos.push(solvable.repo);
+            }
+        }
+
+        // The removals only take effect once the repos are internalized again
+        for repo in repos {
+            unsafe { ffi::repo_internalize(repo) };
+        }
+    }
+
     /// Interns a REL_EQ relation between `id1` and `id2`
     pub fn rel_eq(&self, id1: Id, id2: Id) -> Id {
         unsafe { ffi::pool_rel2id(self.raw_ptr(), id1, id2, ffi::REL_EQ as i32, 1) }
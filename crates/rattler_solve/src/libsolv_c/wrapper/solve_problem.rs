@@ -67,42 +67,55 @@ pub enum SolveProblem {
 }
 
 impl SolveProblem {
+    /// Constructs a problem from the information of a rule that is involved in a problem. Returns
+    /// `None` if the type of the rule is unknown or if information that is required for the type
+    /// is missing.
     pub fn from_raw(
         problem_type: ffi::SolverRuleinfo,
         dep: Option<String>,
         source: Option<SolvableId>,
         target: Option<SolvableId>,
-    ) -> Self {
-        match problem_type {
-            SOLVER_RULE_JOB => Self::Job { dep: dep.unwrap() },
-            SOLVER_RULE_JOB_NOTHING_PROVIDES_DEP => {
-                Self::JobNothingProvidesDep { dep: dep.unwrap() }
-            }
-            SOLVER_RULE_JOB_UNKNOWN_PACKAGE => Self::JobUnknownPackage { dep: dep.unwrap() },
-            SOLVER_RULE_PKG => Self::Pkg { dep: dep.unwrap() },
+    ) -> Option<Self> {
+        Some(match problem_type {
+            SOLVER_RULE_JOB => Self::Job { dep: dep? },
+            SOLVER_RULE_JOB_NOTHING_PROVIDES_DEP => Self::JobNothingProvidesDep { dep: dep? },
+            SOLVER_RULE_JOB_UNKNOWN_PACKAGE => Self::JobUnknownPackage { dep: dep? },
+            SOLVER_RULE_PKG => Self::Pkg { dep: dep? },
             SOLVER_RULE_SOLVER_RULE_PKG_CONFLICTS => Self::PkgConflicts {
-                source: source.unwrap(),
-                target: target.unwrap(),
+                source: source?,
+                target: target?,
             },
             SOLVER_RULE_PKG_CONSTRAINS => Self::PkgConstrains {
-                source: source.unwrap(),
-                target: target.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                target: target?,
+                dep: dep?,
             },
             SOLVER_RULE_SOLVER_RULE_PKG_NOTHING_PROVIDES_DEP => Self::PkgNothingProvidesDep {
-                source: source.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                dep: dep?,
             },
             SOLVER_RULE_PKG_REQUIRES => Self::PkgRequires {
-                source: source.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                dep: dep?,
             },
             SOLVER_RULE_SOLVER_RULE_PKG_SAME_NAME => Self::PkgSameName {
-                source: source.unwrap(),
-                target: target.unwrap(),
+                source: source?,
+                target: target?,
             },
             SOLVER_RULE_SOLVER_RULE_UPDATE => Self::Update,
-            _ => panic!("Unknown problem type: {}", problem_type),
+            _ => return None,
+        })
+    }
+
+    /// Returns the name of the package of a dependency that no package provides, or of a
+    /// constraint that excludes a package that would otherwise be installed.
+    pub fn conflicting_package_name(&self) -> Option<&str> {
+        match self {
+            Self::JobNothingProvidesDep { dep }
+            | Self::JobUnknownPackage { dep }
+            | Self::PkgNothingProvidesDep { dep, .. }
+            | Self::PkgConstrains { dep, .. } => dep.split_whitespace().next(),
+            _ => None,
         }
     }
 }
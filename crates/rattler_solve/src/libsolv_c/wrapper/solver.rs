@@ -105,7 +105,7 @@ impl Solver<'_> {
                     let source = if source_id < 0 || source_id >= nsolvables {
                         None
                     } else {
-                        Some(SolvableId(target_id))
+                        Some(SolvableId(source_id))
                     };
 
                     let dep = if dep_id == 0 {
@@ -117,7 +117,7 @@ impl Solver<'_> {
                         Some(dep)
                     };
 
-                    problems.push(SolveProblem::from_raw(problem_type, dep, source, target));
+                    problems.extend(SolveProblem::from_raw(problem_type, dep, source, target));
                 }
             }
         }
@@ -130,8 +130,12 @@ impl Solver<'_> {
     }
 
     /// Solves all the problems in the `queue` and returns a transaction from the found solution.
-    /// Returns an error if problems remain unsolved.
-    pub fn solve(&mut self, queue: &mut SolveGoal) -> Result<Transaction, Vec<String>> {
+    /// Returns a user-friendly description of each problem and the rules involved in the problems
+    /// if problems remain unsolved.
+    pub fn solve(
+        &mut self,
+        queue: &mut SolveGoal,
+    ) -> Result<Transaction, (Vec<String>, Vec<SolveProblem>)> {
         let result = unsafe {
             // Run the solve method
             ffi::solver_solve(self.raw_ptr(), queue.raw_ptr());
@@ -146,7 +150,7 @@ impl Solver<'_> {
             // Safe because we know the `transaction` ptr is valid
             Ok(unsafe { Transaction::new(self, transaction) })
         } else {
-            Err(self.solver_problems())
+            Err((self.solver_problems(), self.all_solver_problems()))
         }
     }
 }
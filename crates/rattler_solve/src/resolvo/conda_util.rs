@@ -1,54 +1,20 @@
 use crate::resolvo::{CondaDependencyProvider, SolverMatchSpec};
-use rattler_conda_types::Version;
-use resolvo::{SolvableId, SolverCache, VersionSetId};
+use rattler_conda_types::version_spec::{EqualityOperator, LogicalOperator, RangeOperator};
+use rattler_conda_types::{Version, VersionSpec};
+use resolvo::{NameId, SolvableId, SolverCache, VersionSet, VersionSetId};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
-/// Returns the order of two candidates based on the order used by conda.
-#[allow(clippy::too_many_arguments)]
-pub(super) fn compare_candidates<'a>(
+/// Compares the dependencies of two candidates. If there are similar dependencies the candidate
+/// that selects the highest version of the dependency is sorted first.
+pub(super) fn compare_dependencies<'a>(
     a: SolvableId,
     b: SolvableId,
     solver: &SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
-    match_spec_highest_version: &mut HashMap<
-        VersionSetId,
-        Option<(rattler_conda_types::Version, bool)>,
-    >,
+    highest_versions: &mut HighestVersionCache<'a>,
 ) -> Ordering {
     let pool = solver.pool();
 
-    let a_solvable = pool.resolve_solvable(a);
-    let b_solvable = pool.resolve_solvable(b);
-
-    let a_record = &a_solvable.inner();
-    let b_record = &b_solvable.inner();
-
-    // First compare by "tracked_features". If one of the packages has a tracked feature it is
-    // sorted below the one that doesn't have the tracked feature.
-    let a_has_tracked_features = !a_record.track_features().is_empty();
-    let b_has_tracked_features = !b_record.track_features().is_empty();
-    match a_has_tracked_features.cmp(&b_has_tracked_features) {
-        Ordering::Less => return Ordering::Less,
-        Ordering::Greater => return Ordering::Greater,
-        Ordering::Equal => {}
-    };
-
-    // Otherwise, select the variant with the highest version
-    match a_record.version().cmp(b_record.version()) {
-        Ordering::Less => return Ordering::Greater,
-        Ordering::Greater => return Ordering::Less,
-        Ordering::Equal => {}
-    };
-
-    // Otherwise, select the variant with the highest build number
-    match a_record.build_number().cmp(&b_record.build_number()) {
-        Ordering::Less => return Ordering::Greater,
-        Ordering::Greater => return Ordering::Less,
-        Ordering::Equal => {}
-    };
-
-    // Otherwise, compare the dependencies of the variants. If there are similar
-    // dependencies select the variant that selects the highest version of the dependency.
     let a_match_specs = solver
         .get_or_cache_dependencies(a)
         .requirements
@@ -75,8 +41,8 @@ pub(super) fn compare_candidates<'a>(
             }
 
             // Find which of the two specs selects the highest version
-            let highest_a = find_highest_version(a_spec_id, solver, match_spec_highest_version);
-            let highest_b = find_highest_version(*b_spec_id, solver, match_spec_highest_version);
+            let highest_a = highest_versions.find_highest_version(a_spec_id, solver);
+            let highest_b = highest_versions.find_highest_version(*b_spec_id, solver);
 
             // Skip version if no package is selected by either spec
             let (a_version, a_tracked_features, b_version, b_tracked_features) = if let (
@@ -102,7 +68,7 @@ pub(super) fn compare_candidates<'a>(
             }
 
             // Otherwise, down-weigh the version with the lowest selected version.
-            total_score += match a_version.cmp(&b_version) {
+            total_score += match a_version.cmp(b_version) {
                 Ordering::Less => 1,
                 Ordering::Equal => 0,
                 Ordering::Greater => -1,
@@ -111,46 +77,153 @@ pub(super) fn compare_candidates<'a>(
     }
 
     // If ranking the dependencies provides a score, use that for the sorting.
-    match total_score.cmp(&0) {
-        Ordering::Equal => {}
-        ord => return ord,
-    };
+    total_score.cmp(&0)
+}
+
+/// Caches the highest version of the candidates that match a spec, which is used to order
+/// candidates by their dependencies.
+#[derive(Default)]
+pub(super) struct HighestVersionCache<'a> {
+    /// The candidates of every package that was looked up, indexed by the name of the package.
+    packages: HashMap<NameId, PackageVersions>,
 
-    // Otherwise, order by timestamp
-    b_record.timestamp().cmp(&a_record.timestamp())
+    /// The highest version selected by a spec and whether the candidates track features.
+    highest_versions: HashMap<VersionSetId, Option<(&'a Version, bool)>>,
 }
 
-pub(super) fn find_highest_version<'a>(
-    match_spec_id: VersionSetId,
-    solver: &SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
-    match_spec_highest_version: &mut HashMap<
-        VersionSetId,
-        Option<(rattler_conda_types::Version, bool)>,
-    >,
-) -> Option<(Version, bool)> {
-    match_spec_highest_version
-        .entry(match_spec_id)
-        .or_insert_with(|| {
-            let candidates = solver.get_or_cache_matching_candidates(match_spec_id);
-            candidates
+/// The candidates of a single package, ordered by version.
+struct PackageVersions {
+    /// The candidates ordered from the highest to the lowest version. Candidates with the same
+    /// version keep their original order.
+    by_version: Vec<SolvableId>,
+
+    /// Whether any of the candidates tracks features.
+    tracks_features: bool,
+}
+
+impl PackageVersions {
+    fn new<'a>(
+        solver: &SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
+        name: NameId,
+    ) -> Self {
+        let pool = solver.pool();
+        let mut candidates = solver
+            .get_or_cache_candidates(name)
+            .candidates
+            .iter()
+            .map(|&id| (id, pool.resolve_solvable(id).inner()))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|(_, a), (_, b)| b.version().cmp(a.version()));
+        Self {
+            tracks_features: candidates
+                .iter()
+                .any(|(_, record)| !record.track_features().is_empty()),
+            by_version: candidates.into_iter().map(|(id, _)| id).collect(),
+        }
+    }
+}
+
+impl<'a> HighestVersionCache<'a> {
+    /// Returns the highest version of the candidates that match the spec and whether the
+    /// candidates that match the spec track features. Returns `None` if no candidate matches.
+    ///
+    /// The candidates of the package are sorted by version once. If the version of the spec has an
+    /// upper bound, the candidates above that bound are skipped with a binary search. The
+    /// remaining candidates are visited from the highest version down until the first one that
+    /// matches the spec, so candidates that only differ in their build are still visited one by
+    /// one. Only packages that have candidates which track features require visiting all matching
+    /// candidates.
+    pub(super) fn find_highest_version(
+        &mut self,
+        match_spec_id: VersionSetId,
+        solver: &SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
+    ) -> Option<(&'a Version, bool)> {
+        if let Some(highest) = self.highest_versions.get(&match_spec_id) {
+            return *highest;
+        }
+
+        let pool = solver.pool();
+        let name = pool.resolve_version_set_package_name(match_spec_id);
+        let package = self
+            .packages
+            .entry(name)
+            .or_insert_with(|| PackageVersions::new(solver, name));
+
+        let match_spec = pool.resolve_version_set(match_spec_id);
+        let start = match match_spec.version.as_ref().and_then(upper_bound) {
+            Some(bound) => package
+                .by_version
+                .partition_point(|&id| pool.resolve_solvable(id).inner().version() > bound),
+            None => 0,
+        };
+        let highest = package.by_version[start..]
+            .iter()
+            .map(|&id| pool.resolve_solvable(id).inner())
+            .find(|record| match_spec.contains(record))
+            .map(|record| record.version());
+
+        // The first matching candidate must track features and none of the others may.
+        let tracks_features = package.tracks_features && {
+            let mut matching = solver
+                .get_or_cache_matching_candidates(match_spec_id)
                 .iter()
-                .map(|id| solver.pool().resolve_solvable(*id).inner())
-                .fold(None, |init, record| {
-                    Some(init.map_or_else(
-                        || {
-                            (
-                                record.version().clone(),
-                                !record.track_features().is_empty(),
-                            )
-                        },
-                        |(version, has_tracked_features)| {
-                            (
-                                version.max(record.version().clone()),
-                                has_tracked_features && record.track_features().is_empty(),
-                            )
-                        },
-                    ))
-                })
-        })
-        .clone()
+                .map(|&id| pool.resolve_solvable(id).inner());
+            matching
+                .next()
+                .map_or(false, |first| !first.track_features().is_empty())
+                && matching.all(|record| record.track_features().is_empty())
+        };
+
+        let highest = highest.map(|version| (version, tracks_features));
+        self.highest_versions.insert(match_spec_id, highest);
+        highest
+    }
+}
+
+/// Returns a version that no version matching the spec is greater than, or `None` if the spec has
+/// no upper bound.
+fn upper_bound(spec: &VersionSpec) -> Option<&Version> {
+    match spec {
+        VersionSpec::Range(RangeOperator::Less | RangeOperator::LessEquals, limit)
+        | VersionSpec::Exact(EqualityOperator::Equals, limit) => Some(limit),
+        VersionSpec::Group(LogicalOperator::And, group) => {
+            group.iter().filter_map(upper_bound).min()
+        }
+        VersionSpec::Group(LogicalOperator::Or, group) => group
+            .iter()
+            .map(upper_bound)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::upper_bound;
+    use rattler_conda_types::{Version, VersionSpec};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_upper_bound() {
+        let bound = |spec: &str| {
+            upper_bound(&VersionSpec::from_str(spec).unwrap()).map(ToString::to_string)
+        };
+        assert_eq!(bound("<2"), Some(String::from("2")));
+        assert_eq!(bound("==1.2.3"), Some(String::from("1.2.3")));
+        assert_eq!(bound(">=1,<=1.5"), Some(String::from("1.5")));
+        assert_eq!(bound("<1|<=3"), Some(String::from("3")));
+        assert_eq!(bound("<1|>2"), None);
+        assert_eq!(bound(">=1"), None);
+        assert_eq!(bound("1.2.*"), None);
+
+        // Every version that matches the spec is at most the bound.
+        let spec = VersionSpec::from_str(">=1.0,<1.5|==2.0").unwrap();
+        let bound = upper_bound(&spec).unwrap();
+        for version in ["0.9", "1.0", "1.4.9", "1.5", "2.0", "2.0.1", "3"] {
+            let version = Version::from_str(version).unwrap();
+            assert!(!spec.matches(&version) || &version <= bound, "{version}");
+        }
+    }
 }
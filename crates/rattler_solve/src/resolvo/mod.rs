@@ -1,10 +1,16 @@
 //! Provides an solver implementation based on the [`resolvo`] crate.
 
-use crate::{IntoRepoData, SolveError, SolverRepoData, SolverTask};
+use crate::{
+    candidate_constraints, check_solution_policy, check_specs_policy, is_selectable,
+    virtual_packages::{describe_virtual_packages, is_virtual_package, matches_virtual_package},
+    ChannelPriority, DependencyModeFilter, IntoRepoData, Policy, SolveError, SolveStrategy,
+    SolverRepoData, SolverTask,
+};
+use chrono::{DateTime, Utc};
 use rattler_conda_types::package::ArchiveType;
 use rattler_conda_types::{
-    GenericVirtualPackage, MatchSpec, NamelessMatchSpec, PackageRecord, ParseMatchSpecError,
-    RepoDataRecord,
+    GenericVirtualPackage, MatchSpec, NamelessMatchSpec, PackageName, PackageRecord,
+    ParseMatchSpecError, RepoDataRecord,
 };
 use resolvo::{
     Candidates, Dependencies, DependencyProvider, NameId, Pool, SolvableDisplay, SolvableId,
@@ -13,16 +19,82 @@ use resolvo::{
 use std::{
     cell::RefCell,
     cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Display, Formatter},
     marker::PhantomData,
     ops::Deref,
+    rc::Rc,
     str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use itertools::Itertools;
+use typed_arena::Arena;
 
 mod conda_util;
+mod sort_strategy;
+
+use conda_util::HighestVersionCache;
+
+pub use sort_strategy::{Candidate, CondaSortStrategy, SortContext, SortStrategy};
+
+/// Contributes additional candidates for a package while solving, e.g. locally built packages
+/// that are not part of any channel yet or editable installs.
+///
+/// The provider is asked for the candidates of a package the first time the solver needs them.
+/// The returned records are merged with the candidates from the repodata. Like the records from
+/// the repodata, records that do not satisfy the constraints of the task are ignored.
+pub trait ExtraCandidateProvider: Send + Sync {
+    /// Returns the additional candidates for the package with the given name.
+    fn candidates(&self, name: &PackageName) -> Vec<RepoDataRecord>;
+}
+
+impl<F: Fn(&PackageName) -> Vec<RepoDataRecord> + Send + Sync> ExtraCandidateProvider for F {
+    fn candidates(&self, name: &PackageName) -> Vec<RepoDataRecord> {
+        self(name)
+    }
+}
+
+/// Counters that describe the work that was performed by a solve, useful to debug the performance
+/// of the solver. See [`Solver::stats`].
+///
+/// Only this backend records statistics, the `libsolv_c` backend does not expose any. The number
+/// of decisions and backtracks is not included because `resolvo` does not report them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SolveStats {
+    /// The number of records that were added to the solver, including the locked and pinned
+    /// records and the candidates of an [`ExtraCandidateProvider`]. Virtual packages and records
+    /// that are excluded by the constraints, the policy or the cutoff of the task are not counted.
+    pub records: usize,
+
+    /// The number of packages whose candidates were requested by the solver.
+    pub packages_considered: usize,
+
+    /// The number of records whose dependencies were requested by the solver.
+    pub dependencies_requested: usize,
+
+    /// The number of dependencies and constraints whose match spec was already parsed.
+    pub match_spec_cache_hits: usize,
+
+    /// The number of dependencies and constraints whose match spec had to be parsed.
+    pub match_spec_cache_misses: usize,
+
+    /// The time it took to add the records to the solver.
+    pub setup_duration: Duration,
+
+    /// The time it took to solve the task.
+    pub solve_duration: Duration,
+}
+
+impl SolveStats {
+    /// Returns the fraction of match specs that were served from the cache, or `None` if no
+    /// match specs were requested.
+    pub fn match_spec_cache_hit_rate(&self) -> Option<f64> {
+        let total = self.match_spec_cache_hits + self.match_spec_cache_misses;
+        (total > 0).then_some(self.match_spec_cache_hits as f64 / total as f64)
+    }
+}
 
 /// Represents the information required to load available packages into libsolv for a single channel
 /// and platform combination
@@ -110,8 +182,8 @@ enum SolverPackageRecord<'a> {
 }
 
 impl<'a> SolverPackageRecord<'a> {
-    fn version(&self) -> &rattler_conda_types::Version {
-        match self {
+    fn version(&self) -> &'a rattler_conda_types::Version {
+        match *self {
             SolverPackageRecord::Record(rec) => rec.package_record.version.version(),
             SolverPackageRecord::VirtualPackage(rec) => &rec.version,
         }
@@ -134,10 +206,17 @@ impl<'a> SolverPackageRecord<'a> {
 
     fn timestamp(&self) -> Option<&chrono::DateTime<chrono::Utc>> {
         match self {
-            SolverPackageRecord::Record(rec) => rec.package_record.timestamp.as_ref(),
+            SolverPackageRecord::Record(rec) => rec.package_record.timestamp.as_deref(),
             SolverPackageRecord::VirtualPackage(_rec) => None,
         }
     }
+
+    fn is_noarch(&self) -> bool {
+        match self {
+            SolverPackageRecord::Record(rec) => !rec.package_record.noarch.is_none(),
+            SolverPackageRecord::VirtualPackage(_rec) => false,
+        }
+    }
 }
 
 impl<'a> Display for SolverPackageRecord<'a> {
@@ -154,24 +233,67 @@ impl<'a> Display for SolverPackageRecord<'a> {
 }
 
 /// Dependency provider for conda
-#[derive(Default)]
 pub(crate) struct CondaDependencyProvider<'a> {
     pool: Pool<SolverMatchSpec<'a>, String>,
 
     records: HashMap<NameId, Candidates>,
 
-    matchspec_to_highest_version:
-        RefCell<HashMap<VersionSetId, Option<(rattler_conda_types::Version, bool)>>>,
+    highest_versions: RefCell<HighestVersionCache<'a>>,
 
     parse_match_spec_cache: RefCell<HashMap<&'a str, VersionSetId>>,
+
+    sort_strategy: Arc<dyn SortStrategy>,
+
+    prefer_arch_over_noarch: bool,
+
+    extra_candidates: ExtraCandidates<'a>,
+
+    /// The packages whose dependencies are not passed to the solver, see
+    /// [`crate::DependencyMode::NoDeps`].
+    ignored_dependencies: HashSet<PackageName>,
+
+    /// The packages whose constraints are not passed to the solver, see
+    /// [`crate::DependencyMode::OnlyDeps`].
+    ignored_constraints: HashSet<PackageName>,
+
+    /// Shared with the [`Solver`] so the counters can be read after the provider has been moved
+    /// into the solver.
+    stats: Rc<RefCell<SolveStats>>,
+}
+
+/// The state required to add the candidates of [`ExtraCandidateProvider`]s on demand.
+pub(crate) struct ExtraCandidates<'a> {
+    providers: Vec<Arc<dyn ExtraCandidateProvider>>,
+
+    /// Owns the records returned by the providers for as long as the solver references them.
+    arena: &'a Arena<RepoDataRecord>,
+
+    constraints: Vec<MatchSpec>,
+
+    policy: Policy,
+
+    /// Candidates that were published after this moment are never selected.
+    exclude_newer: Option<DateTime<Utc>>,
+
+    /// The candidates of the packages that the providers have been asked about, including the
+    /// candidates from the repodata.
+    merged: RefCell<HashMap<NameId, Option<Candidates>>>,
 }
 
 impl<'a> CondaDependencyProvider<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn from_solver_task(
         repodata: impl IntoIterator<Item = RepoData<'a>>,
         favored_records: &'a [RepoDataRecord],
         locked_records: &'a [RepoDataRecord],
         virtual_packages: &'a [GenericVirtualPackage],
+        constraints: &[MatchSpec],
+        policy: &Policy,
+        exclude_newer: Option<&DateTime<Utc>>,
+        channel_priority: ChannelPriority,
+        sort_strategy: Arc<dyn SortStrategy>,
+        prefer_arch_over_noarch: bool,
+        extra_candidates: ExtraCandidates<'a>,
     ) -> Self {
         let pool = Pool::default();
         let mut records: HashMap<NameId, Candidates> = HashMap::default();
@@ -184,6 +306,10 @@ impl<'a> CondaDependencyProvider<'a> {
             records.entry(name).or_default().candidates.push(solvable);
         }
 
+        // The rank of every channel in the order in which the channels are first encountered, a
+        // lower rank means a higher priority.
+        let mut channel_ranks: HashMap<&'a str, usize> = HashMap::default();
+
         // Add additional records
         for repo_datas in repodata {
             // Iterate over all records and dedup records that refer to the same package data but with
@@ -199,6 +325,12 @@ impl<'a> CondaDependencyProvider<'a> {
                 HashMap::with_capacity(repo_datas.records.len());
 
             for record in repo_datas.records {
+                // Records that do not satisfy the constraints or the policy, or that were published
+                // after the cutoff, can never be selected.
+                if !is_selectable(record, constraints, policy, exclude_newer) {
+                    continue;
+                }
+
                 let (file_name, archive_type) = ArchiveType::split_str(&record.file_name)
                     .unwrap_or((&record.file_name, ArchiveType::TarBz2));
                 match package_to_type.get_mut(file_name) {
@@ -231,6 +363,10 @@ impl<'a> CondaDependencyProvider<'a> {
             }
 
             for record in ordered_repodata {
+                let next_rank = channel_ranks.len();
+                channel_ranks
+                    .entry(record.channel.as_str())
+                    .or_insert(next_rank);
                 let package_name =
                     pool.intern_package_name(record.package_record.name.as_normalized());
                 let solvable_id =
@@ -241,8 +377,30 @@ impl<'a> CondaDependencyProvider<'a> {
             }
         }
 
+        // With a strict channel priority only the candidates from the channel with the highest
+        // priority that provides a package are considered.
+        if channel_priority == ChannelPriority::Strict {
+            let rank = |solvable: &SolvableId| match pool.resolve_solvable(*solvable).inner() {
+                SolverPackageRecord::Record(record) => channel_ranks[record.channel.as_str()],
+                SolverPackageRecord::VirtualPackage(_) => 0,
+            };
+            for candidates in records.values_mut() {
+                if let Some(highest_priority) = candidates.candidates.iter().map(rank).min() {
+                    candidates
+                        .candidates
+                        .retain(|solvable| rank(solvable) == highest_priority);
+                    candidates
+                        .hint_dependencies_available
+                        .retain(|solvable| rank(solvable) == highest_priority);
+                }
+            }
+        }
+
         // Add favored packages to the records
-        for favored_record in favored_records {
+        for favored_record in favored_records
+            .iter()
+            .filter(|record| is_selectable(record, constraints, policy, exclude_newer))
+        {
             let name = pool.intern_package_name(favored_record.package_record.name.as_normalized());
             let solvable = pool.intern_solvable(name, SolverPackageRecord::Record(favored_record));
             let mut candidates = records.entry(name).or_default();
@@ -258,12 +416,75 @@ impl<'a> CondaDependencyProvider<'a> {
             candidates.locked = Some(solvable);
         }
 
+        let stats = SolveStats {
+            records: records
+                .values()
+                .map(|candidates| candidates.candidates.len())
+                .sum::<usize>()
+                - virtual_packages.len(),
+            ..SolveStats::default()
+        };
+
+        let sort_strategy = match channel_priority {
+            ChannelPriority::Disabled => sort_strategy,
+            ChannelPriority::Strict | ChannelPriority::Flexible => {
+                Arc::new(sort_strategy::ChannelPrioritySortStrategy::new(
+                    channel_ranks
+                        .into_iter()
+                        .map(|(channel, rank)| (channel.to_owned(), rank))
+                        .collect(),
+                    sort_strategy,
+                ))
+            }
+        };
+
         Self {
             pool,
             records,
-            matchspec_to_highest_version: Default::default(),
+            highest_versions: Default::default(),
             parse_match_spec_cache: Default::default(),
+            sort_strategy,
+            prefer_arch_over_noarch,
+            extra_candidates,
+            ignored_dependencies: HashSet::default(),
+            ignored_constraints: HashSet::default(),
+            stats: Rc::new(RefCell::new(stats)),
+        }
+    }
+
+    /// Returns the candidates of the package from the repodata merged with the candidates from
+    /// the [`ExtraCandidateProvider`]s.
+    fn merged_candidates(&self, name: NameId) -> Option<Candidates> {
+        let extra = &self.extra_candidates;
+        if let Some(candidates) = extra.merged.borrow().get(&name) {
+            return candidates.clone();
+        }
+
+        let mut candidates = self.records.get(&name).cloned();
+        let package_name = PackageName::new_unchecked(self.pool.resolve_package_name(name));
+        for provider in &extra.providers {
+            for record in provider.candidates(&package_name) {
+                if !is_selectable(
+                    &record,
+                    &extra.constraints,
+                    &extra.policy,
+                    extra.exclude_newer.as_ref(),
+                ) {
+                    continue;
+                }
+                self.stats.borrow_mut().records += 1;
+                let record = &*extra.arena.alloc(record);
+                let solvable = self
+                    .pool
+                    .intern_solvable(name, SolverPackageRecord::Record(record));
+                let candidates = candidates.get_or_insert_with(Candidates::default);
+                candidates.candidates.push(solvable);
+                candidates.hint_dependencies_available.push(solvable);
+            }
         }
+
+        extra.merged.borrow_mut().insert(name, candidates.clone());
+        candidates
     }
 }
 
@@ -277,33 +498,63 @@ impl<'a> DependencyProvider<SolverMatchSpec<'a>> for CondaDependencyProvider<'a>
         solver: &SolverCache<SolverMatchSpec<'a>, String, Self>,
         solvables: &mut [SolvableId],
     ) {
-        let mut highest_version_spec = self.matchspec_to_highest_version.borrow_mut();
+        let mut highest_versions = self.highest_versions.borrow_mut();
+        let mut context =
+            SortContext::new(solver, &mut highest_versions, self.prefer_arch_over_noarch);
         solvables.sort_by(|&p1, &p2| {
-            conda_util::compare_candidates(p1, p2, solver, &mut highest_version_spec)
+            let a = Candidate::new(p1, self.pool.resolve_solvable(p1).inner());
+            let b = Candidate::new(p2, self.pool.resolve_solvable(p2).inner());
+            self.sort_strategy.compare(&a, &b, &mut context)
         });
     }
 
     fn get_candidates(&self, name: NameId) -> Option<Candidates> {
-        self.records.get(&name).cloned()
+        self.stats.borrow_mut().packages_considered += 1;
+        if self.extra_candidates.providers.is_empty() {
+            self.records.get(&name).cloned()
+        } else {
+            self.merged_candidates(name)
+        }
     }
 
     fn get_dependencies(&self, solvable: SolvableId) -> Dependencies {
-        let SolverPackageRecord::Record(rec) = self.pool.resolve_solvable(solvable).inner() else { return Dependencies::default() };
+        let SolverPackageRecord::Record(rec) = self.pool.resolve_solvable(solvable).inner() else {
+            return Dependencies::default();
+        };
 
         let mut parse_match_spec_cache = self.parse_match_spec_cache.borrow_mut();
+        let cached_match_specs = parse_match_spec_cache.len();
         let mut dependencies = Dependencies::default();
-        for depends in rec.package_record.depends.iter() {
+        let requirements: &[String] =
+            if self.ignored_dependencies.contains(&rec.package_record.name) {
+                &[]
+            } else {
+                &rec.package_record.depends
+            };
+        for depends in requirements.iter() {
             let version_set_id =
                 parse_match_spec(&self.pool, depends, &mut parse_match_spec_cache).unwrap();
             dependencies.requirements.push(version_set_id);
         }
 
-        for constrains in rec.package_record.constrains.iter() {
+        let constraints: &[String] = if self.ignored_constraints.contains(&rec.package_record.name)
+        {
+            &[]
+        } else {
+            &rec.package_record.constrains
+        };
+        for constrains in constraints.iter() {
             let version_set_id =
                 parse_match_spec(&self.pool, constrains, &mut parse_match_spec_cache).unwrap();
             dependencies.constrains.push(version_set_id);
         }
 
+        let mut stats = self.stats.borrow_mut();
+        let misses = parse_match_spec_cache.len() - cached_match_specs;
+        stats.dependencies_requested += 1;
+        stats.match_spec_cache_misses += misses;
+        stats.match_spec_cache_hits += requirements.len() + constraints.len() - misses;
+
         dependencies
     }
 }
@@ -326,9 +577,134 @@ impl SolvableDisplay<SolverMatchSpec<'_>> for CondaSolvableDisplay {
     }
 }
 
-/// A [`Solver`] implemented using the `resolvo` library
+/// A [`SolvableDisplay`] that displays candidates like [`CondaSolvableDisplay`] and records which
+/// candidates were displayed. When a problem is displayed, these are the candidates that are
+/// involved in the conflict.
 #[derive(Default)]
-pub struct Solver;
+struct RecordingSolvableDisplay {
+    displayed: RefCell<Vec<SolvableId>>,
+}
+
+impl SolvableDisplay<SolverMatchSpec<'_>> for RecordingSolvableDisplay {
+    fn display_candidates(
+        &self,
+        pool: &Pool<SolverMatchSpec, String>,
+        merged_candidates: &[SolvableId],
+    ) -> String {
+        self.displayed
+            .borrow_mut()
+            .extend_from_slice(merged_candidates);
+        CondaSolvableDisplay.display_candidates(pool, merged_candidates)
+    }
+}
+
+/// Returns the names of the virtual packages that are involved in a conflict: the virtual
+/// packages among the `involved` candidates, the virtual packages that are required by the root
+/// `specs` or by an involved candidate in a version that is not available, and the virtual
+/// packages whose available version is excluded by a constraint of an involved candidate.
+fn conflicting_virtual_packages(
+    pool: &Pool<SolverMatchSpec, String>,
+    involved: &[SolvableId],
+    specs: &[MatchSpec],
+    virtual_packages: &[GenericVirtualPackage],
+) -> Vec<PackageName> {
+    let virtual_specs = |specs: &[String]| {
+        specs
+            .iter()
+            .filter_map(|spec| MatchSpec::from_str(spec).ok())
+            .filter(|spec| {
+                spec.name
+                    .as_ref()
+                    .map_or(false, |name| is_virtual_package(name.as_normalized()))
+            })
+            .collect::<Vec<_>>()
+    };
+    let available = |spec: &MatchSpec| {
+        virtual_packages
+            .iter()
+            .filter(|package| spec.name.as_ref() == Some(&package.name))
+            .collect::<Vec<_>>()
+    };
+
+    let mut names = Vec::new();
+    let mut required = specs.to_vec();
+    let mut constrained = Vec::new();
+    for &id in involved {
+        match pool.resolve_solvable(id).inner() {
+            SolverPackageRecord::Record(rec) => {
+                required.extend(virtual_specs(&rec.package_record.depends));
+                constrained.extend(virtual_specs(&rec.package_record.constrains));
+            }
+            SolverPackageRecord::VirtualPackage(package) => names.push(package.name.clone()),
+        }
+    }
+
+    names.extend(
+        required
+            .into_iter()
+            .filter(|spec| {
+                !available(spec)
+                    .into_iter()
+                    .any(|package| matches_virtual_package(spec, package))
+            })
+            .filter_map(|spec| spec.name),
+    );
+    names.extend(
+        constrained
+            .into_iter()
+            .filter(|spec| {
+                available(spec)
+                    .into_iter()
+                    .any(|package| !matches_virtual_package(spec, package))
+            })
+            .filter_map(|spec| spec.name),
+    );
+    names
+}
+
+/// A [`Solver`] implemented using the `resolvo` library
+pub struct Solver {
+    sort_strategy: Arc<dyn SortStrategy>,
+    extra_candidate_providers: Vec<Arc<dyn ExtraCandidateProvider>>,
+    stats: Option<SolveStats>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self {
+            sort_strategy: Arc::new(CondaSortStrategy),
+            extra_candidate_providers: Vec::new(),
+            stats: None,
+        }
+    }
+}
+
+impl Solver {
+    /// Sets the strategy that is used to order the candidates of a package. Defaults to
+    /// [`CondaSortStrategy`].
+    pub fn with_sort_strategy(self, sort_strategy: impl SortStrategy + 'static) -> Self {
+        Self {
+            sort_strategy: Arc::new(sort_strategy),
+            ..self
+        }
+    }
+
+    /// Registers a provider that contributes additional candidates for packages while solving.
+    /// See [`ExtraCandidateProvider`].
+    pub fn with_extra_candidates(
+        mut self,
+        provider: impl ExtraCandidateProvider + 'static,
+    ) -> Self {
+        self.extra_candidate_providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Returns the statistics of the last solve, or `None` if nothing has been solved yet. The
+    /// statistics are also recorded when the task turned out to be unsolvable.
+    pub fn stats(&self) -> Option<&SolveStats> {
+        self.stats.as_ref()
+    }
+}
 
 impl super::SolverImpl for Solver {
     type RepoData<'a> = RepoData<'a>;
@@ -341,14 +717,56 @@ impl super::SolverImpl for Solver {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<Vec<RepoDataRecord>, SolveError> {
+        check_specs_policy(&task.specs, &task.policy)?;
+        let dependency_mode = DependencyModeFilter::new(&task);
+        let constraints = candidate_constraints(&task.specs, &task.constraints);
+        let virtual_specs = task
+            .specs
+            .iter()
+            .filter(|spec| {
+                spec.name
+                    .as_ref()
+                    .map_or(false, |name| is_virtual_package(name.as_normalized()))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
         // Construct a provider that can serve the data.
-        let provider = CondaDependencyProvider::from_solver_task(
+        let setup_start = Instant::now();
+        let extra_records = Arena::new();
+        let mut provider = CondaDependencyProvider::from_solver_task(
             task.available_packages.into_iter().map(|r| r.into()),
             &task.locked_packages,
             &task.pinned_packages,
             &task.virtual_packages,
+            &constraints,
+            &task.policy,
+            task.exclude_newer.as_ref(),
+            task.channel_priority,
+            match task.strategy {
+                SolveStrategy::Highest => self.sort_strategy.clone(),
+                SolveStrategy::LowestVersion => Arc::new(sort_strategy::LowestVersionSortStrategy(
+                    self.sort_strategy.clone(),
+                )),
+            },
+            task.prefer_arch_over_noarch,
+            ExtraCandidates {
+                providers: self.extra_candidate_providers.clone(),
+                arena: &extra_records,
+                constraints: constraints.clone(),
+                policy: task.policy.clone(),
+                exclude_newer: task.exclude_newer,
+                merged: Default::default(),
+            },
         );
 
+        provider
+            .ignored_dependencies
+            .extend(dependency_mode.ignored_dependencies().cloned());
+        provider
+            .ignored_constraints
+            .extend(dependency_mode.ignored_constraints().cloned());
+
         // Construct the requirements that the solver needs to satisfy.
         let root_requirements = task
             .specs
@@ -360,13 +778,32 @@ impl super::SolverImpl for Solver {
                 provider.pool.intern_version_set(name_id, spec.into())
             })
             .collect();
+        let stats = provider.stats.clone();
+        stats.borrow_mut().setup_duration = setup_start.elapsed();
 
         // Construct a solver and solve the problems in the queue
+        let solve_start = Instant::now();
         let mut solver = LibSolvRsSolver::new(provider);
-        let solvables = solver.solve(root_requirements).map_err(|problem| {
-            SolveError::Unsolvable(vec![problem
-                .display_user_friendly(&solver, &CondaSolvableDisplay)
-                .to_string()])
+        let result = solver.solve(root_requirements);
+        stats.borrow_mut().solve_duration = solve_start.elapsed();
+        let stats = stats.borrow().clone();
+        tracing::debug!("resolvo solve statistics: {stats:?}");
+        self.stats = Some(stats);
+
+        let solvables = result.map_err(|problem| {
+            let display = RecordingSolvableDisplay::default();
+            let mut problems = vec![problem.display_user_friendly(&solver, &display).to_string()];
+            let conflicting = conflicting_virtual_packages(
+                solver.pool(),
+                &display.displayed.into_inner(),
+                &virtual_specs,
+                &task.virtual_packages,
+            );
+            problems.extend(describe_virtual_packages(
+                conflicting.iter().map(PackageName::as_normalized),
+                &task.virtual_packages,
+            ));
+            SolveError::Unsolvable(problems)
         })?;
 
         // Get the resulting packages from the solver.
@@ -378,7 +815,9 @@ impl super::SolverImpl for Solver {
             })
             .collect();
 
-        Ok(required_records)
+        let solution = dependency_mode.apply(required_records);
+        check_solution_policy(&solution, &task.policy)?;
+        Ok(solution)
     }
 }
 
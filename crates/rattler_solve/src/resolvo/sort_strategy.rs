@@ -0,0 +1,262 @@
+use super::conda_util::{self, HighestVersionCache};
+use super::{CondaDependencyProvider, SolverMatchSpec, SolverPackageRecord};
+use rattler_conda_types::{GenericVirtualPackage, RepoDataRecord, Version};
+use resolvo::{SolvableId, SolverCache};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Defines the order in which the solver considers the candidates of a single package. The solver
+/// tries the candidates in order and selects the first one that satisfies all constraints.
+///
+/// The default strategy is [`CondaSortStrategy`] which mimics the order used by conda. A custom
+/// strategy can be used to experiment with a different order (e.g. to prefer the lowest versions
+/// or the oldest builds) without having to modify the solver.
+pub trait SortStrategy: Send + Sync {
+    /// Compares two candidates of the same package. Returning [`Ordering::Less`] means that `a`
+    /// is preferred over `b`.
+    fn compare(
+        &self,
+        a: &Candidate<'_>,
+        b: &Candidate<'_>,
+        context: &mut SortContext<'_, '_>,
+    ) -> Ordering;
+}
+
+/// A candidate of a package that is being sorted by a [`SortStrategy`]. A candidate is either a
+/// record from a channel or a virtual package.
+pub struct Candidate<'a> {
+    id: SolvableId,
+    record: &'a SolverPackageRecord<'a>,
+}
+
+impl<'a> Candidate<'a> {
+    pub(super) fn new(id: SolvableId, record: &'a SolverPackageRecord<'a>) -> Self {
+        Self { id, record }
+    }
+
+    /// Returns the record of this candidate or `None` if this candidate is a virtual package.
+    pub fn record(&self) -> Option<&'a RepoDataRecord> {
+        match self.record {
+            SolverPackageRecord::Record(rec) => Some(*rec),
+            SolverPackageRecord::VirtualPackage(_) => None,
+        }
+    }
+
+    /// Returns the virtual package of this candidate or `None` if this candidate is a record.
+    pub fn virtual_package(&self) -> Option<&'a GenericVirtualPackage> {
+        match self.record {
+            SolverPackageRecord::Record(_) => None,
+            SolverPackageRecord::VirtualPackage(pkg) => Some(*pkg),
+        }
+    }
+
+    /// Returns the version of the candidate.
+    pub fn version(&self) -> &Version {
+        self.record.version()
+    }
+
+    /// Returns the build number of the candidate. Virtual packages always have build number 0.
+    pub fn build_number(&self) -> u64 {
+        self.record.build_number()
+    }
+
+    /// Returns the features tracked by the candidate.
+    pub fn track_features(&self) -> &[String] {
+        self.record.track_features()
+    }
+
+    /// Returns the time at which the candidate was built, if known.
+    pub fn timestamp(&self) -> Option<&chrono::DateTime<chrono::Utc>> {
+        self.record.timestamp()
+    }
+
+    /// Returns true if the candidate is a `noarch` package.
+    pub fn is_noarch(&self) -> bool {
+        self.record.is_noarch()
+    }
+}
+
+/// Provides access to information of the solver that is not available on the candidates
+/// themselves, like the candidates of their dependencies.
+pub struct SortContext<'s, 'a> {
+    solver: &'s SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
+    highest_versions: &'s mut HighestVersionCache<'a>,
+    prefer_arch_over_noarch: bool,
+}
+
+impl<'s, 'a> SortContext<'s, 'a> {
+    pub(super) fn new(
+        solver: &'s SolverCache<SolverMatchSpec<'a>, String, CondaDependencyProvider<'a>>,
+        highest_versions: &'s mut HighestVersionCache<'a>,
+        prefer_arch_over_noarch: bool,
+    ) -> Self {
+        Self {
+            solver,
+            highest_versions,
+            prefer_arch_over_noarch,
+        }
+    }
+
+    /// Returns true if [`crate::SolverTask::prefer_arch_over_noarch`] is set for the current
+    /// solve.
+    pub fn prefer_arch_over_noarch(&self) -> bool {
+        self.prefer_arch_over_noarch
+    }
+
+    /// Compares the dependencies of two candidates. If both candidates depend on the same package
+    /// with a different spec, the candidate whose spec selects the highest version of that
+    /// dependency is preferred. Dependencies that only select packages with tracked features are
+    /// weighed down.
+    pub fn compare_dependencies(&mut self, a: &Candidate<'_>, b: &Candidate<'_>) -> Ordering {
+        conda_util::compare_dependencies(a.id, b.id, self.solver, self.highest_versions)
+    }
+}
+
+/// The default [`SortStrategy`] which orders candidates the same way conda does.
+///
+/// Candidates are ordered by (in order of importance):
+///
+/// 1. Whether they track features (candidates without tracked features first)
+/// 2. Their version (highest first)
+/// 3. Optionally, whether they are `noarch` (architecture specific candidates first)
+/// 4. Their build number (highest first)
+/// 5. The versions selected by their dependencies (highest first)
+/// 6. Their timestamp (most recent first)
+///
+/// The preference for architecture specific candidates is enabled with
+/// [`crate::SolverTask::prefer_arch_over_noarch`].
+#[derive(Debug, Default, Clone)]
+pub struct CondaSortStrategy;
+
+impl SortStrategy for CondaSortStrategy {
+    fn compare(
+        &self,
+        a: &Candidate<'_>,
+        b: &Candidate<'_>,
+        context: &mut SortContext<'_, '_>,
+    ) -> Ordering {
+        // First compare by "tracked_features". If one of the packages has a tracked feature it is
+        // sorted below the one that doesn't have the tracked feature.
+        let a_has_tracked_features = !a.track_features().is_empty();
+        let b_has_tracked_features = !b.track_features().is_empty();
+        match a_has_tracked_features.cmp(&b_has_tracked_features) {
+            Ordering::Less => return Ordering::Less,
+            Ordering::Greater => return Ordering::Greater,
+            Ordering::Equal => {}
+        };
+
+        // Otherwise, select the variant with the highest version
+        match a.version().cmp(b.version()) {
+            Ordering::Less => return Ordering::Greater,
+            Ordering::Greater => return Ordering::Less,
+            Ordering::Equal => {}
+        };
+
+        // Otherwise, if requested, select the architecture specific variant over the noarch
+        // variant.
+        if context.prefer_arch_over_noarch() {
+            match a.is_noarch().cmp(&b.is_noarch()) {
+                Ordering::Less => return Ordering::Less,
+                Ordering::Greater => return Ordering::Greater,
+                Ordering::Equal => {}
+            };
+        }
+
+        // Otherwise, select the variant with the highest build number
+        match a.build_number().cmp(&b.build_number()) {
+            Ordering::Less => return Ordering::Greater,
+            Ordering::Greater => return Ordering::Less,
+            Ordering::Equal => {}
+        };
+
+        // Otherwise, compare the dependencies of the variants. If there are similar
+        // dependencies select the variant that selects the highest version of the dependency.
+        match context.compare_dependencies(a, b) {
+            Ordering::Equal => {}
+            ord => return ord,
+        };
+
+        // Otherwise, order by timestamp
+        b.timestamp().cmp(&a.timestamp())
+    }
+}
+
+/// A [`SortStrategy`] that prefers the lowest version of a package. Candidates with the same
+/// version are ordered by the wrapped strategy. This is used to implement
+/// [`crate::SolveStrategy::LowestVersion`].
+pub(super) struct LowestVersionSortStrategy(pub Arc<dyn SortStrategy>);
+
+impl SortStrategy for LowestVersionSortStrategy {
+    fn compare(
+        &self,
+        a: &Candidate<'_>,
+        b: &Candidate<'_>,
+        context: &mut SortContext<'_, '_>,
+    ) -> Ordering {
+        // Candidates that track features are still sorted below the ones that dont.
+        let a_has_tracked_features = !a.track_features().is_empty();
+        let b_has_tracked_features = !b.track_features().is_empty();
+        match a_has_tracked_features.cmp(&b_has_tracked_features) {
+            Ordering::Equal => {}
+            ord => return ord,
+        };
+
+        // Otherwise, select the variant with the lowest version
+        match a.version().cmp(b.version()) {
+            Ordering::Equal => self.0.compare(a, b, context),
+            ord => ord,
+        }
+    }
+}
+
+/// A [`SortStrategy`] that prefers candidates from channels with a higher priority. Candidates
+/// from the same channel are ordered by the wrapped strategy. This is used to implement
+/// [`crate::ChannelPriority::Strict`] and [`crate::ChannelPriority::Flexible`].
+pub(super) struct ChannelPrioritySortStrategy {
+    /// The rank of every channel, a lower rank means a higher priority.
+    channel_ranks: HashMap<String, usize>,
+    inner: Arc<dyn SortStrategy>,
+}
+
+impl ChannelPrioritySortStrategy {
+    pub fn new(channel_ranks: HashMap<String, usize>, inner: Arc<dyn SortStrategy>) -> Self {
+        Self {
+            channel_ranks,
+            inner,
+        }
+    }
+
+    /// Returns the rank of the channel of the candidate. Candidates from unknown channels (e.g.
+    /// from an [`super::ExtraCandidateProvider`]) have the lowest priority.
+    fn rank(&self, candidate: &Candidate<'_>) -> usize {
+        candidate
+            .record()
+            .and_then(|record| self.channel_ranks.get(&record.channel))
+            .copied()
+            .unwrap_or(usize::MAX)
+    }
+}
+
+impl SortStrategy for ChannelPrioritySortStrategy {
+    fn compare(
+        &self,
+        a: &Candidate<'_>,
+        b: &Candidate<'_>,
+        context: &mut SortContext<'_, '_>,
+    ) -> Ordering {
+        // Like conda, candidates that track features are still sorted below the ones that dont.
+        let a_has_tracked_features = !a.track_features().is_empty();
+        let b_has_tracked_features = !b.track_features().is_empty();
+        match a_has_tracked_features.cmp(&b_has_tracked_features) {
+            Ordering::Equal => {}
+            ord => return ord,
+        };
+
+        // Otherwise, select the variant from the channel with the highest priority
+        match self.rank(a).cmp(&self.rank(b)) {
+            Ordering::Equal => self.inner.compare(a, b, context),
+            ord => ord,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Provides [`UpdateRequest`] which determines how an existing environment should be re-solved to
+//! update its packages.
+//!
+//! The specs of the environment are the specs with which packages were requested by the user
+//! (stored as [`PrefixRecord::requested_spec`]). Updating re-solves these specs. Packages that are
+//! installed but that are not required by any of the specs anymore are kept unless the update
+//! prunes them.
+
+use rattler_conda_types::{
+    MatchSpec, PackageName, ParseMatchSpecError, PrefixRecord, RepoDataRecord,
+};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Describes which packages of an environment to update.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateRequest {
+    /// The names of the packages to update. Other packages are only updated if that is required to
+    /// update these packages. Ignored if [`Self::all`] is true.
+    pub packages: Vec<PackageName>,
+
+    /// Update all packages in the environment to the latest version that satisfies the specs.
+    pub all: bool,
+
+    /// Remove the packages that are no longer required by any of the specs of the environment.
+    pub prune: bool,
+}
+
+/// The specs and locked packages with which to solve an update. Use these to construct a
+/// [`crate::SolverTask`].
+#[derive(Debug, Clone, Default)]
+pub struct UpdateSpecs {
+    /// The specs to solve.
+    pub specs: Vec<MatchSpec>,
+
+    /// The installed packages that should preferably not change.
+    pub locked_packages: Vec<RepoDataRecord>,
+}
+
+/// An error that can occur when determining the specs of an update.
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateError {
+    /// The requested spec of an installed package is invalid.
+    #[error("invalid requested spec '{0}'")]
+    InvalidRequestedSpec(String, #[source] ParseMatchSpecError),
+
+    /// A package that should be updated is not installed.
+    #[error("package '{}' is not installed", .0.as_source())]
+    PackageNotInstalled(PackageName),
+}
+
+impl UpdateRequest {
+    /// Constructs a request that updates all packages in the environment.
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            ..Self::default()
+        }
+    }
+
+    /// Sets whether packages that are no longer required are removed.
+    pub fn with_prune(self, prune: bool) -> Self {
+        Self { prune, ..self }
+    }
+
+    /// Determines the specs and locked packages to update the environment that consists of the
+    /// `installed` packages.
+    ///
+    /// If none of the installed packages has a requested spec (e.g. because the environment was
+    /// not created by a tool that records them) every installed package is treated as if it was
+    /// requested by name.
+    pub fn specs(&self, installed: &[PrefixRecord]) -> Result<UpdateSpecs, UpdateError> {
+        let mut specs = if installed.iter().any(|r| r.requested_spec.is_some()) {
+            installed
+                .iter()
+                .filter_map(|record| record.requested_spec.as_deref())
+                .map(|spec| {
+                    MatchSpec::from_str(spec)
+                        .map_err(|e| UpdateError::InvalidRequestedSpec(spec.to_owned(), e))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            installed
+                .iter()
+                .map(|record| name_spec(&record.repodata_record.package_record.name))
+                .collect()
+        };
+
+        // Make sure the packages to update are part of the specs.
+        for name in &self.packages {
+            if !installed
+                .iter()
+                .any(|record| &record.repodata_record.package_record.name == name)
+            {
+                return Err(UpdateError::PackageNotInstalled(name.clone()));
+            }
+            if !specs.iter().any(|spec| spec.name.as_ref() == Some(name)) {
+                specs.push(name_spec(name));
+            }
+        }
+
+        // Unless the environment is pruned, keep all installed packages by adding them to the
+        // specs.
+        if !self.prune {
+            let names = specs
+                .iter()
+                .filter_map(|spec| spec.name.clone())
+                .collect::<HashSet<_>>();
+            specs.extend(
+                installed
+                    .iter()
+                    .map(|record| &record.repodata_record.package_record.name)
+                    .filter(|name| !names.contains(name))
+                    .map(name_spec),
+            );
+        }
+
+        // Lock the installed packages that are not being updated.
+        let locked_packages = if self.all {
+            Vec::new()
+        } else {
+            installed
+                .iter()
+                .filter(|record| {
+                    !self
+                        .packages
+                        .contains(&record.repodata_record.package_record.name)
+                })
+                .map(|record| record.repodata_record.clone())
+                .collect()
+        };
+
+        Ok(UpdateSpecs {
+            specs,
+            locked_packages,
+        })
+    }
+}
+
+/// Returns a spec that matches any package with the given name.
+fn name_spec(name: &PackageName) -> MatchSpec {
+    MatchSpec {
+        name: Some(name.clone()),
+        ..MatchSpec::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UpdateRequest;
+    use rattler_conda_types::{PackageName, PackageRecord, PrefixRecord, RepoDataRecord, Version};
+    use std::str::FromStr;
+
+    fn installed(name: &str, requested_spec: Option<&str>) -> PrefixRecord {
+        PrefixRecord {
+            repodata_record: RepoDataRecord {
+                package_record: PackageRecord::new(
+                    PackageName::new_unchecked(name),
+                    Version::from_str("1.0").unwrap(),
+                    String::from("0"),
+                ),
+                file_name: format!("{name}-1.0-0.tar.bz2"),
+                url: url::Url::parse("https://conda.anaconda.org/conda-forge/noarch/").unwrap(),
+                channel: String::from("conda-forge"),
+            },
+            package_tarball_full_path: None,
+            extracted_package_dir: None,
+            files: Vec::new(),
+            paths_data: Default::default(),
+            link: None,
+            requested_spec: requested_spec.map(ToOwned::to_owned),
+        }
+    }
+
+    fn spec_strings(request: &UpdateRequest, installed: &[PrefixRecord]) -> Vec<String> {
+        let specs = request.specs(installed).unwrap().specs;
+        specs.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_update_specs() {
+        let installed = [
+            installed("python", Some("python 3.11.*")),
+            installed("numpy", None),
+            installed("requests", Some("requests")),
+        ];
+
+        // By default the packages that are no longer required are kept.
+        assert_eq!(
+            spec_strings(&UpdateRequest::default(), &installed),
+            ["python 3.11.*", "requests", "numpy"]
+        );
+        assert_eq!(
+            spec_strings(&UpdateRequest::all().with_prune(true), &installed),
+            ["python 3.11.*", "requests"]
+        );
+
+        // Updating a package unlocks it.
+        let request = UpdateRequest {
+            packages: vec![PackageName::new_unchecked("numpy")],
+            ..UpdateRequest::default()
+        };
+        let update = request.specs(&installed).unwrap();
+        assert_eq!(update.locked_packages.len(), 2);
+        assert!(UpdateRequest::all()
+            .specs(&installed)
+            .unwrap()
+            .locked_packages
+            .is_empty());
+
+        // Packages that are not installed cannot be updated.
+        let request = UpdateRequest {
+            packages: vec![PackageName::new_unchecked("scipy")],
+            ..UpdateRequest::default()
+        };
+        assert!(request.specs(&installed).is_err());
+    }
+
+    #[test]
+    fn test_update_without_requested_specs() {
+        let installed = [installed("python", None), installed("numpy", None)];
+        assert_eq!(
+            spec_strings(&UpdateRequest::all().with_prune(true), &installed),
+            ["python", "numpy"]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Helpers to make solver errors that involve virtual packages easier to understand.
+
+use itertools::Itertools;
+use rattler_conda_types::{GenericVirtualPackage, MatchSpec};
+
+/// Returns a human readable description of the virtual packages with the given `names`. The
+/// backends determine the names from the problems reported by the solver, names of regular
+/// packages are ignored.
+///
+/// Virtual packages (e.g. `__glibc`) describe properties of the system and are not installed from
+/// a channel. Solver errors like "`__glibc >=2.28` cannot be installed" are therefore confusing.
+/// For each virtual package this returns what the virtual package represents, which value was
+/// detected on the system and how the value can be overridden.
+pub(crate) fn describe_virtual_packages<'n>(
+    names: impl IntoIterator<Item = &'n str>,
+    virtual_packages: &[GenericVirtualPackage],
+) -> Vec<String> {
+    names
+        .into_iter()
+        .filter(|name| is_virtual_package(name))
+        .unique()
+        .map(|name| describe_virtual_package(name, virtual_packages))
+        .collect()
+}
+
+/// Returns true if the package with the given name is a virtual package.
+pub(crate) fn is_virtual_package(name: &str) -> bool {
+    name.starts_with("__") && name.len() > 2
+}
+
+/// Returns true if the virtual `package` satisfies the `spec`. The name of the spec is not
+/// checked.
+pub(crate) fn matches_virtual_package(spec: &MatchSpec, package: &GenericVirtualPackage) -> bool {
+    spec.version
+        .as_ref()
+        .map_or(true, |version| version.matches(&package.version))
+        && spec
+            .build
+            .as_ref()
+            .map_or(true, |build| build.matches(&package.build_string))
+}
+
+/// Describes a single virtual package.
+fn describe_virtual_package(name: &str, virtual_packages: &[GenericVirtualPackage]) -> String {
+    // The variables are read by `rattler_virtual_packages` when it detects the virtual packages.
+    let (description, override_env_var) = match name {
+        "__glibc" => (
+            "the version of glibc available on the system",
+            Some("GLIBC"),
+        ),
+        "__cuda" => (
+            "the maximum CUDA version supported by the installed driver",
+            Some("CUDA"),
+        ),
+        "__osx" => ("the version of macOS", Some("OSX")),
+        "__linux" => ("the version of the Linux kernel", Some("LINUX")),
+        "__archspec" => ("the microarchitecture of the CPU", Some("ARCHSPEC")),
+        "__unix" => ("that the system is a unix based system", None),
+        "__win" => ("that the system is a Windows system", None),
+        _ => ("a property of the system", None),
+    };
+
+    let detected = match virtual_packages
+        .iter()
+        .find(|package| package.name.as_normalized() == name)
+    {
+        Some(package) => format!("the detected version is {}", &package.version),
+        None => String::from("it was not detected on this system"),
+    };
+
+    match override_env_var {
+        Some(var) => format!(
+            "{name} is a virtual package that represents {description}, {detected} (this can be overridden with the CONDA_OVERRIDE_{var} environment variable)"
+        ),
+        None => format!("{name} is a virtual package that represents {description}, {detected}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe_virtual_packages, matches_virtual_package};
+    use rattler_conda_types::{GenericVirtualPackage, MatchSpec, PackageName, Version};
+    use std::str::FromStr;
+
+    fn glibc(version: &str) -> GenericVirtualPackage {
+        GenericVirtualPackage {
+            name: PackageName::new_unchecked("__glibc"),
+            version: Version::from_str(version).unwrap(),
+            build_string: String::from("0"),
+        }
+    }
+
+    #[test]
+    fn test_describe_virtual_packages() {
+        let descriptions =
+            describe_virtual_packages(["foo", "__glibc", "__cuda", "__glibc"], &[glibc("2.17")]);
+
+        assert_eq!(
+            descriptions,
+            vec![
+                "__glibc is a virtual package that represents the version of glibc available on the system, the detected version is 2.17 (this can be overridden with the CONDA_OVERRIDE_GLIBC environment variable)",
+                "__cuda is a virtual package that represents the maximum CUDA version supported by the installed driver, it was not detected on this system (this can be overridden with the CONDA_OVERRIDE_CUDA environment variable)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_virtual_packages() {
+        assert!(describe_virtual_packages(["foo", "__"], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_matches_virtual_package() {
+        let spec = |spec| MatchSpec::from_str(spec).unwrap();
+        assert!(matches_virtual_package(&spec("__glibc"), &glibc("2.17")));
+        assert!(matches_virtual_package(
+            &spec("__glibc >=2.17"),
+            &glibc("2.28")
+        ));
+        assert!(!matches_virtual_package(
+            &spec("__glibc >=2.28"),
+            &glibc("2.17")
+        ));
+        assert!(!matches_virtual_package(
+            &spec("__glibc >=2.17 1"),
+            &glibc("2.28")
+        ));
+    }
+}
@@ -0,0 +1,655 @@
+//! Provides [`why_not`] which explains why a specific candidate record was not selected by a
+//! solver.
+
+use crate::{is_excluded_newer, IntoRepoData, PolicyViolation, SolveError, SolverImpl, SolverTask};
+use rattler_conda_types::{MatchSpec, PackageName, RepoDataRecord};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The depth up to which the candidates of a conflicting requirement are explained. Deeper
+/// conflicts are reported without the candidates of the requirement.
+const MAX_DEPTH: usize = 3;
+
+/// The maximum number of candidates of a conflicting requirement that are explained. The highest
+/// versions are explained first.
+const MAX_CANDIDATES: usize = 3;
+
+/// The maximum number of solves that are performed to explain why a candidate was not selected,
+/// including the solves of the task itself. Every solve takes about as long as solving the task.
+const MAX_SOLVES: usize = 100;
+
+/// Describes why a specific candidate record was (not) selected by a solver. See [`why_not`].
+///
+/// The [`Display`] implementation formats the explanation as a human readable tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhyNot {
+    /// The candidate was actually selected by the solver.
+    Selected,
+
+    /// No package with the name of the candidate is part of the solution. The candidate is not
+    /// required by any of the specs.
+    NotRequired,
+
+    /// Another variant of the package is pinned, which excludes the candidate.
+    ExcludedByPin(Box<RepoDataRecord>),
+
+    /// The candidate does not satisfy one of the constraints of the task.
+    ExcludedByConstraint(Box<MatchSpec>),
+
+    /// The candidate is not allowed by the policy of the task.
+    ExcludedByPolicy(PolicyViolation),
+
+    /// The candidate was published after the [`SolverTask::exclude_newer`] cutoff of the task.
+    PublishedLater,
+
+    /// Selecting the candidate conflicts with the requirements. Contains the reasons why, see
+    /// [`Conflict`].
+    Conflict(Vec<Conflict>),
+
+    /// The candidate could have been selected but the solver preferred another variant of the
+    /// package. This happens when another variant has a higher version or build number, or comes
+    /// from a channel with a higher priority.
+    NotPreferred(Box<RepoDataRecord>),
+}
+
+/// A reason why a record conflicts with the requirements of a task.
+///
+/// The conflicts form a tree. When a spec of the task or a dependency of the record cannot be
+/// satisfied, the conflict contains the candidates of that spec together with the reasons why
+/// they conflict in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// A spec of the task for the same package is not satisfied by the record.
+    Spec(MatchSpec),
+
+    /// Another variant of the package is pinned.
+    Pin(Box<RepoDataRecord>),
+
+    /// The record does not satisfy one of the constraints of the task.
+    Constraint(MatchSpec),
+
+    /// The record is not allowed by the policy of the task.
+    Policy(PolicyViolation),
+
+    /// The record was published after the [`SolverTask::exclude_newer`] cutoff of the task.
+    PublishedLater,
+
+    /// The record constrains another package in a way that conflicts with the requirements.
+    Constrains(MatchSpec),
+
+    /// None of the available packages satisfies a dependency of the record.
+    MissingDependency(MatchSpec),
+
+    /// A dependency of the record cannot be satisfied together with the requirements.
+    Dependency(CandidateConflicts),
+
+    /// A spec of the task for another package cannot be satisfied together with the record.
+    Requirement(CandidateConflicts),
+
+    /// The conflict could not be attributed to a single requirement, for instance because it is
+    /// caused by a combination of dependencies.
+    Unexplained,
+
+    /// The remaining requirements were not examined because the maximum number of solves was
+    /// reached, see [`why_not`].
+    NotExamined,
+}
+
+/// The candidates of a spec that cannot be satisfied, see [`Conflict::Dependency`] and
+/// [`Conflict::Requirement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateConflicts {
+    /// The spec that cannot be satisfied.
+    pub spec: MatchSpec,
+
+    /// The candidates that satisfy the spec together with the reasons why they conflict, the
+    /// highest versions first.
+    pub candidates: Vec<(RepoDataRecord, Vec<Conflict>)>,
+
+    /// The number of candidates that satisfy the spec but are not explained in `candidates`, to
+    /// keep the explanation short or because the maximum number of solves was reached.
+    pub omitted: usize,
+}
+
+/// The requirements against which a record is explained. These start out as the requirements of
+/// the task, the records that are being explained are pinned on top of them.
+#[derive(Clone)]
+struct Requirements {
+    specs: Vec<MatchSpec>,
+    constraints: Vec<MatchSpec>,
+    pinned_packages: Vec<RepoDataRecord>,
+}
+
+impl Requirements {
+    /// Returns these requirements with the `record` pinned and required.
+    fn with_record(&self, record: &RepoDataRecord) -> Self {
+        let mut requirements = self.clone();
+        requirements.pinned_packages.push(record.clone());
+        requirements.specs.push(MatchSpec {
+            name: Some(record.package_record.name.clone()),
+            ..MatchSpec::default()
+        });
+        requirements
+    }
+
+    /// Returns true if a package with the given name is pinned.
+    fn is_pinned(&self, name: &PackageName) -> bool {
+        self.pinned_packages
+            .iter()
+            .any(|pinned| &pinned.package_record.name == name)
+    }
+}
+
+/// Explains why the solver did not select the `candidate` record when solving `task`.
+///
+/// This first solves the task as is. If the candidate is not part of the solution, the task is
+/// solved again with the candidate pinned. If that succeeds, the solver simply preferred another
+/// variant of the package. Otherwise, the task is solved with each of the dependencies and
+/// constraints of the candidate, and with each of the specs of the task on its own, to find the
+/// requirements that conflict with the candidate. The candidates of those requirements are
+/// explained in the same way.
+///
+/// This is expensive: every requirement that is examined costs another solve of the task, and the
+/// candidates of conflicting requirements are examined recursively. To bound the cost, at most
+/// 100 solves are performed in total. Requirements that are not examined because of this are
+/// reported as [`Conflict::NotExamined`], candidates are counted in
+/// [`CandidateConflicts::omitted`]. Independently, only the 3 highest candidates of a requirement
+/// are explained, up to a depth of 3 requirements.
+pub fn why_not<'a, S, R, TAvailablePackagesIterator>(
+    solver: &mut S,
+    task: SolverTask<TAvailablePackagesIterator>,
+    candidate: &RepoDataRecord,
+) -> Result<WhyNot, SolveError>
+where
+    S: SolverImpl,
+    R: IntoRepoData<'a, S::RepoData<'a>> + AsRef<[RepoDataRecord]>,
+    TAvailablePackagesIterator: IntoIterator<Item = R> + Clone,
+{
+    let name = &candidate.package_record.name;
+    let requirements = Requirements {
+        specs: task.specs.clone(),
+        constraints: task.constraints.clone(),
+        pinned_packages: task.pinned_packages.clone(),
+    };
+
+    // Candidates that are excluded by the task itself are never selected.
+    match direct_conflicts(&task, &requirements, candidate)
+        .into_iter()
+        .next()
+    {
+        Some(Conflict::Pin(pinned)) => return Ok(WhyNot::ExcludedByPin(pinned)),
+        Some(Conflict::Constraint(constraint)) => {
+            return Ok(WhyNot::ExcludedByConstraint(Box::new(constraint)))
+        }
+        Some(Conflict::Policy(violation)) => return Ok(WhyNot::ExcludedByPolicy(violation)),
+        Some(Conflict::PublishedLater) => return Ok(WhyNot::PublishedLater),
+        _ => {}
+    }
+
+    // Solve the task as is to find out what is selected instead.
+    let mut explainer = Explainer {
+        solver,
+        task: &task,
+        remaining_solves: MAX_SOLVES,
+    };
+    let solution = explainer.solve(requirements.clone())?;
+    let selected = match solution
+        .into_iter()
+        .find(|record| &record.package_record.name == name)
+    {
+        Some(record) if &record == candidate => return Ok(WhyNot::Selected),
+        Some(record) => record,
+        None => return Ok(WhyNot::NotRequired),
+    };
+
+    // Solve again but this time force the solver to select the candidate.
+    let mut pinned_requirements = requirements.clone();
+    pinned_requirements.pinned_packages.push(candidate.clone());
+    if !is_unsolvable(explainer.solve(pinned_requirements))? {
+        return Ok(WhyNot::NotPreferred(Box::new(selected)));
+    }
+
+    let conflicts = explainer.find_conflicts(&requirements, candidate, 0)?;
+    Ok(WhyNot::Conflict(if conflicts.is_empty() {
+        vec![Conflict::Unexplained]
+    } else {
+        conflicts
+    }))
+}
+
+/// Returns the conflicts between the `record` and the requirements that do not require solving.
+fn direct_conflicts<T>(
+    task: &SolverTask<T>,
+    requirements: &Requirements,
+    record: &RepoDataRecord,
+) -> Vec<Conflict> {
+    let name = &record.package_record.name;
+    let mut conflicts = Vec::new();
+
+    if let Some(pinned) = requirements
+        .pinned_packages
+        .iter()
+        .find(|pinned| &pinned.package_record.name == name && *pinned != record)
+    {
+        conflicts.push(Conflict::Pin(Box::new(pinned.clone())));
+    }
+
+    conflicts.extend(
+        requirements
+            .constraints
+            .iter()
+            .filter(|constraint| {
+                constraint.name.as_ref() == Some(name)
+                    && !constraint.matches(&record.package_record)
+            })
+            .map(|constraint| Conflict::Constraint(constraint.clone())),
+    );
+
+    if let Err(violation) = task.policy.check(record) {
+        conflicts.push(Conflict::Policy(violation));
+    }
+
+    if is_excluded_newer(record, task.exclude_newer.as_ref()) {
+        conflicts.push(Conflict::PublishedLater);
+    }
+
+    conflicts.extend(
+        requirements
+            .specs
+            .iter()
+            .filter(|spec| {
+                spec.name.as_ref() == Some(name) && !spec.matches(&record.package_record)
+            })
+            .map(|spec| Conflict::Spec(spec.clone())),
+    );
+
+    conflicts
+}
+
+/// Explains the conflicts of the records of a task by solving the task with different
+/// requirements. Keeps track of the number of solves, see [`MAX_SOLVES`].
+struct Explainer<'s, S, TAvailablePackagesIterator> {
+    solver: &'s mut S,
+    task: &'s SolverTask<TAvailablePackagesIterator>,
+    remaining_solves: usize,
+}
+
+impl<'s, 'a, S, R, TAvailablePackagesIterator> Explainer<'s, S, TAvailablePackagesIterator>
+where
+    S: SolverImpl,
+    R: IntoRepoData<'a, S::RepoData<'a>> + AsRef<[RepoDataRecord]>,
+    TAvailablePackagesIterator: IntoIterator<Item = R> + Clone,
+{
+    /// Solves the task with the given requirements instead of its own.
+    fn solve(&mut self, requirements: Requirements) -> Result<Vec<RepoDataRecord>, SolveError> {
+        self.remaining_solves = self.remaining_solves.saturating_sub(1);
+        solve_with(self.solver, self.task, requirements)
+    }
+
+    /// Returns whether the task cannot be solved with the given requirements, or `None` if the
+    /// maximum number of solves has been reached.
+    fn is_unsolvable(&mut self, requirements: Requirements) -> Result<Option<bool>, SolveError> {
+        if self.remaining_solves == 0 {
+            return Ok(None);
+        }
+        is_unsolvable(self.solve(requirements)).map(Some)
+    }
+
+    /// Determines why the `record` conflicts with the `requirements`. Returns an empty list if the
+    /// conflict cannot be attributed to a single requirement.
+    fn find_conflicts(
+        &mut self,
+        requirements: &Requirements,
+        record: &RepoDataRecord,
+        depth: usize,
+    ) -> Result<Vec<Conflict>, SolveError> {
+        let conflicts = direct_conflicts(self.task, requirements, record);
+        if !conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+
+        let mut conflicts = Vec::new();
+
+        // Find the constraints of the record that cannot be satisfied together with the
+        // requirements.
+        for constrains in &record.package_record.constrains {
+            let spec = MatchSpec::from_str(constrains)?;
+            let mut constrained = requirements.clone();
+            constrained.constraints.push(spec.clone());
+            match self.is_unsolvable(constrained)? {
+                Some(true) => conflicts.push(Conflict::Constrains(spec)),
+                Some(false) => {}
+                None => return Ok(not_examined(conflicts)),
+            }
+        }
+
+        // Find the dependencies of the record that cannot be satisfied together with the
+        // requirements. The candidates of such a dependency all conflict with the requirements.
+        for depends in &record.package_record.depends {
+            let spec = MatchSpec::from_str(depends)?;
+            let is_virtual = self
+                .task
+                .virtual_packages
+                .iter()
+                .any(|package| Some(&package.name) == spec.name.as_ref());
+            if !is_virtual && matching_records(self.task, &spec).is_empty() {
+                conflicts.push(Conflict::MissingDependency(spec));
+                continue;
+            }
+
+            let mut dependency = requirements.clone();
+            dependency.specs.push(spec.clone());
+            match self.is_unsolvable(dependency)? {
+                Some(true) => conflicts.push(Conflict::Dependency(self.explain_candidates(
+                    requirements,
+                    spec,
+                    depth,
+                )?)),
+                Some(false) => {}
+                None => return Ok(not_examined(conflicts)),
+            }
+        }
+
+        // Find the specs for other packages that cannot be satisfied together with the record.
+        // The specs of packages that are pinned are skipped, their conflicts are reported as
+        // pins.
+        let with_record = requirements.with_record(record);
+        for spec in &requirements.specs {
+            match &spec.name {
+                Some(name)
+                    if name != &record.package_record.name && !requirements.is_pinned(name) => {}
+                _ => continue,
+            }
+
+            let single = Requirements {
+                specs: vec![
+                    spec.clone(),
+                    MatchSpec {
+                        name: Some(record.package_record.name.clone()),
+                        ..MatchSpec::default()
+                    },
+                ],
+                ..with_record.clone()
+            };
+            match self.is_unsolvable(single.clone())? {
+                Some(true) => conflicts.push(Conflict::Requirement(self.explain_candidates(
+                    &single,
+                    spec.clone(),
+                    depth,
+                )?)),
+                Some(false) => {}
+                None => return Ok(not_examined(conflicts)),
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Explains why each of the candidates of `spec` conflicts with the `requirements`.
+    fn explain_candidates(
+        &mut self,
+        requirements: &Requirements,
+        spec: MatchSpec,
+        depth: usize,
+    ) -> Result<CandidateConflicts, SolveError> {
+        let records = matching_records(self.task, &spec);
+        let explained = if depth < MAX_DEPTH {
+            records.len().min(MAX_CANDIDATES)
+        } else {
+            0
+        };
+
+        let mut candidates = Vec::with_capacity(explained);
+        for record in records.iter().take(explained) {
+            if self.remaining_solves == 0 {
+                break;
+            }
+
+            let mut conflicts = self.find_conflicts(requirements, record, depth + 1)?;
+            if conflicts.is_empty() {
+                let mut pinned = requirements.clone();
+                pinned.pinned_packages.push(record.clone());
+                match self.is_unsolvable(pinned)? {
+                    Some(true) => conflicts.push(Conflict::Unexplained),
+                    Some(false) => {}
+                    None => conflicts.push(Conflict::NotExamined),
+                }
+            }
+            candidates.push((record.clone(), conflicts));
+        }
+
+        Ok(CandidateConflicts {
+            spec,
+            omitted: records.len() - candidates.len(),
+            candidates,
+        })
+    }
+}
+
+/// Marks the `conflicts` that were found so far as incomplete because the maximum number of
+/// solves was reached.
+fn not_examined(mut conflicts: Vec<Conflict>) -> Vec<Conflict> {
+    conflicts.push(Conflict::NotExamined);
+    conflicts
+}
+
+/// Returns the available records that satisfy the `spec`, the highest versions first.
+fn matching_records<R, TAvailablePackagesIterator>(
+    task: &SolverTask<TAvailablePackagesIterator>,
+    spec: &MatchSpec,
+) -> Vec<RepoDataRecord>
+where
+    R: AsRef<[RepoDataRecord]>,
+    TAvailablePackagesIterator: IntoIterator<Item = R> + Clone,
+{
+    let mut records = Vec::new();
+    for available in task.available_packages.clone() {
+        records.extend(
+            available
+                .as_ref()
+                .iter()
+                .filter(|record| {
+                    spec.name.as_ref() == Some(&record.package_record.name)
+                        && spec.matches(&record.package_record)
+                })
+                .cloned(),
+        );
+    }
+    records.sort_by(|a, b| {
+        b.package_record
+            .version
+            .cmp(&a.package_record.version)
+            .then_with(|| {
+                b.package_record
+                    .build_number
+                    .cmp(&a.package_record.build_number)
+            })
+    });
+    records
+}
+
+/// Solves the task with the given requirements instead of its own.
+fn solve_with<'a, S, R, TAvailablePackagesIterator>(
+    solver: &mut S,
+    task: &SolverTask<TAvailablePackagesIterator>,
+    requirements: Requirements,
+) -> Result<Vec<RepoDataRecord>, SolveError>
+where
+    S: SolverImpl,
+    R: IntoRepoData<'a, S::RepoData<'a>>,
+    TAvailablePackagesIterator: IntoIterator<Item = R> + Clone,
+{
+    solver.solve(SolverTask {
+        available_packages: task.available_packages.clone(),
+        locked_packages: task.locked_packages.clone(),
+        pinned_packages: requirements.pinned_packages,
+        virtual_packages: task.virtual_packages.clone(),
+        specs: requirements.specs,
+        constraints: requirements.constraints,
+        strategy: task.strategy,
+        channel_priority: task.channel_priority,
+        prefer_arch_over_noarch: task.prefer_arch_over_noarch,
+        dependency_mode: task.dependency_mode,
+        policy: task.policy.clone(),
+        exclude_newer: task.exclude_newer,
+    })
+}
+
+/// Returns true if the result of a solve indicates that the requirements cannot be satisfied.
+fn is_unsolvable(result: Result<Vec<RepoDataRecord>, SolveError>) -> Result<bool, SolveError> {
+    match result {
+        Ok(_) => Ok(false),
+        Err(SolveError::Unsolvable(_)) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+impl Display for WhyNot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WhyNot::Selected => write!(f, "the package is selected"),
+            WhyNot::NotRequired => write!(f, "the package is not required"),
+            WhyNot::ExcludedByPin(pinned) => write!(f, "{} is pinned", pinned.package_record),
+            WhyNot::ExcludedByConstraint(constraint) => {
+                write!(
+                    f,
+                    "the package does not satisfy the constraint {constraint}"
+                )
+            }
+            WhyNot::ExcludedByPolicy(violation) => write!(f, "{violation}"),
+            WhyNot::PublishedLater => write!(f, "the package was published after the cutoff"),
+            WhyNot::Conflict(conflicts) => {
+                write!(f, "the package conflicts with the requirements")?;
+                fmt_conflicts(f, conflicts, 0)
+            }
+            WhyNot::NotPreferred(selected) => write!(f, "{} is preferred", selected.package_record),
+        }
+    }
+}
+
+/// Formats the `conflicts` as a tree, every conflict on its own line indented by `depth`.
+fn fmt_conflicts(f: &mut Formatter<'_>, conflicts: &[Conflict], depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    for conflict in conflicts {
+        write!(f, "\n{indent}- ")?;
+        let candidates = match conflict {
+            Conflict::Spec(spec) => {
+                write!(f, "the spec {spec} is not satisfied")?;
+                continue;
+            }
+            Conflict::Pin(pinned) => {
+                write!(f, "{} is pinned", pinned.package_record)?;
+                continue;
+            }
+            Conflict::Constraint(constraint) => {
+                write!(f, "the constraint {constraint} is not satisfied")?;
+                continue;
+            }
+            Conflict::Policy(violation) => {
+                write!(f, "{violation}")?;
+                continue;
+            }
+            Conflict::PublishedLater => {
+                write!(f, "it was published after the cutoff")?;
+                continue;
+            }
+            Conflict::Constrains(spec) => {
+                write!(f, "it constrains {spec}")?;
+                continue;
+            }
+            Conflict::MissingDependency(spec) => {
+                write!(f, "no package satisfies the dependency {spec}")?;
+                continue;
+            }
+            Conflict::Unexplained => {
+                write!(f, "the conflict is caused by a combination of requirements")?;
+                continue;
+            }
+            Conflict::NotExamined => {
+                write!(
+                    f,
+                    "the remaining requirements are not examined, the maximum number of solves was reached"
+                )?;
+                continue;
+            }
+            Conflict::Dependency(candidates) => {
+                write!(f, "the dependency {} cannot be satisfied", candidates.spec)?;
+                candidates
+            }
+            Conflict::Requirement(candidates) => {
+                write!(f, "the spec {} cannot be satisfied", candidates.spec)?;
+                candidates
+            }
+        };
+
+        for (candidate, conflicts) in &candidates.candidates {
+            write!(f, "\n{indent}  - {} conflicts", candidate.package_record)?;
+            fmt_conflicts(f, conflicts, depth + 2)?;
+        }
+        if candidates.omitted > 0 {
+            write!(
+                f,
+                "\n{indent}  - {} more candidates are not shown",
+                candidates.omitted
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "resolvo"))]
+mod test {
+    use super::{CandidateConflicts, Conflict, Explainer, Requirements};
+    use crate::{resolvo::Solver, SolverTask};
+    use rattler_conda_types::{test_utils::RecordBuilder, MatchSpec};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_solve_budget() {
+        let repo_data = vec![
+            RecordBuilder::new("foo", "1.0").build(),
+            RecordBuilder::new("foo", "2.0").build(),
+            RecordBuilder::new("bar", "1.0")
+                .with_dependency("foo >=2")
+                .build(),
+        ];
+        let specs = vec![
+            MatchSpec::from_str("foo").unwrap(),
+            MatchSpec::from_str("bar").unwrap(),
+        ];
+        let task = SolverTask {
+            specs: specs.clone(),
+            ..SolverTask::new([&repo_data])
+        };
+        let requirements = Requirements {
+            specs,
+            constraints: Vec::new(),
+            pinned_packages: Vec::new(),
+        };
+        let mut solver = Solver::default();
+        let mut conflicts = |remaining_solves| {
+            Explainer {
+                solver: &mut solver,
+                task: &task,
+                remaining_solves,
+            }
+            .find_conflicts(&requirements, &repo_data[0], 0)
+            .unwrap()
+        };
+
+        // Without any solves left nothing is examined.
+        assert_eq!(conflicts(0), vec![Conflict::NotExamined]);
+
+        // The conflicting requirement is found, but there are no solves left to explain its
+        // candidates.
+        assert_eq!(
+            conflicts(1),
+            vec![Conflict::Requirement(CandidateConflicts {
+                spec: MatchSpec::from_str("bar").unwrap(),
+                candidates: Vec::new(),
+                omitted: 1,
+            })]
+        );
+    }
+}
@@ -1,10 +1,14 @@
+use chrono::{TimeZone, Utc};
 use once_cell::sync::Lazy;
 use rattler_conda_types::{
     Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, NoArchType, PackageRecord, RepoData,
-    RepoDataRecord, Version,
+    RepoDataRecord, Timestamp, Version,
 };
 use rattler_repodata_gateway::sparse::SparseRepoData;
-use rattler_solve::{SolveError, SolverImpl, SolverTask};
+use rattler_solve::{
+    why_not, CandidateConflicts, ChannelPriority, Conflict, DependencyMode, Policy,
+    PolicyViolation, SolveError, SolveStrategy, SolverImpl, SolverTask, WhyNot,
+};
 use std::str::FromStr;
 use std::time::Instant;
 use url::Url;
@@ -53,10 +57,10 @@ fn read_repodata(path: &str) -> Vec<RepoDataRecord> {
     )
 }
 
-fn read_sparse_repodata(path: &str) -> SparseRepoData {
+fn read_sparse_repodata(path: &str, subdir: &str) -> SparseRepoData {
     SparseRepoData::new(
         Channel::from_str("dummy", &ChannelConfig::default()).unwrap(),
-        "dummy".to_string(),
+        subdir.to_string(),
         path,
         None,
     )
@@ -113,11 +117,8 @@ fn solve_real_world<T: SolverImpl + Default>(specs: Vec<&str>) -> Vec<String> {
         SparseRepoData::load_records_recursive(sparse_repo_datas, names, None, true).unwrap();
 
     let solver_task = SolverTask {
-        available_packages: &available_packages,
         specs: specs.clone(),
-        locked_packages: Default::default(),
-        pinned_packages: Default::default(),
-        virtual_packages: Default::default(),
+        ..SolverTask::new(&available_packages)
     };
 
     let pkgs1 = match T::default().solve(solver_task) {
@@ -153,14 +154,85 @@ fn read_real_world_repo_data() -> &'static Vec<SparseRepoData> {
         let json_file_noarch = conda_json_path_noarch();
 
         vec![
-            read_sparse_repodata(&json_file),
-            read_sparse_repodata(&json_file_noarch),
+            read_sparse_repodata(&json_file, "linux-64"),
+            read_sparse_repodata(&json_file_noarch, "noarch"),
         ]
     });
 
     &REPO_DATA
 }
 
+/// Reads the repodata of a single subdir of one of the synthetic channels in
+/// `test-data/channels`. These channels are small enough to be reviewed by hand.
+fn read_synthetic_repodata(channel: &str, subdir: &str) -> Vec<RepoDataRecord> {
+    let path = format!(
+        "{}/../../test-data/channels/{channel}/{subdir}/repodata.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let repo_data: RepoData =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    repo_data
+        .into_repo_data_records(&Channel::from_str(channel, &ChannelConfig::default()).unwrap())
+}
+
+/// Solves the `specs` with the packages from the given subdirs of the synthetic channels. Returns
+/// the sorted urls of the selected packages so the snapshots show where each package came from.
+fn solve_synthetic<T: SolverImpl + Default>(
+    channels: &[(&str, &[&str])],
+    virtual_packages: &[(&str, &str)],
+    specs: &[&str],
+) -> Result<Vec<String>, SolveError> {
+    solve_synthetic_with_channel_priority::<T>(
+        channels,
+        virtual_packages,
+        specs,
+        ChannelPriority::default(),
+    )
+}
+
+/// Like [`solve_synthetic`] but with the given channel priority. The channels are passed in the
+/// order of their priority.
+fn solve_synthetic_with_channel_priority<T: SolverImpl + Default>(
+    channels: &[(&str, &[&str])],
+    virtual_packages: &[(&str, &str)],
+    specs: &[&str],
+    channel_priority: ChannelPriority,
+) -> Result<Vec<String>, SolveError> {
+    let available_packages = channels
+        .iter()
+        .flat_map(|(channel, subdirs)| {
+            subdirs
+                .iter()
+                .map(|subdir| read_synthetic_repodata(channel, subdir))
+        })
+        .collect::<Vec<_>>();
+
+    let solver_task = SolverTask {
+        specs: specs
+            .iter()
+            .map(|s| MatchSpec::from_str(s).unwrap())
+            .collect(),
+        virtual_packages: virtual_packages
+            .iter()
+            .map(|(name, version)| GenericVirtualPackage {
+                name: rattler_conda_types::PackageName::new_unchecked(*name),
+                version: Version::from_str(version).unwrap(),
+                build_string: "0".to_string(),
+            })
+            .collect(),
+        channel_priority,
+        ..SolverTask::new(&available_packages)
+    };
+
+    let mut urls = T::default()
+        .solve(solver_task)?
+        .into_iter()
+        .map(|record| record.url.to_string())
+        .collect::<Vec<_>>();
+    urls.sort();
+    Ok(urls)
+}
+
 macro_rules! solver_backend_tests {
     ($T:path) => {
         #[test]
@@ -424,6 +496,568 @@ macro_rules! solver_backend_tests {
             assert_eq!("3.0.2", &info.package_record.version.to_string());
         }
 
+        #[test]
+        fn test_why_not() {
+            let record = |name: &str, version: &str, depends: &[&str]| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", name, version, "h_0", 0);
+                record.file_name = format!("{name}-{version}-h_0.tar.bz2");
+                record.package_record.depends = depends.iter().map(|d| d.to_string()).collect();
+                record
+            };
+            let repo_data = vec![
+                record("foo", "1.0", &[]),
+                record("foo", "2.0", &[]),
+                record("bar", "1.0", &["foo >=2"]),
+            ];
+            let task = |specs: &[&str]| SolverTask {
+                specs: specs
+                    .iter()
+                    .map(|s| MatchSpec::from_str(s).unwrap())
+                    .collect(),
+                ..SolverTask::new([&repo_data])
+            };
+            let mut solver = <$T>::default();
+
+            assert_eq!(
+                why_not(&mut solver, task(&["foo"]), &repo_data[1]).unwrap(),
+                WhyNot::Selected
+            );
+            assert_eq!(
+                why_not(&mut solver, task(&["foo"]), &repo_data[2]).unwrap(),
+                WhyNot::NotRequired
+            );
+            assert_eq!(
+                why_not(&mut solver, task(&["foo"]), &repo_data[0]).unwrap(),
+                WhyNot::NotPreferred(Box::new(repo_data[1].clone()))
+            );
+
+            // `bar` requires `foo >=2` which conflicts with `foo 1.0`.
+            let why = why_not(&mut solver, task(&["foo", "bar"]), &repo_data[0]).unwrap();
+            assert_eq!(
+                why,
+                WhyNot::Conflict(vec![Conflict::Requirement(CandidateConflicts {
+                    spec: MatchSpec::from_str("bar").unwrap(),
+                    candidates: vec![(
+                        repo_data[2].clone(),
+                        vec![Conflict::Dependency(CandidateConflicts {
+                            spec: MatchSpec::from_str("foo >=2").unwrap(),
+                            candidates: vec![(
+                                repo_data[1].clone(),
+                                vec![Conflict::Pin(Box::new(repo_data[0].clone()))]
+                            )],
+                            omitted: 0,
+                        })]
+                    )],
+                    omitted: 0,
+                })])
+            );
+            assert_eq!(
+                why.to_string(),
+                "the package conflicts with the requirements\n\
+                 - the spec bar cannot be satisfied\n  \
+                   - bar=1.0=h_0 conflicts\n    \
+                     - the dependency foo >=2 cannot be satisfied\n      \
+                       - foo=2.0=h_0 conflicts\n        \
+                         - foo=1.0=h_0 is pinned"
+            );
+
+            let mut pinned_task = task(&["foo"]);
+            pinned_task.pinned_packages = vec![repo_data[1].clone()];
+            assert_eq!(
+                why_not(&mut solver, pinned_task, &repo_data[0]).unwrap(),
+                WhyNot::ExcludedByPin(Box::new(repo_data[1].clone()))
+            );
+
+            let mut constrained_task = task(&["foo"]);
+            constrained_task.constraints = vec![MatchSpec::from_str("foo <2").unwrap()];
+            assert_eq!(
+                why_not(&mut solver, constrained_task, &repo_data[1]).unwrap(),
+                WhyNot::ExcludedByConstraint(Box::new(MatchSpec::from_str("foo <2").unwrap()))
+            );
+        }
+
+        #[test]
+        fn test_constraints() {
+            let record = |name: &str, version: &str| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", name, version, "h_0", 0);
+                record.file_name = format!("{name}-{version}-h_0.tar.bz2");
+                record
+            };
+            let repo_data = vec![
+                record("foo", "1.0"),
+                record("foo", "2.0"),
+                record("bar", "1.0"),
+            ];
+            let task = |specs: &[&str], locked_packages: Vec<RepoDataRecord>| SolverTask {
+                specs: specs
+                    .iter()
+                    .map(|s| MatchSpec::from_str(s).unwrap())
+                    .collect(),
+                locked_packages,
+                constraints: vec![MatchSpec::from_str("foo <2").unwrap()],
+                ..SolverTask::new([&repo_data])
+            };
+            let versions = |records: Vec<RepoDataRecord>| {
+                let mut versions = records
+                    .into_iter()
+                    .map(|r| {
+                        format!(
+                            "{} {}",
+                            r.package_record.name.as_normalized(),
+                            r.package_record.version
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                versions.sort();
+                versions
+            };
+
+            // The constraint excludes the highest version of foo.
+            let result = <$T>::default().solve(task(&["foo"], Vec::new())).unwrap();
+            assert_eq!(versions(result), ["foo 1.0"]);
+
+            // Constraints do not cause packages to be installed.
+            let result = <$T>::default().solve(task(&["bar"], Vec::new())).unwrap();
+            assert_eq!(versions(result), ["bar 1.0"]);
+
+            // Locked packages that do not satisfy the constraint are not kept.
+            let result = <$T>::default()
+                .solve(task(&["foo"], vec![repo_data[1].clone()]))
+                .unwrap();
+            assert_eq!(versions(result), ["foo 1.0"]);
+        }
+
+        #[test]
+        fn test_policy() {
+            let record = |name: &str, version: &str, license_family: &str| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", name, version, "h_0", 0);
+                record.file_name = format!("{name}-{version}-h_0.tar.bz2");
+                record.package_record.license_family = Some(license_family.to_string());
+                record
+            };
+            let repo_data = vec![
+                record("foo", "1.0", "MIT"),
+                record("foo", "2.0", "GPL"),
+                record("bar-gpl", "1.0", "MIT"),
+            ];
+            let task = |spec: &str, pinned_packages: Vec<RepoDataRecord>| SolverTask {
+                specs: vec![MatchSpec::from_str(spec).unwrap()],
+                pinned_packages,
+                policy: Policy {
+                    denied_packages: vec!["*-gpl".parse().unwrap()],
+                    allowed_license_families: Some(vec![String::from("MIT")]),
+                    allowed_channel_hosts: None,
+                },
+                ..SolverTask::new([&repo_data])
+            };
+
+            // The highest version of foo is not allowed.
+            let result = <$T>::default().solve(task("foo", Vec::new())).unwrap();
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].package_record.version.to_string(), "1.0");
+
+            // Requesting a denied package is an error.
+            assert!(matches!(
+                <$T>::default().solve(task("bar-gpl", Vec::new())),
+                Err(SolveError::PolicyViolation(violations))
+                    if violations == [PolicyViolation::DeniedPackage { package: String::from("bar-gpl") }]
+            ));
+
+            // Pinned packages are never replaced, so a pinned package that is not allowed is an
+            // error as well.
+            assert!(matches!(
+                <$T>::default().solve(task("foo", vec![repo_data[1].clone()])),
+                Err(SolveError::PolicyViolation(violations))
+                    if matches!(violations[..], [PolicyViolation::DisallowedLicenseFamily { .. }])
+            ));
+        }
+
+        #[test]
+        fn test_exclude_newer() {
+            let record = |version: &str, timestamp: i64| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", "foo", version, "h_0", 0);
+                record.file_name = format!("foo-{version}-h_0.tar.bz2");
+                record.package_record.timestamp = Timestamp::from_raw(timestamp);
+                record
+            };
+            let repo_data = vec![record("1.0", 1_600_000_000), record("2.0", 4_000_000_000)];
+            let task = |cutoff: Option<i64>, locked_packages: Vec<RepoDataRecord>| SolverTask {
+                specs: vec![MatchSpec::from_str("foo").unwrap()],
+                locked_packages,
+                exclude_newer: cutoff.map(|cutoff| Utc.timestamp_opt(cutoff, 0).unwrap()),
+                ..SolverTask::new([&repo_data])
+            };
+            let version = |records: Vec<RepoDataRecord>| {
+                assert_eq!(records.len(), 1);
+                records[0].package_record.version.to_string()
+            };
+
+            // Without a cutoff the timestamps do not matter, even if they are in the future.
+            let result = <$T>::default().solve(task(None, Vec::new())).unwrap();
+            assert_eq!(version(result), "2.0");
+
+            // Packages that were published after the cutoff are not selected.
+            let result = <$T>::default().solve(task(Some(1_650_000_000), Vec::new())).unwrap();
+            assert_eq!(version(result), "1.0");
+            let result = <$T>::default().solve(task(Some(4_100_000_000), Vec::new())).unwrap();
+            assert_eq!(version(result), "2.0");
+
+            // Not even when they are locked.
+            let result = <$T>::default()
+                .solve(task(Some(1_650_000_000), vec![repo_data[1].clone()]))
+                .unwrap();
+            assert_eq!(version(result), "1.0");
+
+            assert_eq!(
+                why_not(
+                    &mut <$T>::default(),
+                    task(Some(1_650_000_000), Vec::new()),
+                    &repo_data[1]
+                )
+                .unwrap(),
+                WhyNot::PublishedLater
+            );
+        }
+
+        #[test]
+        fn test_dependency_mode() {
+            let record = |name: &str, depends: &[&str]| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", name, "1.0", "h_0", 0);
+                record.file_name = format!("{name}-1.0-h_0.tar.bz2");
+                record.package_record.depends = depends.iter().map(|d| d.to_string()).collect();
+                record
+            };
+            let repo_data = vec![
+                record("python", &[]),
+                record("click", &["python"]),
+                record("flask", &["python", "click"]),
+                record("zlib", &[]),
+                record("broken", &["missing"]),
+                {
+                    let mut record = record("strict-flask", &["python", "click"]);
+                    record.package_record.constrains = vec!["click >=2".to_string()];
+                    record
+                },
+            ];
+            let task = |specs: &[&str], dependency_mode: DependencyMode| SolverTask {
+                specs: specs
+                    .iter()
+                    .map(|s| MatchSpec::from_str(s).unwrap())
+                    .collect(),
+                locked_packages: vec![repo_data[3].clone()],
+                dependency_mode,
+                ..SolverTask::new([&repo_data])
+            };
+            let names = |records: Vec<RepoDataRecord>| {
+                let mut names = records
+                    .into_iter()
+                    .map(|r| r.package_record.name.as_normalized().to_string())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names
+            };
+
+            let result = <$T>::default()
+                .solve(task(&["python", "flask"], DependencyMode::All))
+                .unwrap();
+            assert_eq!(names(result), ["click", "flask", "python"]);
+
+            // Python is a spec but it is also a dependency of flask.
+            let result = <$T>::default()
+                .solve(task(&["python", "flask"], DependencyMode::OnlyDeps))
+                .unwrap();
+            assert_eq!(names(result), ["click", "python"]);
+
+            // The locked packages are kept when the dependencies are skipped.
+            let result = <$T>::default()
+                .solve(task(&["flask"], DependencyMode::NoDeps))
+                .unwrap();
+            assert_eq!(names(result), ["flask", "zlib"]);
+
+            // The dependencies of the specs are not solved at all, so they may be missing.
+            let result = <$T>::default()
+                .solve(task(&["broken"], DependencyMode::NoDeps))
+                .unwrap();
+            assert_eq!(names(result), ["broken", "zlib"]);
+            assert!(<$T>::default()
+                .solve(task(&["broken"], DependencyMode::All))
+                .is_err());
+
+            // The constraints of a package that is not installed do not apply.
+            let result = <$T>::default()
+                .solve(task(&["strict-flask"], DependencyMode::OnlyDeps))
+                .unwrap();
+            assert_eq!(names(result), ["click", "python"]);
+            assert!(<$T>::default()
+                .solve(task(&["strict-flask"], DependencyMode::All))
+                .is_err());
+        }
+
+        #[test]
+        fn test_synthetic_linux() {
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["linux-64", "noarch"])],
+                &[("__glibc", "2.28"), ("__unix", "0")],
+                &["numpy", "pure-tool"],
+            )
+            .unwrap());
+        }
+
+        #[test]
+        fn test_synthetic_osx() {
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["osx-64", "noarch"])],
+                &[("__osx", "12.0"), ("__unix", "0")],
+                &["python", "pure-tool"],
+            )
+            .unwrap());
+        }
+
+        #[test]
+        fn test_synthetic_virtual_packages() {
+            // Without `__glibc` python 3.10 cannot be installed and without `__cuda >=12` the
+            // highest version of cuda-app cannot be installed.
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["linux-64"])],
+                &[("__cuda", "11.8")],
+                &["python", "cuda-app"],
+            )
+            .unwrap());
+
+            assert!(matches!(
+                solve_synthetic::<$T>(&[("synthetic", &["linux-64"])], &[], &["cuda-app"]),
+                Err(SolveError::Unsolvable(_))
+            ));
+        }
+
+        #[test]
+        fn test_synthetic_feature_penalty() {
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["linux-64"])],
+                &[],
+                &["blas"],
+            )
+            .unwrap());
+
+            // The package with the tracked feature is still selected if it is explicitly requested.
+            assert_eq!(
+                solve_synthetic::<$T>(&[("synthetic", &["linux-64"])], &[], &["blas >=2"]).unwrap(),
+                vec!["https://conda.anaconda.org/synthetic/linux-64/blas-2.0-openblas_0.tar.bz2"]
+            );
+        }
+
+        #[test]
+        fn test_tie_breaking_parity() {
+            // Every case lists the candidates as `(version, build, build_number, timestamp,
+            // track_features)` followed by the build that conda selects.
+            let cases: &[(&[(&str, &str, u64, Option<i64>, &str)], &str)] = &[
+                // The highest version wins over the highest build number.
+                (
+                    &[("1.0", "h_5", 5, None, ""), ("2.0", "h_0", 0, None, "")],
+                    "h_0",
+                ),
+                // For the same version the highest build number wins, regardless of the order of
+                // the records and the build string.
+                (
+                    &[
+                        ("1.0", "h_b", 1, None, ""),
+                        ("1.0", "h_a", 2, None, ""),
+                        ("1.0", "h_c", 0, None, ""),
+                    ],
+                    "h_a",
+                ),
+                // For the same version and build number the most recent build wins.
+                (
+                    &[
+                        ("1.0", "h_old", 0, Some(1_600_000_000), ""),
+                        ("1.0", "h_new", 0, Some(1_700_000_000), ""),
+                    ],
+                    "h_new",
+                ),
+                // A build with tracked features loses from a build without, even if it has a
+                // higher build number.
+                (
+                    &[("1.0", "h_feat", 9, None, "feat"), ("1.0", "h_0", 0, None, "")],
+                    "h_0",
+                ),
+            ];
+
+            for (candidates, expected) in cases {
+                let records = candidates
+                    .iter()
+                    .map(|(version, build, build_number, timestamp, track_features)| {
+                        let mut record = installed_package(
+                            "conda-forge",
+                            "linux-64",
+                            "foo",
+                            version,
+                            build,
+                            *build_number,
+                        );
+                        record.file_name = format!("foo-{version}-{build}.tar.bz2");
+                        record.url = Url::parse(&format!(
+                            "https://conda.anaconda.org/conda-forge/linux-64/{}",
+                            record.file_name
+                        ))
+                        .unwrap();
+                        record.package_record.timestamp = timestamp.map(|timestamp| {
+                            chrono::TimeZone::timestamp_opt(&chrono::Utc, timestamp, 0)
+                                .unwrap()
+                                .into()
+                        });
+                        record.package_record.track_features = track_features
+                            .split_whitespace()
+                            .map(ToOwned::to_owned)
+                            .collect();
+                        record
+                    })
+                    .collect::<Vec<_>>();
+
+                let pkgs = <$T>::default()
+                    .solve(SolverTask {
+                        specs: vec![MatchSpec::from_str("foo").unwrap()],
+                        ..SolverTask::new([&records])
+                    })
+                    .unwrap();
+
+                assert_eq!(pkgs.len(), 1);
+                assert_eq!(
+                    &pkgs[0].package_record.build, expected,
+                    "unexpected candidate selected from {candidates:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn test_synthetic_constrains() {
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["linux-64"])],
+                &[],
+                &["legacy-app", "libzlib"],
+            )
+            .unwrap());
+
+            // A constraint does not cause the constrained package to be installed.
+            assert_eq!(
+                solve_synthetic::<$T>(&[("synthetic", &["linux-64"])], &[], &["legacy-app"])
+                    .unwrap(),
+                vec!["https://conda.anaconda.org/synthetic/linux-64/legacy-app-1.0-h1_0.tar.bz2"]
+            );
+        }
+
+        #[test]
+        fn test_solve_channel_pin() {
+            let record = |channel: &str, subdir: &str, version: &str| {
+                let mut record = installed_package(channel, subdir, "foo", version, "h_0", 0);
+                record.file_name = format!("foo-{version}-h_0.tar.bz2");
+                record
+            };
+            let repo_data = vec![
+                record("https://conda.anaconda.org/conda-forge/", "linux-64", "2.0"),
+                record("https://conda.anaconda.org/conda-forge/", "noarch", "0.5"),
+                record(
+                    "https://conda.anaconda.org/conda-forge/label/dev/",
+                    "linux-64",
+                    "1.0",
+                ),
+                record("https://repo.anaconda.com/pkgs/main/", "linux-64", "3.0"),
+            ];
+            let solve = |spec: &str| {
+                let result = <$T>::default()
+                    .solve(SolverTask {
+                        specs: vec![MatchSpec::from_str(spec).unwrap()],
+                        ..SolverTask::new([&repo_data])
+                    })
+                    .unwrap();
+                assert_eq!(result.len(), 1);
+                result[0].package_record.version.to_string()
+            };
+
+            assert_eq!(solve("foo"), "3.0");
+            assert_eq!(solve("conda-forge::foo"), "2.0");
+            assert_eq!(solve("conda-forge/label/dev::foo"), "1.0");
+            assert_eq!(solve("conda-forge/noarch::foo"), "0.5");
+            assert_eq!(solve("pkgs/main/linux-64::foo"), "3.0");
+        }
+
+        #[test]
+        fn test_synthetic_broken_dependency() {
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[("synthetic", &["linux-64"])],
+                &[],
+                &["broken"],
+            )
+            .unwrap());
+
+            assert!(matches!(
+                solve_synthetic::<$T>(&[("synthetic", &["linux-64"])], &[], &["broken >=1"]),
+                Err(SolveError::Unsolvable(_))
+            ));
+        }
+
+        #[test]
+        fn test_synthetic_channel_priority() {
+            // By default channels are not prioritized, the highest version is selected regardless
+            // of the channel it comes from.
+            insta::assert_yaml_snapshot!(solve_synthetic::<$T>(
+                &[
+                    ("synthetic", &["linux-64"]),
+                    ("synthetic-priority", &["linux-64"])
+                ],
+                &[],
+                &["libzlib"],
+            )
+            .unwrap());
+        }
+
+        #[test]
+        fn test_unsolvable_virtual_package_hints() {
+            let mut requires_glibc =
+                installed_package("conda-forge", "linux-64", "foo", "1.0", "h_0", 0);
+            requires_glibc.package_record.depends =
+                vec![String::from("__unix"), String::from("__glibc >=2.28")];
+            let mut requires_missing =
+                installed_package("conda-forge", "linux-64", "bar", "1.0", "h_0", 0);
+            requires_missing.file_name = String::from("bar-1.0-h_0.tar.bz2");
+            requires_missing.package_record.depends =
+                vec![String::from("__unix"), String::from("baz")];
+            let repo_data = vec![requires_glibc, requires_missing];
+            let virtual_packages = vec![
+                virtual_package("__unix", "0"),
+                virtual_package("__glibc", "2.17"),
+            ];
+            let solve = |spec: &str| {
+                match <$T>::default().solve(SolverTask {
+                    specs: vec![MatchSpec::from_str(spec).unwrap()],
+                    virtual_packages: virtual_packages.clone(),
+                    ..SolverTask::new([&repo_data])
+                }) {
+                    Err(SolveError::Unsolvable(problems)) => problems,
+                    result => panic!("expected the solve to fail, got {result:?}"),
+                }
+            };
+
+            // Only the virtual package that is not available in the required version is described.
+            let problems = solve("foo");
+            assert!(problems.iter().any(|problem| problem.starts_with(
+                "__glibc is a virtual package that represents the version of glibc available on the system, the detected version is 2.17"
+            )));
+            assert!(!problems
+                .iter()
+                .any(|problem| problem.starts_with("__unix is a virtual package")));
+
+            // Conflicts that do not involve a virtual package are not described.
+            let problems = solve("bar");
+            assert!(!problems
+                .iter()
+                .any(|problem| problem.contains("is a virtual package")));
+        }
+
         #[test]
         fn test_solve_dummy_repo_remove() {
             let already_installed = vec![installed_package(
@@ -456,6 +1090,22 @@ mod libsolv_c {
 
     solver_backend_tests!(rattler_solve::libsolv_c::Solver);
 
+    #[test]
+    fn test_channel_priority_unsupported() {
+        assert!(matches!(
+            solve_synthetic_with_channel_priority::<rattler_solve::libsolv_c::Solver>(
+                &[
+                    ("synthetic", &["linux-64"]),
+                    ("synthetic-priority", &["linux-64"])
+                ],
+                &[],
+                &["libzlib"],
+                ChannelPriority::Strict,
+            ),
+            Err(SolveError::UnsupportedOperations(_))
+        ));
+    }
+
     #[test]
     #[cfg(target_family = "unix")]
     fn test_solve_with_cached_solv_file_install_new() {
@@ -478,11 +1128,8 @@ mod libsolv_c {
 
         let pkgs = rattler_solve::libsolv_c::Solver
             .solve(SolverTask {
-                locked_packages: Vec::new(),
-                virtual_packages: Vec::new(),
-                available_packages: [libsolv_repodata],
                 specs,
-                pinned_packages: Vec::new(),
+                ..SolverTask::new([libsolv_repodata])
             })
             .unwrap();
 
@@ -523,9 +1170,95 @@ mod libsolv_c {
     }
 }
 
+#[cfg(feature = "libsolv_c")]
+#[test]
+fn test_libsolv_c_lowest_version_unsupported() {
+    let result = rattler_solve::libsolv_c::Solver.solve(SolverTask {
+        strategy: SolveStrategy::LowestVersion,
+        ..SolverTask::new(Vec::<&Vec<RepoDataRecord>>::new())
+    });
+
+    assert!(matches!(result, Err(SolveError::UnsupportedOperations(_))));
+}
+
+#[cfg(feature = "libsolv_c")]
+#[test]
+fn test_libsolv_c_prefer_arch_over_noarch_unsupported() {
+    let result = rattler_solve::libsolv_c::Solver.solve(SolverTask {
+        prefer_arch_over_noarch: true,
+        ..SolverTask::new(Vec::<&Vec<RepoDataRecord>>::new())
+    });
+
+    assert!(matches!(result, Err(SolveError::UnsupportedOperations(_))));
+}
+
+#[cfg(all(feature = "libsolv_c", feature = "resolvo"))]
+#[test]
+fn test_solve_environment_options() {
+    use rattler_solve::{solve_environment, EnvironmentSpec, SolveOptions, SolverBackend};
+
+    let repo_data = ["1.0", "2.0", "3.0"]
+        .into_iter()
+        .map(|version| {
+            let mut record = installed_package("conda-forge", "linux-64", "foo", version, "h_0", 0);
+            record.file_name = format!("foo-{version}-h_0.tar.bz2");
+            record
+        })
+        .collect::<Vec<_>>();
+    let available_packages = [repo_data];
+    let environment = || EnvironmentSpec {
+        available_packages: &available_packages,
+        specs: vec![MatchSpec::from_str("foo>=2").unwrap()],
+        ..EnvironmentSpec::default()
+    };
+    let lowest_version = |backend| SolveOptions {
+        backend,
+        strategy: SolveStrategy::LowestVersion,
+        ..SolveOptions::default()
+    };
+
+    // The options are passed to the selected backend.
+    let pkgs = solve_environment(environment(), lowest_version(SolverBackend::Resolvo)).unwrap();
+    assert_eq!(pkgs.len(), 1);
+    assert_eq!(pkgs[0].package_record.version.to_string(), "2.0");
+    assert!(matches!(
+        solve_environment(environment(), lowest_version(SolverBackend::LibsolvC)),
+        Err(SolveError::UnsupportedOperations(_))
+    ));
+
+    // Policies are applied by every backend.
+    let denied = SolveOptions {
+        policy: Policy {
+            denied_packages: vec!["foo".parse().unwrap()],
+            ..Policy::default()
+        },
+        ..SolveOptions::default()
+    };
+    assert!(matches!(
+        solve_environment(environment(), denied),
+        Err(SolveError::PolicyViolation(_))
+    ));
+
+    assert_eq!(
+        "resolvo".parse::<SolverBackend>().unwrap(),
+        SolverBackend::Resolvo
+    );
+    assert_eq!(
+        "flexible".parse::<ChannelPriority>().unwrap(),
+        ChannelPriority::Flexible
+    );
+    assert_eq!(
+        "lowest".parse::<SolveStrategy>().unwrap_err().to_string(),
+        "'lowest' is not a solve strategy, use highest, lowest-version"
+    );
+}
+
 #[cfg(feature = "resolvo")]
 mod resolvo {
     use super::*;
+    use rattler_conda_types::PackageName;
+    use rattler_solve::resolvo::{Candidate, SortContext, SortStrategy};
+    use std::cmp::Ordering;
 
     solver_backend_tests!(rattler_solve::resolvo::Solver);
 
@@ -549,6 +1282,350 @@ mod resolvo {
         // We expect an error here. `bors` is pinnend to 1, but we try to install `>=2`.
         insta::assert_display_snapshot!(result.unwrap_err());
     }
+
+    #[test]
+    fn test_solve_prefer_arch_over_noarch() {
+        let mut noarch = installed_package("conda-forge", "noarch", "foo", "1.0", "pyh_1", 1);
+        noarch.package_record.noarch = NoArchType::python();
+        noarch.file_name = "foo-1.0-pyh_1.tar.bz2".to_string();
+        let mut arch = installed_package("conda-forge", "linux-64", "foo", "1.0", "h_0", 0);
+        arch.file_name = "foo-1.0-h_0.tar.bz2".to_string();
+        let repo_data = vec![noarch, arch];
+
+        let solve = |prefer_arch_over_noarch: bool| {
+            rattler_solve::resolvo::Solver::default()
+                .solve(SolverTask {
+                    specs: vec![MatchSpec::from_str("foo").unwrap()],
+                    prefer_arch_over_noarch,
+                    ..SolverTask::new([&repo_data])
+                })
+                .unwrap()
+        };
+
+        // By default the build with the highest build number is selected.
+        let pkgs = solve(false);
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].package_record.build, "pyh_1");
+
+        // With the preference enabled the architecture specific build is selected.
+        let pkgs = solve(true);
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].package_record.build, "h_0");
+    }
+
+    /// Solves the real-world conda-forge repodata for packages that have a `noarch` build and an
+    /// architecture specific build of the same version, where the `noarch` build has the highest
+    /// build number. These are the packages for which the preference changes the solution.
+    #[test]
+    fn test_solve_real_world_prefer_arch_over_noarch() {
+        let sparse_repo_datas = read_real_world_repo_data();
+        let [linux_64, noarch] = sparse_repo_datas.as_slice() else {
+            panic!("expected the linux-64 and noarch repodata")
+        };
+        let virtual_packages = vec![
+            GenericVirtualPackage {
+                name: PackageName::new_unchecked("__unix"),
+                version: Version::from_str("0").unwrap(),
+                build_string: "0".to_string(),
+            },
+            GenericVirtualPackage {
+                name: PackageName::new_unchecked("__linux"),
+                version: Version::from_str("5.10").unwrap(),
+                build_string: "0".to_string(),
+            },
+            GenericVirtualPackage {
+                name: PackageName::new_unchecked("__glibc"),
+                version: Version::from_str("2.28").unwrap(),
+                build_string: "0".to_string(),
+            },
+        ];
+
+        let solve = |available_packages: &Vec<Vec<RepoDataRecord>>,
+                     spec: &MatchSpec,
+                     prefer_arch_over_noarch: bool| {
+            rattler_solve::resolvo::Solver::default()
+                .solve(SolverTask {
+                    specs: vec![spec.clone()],
+                    virtual_packages: virtual_packages.clone(),
+                    prefer_arch_over_noarch,
+                    ..SolverTask::new(available_packages)
+                })
+                .ok()
+                .and_then(|records| {
+                    records
+                        .into_iter()
+                        .find(|record| Some(&record.package_record.name) == spec.name.as_ref())
+                })
+        };
+
+        let mut names = noarch.package_names().collect::<Vec<_>>();
+        names.sort();
+
+        let mut tested = 0;
+        for name in names {
+            let name = PackageName::new_unchecked(name);
+            let noarch_records = noarch.load_records(&name).unwrap();
+            let arch_records = linux_64.load_records(&name).unwrap();
+
+            // Find the highest version for which the noarch build is selected by default.
+            let max_build_number = |records: &[RepoDataRecord], version: &Version| {
+                records
+                    .iter()
+                    .filter(|r| &r.package_record.version == version)
+                    .map(|r| r.package_record.build_number)
+                    .max()
+            };
+            let Some(version) = noarch_records
+                .iter()
+                .map(|r| &r.package_record.version)
+                .filter(|version| {
+                    match (
+                        max_build_number(&noarch_records, version),
+                        max_build_number(&arch_records, version),
+                    ) {
+                        (Some(noarch), Some(arch)) => noarch > arch,
+                        _ => false,
+                    }
+                })
+                .max()
+            else {
+                continue;
+            };
+
+            let spec =
+                MatchSpec::from_str(&format!("{} =={version}", name.as_normalized())).unwrap();
+            let available_packages = SparseRepoData::load_records_recursive(
+                sparse_repo_datas,
+                [name.clone()],
+                None,
+                true,
+            )
+            .unwrap();
+
+            // Only consider the package if its architecture specific build is installable at all.
+            let arch_only = available_packages
+                .iter()
+                .map(|records| {
+                    records
+                        .iter()
+                        .filter(|r| {
+                            r.package_record.name != name || r.package_record.noarch.is_none()
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            if solve(&arch_only, &spec, false).is_none() {
+                continue;
+            }
+
+            let Some(default) = solve(&available_packages, &spec, false) else {
+                continue;
+            };
+            assert!(
+                !default.package_record.noarch.is_none(),
+                "expected the noarch build of {spec} to be selected by default"
+            );
+
+            let preferred = solve(&available_packages, &spec, true)
+                .unwrap_or_else(|| panic!("failed to solve {spec}"));
+            assert!(
+                preferred.package_record.noarch.is_none(),
+                "expected the architecture specific build of {spec} to be selected, got {}",
+                preferred.file_name
+            );
+
+            tested += 1;
+            if tested == 5 {
+                break;
+            }
+        }
+
+        assert!(
+            tested > 0,
+            "no package in the repodata is affected by the preference"
+        );
+    }
+
+    #[test]
+    fn test_channel_priority() {
+        let channels: &[(&str, &[&str])] = &[
+            ("synthetic", &["linux-64"]),
+            ("synthetic-priority", &["linux-64"]),
+        ];
+        let solve = |spec: &str, channel_priority| {
+            solve_synthetic_with_channel_priority::<rattler_solve::resolvo::Solver>(
+                channels,
+                &[],
+                &[spec],
+                channel_priority,
+            )
+        };
+        let highest_priority =
+            "https://conda.anaconda.org/synthetic/linux-64/libzlib-1.2.13-h1_1.tar.bz2";
+        let highest_version =
+            "https://conda.anaconda.org/synthetic-priority/linux-64/libzlib-1.3.0-h9_0.tar.bz2";
+
+        // Without channel priority the highest version is selected.
+        assert_eq!(
+            solve("libzlib", ChannelPriority::Disabled).unwrap(),
+            [highest_version]
+        );
+
+        // Otherwise the package from the first channel is preferred over a higher version.
+        assert_eq!(
+            solve("libzlib", ChannelPriority::Flexible).unwrap(),
+            [highest_priority]
+        );
+        assert_eq!(
+            solve("libzlib", ChannelPriority::Strict).unwrap(),
+            [highest_priority]
+        );
+
+        // If the first channel cannot satisfy the spec, a flexible priority falls back to the
+        // other channels while a strict priority does not.
+        assert_eq!(
+            solve("libzlib >=1.3", ChannelPriority::Flexible).unwrap(),
+            [highest_version]
+        );
+        assert!(matches!(
+            solve("libzlib >=1.3", ChannelPriority::Strict),
+            Err(SolveError::Unsolvable(_))
+        ));
+    }
+
+    /// A sort strategy that prefers the lowest version of a package.
+    struct LowestVersionFirst;
+
+    impl SortStrategy for LowestVersionFirst {
+        fn compare(
+            &self,
+            a: &Candidate<'_>,
+            b: &Candidate<'_>,
+            _context: &mut SortContext<'_, '_>,
+        ) -> Ordering {
+            a.version().cmp(b.version())
+        }
+    }
+
+    #[test]
+    fn test_solve_lowest_version_strategy() {
+        let repo_data = ["1.0", "2.0", "3.0"]
+            .into_iter()
+            .map(|version| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", "foo", version, "h_0", 0);
+                record.file_name = format!("foo-{version}-h_0.tar.bz2");
+                record
+            })
+            .collect::<Vec<_>>();
+
+        let pkgs = rattler_solve::resolvo::Solver::default()
+            .solve(SolverTask {
+                specs: vec![MatchSpec::from_str("foo>=2").unwrap()],
+                strategy: SolveStrategy::LowestVersion,
+                ..SolverTask::new([&repo_data])
+            })
+            .unwrap();
+
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].package_record.version.to_string(), "2.0");
+    }
+
+    #[test]
+    fn test_solve_custom_sort_strategy() {
+        let repo_data = ["1.0", "2.0", "3.0"]
+            .into_iter()
+            .map(|version| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", "foo", version, "h_0", 0);
+                record.file_name = format!("foo-{version}-h_0.tar.bz2");
+                record
+            })
+            .collect::<Vec<_>>();
+
+        let pkgs = rattler_solve::resolvo::Solver::default()
+            .with_sort_strategy(LowestVersionFirst)
+            .solve(SolverTask {
+                specs: vec![MatchSpec::from_str("foo>=2").unwrap()],
+                ..SolverTask::new([&repo_data])
+            })
+            .unwrap();
+
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].package_record.version.to_string(), "2.0");
+    }
+
+    #[test]
+    fn test_solve_extra_candidates() {
+        let mut root = installed_package("conda-forge", "linux-64", "foo", "1.0", "h_0", 0);
+        root.file_name = String::from("foo-1.0-h_0.tar.bz2");
+        root.package_record.depends = vec![String::from("bar >=2")];
+        let mut bar = installed_package("conda-forge", "linux-64", "bar", "1.0", "h_0", 0);
+        bar.file_name = String::from("bar-1.0-h_0.tar.bz2");
+        let repo_data = vec![root, bar];
+
+        // Contribute a locally built `bar` that is not part of the channel.
+        let pkgs = rattler_solve::resolvo::Solver::default()
+            .with_extra_candidates(|name: &PackageName| {
+                if name.as_normalized() == "bar" {
+                    let mut local = installed_package("local", "linux-64", "bar", "2.0", "h_0", 0);
+                    local.file_name = String::from("bar-2.0-h_0.tar.bz2");
+                    vec![local]
+                } else {
+                    Vec::new()
+                }
+            })
+            .solve(SolverTask {
+                specs: vec![MatchSpec::from_str("foo").unwrap()],
+                ..SolverTask::new([&repo_data])
+            })
+            .unwrap();
+
+        let bar = pkgs
+            .iter()
+            .find(|pkg| pkg.package_record.name.as_normalized() == "bar")
+            .unwrap();
+        assert_eq!(bar.channel, "local");
+        assert_eq!(bar.package_record.version.to_string(), "2.0");
+    }
+
+    #[test]
+    fn test_solve_stats() {
+        let mut root = installed_package("conda-forge", "linux-64", "foo", "1.0", "h_0", 0);
+        root.package_record.depends = vec![String::from("bar"), String::from("baz")];
+        let mut middle = installed_package("conda-forge", "linux-64", "bar", "1.0", "h_0", 0);
+        middle.file_name = String::from("bar-1.0-h_0.tar.bz2");
+        middle.package_record.depends = vec![String::from("baz")];
+        let mut leaf = installed_package("conda-forge", "linux-64", "baz", "1.0", "h_0", 0);
+        leaf.file_name = String::from("baz-1.0-h_0.tar.bz2");
+        let repo_data = vec![root, middle, leaf];
+
+        let mut solver = rattler_solve::resolvo::Solver::default();
+        assert!(solver.stats().is_none());
+        solver
+            .solve(SolverTask {
+                specs: vec![MatchSpec::from_str("foo").unwrap()],
+                ..SolverTask::new([&repo_data])
+            })
+            .unwrap();
+
+        let stats = solver.stats().unwrap();
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.packages_considered, 3);
+        assert_eq!(stats.dependencies_requested, 3);
+        assert_eq!(stats.match_spec_cache_misses, 2);
+        assert_eq!(stats.match_spec_cache_hits, 1);
+        assert_eq!(stats.match_spec_cache_hit_rate(), Some(1.0 / 3.0));
+    }
+}
+
+fn virtual_package(name: &str, version: &str) -> GenericVirtualPackage {
+    GenericVirtualPackage {
+        name: name.try_into().unwrap(),
+        version: Version::from_str(version).unwrap(),
+        build_string: String::from("0"),
+    }
 }
 
 fn solve<T: SolverImpl + Default>(
@@ -568,9 +1645,9 @@ fn solve<T: SolverImpl + Default>(
     let task = SolverTask {
         locked_packages: installed_packages,
         virtual_packages,
-        available_packages: [&repo_data],
         specs,
         pinned_packages,
+        ..SolverTask::new([&repo_data])
     };
 
     let pkgs = T::default().solve(task)?;
@@ -623,11 +1700,8 @@ fn compare_solve(specs: Vec<&str>) {
             extract_pkgs(
                 rattler_solve::libsolv_c::Solver
                     .solve(SolverTask {
-                        available_packages: &available_packages,
                         specs: specs.clone(),
-                        locked_packages: Default::default(),
-                        pinned_packages: Default::default(),
-                        virtual_packages: Default::default(),
+                        ..SolverTask::new(&available_packages)
                     })
                     .unwrap(),
             ),
@@ -642,13 +1716,10 @@ fn compare_solve(specs: Vec<&str>) {
         results.push((
             "resolvo",
             extract_pkgs(
-                rattler_solve::resolvo::Solver
+                rattler_solve::resolvo::Solver::default()
                     .solve(SolverTask {
-                        available_packages: &available_packages,
                         specs: specs.clone(),
-                        locked_packages: Default::default(),
-                        pinned_packages: Default::default(),
-                        virtual_packages: Default::default(),
+                        ..SolverTask::new(&available_packages)
                     })
                     .unwrap(),
             ),
@@ -27,6 +27,18 @@
 //! Finally at the core of the library are detection functions to perform specific capability
 //! detections that are not tied to anything related to virtual packages. See
 //! [`cuda::detect_cuda_version_via_libcuda`] as an example.
+//!
+//! Like conda, the virtual packages returned by [`VirtualPackage::current`] and
+//! [`VirtualPackage::for_platform`] can be overridden with environment variables. Setting a
+//! variable to an empty string removes the virtual package.
+//!
+//! | Variable                  | Overrides                                        |
+//! |---------------------------|--------------------------------------------------|
+//! | `CONDA_OVERRIDE_GLIBC`    | The version of `__glibc`, only on Linux          |
+//! | `CONDA_OVERRIDE_LINUX`    | The version of `__linux`, only on Linux          |
+//! | `CONDA_OVERRIDE_OSX`      | The version of `__osx`, only on macOS            |
+//! | `CONDA_OVERRIDE_CUDA`     | The version of `__cuda`                          |
+//! | `CONDA_OVERRIDE_ARCHSPEC` | The build string of `__archspec`, e.g. `skylake` |
 
 pub mod cuda;
 pub mod libc;
@@ -34,7 +46,9 @@ pub mod linux;
 pub mod osx;
 
 use once_cell::sync::OnceCell;
-use rattler_conda_types::{GenericVirtualPackage, PackageName, Platform, Version};
+use rattler_conda_types::{
+    GenericVirtualPackage, PackageName, ParseVersionError, Platform, Version,
+};
 use std::str::FromStr;
 
 use crate::osx::ParseOsxVersionError;
@@ -92,12 +106,80 @@ impl From<VirtualPackage> for GenericVirtualPackage {
 impl VirtualPackage {
     /// Returns virtual packages detected for the current system or an error if the versions could
     /// not be properly detected.
+    ///
+    /// The `CONDA_OVERRIDE_*` environment variables (see the [crate] documentation) are read when
+    /// the virtual packages are detected for the first time.
     pub fn current() -> Result<&'static [Self], DetectVirtualPackageError> {
         static DETECED_VIRTUAL_PACKAGES: OnceCell<Vec<VirtualPackage>> = OnceCell::new();
         DETECED_VIRTUAL_PACKAGES
             .get_or_try_init(try_detect_virtual_packages)
             .map(Vec::as_slice)
     }
+
+    /// Returns the virtual packages to use when solving an environment for the given platform,
+    /// which does not have to be the platform of the current system.
+    ///
+    /// For the current platform this returns the detected virtual packages. For other platforms
+    /// the versions of the operating system and LibC are detected if the current system runs the
+    /// same operating system, otherwise conservative defaults are used (see
+    /// [`Linux::default_version`], [`LibC::default_glibc_version`] and
+    /// [`Osx::default_version`]). Cuda is only detected for the current platform. In both cases
+    /// the `CONDA_OVERRIDE_*` environment variables (see the [crate] documentation) are applied.
+    pub fn for_platform(platform: Platform) -> Result<Vec<Self>, DetectVirtualPackageError> {
+        let current_platform = Platform::current();
+        if platform == current_platform {
+            return Ok(Self::current()?.to_vec());
+        }
+
+        let mut result = Vec::new();
+        if platform.is_unix() {
+            result.push(VirtualPackage::Unix);
+        }
+
+        if platform.is_windows() {
+            result.push(VirtualPackage::Win);
+        }
+
+        if platform.is_linux() {
+            if current_platform.is_linux() {
+                result.extend(Linux::current()?.map(Self::from));
+                result.extend(LibC::current()?.map(Self::from));
+            } else {
+                result.push(
+                    Linux {
+                        version: Linux::default_version(),
+                    }
+                    .into(),
+                );
+                result.push(
+                    LibC {
+                        family: String::from("glibc"),
+                        version: LibC::default_glibc_version(),
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        if platform.is_osx() {
+            if current_platform.is_osx() {
+                result.extend(Osx::current()?.map(Self::from));
+            } else {
+                result.push(
+                    Osx {
+                        version: Osx::default_version(platform),
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        if let Some(archspec) = Archspec::from_platform(platform) {
+            result.push(archspec.into())
+        }
+
+        apply_overrides(platform, result, |var| std::env::var(var).ok())
+    }
 }
 
 /// An error that might be returned by [`VirtualPackage::current`].
@@ -112,6 +194,13 @@ pub enum DetectVirtualPackageError {
 
     #[error(transparent)]
     DetectLibC(#[from] DetectLibCError),
+
+    #[error("failed to parse the version in {var}")]
+    ParseOverride {
+        var: String,
+        #[source]
+        source: ParseVersionError,
+    },
 }
 
 // Detect the available virtual packages on the system
@@ -150,7 +239,102 @@ fn try_detect_virtual_packages() -> Result<Vec<VirtualPackage>, DetectVirtualPac
         result.push(archspec.into())
     }
 
-    Ok(result)
+    apply_overrides(platform, result, |var| std::env::var(var).ok())
+}
+
+/// Applies the `CONDA_OVERRIDE_*` variables (see the [crate] documentation) to the virtual
+/// `packages` of the given platform. `env_var` returns the value of a variable, if it is set.
+fn apply_overrides(
+    platform: Platform,
+    mut packages: Vec<VirtualPackage>,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<VirtualPackage>, DetectVirtualPackageError> {
+    if platform.is_linux() {
+        override_package(
+            &mut packages,
+            "CONDA_OVERRIDE_GLIBC",
+            &env_var,
+            |package| matches!(package, VirtualPackage::LibC(libc) if libc.family == "glibc"),
+            |version| {
+                VirtualPackage::LibC(LibC {
+                    family: String::from("glibc"),
+                    version,
+                })
+            },
+        )?;
+        override_package(
+            &mut packages,
+            "CONDA_OVERRIDE_LINUX",
+            &env_var,
+            |package| matches!(package, VirtualPackage::Linux(_)),
+            |version| VirtualPackage::Linux(Linux { version }),
+        )?;
+    }
+
+    if platform.is_osx() {
+        override_package(
+            &mut packages,
+            "CONDA_OVERRIDE_OSX",
+            &env_var,
+            |package| matches!(package, VirtualPackage::Osx(_)),
+            |version| VirtualPackage::Osx(Osx { version }),
+        )?;
+    }
+
+    override_package(
+        &mut packages,
+        "CONDA_OVERRIDE_CUDA",
+        &env_var,
+        |package| matches!(package, VirtualPackage::Cuda(_)),
+        |version| VirtualPackage::Cuda(Cuda { version }),
+    )?;
+
+    // The archspec is not a version, the value is used as the build string as is.
+    if let Some(spec) = env_var("CONDA_OVERRIDE_ARCHSPEC") {
+        packages.retain(|package| !matches!(package, VirtualPackage::Archspec(_)));
+        if !spec.trim().is_empty() {
+            packages.push(VirtualPackage::Archspec(Archspec {
+                spec: spec.trim().to_owned(),
+            }));
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Overrides the version of the virtual package that `is_overridden` selects with the version in
+/// the environment variable `var`. The virtual package is added if it was not detected and removed
+/// if the variable is empty. Nothing changes if the variable is not set.
+fn override_package(
+    packages: &mut Vec<VirtualPackage>,
+    var: &str,
+    env_var: impl Fn(&str) -> Option<String>,
+    is_overridden: impl Fn(&VirtualPackage) -> bool,
+    package: impl FnOnce(Version) -> VirtualPackage,
+) -> Result<(), DetectVirtualPackageError> {
+    let Some(value) = env_var(var) else {
+        return Ok(());
+    };
+
+    let position = packages.iter().position(is_overridden);
+    let value = value.trim();
+    if value.is_empty() {
+        if let Some(position) = position {
+            packages.remove(position);
+        }
+        return Ok(());
+    }
+
+    let version =
+        Version::from_str(value).map_err(|source| DetectVirtualPackageError::ParseOverride {
+            var: var.to_owned(),
+            source,
+        })?;
+    match position {
+        Some(position) => packages[position] = package(version),
+        None => packages.push(package(version)),
+    }
+    Ok(())
 }
 
 /// Linux virtual package description
@@ -169,6 +353,12 @@ impl Linux {
     pub fn current() -> Result<Option<Self>, ParseLinuxVersionError> {
         Ok(linux::linux_version()?.map(|version| Self { version }))
     }
+
+    /// Returns the Linux version that is assumed when solving for Linux on another operating
+    /// system.
+    pub fn default_version() -> Version {
+        Version::from_str("5.10").unwrap()
+    }
 }
 
 impl From<Linux> for GenericVirtualPackage {
@@ -206,6 +396,12 @@ impl LibC {
     pub fn current() -> Result<Option<Self>, DetectLibCError> {
         Ok(libc::libc_family_and_version()?.map(|(family, version)| Self { family, version }))
     }
+
+    /// Returns the glibc version that is assumed when solving for Linux on another operating
+    /// system. This is the oldest version supported by conda-forge.
+    pub fn default_glibc_version() -> Version {
+        Version::from_str("2.17").unwrap()
+    }
 }
 
 impl From<LibC> for GenericVirtualPackage {
@@ -328,6 +524,15 @@ impl Osx {
     pub fn current() -> Result<Option<Self>, ParseOsxVersionError> {
         Ok(osx::osx_version()?.map(|version| Self { version }))
     }
+
+    /// Returns the macOS version that is assumed when solving for the given macOS platform on
+    /// another operating system.
+    pub fn default_version(platform: Platform) -> Version {
+        match platform {
+            Platform::OsxArm64 => Version::from_str("11.0").unwrap(),
+            _ => Version::from_str("10.15").unwrap(),
+        }
+    }
 }
 
 impl From<Osx> for GenericVirtualPackage {
@@ -348,11 +553,126 @@ impl From<Osx> for VirtualPackage {
 
 #[cfg(test)]
 mod test {
-    use crate::VirtualPackage;
+    use crate::{
+        apply_overrides, Archspec, DetectVirtualPackageError, LibC, Linux, VirtualPackage,
+    };
+    use rattler_conda_types::{GenericVirtualPackage, Platform, Version};
+    use std::collections::HashMap;
+    use std::str::FromStr;
 
     #[test]
     fn doesnt_crash() {
         let virtual_packages = VirtualPackage::current().unwrap();
         println!("{:?}", virtual_packages);
     }
+
+    #[test]
+    fn test_for_platform() {
+        let names = |platform| {
+            VirtualPackage::for_platform(platform)
+                .unwrap()
+                .into_iter()
+                .map(|package| {
+                    let package = GenericVirtualPackage::from(package);
+                    format!("{}={}", package.name.as_normalized(), package.version)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            VirtualPackage::for_platform(Platform::current()).unwrap(),
+            VirtualPackage::current().unwrap()
+        );
+        if !Platform::current().is_windows() {
+            assert_eq!(names(Platform::Win64), ["__win=0", "__archspec=1"]);
+        }
+        if !Platform::current().is_osx() {
+            assert_eq!(
+                names(Platform::OsxArm64),
+                ["__unix=0", "__osx=11.0", "__archspec=1"]
+            );
+        }
+        if !Platform::current().is_linux() {
+            assert_eq!(
+                names(Platform::LinuxAarch64),
+                ["__unix=0", "__linux=5.10", "__glibc=2.17", "__archspec=1"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let detected = vec![
+            VirtualPackage::Unix,
+            VirtualPackage::Linux(Linux {
+                version: Version::from_str("5.10").unwrap(),
+            }),
+            VirtualPackage::LibC(LibC {
+                family: String::from("glibc"),
+                version: Version::from_str("2.17").unwrap(),
+            }),
+            VirtualPackage::Archspec(Archspec {
+                spec: String::from("x86_64"),
+            }),
+        ];
+        let names = |platform, vars: &[(&str, &str)]| {
+            let vars: HashMap<_, _> = vars.iter().copied().collect();
+            apply_overrides(platform, detected.clone(), |var| {
+                vars.get(var).map(|value| value.to_string())
+            })
+            .map(|packages| {
+                packages
+                    .into_iter()
+                    .map(|package| {
+                        let package = GenericVirtualPackage::from(package);
+                        format!(
+                            "{}={}={}",
+                            package.name.as_normalized(),
+                            package.version,
+                            package.build_string
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            names(Platform::Linux64, &[]).unwrap(),
+            [
+                "__unix=0=0",
+                "__linux=5.10=0",
+                "__glibc=2.17=0",
+                "__archspec=1=x86_64"
+            ]
+        );
+        assert_eq!(
+            names(
+                Platform::Linux64,
+                &[
+                    ("CONDA_OVERRIDE_GLIBC", "2.28"),
+                    ("CONDA_OVERRIDE_LINUX", ""),
+                    ("CONDA_OVERRIDE_CUDA", "12.0"),
+                    ("CONDA_OVERRIDE_ARCHSPEC", "skylake"),
+                ]
+            )
+            .unwrap(),
+            [
+                "__unix=0=0",
+                "__glibc=2.28=0",
+                "__cuda=12.0=0",
+                "__archspec=1=skylake"
+            ]
+        );
+
+        // Overrides of virtual packages that do not exist on the platform are ignored.
+        assert_eq!(
+            names(Platform::Linux64, &[("CONDA_OVERRIDE_OSX", "13.0")]).unwrap(),
+            names(Platform::Linux64, &[]).unwrap()
+        );
+
+        assert!(matches!(
+            names(Platform::Linux64, &[("CONDA_OVERRIDE_GLIBC", ">=2.28")]),
+            Err(DetectVirtualPackageError::ParseOverride { var, .. }) if var == "CONDA_OVERRIDE_GLIBC"
+        ));
+    }
 }
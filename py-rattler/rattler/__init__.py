@@ -16,6 +16,7 @@ from rattler.solver import solve
 from rattler.platform import Platform
 from rattler.utils.rattler_version import get_rattler_version as _get_rattler_version
 from rattler.linker import link
+from rattler.lock_file import Environment, LockFile
 
 __version__ = _get_rattler_version()
 del _get_rattler_version
@@ -42,4 +43,6 @@ __all__ = [
     "solve",
     "Platform",
     "link",
+    "Environment",
+    "LockFile",
 ]
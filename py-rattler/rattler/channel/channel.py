@@ -1,8 +1,9 @@
 from __future__ import annotations
-from typing import Optional
+from typing import List, Optional, Tuple
 
 from rattler.rattler import PyChannel
 from rattler.channel.channel_config import ChannelConfig
+from rattler.platform import Platform
 
 
 class Channel:
@@ -21,6 +22,13 @@ class Channel:
         """
         self._channel = PyChannel(name, channel_configuration._channel_configuration)
 
+    @classmethod
+    def _from_py_channel(cls, py_channel: PyChannel) -> Channel:
+        """Construct Rattler channel from FFI PyChannel object."""
+        channel = cls.__new__(cls)
+        channel._channel = py_channel
+        return channel
+
     @property
     def name(self) -> Optional[str]:
         """
@@ -53,6 +61,109 @@ class Channel:
         """
         return self._channel.base_url
 
+    @property
+    def canonical_name(self) -> str:
+        """
+        Return the canonical name of this channel, which is its base URL.
+
+        Examples
+        --------
+        ```python
+        >>> Channel("conda-forge").canonical_name
+        'https://conda.anaconda.org/conda-forge/'
+        >>>
+        ```
+        """
+        return self._channel.canonical_name
+
+    @property
+    def platforms(self) -> Optional[List[Platform]]:
+        """
+        Return the platforms this channel is restricted to, or `None` if
+        the channel is not restricted.
+
+        Examples
+        --------
+        ```python
+        >>> Channel("conda-forge[linux-64]").platforms
+        [Platform(linux-64)]
+        >>> Channel("conda-forge").platforms is None
+        True
+        >>>
+        ```
+        """
+        platforms = self._channel.platforms
+        if platforms is None:
+            return None
+        return [Platform._from_py_platform(platform) for platform in platforms]
+
+    def platform_url(self, platform: Platform) -> str:
+        """
+        Return the URL of the subdirectory of the given platform.
+
+        Examples
+        --------
+        ```python
+        >>> Channel("conda-forge").platform_url(Platform("linux-64"))
+        'https://conda.anaconda.org/conda-forge/linux-64/'
+        >>>
+        ```
+        """
+        return self._channel.platform_url(platform._inner)
+
+    def platforms_url(self) -> List[Tuple[Platform, str]]:
+        """
+        Return the URLs of the subdirectories of all platforms of this
+        channel. If the channel is not restricted to specific platforms
+        these are the current platform and `noarch`.
+
+        Examples
+        --------
+        ```python
+        >>> for platform, url in Channel("conda-forge[linux-64,noarch]").platforms_url():
+        ...     print(platform, url)
+        linux-64 https://conda.anaconda.org/conda-forge/linux-64/
+        noarch https://conda.anaconda.org/conda-forge/noarch/
+        >>>
+        ```
+        """
+        return [
+            (Platform._from_py_platform(platform), url)
+            for platform, url in self._channel.platforms_url()
+        ]
+
+    def __hash__(self) -> int:
+        """
+        Computes the hash of this channel.
+
+        Examples
+        --------
+        ```python
+        >>> hash(Channel("conda-forge")) == hash(Channel("conda-forge"))
+        True
+        >>>
+        ```
+        """
+        return self._channel.__hash__()
+
+    def __eq__(self, other: object) -> bool:
+        """
+        Returns True if this channel is the same as `other`.
+
+        Examples
+        --------
+        ```python
+        >>> Channel("conda-forge") == Channel("https://conda.anaconda.org/conda-forge")
+        True
+        >>> Channel("conda-forge") == Channel("bioconda")
+        False
+        >>>
+        ```
+        """
+        if not isinstance(other, Channel):
+            return NotImplemented
+        return self._channel == other._channel
+
     def __repr__(self) -> str:
         """
         Return a string representation of this channel.
@@ -1,13 +1,25 @@
 from __future__ import annotations
+from typing import List, Optional, TYPE_CHECKING
 
 from rattler.rattler import PyChannelConfig
 
+if TYPE_CHECKING:
+    from rattler.channel.channel import Channel
+
 
 class ChannelConfig:
-    def __init__(self, channel_alias: str = "https://conda.anaconda.org/") -> None:
+    def __init__(
+        self,
+        channel_alias: str = "https://conda.anaconda.org/",
+        default_channels: Optional[List[str]] = None,
+        denied_channels: Optional[List[str]] = None,
+    ) -> None:
         """
         Create a new channel configuration.
 
+        `default_channels` are the channels the `defaults` keyword refers
+        to and `denied_channels` are channels that must never be used.
+
         Examples
         --------
         ```python
@@ -20,7 +32,95 @@ class ChannelConfig:
         >>>
         ```
         """
-        self._channel_configuration = PyChannelConfig(channel_alias)
+        self._channel_configuration = PyChannelConfig(
+            channel_alias, default_channels, denied_channels
+        )
+
+    @property
+    def channel_alias(self) -> str:
+        """
+        Return the URL that is prefixed to channel names.
+
+        Examples
+        --------
+        ```python
+        >>> ChannelConfig("https://repo.prefix.dev/").channel_alias
+        'https://repo.prefix.dev/'
+        >>>
+        ```
+        """
+        return self._channel_configuration.channel_alias
+
+    @property
+    def default_channels(self) -> List[str]:
+        """
+        Return the channels the `defaults` keyword refers to.
+
+        Examples
+        --------
+        ```python
+        >>> ChannelConfig(default_channels=["conda-forge"]).default_channels
+        ['conda-forge']
+        >>>
+        ```
+        """
+        return self._channel_configuration.default_channels
+
+    @property
+    def denied_channels(self) -> List[str]:
+        """
+        Return the channels that must never be used.
+
+        Examples
+        --------
+        ```python
+        >>> ChannelConfig(denied_channels=["bioconda"]).denied_channels
+        ['bioconda']
+        >>>
+        ```
+        """
+        return self._channel_configuration.denied_channels
+
+    def resolve_channels(self, channels: List[str]) -> List[Channel]:
+        """
+        Resolves the channels as specified by a user, in order of priority.
+        The `defaults` keyword is replaced by the default channels and
+        duplicate channels are only returned once.
+
+        Examples
+        --------
+        ```python
+        >>> config = ChannelConfig(default_channels=["bioconda"])
+        >>> channels = config.resolve_channels(["conda-forge", "defaults", "conda-forge"])
+        >>> [channel.name for channel in channels]
+        ['conda-forge', 'bioconda']
+        >>>
+        ```
+        """
+        from rattler.channel.channel import Channel
+
+        return [
+            Channel._from_py_channel(channel)
+            for channel in self._channel_configuration.resolve_channels(channels)
+        ]
+
+    def __eq__(self, other: object) -> bool:
+        """
+        Returns True if this configuration is the same as `other`.
+
+        Examples
+        --------
+        ```python
+        >>> ChannelConfig() == ChannelConfig("https://conda.anaconda.org/")
+        True
+        >>> ChannelConfig() == ChannelConfig("https://repo.prefix.dev/")
+        False
+        >>>
+        ```
+        """
+        if not isinstance(other, ChannelConfig):
+            return NotImplemented
+        return self._channel_configuration == other._channel_configuration
 
     def __repr__(self) -> str:
         """
@@ -10,6 +10,7 @@ try:
         ParseArchError,
         TransactionError,
         LinkError,
+        LockFileError,
         IoError,
         DetectVirtualPackageError,
         CacheDirError,
@@ -50,6 +51,9 @@ except ImportError:
     class LinkError(Exception):  # type: ignore[no-redef]
         """An error that can occur when linking a package"""
 
+    class LockFileError(Exception):  # type: ignore[no-redef]
+        """An error that can occur when reading or creating a lock file"""
+
     class IoError(Exception):  # type: ignore[no-redef]
         """An error that can occur during io operations"""
 
@@ -78,6 +82,7 @@ __all__ = [
     "InvalidVersionError",
     "IoError",
     "LinkError",
+    "LockFileError",
     "ParseArchError",
     "ParsePlatformError",
     "SolverError",
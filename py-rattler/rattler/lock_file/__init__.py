@@ -0,0 +1,4 @@
+from rattler.lock_file.lock_file import LockFile
+from rattler.lock_file.environment import Environment
+
+__all__ = ["LockFile", "Environment"]
@@ -0,0 +1,77 @@
+from __future__ import annotations
+from typing import Dict, List, Union
+
+from rattler.channel import Channel
+from rattler.lock_file.lock_file import LockFile
+from rattler.match_spec import MatchSpec
+from rattler.platform import Platform
+from rattler.repo_data import RepoDataRecord
+
+from rattler.rattler import PyLockFile
+
+
+class Environment:
+    """
+    The packages that were resolved for a number of platforms, together
+    with the channels and specs they were resolved from.
+    """
+
+    def __init__(
+        self,
+        channels: List[Union[Channel, str]],
+        specs: List[MatchSpec],
+        records: Dict[Platform, List[RepoDataRecord]],
+    ) -> None:
+        """
+        Arguments:
+            channels: The channels (or channel names) the records were
+                      resolved from.
+            specs: The specs the records were resolved for.
+            records: The resolved `RepoDataRecord`s of every platform, for
+                     example the result of `solve`.
+        """
+        self.channels = channels
+        self.specs = specs
+        self.records = records
+
+    def to_lock(self) -> LockFile:
+        """
+        Locks the records of every platform in a new `LockFile`.
+
+        Examples
+        --------
+        ```python
+        >>> env = Environment(["conda-forge"], [MatchSpec("python")], {})
+        >>> env.to_lock().channels
+        ['conda-forge']
+        >>>
+        ```
+        """
+        channels = [
+            channel.canonical_name if isinstance(channel, Channel) else channel
+            for channel in self.channels
+        ]
+        return LockFile._from_py_lock_file(
+            PyLockFile.from_records(
+                channels,
+                [spec._match_spec for spec in self.specs],
+                [
+                    (platform._inner, [record._record for record in records])
+                    for platform, records in self.records.items()
+                ],
+            )
+        )
+
+    def __repr__(self) -> str:
+        """
+        Returns a representation of the Environment.
+
+        Examples
+        --------
+        ```python
+        >>> Environment(["conda-forge"], [MatchSpec("python")], {})
+        Environment(channels=['conda-forge'], specs=[MatchSpec("python")])
+        >>>
+        ```
+        """
+        return f"Environment(channels={self.channels!r}, specs={self.specs!r})"
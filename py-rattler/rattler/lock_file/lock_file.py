@@ -0,0 +1,157 @@
+from __future__ import annotations
+import os
+from typing import List, Optional
+
+from rattler.linker import link
+from rattler.platform.platform import Platform
+from rattler.prefix.prefix_record import PrefixRecord
+from rattler.repo_data.record import RepoDataRecord
+
+from rattler.rattler import PyLockFile
+
+
+class LockFile:
+    """
+    A conda-lock file that contains the packages that are locked for a
+    number of platforms.
+    """
+
+    _lock_file: PyLockFile
+
+    @classmethod
+    def _from_py_lock_file(cls, py_lock_file: PyLockFile) -> LockFile:
+        """Construct Rattler LockFile from FFI PyLockFile object."""
+        lock_file = cls.__new__(cls)
+        lock_file._lock_file = py_lock_file
+        return lock_file
+
+    @staticmethod
+    def parse(contents: str) -> LockFile:
+        """
+        Parses a conda-lock file from a string.
+
+        Examples
+        --------
+        ```python
+        >>> with open("../test-data/conda-lock/numpy-conda-lock.yml") as f:
+        ...     lock_file = LockFile.parse(f.read())
+        >>> lock_file.channels
+        ['conda-forge']
+        >>>
+        ```
+        """
+        return LockFile._from_py_lock_file(PyLockFile.parse(contents))
+
+    @staticmethod
+    def from_path(path: os.PathLike[str]) -> LockFile:
+        """
+        Parses a conda-lock file from a file.
+
+        Examples
+        --------
+        ```python
+        >>> lock_file = LockFile.from_path(
+        ...     "../test-data/conda-lock/numpy-conda-lock.yml"
+        ... )
+        >>>
+        ```
+        """
+        return LockFile._from_py_lock_file(PyLockFile.from_path(path))
+
+    def to_path(self, path: os.PathLike[str]) -> None:
+        """
+        Writes the lock file to the file at the specified location.
+        """
+        self._lock_file.to_path(path)
+
+    @property
+    def channels(self) -> List[str]:
+        """
+        The channels that were used to resolve the locked packages.
+
+        Examples
+        --------
+        ```python
+        >>> lock_file = LockFile.from_path(
+        ...     "../test-data/conda-lock/numpy-conda-lock.yml"
+        ... )
+        >>> lock_file.channels
+        ['conda-forge']
+        >>>
+        ```
+        """
+        return self._lock_file.channels
+
+    @property
+    def platforms(self) -> List[Platform]:
+        """
+        The platforms for which packages are locked.
+
+        Examples
+        --------
+        ```python
+        >>> lock_file = LockFile.from_path(
+        ...     "../test-data/conda-lock/numpy-conda-lock.yml"
+        ... )
+        >>> lock_file.platforms[:2]
+        [Platform(linux-64), Platform(linux-aarch64)]
+        >>>
+        ```
+        """
+        return [
+            Platform._from_py_platform(platform)
+            for platform in self._lock_file.platforms
+        ]
+
+    def conda_packages(self, platform: Platform) -> List[RepoDataRecord]:
+        """
+        Returns the conda packages that are locked for the given platform.
+        """
+        return [
+            RepoDataRecord._from_py_record(record)
+            for record in self._lock_file.conda_packages(platform._inner)
+        ]
+
+    async def install(
+        self,
+        target_prefix: os.PathLike[str],
+        cache_dir: os.PathLike[str],
+        installed_packages: Optional[List[PrefixRecord]] = None,
+        platform: Optional[Platform] = None,
+    ) -> None:
+        """
+        Creates an environment in the `target_prefix` directory from the
+        conda packages that are locked for the `platform`.
+
+        Arguments:
+            target_prefix: Path to the directory where the environment
+                           should be created.
+            cache_dir: Path to directory where the packages will be
+                       downloaded and cached.
+            installed_packages: A list of `PrefixRecord`s which are already
+                                installed in the `target_prefix`.
+            platform: The platform for which the environment is created.
+                      Defaults to the current platform.
+        """
+        platform = platform or Platform.current()
+        await link(
+            self.conda_packages(platform),
+            target_prefix,
+            cache_dir,
+            installed_packages,
+            platform,
+        )
+
+    def __repr__(self) -> str:
+        """
+        Returns a representation of the LockFile.
+
+        Examples
+        --------
+        ```python
+        >>> LockFile.from_path("../test-data/conda-lock/numpy-conda-lock.yml")
+        LockFile()
+        >>>
+        ```
+        """
+        return "LockFile()"
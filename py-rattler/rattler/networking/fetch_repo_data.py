@@ -3,39 +3,56 @@ from typing import Callable, List, Optional, Union, TYPE_CHECKING
 
 
 from rattler.rattler import py_fetch_repo_data
+from rattler.channel import Channel
+from rattler.platform import Platform
 from rattler.repo_data.sparse import SparseRepoData
 
 if TYPE_CHECKING:
     import os
-    from rattler.channel import Channel
-    from rattler.platform import Platform
+    from rattler.platform.platform import PlatformLiteral
 
 
 async def fetch_repo_data(
     *,
-    channels: List[Channel],
-    platforms: List[Platform],
+    channels: List[Union[Channel, str]],
+    platforms: List[Union[Platform, PlatformLiteral]],
     cache_path: Union[str, os.PathLike[str]],
-    callback: Optional[Callable[[int, int], None]],
+    callback: Optional[Callable[[int, Optional[int]], None]] = None,
 ) -> List[SparseRepoData]:
     """
     Returns a list of RepoData for given channels and platform.
 
+    The repodata of all subdirectories is fetched concurrently without
+    holding the GIL.
+
     Arguments:
-        channels: A list of `Channel`s to fetch repo data.
-        platforms: A list of `Platform`s for which the repo data
-                   should be fetched.
+        channels: A list of `Channel`s (or channel names) to fetch repo
+                  data.
+        platforms: A list of `Platform`s (or platform names) for which the
+                   repo data should be fetched.
         cache_path: A `os.PathLike[str]` where the repo data should
                     be downloaded.
-        callback: A `Callable[[int, int], None]` to report the download
-                  progress of repo data.
+        callback: A `Callable[[int, Optional[int]], None]` to report the
+                  download progress of repo data. It is called with the
+                  number of bytes downloaded and the total number of bytes
+                  (or `None` if that is not known yet) combined over all
+                  subdirectories. An exception raised by the callback is
+                  raised once the repo data has been fetched.
 
     Returns:
         A list of `SparseRepoData` for requested channels and platforms.
     """
+    resolved_channels = [
+        channel if isinstance(channel, Channel) else Channel(channel)
+        for channel in channels
+    ]
+    resolved_platforms = [
+        platform if isinstance(platform, Platform) else Platform(platform)
+        for platform in platforms
+    ]
     repo_data_list = await py_fetch_repo_data(
-        [channel._channel for channel in channels],
-        [platform._inner for platform in platforms],
+        [channel._channel for channel in resolved_channels],
+        [platform._inner for platform in resolved_platforms],
         cache_path,
         callback,
     )
@@ -56,3 +56,35 @@ class Arch:
         ```
         """
         return f"Arch({self._inner.as_str()})"
+
+    def __hash__(self) -> int:
+        """
+        Computes the hash of the architecture.
+
+        Examples
+        --------
+        ```python
+        >>> hash(Arch("x86_64")) == hash(Arch("x86_64"))
+        True
+        >>>
+        ```
+        """
+        return self._inner.__hash__()
+
+    def __eq__(self, other: object) -> bool:
+        """
+        Returns True if this architecture is the same as `other`.
+
+        Examples
+        --------
+        ```python
+        >>> Arch("x86_64") == Arch("x86_64")
+        True
+        >>> Arch("x86_64") == Arch("aarch64")
+        False
+        >>>
+        ```
+        """
+        if not isinstance(other, Arch):
+            return NotImplemented
+        return self._inner == other._inner
@@ -1,5 +1,5 @@
 from __future__ import annotations
-from typing import Literal
+from typing import List, Literal
 
 from rattler.rattler import PyPlatform
 from rattler.platform.arch import Arch
@@ -71,6 +71,53 @@ class Platform:
         """
         return Platform._from_py_platform(PyPlatform.current())
 
+    @classmethod
+    def all(cls) -> List[Platform]:
+        """
+        Returns all known platforms.
+
+        Examples
+        --------
+        ```python
+        >>> Platform("linux-64") in Platform.all()
+        True
+        >>>
+        ```
+        """
+        return [Platform._from_py_platform(platform) for platform in PyPlatform.all()]
+
+    def __hash__(self) -> int:
+        """
+        Computes the hash of the platform.
+
+        Examples
+        --------
+        ```python
+        >>> hash(Platform("linux-64")) == hash(Platform("linux-64"))
+        True
+        >>>
+        ```
+        """
+        return self._inner.__hash__()
+
+    def __eq__(self, other: object) -> bool:
+        """
+        Returns True if this platform is the same as `other`.
+
+        Examples
+        --------
+        ```python
+        >>> Platform("linux-64") == Platform("linux-64")
+        True
+        >>> Platform("linux-64") == Platform("osx-64")
+        False
+        >>>
+        ```
+        """
+        if not isinstance(other, Platform):
+            return NotImplemented
+        return self._inner == other._inner
+
     @property
     def is_linux(self) -> bool:
         """
@@ -1,4 +1,9 @@
-use pyo3::{pyclass, pymethods};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use pyo3::{basic::CompareOp, pyclass, pymethods, IntoPy, PyObject, Python};
 use rattler_conda_types::{Channel, ChannelConfig};
 use url::Url;
 
@@ -14,10 +19,18 @@ pub struct PyChannelConfig {
 #[pymethods]
 impl PyChannelConfig {
     #[new]
-    pub fn __init__(channel_alias: &str) -> pyo3::PyResult<Self> {
+    #[pyo3(signature = (channel_alias, default_channels=None, denied_channels=None))]
+    pub fn __init__(
+        channel_alias: &str,
+        default_channels: Option<Vec<String>>,
+        denied_channels: Option<Vec<String>>,
+    ) -> pyo3::PyResult<Self> {
+        let defaults = ChannelConfig::default();
         Ok(Self {
             inner: ChannelConfig {
                 channel_alias: Url::parse(channel_alias).map_err(PyRattlerError::from)?,
+                default_channels: default_channels.unwrap_or(defaults.default_channels),
+                denied_channels: denied_channels.unwrap_or(defaults.denied_channels),
             },
         })
     }
@@ -27,6 +40,47 @@ impl PyChannelConfig {
     fn channel_alias(&self) -> String {
         self.inner.channel_alias.to_string()
     }
+
+    /// Returns the channels that the `defaults` keyword refers to.
+    #[getter]
+    fn default_channels(&self) -> Vec<String> {
+        self.inner.default_channels.clone()
+    }
+
+    /// Returns the channels that must never be used.
+    #[getter]
+    fn denied_channels(&self) -> Vec<String> {
+        self.inner.denied_channels.clone()
+    }
+
+    /// Resolves the channels as specified by a user, replacing the `defaults` keyword by the
+    /// default channels.
+    pub fn resolve_channels(&self, channels: Vec<String>) -> pyo3::PyResult<Vec<PyChannel>> {
+        Ok(self
+            .inner
+            .resolve_channels(channels)
+            .map_err(PyRattlerError::from)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Returns true if the channel is on the deny list.
+    pub fn is_denied(&self, channel: &PyChannel) -> bool {
+        self.inner.is_denied(&channel.inner)
+    }
+
+    /// Compares two channel configurations for equality.
+    pub fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyObject {
+        let eq = self.inner.channel_alias == other.inner.channel_alias
+            && self.inner.default_channels == other.inner.default_channels
+            && self.inner.denied_channels == other.inner.denied_channels;
+        match op {
+            CompareOp::Eq => eq.into_py(py),
+            CompareOp::Ne => (!eq).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
 }
 
 #[pyclass]
@@ -69,8 +123,49 @@ impl PyChannel {
         self.inner.base_url.to_string()
     }
 
+    /// Returns the platforms the channel is restricted to, or `None` if the channel is not
+    /// restricted.
+    #[getter]
+    fn platforms(&self) -> Option<Vec<PyPlatform>> {
+        self.inner
+            .platforms
+            .as_ref()
+            .map(|platforms| platforms.iter().copied().map(Into::into).collect())
+    }
+
+    /// Returns the canonical name of the channel.
+    #[getter]
+    fn canonical_name(&self) -> String {
+        self.inner.canonical_name()
+    }
+
     /// Returns the Urls for the given platform.
     pub fn platform_url(&self, platform: &PyPlatform) -> String {
         self.inner.platform_url(platform.clone().into()).into()
     }
+
+    /// Returns the Urls of all the platforms of the channel.
+    pub fn platforms_url(&self) -> Vec<(PyPlatform, String)> {
+        self.inner
+            .platforms_url()
+            .into_iter()
+            .map(|(platform, url)| (platform.into(), url.into()))
+            .collect()
+    }
+
+    /// Compute the hash of the channel.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.inner.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares two channels for equality.
+    pub fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyObject {
+        match op {
+            CompareOp::Eq => (self.inner == other.inner).into_py(py),
+            CompareOp::Ne => (self.inner != other.inner).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
 }
@@ -5,8 +5,9 @@ use pyo3::{create_exception, PyErr};
 use rattler::install::TransactionError;
 use rattler_conda_types::{
     InvalidPackageNameError, ParseArchError, ParseChannelError, ParseMatchSpecError,
-    ParsePlatformError, ParseVersionError,
+    ParsePlatformError, ParseVersionError, ResolveChannelsError,
 };
+use rattler_lock::{CalculateContentHashError, ConversionError, ParseCondaLockError};
 use rattler_repodata_gateway::fetch::FetchRepoDataError;
 use rattler_shell::activation::ActivationError;
 use rattler_solve::SolveError;
@@ -27,6 +28,8 @@ pub enum PyRattlerError {
     #[error(transparent)]
     InvalidChannel(#[from] ParseChannelError),
     #[error(transparent)]
+    ResolveChannelsError(#[from] ResolveChannelsError),
+    #[error(transparent)]
     ActivationError(#[from] ActivationError),
     #[error(transparent)]
     ParsePlatformError(#[from] ParsePlatformError),
@@ -46,6 +49,12 @@ pub enum PyRattlerError {
     TransactionError(#[from] TransactionError),
     #[error("{0}")]
     LinkError(String),
+    #[error(transparent)]
+    ParseLockFileError(#[from] ParseCondaLockError),
+    #[error(transparent)]
+    LockFileConversionError(#[from] ConversionError),
+    #[error(transparent)]
+    CalculateContentHashError(#[from] CalculateContentHashError),
 }
 
 impl From<PyRattlerError> for PyErr {
@@ -64,6 +73,9 @@ impl From<PyRattlerError> for PyErr {
             PyRattlerError::InvalidChannel(err) => {
                 InvalidChannelException::new_err(err.to_string())
             }
+            PyRattlerError::ResolveChannelsError(err) => {
+                InvalidChannelException::new_err(err.to_string())
+            }
             PyRattlerError::ActivationError(err) => ActivationException::new_err(err.to_string()),
             PyRattlerError::ParsePlatformError(err) => {
                 ParsePlatformException::new_err(err.to_string())
@@ -80,6 +92,13 @@ impl From<PyRattlerError> for PyErr {
             PyRattlerError::SolverError(err) => SolverException::new_err(err.to_string()),
             PyRattlerError::TransactionError(err) => TransactionException::new_err(err.to_string()),
             PyRattlerError::LinkError(err) => LinkException::new_err(err),
+            PyRattlerError::ParseLockFileError(err) => LockFileException::new_err(err.to_string()),
+            PyRattlerError::LockFileConversionError(err) => {
+                LockFileException::new_err(err.to_string())
+            }
+            PyRattlerError::CalculateContentHashError(err) => {
+                LockFileException::new_err(err.to_string())
+            }
         }
     }
 }
@@ -99,3 +118,4 @@ create_exception!(exceptions, IoException, PyException);
 create_exception!(exceptions, SolverException, PyException);
 create_exception!(exceptions, TransactionException, PyException);
 create_exception!(exceptions, LinkException, PyException);
+create_exception!(exceptions, LockFileException, PyException);
@@ -2,6 +2,7 @@ mod channel;
 mod error;
 mod generic_virtual_package;
 mod linker;
+mod lock_file;
 mod match_spec;
 mod meta;
 mod nameless_match_spec;
@@ -19,10 +20,12 @@ use channel::{PyChannel, PyChannelConfig};
 use error::{
     ActivationException, CacheDirException, DetectVirtualPackageException, FetchRepoDataException,
     InvalidChannelException, InvalidMatchSpecException, InvalidPackageNameException,
-    InvalidUrlException, InvalidVersionException, IoException, LinkException, ParseArchException,
-    ParsePlatformException, PyRattlerError, SolverException, TransactionException,
+    InvalidUrlException, InvalidVersionException, IoException, LinkException, LockFileException,
+    ParseArchException, ParsePlatformException, PyRattlerError, SolverException,
+    TransactionException,
 };
 use generic_virtual_package::PyGenericVirtualPackage;
+use lock_file::PyLockFile;
 use match_spec::PyMatchSpec;
 use nameless_match_spec::PyNamelessMatchSpec;
 use networking::{authenticated_client::PyAuthenticatedClient, py_fetch_repo_data};
@@ -77,6 +80,7 @@ fn rattler(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyVirtualPackage>().unwrap();
     m.add_class::<PyPrefixRecord>().unwrap();
     m.add_class::<PyPrefixPaths>().unwrap();
+    m.add_class::<PyLockFile>().unwrap();
 
     m.add_function(wrap_pyfunction!(py_solve, m).unwrap())
         .unwrap();
@@ -122,6 +126,8 @@ fn rattler(py: Python, m: &PyModule) -> PyResult<()> {
     m.add("TransactionError", py.get_type::<TransactionException>())
         .unwrap();
     m.add("LinkError", py.get_type::<LinkException>()).unwrap();
+    m.add("LockFileError", py.get_type::<LockFileException>())
+        .unwrap();
     m.add("IoError", py.get_type::<IoException>()).unwrap();
     m.add(
         "DetectVirtualPackageError",
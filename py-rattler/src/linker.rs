@@ -1,10 +1,13 @@
-use std::{future::ready, io::ErrorKind, path::PathBuf};
+use std::{future::ready, path::PathBuf, sync::Arc};
 
 use futures::{stream, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
 use pyo3::{pyfunction, PyAny, PyResult, Python};
 use pyo3_asyncio::tokio::future_into_py;
 use rattler::{
-    install::{link_package, InstallDriver, InstallOptions, Transaction, TransactionOperation},
+    install::{
+        link_package, unlink_package, InstallDriver, InstallOptions, Transaction,
+        TransactionOperation,
+    },
     package_cache::PackageCache,
 };
 use rattler_conda_types::{PackageRecord, PrefixRecord, RepoDataRecord};
@@ -110,9 +113,8 @@ pub async fn execute_operation(
     let cached_package_dir_fut = if let Some(install_record) = install_record {
         async {
             package_cache
-                .get_or_fetch_from_url_with_retry(
-                    &install_record.package_record,
-                    install_record.url.clone(),
+                .get_or_fetch_from_record_with_retry(
+                    install_record,
                     client.clone(),
                     default_retry_policy(),
                 )
@@ -153,7 +155,10 @@ pub async fn install_package_to_environment(
         &package_dir,
         target_prefix.as_path(),
         install_driver,
-        install_options.clone(),
+        InstallOptions {
+            package: Some(Arc::new(repodata_record.clone())),
+            ..install_options.clone()
+        },
     )
     .await
     .map_err(|e| PyRattlerError::LinkError(e.to_string()))?;
@@ -173,20 +178,7 @@ pub async fn install_package_to_environment(
 
     let target_prefix = target_prefix.to_path_buf();
     match tokio::task::spawn_blocking(move || {
-        let conda_meta_path = target_prefix.join("conda-meta");
-        std::fs::create_dir_all(&conda_meta_path)?;
-
-        let pkg_meta_path = conda_meta_path.join(format!(
-            "{}-{}-{}.json",
-            prefix_record
-                .repodata_record
-                .package_record
-                .name
-                .as_normalized(),
-            prefix_record.repodata_record.package_record.version,
-            prefix_record.repodata_record.package_record.build
-        ));
-        prefix_record.write_to_path(pkg_meta_path, true)
+        prefix_record.write_to_prefix(&target_prefix).map(|_| ())
     })
     .await
     {
@@ -205,27 +197,16 @@ async fn remove_package_from_environment(
     target_prefix: PathBuf,
     package: &PrefixRecord,
 ) -> Result<(), PyRattlerError> {
-    for paths in package.paths_data.paths.iter() {
-        match tokio::fs::remove_file(target_prefix.join(&paths.relative_path)).await {
-            Ok(_) => {}
-            Err(e) if e.kind() == ErrorKind::NotFound => {}
-            Err(_) => {
-                return Err(PyRattlerError::LinkError(format!(
-                    "failed to delete {}",
-                    paths.relative_path.display()
-                )))
+    // Removing the files, the empty directories and the conda-meta record of the package is
+    // shared with the other frontends, see `unlink_package`.
+    let package = package.clone();
+    match tokio::task::spawn_blocking(move || unlink_package(&target_prefix, &package)).await {
+        Ok(result) => result.map_err(|e| PyRattlerError::LinkError(e.to_string())),
+        Err(err) => {
+            if let Ok(panic) = err.try_into_panic() {
+                std::panic::resume_unwind(panic);
             }
+            Ok(())
         }
     }
-
-    let conda_meta_path = target_prefix.join("conda-meta").join(format!(
-        "{}-{}-{}.json",
-        package.repodata_record.package_record.name.as_normalized(),
-        package.repodata_record.package_record.version,
-        package.repodata_record.package_record.build
-    ));
-
-    tokio::fs::remove_file(&conda_meta_path).await.map_err(|_| {
-        PyRattlerError::LinkError(format!("failed to delete {}", conda_meta_path.display()))
-    })
 }
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use pyo3::{pyclass, pymethods, PyResult};
+use rattler_lock::{
+    builder::{CondaLockedDependencyBuilder, LockFileBuilder, LockedPackagesBuilder},
+    CondaLock,
+};
+
+use crate::{
+    error::PyRattlerError, match_spec::PyMatchSpec, platform::PyPlatform,
+    repo_data::repo_data_record::PyRepoDataRecord,
+};
+
+#[pyclass]
+#[repr(transparent)]
+#[derive(Clone)]
+pub struct PyLockFile {
+    pub(crate) inner: CondaLock,
+}
+
+impl From<CondaLock> for PyLockFile {
+    fn from(value: CondaLock) -> Self {
+        Self { inner: value }
+    }
+}
+
+impl From<PyLockFile> for CondaLock {
+    fn from(value: PyLockFile) -> Self {
+        value.inner
+    }
+}
+
+#[pymethods]
+impl PyLockFile {
+    /// Parses a conda-lock file from a string.
+    #[staticmethod]
+    pub fn parse(contents: &str) -> PyResult<Self> {
+        Ok(CondaLock::from_str(contents)
+            .map(Into::into)
+            .map_err(PyRattlerError::from)?)
+    }
+
+    /// Parses a conda-lock file from a file.
+    #[staticmethod]
+    pub fn from_path(path: PathBuf) -> PyResult<Self> {
+        Ok(CondaLock::from_path(&path)
+            .map(Into::into)
+            .map_err(PyRattlerError::from)?)
+    }
+
+    /// Constructs a lock file that locks the `records` of every platform. The `channels` and
+    /// `specs` are the input from which the records were resolved.
+    #[staticmethod]
+    pub fn from_records(
+        channels: Vec<String>,
+        specs: Vec<PyMatchSpec>,
+        records: Vec<(PyPlatform, Vec<PyRepoDataRecord>)>,
+    ) -> PyResult<Self> {
+        let mut builder = LockFileBuilder::new(
+            channels,
+            records.iter().map(|(platform, _)| platform.inner),
+            specs.into_iter().map(Into::into),
+        );
+
+        for (platform, records) in records {
+            let mut packages = LockedPackagesBuilder::new(platform.inner);
+            for record in records {
+                packages.add_locked_package(
+                    CondaLockedDependencyBuilder::try_from(record.inner)
+                        .map_err(PyRattlerError::from)?,
+                );
+            }
+            builder = builder.add_locked_packages(packages);
+        }
+
+        Ok(builder
+            .build()
+            .map(Into::into)
+            .map_err(PyRattlerError::from)?)
+    }
+
+    /// Writes the lock file to the file at the specified location.
+    pub fn to_path(&self, path: PathBuf) -> PyResult<()> {
+        Ok(self.inner.to_path(&path).map_err(PyRattlerError::from)?)
+    }
+
+    /// The channels that were used to resolve the locked packages.
+    #[getter]
+    pub fn channels(&self) -> Vec<String> {
+        self.inner
+            .metadata
+            .channels
+            .iter()
+            .map(|channel| channel.url.clone())
+            .collect()
+    }
+
+    /// The platforms for which packages are locked.
+    #[getter]
+    pub fn platforms(&self) -> Vec<PyPlatform> {
+        self.inner
+            .metadata
+            .platforms
+            .iter()
+            .copied()
+            .map(Into::into)
+            .collect()
+    }
+
+    /// Returns the conda packages that are locked for the given platform.
+    pub fn conda_packages(&self, platform: PyPlatform) -> PyResult<Vec<PyRepoDataRecord>> {
+        Ok(self
+            .inner
+            .get_conda_packages_by_platform(platform.inner)
+            .map_err(PyRattlerError::from)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
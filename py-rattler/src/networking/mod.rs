@@ -1,13 +1,15 @@
-use futures::future::try_join_all;
-use pyo3::{pyfunction, types::PyTuple, Py, PyAny, PyResult, Python, ToPyObject};
+use pyo3::{pyfunction, types::PyTuple, Py, PyAny, PyErr, PyResult, Python, ToPyObject};
 use pyo3_asyncio::tokio::future_into_py;
 
-use rattler_repodata_gateway::fetch::{
-    fetch_repo_data, CachedRepoData, DownloadProgress, FetchRepoDataError, FetchRepoDataOptions,
-};
+use rattler_networking::progress::{AggregateProgress, AggregateProgressEntry};
+use rattler_repodata_gateway::fetch::{DownloadProgress, MultiRequestRepoDataBuilder};
 use url::Url;
 
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
     channel::PyChannel, error::PyRattlerError, platform::PyPlatform,
@@ -20,6 +22,11 @@ pub mod cached_repo_data;
 
 /// High-level function to fetch repodata for all the subdirectory of channels and platform.
 /// Returns a list of `PyRepoData`.
+///
+/// The `callback` is called with the combined number of bytes downloaded and the combined total
+/// (or `None` if unknown) of all subdirectories. The GIL is only held while the callback is called.
+/// If the callback raises an exception the remaining progress updates are skipped and the
+/// exception is raised once all repodata has been fetched.
 #[pyfunction]
 pub fn py_fetch_repo_data<'a>(
     py: Python<'a>,
@@ -28,56 +35,66 @@ pub fn py_fetch_repo_data<'a>(
     cache_path: PathBuf,
     callback: Option<&'a PyAny>,
 ) -> PyResult<&'a PyAny> {
-    let mut meta_futures = Vec::new();
     let client = PyAuthenticatedClient::new();
+    let aggregate = AggregateProgress::default();
+    let callback_error = Arc::new(Mutex::new(None));
 
-    for (subdir, chan) in get_subdir_urls(channels, platforms)? {
-        let progress = if let Some(callback) = callback {
-            let callback = callback.to_object(py);
-            Some(get_progress_func(callback))
-        } else {
-            None
-        };
-        let cache_path = cache_path.clone();
-        let client = client.clone();
-
-        // Push all the future into meta_future vec to be resolve later
-        meta_futures.push(async move {
-            Ok((
-                fetch_repo_data(
-                    subdir,
-                    client.into(),
-                    cache_path,
-                    FetchRepoDataOptions::default(),
-                    progress,
-                )
-                .await?,
-                chan,
-            )) as Result<(CachedRepoData, PyChannel), FetchRepoDataError>
+    // Requests to the same host are limited, requests to different hosts are sent in parallel.
+    let mut requests = MultiRequestRepoDataBuilder::new(client.into(), cache_path);
+    let mut subdirs = Vec::new();
+    for (subdir, chan, platform) in get_subdir_urls(channels, platforms)? {
+        let progress = callback.map(|callback| {
+            get_progress_func(
+                callback.to_object(py),
+                aggregate.clone(),
+                aggregate.add_download(None),
+                callback_error.clone(),
+            )
         });
+        requests = requests.add_subdir_with_progress(subdir, progress);
+        subdirs.push((chan, platform));
     }
 
     future_into_py(py, async move {
-        // Resolve all the meta_futures together
-        match try_join_all(meta_futures).await {
-            Ok(res) => res
-                .into_iter()
-                .map(|(cache, chan)| {
-                    let path = cache_path.to_string_lossy().into_owned();
-                    PySparseRepoData::new(chan, path, cache.repo_data_json_path)
-                })
-                .collect::<Result<Vec<_>, _>>(),
-            Err(e) => Err(PyRattlerError::from(e).into()),
+        // The results are returned in the order in which the subdirectories were added
+        let results = requests.fetch().await;
+        if let Some(err) = callback_error.lock().unwrap().take() {
+            return Err(err);
         }
+        let mut repo_datas = Vec::with_capacity(results.len());
+        for ((chan, platform), (_, result)) in subdirs.into_iter().zip(results) {
+            let cache = result.map_err(PyRattlerError::from)?;
+            repo_datas.push(PySparseRepoData::new(
+                chan,
+                platform,
+                cache.repo_data_json_path,
+            )?);
+        }
+        Ok(repo_datas)
     })
 }
 
-/// Creates a closure to show progress of Download
-fn get_progress_func(callback: Py<PyAny>) -> Box<dyn FnMut(DownloadProgress) + Send + Sync> {
+/// Creates a closure that reports the progress of a single download as part of the combined
+/// progress of all downloads. The first exception raised by the callback is stored in
+/// `callback_error`, after which the callback is no longer called.
+fn get_progress_func(
+    callback: Py<PyAny>,
+    aggregate: AggregateProgress,
+    entry: AggregateProgressEntry,
+    callback_error: Arc<Mutex<Option<PyErr>>>,
+) -> Box<dyn FnMut(DownloadProgress) + Send + Sync> {
     Box::new(move |progress: DownloadProgress| {
+        entry.update(progress.bytes, progress.total);
+        let mut callback_error = callback_error.lock().unwrap();
+        if callback_error.is_some() {
+            return;
+        }
+        let progress = aggregate.progress();
         Python::with_gil(|py| {
             let args = PyTuple::new(py, [Some(progress.bytes), progress.total]);
-            callback.call1(py, args).expect("Callback failed!");
+            if let Err(err) = callback.call1(py, args) {
+                *callback_error = Some(err);
+            }
         });
     })
 }
@@ -86,7 +103,7 @@ fn get_progress_func(callback: Py<PyAny>) -> Box<dyn FnMut(DownloadProgress) + S
 fn get_subdir_urls(
     channels: Vec<PyChannel>,
     platforms: Vec<PyPlatform>,
-) -> PyResult<Vec<(Url, PyChannel)>> {
+) -> PyResult<Vec<(Url, PyChannel, String)>> {
     let mut urls = Vec::new();
 
     for c in channels {
@@ -95,6 +112,7 @@ fn get_subdir_urls(
             urls.push((
                 Url::from_str(r.as_str()).map_err(PyRattlerError::from)?,
                 c.clone(),
+                p.inner.to_string(),
             ));
         }
     }
@@ -1,6 +1,10 @@
-use std::str::FromStr;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 
-use pyo3::{pyclass, pymethods};
+use pyo3::{basic::CompareOp, pyclass, pymethods, IntoPy, PyObject, Python};
 use rattler_conda_types::{Arch, Platform};
 
 use crate::error::PyRattlerError;
@@ -46,6 +50,22 @@ impl PyArch {
     pub fn as_str(&self) -> &str {
         self.inner.as_str()
     }
+
+    /// Compute the hash of the architecture.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.inner.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares two architectures for equality.
+    pub fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyObject {
+        match op {
+            CompareOp::Eq => (self.inner == other.inner).into_py(py),
+            CompareOp::Ne => (self.inner != other.inner).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
 }
 
 ///////////////////////////
@@ -92,6 +112,12 @@ impl PyPlatform {
         Platform::current().into()
     }
 
+    /// Returns all known platforms.
+    #[staticmethod]
+    pub fn all() -> Vec<Self> {
+        Platform::all().map(Into::into).collect()
+    }
+
     #[getter]
     pub fn name(&self) -> String {
         self.inner.to_string()
@@ -120,4 +146,20 @@ impl PyPlatform {
     pub fn arch(&self) -> Option<PyArch> {
         self.inner.arch().map(Into::into)
     }
+
+    /// Compute the hash of the platform.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.inner.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares two platforms for equality.
+    pub fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyObject {
+        match op {
+            CompareOp::Eq => (self.inner == other.inner).into_py(py),
+            CompareOp::Ne => (self.inner != other.inner).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
 }
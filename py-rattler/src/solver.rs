@@ -32,14 +32,14 @@ pub fn py_solve(
         )?;
 
         let task = SolverTask {
-            available_packages: &available_packages,
             locked_packages: locked_packages.into_iter().map(Into::into).collect(),
             pinned_packages: pinned_packages.into_iter().map(Into::into).collect(),
             virtual_packages: virtual_packages.into_iter().map(Into::into).collect(),
             specs: specs.into_iter().map(Into::into).collect(),
+            ..SolverTask::new(&available_packages)
         };
 
-        Ok(Solver
+        Ok(Solver::default()
             .solve(task)
             .map(|res| {
                 res.into_iter()
@@ -64,3 +64,46 @@ async def test_fetch_repo_data(
         repodata_record.url
         == f"http://localhost:{port}/test-repo/noarch/test-package-0.1-0.tar.bz2"
     )
+
+
+@pytest.mark.asyncio
+async def test_fetch_repo_data_progress(
+    tmp_path,
+    serve_repo_data,
+):
+    port, repo = serve_repo_data
+    cache_dir = tmp_path / "test_repo_data_progress"
+    chan = Channel(repo, ChannelConfig(f"http://localhost:{port}/"))
+
+    progress = []
+    result = await fetch_repo_data(
+        channels=[chan],
+        platforms=["noarch"],
+        cache_path=cache_dir,
+        callback=lambda downloaded, total: progress.append((downloaded, total)),
+    )
+    assert len(result) == 1
+    assert len(progress) > 0
+    downloaded, total = progress[-1]
+    assert total is None or downloaded == total
+
+
+@pytest.mark.asyncio
+async def test_fetch_repo_data_callback_error(
+    tmp_path,
+    serve_repo_data,
+):
+    port, repo = serve_repo_data
+    cache_dir = tmp_path / "test_repo_data_callback_error"
+    chan = Channel(repo, ChannelConfig(f"http://localhost:{port}/"))
+
+    def callback(downloaded, total):
+        raise ValueError("stop")
+
+    with pytest.raises(ValueError, match="stop"):
+        await fetch_repo_data(
+            channels=[chan],
+            platforms=[Platform("noarch")],
+            cache_path=cache_dir,
+            callback=callback,
+        )
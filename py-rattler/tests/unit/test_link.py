@@ -1,8 +1,9 @@
 # type: ignore
+import json
 import os
 import pytest
 
-from rattler import Channel, SparseRepoData, MatchSpec, solve, link
+from rattler import Channel, SparseRepoData, MatchSpec, PrefixRecord, solve, link
 
 
 @pytest.mark.asyncio
@@ -29,3 +30,32 @@ async def test_link(tmp_path):
     assert os.path.exists(env_dir / "include/xtensor.hpp")
     assert os.path.exists(env_dir / "include/xtensor")
     assert os.path.exists(env_dir / "include/xtl")
+
+
+@pytest.mark.asyncio
+async def test_unlink_package_with_directories(tmp_path):
+    cache_dir = tmp_path / "cache"
+    env_dir = tmp_path / "env"
+
+    data_dir = os.path.join(os.path.dirname(__file__), "../../../test-data/")
+    with open(os.path.join(data_dir, "conda-meta/xz-5.2.6-h8d14728_0.json")) as f:
+        record = json.load(f)
+    record["files"] = ["share/xz/doc.txt"]
+    record["paths_data"]["paths"] = [
+        {"_path": "share/xz/doc.txt", "path_type": "hardlink"},
+        {"_path": "share/xz/empty", "path_type": "directory"},
+        {"_path": "share/xz", "path_type": "directory"},
+    ]
+
+    (env_dir / "share/xz/empty").mkdir(parents=True)
+    (env_dir / "share/xz/doc.txt").write_text("xz")
+    (env_dir / "conda-meta").mkdir()
+    record_path = env_dir / "conda-meta/xz-5.2.6-h8d14728_0.json"
+    record_path.write_text(json.dumps(record))
+
+    # Linking an empty environment removes the package, including its directories.
+    await link([], env_dir, cache_dir, [PrefixRecord.from_path(record_path)])
+
+    assert not os.path.exists(env_dir / "share/xz")
+    assert os.path.isdir(env_dir / "share")
+    assert not os.path.exists(record_path)
@@ -0,0 +1,49 @@
+# type: ignore
+import os
+import pytest
+
+from rattler import (
+    Channel,
+    Environment,
+    LockFile,
+    MatchSpec,
+    Platform,
+    SparseRepoData,
+    solve,
+)
+
+
+@pytest.mark.asyncio
+async def test_lock_and_install(tmp_path):
+    cache_dir = tmp_path / "cache"
+    env_dir = tmp_path / "env"
+    lock_file_path = tmp_path / "conda-lock.yml"
+
+    linux64_chan = Channel("conda-forge")
+    data_dir = os.path.join(os.path.dirname(__file__), "../../../test-data/")
+    linux64_path = os.path.join(data_dir, "channels/conda-forge/linux-64/repodata.json")
+    linux64_data = SparseRepoData(
+        channel=linux64_chan,
+        subdir="linux-64",
+        path=linux64_path,
+    )
+
+    specs = [MatchSpec("xtensor")]
+    solved_data = solve(specs, [linux64_data])
+
+    linux64 = Platform("linux-64")
+    env = Environment([linux64_chan], specs, {linux64: solved_data})
+    env.to_lock().to_path(lock_file_path)
+
+    lock_file = LockFile.from_path(lock_file_path)
+    assert lock_file.platforms == [linux64]
+    assert lock_file.channels == ["https://conda.anaconda.org/conda-forge/"]
+    locked = lock_file.conda_packages(linux64)
+    assert sorted(r.file_name for r in locked) == sorted(
+        r.file_name for r in solved_data
+    )
+
+    await lock_file.install(env_dir, cache_dir, platform=linux64)
+
+    assert os.path.exists(env_dir / "include/xtensor.hpp")
+    assert os.path.exists(env_dir / "include/xtl")